@@ -1,6 +1,8 @@
 // SPDX-FileCopyrightText: 2024 AerynOS Developers
 // SPDX-License-Identifier: MPL-2.0
 
+use std::str::FromStr;
+
 use derive_more::Display;
 
 pub const fn host() -> Architecture {
@@ -22,7 +24,7 @@ pub const fn host() -> Architecture {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, strum::Display)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, strum::Display, strum::EnumString)]
 #[strum(serialize_all = "lowercase")]
 pub enum Architecture {
     X86_64,
@@ -62,3 +64,91 @@ impl BuildTarget {
         }
     }
 }
+
+impl FromStr for BuildTarget {
+    type Err = String;
+
+    /// Parse a target as displayed, i.e. `x86_64` or `emul32/x86_64`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |architecture: &str| {
+            architecture
+                .parse::<Architecture>()
+                .map_err(|_| format!("unknown architecture: {architecture}"))
+        };
+
+        match s.strip_prefix("emul32/") {
+            Some(architecture) => parse(architecture).map(BuildTarget::Emul32),
+            None => parse(s).map(BuildTarget::Native),
+        }
+    }
+}
+
+/// Whether `target` is one of the `architectures` supported by a recipe, empty for all
+///
+/// Entries name an architecture, i.e. `x86_64`, or its emul32 target, i.e.
+/// `emul32/x86_64`, while `native` & `emul32` match those of any architecture.
+pub fn supports(architectures: &[String], target: BuildTarget) -> bool {
+    architectures.is_empty()
+        || architectures
+            .iter()
+            .any(|architecture| match (architecture.as_str(), target) {
+                ("native", BuildTarget::Native(_)) | ("emul32", BuildTarget::Emul32(_)) => true,
+                (architecture, target) => architecture == target.to_string(),
+            })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn architectures(list: &[&str]) -> Vec<String> {
+        list.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn supported_targets() {
+        let list = architectures(&["x86_64", "emul32/x86_64"]);
+        assert!(supports(&list, BuildTarget::Native(Architecture::X86_64)));
+        assert!(supports(&list, BuildTarget::Emul32(Architecture::X86_64)));
+        assert!(!supports(&list, BuildTarget::Native(Architecture::Aarch64)));
+        assert!(!supports(&list, BuildTarget::Emul32(Architecture::Aarch64)));
+
+        // Native only, emul32 must be listed
+        let list = architectures(&["x86_64"]);
+        assert!(supports(&list, BuildTarget::Native(Architecture::X86_64)));
+        assert!(!supports(&list, BuildTarget::Emul32(Architecture::X86_64)));
+
+        let list = architectures(&["aarch64", "emul32/aarch64"]);
+        assert!(!supports(&list, BuildTarget::Native(Architecture::X86_64)));
+        assert!(!supports(&list, BuildTarget::Emul32(Architecture::X86_64)));
+        assert!(supports(&list, BuildTarget::Emul32(Architecture::Aarch64)));
+
+        // Pseudo architectures match any host
+        let list = architectures(&["native", "emul32"]);
+        for arch in [Architecture::X86_64, Architecture::Aarch64, Architecture::Riscv64] {
+            assert!(supports(&list, BuildTarget::Native(arch)));
+            assert!(supports(&list, BuildTarget::Emul32(arch)));
+        }
+        assert!(!supports(
+            &architectures(&["emul32"]),
+            BuildTarget::Native(Architecture::X86_64)
+        ));
+
+        // Empty means all
+        assert!(supports(&[], BuildTarget::Native(Architecture::Riscv64)));
+        assert!(supports(&[], BuildTarget::Emul32(Architecture::X86_64)));
+    }
+
+    #[test]
+    fn parse_targets() {
+        for target in [
+            BuildTarget::Native(Architecture::X86_64),
+            BuildTarget::Emul32(Architecture::X86_64),
+            BuildTarget::Native(Architecture::Riscv64),
+        ] {
+            assert_eq!(target.to_string().parse(), Ok(target));
+        }
+        assert!("emul32".parse::<BuildTarget>().is_err());
+        assert!("x86-64".parse::<BuildTarget>().is_err());
+    }
+}
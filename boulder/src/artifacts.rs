@@ -0,0 +1,327 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Built stones & publishing them into a local repository of the host
+//!
+//! Each build lists the stones it produced in a [`Manifest`] written alongside
+//! them in the output directory. Local repositories are those moss is
+//! configured with whose index is a `file://` URI, the stones living alongside
+//! their `stone.index`, and the local overlay repository moss indexes on the fly.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use fs_err as fs;
+use moss::{
+    client::index,
+    registry::plugin,
+    repository::{self, Source},
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use url::Url;
+
+/// The stones produced by a build
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Manifest {
+    pub source_name: String,
+    pub source_version: String,
+    pub source_release: u64,
+    pub build_release: u64,
+    pub architecture: String,
+    pub stones: Vec<Stone>,
+    /// Only some of the packages of the recipe were emitted, i.e. with `--only-package`,
+    /// so the stones aren't moved into a repository unless forced
+    #[serde(default)]
+    pub partial: bool,
+}
+
+/// A stone produced by a build
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Stone {
+    /// Name of the (sub-)package
+    pub package: String,
+    pub filename: String,
+    /// In bytes
+    pub size: u64,
+    pub sha256: String,
+    /// Number of layout entries, one per path installed
+    pub layouts: usize,
+    pub dependencies: usize,
+    pub providers: usize,
+}
+
+impl Manifest {
+    /// Name of the manifest of the source at `version` & `release`, i.e.
+    /// `nano-8.7-1.manifest.json`
+    pub fn file_name(name: &str, version: &str, release: u64) -> String {
+        format!("{name}-{version}-{release}.manifest.json")
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        serde_json::from_slice(&fs::read(path)?).map_err(|error| Error::Manifest(path.to_owned(), error))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let content = serde_json::to_vec_pretty(self).map_err(|error| Error::Manifest(path.to_owned(), error))?;
+        Ok(fs::write(path, content)?)
+    }
+}
+
+/// Directory of the local repository `name`, as configured for moss by `config`
+///
+/// The local overlay repository, when configured, is named [`plugin::OVERLAY_NAME`]
+/// ahead of the repositories, as moss prefers its stones over theirs
+pub fn resolve_local_repo(config: &config::Manager, name: &str) -> Result<PathBuf, Error> {
+    if name == plugin::OVERLAY_NAME
+        && let Some(overlay) = plugin::overlay_directories(config, Path::new("/")).into_iter().next()
+    {
+        return Ok(overlay);
+    }
+
+    let repositories = config
+        .load::<repository::Map>()
+        .into_iter()
+        .fold(repository::Map::default(), |merged, loaded| merged.merge(loaded.value));
+
+    let repository = repositories
+        .get(&repository::Id::new(name))
+        .ok_or_else(|| Error::RepositoryNotFound(name.to_owned()))?;
+
+    let not_local = || Error::NotLocal(name.to_owned());
+
+    let Source::DirectIndex(uri) = &repository.source else {
+        return Err(not_local());
+    };
+    let index = local_path(uri).ok_or_else(not_local)?;
+
+    index.parent().map(Path::to_path_buf).ok_or_else(not_local)
+}
+
+/// Move the stones listed by the [`Manifest`] at `manifest`, alongside it,
+/// into `to`, returning where they were moved
+///
+/// Stones already in `to` are replaced, while other stones alongside the
+/// manifest are left alone. Those of a partial build are refused unless `force`.
+pub fn move_stones(manifest: &Path, to: &Path, force: bool) -> Result<Vec<PathBuf>, Error> {
+    let from = manifest.parent().unwrap_or(Path::new("."));
+    let loaded = Manifest::load(manifest)?;
+    if loaded.partial && !force {
+        return Err(Error::Partial(manifest.to_owned()));
+    }
+    let stones = loaded.stones;
+
+    fs::create_dir_all(to)?;
+
+    stones
+        .into_iter()
+        .map(|stone| {
+            let source = from.join(&stone.filename);
+            if !source.is_file() {
+                return Err(Error::MissingStone(source));
+            }
+            let target = to.join(&stone.filename);
+            move_file(&source, &target)?;
+            Ok(target)
+        })
+        .collect()
+}
+
+/// Index the stones of the local repository at `path`
+pub fn reindex(path: &Path) -> Result<(), Error> {
+    Ok(index(path, None, &index::Options::default())?)
+}
+
+fn local_path(uri: &Url) -> Option<PathBuf> {
+    (uri.scheme() == "file").then(|| uri.to_file_path().ok())?
+}
+
+/// Rename `from` to `to`, copying it across filesystems
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(error) if error.kind() == io::ErrorKind::CrossesDevices => {
+            fs::copy(from, to)?;
+            fs::remove_file(from)
+        }
+        result => result,
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("no repository named {0} is configured")]
+    RepositoryNotFound(String),
+    #[error("repository {0} isn't a local repository")]
+    NotLocal(String),
+    #[error("index repository")]
+    Index(#[from] index::Error),
+    #[error("artifacts manifest {0:?}")]
+    Manifest(PathBuf, #[source] serde_json::Error),
+    #[error("stone listed by the artifacts manifest is missing: {0:?}")]
+    MissingStone(PathBuf),
+    #[error("stones of {0:?} are of a partial build, pass --force to move them anyway")]
+    Partial(PathBuf),
+    #[error("io")]
+    Io(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fixtures() -> config::Manager {
+        config::Manager::system(concat!(env!("CARGO_MANIFEST_DIR"), "/../test/artifacts"), "moss")
+    }
+
+    #[test]
+    fn resolve() {
+        let config = fixtures();
+
+        assert_eq!(
+            resolve_local_repo(&config, "local").unwrap(),
+            PathBuf::from("/var/lib/boulder/repo")
+        );
+        // Configured by the vendor rather than the admin
+        assert_eq!(
+            resolve_local_repo(&config, "staging").unwrap(),
+            PathBuf::from("/var/cache/staging")
+        );
+
+        assert!(matches!(
+            resolve_local_repo(&config, "volatile"),
+            Err(Error::NotLocal(name)) if name == "volatile"
+        ));
+        assert!(matches!(
+            resolve_local_repo(&config, "unstable"),
+            Err(Error::NotLocal(name)) if name == "unstable"
+        ));
+        assert!(matches!(
+            resolve_local_repo(&config, "missing"),
+            Err(Error::RepositoryNotFound(name)) if name == "missing"
+        ));
+    }
+
+    #[test]
+    fn resolve_overlay() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config::Manager::custom(dir.path());
+
+        assert!(matches!(
+            resolve_local_repo(&config, "local"),
+            Err(Error::RepositoryNotFound(name)) if name == "local"
+        ));
+
+        let overlay = dir.path().join("local-repo.d");
+        fs::create_dir_all(&overlay).unwrap();
+        fs::write(overlay.join("builds.yaml"), "path: /var/lib/moss/local-repo\n").unwrap();
+
+        assert_eq!(
+            resolve_local_repo(&config, "local").unwrap(),
+            PathBuf::from("/var/lib/moss/local-repo")
+        );
+    }
+
+    fn stone(package: &str) -> Stone {
+        Stone {
+            package: package.to_owned(),
+            filename: format!("{package}-8.7-1-1-x86_64.stone"),
+            size: 0,
+            sha256: String::new(),
+            layouts: 0,
+            dependencies: 0,
+            providers: 0,
+        }
+    }
+
+    #[test]
+    fn move_only_stones() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("output");
+        let repo = dir.path().join("repo");
+
+        fs::create_dir_all(output.join("nested.stone")).unwrap();
+        for file in [
+            "nano-8.7-1-1-x86_64.stone",
+            "nano-dbginfo-8.7-1-1-x86_64.stone",
+            "manifest.x86_64.bin",
+            // Built earlier, of another recipe
+            "vim-9.1-1-1-x86_64.stone",
+        ] {
+            fs::write(output.join(file), file).unwrap();
+        }
+        let manifest = output.join(Manifest::file_name("nano", "8.7", 1));
+        Manifest {
+            source_name: "nano".to_owned(),
+            source_version: "8.7".to_owned(),
+            source_release: 1,
+            build_release: 1,
+            architecture: "x86_64".to_owned(),
+            stones: vec![stone("nano"), stone("nano-dbginfo")],
+            partial: false,
+        }
+        .save(&manifest)
+        .unwrap();
+        // Replaced by the newly built stone
+        fs::create_dir_all(&repo).unwrap();
+        fs::write(repo.join("nano-8.7-1-1-x86_64.stone"), "stale").unwrap();
+
+        let moved = move_stones(&manifest, &repo, false).unwrap();
+
+        assert_eq!(
+            moved,
+            [
+                repo.join("nano-8.7-1-1-x86_64.stone"),
+                repo.join("nano-dbginfo-8.7-1-1-x86_64.stone"),
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(repo.join("nano-8.7-1-1-x86_64.stone")).unwrap(),
+            "nano-8.7-1-1-x86_64.stone"
+        );
+        assert!(!output.join("nano-8.7-1-1-x86_64.stone").exists());
+        assert!(output.join("manifest.x86_64.bin").exists());
+        assert!(output.join("vim-9.1-1-1-x86_64.stone").exists());
+        assert!(output.join("nested.stone").is_dir());
+
+        // Already moved
+        assert!(matches!(
+            move_stones(&manifest, &repo, false),
+            Err(Error::MissingStone(path)) if path == output.join("nano-8.7-1-1-x86_64.stone")
+        ));
+        assert!(move_stones(&output.join("missing.manifest.json"), &repo, false).is_err());
+    }
+
+    #[test]
+    fn refuse_partial() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        fs::write(dir.path().join("nano-devel-8.7-1-1-x86_64.stone"), "").unwrap();
+        let manifest = dir.path().join(Manifest::file_name("nano", "8.7", 1));
+        Manifest {
+            source_name: "nano".to_owned(),
+            source_version: "8.7".to_owned(),
+            source_release: 1,
+            build_release: 1,
+            architecture: "x86_64".to_owned(),
+            stones: vec![stone("nano-devel")],
+            partial: true,
+        }
+        .save(&manifest)
+        .unwrap();
+
+        assert!(matches!(
+            move_stones(&manifest, &repo, false),
+            Err(Error::Partial(path)) if path == manifest
+        ));
+        assert!(!repo.exists());
+
+        assert_eq!(
+            move_stones(&manifest, &repo, true).unwrap(),
+            [repo.join("nano-devel-8.7-1-1-x86_64.stone")]
+        );
+    }
+}
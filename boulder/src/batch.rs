@@ -0,0 +1,449 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Builds of several recipes, ordered by their build dependencies
+//!
+//! A recipe depends on another when one of its `builddeps` or `checkdeps`
+//! names a package the other produces, either plainly or as `name(...)`.
+//! Other providers, i.e. `pkgconfig(...)`, are only known once built, so
+//! they never order recipes. The stones of each successful build are indexed
+//! into a [`Local`] repository, which the builds following it install from.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io,
+    path::{Path, PathBuf},
+};
+
+use dag::Dag;
+use fs_err as fs;
+use moss::{client::index, repository, util};
+use tempfile::TempDir;
+use thiserror::Error;
+use url::Url;
+
+use crate::recipe::{self, Parsed};
+
+/// Id of the [`Local`] repository
+pub const REPOSITORY_ID: &str = "boulder-batch";
+
+/// Order in which to build a set of recipes, referenced by their index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    /// Recipes which can be built once all previous batches are, in order
+    pub batches: Vec<Vec<usize>>,
+    /// The recipes each recipe depends on
+    pub dependencies: Vec<BTreeSet<usize>>,
+}
+
+impl Plan {
+    /// Order the `recipes` so each is built after those it depends on
+    pub fn new<'a>(recipes: impl IntoIterator<Item = &'a Parsed>) -> Result<Self, Error> {
+        let recipes = recipes.into_iter().collect::<Vec<_>>();
+
+        // Recipe producing each package
+        let mut producers = BTreeMap::<String, usize>::new();
+        for (index, recipe) in recipes.iter().enumerate() {
+            for package in packages(recipe) {
+                if let Some(other) = producers.insert(package.clone(), index) {
+                    return Err(Error::DuplicatePackage {
+                        package,
+                        first: recipes[other].source.name.clone(),
+                        second: recipe.source.name.clone(),
+                    });
+                }
+            }
+        }
+
+        let mut dag = Dag::new();
+        let nodes = (0..recipes.len())
+            .map(|index| dag.add_node_or_get_index(&index))
+            .collect::<Vec<_>>();
+        let mut dependencies = vec![BTreeSet::new(); recipes.len()];
+
+        for (index, recipe) in recipes.iter().enumerate() {
+            let deps = recipe.build.build_deps.iter().chain(&recipe.build.check_deps);
+
+            for dependency in deps.filter_map(|dep| producers.get(package_name(dep))) {
+                // Recipes may depend on their own packages, i.e. to bootstrap
+                if *dependency == index || !dependencies[index].insert(*dependency) {
+                    continue;
+                }
+
+                if dag.dfs(nodes[index]).any(|node| node == dependency) {
+                    return Err(Error::Cycle {
+                        first: recipes[*dependency].source.name.clone(),
+                        second: recipe.source.name.clone(),
+                    });
+                }
+                dag.add_edge(nodes[*dependency], nodes[index]);
+            }
+        }
+
+        Ok(Self {
+            batches: dag.batched_topo(),
+            dependencies,
+        })
+    }
+
+    /// Build the recipes in order with `build`, returning what happened to each
+    ///
+    /// Recipes depending on a failed build are skipped. Unless `keep_going`,
+    /// the first failure skips all recipes not yet built.
+    pub fn run<E>(&self, keep_going: bool, mut build: impl FnMut(usize) -> Result<(), E>) -> Outcome<E> {
+        let mut outcome = Outcome {
+            built: vec![],
+            failed: vec![],
+            skipped: vec![],
+        };
+        // Failed & skipped recipes
+        let mut broken = BTreeSet::new();
+
+        for index in self.batches.iter().flatten().copied() {
+            let stopped = !keep_going && !outcome.failed.is_empty();
+
+            if stopped || !self.dependencies[index].is_disjoint(&broken) {
+                broken.insert(index);
+                outcome.skipped.push(index);
+                continue;
+            }
+
+            match build(index) {
+                Ok(()) => outcome.built.push(index),
+                Err(error) => {
+                    broken.insert(index);
+                    outcome.failed.push((index, error));
+                }
+            }
+        }
+
+        outcome
+    }
+}
+
+/// Result of [`Plan::run`], with recipes in build order
+#[derive(Debug)]
+pub struct Outcome<E> {
+    pub built: Vec<usize>,
+    pub failed: Vec<(usize, E)>,
+    /// Recipes not built, as a build they depend on failed or the run stopped
+    pub skipped: Vec<usize>,
+}
+
+/// Repository of the stones built so far by the batch
+///
+/// They're staged in a directory of their own, so stones left in the output
+/// directory by earlier builds never shadow those of the repositories.
+#[derive(Debug)]
+pub struct Local {
+    dir: TempDir,
+}
+
+impl Local {
+    /// Stage the repository in a hidden directory of `output`, removed once dropped
+    pub fn new(output: &Path) -> Result<Self, Error> {
+        let dir = tempfile::Builder::new()
+            .prefix(".boulder-batch-")
+            .tempdir_in(output)
+            .map_err(|error| Error::Stage(error, output.to_owned()))?;
+
+        Ok(Self { dir })
+    }
+
+    /// Stage the `stones` of a build & index all those staged, so later builds
+    /// can install them. Stones staged before with the same name are replaced.
+    pub fn publish<'a>(&self, stones: impl IntoIterator<Item = &'a Path>) -> Result<(), Error> {
+        for stone in stones {
+            let Some(name) = stone.file_name() else {
+                continue;
+            };
+            let staged = self.dir.path().join(name);
+
+            if staged.exists() {
+                fs::remove_file(&staged).map_err(|error| Error::Stage(error, stone.to_owned()))?;
+            }
+            fs::hard_link(stone, &staged)
+                .or_else(|_| fs::copy(stone, &staged).map(|_| ()))
+                .map_err(|error| Error::Stage(error, stone.to_owned()))?;
+        }
+
+        Ok(index(self.dir.path(), None, &index::Options::default())?)
+    }
+
+    /// The repository, preferred over those of the build profile
+    pub fn repositories(&self) -> Result<repository::Map, Error> {
+        let path = self.dir.path().join("stone.index");
+        let uri = Url::from_file_path(&path).map_err(|_| Error::RepositoryPath(path))?;
+
+        Ok(repository::Map::with([(
+            repository::Id::new(REPOSITORY_ID),
+            repository::Repository {
+                description: "Recipes built earlier in the batch".to_owned(),
+                source: repository::Source::DirectIndex(uri),
+                priority: repository::Priority::new(u64::MAX),
+                active: true,
+            },
+        )]))
+    }
+}
+
+/// Find the recipes at `paths`, searching directories for `stone.yaml` files
+pub fn find(paths: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+    let mut found = BTreeSet::new();
+
+    for path in paths {
+        if path.is_dir() && !path.join("stone.yaml").exists() {
+            for recipe in util::enumerate_files(path, is_recipe).map_err(|error| Error::Find(error, path.clone()))? {
+                found.insert(recipe::resolve_path(recipe)?);
+            }
+        } else {
+            found.insert(recipe::resolve_path(path)?);
+        }
+    }
+
+    Ok(found.into_iter().collect())
+}
+
+/// Names of the packages built from `recipe`
+fn packages(recipe: &Parsed) -> impl Iterator<Item = String> + '_ {
+    let name = &recipe.source.name;
+
+    std::iter::once(name.clone()).chain(
+        recipe
+            .sub_packages
+            .iter()
+            .map(move |package| package.key.replace("%(name)", name)),
+    )
+}
+
+fn is_recipe(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name == "stone.yaml")
+}
+
+/// The package named by a dependency, or the dependency itself
+fn package_name(dependency: &str) -> &str {
+    dependency
+        .strip_prefix("name(")
+        .and_then(|name| name.strip_suffix(')'))
+        .unwrap_or(dependency)
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("package {package} is built by both {first} and {second}")]
+    DuplicatePackage {
+        package: String,
+        first: String,
+        second: String,
+    },
+    #[error("recipes {first} and {second} depend on each other")]
+    Cycle { first: String, second: String },
+    #[error("finding recipes in {1:?}")]
+    Find(#[source] io::Error, PathBuf),
+    #[error("recipe")]
+    Recipe(#[from] recipe::Error),
+    #[error("invalid repository path {0:?}")]
+    RepositoryPath(PathBuf),
+    #[error("stage {1:?} in the batch repository")]
+    Stage(#[source] io::Error, PathBuf),
+    #[error("index the batch repository")]
+    Index(#[from] index::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use fs_err as fs;
+
+    use super::*;
+
+    fn recipe(name: &str, sub_packages: &[&str], build_deps: &[&str]) -> Parsed {
+        let packages = sub_packages
+            .iter()
+            .map(|package| format!("    - \"{package}\":\n        summary: \"{package}\"\n"))
+            .collect::<String>();
+        let packages = if packages.is_empty() {
+            packages
+        } else {
+            format!("packages:\n{packages}")
+        };
+        let build_deps = build_deps
+            .iter()
+            .map(|dep| format!("    - {dep}\n"))
+            .collect::<String>();
+
+        stone_recipe::from_str(&format!(
+            "\
+name: {name}
+version: 1.0.0
+release: 1
+homepage: https://example.com
+license: MPL-2.0
+summary: {name}
+description: {name}
+builddeps:
+    - binutils
+{build_deps}{packages}"
+        ))
+        .unwrap()
+    }
+
+    fn names(recipes: &[Parsed], indices: &[usize]) -> Vec<String> {
+        indices
+            .iter()
+            .map(|index| recipes[*index].source.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn dependency_order() {
+        let recipes = [
+            recipe("app", &[], &["name(libfoo-devel)", "pkgconfig(zlib)"]),
+            recipe("libfoo", &["%(name)-devel"], &["zlib-devel"]),
+            recipe("zlib", &["zlib-devel"], &[]),
+            recipe("docs", &[], &[]),
+            recipe("plugin", &[], &["libfoo", "app"]),
+        ];
+        let plan = Plan::new(&recipes).unwrap();
+
+        let batches = plan
+            .batches
+            .iter()
+            .map(|batch| {
+                let mut names = names(&recipes, batch);
+                names.sort();
+                names
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            batches,
+            [vec!["docs", "zlib"], vec!["libfoo"], vec!["app"], vec!["plugin"]]
+        );
+        assert_eq!(plan.dependencies[0], BTreeSet::from([1]));
+        assert_eq!(plan.dependencies[4], BTreeSet::from([0, 1]));
+    }
+
+    #[test]
+    fn invalid_plans() {
+        let cycle = [recipe("a", &[], &["b"]), recipe("b", &[], &["a"])];
+        assert!(matches!(Plan::new(&cycle), Err(Error::Cycle { .. })));
+
+        let duplicate = [recipe("a", &["common"], &[]), recipe("b", &["common"], &[])];
+        assert!(matches!(
+            Plan::new(&duplicate),
+            Err(Error::DuplicatePackage { package, .. }) if package == "common"
+        ));
+
+        // Depending on its own package isn't a cycle
+        let own = [recipe("gcc", &["libgcc"], &["libgcc"])];
+        assert_eq!(Plan::new(&own).unwrap().batches, [[0]]);
+    }
+
+    #[test]
+    fn failures() {
+        let recipes = [
+            recipe("zlib", &[], &[]),
+            recipe("libfoo", &[], &["zlib"]),
+            recipe("app", &[], &["libfoo"]),
+            recipe("docs", &[], &[]),
+            recipe("tools", &[], &["docs"]),
+            recipe("extras", &[], &["tools"]),
+        ];
+        let plan = Plan::new(&recipes).unwrap();
+        let build = |index: usize| {
+            if recipes[index].source.name == "libfoo" {
+                Err(index)
+            } else {
+                Ok(())
+            }
+        };
+        let sorted = |indices: &[usize]| {
+            let mut names = names(&recipes, indices);
+            names.sort();
+            names
+        };
+
+        let outcome = plan.run(true, build);
+        assert_eq!(sorted(&outcome.built), ["docs", "extras", "tools", "zlib"]);
+        assert_eq!(outcome.failed, [(1, 1)]);
+        assert_eq!(names(&recipes, &outcome.skipped), ["app"]);
+
+        // Independent recipes ordered after the failure are skipped too
+        let outcome = plan.run(false, build);
+        assert!(outcome.built.starts_with(&[0, 3]) || outcome.built.starts_with(&[3, 0]));
+        assert_eq!(outcome.failed, [(1, 1)]);
+        assert!(sorted(&outcome.skipped).ends_with(&["app".to_owned(), "extras".to_owned()]));
+    }
+
+    #[test]
+    fn dependent_recipes() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("output");
+        fs::create_dir_all(&output).unwrap();
+
+        for (name, deps) in [("libfoo", vec![]), ("app", vec!["libfoo"])] {
+            let recipe = dir.path().join("recipes").join(name).join("stone.yaml");
+            fs::create_dir_all(recipe.parent().unwrap()).unwrap();
+            let deps = deps.iter().map(|dep| format!("    - {dep}\n")).collect::<String>();
+            fs::write(
+                &recipe,
+                format!(
+                    "\
+name: {name}
+version: 1.0.0
+release: 1
+homepage: https://example.com
+license: MPL-2.0
+summary: {name}
+description: {name}
+builddeps:
+    - binutils
+{deps}"
+                ),
+            )
+            .unwrap();
+        }
+
+        let paths = find(&[dir.path().join("recipes")]).unwrap();
+        assert!(paths.iter().all(|path| is_recipe(path)));
+        let recipes = paths
+            .iter()
+            .map(|path| recipe::Recipe::load(path).unwrap())
+            .collect::<Vec<_>>();
+        let plan = Plan::new(recipes.iter().map(|recipe| &recipe.parsed)).unwrap();
+
+        let local = Local::new(&output).unwrap();
+        let stone = Path::new(env!("CARGO_MANIFEST_DIR")).join("../test/bash-completion-2.11-1-1-x86_64.stone");
+        // Left by an earlier build, never indexed as the same release would clash
+        fs::copy(&stone, output.join("stale.stone")).unwrap();
+
+        let mut order = vec![];
+        let outcome = plan.run(false, |index| {
+            let name = recipes[index].parsed.source.name.clone();
+
+            // Builds after the first install from the stones built before them
+            if !order.is_empty() {
+                let repositories = local.repositories()?;
+                let (_, repository) = repositories.iter().next().unwrap();
+                let index = repository.source.direct_index().unwrap().to_file_path().unwrap();
+                assert!(index.exists());
+            }
+
+            // Stand in for the stone the recipe produces, replacing the one
+            // staged by the previous build
+            let built = output.join("built.stone");
+            fs::copy(&stone, &built).unwrap();
+            local.publish([built.as_path()])?;
+
+            order.push(name);
+            Ok::<_, Error>(())
+        });
+
+        assert!(outcome.failed.is_empty(), "{:?}", outcome.failed);
+        assert_eq!(order, ["libfoo", "app"]);
+
+        let staged = local.dir.path().to_owned();
+        assert!(staged.join("built.stone").exists() && !staged.join("stale.stone").exists());
+        drop(local);
+        assert!(!staged.exists());
+    }
+}
@@ -2,16 +2,21 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use std::{
-    io,
+    collections::BTreeSet,
+    io, mem,
+    num::NonZeroUsize,
     os::unix::process::ExitStatusExt,
     path::{Path, PathBuf},
     process, thread,
+    time::{Instant, SystemTime},
 };
 
+use chrono::Utc;
 use fs_err as fs;
 use itertools::Itertools;
-use moss::{repository, util};
+use moss::{Provider, repository, util};
 use nix::{
+    libc,
     sys::signal::Signal,
     unistd::{Pid, getpgrp, setpgid},
 };
@@ -22,17 +27,43 @@ use stone_recipe::{
 use thiserror::Error;
 use tui::Styled;
 
-use self::job::Job;
+use self::{
+    deps::Explanation,
+    environment::Environment,
+    job::Job,
+    jobs::Jobs,
+    log::Logs,
+    report::{BuildReport, Usage},
+    resume::Resume,
+};
 use crate::{
     Env, Macros, Paths, Recipe, Timing,
-    architecture::BuildTarget,
-    container, macros, profile, recipe, timing,
-    upstream::{self, Upstream},
+    architecture::{self, Architecture, BuildTarget},
+    cache,
+    container::{self, network::Allowlist},
+    macros,
+    package::sbom,
+    paths, profile, recipe, timing,
+    upstream::{
+        self, Upstream,
+        lock::{self, Lock},
+    },
 };
 
+pub mod archive;
+pub mod cancel;
+pub mod deps;
+pub mod environment;
+pub mod inputs;
 pub mod job;
+pub mod jobs;
+pub mod log;
 pub mod pgo;
+pub mod report;
+pub mod resume;
 mod root;
+pub mod validate;
+pub mod warnings;
 
 pub struct Builder {
     pub targets: Vec<Target>,
@@ -40,9 +71,38 @@ pub struct Builder {
     pub paths: Paths,
     pub macros: Macros,
     pub ccache: bool,
+    /// Run in parallel by each phase, see [`jobs`]
+    pub jobs: Jobs,
     pub env: Env,
+    /// Skip the phases completed by the previous build, see [`resume`]
+    pub resume: bool,
+    /// Open an interactive shell in the environment of a failed phase
+    pub shell_on_failure: bool,
+    /// Variables set within the container, see [`environment`]
+    pub environment: Environment,
+    /// Hosts reachable with networking, see [`container::network`]
+    pub allowlist: Allowlist,
+    /// Fail when paths are installed outside `/usr`, see [`crate::package::layout`]
+    pub strict_layout: bool,
+    /// Omit the check phase & its dependencies, whatever the recipe
+    pub skip_check: bool,
     upstreams: Vec<Upstream>,
+    /// Upstreams & packages the rootfs was set up from
+    materials: sbom::Materials,
+    /// Why each package of the rootfs was installed
+    pub dependencies: Explanation,
+    /// Shared libraries & interpreters of the repositories, see [`crate::package::unresolved`]
+    pub repository_providers: BTreeSet<Provider>,
+    /// Selected to build with, see [`inputs`]
+    profile: profile::Id,
+    /// Replace the lock of the upstreams rather than verify them against it, see [`lock`]
+    pub update_lock: bool,
+    /// The upstreams fetched, locked once built
+    lock: Option<Lock>,
+    /// Entries of the cache used, protected from pruning while building
+    claim: Option<cache::Claim>,
     repos: repository::Map,
+    system_triggers: bool,
 }
 
 pub struct Target {
@@ -51,36 +111,72 @@ pub struct Target {
 }
 
 impl Builder {
+    /// The build of the recipe at `recipe_path`, running `jobs` in parallel
+    /// rather than those the recipe allows & omitting its check phase when `skip_check`
+    ///
+    /// Fails with the macros its scripts use that aren't defined, unless
+    /// `skip_validation`, see [`validate`].
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         recipe_path: &Path,
         verify_against_manifest: Option<PathBuf>,
         env: Env,
         profile: profile::Id,
         ccache: bool,
+        ignore_arch: bool,
         output_dir: impl Into<PathBuf>,
+        jobs: Option<NonZeroUsize>,
+        skip_check: bool,
+        skip_validation: bool,
     ) -> Result<Self, Error> {
         let recipe = Recipe::load(recipe_path)?;
+        let jobs = Jobs::new(&recipe, jobs)?;
 
         let macros = Macros::load(&env)?;
 
         let paths = Paths::new(&recipe, verify_against_manifest, &env.cache_dir, "/mason", output_dir)?;
 
-        let build_targets = recipe.build_targets();
+        let mut build_targets = recipe.build_targets();
 
         if build_targets.is_empty() {
-            return Err(Error::NoBuildTargets);
+            if !ignore_arch {
+                return Err(Error::UnsupportedArchitecture {
+                    host: architecture::host(),
+                    supported: recipe.parsed.architectures.clone(),
+                });
+            }
+            build_targets.push(BuildTarget::Native(architecture::host()));
+        }
+
+        if !skip_validation {
+            let undefined = validate::check(&recipe, &macros, &build_targets, skip_check)?;
+            if !undefined.is_empty() {
+                return Err(Error::UndefinedMacros(undefined));
+            }
         }
 
         let targets = build_targets
             .into_iter()
             .map(|build_target| {
-                let stages = pgo::stages(&recipe, build_target)
+                let stages = pgo::stages(&recipe, build_target)?
                     .map(|stages| stages.into_iter().map(Some).collect::<Vec<_>>())
                     .unwrap_or_else(|| vec![None]);
 
                 let jobs = stages
                     .into_iter()
-                    .map(|stage| Job::new(build_target, stage, &recipe, &paths, &macros, ccache))
+                    .map(|stage| {
+                        Job::new(
+                            build_target,
+                            stage,
+                            &recipe,
+                            &paths,
+                            &macros,
+                            ccache,
+                            jobs.count,
+                            skip_check,
+                            skip_validation,
+                        )
+                    })
                     .collect::<Result<Vec<_>, _>>()?;
 
                 Ok(Target { build_target, jobs })
@@ -90,7 +186,13 @@ impl Builder {
         let upstreams = upstream::parse_recipe(&recipe)?;
 
         let profiles = profile::Manager::new(&env);
-        let repos = profiles.repositories(&profile)?.clone();
+        let selected = profiles.profile(&profile)?;
+        let repos = selected.repositories.clone();
+        let system_triggers = selected.system_triggers;
+        let strict_layout = selected.strict_layout;
+        let environment = Environment::new(&selected.environment);
+        let allowlist =
+            Allowlist::new(&selected.network_allowlist).merge(Allowlist::new(&recipe.parsed.options.network_allowlist));
 
         Ok(Self {
             targets,
@@ -98,9 +200,49 @@ impl Builder {
             paths,
             macros,
             ccache,
+            jobs,
             env,
+            resume: false,
+            shell_on_failure: false,
+            environment,
+            allowlist,
+            strict_layout,
+            skip_check,
             upstreams,
+            materials: sbom::Materials::default(),
+            dependencies: Explanation::default(),
+            repository_providers: BTreeSet::new(),
+            profile,
+            update_lock: false,
+            lock: None,
+            claim: None,
             repos,
+            system_triggers,
+        })
+    }
+
+    /// Also install build dependencies from `repositories`, i.e. the packages
+    /// built earlier in a batch
+    pub fn add_repositories(&mut self, repositories: repository::Map) {
+        self.repos = self.repos.clone().merge(repositories);
+    }
+
+    /// Whether the previous build of the recipe can be resumed, as its
+    /// rootfs remains & it completed some phases
+    pub fn can_resume(&self) -> bool {
+        let markers = resume::markers(&self.recipe, &self.paths.build().host);
+
+        self.paths.rootfs().host.join("usr").exists() && markers.any_complete(self.phases())
+    }
+
+    /// All phases of the build, in order
+    fn phases(&self) -> impl Iterator<Item = resume::Key> + '_ {
+        self.targets.iter().flat_map(|target| {
+            target.jobs.iter().flat_map(|job| {
+                job.phases
+                    .keys()
+                    .map(|phase| (job.target, job.pgo_stage.clone(), *phase))
+            })
         })
     }
 
@@ -115,19 +257,62 @@ impl Builder {
     }
 
     pub fn setup(
-        &self,
+        &mut self,
         timing: &mut Timing,
         initialize_timer: timing::Timer,
         update_repos: bool,
+        offline: bool,
     ) -> Result<Vec<upstream::Stored>, Error> {
+        self.claim = Some(self.claim_cache().map_err(Error::ClaimCache)?);
+
+        // Packages & upstreams are fetched through the proxies builds use
+        let proxies = self
+            .environment
+            .proxies()
+            .map(|(key, value)| (key.to_owned(), value.to_owned()))
+            .collect::<Vec<_>>();
+        moss::request::set_proxies(proxies.clone());
+        gitwrap::set_environment(proxies);
+
         // Recreate artifacts
         util::recreate_dir(&self.paths.artefacts().host).map_err(Error::RecreateArtefactsDir)?;
 
+        // Reuse the rootfs, upstreams & logs of the build resumed
+        if self.resume {
+            // Builds predating saved materials only know the upstreams
+            self.materials = sbom::Materials::load(&self.materials_path()).unwrap_or_else(|_| sbom::Materials {
+                upstreams: self.upstreams.iter().map(sbom::Upstream::from).collect(),
+                installed: vec![],
+                verifications: vec![],
+            });
+            self.dependencies = Explanation::load(&self.dependencies_path()).unwrap_or_default();
+            self.repository_providers = root::repository_providers(self, self.repos.clone(), offline)?;
+            self.key_compiler_caches();
+            timing.finish(initialize_timer);
+            return Ok(vec![]);
+        }
+        resume::markers(&self.recipe, &self.paths.build().host).clear()?;
+
+        // Recreate logs, phases append to them
+        util::recreate_dir(&self.paths.logs().host).map_err(Error::RecreateLogsDir)?;
+
+        // Fail before anything is installed when upstreams can't be fetched
+        if offline {
+            upstream::ensure_stored(&self.upstreams, &self.paths.upstreams().host)?;
+        }
+
         // Recreate rootfs
         root::recreate(self)?;
 
         // Populate rootfs
-        root::populate(self, self.repos.clone(), timing, initialize_timer, update_repos)?;
+        let (installed, dependencies, repository_providers) = root::populate(
+            self,
+            self.repos.clone(),
+            timing,
+            initialize_timer,
+            update_repos,
+            offline,
+        )?;
 
         let timer = timing.begin(timing::Kind::Fetch);
 
@@ -141,9 +326,170 @@ impl Builder {
 
         timing.finish(timer);
 
+        let previous = Lock::load(self.recipe_dir())?;
+        let fetched = Lock::new(&stored, previous.as_ref(), Utc::now())?;
+        if let Some(previous) = previous
+            && !self.update_lock
+        {
+            let drift = previous.drift(&fetched);
+            if !drift.is_empty() {
+                return Err(Error::LockDrift(drift));
+            }
+        }
+        self.lock = Some(fetched);
+
+        // Repositories as pinned to the commit their ref resolved to
+        let upstreams = self
+            .upstreams
+            .iter()
+            .map(|upstream| {
+                let resolved = stored.iter().find_map(|stored| match (upstream, stored) {
+                    (Upstream::Git(git), upstream::Stored::Git(resolved))
+                        if resolved.original_index == git.original_index =>
+                    {
+                        Some(sbom::Upstream::Git {
+                            url: git.url.clone(),
+                            commit: resolved.resolved_hash.clone(),
+                        })
+                    }
+                    (Upstream::Vcs(vcs), upstream::Stored::Vcs(resolved))
+                        if resolved.original_index == vcs.original_index =>
+                    {
+                        Some(sbom::Upstream::Vcs {
+                            vcs: vcs.kind,
+                            url: vcs.url.clone(),
+                            id: resolved.id.clone(),
+                        })
+                    }
+                    _ => None,
+                });
+                resolved.unwrap_or_else(|| sbom::Upstream::from(upstream))
+            })
+            .collect();
+        let verifications = stored
+            .iter()
+            .filter_map(upstream::Stored::verification)
+            .cloned()
+            .collect();
+        self.materials = sbom::Materials {
+            upstreams,
+            installed,
+            verifications,
+        };
+        self.key_compiler_caches();
+        self.materials
+            .save(&self.materials_path())
+            .map_err(Error::SaveMaterials)?;
+        self.dependencies = dependencies;
+        self.repository_providers = repository_providers;
+        self.dependencies
+            .save(&self.dependencies_path())
+            .map_err(Error::SaveMaterials)?;
+
         Ok(stored)
     }
 
+    /// Key the compiler caches by the compiler installed to the rootfs
+    fn key_compiler_caches(&mut self) {
+        let key = compiler_key(&self.recipe, &self.materials.installed);
+        self.paths.set_compiler(key);
+    }
+
+    /// Inputs of the bills of materials of the stones built
+    pub fn sbom_inputs(&self) -> sbom::Inputs<'_> {
+        sbom::Inputs {
+            source: &self.recipe.parsed.source,
+            materials: &self.materials,
+            created: self.recipe.build_time,
+            tool_version: tools_buildinfo::get_simple_version(),
+        }
+    }
+
+    /// Signatures of the upstreams verified by [`Builder::setup`], see [`upstream::verify`]
+    pub fn verifications(&self) -> &[upstream::verify::Verification] {
+        &self.materials.verifications
+    }
+
+    /// Hash of everything the stones built are from, once [`Builder::setup`], see [`inputs`]
+    pub fn input_hash(&self) -> Result<String, Error> {
+        let macros = Macros::version(&self.env)?;
+        let profile = self.profile.to_string();
+
+        Ok(inputs::Inputs {
+            recipe: &self.recipe.source,
+            macros: &macros,
+            profile: &profile,
+            upstreams: &self.materials.upstreams,
+            installed: &self.materials.installed,
+        }
+        .hash())
+    }
+
+    /// Archive the rootfs populated by [`Builder::setup`] to `path`, returning its manifest
+    pub fn export_root(&self, path: &Path) -> Result<archive::Manifest, Error> {
+        let manifest = archive::Manifest::new(&self.materials.installed);
+        let excluded = self.paths.bind_mounted();
+
+        archive::export(
+            &self.paths.rootfs().host,
+            &excluded.iter().map(PathBuf::as_path).collect::<Vec<_>>(),
+            &manifest,
+            path,
+        )
+        .map_err(Error::ExportRoot)?;
+
+        Ok(manifest)
+    }
+
+    /// Lock the upstreams fetched by [`Builder::setup`], once built
+    pub fn save_lock(&self) -> Result<(), Error> {
+        if let Some(lock) = &self.lock {
+            lock.save(self.recipe_dir())?;
+        }
+        Ok(())
+    }
+
+    fn recipe_dir(&self) -> &Path {
+        self.recipe.path.parent().unwrap_or(Path::new("."))
+    }
+
+    fn materials_path(&self) -> PathBuf {
+        self.paths.materials()
+    }
+
+    fn dependencies_path(&self) -> PathBuf {
+        self.paths.build().host.join("dependencies.json")
+    }
+
+    /// Claim the entries of the cache used by the build, see [`cache`]
+    fn claim_cache(&self) -> io::Result<cache::Claim> {
+        let paths = &self.paths;
+        let storage_dir = paths.upstreams().host;
+
+        let claimed = self
+            .upstreams
+            .iter()
+            .map(|upstream| upstream.stored_path(&storage_dir))
+            .chain(
+                [
+                    paths.rootfs(),
+                    paths.artefacts(),
+                    paths.build(),
+                    paths.logs(),
+                    paths.ccache(),
+                    paths.gocache(),
+                    paths.gomodcache(),
+                    paths.cargocache(),
+                    paths.zigcache(),
+                    paths.sccache(),
+                ]
+                .map(|mapping| mapping.host),
+            )
+            .collect();
+
+        cache::Claim::new(&self.env.cache_dir, claimed)
+    }
+
     pub fn cleanup(&self) -> Result<(), Error> {
         // Remove rootfs
         root::remove(self)?;
@@ -163,15 +509,18 @@ impl Builder {
 
         // Prune moss cache, retaining stones from the repos defined
         // by our boulder profile
-        moss::Client::builder("boulder", moss::Installation::open(&self.env.moss_dir, None)?)
-            .repositories(self.repos.clone())
-            .build()?
-            .prune_cache()?;
+        moss::Client::builder(
+            "boulder",
+            moss::Installation::open(&self.env.moss_dir, None)?.wait_for_lock(true),
+        )
+        .repositories(self.repos.clone())
+        .build()?
+        .prune_cache(false, false)?;
 
         Ok(())
     }
 
-    pub fn build(&self, timing: &mut Timing) -> Result<(), Error> {
+    pub fn build(&self, timing: &mut Timing, report: &mut BuildReport, logs: &mut Logs) -> Result<(), Error> {
         // Set ourselves into our own process group
         // and set it as fg term
         //
@@ -182,26 +531,43 @@ impl Builder {
         let pgid = getpgrp();
         ::container::set_term_fg(pgid)?;
 
+        let markers = resume::markers(&self.recipe, &self.paths.build().guest);
+        let mut resume = Resume::new(&markers, self.resume);
+
         for (i, target) in self.targets.iter().enumerate() {
             println!("{}", build_target_prefix(target.build_target, i));
 
             for (i, job) in target.jobs.iter().enumerate() {
                 let is_pgo = job.pgo_stage.is_some();
 
-                // Recreate work dir for each job
-                util::recreate_dir(&job.work_dir)?;
+                // Recreate work dir for each job, unless resuming its phases
+                let first_phase = job.phases.keys().next();
+                if !first_phase.is_some_and(|phase| resume.skips((job.target, job.pgo_stage.clone(), *phase))) {
+                    util::recreate_dir(&job.work_dir)?;
+                }
                 // Ensure pgo dir exists
                 if is_pgo {
                     let pgo_dir = PathBuf::from(format!("{}-pgo", job.build_dir.display()));
                     util::ensure_dir_exists(&pgo_dir)?;
                 }
 
-                if let Some(stage) = job.pgo_stage {
+                if let Some(stage) = &job.pgo_stage {
                     println!("{}", pgo_stage_prefix(stage, i));
                 }
 
                 for (i, (phase, script)) in job.phases.iter().enumerate() {
+                    let key = (job.target, job.pgo_stage.clone(), *phase);
+
+                    if resume.skip(key.clone()) {
+                        println!(
+                            "{} {}",
+                            phase_prefix(*phase, is_pgo, i),
+                            "(completed by the previous build)".dim()
+                        );
+                        continue;
+                    }
                     println!("{}", phase_prefix(*phase, is_pgo, i));
+                    resume.start(key.clone())?;
 
                     let build_dir = &job.build_dir;
                     let work_dir = &job.work_dir;
@@ -209,9 +575,12 @@ impl Builder {
 
                     let timer = timing.begin(timing::Kind::Build(timing::Build {
                         target: job.target,
-                        pgo_stage: job.pgo_stage,
+                        pgo_stage: job.pgo_stage.clone(),
                         phase: *phase,
                     }));
+                    let started = Instant::now();
+                    let mut usage = Usage::default();
+                    let mut status = None;
 
                     for command in &script.commands {
                         match command {
@@ -230,22 +599,7 @@ impl Builder {
                                     },
                                 );
 
-                                // Write env to $HOME/.profile
-                                fs::write(build_dir.join(".profile"), format_profile(script))?;
-
-                                let mut command = process::Command::new("/usr/bin/bash")
-                                    .arg("--login")
-                                    .env_clear()
-                                    .env("HOME", build_dir)
-                                    .env("PATH", "/usr/bin:/usr/sbin")
-                                    .env("TERM", "xterm-256color")
-                                    .current_dir(current_dir)
-                                    .spawn()?;
-
-                                command.wait()?;
-
-                                // Restore ourselves as fg term since bash steals it
-                                ::container::set_term_fg(pgid)?;
+                                shell(script, build_dir, current_dir, &self.environment, pgid)?;
 
                                 if breakpoint.exit {
                                     return Ok(());
@@ -256,16 +610,37 @@ impl Builder {
                                 let script_path = "/tmp/script";
                                 fs::write(script_path, content).unwrap();
 
-                                let result = logged(*phase, is_pgo, "/usr/bin/bash", |command| {
-                                    command
-                                        .arg(script_path)
-                                        .env_clear()
-                                        .env("HOME", build_dir)
-                                        .env("PATH", "/usr/bin:/usr/sbin")
-                                        .current_dir(current_dir)
-                                })?;
+                                let (result, script_usage) = logged(
+                                    job.target,
+                                    job.pgo_stage.as_ref(),
+                                    *phase,
+                                    logs,
+                                    "/usr/bin/bash",
+                                    |command| {
+                                        command
+                                            .arg(script_path)
+                                            .env_clear()
+                                            .envs(self.environment.iter())
+                                            .env("HOME", build_dir)
+                                            .env("PATH", "/usr/bin:/usr/sbin")
+                                            .current_dir(current_dir)
+                                    },
+                                )?;
+                                usage.add(script_usage);
+                                status = Some(result);
 
                                 if !result.success() {
+                                    logs.record(job.target, job.pgo_stage.as_ref(), *phase, status, started.elapsed())?;
+
+                                    if self.shell_on_failure {
+                                        println!(
+                                            "\n{} {}",
+                                            "Failed".red().bold(),
+                                            "(exit the shell to abort the build)".dim()
+                                        );
+                                        shell(script, build_dir, current_dir, &self.environment, pgid)?;
+                                    }
+
                                     match result.code() {
                                         Some(code) => {
                                             return Err(Error::Code(code));
@@ -288,6 +663,18 @@ impl Builder {
                     }
 
                     timing.finish(timer);
+                    logs.record(job.target, job.pgo_stage.as_ref(), *phase, status, started.elapsed())?;
+                    resume.complete(key)?;
+                    report.record(
+                        job.target,
+                        job.pgo_stage.clone(),
+                        *phase,
+                        Usage {
+                            wall: started.elapsed(),
+                            ..usage
+                        },
+                    );
+                    report.record_finished(job.target, job.pgo_stage.clone(), *phase, SystemTime::now());
                 }
             }
         }
@@ -304,7 +691,7 @@ pub fn build_target_prefix(target: BuildTarget, i: usize) -> String {
     format!("{newline}{}", target.to_string().dim())
 }
 
-pub fn pgo_stage_prefix(stage: pgo::Stage, i: usize) -> String {
+pub fn pgo_stage_prefix(stage: &pgo::Stage, i: usize) -> String {
     let newline = if i > 0 {
         format!("{}\n", "│".dim())
     } else {
@@ -321,12 +708,16 @@ pub fn phase_prefix(phase: job::Phase, is_pgo: bool, i: usize) -> String {
     format!("{newline}{pipes}{}", phase.styled(phase))
 }
 
+/// Run `command`, writing its output to the log of `phase` & the terminal
+/// unless [`Logs::quiet`]
 fn logged(
+    target: BuildTarget,
+    pgo_stage: Option<&pgo::Stage>,
     phase: job::Phase,
-    is_pgo: bool,
+    logs: &Logs,
     command: &str,
     f: impl FnOnce(&mut process::Command) -> &mut process::Command,
-) -> io::Result<process::ExitStatus> {
+) -> io::Result<(process::ExitStatus, Usage)> {
     let mut command = process::Command::new(command);
 
     f(&mut command);
@@ -337,13 +728,26 @@ fn logged(
         .spawn()?;
 
     // Log stdout and stderr
-    let stdout_log = log(phase, is_pgo, child.stdout.take().unwrap());
-    let stderr_log = log(phase, is_pgo, child.stderr.take().unwrap());
+    let is_pgo = pgo_stage.is_some();
+    let stdout_log = log(
+        phase,
+        is_pgo,
+        logs.quiet,
+        logs.open(target, pgo_stage, phase)?,
+        child.stdout.take().unwrap(),
+    );
+    let stderr_log = log(
+        phase,
+        is_pgo,
+        logs.quiet,
+        logs.open(target, pgo_stage, phase)?,
+        child.stderr.take().unwrap(),
+    );
 
     // Forward SIGINT to this process
     ::container::forward_sigint(Pid::from_raw(child.id() as i32))?;
 
-    let result = child.wait()?;
+    let result = wait(&child)?;
 
     let _ = stdout_log.join();
     let _ = stderr_log.join();
@@ -351,11 +755,33 @@ fn logged(
     Ok(result)
 }
 
-fn log<R>(phase: job::Phase, is_pgo: bool, pipe: R) -> thread::JoinHandle<()>
+/// Wait for `child` to exit, returning its status along with the resources
+/// it & its waited for children used
+fn wait(child: &process::Child) -> io::Result<(process::ExitStatus, Usage)> {
+    let mut status = 0;
+    // SAFETY: rusage is plain data, valid when zeroed
+    let mut rusage = unsafe { mem::zeroed::<libc::rusage>() };
+
+    loop {
+        // SAFETY: status & rusage outlive the call
+        if unsafe { libc::wait4(child.id() as libc::pid_t, &mut status, 0, &mut rusage) } >= 0 {
+            break;
+        }
+
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+
+    Ok((process::ExitStatus::from_raw(status), Usage::from_rusage(&rusage)))
+}
+
+fn log<R>(phase: job::Phase, is_pgo: bool, quiet: bool, mut file: fs::File, pipe: R) -> thread::JoinHandle<()>
 where
     R: io::Read + Send + 'static,
 {
-    use std::io::BufRead;
+    use std::io::{BufRead, Write};
 
     thread::spawn(move || {
         let pgo = if is_pgo { "│" } else { "" }.dim();
@@ -365,11 +791,57 @@ where
         let mut lines = io::BufReader::new(pipe).lines();
 
         while let Some(Ok(line)) = lines.next() {
-            println!("{tag} {line}");
+            // Whole lines in one write, as both pipes append to the file
+            let _ = file.write_all(format!("{line}\n").as_bytes());
+
+            if !quiet {
+                println!("{tag} {line}");
+            }
         }
     })
 }
 
+/// Open an interactive login shell in `current_dir`, with the environment,
+/// actions & definitions of `script` exported by `$HOME/.profile`
+fn shell(
+    script: &Script,
+    build_dir: &Path,
+    current_dir: &Path,
+    environment: &Environment,
+    pgid: Pid,
+) -> Result<(), Error> {
+    // Write env to $HOME/.profile
+    fs::write(build_dir.join(".profile"), format_profile(script))?;
+
+    let mut command = process::Command::new("/usr/bin/bash")
+        .arg("--login")
+        .env_clear()
+        .envs(environment.iter())
+        .env("HOME", build_dir)
+        .env("PATH", "/usr/bin:/usr/sbin")
+        .env("TERM", "xterm-256color")
+        .current_dir(current_dir)
+        .spawn()?;
+
+    command.wait()?;
+
+    // Restore ourselves as fg term since bash steals it
+    ::container::set_term_fg(pgid)?;
+
+    Ok(())
+}
+
+/// The key of the compiler caches of `recipe`, by its compiler among the `installed` packages
+pub fn compiler_key(recipe: &Recipe, installed: &[sbom::Installed]) -> paths::CompilerKey {
+    paths::CompilerKey::installed(
+        recipe.parsed.options.toolchain,
+        installed
+            .iter()
+            .map(|installed| (installed.name.as_str(), installed.version.as_str())),
+    )
+}
+
+/// Serialize the resolved environment of `script`, to be sourced by a shell
 pub fn format_profile(script: &Script) -> String {
     let env = script
         .env
@@ -400,6 +872,11 @@ fn breakpoint_line(
     build_target: BuildTarget,
     phase: job::Phase,
 ) -> Option<usize> {
+    script_line(recipe, build_target, phase).map(|line_num| line_num + breakpoint.line_num)
+}
+
+/// Line of the recipe the script of `phase` starts at, when building for `build_target`
+pub fn script_line(recipe: &Recipe, build_target: BuildTarget, phase: job::Phase) -> Option<usize> {
     let profile = recipe.build_target_profile_key(build_target);
 
     let has_key = |line: &str, key: &str| {
@@ -429,7 +906,7 @@ fn breakpoint_line(
         });
 
     let phase = match phase {
-        // Internal phase, no breakpoint will occur
+        // Internal phase, not scripted by the recipe
         job::Phase::Prepare => return None,
         job::Phase::Setup => "setup",
         job::Phase::Build => "build",
@@ -450,7 +927,7 @@ fn breakpoint_line(
                 line_num += 1;
             }
 
-            Some(line_num + breakpoint.line_num)
+            Some(line_num)
         } else {
             None
         }
@@ -459,18 +936,33 @@ fn breakpoint_line(
 
 #[derive(Debug, Error)]
 pub enum Error {
-    #[error("no supported build targets for recipe")]
-    NoBuildTargets,
+    #[error(
+        "recipe doesn't support {host}, only {}. Pass --ignore-arch to build it anyway",
+        supported.join(", ")
+    )]
+    UnsupportedArchitecture { host: Architecture, supported: Vec<String> },
     #[error("macros")]
     Macros(#[from] macros::Error),
+    #[error("{} macros used by the scripts of the recipe aren't defined", .0.len())]
+    UndefinedMacros(Vec<validate::Issue>),
     #[error("job")]
     Job(#[from] job::Error),
+    #[error("jobs")]
+    Jobs(#[from] jobs::Error),
     #[error("profile")]
     Profile(#[from] profile::Error),
     #[error("root")]
     Root(#[from] root::Error),
     #[error("upstream")]
     Upstream(#[from] upstream::Error),
+    #[error("upstream lock")]
+    Lock(#[from] lock::Error),
+    #[error(
+        "upstreams no longer match {}, pass --update-lock if intended: {}",
+        lock::FILE_NAME,
+        .0.iter().join("; ")
+    )]
+    LockDrift(Vec<lock::Drift>),
     #[error("container")]
     Container(#[from] container::Error),
     #[error("recipe")]
@@ -487,8 +979,101 @@ pub enum Error {
     Io(#[from] io::Error),
     #[error("recreate artefacts dir")]
     RecreateArtefactsDir(#[source] io::Error),
+    #[error("recreate logs dir")]
+    RecreateLogsDir(#[source] io::Error),
+    #[error("claim cache entries")]
+    ClaimCache(#[source] io::Error),
+    #[error("save build root materials")]
+    SaveMaterials(#[source] io::Error),
+    #[error("export build root")]
+    ExportRoot(#[source] io::Error),
     #[error("moss client")]
     MossClient(#[from] moss::client::Error),
     #[error("moss installation")]
     MossInstallation(#[from] moss::installation::Error),
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn script(env: Option<&str>, actions: &[(&str, &str)], definitions: &[(&str, &str)]) -> Script {
+        let map = |items: &[(&str, &str)]| {
+            items
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect::<BTreeMap<_, _>>()
+        };
+
+        Script {
+            commands: vec![],
+            dependencies: vec![],
+            env: env.map(ToOwned::to_owned),
+            resolved_actions: map(actions),
+            resolved_definitions: map(definitions),
+        }
+    }
+
+    #[test]
+    fn profile_env() {
+        let script = script(
+            Some("#!/bin/sh\nset -e\nset -x\nTERM=\"dumb\"\nexport CFLAGS=\"-O2\"\ncd /mason/build"),
+            &[],
+            &[],
+        );
+
+        // Shebang, shell options & the terminal of the build are left out
+        assert_eq!(format_profile(&script), "export CFLAGS=\"-O2\"\ncd /mason/build\n\n");
+    }
+
+    #[test]
+    fn profile_actions_definitions() {
+        let script = script(
+            None,
+            &[("make", "make -j4"), ("configure", "./configure \\\n    --prefix=/usr")],
+            &[("prefix", "/usr"), ("bindir", "/usr/bin")],
+        );
+
+        assert_eq!(
+            format_profile(&script).lines().collect::<Vec<_>>(),
+            [
+                "",
+                "a_configure() {",
+                "./configure \\",
+                "    --prefix=/usr",
+                "}",
+                "export -f a_configure",
+                "a_make() {",
+                "make -j4",
+                "}",
+                "export -f a_make",
+                "d_bindir=\"/usr/bin\"; export d_bindir",
+                "d_prefix=\"/usr\"; export d_prefix",
+            ]
+        );
+    }
+
+    #[test]
+    fn profile_sourced() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = script(
+            Some("#!/bin/sh\nset -e\nexport PHASE_VAR=\"phase\""),
+            &[("greet", "echo \"hello $1\"")],
+            &[("libdir", "/usr/lib")],
+        );
+        fs::write(dir.path().join(".profile"), format_profile(&script)).unwrap();
+
+        let output = process::Command::new("/bin/bash")
+            .arg("-c")
+            .arg(". \"$HOME/.profile\" && echo \"$PHASE_VAR $d_libdir\" && bash -c 'a_greet world'")
+            .env_clear()
+            .env("HOME", dir.path())
+            .output()
+            .unwrap();
+
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "phase /usr/lib\nhello world\n");
+    }
+}
@@ -0,0 +1,230 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Build roots exported as a reusable tarball, see `boulder build --export-root`
+//!
+//! The rootfs is streamed into a zstd compressed tar in path order, owners
+//! stored numerically, preceded by a manifest of the packages installed.
+//! Dirs bind mounted by the container are kept empty, so importing a root
+//! needs neither moss nor network access.
+
+use std::{
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use fs_err::{self as fs, File};
+use moss::util;
+use serde::{Deserialize, Serialize};
+use tar::{EntryType, Header};
+use walkdir::WalkDir;
+
+use crate::package::sbom::Installed;
+
+/// Path of the manifest within the archive, never unpacked into the rootfs
+pub const MANIFEST: &str = "boulder-root.json";
+
+/// Version of the manifest format written
+pub const VERSION: u32 = 1;
+
+const LEVEL: i32 = 3;
+
+/// The packages installed into an exported build root
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    /// Sorted by name
+    pub packages: Vec<Installed>,
+}
+
+impl Manifest {
+    pub fn new(installed: &[Installed]) -> Self {
+        let mut packages = installed.to_vec();
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Self {
+            version: VERSION,
+            packages,
+        }
+    }
+}
+
+/// Archive `rootfs` to `path` along with its `manifest`, returning the entries archived
+///
+/// The contents of the guest dirs `excluded` aren't archived, only the dirs themselves.
+pub fn export(rootfs: &Path, excluded: &[&Path], manifest: &Manifest, path: &Path) -> io::Result<usize> {
+    let excluded = excluded
+        .iter()
+        .map(|dir| rootfs.join(dir.strip_prefix("/").unwrap_or(dir)))
+        .collect::<Vec<_>>();
+
+    let mut encoder = zstd::Encoder::new(BufWriter::new(File::create(path)?), LEVEL)?;
+    encoder.multithread(util::num_cpus().get() as u32)?;
+
+    let mut archive = tar::Builder::new(encoder);
+    archive.follow_symlinks(false);
+
+    let json = serde_json::to_vec_pretty(manifest).map_err(io::Error::other)?;
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::Regular);
+    header.set_size(json.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(0);
+    archive.append_data(&mut header, MANIFEST, json.as_slice())?;
+
+    let mut entries = 0;
+    let mut walk = WalkDir::new(rootfs).min_depth(1).sort_by_file_name().into_iter();
+
+    while let Some(entry) = walk.next() {
+        let entry = entry?;
+        let path = entry.path();
+        let name = path.strip_prefix(rootfs).map_err(io::Error::other)?;
+
+        archive.append_path_with_name(path, name)?;
+        entries += 1;
+
+        if entry.file_type().is_dir() && excluded.iter().any(|dir| dir == path) {
+            walk.skip_current_dir();
+        }
+    }
+
+    archive.into_inner()?.finish()?.flush()?;
+
+    Ok(entries)
+}
+
+/// Unpack the archive at `path` as `rootfs`, replacing it, returning its manifest
+///
+/// Owners are only restored when running as root.
+pub fn import(path: &Path, rootfs: &Path) -> io::Result<Manifest> {
+    if rootfs.exists() {
+        fs::remove_dir_all(rootfs)?;
+    }
+    fs::create_dir_all(rootfs)?;
+
+    let mut archive = tar::Archive::new(zstd::Decoder::new(File::open(path)?)?);
+    archive.set_preserve_permissions(true);
+    archive.set_preserve_mtime(true);
+    archive.set_preserve_ownerships(util::is_root());
+
+    let mut manifest = None;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+
+        if entry.path()? == Path::new(MANIFEST) {
+            manifest = Some(serde_json::from_reader::<_, Manifest>(&mut entry).map_err(io::Error::other)?);
+        } else {
+            entry.unpack_in(rootfs)?;
+        }
+    }
+
+    let manifest = manifest.ok_or_else(|| io::Error::other(format!("{} has no {MANIFEST}", path.display())))?;
+    if manifest.version > VERSION {
+        return Err(io::Error::other(format!(
+            "{MANIFEST} version {} is newer than supported",
+            manifest.version
+        )));
+    }
+
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        fs::Permissions,
+        os::unix::fs::{PermissionsExt, symlink},
+    };
+
+    use super::*;
+
+    fn installed(name: &str) -> Installed {
+        Installed {
+            name: name.to_owned(),
+            version: "1.0".to_owned(),
+            source_release: 1,
+            build_release: 1,
+            architecture: "x86_64".to_owned(),
+            sha256: Some(format!("{name}-sha256")),
+        }
+    }
+
+    #[test]
+    fn export_and_import() {
+        let dir = tempfile::tempdir().unwrap();
+        let rootfs = dir.path().join("root");
+        for dir in ["usr/bin", "usr/lib", "etc/ccache", "mason/build/cache"] {
+            fs::create_dir_all(rootfs.join(dir)).unwrap();
+        }
+        fs::write(rootfs.join("usr/bin/bash"), "#!bash").unwrap();
+        fs::set_permissions(rootfs.join("usr/bin/bash"), Permissions::from_mode(0o755)).unwrap();
+        fs::write(rootfs.join("usr/lib/libc.so.6"), "libc").unwrap();
+        symlink("libc.so.6", rootfs.join("usr/lib/libc.so")).unwrap();
+        symlink("usr/bin", rootfs.join("bin")).unwrap();
+        // Bind mounted by the container
+        fs::write(rootfs.join("etc/ccache/ccache.conf"), "max_size = 5G").unwrap();
+        fs::write(rootfs.join("mason/build/cache/object.o"), "object").unwrap();
+
+        let manifest = Manifest::new(&[installed("glibc"), installed("bash")]);
+        assert_eq!(manifest.packages[0].name, "bash");
+
+        let excluded = [Path::new("/mason"), Path::new("/etc/ccache")];
+        let archive = dir.path().join("root.tar.zst");
+        assert_eq!(export(&rootfs, &excluded, &manifest, &archive).unwrap(), 10);
+
+        // Exported again byte for byte
+        let again = dir.path().join("again.tar.zst");
+        export(&rootfs, &excluded, &manifest, &again).unwrap();
+        assert_eq!(fs::read(&archive).unwrap(), fs::read(&again).unwrap());
+
+        let names = tar::Archive::new(zstd::Decoder::new(File::open(&archive).unwrap()).unwrap())
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().display().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                MANIFEST,
+                "bin",
+                "etc",
+                "etc/ccache",
+                "mason",
+                "usr",
+                "usr/bin",
+                "usr/bin/bash",
+                "usr/lib",
+                "usr/lib/libc.so",
+                "usr/lib/libc.so.6",
+            ]
+        );
+
+        // Replacing whatever was there
+        let imported = dir.path().join("imported");
+        fs::create_dir_all(imported.join("stale")).unwrap();
+        assert_eq!(import(&archive, &imported).unwrap(), manifest);
+
+        assert!(!imported.join("stale").exists());
+        assert!(!imported.join(MANIFEST).exists());
+        assert_eq!(fs::read_to_string(imported.join("usr/bin/bash")).unwrap(), "#!bash");
+        assert_eq!(
+            fs::metadata(imported.join("usr/bin/bash"))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777,
+            0o755
+        );
+        assert_eq!(
+            fs::read_link(imported.join("usr/lib/libc.so")).unwrap(),
+            Path::new("libc.so.6")
+        );
+        assert_eq!(fs::read_link(imported.join("bin")).unwrap(), Path::new("usr/bin"));
+        assert!(imported.join("mason").is_dir());
+        assert_eq!(fs::read_dir(imported.join("mason")).unwrap().count(), 0);
+        assert_eq!(fs::read_dir(imported.join("etc/ccache")).unwrap().count(), 0);
+
+        fs::write(&again, "not an archive").unwrap();
+        assert!(import(&again, &imported).is_err());
+    }
+}
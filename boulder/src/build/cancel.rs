@@ -0,0 +1,457 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Cleaning up after builds which were cancelled or crashed
+//!
+//! `SIGINT` & `SIGTERM` received while building are forwarded to the container,
+//! killing it on the second, see [`::container::forward_signals`]. Once it exits,
+//! [`cleanup`] unmounts whatever remains mounted within the build root, removes
+//! the partial stones & manifests of the artefacts dir and marks the phases left
+//! running as failed, see [`resume`].
+//!
+//! A build holds the [`Lock`] of its build root for as long as it runs, so one
+//! left behind by a build which crashed is cleaned up by the next.
+
+use std::{
+    io,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    process,
+};
+
+use fs_err as fs;
+use nix::{
+    errno::Errno,
+    fcntl::{FlockArg, flock},
+    mount::{MntFlags, umount2},
+};
+use thiserror::Error;
+
+use crate::{Paths, build::resume, container::network};
+
+/// Lock of a build root, held by the build using it
+#[derive(Debug)]
+pub struct Lock {
+    path: PathBuf,
+    _file: fs::File,
+}
+
+impl Lock {
+    /// Lock the build root of `paths` for this process, along with whether it was
+    /// left locked by a build which didn't exit cleanly
+    pub fn acquire(paths: &Paths) -> Result<(Self, bool), Error> {
+        let path = paths.build().host.join(".lock");
+
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        match flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+            Ok(()) => {}
+            Err(Errno::EWOULDBLOCK) => return Err(Error::Locked(path)),
+            Err(errno) => return Err(Error::Io(errno.into())),
+        }
+
+        // Removed once dropped, so any pid recorded is of a build which crashed
+        let stale = !fs::read_to_string(&path)?.trim().is_empty();
+        fs::write(&path, process::id().to_string())?;
+
+        Ok((Self { path, _file: file }, stale))
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// What [`cleanup`] cleaned up
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Cleanup {
+    pub unmounted: Vec<PathBuf>,
+    /// Partial stones & manifests
+    pub removed: Vec<PathBuf>,
+    /// Names of the markers of the phases marked as failed
+    pub failed: Vec<String>,
+}
+
+impl Cleanup {
+    pub fn is_empty(&self) -> bool {
+        self.unmounted.is_empty() && self.removed.is_empty() && self.failed.is_empty()
+    }
+}
+
+/// Clean up after a build using `paths` which didn't complete
+///
+/// The artefacts dir is recreated by each build & emptied once synced to the
+/// output dir, so any stones left within it are partial.
+pub fn cleanup(paths: &Paths, markers: &resume::Markers) -> Result<Cleanup, Error> {
+    let mut cleanup = Cleanup::default();
+
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo")?;
+    for mount in mounts_within(&mountinfo, &paths.rootfs().host) {
+        match umount2(&mount, MntFlags::MNT_DETACH) {
+            // Already unmounted along with its parent
+            Ok(()) | Err(Errno::EINVAL | Errno::ENOENT) => cleanup.unmounted.push(mount),
+            Err(errno) => return Err(Error::Unmount(mount, errno)),
+        }
+    }
+
+    cleanup.removed = remove_partial(&paths.artefacts().host)?;
+
+    // Left behind by the proxy of a killed container
+    let socket = paths.build().host.join(network::SOCKET);
+    if socket.exists() {
+        fs::remove_file(socket)?;
+    }
+
+    cleanup.failed = markers.fail_running()?;
+
+    Ok(cleanup)
+}
+
+/// Remove the stones & manifests of `dir`
+fn remove_partial(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut removed = vec![];
+
+    if !dir.exists() {
+        return Ok(removed);
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+        let manifest = name.starts_with("manifest.") && (name.ends_with(".bin") || name.ends_with(".jsonc"));
+        if manifest || name.ends_with(".stone") {
+            fs::remove_file(&path)?;
+            removed.push(path);
+        }
+    }
+
+    removed.sort();
+    Ok(removed)
+}
+
+/// Mount points of `mountinfo` within `root`, deepest first, as listed by
+/// `/proc/self/mountinfo`
+fn mounts_within(mountinfo: &str, root: &Path) -> Vec<PathBuf> {
+    let mut mounts = mountinfo
+        .lines()
+        .filter_map(|line| line.split(' ').nth(4))
+        .map(|point| PathBuf::from(unescape(point)))
+        .filter(|point| point.starts_with(root))
+        .collect::<Vec<_>>();
+
+    mounts.sort_by_key(|point| std::cmp::Reverse(point.components().count()));
+    mounts.dedup();
+    mounts
+}
+
+/// `field` of mountinfo, whose spaces, tabs, newlines & backslashes are escaped
+/// as octal, i.e. `\040`
+fn unescape(field: &str) -> String {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut rest = field;
+
+    while let Some(index) = rest.find('\\') {
+        unescaped.push_str(&rest[..index]);
+        let digits = rest
+            .get(index + 1..index + 4)
+            .filter(|digits| digits.bytes().all(|digit| matches!(digit, b'0'..=b'7')));
+        match digits.and_then(|digits| u8::from_str_radix(digits, 8).ok()) {
+            Some(byte) => {
+                unescaped.push(char::from(byte));
+                rest = &rest[index + 4..];
+            }
+            None => {
+                unescaped.push('\\');
+                rest = &rest[index + 1..];
+            }
+        }
+    }
+
+    unescaped.push_str(rest);
+    unescaped
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("io")]
+    Io(#[from] io::Error),
+    #[error("build root is in use by another build, remove {0:?} if it isn't")]
+    Locked(PathBuf),
+    #[error("unmount {0:?}")]
+    Unmount(PathBuf, #[source] Errno),
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        num::NonZeroUsize,
+        os::unix::process::ExitStatusExt,
+        process::Command,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use nix::{
+        sys::signal::{Signal, kill},
+        unistd::Pid,
+    };
+    use stone_recipe::script;
+
+    use super::*;
+    use crate::{
+        Env, Macros, Recipe,
+        architecture::{Architecture, BuildTarget},
+        build::job::{Job, Phase},
+    };
+
+    const NATIVE: BuildTarget = BuildTarget::Native(Architecture::X86_64);
+
+    const RECIPE: &str = "\
+name: example
+version: 1.0
+release: 1
+homepage: https://example.com
+license: MPL-2.0
+summary: example
+description: example
+";
+
+    fn paths(dir: &Path) -> (Paths, resume::Markers) {
+        let path = dir.join("stone.yaml");
+        fs::write(&path, RECIPE).unwrap();
+        let recipe = Recipe::load(&path).unwrap();
+        let paths = Paths::new(&recipe, None, dir, "/mason", dir.join("out")).unwrap();
+        let markers = resume::markers(&recipe, &paths.build().host);
+        (paths, markers)
+    }
+
+    /// Runs the phases of a build until `cancelled` runs, interrupting it like a
+    /// Ctrl-C with `signal`
+    fn cancel(paths: &Paths, markers: &resume::Markers, cancelled: Phase, signal: Signal) {
+        let phases = [Phase::Prepare, Phase::Setup, Phase::Build, Phase::Install, Phase::Check];
+        let resume = resume::Resume::new(markers, false);
+
+        for phase in phases {
+            let key = (NATIVE, None, phase);
+            resume.start(key.clone()).unwrap();
+
+            if phase == Phase::Install {
+                fs::write(paths.artefacts().host.join("example-1.0-1-1-x86_64.stone"), "partial").unwrap();
+            }
+
+            if phase == cancelled {
+                let mut script = Command::new("sleep").arg("30").spawn().unwrap();
+                kill(Pid::from_raw(script.id() as i32), signal).unwrap();
+                assert!(!script.wait().unwrap().success());
+                return;
+            }
+
+            resume.complete(key).unwrap();
+        }
+    }
+
+    #[test]
+    fn cancel_build() {
+        let dir = tempfile::tempdir().unwrap();
+        let (paths, markers) = paths(dir.path());
+
+        cancel(&paths, &markers, Phase::Build, Signal::SIGINT);
+        assert_eq!(
+            markers.state((NATIVE, None, Phase::Build)),
+            Some(resume::State::Running)
+        );
+
+        let cleanup = cleanup(&paths, &markers).unwrap();
+        assert_eq!(
+            cleanup,
+            Cleanup {
+                failed: vec!["x86_64-build".to_owned()],
+                ..Cleanup::default()
+            }
+        );
+        assert_eq!(markers.state((NATIVE, None, Phase::Build)), Some(resume::State::Failed));
+        assert!(markers.is_complete((NATIVE, None, Phase::Setup)));
+
+        // Resuming runs the failed phase again
+        let mut resume = resume::Resume::new(&markers, true);
+        assert!(resume.skip((NATIVE, None, Phase::Prepare)));
+        assert!(resume.skip((NATIVE, None, Phase::Setup)));
+        assert!(!resume.skip((NATIVE, None, Phase::Build)));
+
+        // Nothing is left to clean up
+        assert!(super::cleanup(&paths, &markers).unwrap().is_empty());
+    }
+
+    /// The job of a recipe whose build waits to be cancelled, with its guest paths
+    /// on the host so its scripts run outside of a container
+    fn job(dir: &Path) -> (Job, Paths, resume::Markers) {
+        let path = dir.join("stone.yaml");
+        fs::write(
+            &path,
+            format!("{RECIPE}build: |\n    echo $$ > \"$HOME/pid\"\n    exec sleep 30\n"),
+        )
+        .unwrap();
+        let recipe = Recipe::load(&path).unwrap();
+        let paths = Paths::new(&recipe, None, dir, dir, dir.join("out")).unwrap();
+        let markers = resume::markers(&recipe, &paths.build().host);
+
+        let env = Env::new(
+            Some(dir.join("cache")),
+            Some(dir.join("config")),
+            Some(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("data")),
+            Some(dir.join("moss")),
+        )
+        .unwrap();
+        let macros = Macros::load(&env).unwrap();
+        let job = Job::new(
+            NATIVE,
+            None,
+            &recipe,
+            &paths,
+            &macros,
+            false,
+            NonZeroUsize::MIN,
+            true,
+            false,
+        )
+        .unwrap();
+
+        (job, paths, markers)
+    }
+
+    #[test]
+    fn cancel_job() {
+        let dir = tempfile::tempdir().unwrap();
+        let (job, paths, markers) = job(dir.path());
+        let key = (job.target, job.pgo_stage.clone(), Phase::Build);
+
+        let resume = resume::Resume::new(&markers, false);
+        for phase in job.phases.keys().take_while(|phase| **phase != Phase::Build) {
+            let key = (job.target, job.pgo_stage.clone(), *phase);
+            resume.start(key.clone()).unwrap();
+            resume.complete(key).unwrap();
+        }
+        resume.start(key.clone()).unwrap();
+
+        // Run the build script as a build does, interrupted like a Ctrl-C once it starts
+        fs::create_dir_all(&job.build_dir).unwrap();
+        let script = dir.path().join("script");
+        let content = job.phases[&Phase::Build]
+            .commands
+            .iter()
+            .find_map(|command| match command {
+                script::Command::Content(content) => Some(content),
+                script::Command::Break(_) => None,
+            })
+            .unwrap();
+        fs::write(&script, content).unwrap();
+        let mut child = Command::new("/usr/bin/bash")
+            .arg(&script)
+            .env_clear()
+            .env("HOME", &job.build_dir)
+            .env("PATH", "/usr/bin:/usr/sbin")
+            .current_dir(&job.build_dir)
+            .stdout(process::Stdio::null())
+            .stderr(process::Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let pid = job.build_dir.join("pid");
+        let started = Instant::now();
+        let pid = loop {
+            if let Ok(pid) = fs::read_to_string(&pid)
+                && let Ok(pid) = pid.trim().parse()
+            {
+                break pid;
+            }
+            assert!(started.elapsed() < Duration::from_secs(10), "build script didn't start");
+            thread::sleep(Duration::from_millis(10));
+        };
+        kill(Pid::from_raw(pid), Signal::SIGINT).unwrap();
+        assert_eq!(child.wait().unwrap().signal(), Some(Signal::SIGINT as i32));
+
+        let cleanup = cleanup(&paths, &markers).unwrap();
+        assert_eq!(cleanup.failed, ["x86_64-build"]);
+        assert_eq!(markers.state(key.clone()), Some(resume::State::Failed));
+
+        // Resuming runs the cancelled phase again
+        let mut resume = resume::Resume::new(&markers, true);
+        assert!(!resume.skip(key));
+    }
+
+    #[test]
+    fn cancel_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let (paths, markers) = paths(dir.path());
+        let manifest = paths.artefacts().host.join("manifest.x86_64.jsonc");
+        fs::write(&manifest, "{}").unwrap();
+        let socket = paths.build().host.join(network::SOCKET);
+        fs::write(&socket, "").unwrap();
+
+        cancel(&paths, &markers, Phase::Check, Signal::SIGTERM);
+
+        let cleanup = cleanup(&paths, &markers).unwrap();
+        assert_eq!(
+            cleanup.removed,
+            [
+                paths.artefacts().host.join("example-1.0-1-1-x86_64.stone"),
+                manifest.clone()
+            ]
+        );
+        assert_eq!(cleanup.failed, ["x86_64-check"]);
+        assert!(!manifest.exists());
+        assert!(!socket.exists());
+        assert!(markers.is_complete((NATIVE, None, Phase::Install)));
+    }
+
+    #[test]
+    fn stale_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let (paths, _) = paths(dir.path());
+        let path = paths.build().host.join(".lock");
+
+        let (lock, stale) = Lock::acquire(&paths).unwrap();
+        assert!(!stale);
+        assert_eq!(fs::read_to_string(&path).unwrap(), process::id().to_string());
+        assert!(matches!(Lock::acquire(&paths), Err(Error::Locked(_))));
+        drop(lock);
+        assert!(!path.exists());
+
+        // Left behind by a build which crashed
+        fs::write(&path, "4242").unwrap();
+        let (_lock, stale) = Lock::acquire(&paths).unwrap();
+        assert!(stale);
+    }
+
+    #[test]
+    fn mounts() {
+        let mountinfo = r"
+22 1 0:21 / / rw,relatime shared:1 - ext4 /dev/sda1 rw
+36 22 0:32 / /var/cache/boulder/root/example rw - overlay overlay rw
+37 36 0:33 / /var/cache/boulder/root/example/proc rw - proc proc rw
+38 36 0:34 / /var/cache/boulder/root/example/mason/with\040space rw - tmpfs tmpfs rw
+39 37 0:35 / /var/cache/boulder/root/example/proc/sys/fs/binfmt_misc rw - binfmt_misc binfmt_misc rw
+40 22 0:36 / /var/cache/boulder/root/example-devel rw - tmpfs tmpfs rw
+";
+        let root = Path::new("/var/cache/boulder/root/example");
+
+        let mounts = mounts_within(mountinfo, root);
+        assert_eq!(
+            mounts,
+            [
+                root.join("proc/sys/fs/binfmt_misc"),
+                root.join("mason/with space"),
+                root.join("proc"),
+                root.to_owned(),
+            ]
+        );
+        assert_eq!(unescape(r"tab\011and\\slash\0"), "tab\tand\\\\slash\\0");
+    }
+}
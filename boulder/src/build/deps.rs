@@ -0,0 +1,237 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Why each package of the rootfs was installed
+//!
+//! Every dependency the build requests resolves to a package, which pulls in
+//! those it depends on in turn. Printed as a tree, each package's
+//! dependencies are only expanded where it first appears.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write as _,
+    io,
+    path::Path,
+};
+
+use fs_err as fs;
+use itertools::Itertools;
+use moss::{client::install, package};
+use serde::{Deserialize, Serialize};
+
+/// The packages installed into the rootfs, by the dependency pulling them in
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Explanation {
+    /// Each dependency requested by the build, in order
+    pub requested: Vec<Requested>,
+    /// Names of the packages each installed package depends on
+    pub dependencies: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Requested {
+    /// As requested, i.e. `binary(cmake)`
+    pub dependency: String,
+    /// Name of the package it resolved to
+    pub package: String,
+    /// Every package it pulls in, itself included
+    pub closure: Vec<String>,
+}
+
+impl Explanation {
+    /// Explain the `graph` resolved by installing `packages`
+    pub fn new(graph: &install::Graph, packages: &[moss::Package]) -> Self {
+        let names = packages
+            .iter()
+            .map(|package| (&package.id, package.meta.name.to_string()))
+            .collect::<BTreeMap<_, _>>();
+        let name = |id: &package::Id| names.get(id).cloned().unwrap_or_else(|| id.to_string());
+
+        let dependencies = graph
+            .dependencies
+            .iter()
+            .map(|(id, dependencies)| (name(id), dependencies.iter().map(name).sorted().collect()))
+            .collect::<BTreeMap<_, Vec<_>>>();
+
+        let requested = graph
+            .requested
+            .iter()
+            .map(|(dependency, id)| {
+                let package = name(id);
+                Requested {
+                    dependency: dependency.clone(),
+                    closure: closure(&dependencies, &package),
+                    package,
+                }
+            })
+            .collect();
+
+        Self {
+            requested,
+            dependencies,
+        }
+    }
+
+    /// Render as a tree of the requested dependencies
+    pub fn render(&self) -> String {
+        let mut tree = String::new();
+        let mut expanded = BTreeSet::new();
+
+        for requested in &self.requested {
+            let _ = write!(tree, "{}", requested.dependency);
+            if requested.dependency != requested.package {
+                let _ = write!(tree, " → {}", requested.package);
+            }
+            if requested.closure.len() > 1 {
+                let _ = write!(tree, " (+{})", requested.closure.len() - 1);
+            }
+
+            if self.expand(&requested.package, &mut expanded) {
+                tree.push('\n');
+                self.render_dependencies(&requested.package, "", &mut expanded, &mut tree);
+            } else {
+                tree.push_str(" (*)\n");
+            }
+        }
+
+        let _ = writeln!(tree, "\n{} packages installed", self.dependencies.len());
+        if tree.contains(" (*)\n") {
+            tree.push_str("(*) dependencies listed above\n");
+        }
+
+        tree
+    }
+
+    fn render_dependencies(&self, package: &str, prefix: &str, expanded: &mut BTreeSet<String>, tree: &mut String) {
+        let dependencies = self.dependencies.get(package).map(Vec::as_slice).unwrap_or_default();
+
+        for (i, dependency) in dependencies.iter().enumerate() {
+            let (branch, indent) = if i + 1 == dependencies.len() {
+                ("└─", "   ")
+            } else {
+                ("├─", "│  ")
+            };
+
+            if self.expand(dependency, expanded) {
+                let _ = writeln!(tree, "{prefix}{branch} {dependency}");
+                self.render_dependencies(dependency, &format!("{prefix}{indent}"), expanded, tree);
+            } else {
+                let _ = writeln!(tree, "{prefix}{branch} {dependency} (*)");
+            }
+        }
+    }
+
+    /// Whether the dependencies of `package` are yet to be rendered, now marked as they are
+    ///
+    /// Packages without dependencies are always expanded.
+    fn expand(&self, package: &str, expanded: &mut BTreeSet<String>) -> bool {
+        let leaf = self.dependencies.get(package).is_none_or(Vec::is_empty);
+        expanded.insert(package.to_owned()) || leaf
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        serde_json::from_slice(&fs::read(path)?).map_err(io::Error::other)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self).map_err(io::Error::other)?)
+    }
+}
+
+/// `package` & every package it transitively depends on, sorted
+fn closure(dependencies: &BTreeMap<String, Vec<String>>, package: &str) -> Vec<String> {
+    let mut closure = BTreeSet::new();
+    let mut next = vec![package];
+
+    while let Some(package) = next.pop() {
+        if closure.insert(package.to_owned()) {
+            next.extend(dependencies.get(package).into_iter().flatten().map(String::as_str));
+        }
+    }
+
+    closure.into_iter().collect()
+}
+
+#[cfg(test)]
+mod test {
+    use moss::package::{Flags, fixture};
+
+    use super::*;
+
+    fn package(id: &str) -> moss::Package {
+        moss::Package {
+            id: package::Id::from(format!("{id}-hash")),
+            meta: fixture::meta(id),
+            flags: Flags::new().with_available(),
+        }
+    }
+
+    fn explanation() -> Explanation {
+        let packages = ["cmake", "curl", "zlib", "make", "ninja", "openssl"].map(package);
+        let id = |name: &str| package::Id::from(format!("{name}-hash"));
+
+        let graph = install::Graph {
+            requested: vec![
+                ("binary(cmake)".to_owned(), id("cmake")),
+                ("curl".to_owned(), id("curl")),
+                ("ninja".to_owned(), id("ninja")),
+            ],
+            dependencies: BTreeMap::from([
+                (id("cmake"), vec![id("make"), id("curl")]),
+                (id("curl"), vec![id("zlib"), id("openssl")]),
+                (id("openssl"), vec![id("zlib")]),
+                (id("make"), vec![]),
+                (id("ninja"), vec![]),
+                (id("zlib"), vec![]),
+            ]),
+        };
+
+        Explanation::new(&graph, &packages)
+    }
+
+    #[test]
+    fn explain() {
+        let explanation = explanation();
+
+        assert_eq!(
+            explanation.requested[0],
+            Requested {
+                dependency: "binary(cmake)".to_owned(),
+                package: "cmake".to_owned(),
+                closure: ["cmake", "curl", "make", "openssl", "zlib"].map(String::from).to_vec(),
+            }
+        );
+        assert_eq!(explanation.requested[2].closure, ["ninja"]);
+        assert_eq!(explanation.dependencies["cmake"], ["curl", "make"]);
+    }
+
+    #[test]
+    fn render() {
+        assert_eq!(
+            explanation().render(),
+            "\
+binary(cmake) → cmake (+4)
+├─ curl
+│  ├─ openssl
+│  │  └─ zlib
+│  └─ zlib
+└─ make
+curl (+2) (*)
+ninja
+
+6 packages installed
+(*) dependencies listed above
+"
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dependencies.json");
+
+        let explanation = explanation();
+        explanation.save(&path).unwrap();
+        assert_eq!(Explanation::load(&path).unwrap(), explanation);
+    }
+}
@@ -0,0 +1,517 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Whether building a recipe again can change anything
+//!
+//! Everything a build is from is hashed into the meta of each stone built, and
+//! the stones of each hash are indexed locally. A recipe whose inputs hash like
+//! those of a stone already in the target repository needn't be built again.
+
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use fs_err as fs;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use stone::{StonePayloadMetaPrimitive, StonePayloadMetaTag};
+
+use crate::package::sbom;
+
+/// File name of the [`Index`], within the cache dir
+pub const INDEX_FILE: &str = "build-inputs.json";
+
+/// Everything the stones of a build are built from
+#[derive(Debug, Clone, Copy)]
+pub struct Inputs<'a> {
+    /// Contents of the recipe file
+    pub recipe: &'a str,
+    /// See [`crate::Macros::version`]
+    pub macros: &'a str,
+    pub profile: &'a str,
+    /// As fetched, Git upstreams by the commit their ref resolved to
+    pub upstreams: &'a [sbom::Upstream],
+    /// Packages of the build root
+    pub installed: &'a [sbom::Installed],
+}
+
+impl Inputs<'_> {
+    /// SHA256 of the inputs, as hex
+    ///
+    /// Each field is labelled & length prefixed, so moving bytes from one
+    /// field to the next changes the hash. The order of the upstreams is that
+    /// they're unpacked in, whereas packages are installed in any order.
+    pub fn hash(&self) -> String {
+        let Self {
+            recipe,
+            macros,
+            profile,
+            upstreams,
+            installed,
+        } = self;
+
+        let mut hasher = Sha256::new();
+        let mut field = |label: &str, value: &str| {
+            for bytes in [label, value] {
+                hasher.update((bytes.len() as u64).to_le_bytes());
+                hasher.update(bytes);
+            }
+        };
+
+        field("recipe", recipe);
+        field("macros", macros);
+        field("profile", profile);
+
+        for upstream in *upstreams {
+            match upstream {
+                sbom::Upstream::Plain { url, sha256 } => {
+                    field("plain", url.as_str());
+                    field("sha256", sha256);
+                }
+                sbom::Upstream::Git { url, commit } => {
+                    field("git", url.as_str());
+                    field("commit", commit);
+                }
+                sbom::Upstream::Vcs { vcs, url, id } => {
+                    field(vcs.command(), url.as_str());
+                    field("id", id);
+                }
+            }
+        }
+
+        let installed = installed
+            .iter()
+            .map(|package| {
+                (
+                    &package.name,
+                    &package.version,
+                    package.source_release,
+                    package.build_release,
+                    &package.architecture,
+                )
+            })
+            .sorted();
+        for (name, version, source_release, build_release, architecture) in installed {
+            field("installed", name);
+            field("version", version);
+            field("source-release", &source_release.to_string());
+            field("build-release", &build_release.to_string());
+            field("architecture", architecture);
+        }
+
+        hex::encode(hasher.finalize())
+    }
+}
+
+/// Stones built from each hash of inputs, by file name
+///
+/// Only a shortcut to finding the stones of a hash, each is still verified
+/// by its meta.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Index {
+    builds: BTreeMap<String, Vec<String>>,
+}
+
+impl Index {
+    /// The index at `path`, empty if it's missing or unreadable
+    pub fn load(path: &Path) -> Self {
+        fs::read(path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self).map_err(io::Error::other)?)
+    }
+
+    /// Record the `stones` built from inputs hashing to `hash`, replacing
+    /// those of other hashes by the same file names
+    pub fn record(&mut self, hash: &str, stones: impl IntoIterator<Item = String>) {
+        let stones = stones.into_iter().sorted().collect::<Vec<_>>();
+
+        self.builds.retain(|_, built| {
+            built.retain(|stone| !stones.contains(stone));
+            !built.is_empty()
+        });
+        self.builds.insert(hash.to_owned(), stones);
+    }
+}
+
+/// A stone of `dir` built from inputs hashing to `hash`
+///
+/// The stones `index` lists for the hash are checked before every other stone of `dir`.
+pub fn find(dir: &Path, hash: &str, index: &Index) -> Option<PathBuf> {
+    let indexed = index
+        .builds
+        .get(hash)
+        .into_iter()
+        .flatten()
+        .map(|stone| dir.join(stone))
+        .collect::<Vec<_>>();
+
+    let others = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "stone") && !indexed.contains(path))
+        .sorted();
+
+    indexed
+        .iter()
+        .cloned()
+        .chain(others)
+        .find(|path| stone_hash(path).as_deref() == Some(hash))
+}
+
+/// Hash of the inputs of the stone at `path`, if it records one
+pub fn stone_hash(path: &Path) -> Option<String> {
+    let mut reader = stone::read(fs::File::open(path).ok()?).ok()?;
+    let payloads = reader.payloads().ok()?.collect::<Result<Vec<_>, _>>().ok()?;
+
+    payloads
+        .iter()
+        .find_map(|payload| payload.meta())?
+        .body
+        .iter()
+        .find_map(|record| match (&record.tag, &record.primitive) {
+            (StonePayloadMetaTag::BuildInputs, StonePayloadMetaPrimitive::String(hash)) => Some(hash.clone()),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use moss::package::{Meta, fixture};
+    use stone::StonePayloadMetaRecord;
+
+    use super::*;
+
+    fn upstreams() -> Vec<sbom::Upstream> {
+        vec![
+            sbom::Upstream::Plain {
+                url: "https://example.com/nano-8.7.tar.xz".parse().unwrap(),
+                sha256: "cbf684b5a37a3a433e0526beb04a7b3419b71e81b3709c3b0d7ed6a1987d3dcb".to_owned(),
+            },
+            sbom::Upstream::Git {
+                url: "https://example.com/nano-extras.git".parse().unwrap(),
+                commit: "0e0526beb04a7b3419b71e81b3709c3b0d7ed6a1".to_owned(),
+            },
+        ]
+    }
+
+    fn installed(name: &str, version: &str) -> sbom::Installed {
+        sbom::Installed {
+            name: name.to_owned(),
+            version: version.to_owned(),
+            source_release: 1,
+            build_release: 1,
+            architecture: "x86_64".to_owned(),
+            sha256: None,
+        }
+    }
+
+    #[test]
+    fn hash_every_input() {
+        let upstreams = upstreams();
+        let packages = [installed("glibc", "2.41"), installed("ncurses", "6.5")];
+        let base = Inputs {
+            recipe: "name: nano\n",
+            macros: "6b1f",
+            profile: "default-x86_64",
+            upstreams: &upstreams,
+            installed: &packages,
+        };
+        let hash = base.hash();
+
+        // Deterministic & hex encoded SHA256
+        assert_eq!(base.hash(), hash);
+        assert_eq!(hash.len(), 64);
+
+        let mut other_plain = upstreams.clone();
+        other_plain[0] = sbom::Upstream::Plain {
+            url: "https://example.com/nano-8.7.tar.xz".parse().unwrap(),
+            sha256: "0".repeat(64),
+        };
+        let mut other_url = upstreams.clone();
+        other_url[0] = sbom::Upstream::Plain {
+            url: "https://mirror.example.com/nano-8.7.tar.xz".parse().unwrap(),
+            sha256: "cbf684b5a37a3a433e0526beb04a7b3419b71e81b3709c3b0d7ed6a1987d3dcb".to_owned(),
+        };
+        let mut other_commit = upstreams.clone();
+        other_commit[1] = sbom::Upstream::Git {
+            url: "https://example.com/nano-extras.git".parse().unwrap(),
+            commit: "1".repeat(40),
+        };
+        let reordered = upstreams.iter().rev().cloned().collect::<Vec<_>>();
+        let fewer_upstreams = upstreams[..1].to_vec();
+
+        let newer = [installed("glibc", "2.42"), installed("ncurses", "6.5")];
+        let rebuilt = [
+            sbom::Installed {
+                build_release: 2,
+                ..installed("glibc", "2.41")
+            },
+            installed("ncurses", "6.5"),
+        ];
+        let released = [
+            sbom::Installed {
+                source_release: 2,
+                ..installed("glibc", "2.41")
+            },
+            installed("ncurses", "6.5"),
+        ];
+        let emul32 = [
+            sbom::Installed {
+                architecture: "emul32".to_owned(),
+                ..installed("glibc", "2.41")
+            },
+            installed("ncurses", "6.5"),
+        ];
+        let renamed = [installed("glibc-devel", "2.41"), installed("ncurses", "6.5")];
+        let more = [
+            installed("glibc", "2.41"),
+            installed("ncurses", "6.5"),
+            installed("zlib", "1.3"),
+        ];
+        let fewer = [installed("glibc", "2.41")];
+
+        let changed = [
+            (
+                "recipe",
+                Inputs {
+                    recipe: "name: nano\n#\n",
+                    ..base
+                },
+            ),
+            ("macros", Inputs { macros: "6b1e", ..base }),
+            (
+                "profile",
+                Inputs {
+                    profile: "local-x86_64",
+                    ..base
+                },
+            ),
+            (
+                "upstream hash",
+                Inputs {
+                    upstreams: &other_plain,
+                    ..base
+                },
+            ),
+            (
+                "upstream url",
+                Inputs {
+                    upstreams: &other_url,
+                    ..base
+                },
+            ),
+            (
+                "upstream commit",
+                Inputs {
+                    upstreams: &other_commit,
+                    ..base
+                },
+            ),
+            (
+                "upstream order",
+                Inputs {
+                    upstreams: &reordered,
+                    ..base
+                },
+            ),
+            (
+                "upstream removed",
+                Inputs {
+                    upstreams: &fewer_upstreams,
+                    ..base
+                },
+            ),
+            (
+                "package version",
+                Inputs {
+                    installed: &newer,
+                    ..base
+                },
+            ),
+            (
+                "package build release",
+                Inputs {
+                    installed: &rebuilt,
+                    ..base
+                },
+            ),
+            (
+                "package source release",
+                Inputs {
+                    installed: &released,
+                    ..base
+                },
+            ),
+            (
+                "package architecture",
+                Inputs {
+                    installed: &emul32,
+                    ..base
+                },
+            ),
+            (
+                "package name",
+                Inputs {
+                    installed: &renamed,
+                    ..base
+                },
+            ),
+            (
+                "package added",
+                Inputs {
+                    installed: &more,
+                    ..base
+                },
+            ),
+            (
+                "package removed",
+                Inputs {
+                    installed: &fewer,
+                    ..base
+                },
+            ),
+        ];
+
+        let mut seen = BTreeMap::from([(hash, "base")]);
+        for (change, inputs) in changed {
+            if let Some(other) = seen.insert(inputs.hash(), change) {
+                panic!("{change} hashes like {other}");
+            }
+        }
+    }
+
+    #[test]
+    fn hash_boundaries() {
+        let base = Inputs {
+            recipe: "name: nano",
+            macros: "",
+            profile: "",
+            upstreams: &[],
+            installed: &[],
+        };
+
+        // Bytes moved between fields
+        let shifted = [
+            Inputs {
+                recipe: "name: nan",
+                macros: "o",
+                ..base
+            },
+            Inputs {
+                recipe: "",
+                profile: "name: nano",
+                ..base
+            },
+            Inputs {
+                recipe: "",
+                macros: "name: nano",
+                ..base
+            },
+        ];
+        for inputs in shifted {
+            assert_ne!(inputs.hash(), base.hash());
+        }
+        assert_ne!(shifted[1].hash(), shifted[2].hash());
+
+        // Installed packages hash in any order
+        let packages = [installed("glibc", "2.41"), installed("ncurses", "6.5")];
+        let reversed = [installed("ncurses", "6.5"), installed("glibc", "2.41")];
+        assert_eq!(
+            Inputs {
+                installed: &packages,
+                ..base
+            }
+            .hash(),
+            Inputs {
+                installed: &reversed,
+                ..base
+            }
+            .hash()
+        );
+
+        // Package fields don't run into each other
+        let split = [installed("nano-8", "7")];
+        let joined = [installed("nano", "8-7")];
+        assert_ne!(
+            Inputs {
+                installed: &split,
+                ..base
+            }
+            .hash(),
+            Inputs {
+                installed: &joined,
+                ..base
+            }
+            .hash()
+        );
+    }
+
+    #[test]
+    fn find_stones() {
+        let dir = tempfile::tempdir().unwrap();
+        let index_path = dir.path().join(INDEX_FILE);
+
+        let nano = |version: &str, source_release| {
+            Meta {
+                version_identifier: version.to_owned(),
+                source_release,
+                ..fixture::meta("nano")
+            }
+            .to_stone_payload()
+        };
+        let built_from = |hash: &str| StonePayloadMetaRecord {
+            tag: StonePayloadMetaTag::BuildInputs,
+            primitive: StonePayloadMetaPrimitive::String(hash.to_owned()),
+        };
+        fixture::stone(dir.path(), &[nano("8.7", 1), vec![built_from("aaaa")]].concat(), &[]);
+        fixture::stone(dir.path(), &[nano("8.7", 2), vec![built_from("bbbb")]].concat(), &[]);
+        fixture::stone(dir.path(), &nano("8.6", 1), &[]);
+        fs::write(dir.path().join("nano-8.7-3-1-x86_64.stone"), "not a stone").unwrap();
+
+        assert_eq!(
+            stone_hash(&dir.path().join("nano-8.7-2-1-x86_64.stone")).as_deref(),
+            Some("bbbb")
+        );
+        assert_eq!(stone_hash(&dir.path().join("nano-8.6-1-1-x86_64.stone")), None);
+        assert_eq!(stone_hash(&dir.path().join("nano-8.7-3-1-x86_64.stone")), None);
+
+        // Found without the index
+        let mut index = Index::load(&index_path);
+        assert_eq!(index, Index::default());
+        assert_eq!(
+            find(dir.path(), "bbbb", &index),
+            Some(dir.path().join("nano-8.7-2-1-x86_64.stone"))
+        );
+        assert_eq!(find(dir.path(), "cccc", &index), None);
+
+        // An index listing a stone no longer built from the hash isn't trusted
+        index.record("cccc", ["nano-8.7-1-1-x86_64.stone".to_owned()]);
+        assert_eq!(find(dir.path(), "cccc", &index), None);
+        assert_eq!(
+            find(dir.path(), "aaaa", &index),
+            Some(dir.path().join("nano-8.7-1-1-x86_64.stone"))
+        );
+
+        // Stones rebuilt from other inputs replace their previous entries
+        index.record("aaaa", ["nano-8.7-1-1-x86_64.stone".to_owned()]);
+        index.record("bbbb", ["nano-8.7-2-1-x86_64.stone".to_owned()]);
+        assert_eq!(index.builds.keys().collect::<Vec<_>>(), ["aaaa", "bbbb"]);
+
+        index.save(&index_path).unwrap();
+        assert_eq!(Index::load(&index_path), index);
+        assert_eq!(
+            find(dir.path(), "bbbb", &index),
+            find(dir.path(), "bbbb", &Index::default())
+        );
+        assert_eq!(find(&dir.path().join("missing"), "bbbb", &index), None);
+    }
+}
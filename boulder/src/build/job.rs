@@ -4,6 +4,7 @@
 use std::{
     collections::BTreeMap,
     io,
+    num::NonZeroUsize,
     path::{Path, PathBuf},
 };
 
@@ -14,9 +15,9 @@ use stone_recipe::{
 };
 use thiserror::Error;
 
-pub use self::phase::Phase;
+pub use self::phase::{Phase, builtin_parser, list as phases, recipe_tuning};
 use crate::build::pgo;
-use crate::{Macros, Paths, Recipe, architecture::BuildTarget};
+use crate::{Macros, Paths, Recipe, architecture::BuildTarget, macros};
 
 mod phase;
 
@@ -30,6 +31,7 @@ pub struct Job {
 }
 
 impl Job {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         target: BuildTarget,
         pgo_stage: Option<pgo::Stage>,
@@ -37,15 +39,18 @@ impl Job {
         paths: &Paths,
         macros: &Macros,
         ccache: bool,
+        jobs: NonZeroUsize,
+        skip_check: bool,
+        lenient: bool,
     ) -> Result<Self, Error> {
         let build_dir = paths.build().guest.join(target.to_string());
         let work_dir = work_dir(&build_dir, &recipe.parsed.upstreams);
 
-        let phases = phase::list(pgo_stage)
+        let phases = phase::list(pgo_stage.as_ref(), skip_check)
             .into_iter()
             .filter_map(|phase| {
                 let result = phase
-                    .script(target, pgo_stage, recipe, paths, macros, ccache)
+                    .script(target, pgo_stage.as_ref(), recipe, paths, macros, ccache, jobs, lenient)
                     .transpose()?;
                 Some(result.map(|script| (phase, script)))
             })
@@ -67,7 +72,7 @@ fn work_dir(build_dir: &Path, upstreams: &[Upstream]) -> PathBuf {
     // Work dir is the first upstream that should be unpacked
     if let Some(upstream) = upstreams.iter().find(|upstream| match upstream.props {
         upstream::Props::Plain { unpack, .. } => unpack,
-        upstream::Props::Git { .. } => true,
+        upstream::Props::Git { .. } | upstream::Props::Vcs { .. } => true,
     }) {
         match &upstream.props {
             upstream::Props::Plain { rename, unpack_dir, .. } => {
@@ -80,7 +85,7 @@ fn work_dir(build_dir: &Path, upstreams: &[Upstream]) -> PathBuf {
 
                 work_dir = build_dir.join(unpack_dir);
             }
-            upstream::Props::Git { clone_dir, .. } => {
+            upstream::Props::Git { clone_dir, .. } | upstream::Props::Vcs { clone_dir, .. } => {
                 let source = util::uri_file_name(&upstream.url);
                 let target = clone_dir
                     .as_ref()
@@ -97,12 +102,16 @@ fn work_dir(build_dir: &Path, upstreams: &[Upstream]) -> PathBuf {
 
 #[derive(Debug, Error)]
 pub enum Error {
-    #[error("missing arch macros: {0}")]
-    MissingArchMacros(String),
+    #[error("macros")]
+    Macros(#[from] macros::Error),
+    #[error("recipe definition %({0}) is already a builtin definition, rename it")]
+    BuiltinDefinition(String),
     #[error("script")]
     Script(#[from] script::Error),
     #[error("tuning")]
     Tuning(#[from] tuning::Error),
+    #[error("pgo")]
+    Pgo(#[from] pgo::Error),
     #[error("io")]
     Io(#[from] io::Error),
 }
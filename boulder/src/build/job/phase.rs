@@ -1,13 +1,14 @@
 // SPDX-FileCopyrightText: 2024 AerynOS Developers
 // SPDX-License-Identifier: MPL-2.0
 
-use itertools::Itertools;
-use std::collections::BTreeSet;
+use std::num::NonZeroUsize;
+
 use stone_recipe::upstream;
+use strum::VariantArray as _;
 
 use moss::util;
 use stone_recipe::{
-    Script, script,
+    KeyValue, Script, script,
     tuning::{self, Toolchain},
 };
 use tui::Styled;
@@ -17,11 +18,16 @@ use crate::{Macros, Paths, Recipe, architecture::BuildTarget};
 
 use super::{Error, work_dir};
 
-pub fn list(pgo_stage: Option<pgo::Stage>) -> Vec<Phase> {
-    if matches!(pgo_stage, Some(pgo::Stage::One | pgo::Stage::Two)) {
+/// Phases run by a build at `pgo_stage`, in order, omitting [`Phase::Check`] when `skip_check`
+pub fn list(pgo_stage: Option<&pgo::Stage>, skip_check: bool) -> Vec<Phase> {
+    if pgo_stage.is_some_and(pgo::Stage::is_instrumented) {
         Phase::WORKLOAD.to_vec()
     } else {
-        Phase::NORMAL.to_vec()
+        Phase::NORMAL
+            .iter()
+            .copied()
+            .filter(|phase| !(skip_check && *phase == Phase::Check))
+            .collect()
     }
 }
 
@@ -66,15 +72,111 @@ impl Phase {
         .to_string()
     }
 
+    /// The script of this phase of a build of `target`, if it has one
+    ///
+    /// Macros that aren't defined fail to parse, unless `lenient` where they're
+    /// kept as written, as when building without checking for them ahead of time,
+    /// see [`Phase::undefined`].
+    #[allow(clippy::too_many_arguments)]
     pub fn script(
         &self,
         target: BuildTarget,
-        pgo_stage: Option<pgo::Stage>,
+        pgo_stage: Option<&pgo::Stage>,
         recipe: &Recipe,
         paths: &Paths,
         macros: &Macros,
         ccache: bool,
+        jobs: NonZeroUsize,
+        lenient: bool,
     ) -> Result<Option<Script>, Error> {
+        let Some((content, env)) = self.content(target, pgo_stage, recipe)? else {
+            return Ok(None);
+        };
+
+        let mut parser = macros.parser(target)?.env(env);
+        if lenient {
+            parser = parser.lenient();
+        }
+
+        let build_target = target.to_string();
+        let build_dir = paths.build().guest.join(&build_target);
+        let work_dir = if matches!(self, Phase::Prepare) {
+            build_dir.clone()
+        } else {
+            work_dir(&build_dir, &recipe.parsed.upstreams)
+        };
+
+        parser.add_definition("name", &recipe.parsed.source.name);
+        parser.add_definition("version", &recipe.parsed.source.version);
+        parser.add_definition("release", recipe.parsed.source.release);
+        parser.add_definition("pkgdir", paths.recipe().guest.join("pkg").display());
+        parser.add_definition("sourcedir", paths.upstreams().guest.display());
+        parser.add_definition("installroot", paths.install().guest.display());
+        parser.add_definition("buildroot", build_dir.display());
+        parser.add_definition("workdir", work_dir.display());
+        parser.add_definition("sourcedateepoch", recipe.build_time.timestamp());
+        parser.add_definition("pgo_dir", pgo::Stage::Use.profile_dir(&build_dir).display());
+        parser.add_definition(
+            "pgo_stage_dir",
+            pgo_stage.unwrap_or(&pgo::Stage::Use).profile_dir(&build_dir).display(),
+        );
+
+        add_builtins(
+            target,
+            pgo_stage,
+            &BuildOptions {
+                jobs: Some(jobs),
+                ..BuildOptions::new(recipe)
+            },
+            macros,
+            ccache,
+            &mut parser,
+        )?;
+
+        add_recipe_definitions(recipe, &mut parser)?;
+
+        Ok(Some(parser.parse(&content)?))
+    }
+
+    /// Macros referenced by the script of this phase of a build of `target` that
+    /// aren't defined, by line
+    pub fn undefined(
+        &self,
+        target: BuildTarget,
+        pgo_stage: Option<&pgo::Stage>,
+        recipe: &Recipe,
+        macros: &Macros,
+    ) -> Result<Vec<script::Undefined>, Error> {
+        let Some((content, env)) = self.content(target, pgo_stage, recipe)? else {
+            return Ok(vec![]);
+        };
+
+        // Only which macros are defined matters, not what they expand to
+        let mut parser = macros.parser(target)?.env(env);
+        for identifier in RECIPE_DEFINITIONS {
+            parser.add_definition(identifier, format!("<{identifier}>"));
+        }
+        add_builtins(
+            target,
+            pgo_stage,
+            &BuildOptions::new(recipe),
+            macros,
+            false,
+            &mut parser,
+        )?;
+        add_recipe_definitions(recipe, &mut parser)?;
+
+        Ok(parser.undefined(&content)?)
+    }
+
+    /// Content of the script of this phase of a build of `target` & its environment,
+    /// unless it has none
+    fn content(
+        &self,
+        target: BuildTarget,
+        pgo_stage: Option<&pgo::Stage>,
+        recipe: &Recipe,
+    ) -> Result<Option<(String, String)>, Error> {
         let root_build = &recipe.parsed.build;
         let target_build = recipe.build_target_definition(target);
 
@@ -84,20 +186,7 @@ impl Phase {
             Phase::Build => target_build.build.clone().or_else(|| root_build.build.clone()),
             Phase::Check => target_build.check.clone().or_else(|| root_build.check.clone()),
             Phase::Install => target_build.install.clone().or_else(|| root_build.install.clone()),
-            Phase::Workload => match target_build.workload.clone().or_else(|| root_build.workload.clone()) {
-                Some(mut content) => {
-                    if matches!(recipe.parsed.options.toolchain, Toolchain::Llvm) {
-                        if matches!(pgo_stage, Some(pgo::Stage::One)) {
-                            content.push_str("%llvm_merge_s1");
-                        } else if matches!(pgo_stage, Some(pgo::Stage::Two)) {
-                            content.push_str("%llvm_merge_s2");
-                        }
-                    }
-
-                    Some(content)
-                }
-                None => None,
-            },
+            Phase::Workload => workload_script(target, pgo_stage, recipe)?,
         }) else {
             return Ok(None);
         };
@@ -106,138 +195,225 @@ impl Phase {
             return Ok(None);
         }
 
-        let mut env = target_build
+        let env = target_build
             .environment
             .as_deref()
             .or(root_build.environment.as_deref())
             .filter(|env| *env != "(null)" && !env.is_empty() && !matches!(self, Phase::Prepare))
-            .unwrap_or_default()
-            .to_owned();
-        env = format!("%scriptBase\n{env}\n");
+            .unwrap_or_default();
 
-        let mut parser = script::Parser::new().env(env);
+        Ok(Some((content, format!("%scriptBase\n{env}\n"))))
+    }
+}
 
-        let build_target = target.to_string();
-        let build_dir = paths.build().guest.join(&build_target);
-        let work_dir = if matches!(self, Phase::Prepare) {
-            build_dir.clone()
-        } else {
-            work_dir(&build_dir, &recipe.parsed.upstreams)
-        };
-        let num_jobs = util::num_cpus();
+/// Add the definitions of `recipe` to `parser`, which may only add to the builtin ones
+fn add_recipe_definitions(recipe: &Recipe, parser: &mut script::Parser) -> Result<(), Error> {
+    for (identifier, definition) in &recipe.parsed.definitions {
+        if parser.has_definition(identifier) {
+            return Err(Error::BuiltinDefinition(identifier.clone()));
+        }
+        parser.add_definition(identifier, definition);
+    }
+    Ok(())
+}
 
-        for arch in ["base", &build_target] {
-            let macros = macros
-                .arch
-                .get(arch)
-                .cloned()
-                .ok_or_else(|| Error::MissingArchMacros(arch.to_owned()))?;
+/// The workload run by `pgo_stage` of a build of `target`, merging the profiles it gathered
+///
+/// Each named workload of a plan is profiled by a stage of its own, while the context
+/// sensitive stage runs them all in order.
+fn workload_script(
+    target: BuildTarget,
+    pgo_stage: Option<&pgo::Stage>,
+    recipe: &Recipe,
+) -> Result<Option<String>, Error> {
+    let root_build = &recipe.parsed.build;
+    let target_build = recipe.build_target_definition(target);
+    let plan = pgo::plan(recipe, target)?;
 
-            parser.add_macros(macros.clone());
-        }
+    let mut content = match pgo_stage {
+        Some(pgo::Stage::Workload { name, .. }) => plan
+            .iter()
+            .find(|(planned, _)| planned == name)
+            .map(|(_, content)| content.to_string()),
+        _ if !plan.is_empty() => Some(
+            plan.iter()
+                .map(|(name, content)| format!("# {name}\n(\n{}\n)\n", content.trim_end()))
+                .collect(),
+        ),
+        _ => target_build.workload.clone().or_else(|| root_build.workload.clone()),
+    };
+
+    if let Some(content) = &mut content
+        && matches!(recipe.parsed.options.toolchain, Toolchain::Llvm)
+    {
+        let merge = match pgo_stage {
+            Some(pgo::Stage::One) => Some("%llvm_merge_s1"),
+            Some(pgo::Stage::Workload { .. }) => Some("%llvm_merge_stage"),
+            Some(pgo::Stage::Two) => Some("%llvm_merge_s2"),
+            Some(pgo::Stage::Use) | None => None,
+        };
 
-        for macros in macros.actions.clone() {
-            parser.add_macros(macros.clone());
+        if let Some(merge) = merge {
+            if !plan.is_empty() && !content.ends_with('\n') {
+                content.push('\n');
+            }
+            content.push_str(merge);
         }
+    }
 
-        parser.add_definition("name", &recipe.parsed.source.name);
-        parser.add_definition("version", &recipe.parsed.source.version);
-        parser.add_definition("release", recipe.parsed.source.release);
-        parser.add_definition("jobs", num_jobs);
-        parser.add_definition("pkgdir", paths.recipe().guest.join("pkg").display());
-        parser.add_definition("sourcedir", paths.upstreams().guest.display());
-        parser.add_definition("installroot", paths.install().guest.display());
-        parser.add_definition("buildroot", build_dir.display());
-        parser.add_definition("workdir", work_dir.display());
+    Ok(content)
+}
 
-        parser.add_definition("compiler_cache", "/mason/ccache");
-        parser.add_definition("scompiler_cache", "/mason/sccache");
+/// Definitions of a build naming its recipe & build dirs, see [`builtin_parser`]
+const RECIPE_DEFINITIONS: &[&str] = &[
+    "name",
+    "version",
+    "release",
+    "pkgdir",
+    "sourcedir",
+    "installroot",
+    "buildroot",
+    "workdir",
+    "sourcedateepoch",
+    "pgo_dir",
+    "pgo_stage_dir",
+];
+
+/// A parser with the macros & builtin definitions of a build of `target`,
+/// as a recipe with default options gets them
+///
+/// Definitions depending on the recipe are left as `<identifier>` placeholders.
+pub fn builtin_parser(target: BuildTarget, macros: &Macros) -> Result<script::Parser, Error> {
+    let mut parser = macros.parser(target)?;
+
+    for identifier in RECIPE_DEFINITIONS {
+        parser.add_definition(identifier, format!("<{identifier}>"));
+    }
 
-        parser.add_definition("sourcedateepoch", recipe.build_time.timestamp());
+    add_builtins(target, None, &BuildOptions::default(), macros, false, &mut parser)?;
 
-        let path = if ccache {
-            "/usr/lib/ccache/bin:/usr/bin:/bin"
-        } else {
-            "/usr/bin:/bin"
-        };
+    Ok(parser)
+}
 
-        if ccache {
-            parser.add_definition("compiler_go_cache", "/mason/gocache");
-            parser.add_definition("compiler_go_mod_cache", "/mason/gomodcache");
-            parser.add_definition("compiler_cargo_cache", "/mason/cargocache");
-            parser.add_definition("compiler_zig_cache", "/mason/zigcache");
-            parser.add_definition("rustc_wrapper", "/usr/bin/sccache");
-        } else {
-            parser.add_definition("compiler_go_cache", "");
-            parser.add_definition("compiler_go_mod_cache", "");
-            parser.add_definition("compiler_cargo_cache", "");
-            parser.add_definition("compiler_zig_cache", "");
-            parser.add_definition("rustc_wrapper", "");
-        }
+/// Options of a recipe deciding the builtin definitions of its builds
+#[derive(Debug, Clone, Copy, Default)]
+struct BuildOptions<'a> {
+    toolchain: Toolchain,
+    mold: bool,
+    samplepgo: bool,
+    tuning: &'a [KeyValue<stone_recipe::Tuning>],
+    /// Run in parallel, rather than one per CPU
+    jobs: Option<NonZeroUsize>,
+}
 
-        /* Set the relevant compilers */
-        if matches!(recipe.parsed.options.toolchain, Toolchain::Llvm) {
-            parser.add_definition("compiler_c", "clang");
-            parser.add_definition("compiler_cxx", "clang++");
-            parser.add_definition("compiler_objc", "clang");
-            parser.add_definition("compiler_objcxx", "clang++");
-            parser.add_definition("compiler_cpp", "clang-cpp");
-            parser.add_definition("compiler_objcpp", "clang -E -");
-            parser.add_definition("compiler_objcxxcpp", "clang++ -E");
-            parser.add_definition("compiler_d", "ldc2");
-            parser.add_definition("compiler_ar", "llvm-ar");
-            parser.add_definition("compiler_objcopy", "llvm-objcopy");
-            parser.add_definition("compiler_nm", "llvm-nm");
-            parser.add_definition("compiler_ranlib", "llvm-ranlib");
-            parser.add_definition("compiler_strip", "llvm-strip");
-        } else {
-            parser.add_definition("compiler_c", "gcc");
-            parser.add_definition("compiler_cxx", "g++");
-            parser.add_definition("compiler_objc", "gcc");
-            parser.add_definition("compiler_objcxx", "g++");
-            parser.add_definition("compiler_cpp", "gcc -E");
-            parser.add_definition("compiler_objcpp", "gcc -E");
-            parser.add_definition("compiler_objcxxcpp", "g++ -E");
-            parser.add_definition("compiler_d", "ldc2"); // FIXME: GDC
-            parser.add_definition("compiler_ar", "gcc-ar");
-            parser.add_definition("compiler_objcopy", "objcopy");
-            parser.add_definition("compiler_nm", "gcc-nm");
-            parser.add_definition("compiler_ranlib", "gcc-ranlib");
-            parser.add_definition("compiler_strip", "strip");
+impl<'a> BuildOptions<'a> {
+    fn new(recipe: &'a Recipe) -> Self {
+        Self {
+            toolchain: recipe.parsed.options.toolchain,
+            mold: recipe.parsed.mold,
+            samplepgo: recipe.parsed.options.samplepgo,
+            tuning: &recipe.parsed.tuning,
+            jobs: None,
         }
-        parser.add_definition("compiler_path", path);
+    }
+}
 
-        if recipe.parsed.mold {
-            parser.add_definition("compiler_ld", "ld.mold");
-        } else if matches!(recipe.parsed.options.toolchain, Toolchain::Llvm) {
-            parser.add_definition("compiler_ld", "ld.lld");
-        } else {
-            parser.add_definition("compiler_ld", "ld.bfd");
-        }
+/// Add the definitions boulder sets for every build, besides those of macro files
+fn add_builtins(
+    target: BuildTarget,
+    pgo_stage: Option<&pgo::Stage>,
+    options: &BuildOptions<'_>,
+    macros: &Macros,
+    ccache: bool,
+    parser: &mut script::Parser,
+) -> Result<(), Error> {
+    parser.add_definition("jobs", options.jobs.unwrap_or_else(util::num_cpus));
 
-        /* Allow packagers to do stage specific actions in a pgo build */
-        if matches!(pgo_stage, Some(pgo::Stage::One)) {
-            parser.add_definition("pgo_stage", "ONE");
-        } else if matches!(pgo_stage, Some(pgo::Stage::Two)) {
-            parser.add_definition("pgo_stage", "TWO");
-        } else if matches!(pgo_stage, Some(pgo::Stage::Use)) {
-            parser.add_definition("pgo_stage", "USE");
-        } else {
-            parser.add_definition("pgo_stage", "NONE");
-        }
+    parser.add_definition("compiler_cache", "/mason/ccache");
+    parser.add_definition("scompiler_cache", "/mason/sccache");
 
-        parser.add_definition("pgo_dir", format!("{}-pgo", build_dir.display()));
+    let path = if ccache {
+        "/usr/lib/ccache/bin:/usr/bin:/bin"
+    } else {
+        "/usr/bin:/bin"
+    };
+
+    if ccache {
+        parser.add_definition("compiler_go_cache", "/mason/gocache");
+        parser.add_definition("compiler_go_mod_cache", "/mason/gomodcache");
+        parser.add_definition("compiler_cargo_cache", "/mason/cargocache");
+        parser.add_definition("compiler_zig_cache", "/mason/zigcache");
+        parser.add_definition("rustc_wrapper", "/usr/bin/sccache");
+    } else {
+        parser.add_definition("compiler_go_cache", "");
+        parser.add_definition("compiler_go_mod_cache", "");
+        parser.add_definition("compiler_cargo_cache", "");
+        parser.add_definition("compiler_zig_cache", "");
+        parser.add_definition("rustc_wrapper", "");
+    }
 
-        add_tuning(target, pgo_stage, recipe, macros, &mut parser)?;
+    /* Set the relevant compilers */
+    if matches!(options.toolchain, Toolchain::Llvm) {
+        parser.add_definition("compiler_c", "clang");
+        parser.add_definition("compiler_cxx", "clang++");
+        parser.add_definition("compiler_objc", "clang");
+        parser.add_definition("compiler_objcxx", "clang++");
+        parser.add_definition("compiler_cpp", "clang-cpp");
+        parser.add_definition("compiler_objcpp", "clang -E -");
+        parser.add_definition("compiler_objcxxcpp", "clang++ -E");
+        parser.add_definition("compiler_d", "ldc2");
+        parser.add_definition("compiler_ar", "llvm-ar");
+        parser.add_definition("compiler_objcopy", "llvm-objcopy");
+        parser.add_definition("compiler_nm", "llvm-nm");
+        parser.add_definition("compiler_ranlib", "llvm-ranlib");
+        parser.add_definition("compiler_strip", "llvm-strip");
+    } else {
+        parser.add_definition("compiler_c", "gcc");
+        parser.add_definition("compiler_cxx", "g++");
+        parser.add_definition("compiler_objc", "gcc");
+        parser.add_definition("compiler_objcxx", "g++");
+        parser.add_definition("compiler_cpp", "gcc -E");
+        parser.add_definition("compiler_objcpp", "gcc -E");
+        parser.add_definition("compiler_objcxxcpp", "g++ -E");
+        parser.add_definition("compiler_d", "ldc2"); // FIXME: GDC
+        parser.add_definition("compiler_ar", "gcc-ar");
+        parser.add_definition("compiler_objcopy", "objcopy");
+        parser.add_definition("compiler_nm", "gcc-nm");
+        parser.add_definition("compiler_ranlib", "gcc-ranlib");
+        parser.add_definition("compiler_strip", "strip");
+    }
+    parser.add_definition("compiler_path", path);
 
-        Ok(Some(parser.parse(&content)?))
+    if options.mold {
+        parser.add_definition("compiler_ld", "ld.mold");
+    } else if matches!(options.toolchain, Toolchain::Llvm) {
+        parser.add_definition("compiler_ld", "ld.lld");
+    } else {
+        parser.add_definition("compiler_ld", "ld.bfd");
+    }
+
+    /* Allow packagers to do stage specific actions in a pgo build */
+    if matches!(pgo_stage, Some(pgo::Stage::One | pgo::Stage::Workload { .. })) {
+        parser.add_definition("pgo_stage", "ONE");
+    } else if matches!(pgo_stage, Some(pgo::Stage::Two)) {
+        parser.add_definition("pgo_stage", "TWO");
+    } else if matches!(pgo_stage, Some(pgo::Stage::Use)) {
+        parser.add_definition("pgo_stage", "USE");
+    } else {
+        parser.add_definition("pgo_stage", "NONE");
     }
+
+    add_tuning(target, pgo_stage, options, macros, parser)
 }
 
+/// Extracts or copies each upstream into its own directory, running up to `%(jobs)` at a time
+///
+/// Each runs in the background, naming its upstream should it fail. All are waited on before
+/// the phase fails, so none are left running.
 fn prepare_script(upstreams: &[upstream::Upstream]) -> String {
     use std::fmt::Write;
 
-    let mut content = String::default();
+    let mut steps = vec![];
 
     for upstream in upstreams {
         match &upstream.props {
@@ -259,59 +435,89 @@ fn prepare_script(upstreams: &[upstream::Upstream]) -> String {
                     .unwrap_or_else(|| rename.to_owned());
                 let strip_dirs = strip_dirs.unwrap_or(1);
 
-                let _ = writeln!(&mut content, "mkdir -p {unpack_dir}");
-                let _ = writeln!(
-                    &mut content,
-                    r#"bsdtar-static xf "%(sourcedir)/{rename}" -C "{unpack_dir}" --strip-components={strip_dirs} --no-same-owner || (echo "Failed to extract archive"; exit 1);"#,
-                );
+                steps.push(format!(
+                    r#"{{ mkdir -p "{unpack_dir}" && bsdtar-static xf "%(sourcedir)/{rename}" -C "{unpack_dir}" --strip-components={strip_dirs} --no-same-owner; }} || {{ echo "Failed to extract archive {rename}"; exit 1; }} &"#
+                ));
             }
-            upstream::Props::Git { clone_dir, .. } => {
+            upstream::Props::Git { clone_dir, .. } | upstream::Props::Vcs { clone_dir, .. } => {
                 let source = util::uri_file_name(&upstream.url);
                 let target = clone_dir
                     .as_ref()
                     .map(|dir| dir.display().to_string())
                     .unwrap_or_else(|| source.to_owned());
 
-                let _ = writeln!(&mut content, "mkdir -p {target}");
-                let _ = writeln!(
-                    &mut content,
-                    r#"cp -Ra --no-preserve=ownership "%(sourcedir)/{source}/." "{target}""#,
-                );
+                steps.push(format!(
+                    r#"{{ mkdir -p "{target}" && cp -Ra --no-preserve=ownership "%(sourcedir)/{source}/." "{target}"; }} || {{ echo "Failed to copy repository {source}"; exit 1; }} &"#
+                ));
             }
         }
     }
 
+    if steps.is_empty() {
+        return String::default();
+    }
+
+    // Waits on the oldest step once `%(jobs)` are running, so each is waited on exactly once
+    let mut content = String::from(
+        r#"prepare_pids=()
+prepare_waited=0
+prepare_failed=0
+prepare_slot() {
+    if (( ${#prepare_pids[@]} - prepare_waited >= %(jobs) )); then
+        wait "${prepare_pids[prepare_waited]}" || prepare_failed=1
+        prepare_waited=$((prepare_waited + 1))
+    fi
+}
+"#,
+    );
+
+    for step in steps {
+        let _ = writeln!(&mut content, "prepare_slot");
+        let _ = writeln!(&mut content, "{step}");
+        let _ = writeln!(&mut content, "prepare_pids+=($!)");
+    }
+
+    content.push_str(
+        r#"for pid in "${prepare_pids[@]:prepare_waited}"; do
+    wait "$pid" || prepare_failed=1
+done
+if (( prepare_failed )); then
+    exit 1
+fi
+"#,
+    );
+
     content
 }
 
 fn add_tuning(
     target: BuildTarget,
-    pgo_stage: Option<pgo::Stage>,
-    recipe: &Recipe,
+    pgo_stage: Option<&pgo::Stage>,
+    options: &BuildOptions<'_>,
     macros: &Macros,
     parser: &mut script::Parser,
 ) -> Result<(), Error> {
-    let mut tuning = tuning::Builder::new();
-
-    let build_target = target.to_string();
-
-    for arch in ["base", &build_target] {
-        let macros = macros
-            .arch
-            .get(arch)
-            .cloned()
-            .ok_or_else(|| Error::MissingArchMacros(arch.to_owned()))?;
+    let tuning = tuning(target, pgo_stage, options, macros)?;
 
-        tuning.add_macros(macros);
+    for (name, flags) in tuning_definitions(&tuning, options)? {
+        parser.add_definition(name, flags);
     }
 
-    for macros in macros.actions.clone() {
-        tuning.add_macros(macros);
-    }
+    Ok(())
+}
+
+/// The tuning groups enabled by a build of `target`
+fn tuning(
+    target: BuildTarget,
+    pgo_stage: Option<&pgo::Stage>,
+    options: &BuildOptions<'_>,
+    macros: &Macros,
+) -> Result<tuning::Builder, Error> {
+    let mut tuning = macros.tuning(target)?;
 
     tuning.enable("architecture", None)?;
 
-    for kv in &recipe.parsed.tuning {
+    for kv in options.tuning {
         match &kv.value {
             stone_recipe::Tuning::Enable => tuning.enable(&kv.key, None)?,
             stone_recipe::Tuning::Disable => tuning.disable(&kv.key)?,
@@ -320,110 +526,417 @@ fn add_tuning(
     }
 
     // Add defaults that aren't already in recipe
-    for group in default_tuning_groups(target, macros) {
-        if !recipe.parsed.tuning.iter().any(|kv| &kv.key == group) {
+    for group in macros.default_tuning_groups(target) {
+        if !options.tuning.iter().any(|kv| &kv.key == group) {
             tuning.enable(group, None)?;
         }
     }
 
     if let Some(stage) = pgo_stage {
         match stage {
-            pgo::Stage::One => tuning.enable("pgostage1", None)?,
+            pgo::Stage::One | pgo::Stage::Workload { .. } => tuning.enable("pgostage1", None)?,
             pgo::Stage::Two => tuning.enable("pgostage2", None)?,
             pgo::Stage::Use => {
                 tuning.enable("pgouse", None)?;
-                if recipe.parsed.options.samplepgo {
+                if options.samplepgo {
                     tuning.enable("pgosample", None)?;
                 }
             }
         }
     }
 
-    fn fmt_flags<'a>(flags: impl Iterator<Item = &'a str>) -> String {
-        flags
-            .map(|s| s.trim())
-            .filter(|s| s.len() > 1)
-            .collect::<BTreeSet<_>>()
-            .into_iter()
-            .join(" ")
-    }
+    Ok(tuning)
+}
 
-    let toolchain = recipe.parsed.options.toolchain;
+/// The flag definitions, i.e. `cflags`, of the enabled `tuning` groups
+fn tuning_definitions(tuning: &tuning::Builder, options: &BuildOptions<'_>) -> Result<Vec<(String, String)>, Error> {
     let flags = tuning.build()?;
 
-    let mut cflags = fmt_flags(
-        flags
-            .iter()
-            .filter_map(|flag| flag.get(tuning::CompilerFlag::C, toolchain)),
-    );
-    let mut cxxflags = fmt_flags(
-        flags
-            .iter()
-            .filter_map(|flag| flag.get(tuning::CompilerFlag::Cxx, toolchain)),
-    );
-    let fflags = fmt_flags(
-        flags
-            .iter()
-            .filter_map(|flag| flag.get(tuning::CompilerFlag::F, toolchain)),
-    );
-    let ldflags = fmt_flags(
-        flags
-            .iter()
-            .filter_map(|flag| flag.get(tuning::CompilerFlag::Ld, toolchain)),
-    );
-    let dflags = fmt_flags(
-        flags
-            .iter()
-            .filter_map(|flag| flag.get(tuning::CompilerFlag::D, toolchain)),
-    );
-    let mut rustflags = fmt_flags(
-        flags
-            .iter()
-            .filter_map(|flag| flag.get(tuning::CompilerFlag::Rust, toolchain)),
-    );
-    let valaflags = fmt_flags(
-        flags
-            .iter()
-            .filter_map(|flag| flag.get(tuning::CompilerFlag::Vala, toolchain)),
-    );
-    let goflags = fmt_flags(
-        flags
-            .iter()
-            .filter_map(|flag| flag.get(tuning::CompilerFlag::Go, toolchain)),
-    );
+    Ok(tuning::CompilerFlag::VARIANTS
+        .iter()
+        .map(|&flag| {
+            let mut value = tuning::join(&flags, flag, options.toolchain);
 
-    if recipe.parsed.mold {
-        cflags.push_str(" -fuse-ld=mold");
-        cxxflags.push_str(" -fuse-ld=mold");
-        rustflags.push_str(" -Clink-arg=-fuse-ld=mold");
-    }
+            if options.mold {
+                match flag {
+                    tuning::CompilerFlag::C | tuning::CompilerFlag::Cxx => value.push_str(" -fuse-ld=mold"),
+                    tuning::CompilerFlag::Rust => value.push_str(" -Clink-arg=-fuse-ld=mold"),
+                    _ => {}
+                }
+            }
+
+            (format!("{flag}flags"), value)
+        })
+        .collect())
+}
 
-    parser.add_definition("cflags", cflags);
-    parser.add_definition("cxxflags", cxxflags);
-    parser.add_definition("fflags", fflags);
-    parser.add_definition("ldflags", ldflags);
-    parser.add_definition("dflags", dflags);
-    parser.add_definition("rustflags", rustflags);
-    parser.add_definition("valaflags", valaflags);
-    parser.add_definition("goflags", goflags);
+/// The tuning groups a build of `recipe` for `target` enables, or else those of a recipe
+/// leaving them to the defaults, with the flag definitions they result in
+///
+/// Profile guided optimization is left out, as it only tunes some stages of a build.
+pub fn recipe_tuning(
+    target: BuildTarget,
+    recipe: Option<&Recipe>,
+    macros: &Macros,
+) -> Result<(tuning::Builder, Vec<(String, String)>), Error> {
+    let options = recipe.map(BuildOptions::new).unwrap_or_default();
+    let tuning = tuning(target, None, &options, macros)?;
+    let definitions = tuning_definitions(&tuning, &options)?;
 
-    Ok(())
+    Ok((tuning, definitions))
 }
 
-fn default_tuning_groups(target: BuildTarget, macros: &Macros) -> &[String] {
-    let build_target = target.to_string();
+#[cfg(test)]
+mod test {
+    use std::{collections::BTreeMap, path::Path};
 
-    for arch in [&build_target, "base"] {
-        let Some(arch_macros) = macros.arch.get(arch) else {
-            continue;
-        };
+    use fs_err as fs;
+    use stone_recipe::script::Command;
+
+    use super::*;
+    use crate::architecture::Architecture;
+
+    fn macros() -> Macros {
+        let load = |bytes: &[u8]| stone_recipe::macros::from_slice(bytes).unwrap();
+
+        Macros {
+            arch: BTreeMap::from([
+                ("base".to_owned(), load(include_bytes!("../../../../test/base.yml"))),
+                ("x86_64".to_owned(), load(include_bytes!("../../../../test/x86_64.yml"))),
+            ]),
+            actions: vec![load(include_bytes!("../../../data/macros/actions/pgo.yaml"))],
+        }
+    }
+
+    /// The build script of a recipe with `definitions`, keeping undefined macros when `lenient`
+    fn build_script(dir: &Path, definitions: &str, lenient: bool) -> Result<Option<Script>, Error> {
+        let path = dir.join("stone.yaml");
+        fs::write(
+            &path,
+            format!(
+                "\
+name: nano
+version: 8.7
+release: 1
+homepage: https://nano-editor.org
+license: GPL-3.0-or-later
+summary: GNU Text Editor
+description: GNU Text Editor
+definitions:
+{definitions}
+build: |
+    make %(nano_flags)
+"
+            ),
+        )
+        .unwrap();
+        let recipe = Recipe::load(&path).unwrap();
+        let paths = Paths::new(&recipe, None, dir, "/mason", dir).unwrap();
+
+        Phase::Build.script(
+            BuildTarget::Native(Architecture::X86_64),
+            None,
+            &recipe,
+            &paths,
+            &macros(),
+            false,
+            NonZeroUsize::new(4).unwrap(),
+            lenient,
+        )
+    }
+
+    #[test]
+    fn phase_list() {
+        use Phase::*;
+
+        assert_eq!(list(None, false), [Prepare, Setup, Build, Install, Check]);
+        assert_eq!(list(None, true), [Prepare, Setup, Build, Install]);
+        assert_eq!(list(Some(&pgo::Stage::Use), true), [Prepare, Setup, Build, Install]);
+        // Instrumented builds never run checks
+        assert_eq!(list(Some(&pgo::Stage::One), false), [Prepare, Setup, Build, Workload]);
+        assert_eq!(list(Some(&pgo::Stage::Two), true), [Prepare, Setup, Build, Workload]);
+    }
+
+    #[test]
+    fn recipe_definitions() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let script = build_script(
+            dir.path(),
+            "    nano_flags: --enable-utf8 %(extra_flags) --docdir=%(docdir)\n    extra_flags: --disable-libmagic",
+            false,
+        )
+        .unwrap()
+        .unwrap();
 
-        if arch_macros.default_tuning_groups.is_empty() {
-            continue;
+        let Some(Command::Content(content)) = script.commands.last() else {
+            panic!("no content");
+        };
+        assert!(
+            content.ends_with("make --enable-utf8 --disable-libmagic --docdir=/usr/share/doc"),
+            "{content}"
+        );
+        assert_eq!(
+            script.resolved_definitions["nano_flags"],
+            "--enable-utf8 --disable-libmagic --docdir=/usr/share/doc"
+        );
+        assert_eq!(script.resolved_definitions["jobs"], "4");
+
+        // Builtins, from macros or boulder, can't be redefined
+        for builtin in ["docdir", "jobs", "cflags"] {
+            let result = build_script(dir.path(), &format!("    nano_flags: ''\n    {builtin}: /opt"), false);
+            assert!(matches!(result, Err(Error::BuiltinDefinition(name)) if name == builtin));
         }
+    }
+
+    #[test]
+    fn undefined_macros() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // Only kept as written when building without validation
+        let result = build_script(dir.path(), "    extra_flags: --disable-libmagic", false);
+        assert!(matches!(result, Err(Error::Script(_))), "{result:?}");
+
+        let script = build_script(dir.path(), "    extra_flags: --disable-libmagic", true)
+            .unwrap()
+            .unwrap();
+        let Some(Command::Content(content)) = script.commands.last() else {
+            panic!("no content");
+        };
+        assert!(content.ends_with("make %(nano_flags)"), "{content}");
+    }
+
+    /// The content of the workload script of `pgo_stage`, for a recipe profiling two named workloads
+    fn workload_content(dir: &Path, pgo_stage: &pgo::Stage) -> String {
+        let path = dir.join("stone.yaml");
+        fs::write(
+            &path,
+            "\
+name: zstd
+version: 1.5.7
+release: 1
+homepage: https://example.com
+license: BSD-3-Clause
+summary: Example
+description: Example
+toolchain: llvm
+cspgo: true
+pgo_stages: [training, sampling]
+workloads:
+    training: |
+        zstd -19 corpus
+    sampling: |
+        zstd -d corpus.zst
+build: make
+",
+        )
+        .unwrap();
+        let recipe = Recipe::load(&path).unwrap();
+        let paths = Paths::new(&recipe, None, dir, "/mason", dir).unwrap();
+
+        let script = Phase::Workload
+            .script(
+                BuildTarget::Native(Architecture::X86_64),
+                Some(pgo_stage),
+                &recipe,
+                &paths,
+                &macros(),
+                false,
+                NonZeroUsize::MIN,
+                false,
+            )
+            .unwrap()
+            .unwrap();
+
+        script
+            .commands
+            .into_iter()
+            .filter_map(|command| match command {
+                Command::Content(content) => Some(content),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn named_workloads() {
+        let dir = tempfile::tempdir().unwrap();
 
-        return &arch_macros.default_tuning_groups;
+        let training = workload_content(
+            dir.path(),
+            &pgo::Stage::Workload {
+                index: 0,
+                name: "training".to_owned(),
+            },
+        );
+        assert!(training.contains("zstd -19 corpus"), "{training}");
+        assert!(!training.contains("zstd -d corpus.zst"), "{training}");
+        assert!(
+            training.contains(
+                "-output=/mason/build/x86_64-pgo/training/ir.profdata /mason/build/x86_64-pgo/training/IR/default*.profraw"
+            ),
+            "{training}"
+        );
+        assert!(
+            training.contains("-output=/mason/build/x86_64-pgo/ir.profdata /mason/build/x86_64-pgo/*/ir.profdata"),
+            "{training}"
+        );
+
+        // The context sensitive stage runs every workload in order
+        let stage2 = workload_content(dir.path(), &pgo::Stage::Two);
+        let (first, second) = (
+            stage2.find("# training\n(\nzstd -19 corpus\n)\n").unwrap(),
+            stage2.find("# sampling\n(\nzstd -d corpus.zst\n)\n").unwrap(),
+        );
+        assert!(first < second, "{stage2}");
+        assert!(
+            stage2.contains("/mason/build/x86_64-pgo/CS/default*.profraw"),
+            "{stage2}"
+        );
     }
 
-    &[]
+    /// The upstreams of a recipe, of two archives & a git repository
+    fn upstreams(dir: &Path) -> Vec<upstream::Upstream> {
+        let path = dir.join("stone.yaml");
+        fs::write(
+            &path,
+            "\
+name: nano
+version: 8.7
+release: 1
+homepage: https://nano-editor.org
+license: GPL-3.0-or-later
+summary: GNU Text Editor
+description: GNU Text Editor
+upstreams:
+    - https://nano-editor.org/dist/v8/nano-8.7.tar.xz:
+        hash: 1c3a8c4a6c5e0e4b4d1a6ab1fb2d0e3c8a1b1f5e6a3c7f9d2b6e4a8c0d1e2f3a
+    - https://nano-editor.org/dist/v8/nano-docs.tar.xz:
+        hash: 2c3a8c4a6c5e0e4b4d1a6ab1fb2d0e3c8a1b1f5e6a3c7f9d2b6e4a8c0d1e2f3a
+        unpackdir: docs
+        stripdirs: 0
+    - git|https://git.savannah.gnu.org/git/nano-syntax.git : v1.0
+build: |
+    make
+",
+        )
+        .unwrap();
+
+        Recipe::load(&path).unwrap().parsed.upstreams
+    }
+
+    #[test]
+    fn parallel_prepare() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert_eq!(
+            prepare_script(&upstreams(dir.path())),
+            r#"prepare_pids=()
+prepare_waited=0
+prepare_failed=0
+prepare_slot() {
+    if (( ${#prepare_pids[@]} - prepare_waited >= %(jobs) )); then
+        wait "${prepare_pids[prepare_waited]}" || prepare_failed=1
+        prepare_waited=$((prepare_waited + 1))
+    fi
+}
+prepare_slot
+{ mkdir -p "nano-8.7.tar.xz" && bsdtar-static xf "%(sourcedir)/nano-8.7.tar.xz" -C "nano-8.7.tar.xz" --strip-components=1 --no-same-owner; } || { echo "Failed to extract archive nano-8.7.tar.xz"; exit 1; } &
+prepare_pids+=($!)
+prepare_slot
+{ mkdir -p "docs" && bsdtar-static xf "%(sourcedir)/nano-docs.tar.xz" -C "docs" --strip-components=0 --no-same-owner; } || { echo "Failed to extract archive nano-docs.tar.xz"; exit 1; } &
+prepare_pids+=($!)
+prepare_slot
+{ mkdir -p "nano-syntax.git" && cp -Ra --no-preserve=ownership "%(sourcedir)/nano-syntax.git/." "nano-syntax.git"; } || { echo "Failed to copy repository nano-syntax.git"; exit 1; } &
+prepare_pids+=($!)
+for pid in "${prepare_pids[@]:prepare_waited}"; do
+    wait "$pid" || prepare_failed=1
+done
+if (( prepare_failed )); then
+    exit 1
+fi
+"#
+        );
+        assert_eq!(prepare_script(&[]), "");
+
+        // Expanded to the jobs of the build
+        let recipe = Recipe::load(dir.path().join("stone.yaml")).unwrap();
+        let paths = Paths::new(&recipe, None, dir.path(), "/mason", dir.path()).unwrap();
+        let script = Phase::Prepare
+            .script(
+                BuildTarget::Native(Architecture::X86_64),
+                None,
+                &recipe,
+                &paths,
+                &macros(),
+                false,
+                NonZeroUsize::new(4).unwrap(),
+                false,
+            )
+            .unwrap()
+            .unwrap();
+        let content = script
+            .commands
+            .into_iter()
+            .filter_map(|command| match command {
+                Command::Content(content) => Some(content),
+                _ => None,
+            })
+            .collect::<String>();
+        assert!(content.contains("prepare_waited >= 4 ))"), "{content}");
+        assert!(content.contains(r#""/mason/sourcedir/nano-docs.tar.xz""#), "{content}");
+    }
+
+    #[test]
+    fn prepare_failure() {
+        use std::{fs::Permissions, os::unix::fs::PermissionsExt, process};
+
+        let dir = tempfile::tempdir().unwrap();
+        let upstreams = upstreams(dir.path());
+
+        // Extracts nothing, failing for the docs only
+        let bin = dir.path().join("bin");
+        fs::create_dir(&bin).unwrap();
+        fs::write(
+            bin.join("bsdtar-static"),
+            "#!/bin/sh\ncase \"$2\" in *docs*) exit 1;; esac\n",
+        )
+        .unwrap();
+        fs::set_permissions(bin.join("bsdtar-static"), Permissions::from_mode(0o755)).unwrap();
+        let source = dir.path().join("sourcedir");
+        fs::create_dir_all(source.join("nano-syntax.git")).unwrap();
+        fs::write(source.join("nano-syntax.git/README"), "syntax").unwrap();
+
+        let run = |jobs: usize| {
+            let work = tempfile::tempdir().unwrap();
+            let script = prepare_script(&upstreams)
+                .replace("%(jobs)", &jobs.to_string())
+                .replace("%(sourcedir)", &source.display().to_string());
+            let output = process::Command::new("/bin/bash")
+                .args(["-o", "errexit", "-o", "nounset", "-c", &script])
+                .current_dir(work.path())
+                .env("PATH", format!("{}:/usr/bin:/bin", bin.display()))
+                .output()
+                .unwrap();
+            (output, work)
+        };
+
+        for jobs in [1, 2, 4] {
+            let (output, work) = run(jobs);
+            let stdout = String::from_utf8_lossy(&output.stdout);
+
+            assert!(!output.status.success(), "{jobs} jobs");
+            assert_eq!(stdout, "Failed to extract archive nano-docs.tar.xz\n", "{jobs} jobs");
+            // The others still ran to completion
+            assert_eq!(
+                fs::read_to_string(work.path().join("nano-syntax.git/README")).unwrap(),
+                "syntax"
+            );
+        }
+
+        // Succeeding once every upstream does
+        fs::write(bin.join("bsdtar-static"), "#!/bin/sh\n").unwrap();
+        let (output, _) = run(2);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    }
 }
@@ -0,0 +1,177 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Number of jobs builds run in parallel, as `%(jobs)`
+//!
+//! Every CPU of the host runs a job unless the recipe limits them, by
+//! `max_jobs` or by the memory each job needs with `max_memory_per_job`.
+//! Packages whose jobs each take gigabytes of memory otherwise run the host
+//! out of memory at full parallelism. `--jobs` overrides both.
+
+use std::{fmt, num::NonZeroUsize};
+
+use moss::{client::quota, util};
+use nix::sys::sysinfo;
+use thiserror::Error;
+
+use crate::Recipe;
+
+/// Jobs a build runs in parallel, and what limited them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Jobs {
+    pub count: NonZeroUsize,
+    pub limit: Limit,
+}
+
+impl Jobs {
+    /// The jobs of builds of `recipe` on this host, unless `overridden`
+    pub fn new(recipe: &Recipe, overridden: Option<NonZeroUsize>) -> Result<Self, Error> {
+        let options = &recipe.parsed.options;
+
+        let memory_per_job = options
+            .max_memory_per_job
+            .as_deref()
+            .map(|size| quota::parse_size(size).ok_or_else(|| Error::InvalidMemory(size.to_owned())))
+            .transpose()?;
+        // Only queried when needed, as hosts without `sysinfo` can still build others
+        let total_memory = if memory_per_job.is_some() && overridden.is_none() {
+            sysinfo::sysinfo()?.ram_total()
+        } else {
+            0
+        };
+
+        Ok(count(
+            util::num_cpus(),
+            total_memory,
+            options.max_jobs,
+            memory_per_job,
+            overridden,
+        ))
+    }
+}
+
+impl fmt::Display for Jobs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.limit {
+            Limit::Cpus => write!(f, "{}", self.count),
+            Limit::MaxJobs => write!(f, "{} (limited by max_jobs)", self.count),
+            Limit::Memory => write!(f, "{} (limited by max_memory_per_job)", self.count),
+            Limit::Override => write!(f, "{} (set by --jobs)", self.count),
+        }
+    }
+}
+
+/// What decided the number of [`Jobs`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// One job per CPU of the host
+    Cpus,
+    /// The `max_jobs` of the recipe
+    MaxJobs,
+    /// The jobs fitting in memory, by the `max_memory_per_job` of the recipe
+    Memory,
+    /// Overridden by `--jobs`
+    Override,
+}
+
+/// The jobs to run with `cpus` & `total_memory` bytes, at most `max_jobs`
+/// and each taking `memory_per_job` bytes, unless `overridden`
+///
+/// At least one job runs, even when it doesn't fit in memory.
+pub fn count(
+    cpus: NonZeroUsize,
+    total_memory: u64,
+    max_jobs: Option<NonZeroUsize>,
+    memory_per_job: Option<u64>,
+    overridden: Option<NonZeroUsize>,
+) -> Jobs {
+    if let Some(count) = overridden {
+        return Jobs {
+            count,
+            limit: Limit::Override,
+        };
+    }
+
+    let fitting = memory_per_job
+        .filter(|per_job| *per_job > 0)
+        .map(|per_job| usize::try_from(total_memory / per_job).unwrap_or(usize::MAX))
+        .map(|count| NonZeroUsize::new(count).unwrap_or(NonZeroUsize::MIN));
+
+    [
+        (Some(cpus), Limit::Cpus),
+        (max_jobs, Limit::MaxJobs),
+        (fitting, Limit::Memory),
+    ]
+    .into_iter()
+    .filter_map(|(count, limit)| Some(Jobs { count: count?, limit }))
+    // The first of the lowest, so CPUs win ties
+    .min_by_key(|jobs| jobs.count)
+    .expect("cpus always limit")
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("invalid max_memory_per_job `{0}`, expected a size such as `2G`")]
+    InvalidMemory(String),
+    #[error("query host memory")]
+    Sysinfo(#[from] nix::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const GIB: u64 = 1 << 30;
+
+    fn jobs(count: usize, limit: Limit) -> Jobs {
+        Jobs {
+            count: NonZeroUsize::new(count).unwrap(),
+            limit,
+        }
+    }
+
+    #[test]
+    fn count_jobs() {
+        let n = |count: usize| NonZeroUsize::new(count);
+        let cpus = n(16).unwrap();
+
+        // Unlimited
+        assert_eq!(count(cpus, 64 * GIB, None, None, None), jobs(16, Limit::Cpus));
+
+        // Limited by the recipe
+        assert_eq!(count(cpus, 64 * GIB, n(4), None, None), jobs(4, Limit::MaxJobs));
+        assert_eq!(count(cpus, 64 * GIB, n(32), None, None), jobs(16, Limit::Cpus));
+
+        // Limited by memory, rounding down
+        assert_eq!(count(cpus, 64 * GIB, None, Some(8 * GIB), None), jobs(8, Limit::Memory));
+        assert_eq!(count(cpus, 60 * GIB, None, Some(8 * GIB), None), jobs(7, Limit::Memory));
+        assert_eq!(count(cpus, 64 * GIB, None, Some(2 * GIB), None), jobs(16, Limit::Cpus));
+        assert_eq!(
+            count(cpus, 64 * GIB, n(4), Some(8 * GIB), None),
+            jobs(4, Limit::MaxJobs)
+        );
+        assert_eq!(
+            count(cpus, 64 * GIB, n(12), Some(8 * GIB), None),
+            jobs(8, Limit::Memory)
+        );
+
+        // A job runs even without memory for it
+        assert_eq!(count(cpus, 4 * GIB, None, Some(8 * GIB), None), jobs(1, Limit::Memory));
+        assert_eq!(count(cpus, 64 * GIB, None, Some(0), None), jobs(16, Limit::Cpus));
+
+        // Ties go to the CPUs
+        assert_eq!(count(cpus, 64 * GIB, n(16), Some(4 * GIB), None), jobs(16, Limit::Cpus));
+
+        // Overridden, regardless of limits
+        assert_eq!(
+            count(cpus, 4 * GIB, n(2), Some(8 * GIB), n(24)),
+            jobs(24, Limit::Override)
+        );
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(jobs(16, Limit::Cpus).to_string(), "16");
+        assert_eq!(jobs(7, Limit::Memory).to_string(), "7 (limited by max_memory_per_job)");
+    }
+}
@@ -0,0 +1,200 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Build output kept on disk, with a log file per phase
+//!
+//! Output of each phase is appended to its log within the logs dir of the
+//! recipe, i.e. `/var/cache/boulder/logs/<name>-<version>-<release>`, named
+//! after the target, PGO stage & phase, see [`file_name`]. The
+//! phases run so far are recorded in order in [`INDEX`], along with how they
+//! exited.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::ExitStatus,
+    time::Duration,
+};
+
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    architecture::BuildTarget,
+    build::{job::Phase, pgo},
+};
+
+/// File name of the index of phases run
+pub const INDEX: &str = "index.json";
+
+/// Logs of the phases of a build
+#[derive(Debug)]
+pub struct Logs {
+    dir: PathBuf,
+    /// Only write output to the log files, not the terminal
+    pub quiet: bool,
+    entries: Vec<Entry>,
+}
+
+/// A phase run, as recorded in the [`INDEX`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub target: String,
+    pub pgo_stage: Option<String>,
+    pub phase: String,
+    /// File name of the log, within the logs dir
+    pub log: String,
+    /// Exit code of the phase, absent when killed by a signal
+    pub exit_code: Option<i32>,
+    pub duration_secs: f64,
+}
+
+impl Logs {
+    /// Logs written to `dir`, which must exist
+    pub fn new(dir: impl Into<PathBuf>, quiet: bool) -> Self {
+        Self {
+            dir: dir.into(),
+            quiet,
+            entries: vec![],
+        }
+    }
+
+    /// Open the log of `phase` for appending output
+    pub fn open(&self, target: BuildTarget, pgo_stage: Option<&pgo::Stage>, phase: Phase) -> io::Result<fs::File> {
+        fs::File::options()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(file_name(target, pgo_stage, phase)))
+    }
+
+    /// Record the completion of `phase` in the index
+    ///
+    /// `status` is that of the last script run, which failed unless successful
+    pub fn record(
+        &mut self,
+        target: BuildTarget,
+        pgo_stage: Option<&pgo::Stage>,
+        phase: Phase,
+        status: Option<ExitStatus>,
+        duration: Duration,
+    ) -> io::Result<()> {
+        let exit_code = match status {
+            Some(status) if !status.success() => status.code(),
+            _ => Some(0),
+        };
+
+        self.entries.push(Entry {
+            target: target.to_string(),
+            pgo_stage: pgo_stage.map(|stage| stage.to_string()),
+            phase: phase.to_string().to_lowercase(),
+            log: file_name(target, pgo_stage, phase),
+            exit_code,
+            duration_secs: duration.as_secs_f64(),
+        });
+
+        let index = serde_json::to_string_pretty(&self.entries).map_err(io::Error::other)?;
+        fs::write(self.dir.join(INDEX), index)
+    }
+}
+
+/// File name of the log of `phase`, i.e. `emul32-x86_64-stage1-build.log`
+pub fn file_name(target: BuildTarget, pgo_stage: Option<&pgo::Stage>, phase: Phase) -> String {
+    let stage = pgo_stage.map(|stage| format!("-{stage}")).unwrap_or_default();
+    format!(
+        "{}{stage}-{}.log",
+        target.to_string().replace('/', "-"),
+        phase.to_string().to_lowercase()
+    )
+}
+
+/// Read the index of the logs in `dir`
+pub fn index(dir: &Path) -> io::Result<Vec<Entry>> {
+    serde_json::from_slice(&fs::read(dir.join(INDEX))?).map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod test {
+    use std::process;
+
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::{architecture::Architecture, build::logged};
+
+    #[test]
+    fn phase_logs() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logs = Logs::new(dir.path(), true);
+        let target = BuildTarget::Native(Architecture::X86_64);
+
+        // A fake phase, scripted to write to both pipes
+        let script = |logs: &Logs, stage, phase, marker: &str, code: i32| {
+            let (status, _) = logged(
+                target,
+                stage,
+                phase,
+                logs,
+                "/bin/sh",
+                |command: &mut process::Command| {
+                    command
+                        .arg("-c")
+                        .arg(format!("echo {marker}; echo {marker}-stderr >&2; exit {code}"))
+                },
+            )
+            .unwrap();
+            status
+        };
+
+        let status = script(&logs, None, Phase::Setup, "setup-marker", 0);
+        logs.record(target, None, Phase::Setup, Some(status), Duration::from_millis(1500))
+            .unwrap();
+
+        // Output of the scripts of a phase is appended, up to the failed one
+        let stage = Some(&pgo::Stage::One);
+        assert!(script(&logs, stage, Phase::Build, "build-marker", 0).success());
+        let status = script(&logs, stage, Phase::Build, "failed-marker", 2);
+        assert_eq!(status.code(), Some(2));
+        logs.record(target, stage, Phase::Build, Some(status), Duration::from_secs(3))
+            .unwrap();
+
+        let read = |name: &str| fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(
+            read("x86_64-setup.log").lines().sorted().collect::<Vec<_>>(),
+            ["setup-marker", "setup-marker-stderr"]
+        );
+        let build = read("x86_64-stage1-build.log");
+        for marker in ["build-marker", "failed-marker"] {
+            assert!(build.contains(&format!("{marker}\n")), "{build}");
+            assert!(build.contains(&format!("{marker}-stderr\n")), "{build}");
+        }
+        assert!(!build.contains("setup-marker"));
+
+        assert_eq!(
+            index(dir.path()).unwrap(),
+            [
+                Entry {
+                    target: "x86_64".to_owned(),
+                    pgo_stage: None,
+                    phase: "setup".to_owned(),
+                    log: "x86_64-setup.log".to_owned(),
+                    exit_code: Some(0),
+                    duration_secs: 1.5,
+                },
+                Entry {
+                    target: "x86_64".to_owned(),
+                    pgo_stage: Some("stage1".to_owned()),
+                    phase: "build".to_owned(),
+                    log: "x86_64-stage1-build.log".to_owned(),
+                    exit_code: Some(2),
+                    duration_secs: 3.0,
+                },
+            ]
+        );
+
+        // Each target & stage of a phase has its own log
+        assert_eq!(
+            file_name(BuildTarget::Emul32(Architecture::X86_64), None, Phase::Build),
+            "emul32-x86_64-build.log"
+        );
+    }
+}
@@ -1,33 +1,234 @@
 // SPDX-FileCopyrightText: 2024 AerynOS Developers
 // SPDX-License-Identifier: MPL-2.0
 
+use std::{
+    collections::BTreeSet,
+    fmt,
+    path::{Path, PathBuf},
+};
+
 use stone_recipe::tuning::Toolchain;
+use thiserror::Error;
 
 use crate::architecture::BuildTarget;
 use crate::recipe::Recipe;
 
-pub fn stages(recipe: &Recipe, target: BuildTarget) -> Option<Vec<Stage>> {
+pub fn stages(recipe: &Recipe, target: BuildTarget) -> Result<Option<Vec<Stage>>, Error> {
     let build = recipe.build_target_definition(target);
+    let plan = plan(recipe, target)?;
 
-    build.workload.is_some().then(|| {
-        let mut stages = vec![Stage::One];
+    let mut stages = if !plan.is_empty() {
+        plan.into_iter()
+            .enumerate()
+            .map(|(index, (name, _))| Stage::Workload {
+                index,
+                name: name.to_owned(),
+            })
+            .collect()
+    } else if build.workload.is_some() {
+        vec![Stage::One]
+    } else {
+        return Ok(None);
+    };
 
-        if matches!(recipe.parsed.options.toolchain, Toolchain::Llvm) && recipe.parsed.options.cspgo {
-            stages.push(Stage::Two);
-        }
+    if matches!(recipe.parsed.options.toolchain, Toolchain::Llvm) && recipe.parsed.options.cspgo {
+        stages.push(Stage::Two);
+    }
+
+    stages.push(Stage::Use);
+
+    Ok(Some(stages))
+}
+
+/// The named workloads of `target`, as profiled in order by the `pgo_stages` of `recipe`
+///
+/// Without `pgo_stages`, every named workload is profiled by name. Named workloads of
+/// the build profile of `target` replace those of the recipe.
+pub fn plan(recipe: &Recipe, target: BuildTarget) -> Result<Vec<(&str, &str)>, Error> {
+    let target_build = recipe.build_target_definition(target);
+    let workloads = if target_build.workloads.is_empty() {
+        &recipe.parsed.build.workloads
+    } else {
+        &target_build.workloads
+    };
 
-        stages.push(Stage::Use);
+    let order = &recipe.parsed.options.pgo_stages;
+    if order.is_empty() {
+        return Ok(workloads
+            .iter()
+            .map(|(name, content)| (name.as_str(), content.as_str()))
+            .collect());
+    }
 
-        stages
-    })
+    let mut planned = BTreeSet::new();
+    order
+        .iter()
+        .map(|name| {
+            let content = workloads
+                .get(name)
+                .ok_or_else(|| Error::MissingWorkload(name.clone()))?;
+            if !planned.insert(name) {
+                return Err(Error::DuplicateStage(name.clone()));
+            }
+            Ok((name.as_str(), content.as_str()))
+        })
+        .collect()
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, strum::Display)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Stage {
-    #[strum(serialize = "stage1")]
+    /// Instrumented & profiled by the `workload` of the recipe
     One,
-    #[strum(serialize = "stage1")]
+    /// Instrumented & profiled by the named workload of the `index`th stage of the plan
+    Workload {
+        index: usize,
+        name: String,
+    },
+    /// Context sensitive instrumented & profiled by every workload
     Two,
-    #[strum(serialize = "use")]
     Use,
 }
+
+impl Stage {
+    /// Dir of the profiles of builds in `build_dir`, gathered by this stage
+    ///
+    /// Each named workload gets its own, within that of the build.
+    pub fn profile_dir(&self, build_dir: &Path) -> PathBuf {
+        let dir = PathBuf::from(format!("{}-pgo", build_dir.display()));
+
+        match self {
+            Stage::Workload { name, .. } => dir.join(name),
+            Stage::One | Stage::Two | Stage::Use => dir,
+        }
+    }
+
+    /// Whether the build is instrumented to gather profiles
+    pub fn is_instrumented(&self) -> bool {
+        !matches!(self, Stage::Use)
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::One => f.write_str("stage1"),
+            Stage::Workload { name, .. } => write!(f, "stage1-{name}"),
+            Stage::Two => f.write_str("stage2"),
+            Stage::Use => f.write_str("use"),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("pgo stage {0} has no workload of that name")]
+    MissingWorkload(String),
+    #[error("pgo stage {0} is planned more than once")]
+    DuplicateStage(String),
+}
+
+#[cfg(test)]
+mod test {
+    use fs_err as fs;
+
+    use super::*;
+    use crate::architecture::Architecture;
+
+    const NATIVE: BuildTarget = BuildTarget::Native(Architecture::X86_64);
+
+    fn recipe(dir: &Path, options: &str) -> Recipe {
+        let path = dir.join("stone.yaml");
+        fs::write(
+            &path,
+            format!(
+                "\
+name: zstd
+version: 1.5.7
+release: 1
+homepage: https://example.com
+license: BSD-3-Clause
+summary: Example
+description: Example
+{options}
+build: make
+"
+            ),
+        )
+        .unwrap();
+
+        Recipe::load(&path).unwrap()
+    }
+
+    fn workload(index: usize, name: &str) -> Stage {
+        Stage::Workload {
+            index,
+            name: name.to_owned(),
+        }
+    }
+
+    #[test]
+    fn plan_stages() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert_eq!(stages(&recipe(dir.path(), ""), NATIVE).unwrap(), None);
+        assert_eq!(
+            stages(&recipe(dir.path(), "workload: ./bench"), NATIVE).unwrap(),
+            Some(vec![Stage::One, Stage::Use])
+        );
+        assert_eq!(
+            stages(
+                &recipe(dir.path(), "workload: ./bench\ntoolchain: llvm\ncspgo: true"),
+                NATIVE
+            )
+            .unwrap(),
+            Some(vec![Stage::One, Stage::Two, Stage::Use])
+        );
+
+        let workloads = "workloads:\n    training: ./train\n    sampling: ./sample\ntoolchain: llvm\ncspgo: true";
+        let planned = recipe(dir.path(), &format!("{workloads}\npgo_stages: [training, sampling]"));
+        assert_eq!(
+            plan(&planned, NATIVE).unwrap(),
+            [("training", "./train"), ("sampling", "./sample")]
+        );
+        assert_eq!(
+            stages(&planned, NATIVE).unwrap(),
+            Some(vec![
+                workload(0, "training"),
+                workload(1, "sampling"),
+                Stage::Two,
+                Stage::Use
+            ])
+        );
+
+        // By name without a plan
+        assert_eq!(
+            stages(&recipe(dir.path(), workloads), NATIVE).unwrap().unwrap()[..2],
+            [workload(0, "sampling"), workload(1, "training")]
+        );
+
+        assert!(matches!(
+            stages(&recipe(dir.path(), &format!("{workloads}\npgo_stages: [training, tuning]")), NATIVE),
+            Err(Error::MissingWorkload(name)) if name == "tuning"
+        ));
+        assert!(matches!(
+            stages(&recipe(dir.path(), &format!("{workloads}\npgo_stages: [training, training]")), NATIVE),
+            Err(Error::DuplicateStage(name)) if name == "training"
+        ));
+    }
+
+    #[test]
+    fn profile_dirs() {
+        let build_dir = Path::new("/mason/build/x86_64");
+
+        assert_eq!(Stage::One.profile_dir(build_dir), Path::new("/mason/build/x86_64-pgo"));
+        assert_eq!(Stage::Use.profile_dir(build_dir), Path::new("/mason/build/x86_64-pgo"));
+        assert_eq!(
+            workload(1, "sampling").profile_dir(build_dir),
+            Path::new("/mason/build/x86_64-pgo/sampling")
+        );
+
+        assert_eq!(workload(1, "sampling").to_string(), "stage1-sampling");
+        assert!(workload(1, "sampling") < Stage::Two);
+        assert!(workload(0, "zlib") < workload(1, "bzip2"));
+    }
+}
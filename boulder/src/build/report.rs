@@ -0,0 +1,600 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Time & resources spent by each phase of a build, the use of compiler caches,
+//! why each package of the rootfs was installed, the compiler warnings emitted,
+//! the paths installed outside `/usr` & the changes to the packages since their
+//! previous release
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use fs_err as fs;
+use humansize::BINARY;
+use nix::libc;
+use serde::Serialize;
+
+use crate::{
+    architecture::BuildTarget,
+    build::{
+        deps::Explanation,
+        job::Phase,
+        pgo,
+        warnings::{self, Warning},
+    },
+    compiler_cache,
+    package::{
+        diff::{self, Diff},
+        layout::Stray,
+        unresolved::Unresolved,
+    },
+    timing,
+};
+
+/// Resources used by the processes of a phase
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub wall: Duration,
+    pub user: Duration,
+    pub system: Duration,
+    /// Peak resident set size of the largest process, in bytes
+    pub max_rss: u64,
+}
+
+impl Usage {
+    /// Usage of a waited for process & its own waited for children
+    pub fn from_rusage(rusage: &libc::rusage) -> Self {
+        let duration =
+            |time: libc::timeval| Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64);
+
+        Self {
+            wall: Duration::ZERO,
+            user: duration(rusage.ru_utime),
+            system: duration(rusage.ru_stime),
+            // Reported in KiB on Linux
+            max_rss: rusage.ru_maxrss as u64 * 1024,
+        }
+    }
+
+    /// Accumulate the usage of a process run after this one
+    pub fn add(&mut self, other: Self) {
+        self.wall += other.wall;
+        self.user += other.user;
+        self.system += other.system;
+        self.max_rss = self.max_rss.max(other.max_rss);
+    }
+}
+
+/// Usage of each phase of a build, printed once it completes
+#[derive(Debug, Clone, Default)]
+pub struct BuildReport {
+    phases: BTreeMap<PhaseKey, Usage>,
+    compiler_caches: BTreeMap<compiler_cache::Tool, compiler_cache::Stats>,
+    dependencies: Explanation,
+    packaging: Option<Packaging>,
+    unresolved: Vec<Unresolved>,
+    warnings: Vec<Warning>,
+    /// When each phase run finished, in the order run
+    finished: Vec<(PhaseKey, SystemTime)>,
+    strays: Vec<Stray>,
+    diffs: Vec<Diff>,
+}
+
+/// A phase of the build of a target, in a PGO stage
+pub type PhaseKey = (BuildTarget, Option<pgo::Stage>, Phase);
+
+/// Analysis of the install root & emission of its stones
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Packaging {
+    /// Paths of the install root analyzed
+    pub paths: usize,
+    pub stones: usize,
+    /// Of the rayon pool analyzing & emitting in parallel
+    pub threads: usize,
+    pub analysis: Duration,
+    pub emit: Duration,
+}
+
+impl BuildReport {
+    /// Add `usage` to that of `phase`
+    pub fn record(&mut self, target: BuildTarget, pgo_stage: Option<pgo::Stage>, phase: Phase, usage: Usage) {
+        self.phases.entry((target, pgo_stage, phase)).or_default().add(usage);
+    }
+
+    /// Record the `stats` of a compiler cache over the build
+    pub fn record_compiler_cache(&mut self, tool: compiler_cache::Tool, stats: compiler_cache::Stats) {
+        self.compiler_caches.insert(tool, stats);
+    }
+
+    /// Record why each package of the rootfs was installed
+    pub fn record_dependencies(&mut self, dependencies: Explanation) {
+        self.dependencies = dependencies;
+    }
+
+    /// Record how the stones were packaged
+    pub fn record_packaging(&mut self, packaging: Packaging) {
+        self.packaging = Some(packaging);
+    }
+
+    /// Record the runtime dependencies of the packages that nothing provides
+    pub fn record_unresolved(&mut self, unresolved: Vec<Unresolved>) {
+        self.unresolved = unresolved;
+    }
+
+    /// Runtime dependencies of the packages that nothing provides
+    pub fn unresolved(&self) -> &[Unresolved] {
+        &self.unresolved
+    }
+
+    /// Record that `phase` finished at `at`
+    pub fn record_finished(
+        &mut self,
+        target: BuildTarget,
+        pgo_stage: Option<pgo::Stage>,
+        phase: Phase,
+        at: SystemTime,
+    ) {
+        self.finished.push(((target, pgo_stage, phase), at));
+    }
+
+    /// When each phase run finished, in the order run
+    pub fn finished(&self) -> &[(PhaseKey, SystemTime)] {
+        &self.finished
+    }
+
+    /// Record the paths installed outside `/usr`
+    pub fn record_strays(&mut self, strays: Vec<Stray>) {
+        self.strays = strays;
+    }
+
+    /// Paths installed outside `/usr`, which aren't packaged
+    pub fn strays(&self) -> &[Stray] {
+        &self.strays
+    }
+
+    /// Record the changes to the contents of the packages since their previous release
+    pub fn record_diffs(&mut self, diffs: Vec<Diff>) {
+        self.diffs = diffs;
+    }
+
+    /// Record the compiler warnings found in the logs of the build
+    pub fn record_warnings(&mut self, warnings: Vec<Warning>) {
+        self.warnings = warnings;
+    }
+
+    /// Compiler warnings found in the logs of the build
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Usage of all phases
+    pub fn total(&self) -> Usage {
+        self.phases.values().fold(Usage::default(), |mut total, usage| {
+            total.add(*usage);
+            total
+        })
+    }
+
+    /// Render the report as a table
+    pub fn render(&self) -> String {
+        let rows = self
+            .phases
+            .iter()
+            .map(|((target, stage, phase), usage)| {
+                let stage = stage
+                    .as_ref()
+                    .map(|stage| format!("pgo-{stage}"))
+                    .unwrap_or_else(|| "-".to_owned());
+                ([target.to_string(), stage, phase.to_string()], *usage)
+            })
+            .chain([(["Total".to_owned(), String::new(), String::new()], self.total())])
+            .collect::<Vec<_>>();
+
+        let width = |column: usize, header: &str| {
+            rows.iter()
+                .map(|(labels, _)| labels[column].len())
+                .chain([header.len()])
+                .max()
+                .unwrap_or_default()
+        };
+        let (target, stage, phase) = (width(0, "Target"), width(1, "Stage"), width(2, "Phase"));
+
+        let mut table = format!(
+            "{:<target$}  {:<stage$}  {:<phase$}  {:>13}  {:>13}  {:>13}  {:>10}\n",
+            "Target", "Stage", "Phase", "Wall", "User", "System", "Peak RSS"
+        );
+        for ([t, s, p], usage) in rows {
+            let _ = writeln!(
+                table,
+                "{t:<target$}  {s:<stage$}  {p:<phase$}  {}  {}  {}  {:>10}",
+                timing::fmt_elapsed(usage.wall),
+                timing::fmt_elapsed(usage.user),
+                timing::fmt_elapsed(usage.system),
+                humansize::format_size(usage.max_rss, BINARY),
+            );
+        }
+
+        if let Some(packaging) = &self.packaging {
+            let _ = writeln!(
+                table,
+                "\nAnalyzed {} paths in {} & emitted {} stones in {} ({} threads)",
+                packaging.paths,
+                timing::fmt_elapsed(packaging.analysis).trim_start(),
+                packaging.stones,
+                timing::fmt_elapsed(packaging.emit).trim_start(),
+                packaging.threads,
+            );
+        }
+
+        if !self.compiler_caches.is_empty() {
+            let _ = write!(
+                table,
+                "\nCompiler caches\n{}",
+                compiler_cache::render(&self.compiler_caches)
+            );
+        }
+
+        if !self.warnings.is_empty() {
+            let _ = write!(table, "\nCompiler warnings\n{}", warnings::render(&self.warnings));
+        }
+
+        if !self.diffs.is_empty() {
+            let _ = write!(
+                table,
+                "\nChanges since the previous release\n{}",
+                diff::render(&self.diffs)
+            );
+        }
+
+        table
+    }
+
+    /// Render the report as JSON
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        #[derive(Serialize)]
+        struct Report<'a> {
+            phases: Vec<Entry>,
+            total: Entry,
+            #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+            compiler_caches: &'a BTreeMap<compiler_cache::Tool, compiler_cache::Stats>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            dependencies: Option<&'a Explanation>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            packaging: Option<PackagingEntry>,
+            #[serde(skip_serializing_if = "<[_]>::is_empty")]
+            unresolved_dependencies: &'a [Unresolved],
+            #[serde(skip_serializing_if = "<[_]>::is_empty")]
+            warnings: &'a [Warning],
+            #[serde(skip_serializing_if = "<[_]>::is_empty")]
+            paths_outside_usr: &'a [Stray],
+            #[serde(skip_serializing_if = "<[_]>::is_empty")]
+            changes: &'a [Diff],
+        }
+
+        #[derive(Serialize)]
+        struct PackagingEntry {
+            paths: usize,
+            stones: usize,
+            threads: usize,
+            analysis_secs: f64,
+            emit_secs: f64,
+        }
+
+        #[derive(Serialize)]
+        struct Entry {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            target: Option<String>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pgo_stage: Option<String>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            phase: Option<String>,
+            wall_secs: f64,
+            user_secs: f64,
+            system_secs: f64,
+            max_rss_bytes: u64,
+        }
+
+        impl From<Usage> for Entry {
+            fn from(usage: Usage) -> Self {
+                Self {
+                    target: None,
+                    pgo_stage: None,
+                    phase: None,
+                    wall_secs: usage.wall.as_secs_f64(),
+                    user_secs: usage.user.as_secs_f64(),
+                    system_secs: usage.system.as_secs_f64(),
+                    max_rss_bytes: usage.max_rss,
+                }
+            }
+        }
+
+        let report = Report {
+            phases: self
+                .phases
+                .iter()
+                .map(|((target, stage, phase), usage)| Entry {
+                    target: Some(target.to_string()),
+                    pgo_stage: stage.as_ref().map(|stage| stage.to_string()),
+                    phase: Some(phase.to_string().to_lowercase()),
+                    ..Entry::from(*usage)
+                })
+                .collect(),
+            total: self.total().into(),
+            compiler_caches: &self.compiler_caches,
+            dependencies: Some(&self.dependencies).filter(|dependencies| !dependencies.requested.is_empty()),
+            packaging: self.packaging.map(|packaging| PackagingEntry {
+                paths: packaging.paths,
+                stones: packaging.stones,
+                threads: packaging.threads,
+                analysis_secs: packaging.analysis.as_secs_f64(),
+                emit_secs: packaging.emit.as_secs_f64(),
+            }),
+            unresolved_dependencies: &self.unresolved,
+            warnings: &self.warnings,
+            paths_outside_usr: &self.strays,
+            changes: &self.diffs,
+        };
+
+        serde_json::to_string_pretty(&report)
+    }
+
+    /// Write the report as JSON to `path`
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_json().map_err(io::Error::other)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::architecture::Architecture;
+
+    fn usage(wall: u64, user: u64, system: u64, max_rss: u64) -> Usage {
+        Usage {
+            wall: Duration::from_millis(wall),
+            user: Duration::from_millis(user),
+            system: Duration::from_millis(system),
+            max_rss,
+        }
+    }
+
+    fn report() -> BuildReport {
+        let target = BuildTarget::Native(Architecture::X86_64);
+        let mut report = BuildReport::default();
+
+        report.record(target, None, Phase::Setup, usage(1500, 1000, 250, 8 << 20));
+        report.record(target, None, Phase::Build, usage(61_000, 40_000, 5_000, 512 << 20));
+        // Several scripts of the same phase
+        report.record(target, None, Phase::Build, usage(2_000, 1_000, 500, 1 << 30));
+        report.record(
+            target,
+            Some(pgo::Stage::One),
+            Phase::Build,
+            usage(30_000, 20_000, 1_000, 256 << 20),
+        );
+        report.record(
+            target,
+            Some(pgo::Stage::Two),
+            Phase::Build,
+            usage(10_000, 8_000, 500, 128 << 20),
+        );
+        report
+    }
+
+    #[test]
+    fn render() {
+        assert_eq!(
+            report().render().lines().collect::<Vec<_>>(),
+            [
+                "Target  Stage       Phase           Wall           User         System    Peak RSS",
+                "x86_64  -           Setup          1.50s          1.00s          0.25s       8 MiB",
+                "x86_64  -           Build       1m03.00s         41.00s          5.50s       1 GiB",
+                "x86_64  pgo-stage1  Build         30.00s         20.00s          1.00s     256 MiB",
+                "x86_64  pgo-stage2  Build         10.00s          8.00s          0.50s     128 MiB",
+                "Total                           1m44.50s       1m10.00s          7.25s       1 GiB",
+            ]
+        );
+    }
+
+    #[test]
+    fn json() {
+        let json = serde_json::from_str::<serde_json::Value>(&report().to_json().unwrap()).unwrap();
+
+        assert_eq!(
+            json["phases"][2],
+            serde_json::json!({
+                "target": "x86_64",
+                "pgo_stage": "stage1",
+                "phase": "build",
+                "wall_secs": 30.0,
+                "user_secs": 20.0,
+                "system_secs": 1.0,
+                "max_rss_bytes": 256 << 20,
+            })
+        );
+        assert_eq!(json["phases"][0]["pgo_stage"], serde_json::Value::Null);
+        assert_eq!(json.get("compiler_caches"), None);
+        assert_eq!(json.get("dependencies"), None);
+        assert_eq!(json.get("packaging"), None);
+        assert_eq!(json.get("unresolved_dependencies"), None);
+        assert_eq!(json.get("warnings"), None);
+        assert_eq!(
+            json["total"],
+            serde_json::json!({
+                "wall_secs": 104.5,
+                "user_secs": 70.0,
+                "system_secs": 7.25,
+                "max_rss_bytes": 1 << 30,
+            })
+        );
+    }
+
+    #[test]
+    fn compiler_caches() {
+        let mut report = report();
+        report.record_compiler_cache(
+            compiler_cache::Tool::Ccache,
+            compiler_cache::Stats {
+                hits: 30,
+                misses: 10,
+                size: Some(1 << 30),
+                max_size: None,
+            },
+        );
+
+        assert!(
+            report
+                .render()
+                .ends_with("\nCompiler caches\nccache  30 hits, 10 misses (75.0% hit rate), 1 GiB\n")
+        );
+
+        let json = serde_json::from_str::<serde_json::Value>(&report.to_json().unwrap()).unwrap();
+        assert_eq!(
+            json["compiler_caches"],
+            serde_json::json!({ "ccache": { "hits": 30, "misses": 10, "size": 1 << 30 } })
+        );
+    }
+
+    #[test]
+    fn packaging() {
+        let mut report = report();
+        report.record_packaging(Packaging {
+            paths: 1200,
+            stones: 3,
+            threads: 16,
+            analysis: Duration::from_millis(4_500),
+            emit: Duration::from_millis(75_250),
+        });
+
+        assert!(
+            report
+                .render()
+                .ends_with("\nAnalyzed 1200 paths in 4.50s & emitted 3 stones in 1m15.25s (16 threads)\n")
+        );
+
+        let json = serde_json::from_str::<serde_json::Value>(&report.to_json().unwrap()).unwrap();
+        assert_eq!(
+            json["packaging"],
+            serde_json::json!({
+                "paths": 1200,
+                "stones": 3,
+                "threads": 16,
+                "analysis_secs": 4.5,
+                "emit_secs": 75.25,
+            })
+        );
+    }
+
+    #[test]
+    fn unresolved() {
+        let mut report = report();
+        report.record_unresolved(vec![Unresolved {
+            package: "nano".to_owned(),
+            path: "/usr/bin/nano".into(),
+            dependency: "soname(libmagic.so.1(x86_64))".to_owned(),
+        }]);
+
+        let json = serde_json::from_str::<serde_json::Value>(&report.to_json().unwrap()).unwrap();
+        assert_eq!(
+            json["unresolved_dependencies"],
+            serde_json::json!([
+                { "package": "nano", "path": "/usr/bin/nano", "dependency": "soname(libmagic.so.1(x86_64))" }
+            ])
+        );
+    }
+
+    #[test]
+    fn dependencies() {
+        let mut report = report();
+        report.record_dependencies(Explanation {
+            requested: vec![crate::build::deps::Requested {
+                dependency: "binary(cmake)".to_owned(),
+                package: "cmake".to_owned(),
+                closure: vec!["cmake".to_owned(), "curl".to_owned()],
+            }],
+            dependencies: BTreeMap::from([
+                ("cmake".to_owned(), vec!["curl".to_owned()]),
+                ("curl".to_owned(), vec![]),
+            ]),
+        });
+
+        let json = serde_json::from_str::<serde_json::Value>(&report.to_json().unwrap()).unwrap();
+        assert_eq!(
+            json["dependencies"],
+            serde_json::json!({
+                "requested": [
+                    { "dependency": "binary(cmake)", "package": "cmake", "closure": ["cmake", "curl"] }
+                ],
+                "dependencies": { "cmake": ["curl"], "curl": [] },
+            })
+        );
+    }
+
+    #[test]
+    fn warnings() {
+        let mut report = report();
+        report.record_warnings(vec![Warning {
+            file: "src/main.c".to_owned(),
+            code: "-Wunused-variable".to_owned(),
+            line: Some(12),
+            message: "unused variable 'n'".to_owned(),
+            count: 3,
+            new: true,
+        }]);
+
+        assert!(report.render().ends_with(
+            "\nCompiler warnings\n    3  src/main.c:12  -Wunused-variable (new)\n3 warnings, 1 distinct, 1 new\n"
+        ));
+
+        let json = serde_json::from_str::<serde_json::Value>(&report.to_json().unwrap()).unwrap();
+        assert_eq!(
+            json["warnings"],
+            serde_json::json!([{
+                "file": "src/main.c",
+                "code": "-Wunused-variable",
+                "line": 12,
+                "message": "unused variable 'n'",
+                "count": 3,
+                "new": true,
+            }])
+        );
+    }
+
+    #[test]
+    fn diffs() {
+        let mut report = report();
+        report.record_diffs(vec![Diff {
+            package: "nano".to_owned(),
+            previous: "8.6-1-1".to_owned(),
+            current: "8.7-1-1".to_owned(),
+            added: vec!["bin/rnano".to_owned()],
+            previous_size: 1024,
+            current_size: 1024,
+            ..Diff::default()
+        }]);
+
+        assert!(report.render().ends_with(
+            "\nChanges since the previous release\nnano (8.6-1-1 -> 8.7-1-1)\n  + /usr/bin/rnano\n  Size 1 KiB -> 1 KiB (+0 B)\n"
+        ));
+
+        let json = serde_json::from_str::<serde_json::Value>(&report.to_json().unwrap()).unwrap();
+        assert_eq!(
+            json["changes"],
+            serde_json::json!([{
+                "package": "nano",
+                "previous": "8.6-1-1",
+                "current": "8.7-1-1",
+                "added": ["bin/rnano"],
+                "removed": [],
+                "changed_mode": [],
+                "previous_size": 1024,
+                "current_size": 1024,
+            }])
+        );
+    }
+}
@@ -0,0 +1,369 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Markers of the phases completed by a build, so a failed build can resume
+//!
+//! Each completed phase writes a marker to the build root, namespaced by its
+//! build target & PGO stage. Markers record a [`Fingerprint`] of the recipe &
+//! its upstreams, so they no longer count once either changes. A resumed build
+//! skips the phases completed before, up to the first which isn't.
+//!
+//! A phase is marked as running while it runs, and as failed once the build is
+//! cancelled, see [`crate::build::cancel`].
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    Recipe,
+    architecture::BuildTarget,
+    build::{job::Phase, pgo},
+};
+
+/// A phase of a job
+pub type Key = (BuildTarget, Option<pgo::Stage>, Phase);
+
+/// Hashes of what a build depends on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    /// Hash of the recipe file
+    pub recipe: String,
+    /// Hash of the upstreams, once control file overrides are applied
+    pub upstreams: String,
+}
+
+impl Fingerprint {
+    pub fn new(recipe: &Recipe) -> Self {
+        let mut upstreams = Sha256::new();
+        for upstream in &recipe.parsed.upstreams {
+            upstreams.update(upstream.url.as_str());
+            upstreams.update(format!("{:?}", upstream.props));
+        }
+
+        Self {
+            recipe: hex::encode(Sha256::digest(&recipe.source)),
+            upstreams: hex::encode(upstreams.finalize()),
+        }
+    }
+}
+
+/// Marker of a phase
+#[derive(Debug, Serialize, Deserialize)]
+struct Marker {
+    phase: String,
+    /// Markers predating states are of completed phases
+    #[serde(default)]
+    state: State,
+    #[serde(flatten)]
+    fingerprint: Fingerprint,
+}
+
+/// State of the phase of a [`Marker`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    #[default]
+    Complete,
+    /// Left behind by a build which was cancelled or crashed
+    Running,
+    Failed,
+}
+
+/// Markers of the phases completed by the builds of a recipe
+#[derive(Debug, Clone)]
+pub struct Markers {
+    dir: PathBuf,
+    fingerprint: Fingerprint,
+}
+
+impl Markers {
+    pub fn new(dir: impl Into<PathBuf>, fingerprint: Fingerprint) -> Self {
+        Self {
+            dir: dir.into(),
+            fingerprint,
+        }
+    }
+
+    /// Whether `key` was completed by a build with the same [`Fingerprint`]
+    pub fn is_complete(&self, key: Key) -> bool {
+        self.state(key) == Some(State::Complete)
+    }
+
+    /// State of `key` as marked by a build with the same [`Fingerprint`], if any
+    pub fn state(&self, key: Key) -> Option<State> {
+        read(&self.path(key))
+            .filter(|marker| marker.fingerprint == self.fingerprint)
+            .map(|marker| marker.state)
+    }
+
+    /// Whether any of the `keys` can be skipped by resuming
+    pub fn any_complete(&self, keys: impl IntoIterator<Item = Key>) -> bool {
+        keys.into_iter().any(|key| self.is_complete(key))
+    }
+
+    /// Mark `key` as completed
+    pub fn complete(&self, key: Key) -> io::Result<()> {
+        self.mark(key, State::Complete)
+    }
+
+    /// Mark `key` as running, until completed
+    pub fn start(&self, key: Key) -> io::Result<()> {
+        self.mark(key, State::Running)
+    }
+
+    /// Mark the phases left running as failed, returning the names of their markers
+    pub fn fail_running(&self) -> io::Result<Vec<String>> {
+        let mut failed = vec![];
+
+        if !self.dir.exists() {
+            return Ok(failed);
+        }
+
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let Some(mut marker) = read(&path).filter(|marker| marker.state == State::Running) else {
+                continue;
+            };
+
+            marker.state = State::Failed;
+            write(&path, &marker)?;
+            failed.push(path.file_stem().unwrap_or_default().to_string_lossy().into_owned());
+        }
+
+        failed.sort();
+        Ok(failed)
+    }
+
+    fn mark(&self, key: Key, state: State) -> io::Result<()> {
+        let marker = Marker {
+            phase: key.2.to_string().to_lowercase(),
+            state,
+            fingerprint: self.fingerprint.clone(),
+        };
+
+        fs::create_dir_all(&self.dir)?;
+        write(&self.path(key), &marker)
+    }
+
+    /// Remove all markers, so the next build starts from scratch
+    pub fn clear(&self) -> io::Result<()> {
+        if self.dir.exists() {
+            fs::remove_dir_all(&self.dir)?;
+        }
+        Ok(())
+    }
+
+    fn path(&self, (target, stage, phase): Key) -> PathBuf {
+        let stage = stage.map(|stage| format!("-pgo-{stage}")).unwrap_or_default();
+        let target = target.to_string().replace('/', "-");
+
+        self.dir
+            .join(format!("{target}{stage}-{}.json", phase.to_string().to_lowercase()))
+    }
+}
+
+/// Phases skipped while resuming a build, in the order they run
+#[derive(Debug)]
+pub struct Resume<'a> {
+    markers: &'a Markers,
+    resuming: bool,
+}
+
+impl<'a> Resume<'a> {
+    /// Skip completed phases if `resume`, otherwise run them all
+    pub fn new(markers: &'a Markers, resume: bool) -> Self {
+        Self {
+            markers,
+            resuming: resume,
+        }
+    }
+
+    /// Whether `key` would be skipped, see [`Resume::skip`]
+    pub fn skips(&self, key: Key) -> bool {
+        self.resuming && self.markers.is_complete(key)
+    }
+
+    /// Whether to skip `key`, as it was completed by the build resumed
+    ///
+    /// Once a phase isn't skipped, none of the following are.
+    pub fn skip(&mut self, key: Key) -> bool {
+        self.resuming = self.skips(key);
+        self.resuming
+    }
+
+    /// Mark `key` as running, see [`Markers::start`]
+    pub fn start(&self, key: Key) -> io::Result<()> {
+        self.markers.start(key)
+    }
+
+    /// Mark `key` as completed, for later builds to resume after it
+    pub fn complete(&self, key: Key) -> io::Result<()> {
+        self.markers.complete(key)
+    }
+}
+
+fn read(path: &Path) -> Option<Marker> {
+    fs::read(path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+}
+
+fn write(path: &Path, marker: &Marker) -> io::Result<()> {
+    fs::write(path, serde_json::to_vec_pretty(marker).map_err(io::Error::other)?)
+}
+
+/// The markers of the build of `recipe`, within its build root
+pub fn markers(recipe: &Recipe, build_root: &Path) -> Markers {
+    Markers::new(build_root.join(".markers"), Fingerprint::new(recipe))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::architecture::Architecture;
+
+    const NATIVE: BuildTarget = BuildTarget::Native(Architecture::X86_64);
+    const EMUL32: BuildTarget = BuildTarget::Emul32(Architecture::X86_64);
+
+    fn recipe(dir: &Path, version: &str, upstream: &str) -> Recipe {
+        let path = dir.join("stone.yaml");
+        fs::write(
+            &path,
+            format!(
+                "\
+name: example
+version: {version}
+release: 1
+homepage: https://example.com
+license: MPL-2.0
+summary: example
+description: example
+upstreams:
+  - {upstream}
+"
+            ),
+        )
+        .unwrap();
+        Recipe::load(&path).unwrap()
+    }
+
+    const UPSTREAM: &str =
+        "https://example.com/example-1.0.tar.gz: 5f3d1e8b1f2c6a4d7e9b0c3a2f1e4d5c6b7a8f9e0d1c2b3a4f5e6d7c8b9a0f1e";
+
+    #[test]
+    fn invalidation() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("build");
+        let original = recipe(dir.path(), "1.0", UPSTREAM);
+
+        let marked = markers(&original, &root);
+        marked.complete((NATIVE, None, Phase::Build)).unwrap();
+        assert!(marked.is_complete((NATIVE, None, Phase::Build)));
+        assert!(!marked.is_complete((NATIVE, None, Phase::Install)));
+
+        // Namespaced by target & PGO stage
+        assert!(!marked.is_complete((EMUL32, None, Phase::Build)));
+        assert!(!marked.is_complete((NATIVE, Some(pgo::Stage::One), Phase::Build)));
+        marked
+            .complete((EMUL32, Some(pgo::Stage::Two), Phase::Workload))
+            .unwrap();
+        assert!(marked.is_complete((EMUL32, Some(pgo::Stage::Two), Phase::Workload)));
+        assert!(!marked.is_complete((EMUL32, Some(pgo::Stage::One), Phase::Workload)));
+
+        // Reloading the same recipe keeps them valid
+        assert!(markers(&recipe(dir.path(), "1.0", UPSTREAM), &root).is_complete((NATIVE, None, Phase::Build)));
+
+        // Any change to the recipe invalidates them
+        let changed = markers(&recipe(dir.path(), "1.1", UPSTREAM), &root);
+        assert_eq!(changed.fingerprint.upstreams, marked.fingerprint.upstreams);
+        assert!(!changed.any_complete([
+            (NATIVE, None, Phase::Build),
+            (EMUL32, Some(pgo::Stage::Two), Phase::Workload)
+        ]));
+
+        let upstream = UPSTREAM.replace("1.0", "1.1");
+        let changed = markers(&recipe(dir.path(), "1.0", &upstream), &root);
+        assert_ne!(changed.fingerprint.upstreams, marked.fingerprint.upstreams);
+        assert!(!changed.is_complete((NATIVE, None, Phase::Build)));
+
+        // Markers of the original recipe are left untouched
+        assert!(marked.is_complete((NATIVE, None, Phase::Build)));
+
+        // Nor do those of phases which didn't complete
+        marked.start((NATIVE, None, Phase::Install)).unwrap();
+        assert_eq!(marked.state((NATIVE, None, Phase::Install)), Some(State::Running));
+        assert!(!marked.is_complete((NATIVE, None, Phase::Install)));
+        assert_eq!(marked.fail_running().unwrap(), ["x86_64-install"]);
+        assert_eq!(marked.state((NATIVE, None, Phase::Install)), Some(State::Failed));
+        assert!(marked.fail_running().unwrap().is_empty());
+
+        // Markers predating states are of completed phases
+        fs::write(
+            marked.path((NATIVE, None, Phase::Setup)),
+            serde_json::json!({
+                "phase": "setup",
+                "recipe": marked.fingerprint.recipe,
+                "upstreams": marked.fingerprint.upstreams,
+            })
+            .to_string(),
+        )
+        .unwrap();
+        assert!(marked.is_complete((NATIVE, None, Phase::Setup)));
+
+        // Corrupted markers don't count
+        fs::write(marked.path((NATIVE, None, Phase::Build)), "{").unwrap();
+        assert!(!marked.is_complete((NATIVE, None, Phase::Build)));
+
+        marked.clear().unwrap();
+        assert!(!marked.is_complete((EMUL32, Some(pgo::Stage::Two), Phase::Workload)));
+        assert!(!root.join(".markers").exists());
+    }
+
+    /// Runs the phases of a recipe like a build, failing `check` the first time
+    #[test]
+    fn resume_failed_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let recipe = recipe(dir.path(), "1.0", UPSTREAM);
+        let markers = markers(&recipe, &dir.path().join("build"));
+
+        let phases = [Phase::Prepare, Phase::Setup, Phase::Build, Phase::Install, Phase::Check];
+        let build = |resume: bool, fail: Option<Phase>| {
+            let mut resume = Resume::new(&markers, resume);
+            let mut ran = vec![];
+
+            for phase in phases {
+                let key = (NATIVE, None, phase);
+                if resume.skip(key.clone()) {
+                    continue;
+                }
+
+                ran.push(phase);
+                if fail == Some(phase) {
+                    return ran;
+                }
+                resume.complete(key).unwrap();
+            }
+
+            ran
+        };
+
+        assert_eq!(build(false, Some(Phase::Check)), phases);
+        assert!(markers.any_complete(phases.map(|phase| (NATIVE, None, phase))));
+
+        // Only the failed phase runs again
+        assert_eq!(build(true, None), [Phase::Check]);
+        // Nothing is left to run, but a build which doesn't resume runs everything
+        assert!(build(true, None).is_empty());
+        assert_eq!(build(false, None), phases);
+
+        // Resuming stops skipping at the first incomplete phase
+        fs::remove_file(markers.path((NATIVE, None, Phase::Build))).unwrap();
+        assert_eq!(build(true, None), [Phase::Build, Phase::Install, Phase::Check]);
+    }
+}
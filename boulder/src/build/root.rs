@@ -2,34 +2,40 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use std::collections::BTreeSet;
-use std::{io, iter};
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex};
+use std::{io, iter, mem};
 
 use fs_err as fs;
-use moss::{Installation, repository, runtime, util};
+use moss::client::interaction::{Candidate, Event, Interaction, Question, Terminal, TriggersStage};
+use moss::{
+    Installation, Provider,
+    client::{BlitStats, EphemeralOptions},
+    dependency, package, repository, runtime, util,
+};
 use stone_recipe::tuning::Toolchain;
 use stone_recipe::upstream;
 use thiserror::Error;
+use tui::{ProgressBar, ProgressStyle, Styled};
 
-use crate::build::Builder;
+use crate::build::{Builder, deps::Explanation};
+use crate::package::sbom::Installed;
 use crate::{Timing, container, timing};
 
+/// Install the build dependencies into the rootfs, returning every package installed,
+/// why it was & the runtime providers of the repositories, see [`repository_providers`]
 pub fn populate(
     builder: &Builder,
     repositories: repository::Map,
     timing: &mut Timing,
     initialize_timer: timing::Timer,
     update_repos: bool,
-) -> Result<(), Error> {
-    let packages = packages(builder);
+    offline: bool,
+) -> Result<(Vec<Installed>, Explanation, BTreeSet<Provider>), Error> {
+    let mut packages = packages(builder);
 
-    let rootfs = builder.paths.rootfs().host;
-
-    // Create the moss client
-    let installation = Installation::open(&builder.env.moss_dir, None)?;
-    let mut moss_client = moss::Client::builder("boulder", installation)
-        .repositories(repositories)
-        .ephemeral(rootfs)
-        .build()?;
+    let recorder = Arc::new(Recorder::new(bar()));
+    let mut moss_client = client(builder, repositories, offline, recorder.clone())?;
 
     if update_repos {
         runtime::block_on(moss_client.refresh_repositories())?;
@@ -44,14 +50,321 @@ pub fn populate(
 
     timing.finish(initialize_timer);
 
+    let emul32 = auto_emul32_deps(builder, &packages, &moss_client);
+    packages.extend(emul32.iter().map(String::as_str));
+
     // Install packages
-    let install_timing = moss_client.install(&packages, true, false)?;
+    let outcome = moss_client
+        .install(&packages, &[], true, false)
+        // Reported with the rest of the error once the bar is gone
+        .inspect_err(|_| recorder.draw.clear())?;
+
+    timing.record(timing::Populate::Resolve, outcome.timing.resolve);
+    timing.record(timing::Populate::Fetch, outcome.timing.fetch);
+    timing.record(timing::Populate::Blit, outcome.timing.blit);
+
+    let installed = mem::take(&mut *recorder.installed.lock().unwrap());
+    let explanation = Explanation::new(&outcome.graph, &installed);
+
+    Ok((
+        installed.iter().map(|package| Installed::from(&package.meta)).collect(),
+        explanation,
+        runtime_providers(&moss_client),
+    ))
+}
 
-    timing.record(timing::Populate::Resolve, install_timing.resolve);
-    timing.record(timing::Populate::Fetch, install_timing.fetch);
-    timing.record(timing::Populate::Blit, install_timing.blit);
+/// Shared libraries & interpreters provided by the packages of `repositories`, which
+/// those built may need at runtime, see [`crate::package::unresolved`]
+pub fn repository_providers(
+    builder: &Builder,
+    repositories: repository::Map,
+    offline: bool,
+) -> Result<BTreeSet<Provider>, Error> {
+    let moss_client = client(builder, repositories, offline, Arc::new(Terminal))?;
 
-    Ok(())
+    Ok(runtime_providers(&moss_client))
+}
+
+/// The moss client installing into the rootfs from `repositories`
+fn client(
+    builder: &Builder,
+    repositories: repository::Map,
+    offline: bool,
+    interaction: Arc<dyn Interaction>,
+) -> Result<moss::Client, Error> {
+    // Parallel builds share the moss root, so take turns
+    let installation = Installation::open(&builder.env.moss_dir, None)?.wait_for_lock(true);
+
+    Ok(moss::Client::builder("boulder", installation)
+        .interaction(interaction)
+        .repositories(repositories)
+        .ephemeral_with_options(
+            builder.paths.rootfs().host,
+            EphemeralOptions {
+                run_system_triggers: builder.system_triggers,
+                run_transaction_triggers: true,
+                offline,
+            },
+        )
+        .build()?)
+}
+
+/// The `-32bit-devel` counterparts of the build dependencies, when building
+/// `emul32` unless the recipe opts out, logging those added & missing
+fn auto_emul32_deps(builder: &Builder, requested: &[&str], moss_client: &moss::Client) -> Vec<String> {
+    let recipe = &builder.recipe.parsed;
+    let builds_emul32 = builder.targets.iter().any(|target| target.build_target.emul32());
+    if !(recipe.emul32 && recipe.emul32_deps_auto && builds_emul32) {
+        return vec![];
+    }
+
+    let available = moss_client
+        .list_packages(package::Flags::new().with_available())
+        .map(|package| package.meta.name.to_string())
+        .collect::<BTreeSet<_>>();
+    let deps = emul32_deps(recipe.build.build_deps.iter().map(String::as_str), requested, |name| {
+        available.contains(name)
+    });
+
+    if !deps.added.is_empty() {
+        println!("{} | added {}", "emul32".cyan(), deps.added.join(", "));
+    }
+    if !deps.missing.is_empty() {
+        println!(
+            "{} | no 32-bit counterpart of {}",
+            "emul32".cyan(),
+            deps.missing.join(", ")
+        );
+    }
+    if !(deps.added.is_empty() && deps.missing.is_empty()) {
+        println!();
+    }
+
+    deps.added
+}
+
+/// The 32-bit counterparts of build dependencies
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Emul32Deps {
+    /// Counterparts available, installed alongside
+    added: Vec<String>,
+    /// Build dependencies without a counterpart available
+    missing: Vec<String>,
+}
+
+/// The `-32bit-devel` counterparts of the `-devel` packages of `build_deps`
+/// which `exists`, besides those already `requested`
+fn emul32_deps<'a>(
+    build_deps: impl IntoIterator<Item = &'a str>,
+    requested: &[&str],
+    exists: impl Fn(&str) -> bool,
+) -> Emul32Deps {
+    let mut deps = Emul32Deps::default();
+
+    for name in build_deps.into_iter().collect::<BTreeSet<_>>() {
+        let Some(counterpart) = emul32_counterpart(name) else {
+            continue;
+        };
+        if requested.contains(&counterpart.as_str()) {
+            continue;
+        }
+
+        if exists(&counterpart) {
+            deps.added.push(counterpart);
+        } else {
+            deps.missing.push(name.to_owned());
+        }
+    }
+
+    deps
+}
+
+/// The `-32bit-devel` counterpart of the `-devel` package `name`, i.e.
+/// `zlib-32bit-devel` of `zlib-devel`
+fn emul32_counterpart(name: &str) -> Option<String> {
+    let stem = name.strip_suffix("-devel")?;
+    if stem.is_empty() || stem.ends_with("-32bit") {
+        return None;
+    }
+
+    Some(format!("{stem}-32bit-devel"))
+}
+
+fn runtime_providers(moss_client: &moss::Client) -> BTreeSet<Provider> {
+    moss_client
+        .list_packages(package::Flags::new().with_available())
+        .flat_map(|package| package.meta.providers)
+        .filter(|provider| {
+            matches!(
+                provider.kind,
+                dependency::Kind::SharedLibrary | dependency::Kind::Interpreter
+            )
+        })
+        .collect()
+}
+
+/// Renders the progress of populating the rootfs on a single bar, recording the
+/// packages installed
+struct Recorder {
+    installed: Mutex<Vec<moss::Package>>,
+    population: Mutex<Population>,
+    draw: Box<dyn Draw>,
+}
+
+impl Recorder {
+    fn new(draw: impl Draw + 'static) -> Self {
+        Self {
+            installed: Mutex::default(),
+            population: Mutex::default(),
+            draw: Box::new(draw),
+        }
+    }
+}
+
+impl Interaction for Recorder {
+    fn confirm(&self, question: &Question) -> io::Result<bool> {
+        Terminal.confirm(question)
+    }
+
+    fn choose(&self, provider: &str, candidates: &[Candidate]) -> io::Result<usize> {
+        Terminal.choose(provider, candidates)
+    }
+
+    fn report(&self, event: Event) {
+        let mut population = self.population.lock().unwrap();
+
+        match event {
+            // The rootfs is ephemeral, so every package resolved is added
+            Event::Resolved(resolution) => {
+                population.total = resolution.added.len();
+                self.installed.lock().unwrap().clone_from(&resolution.added);
+                self.draw.status(population.status());
+            }
+            Event::Warning(warning) => self.draw.line(format!("{} | {warning}", "Warning".yellow())),
+            Event::WaitingForLock(holder) => self.draw.line(match holder {
+                Some(holder) => format!("{} | for lock held by PID {}", "Waiting".yellow(), holder.pid),
+                None => format!("{} | for another process to release the lock", "Waiting".yellow()),
+            }),
+            Event::Unpacking { .. } => {
+                population.fetched += 1;
+                self.draw.status(population.status());
+            }
+            Event::Cached { was_cached, .. } => {
+                population.unpacked += 1;
+                population.was_cached += usize::from(was_cached);
+                self.draw.status(population.status());
+            }
+            Event::CacheFailed { .. } => {
+                population.failed += 1;
+                self.draw.status(population.status());
+            }
+            Event::Blitted { entries, total } => {
+                population.blitted = Some((entries, total));
+                self.draw.status(population.status());
+            }
+            Event::BlitFinished(stats) => population.blit = population.blit.merge(stats),
+            Event::TriggersStage(stage) => {
+                population.triggers = Some(stage);
+                self.draw.status(population.status());
+            }
+            Event::Done => {
+                self.draw.clear();
+                self.draw.line(population.summary());
+            }
+            // Progress is shown once packages are unpacked & blitted, other
+            // events aren't reported by installs
+            _ => {}
+        }
+    }
+
+    fn renders_progress(&self) -> bool {
+        true
+    }
+}
+
+/// Progress of populating the rootfs
+#[derive(Debug, Default)]
+struct Population {
+    /// Packages being installed
+    total: usize,
+    fetched: usize,
+    unpacked: usize,
+    /// Of those unpacked, those which weren't downloaded
+    was_cached: usize,
+    failed: usize,
+    /// Entries blitted, of the total
+    blitted: Option<(u64, u64)>,
+    /// What the finished blits wrote
+    blit: BlitStats,
+    triggers: Option<TriggersStage>,
+}
+
+impl Population {
+    /// i.e. `Populating root | fetched 40/40, unpacked 40/40, blitted 45%`
+    fn status(&self) -> String {
+        let total = self.total;
+        let mut status = format!(
+            "Populating root | fetched {}/{total}, unpacked {}/{total}",
+            self.fetched, self.unpacked
+        );
+        if self.failed > 0 {
+            let _ = write!(status, ", {} failed", self.failed);
+        }
+        if let Some((entries, total)) = self.blitted {
+            let _ = write!(status, ", blitted {}%", entries * 100 / total.max(1));
+        }
+        match self.triggers {
+            Some(TriggersStage::Transaction) => status.push_str(", running transaction triggers"),
+            Some(TriggersStage::System) => status.push_str(", running system triggers"),
+            None => {}
+        }
+        status
+    }
+
+    /// i.e. `Populated root with 40 packages (12 downloaded), 51234 entries in 0.42s`
+    fn summary(&self) -> String {
+        format!(
+            "{} root with {} packages ({} downloaded), {} entries in {:.2}s",
+            "Populated".green(),
+            self.total,
+            self.unpacked - self.was_cached,
+            self.blit.num_entries(),
+            self.blit.elapsed.as_secs_f32()
+        )
+    }
+}
+
+/// Where the progress of populating the rootfs is drawn
+trait Draw: Send + Sync {
+    /// Show `status` on the bar, replacing the last one
+    fn status(&self, status: String);
+    /// Print `line` above the bar
+    fn line(&self, line: String);
+    /// Remove the bar
+    fn clear(&self);
+}
+
+impl Draw for ProgressBar {
+    fn status(&self, status: String) {
+        self.set_message(status);
+    }
+
+    fn line(&self, line: String) {
+        self.suspend(|| println!("{line}"));
+    }
+
+    fn clear(&self) {
+        self.finish_and_clear();
+    }
+}
+
+/// The bar drawing the progress of populating the rootfs
+fn bar() -> ProgressBar {
+    ProgressBar::with_draw_target(None, tui::draw_target()).with_style(
+        ProgressStyle::with_template(" {spinner} {wide_msg} ")
+            .unwrap()
+            .tick_chars("--=≡■≡=--"),
+    )
 }
 
 pub fn recreate(builder: &Builder) -> Result<(), Error> {
@@ -83,24 +396,32 @@ fn clean(builder: &Builder) -> Result<(), Error> {
     // We remove certain paths inside the container so we don't
     // get permissions error if this is a rootless build
     // and there's subuid mappings into the user namespace
-    container::exec(&builder.paths, false, || {
-        // Remove install dir
-        let install_dir = builder.paths.install().guest;
-        if install_dir.exists() {
-            fs::remove_dir_all(install_dir)?;
-        }
+    container::exec(
+        &builder.paths,
+        false,
+        &builder.allowlist,
+        &builder.environment,
+        &[],
+        None,
+        || {
+            // Remove install dir
+            let install_dir = builder.paths.install().guest;
+            if install_dir.exists() {
+                fs::remove_dir_all(install_dir)?;
+            }
 
-        for target in &builder.targets {
-            for job in &target.jobs {
-                if job.build_dir.exists() {
-                    // Remove build dir
-                    fs::remove_dir_all(&job.build_dir)?;
+            for target in &builder.targets {
+                for job in &target.jobs {
+                    if job.build_dir.exists() {
+                        // Remove build dir
+                        fs::remove_dir_all(&job.build_dir)?;
+                    }
                 }
             }
-        }
 
-        Ok(()) as io::Result<_>
-    })?;
+            Ok(()) as io::Result<_>
+        },
+    )?;
 
     Ok(())
 }
@@ -140,16 +461,18 @@ fn packages(builder: &Builder) -> Vec<&str> {
                 .flat_map(|kv| kv.value.build_deps.iter().map(String::as_str)),
         ),
     );
-    packages.extend(
-        builder.recipe.parsed.build.check_deps.iter().map(String::as_str).chain(
-            builder
-                .recipe
-                .parsed
-                .profiles
-                .iter()
-                .flat_map(|kv| kv.value.check_deps.iter().map(String::as_str)),
-        ),
-    );
+    if !builder.skip_check {
+        packages.extend(
+            builder.recipe.parsed.build.check_deps.iter().map(String::as_str).chain(
+                builder
+                    .recipe
+                    .parsed
+                    .profiles
+                    .iter()
+                    .flat_map(|kv| kv.value.check_deps.iter().map(String::as_str)),
+            ),
+        );
+    }
 
     for upstream in &builder.recipe.parsed.upstreams {
         if let upstream::Props::Plain { rename, .. } = &upstream.props {
@@ -256,3 +579,178 @@ pub enum Error {
     #[error("container")]
     Container(#[from] container::Error),
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use moss::client::interaction::Resolution;
+
+    use super::*;
+
+    #[test]
+    fn counterpart() {
+        assert_eq!(emul32_counterpart("zlib-devel").as_deref(), Some("zlib-32bit-devel"));
+        assert_eq!(
+            emul32_counterpart("libxml2-devel").as_deref(),
+            Some("libxml2-32bit-devel")
+        );
+        assert_eq!(emul32_counterpart("zlib-32bit-devel"), None);
+        assert_eq!(emul32_counterpart("zlib"), None);
+        assert_eq!(emul32_counterpart("pkgconfig(zlib)"), None);
+        assert_eq!(emul32_counterpart("-devel"), None);
+    }
+
+    #[test]
+    fn auto_deps() {
+        // Packages of the repositories
+        let registry = BTreeSet::from(["zlib-32bit-devel", "libffi-32bit-devel", "glibc-32bit-devel"]);
+        let exists = |name: &str| registry.contains(name);
+
+        let deps = emul32_deps(
+            [
+                "zlib-devel",
+                "libffi-devel",
+                "python-devel",
+                "binary(meson)",
+                "pkgconfig(x11)",
+                "zlib-devel",
+                "glibc-devel",
+            ],
+            // Listed by the emul32 profile already
+            &["glibc-32bit-devel"],
+            exists,
+        );
+        assert_eq!(
+            deps,
+            Emul32Deps {
+                added: vec!["libffi-32bit-devel".to_owned(), "zlib-32bit-devel".to_owned()],
+                missing: vec!["python-devel".to_owned()],
+            }
+        );
+
+        assert_eq!(emul32_deps(["binary(meson)"], &[], exists), Emul32Deps::default());
+    }
+
+    /// Records what would be drawn
+    #[derive(Clone, Default)]
+    struct Calls(Arc<Mutex<Vec<String>>>);
+
+    impl Draw for Calls {
+        fn status(&self, status: String) {
+            self.0.lock().unwrap().push(format!("status {status}"));
+        }
+
+        fn line(&self, line: String) {
+            self.0.lock().unwrap().push(format!("line {line}"));
+        }
+
+        fn clear(&self) {
+            self.0.lock().unwrap().push("clear".to_owned());
+        }
+    }
+
+    fn package(name: &str) -> moss::Package {
+        moss::Package {
+            id: package::Id::from(format!("{name}-hash")),
+            meta: package::fixture::meta(name),
+            flags: package::Flags::new().with_available(),
+        }
+    }
+
+    #[test]
+    fn population_progress() {
+        let calls = Calls::default();
+        let recorder = Recorder::new(calls.clone());
+        assert!(recorder.renders_progress());
+
+        let name = |name: &str| package::Name::from(name.to_owned());
+        for event in [
+            Event::Resolved(Resolution {
+                added: vec![package("bash"), package("nano")],
+                ..Resolution::default()
+            }),
+            Event::Downloading {
+                package: name("bash"),
+                pct: 0.5,
+            },
+            Event::Unpacking { package: name("bash") },
+            Event::Cached {
+                package: name("bash"),
+                was_cached: false,
+            },
+            Event::Warning("repository volatile is outdated".to_owned()),
+            Event::Unpacking { package: name("nano") },
+            Event::Cached {
+                package: name("nano"),
+                was_cached: true,
+            },
+            Event::Blitting,
+            Event::Blitted {
+                entries: 45,
+                total: 100,
+            },
+            Event::Blitted {
+                entries: 100,
+                total: 100,
+            },
+            Event::BlitFinished(BlitStats {
+                num_files: 80,
+                num_symlinks: 5,
+                num_dirs: 15,
+                bytes_linked: 4096,
+                elapsed: Duration::from_millis(420),
+            }),
+            Event::TriggersStage(TriggersStage::Transaction),
+            Event::Done,
+        ] {
+            recorder.report(event);
+        }
+
+        assert_eq!(
+            recorder
+                .installed
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|package| package.meta.name.to_string())
+                .collect::<Vec<_>>(),
+            ["bash", "nano"]
+        );
+        assert_eq!(
+            *calls.0.lock().unwrap(),
+            [
+                "status Populating root | fetched 0/2, unpacked 0/2",
+                "status Populating root | fetched 1/2, unpacked 0/2",
+                "status Populating root | fetched 1/2, unpacked 1/2",
+                "line Warning | repository volatile is outdated",
+                "status Populating root | fetched 2/2, unpacked 1/2",
+                "status Populating root | fetched 2/2, unpacked 2/2",
+                "status Populating root | fetched 2/2, unpacked 2/2, blitted 45%",
+                "status Populating root | fetched 2/2, unpacked 2/2, blitted 100%",
+                "status Populating root | fetched 2/2, unpacked 2/2, blitted 100%, running transaction triggers",
+                "clear",
+                "line Populated root with 2 packages (1 downloaded), 100 entries in 0.42s",
+            ]
+        );
+    }
+
+    #[test]
+    fn population_failure() {
+        let calls = Calls::default();
+        let recorder = Recorder::new(calls.clone());
+
+        recorder.report(Event::Resolved(Resolution {
+            added: vec![package("bash"), package("nano")],
+            ..Resolution::default()
+        }));
+        recorder.report(Event::CacheFailed {
+            package: package::Name::from("nano".to_owned()),
+        });
+
+        assert_eq!(
+            calls.0.lock().unwrap().last().unwrap(),
+            "status Populating root | fetched 0/2, unpacked 0/2, 1 failed"
+        );
+    }
+}
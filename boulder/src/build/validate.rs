@@ -0,0 +1,164 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Macros referenced by the scripts of a recipe that aren't defined
+//!
+//! A typo like `%(instalroot)` would otherwise only fail once the phase using it
+//! runs, so every phase of every build target & PGO stage is checked with the
+//! macros it's built with before building.
+
+use std::fmt;
+
+use stone_recipe::script;
+
+use crate::{
+    Macros, Recipe,
+    architecture::BuildTarget,
+    build::{
+        job::{self, Phase},
+        pgo,
+    },
+};
+
+/// A macro referenced by the script of a phase that isn't defined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub target: BuildTarget,
+    pub pgo_stage: Option<pgo::Stage>,
+    /// `None` when referenced by the environment of the target, shared by its phases
+    pub phase: Option<Phase>,
+    pub undefined: script::Undefined,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.target)?;
+        if let Some(stage) = &self.pgo_stage {
+            write!(f, " pgo-{stage}")?;
+        }
+        match (&self.phase, self.undefined.line) {
+            (Some(phase), Some(line)) => write!(f, " {}, line {line}", phase.to_string().to_lowercase())?,
+            _ => write!(f, " environment")?,
+        }
+        write!(f, ": {}", self.undefined)
+    }
+}
+
+/// Macros referenced by the scripts of builds of `recipe` for `targets` that aren't
+/// defined, in the order the phases run, see [`job::phases`]
+pub fn check(
+    recipe: &Recipe,
+    macros: &Macros,
+    targets: &[BuildTarget],
+    skip_check: bool,
+) -> Result<Vec<Issue>, job::Error> {
+    let mut issues = vec![];
+
+    for &target in targets {
+        let stages = pgo::stages(recipe, target)?
+            .map(|stages| stages.into_iter().map(Some).collect::<Vec<_>>())
+            .unwrap_or_else(|| vec![None]);
+
+        for pgo_stage in stages {
+            for phase in job::phases(pgo_stage.as_ref(), skip_check) {
+                for undefined in phase.undefined(target, pgo_stage.as_ref(), recipe, macros)? {
+                    let issue = if undefined.line.is_some() {
+                        Issue {
+                            target,
+                            pgo_stage: pgo_stage.clone(),
+                            phase: Some(phase),
+                            undefined,
+                        }
+                    } else {
+                        Issue {
+                            target,
+                            pgo_stage: None,
+                            phase: None,
+                            undefined,
+                        }
+                    };
+                    if !issues.contains(&issue) {
+                        issues.push(issue);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(issues)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use fs_err as fs;
+
+    use super::*;
+    use crate::architecture::Architecture;
+
+    const RECIPE: &str = r#"
+name: nano
+version: 8.7
+release: 1
+homepage: https://nano-editor.org
+upstreams:
+  - https://example.com/nano-8.7.tar.xz: cbf684b5a37a3a433e0526beb04a7b3419b71e81b3709c3b0d7ed6a1987d3dcb
+summary: Editor
+description: Editor
+license: GPL-3.0-or-later
+definitions:
+  extra: --enable-utf8
+environment: |
+  export CFLAGS="%(cflags) %(cflag)"
+setup: |
+  %configure %(extra)
+build: |
+  %make
+install: |
+  %make_install
+  install -Dm00644 nanorc %(instalroot)/share/nano/nanorc
+  %(make) check
+"#;
+
+    fn macros() -> Macros {
+        let load = |bytes: &[u8]| stone_recipe::macros::from_slice(bytes).unwrap();
+
+        Macros {
+            arch: BTreeMap::from([
+                (
+                    "base".to_owned(),
+                    load(include_bytes!("../../data/macros/arch/base.yaml")),
+                ),
+                (
+                    "x86_64".to_owned(),
+                    load(include_bytes!("../../data/macros/arch/x86_64.yaml")),
+                ),
+            ]),
+            actions: vec![
+                load(include_bytes!("../../data/macros/actions/autotools.yaml")),
+                load(include_bytes!("../../data/macros/actions/cargo.yaml")),
+            ],
+        }
+    }
+
+    #[test]
+    fn undefined_macros() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stone.yaml");
+        fs::write(&path, RECIPE).unwrap();
+        let recipe = Recipe::load(&path).unwrap();
+
+        let issues = check(&recipe, &macros(), &[BuildTarget::Native(Architecture::X86_64)], false).unwrap();
+
+        assert_eq!(
+            issues.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                // Once, rather than by each phase
+                "x86_64 environment: unknown definition macro %(cflag)",
+                "x86_64 install, line 2: unknown definition macro %(instalroot)",
+                "x86_64 install, line 3: unknown definition macro %(make), did you mean the action %make?",
+            ]
+        );
+    }
+}
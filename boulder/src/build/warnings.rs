@@ -0,0 +1,492 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Compiler warnings found in the logs of a build
+//!
+//! The output of each phase is scanned for the warnings of GCC, Clang & rustc,
+//! which are counted by file & warning and compared against those of a baseline,
+//! i.e. the JSON report of a previous build.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Write as _},
+    io,
+    path::{Component, Path, PathBuf},
+    sync::LazyLock,
+};
+
+use fs_err as fs;
+use itertools::Itertools;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::build::log;
+
+/// Escape sequences of coloured output & hyperlinks, i.e. those of `-fdiagnostics-color`
+static ESCAPE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\x1b\[[0-9;?]*[ -/]*[@-~]|\x1b\][^\x07\x1b]*(?:\x07|\x1b\\)").unwrap());
+/// `file:line[:column]: warning: message [-Wcode]` of GCC & Clang
+static CC: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?<file>[^\s:][^:]*):(?<line>\d+):(?:\d+:)? warning: (?<message>.*?)(?: \[(?<code>-W[^\]]+)\])?$")
+        .unwrap()
+});
+/// `warning[code]: message` heading a diagnostic of rustc
+static RUSTC: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^warning(?:\[(?<code>[^\]]+)\])?: (?<message>.+)$").unwrap());
+/// `--> file:line:column` locating a diagnostic of rustc
+static LOCATION: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*--> (?<file>.+?):(?<line>\d+):\d+$").unwrap());
+/// `= note: #[warn(lint)] on by default`, naming the lint of a diagnostic of rustc
+static LINT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*= note: `#\[warn\((?<lint>[^)]+)\)\]`").unwrap());
+
+/// A warning, counted over each time it was emitted for the same file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Warning {
+    /// Relative to the source dir, when within the build dir
+    pub file: String,
+    /// i.e. `-Wunused-variable` or `unused_variables`, otherwise the message
+    pub code: String,
+    /// Of the first time emitted
+    pub line: Option<u32>,
+    /// Of the first time emitted
+    pub message: String,
+    pub count: usize,
+    /// Not in the baseline
+    #[serde(default)]
+    pub new: bool,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file)?;
+        if let Some(line) = self.line {
+            write!(f, ":{line}")?;
+        }
+        write!(f, ": {}", self.message)?;
+        if self.code != self.message {
+            write!(f, " [{}]", self.code)?;
+        }
+        Ok(())
+    }
+}
+
+/// The warnings of build output, deduplicated by file & code
+#[derive(Debug)]
+pub struct Collector {
+    /// Build dir the absolute paths of files are relative to
+    root: PathBuf,
+    warnings: BTreeMap<(String, String), Warning>,
+}
+
+/// A rustc diagnostic awaiting its location & lint
+#[derive(Debug)]
+struct Pending {
+    code: Option<String>,
+    message: String,
+    location: Option<(String, Option<u32>)>,
+}
+
+impl Collector {
+    /// Collect warnings of builds in `root`, i.e. `/mason/build`
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            warnings: BTreeMap::new(),
+        }
+    }
+
+    /// Add the warnings of `output`
+    pub fn add_log(&mut self, output: &str) {
+        let mut pending = None;
+
+        for line in output.lines() {
+            let line = ESCAPE.replace_all(line, "");
+            let line = line.trim_end();
+
+            if let Some(captures) = CC.captures(line) {
+                self.finish(pending.take());
+                let message = captures["message"].to_owned();
+                let code = captures
+                    .name("code")
+                    .and_then(|code| code.as_str().split(',').next())
+                    .map_or_else(|| message.clone(), str::to_owned);
+                self.add(&captures["file"], captures["line"].parse().ok(), code, message);
+            } else if let Some(captures) = RUSTC.captures(line) {
+                self.finish(pending.take());
+                pending = Some(Pending {
+                    code: captures.name("code").map(|code| code.as_str().to_owned()),
+                    message: captures["message"].to_owned(),
+                    location: None,
+                });
+            } else if let Some(diagnostic) = &mut pending {
+                if let Some(captures) = LOCATION.captures(line) {
+                    diagnostic
+                        .location
+                        .get_or_insert_with(|| (captures["file"].to_owned(), captures["line"].parse().ok()));
+                } else if let Some(captures) = LINT.captures(line) {
+                    diagnostic.code.get_or_insert_with(|| captures["lint"].to_owned());
+                } else if line.is_empty() {
+                    self.finish(pending.take());
+                }
+            }
+        }
+
+        self.finish(pending);
+    }
+
+    /// Add the warnings of the logs of each phase run, as indexed in `dir`
+    pub fn add_logs(&mut self, dir: &Path) -> io::Result<()> {
+        for file_name in log::index(dir)?.into_iter().map(|entry| entry.log).unique() {
+            let output = fs::read(dir.join(file_name))?;
+            self.add_log(&String::from_utf8_lossy(&output));
+        }
+        Ok(())
+    }
+
+    /// The warnings added, by file & code
+    pub fn into_warnings(self) -> Vec<Warning> {
+        self.warnings.into_values().collect()
+    }
+
+    /// Add a rustc diagnostic, skipping those not of a file such as the summary of cargo
+    fn finish(&mut self, pending: Option<Pending>) {
+        if let Some(Pending {
+            code,
+            message,
+            location: Some((file, line)),
+        }) = pending
+        {
+            let code = code.unwrap_or_else(|| message.clone());
+            self.add(&file, line, code, message);
+        }
+    }
+
+    fn add(&mut self, file: &str, line: Option<u32>, code: String, message: String) {
+        let file = normalize(file, &self.root);
+        self.warnings
+            .entry((file.clone(), code.clone()))
+            .or_insert_with(|| Warning {
+                file,
+                code,
+                line,
+                message,
+                count: 0,
+                new: false,
+            })
+            .count += 1;
+    }
+}
+
+/// `path` without `.` & `..`, relative to the source dir when within `root`
+///
+/// Absolute paths within `root` are of the build dir of a target, i.e.
+/// `/mason/build/x86_64/zstd-1.5.7/lib/zstd.c`, so that & the source dir are
+/// skipped. Relative paths are left relative to the dir they were compiled in.
+fn normalize(path: &str, root: &Path) -> String {
+    let mut cleaned = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                cleaned.pop();
+            }
+            component => cleaned.push(component),
+        }
+    }
+
+    match cleaned.strip_prefix(root) {
+        Ok(relative) => {
+            let components = relative.components().count();
+            relative
+                .components()
+                .skip(2.min(components.saturating_sub(1)))
+                .collect::<PathBuf>()
+        }
+        Err(_) => cleaned,
+    }
+    .to_string_lossy()
+    .into_owned()
+}
+
+/// Warnings of a previous build, by file & code
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Baseline(BTreeSet<(String, String)>);
+
+impl Baseline {
+    /// Load the warnings of the JSON build report at `path`, or a JSON list of warnings
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Stored {
+            Warnings(Vec<Warning>),
+            Report {
+                #[serde(default)]
+                warnings: Vec<Warning>,
+            },
+        }
+
+        let stored = serde_json::from_slice(&fs::read(path)?).map_err(|err| Error::Parse(path.to_owned(), err))?;
+        let warnings = match stored {
+            Stored::Warnings(warnings) | Stored::Report { warnings } => warnings,
+        };
+
+        Ok(Self(
+            warnings
+                .into_iter()
+                .map(|warning| (warning.file, warning.code))
+                .collect(),
+        ))
+    }
+
+    /// Mark each of `warnings` not in the baseline as new
+    pub fn mark(&self, warnings: &mut [Warning]) {
+        for warning in warnings {
+            warning.new = !self.0.contains(&(warning.file.clone(), warning.code.clone()));
+        }
+    }
+}
+
+/// Render `warnings` as a table, most frequent first
+pub fn render(warnings: &[Warning]) -> String {
+    let count = warnings.iter().map(|warning| warning.count).sum::<usize>();
+    let new = warnings.iter().filter(|warning| warning.new).count();
+
+    let rows = warnings
+        .iter()
+        .sorted_by_key(|warning| std::cmp::Reverse(warning.count))
+        .map(|warning| {
+            let location = match warning.line {
+                Some(line) => format!("{}:{line}", warning.file),
+                None => warning.file.clone(),
+            };
+            let new = if warning.new { " (new)" } else { "" };
+            (warning.count, location, format!("{}{new}", warning.code))
+        })
+        .collect::<Vec<_>>();
+    let width = rows
+        .iter()
+        .map(|(_, location, _)| location.len())
+        .max()
+        .unwrap_or_default();
+
+    let mut table = String::new();
+    for (count, location, code) in rows {
+        let _ = writeln!(table, "{count:>5}  {location:<width$}  {code}");
+    }
+    let plural = if count == 1 { "" } else { "s" };
+    let _ = write!(table, "{count} warning{plural}, {} distinct", warnings.len());
+    if new > 0 {
+        let _ = write!(table, ", {new} new");
+    }
+    table.push('\n');
+
+    table
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("read warnings baseline")]
+    Read(#[from] io::Error),
+    #[error("parse warnings baseline {0:?}")]
+    Parse(PathBuf, #[source] serde_json::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ROOT: &str = "/mason/build";
+
+    /// GCC 14, building with `-fdiagnostics-color=always` & URLs of the options
+    const GCC: &str = "\
+libtool: compile:  gcc -DHAVE_CONFIG_H -I. -I.. -O2 -c ../lib/xmalloc.c -o xmalloc.o
+\x1b[01m\x1b[K../lib/xmalloc.c:\x1b[m\x1b[K In function '\x1b[01m\x1b[Kxnrealloc\x1b[m\x1b[K':
+\x1b[01m\x1b[K../lib/xmalloc.c:42:10:\x1b[m\x1b[K \x1b[01;35m\x1b[Kwarning: \x1b[m\x1b[Kunused variable '\x1b[01m\x1b[Kn\x1b[m\x1b[K' [\x1b[01;35m\x1b[K\x1b]8;;https://gcc.gnu.org/onlinedocs/gcc/Warning-Options.html#index-Wunused-variable\x07-Wunused-variable\x1b]8;;\x07\x1b[m\x1b[K]
+   42 |   size_t \x1b[01;35m\x1b[Kn\x1b[m\x1b[K;
+      |          \x1b[01;35m\x1b[K^\x1b[m\x1b[K
+/mason/build/x86_64/grep-3.11/lib/xmalloc.c:57:3: warning: implicit declaration of function 'abort' [-Wimplicit-function-declaration]
+/mason/build/x86_64/grep-3.11/src/grep.c:1200:5: warning: 'fd' may be used uninitialized [-Wmaybe-uninitialized]
+cc1: warning: command-line option '-Wno-foo' is valid for C++ but not for C
+/usr/bin/ld: warning: libfoo.so.1, needed by libbar.so, not found
+make[2]: warning: jobserver unavailable: using -j1.  Add '+' to parent make rule.
+";
+
+    /// Clang 19 of a meson build dir, coloured
+    const CLANG: &str = "\
+[12/80] Compiling C object src/libzstd.a.p/compress_zstd_lazy.c.o
+\x1b[1m../src/compress/zstd_lazy.c:1204:17: \x1b[0m\x1b[0;1;35mwarning: \x1b[0m\x1b[1mvariable 'matches' set but not used [-Wunused-but-set-variable]\x1b[0m
+ 1204 |     U32 const matches = 0;
+      | \x1b[0;1;32m                ^
+\x1b[0m\x1b[1m../src/compress/zstd_lazy.c:1310:17: \x1b[0m\x1b[0;1;35mwarning: \x1b[0m\x1b[1mvariable 'matches' set but not used [-Wunused-but-set-variable]\x1b[0m
+./src/common/bits.h:20:5: warning: 'BitScanForward' is deprecated [-Wdeprecated-declarations,-Wdeprecated]
+1 warning generated.
+";
+
+    /// rustc 1.87 through cargo
+    const RUSTC_LOG: &str = "\
+   Compiling fixture v0.1.0 (/mason/build/x86_64/fixture-0.1.0)
+\x1b[0m\x1b[1m\x1b[33mwarning\x1b[0m\x1b[0m\x1b[1m: unused variable: `count`\x1b[0m
+\x1b[0m \x1b[0m\x1b[0m\x1b[1m\x1b[38;5;12m--> \x1b[0m\x1b[0msrc/main.rs:4:9\x1b[0m
+  |
+4 |     let count = 5;
+  |         ^^^^^ help: if this is intentional, prefix it with an underscore: `_count`
+  |
+  = note: `#[warn(unused_variables)]` on by default
+
+warning: unused variable: `total`
+ --> src/main.rs:9:9
+  |
+9 |     let total = 0;
+  |         ^^^^^ help: if this is intentional, prefix it with an underscore: `_total`
+
+warning[E0170]: pattern binding `None` is named the same as one of the variants of the type `Option`
+  --> src/lib.rs:12:9
+   |
+12 |         None => {}
+   |         ^^^^
+
+warning: `fixture` (bin \"fixture\") generated 3 warnings
+    Finished `release` profile [optimized] target(s) in 1.25s
+";
+
+    fn collect(output: &str) -> Vec<(String, String, Option<u32>, usize)> {
+        let mut collector = Collector::new(ROOT);
+        collector.add_log(output);
+        collector
+            .into_warnings()
+            .into_iter()
+            .map(|warning| (warning.file, warning.code, warning.line, warning.count))
+            .collect()
+    }
+
+    fn warning(file: &str, code: &str, line: u32, count: usize) -> (String, String, Option<u32>, usize) {
+        (file.to_owned(), code.to_owned(), Some(line), count)
+    }
+
+    #[test]
+    fn gcc() {
+        assert_eq!(
+            collect(GCC),
+            [
+                warning("lib/xmalloc.c", "-Wimplicit-function-declaration", 57, 1),
+                warning("lib/xmalloc.c", "-Wunused-variable", 42, 1),
+                warning("src/grep.c", "-Wmaybe-uninitialized", 1200, 1),
+            ]
+        );
+
+        let mut collector = Collector::new(ROOT);
+        collector.add_log(GCC);
+        let warnings = collector.into_warnings();
+        assert_eq!(warnings[1].message, "unused variable 'n'");
+        assert_eq!(
+            warnings[1].to_string(),
+            "lib/xmalloc.c:42: unused variable 'n' [-Wunused-variable]"
+        );
+    }
+
+    #[test]
+    fn clang() {
+        assert_eq!(
+            collect(CLANG),
+            [
+                warning("src/common/bits.h", "-Wdeprecated-declarations", 20, 1),
+                warning("src/compress/zstd_lazy.c", "-Wunused-but-set-variable", 1204, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn rustc() {
+        assert_eq!(
+            collect(RUSTC_LOG),
+            [
+                warning("src/lib.rs", "E0170", 12, 1),
+                warning("src/main.rs", "unused variable: `total`", 9, 1),
+                warning("src/main.rs", "unused_variables", 4, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn deduplicate() {
+        let mut collector = Collector::new(ROOT);
+        collector.add_log(GCC);
+        collector.add_log(GCC);
+        collector.add_log(CLANG);
+
+        let warnings = collector.into_warnings();
+        assert_eq!(warnings.len(), 5);
+        assert_eq!(warnings.iter().map(|warning| warning.count).sum::<usize>(), 9);
+        assert_eq!(
+            render(&warnings).lines().collect::<Vec<_>>(),
+            [
+                "    2  lib/xmalloc.c:57               -Wimplicit-function-declaration",
+                "    2  lib/xmalloc.c:42               -Wunused-variable",
+                "    2  src/compress/zstd_lazy.c:1204  -Wunused-but-set-variable",
+                "    2  src/grep.c:1200                -Wmaybe-uninitialized",
+                "    1  src/common/bits.h:20           -Wdeprecated-declarations",
+                "9 warnings, 5 distinct",
+            ]
+        );
+    }
+
+    #[test]
+    fn normalize_paths() {
+        let root = Path::new(ROOT);
+
+        assert_eq!(normalize("../src/main.c", root), "src/main.c");
+        assert_eq!(normalize("./src/../include/./main.h", root), "include/main.h");
+        assert_eq!(
+            normalize("/mason/build/x86_64/zstd-1.5.7/lib/zstd.c", root),
+            "lib/zstd.c"
+        );
+        assert_eq!(
+            normalize("/mason/build/x86_64/zstd-1.5.7/build/../lib/zstd.c", root),
+            "lib/zstd.c"
+        );
+        assert_eq!(normalize("/mason/build/x86_64/generated.c", root), "generated.c");
+        assert_eq!(normalize("/usr/include/stdio.h", root), "/usr/include/stdio.h");
+    }
+
+    #[test]
+    fn baseline() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut collector = Collector::new(ROOT);
+        collector.add_log(GCC);
+        let mut warnings = collector.into_warnings();
+
+        // Of a previous report, lacking the warning of grep.c
+        let report = dir.path().join("report.json");
+        fs::write(
+            &report,
+            serde_json::json!({ "phases": [], "warnings": &warnings[..2] }).to_string(),
+        )
+        .unwrap();
+        Baseline::load(&report).unwrap().mark(&mut warnings);
+        assert_eq!(
+            warnings.iter().map(|warning| warning.new).collect::<Vec<_>>(),
+            [false, false, true]
+        );
+        assert!(render(&warnings).ends_with("-Wmaybe-uninitialized (new)\n3 warnings, 3 distinct, 1 new\n"));
+
+        // A bare list, of a report without warnings
+        let list = dir.path().join("warnings.json");
+        fs::write(&list, serde_json::to_string(&warnings[2..]).unwrap()).unwrap();
+        Baseline::load(&list).unwrap().mark(&mut warnings);
+        assert_eq!(
+            warnings.iter().map(|warning| warning.new).collect::<Vec<_>>(),
+            [true, true, false]
+        );
+
+        fs::write(&report, r#"{ "phases": [] }"#).unwrap();
+        Baseline::load(&report).unwrap().mark(&mut warnings);
+        assert!(warnings.iter().all(|warning| warning.new));
+
+        fs::write(&report, "warnings").unwrap();
+        assert!(matches!(Baseline::load(&report), Err(Error::Parse(..))));
+        assert!(matches!(
+            Baseline::load(&dir.path().join("missing.json")),
+            Err(Error::Read(_))
+        ));
+    }
+}
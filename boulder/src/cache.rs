@@ -0,0 +1,523 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Inventory of the boulder cache, to list & prune what builds leave behind
+//!
+//! Upstreams are stored in `upstreams/fetched` (archives) & `upstreams/git`
+//! (mirrors), the roots of each recipe built in `root`, `build`, `artefacts`
+//! & `logs`, and compilers keep their own caches. A running build claims the
+//! entries it uses in `in-use/<pid>-<n>`, locked for as long as it runs, so they
+//! are never pruned. Builds also mark the upstreams they use as recently used.
+
+use std::{
+    fmt, io,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, SystemTime},
+};
+
+use filetime::FileTime;
+use fs_err as fs;
+use nix::{
+    errno::Errno,
+    fcntl::{FlockArg, flock},
+};
+use walkdir::WalkDir;
+
+/// Directories of the cache holding a build root per recipe
+const BUILD_DIRS: &[&str] = &["root", "build", "artefacts", "logs"];
+/// Directories of the cache shared by the compilers of all builds
+const COMPILER_CACHES: &[&str] = &["ccache", "sccache", "gocache", "gomodcache", "cargocache", "zigcache"];
+/// Directory of the claims of running builds
+const IN_USE: &str = "in-use";
+
+/// Kind of [`Entry`] of the cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    /// A fetched archive upstream
+    Plain,
+    /// A mirror of a Git upstream
+    Git,
+    /// The build root of a recipe
+    Build,
+    /// The cache of a compiler
+    Compiler,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Kind::Plain => write!(f, "plain"),
+            Kind::Git => write!(f, "git"),
+            Kind::Build => write!(f, "build"),
+            Kind::Compiler => write!(f, "compiler"),
+        }
+    }
+}
+
+/// Something stored in the cache, removed as a whole
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub kind: Kind,
+    pub name: String,
+    /// Paths making up the entry, a build root spans several directories
+    pub paths: Vec<PathBuf>,
+    pub bytes: u64,
+    /// When the entry was last modified, or used by a build
+    pub used: SystemTime,
+}
+
+impl Entry {
+    fn new(kind: Kind, name: impl Into<String>, paths: Vec<PathBuf>) -> io::Result<Self> {
+        let mut bytes = 0;
+        let mut used = SystemTime::UNIX_EPOCH;
+
+        for path in &paths {
+            used = used.max(fs::symlink_metadata(path)?.modified()?);
+
+            for entry in WalkDir::new(path) {
+                let metadata = entry?.metadata()?;
+                if metadata.is_file() {
+                    bytes += metadata.len();
+                }
+            }
+        }
+
+        Ok(Self {
+            kind,
+            name: name.into(),
+            paths,
+            bytes,
+            used,
+        })
+    }
+
+    /// Whether any of the `claimed` paths are part of this entry
+    fn is_claimed(&self, claimed: &[PathBuf]) -> bool {
+        self.paths.iter().any(|path| {
+            claimed
+                .iter()
+                .any(|claimed| claimed.starts_with(path) || path.starts_with(claimed))
+        })
+    }
+}
+
+/// The entries of the cache at `cache_dir`, including build roots &
+/// compiler caches if asked for
+pub fn inventory(cache_dir: &Path, builds: bool, compilers: bool) -> io::Result<Vec<Entry>> {
+    let cache_dir = fs::canonicalize(cache_dir)?;
+    let mut entries = upstreams(&cache_dir.join("upstreams"))?;
+
+    if builds {
+        entries.extend(build_roots(&cache_dir)?);
+    }
+    if compilers {
+        for name in COMPILER_CACHES {
+            let path = cache_dir.join(name);
+            if path.exists() {
+                entries.push(Entry::new(Kind::Compiler, *name, vec![path])?);
+            }
+        }
+    }
+
+    entries.sort_by(|a, b| a.used.cmp(&b.used).then_with(|| a.name.cmp(&b.name)));
+    Ok(entries)
+}
+
+/// Stored upstreams, in `fetched/<prefix>/<suffix>/<hash>` & `git/<name>`
+///
+/// The signature of an archive verified, `<hash>.sig`, belongs to its entry.
+fn upstreams(dir: &Path) -> io::Result<Vec<Entry>> {
+    let mut entries = vec![];
+
+    let fetched = dir.join("fetched");
+    if fetched.exists() {
+        for entry in WalkDir::new(&fetched).min_depth(3).max_depth(3) {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.file_type().is_file() && !name.ends_with(".sig") {
+                let signature = entry.path().with_file_name(format!("{name}.sig"));
+                let mut paths = vec![entry.into_path()];
+                if signature.exists() {
+                    paths.push(signature);
+                }
+                entries.push(Entry::new(Kind::Plain, name, paths)?);
+            }
+        }
+    }
+
+    for path in read_dirs(&dir.join("git"))? {
+        let name = file_name(&path);
+        entries.push(Entry::new(Kind::Git, name, vec![path])?);
+    }
+
+    Ok(entries)
+}
+
+/// Build roots of each recipe, spanning the [`BUILD_DIRS`]
+fn build_roots(cache_dir: &Path) -> io::Result<Vec<Entry>> {
+    let mut ids = BUILD_DIRS
+        .iter()
+        .map(|dir| read_dirs(&cache_dir.join(dir)))
+        .collect::<io::Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
+        .map(|path| file_name(&path))
+        .collect::<Vec<_>>();
+    ids.sort();
+    ids.dedup();
+
+    ids.into_iter()
+        .map(|id| {
+            let paths = BUILD_DIRS
+                .iter()
+                .map(|dir| cache_dir.join(dir).join(&id))
+                .filter(|path| path.exists())
+                .collect();
+            Entry::new(Kind::Build, id, paths)
+        })
+        .collect()
+}
+
+/// Which entries to prune
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Policy {
+    /// Entries not used for this long
+    pub older_than: Option<Duration>,
+    /// Least recently used entries, until the rest fit in this many bytes
+    pub max_size: Option<u64>,
+}
+
+/// Pick the `entries` to prune according to `policy`, least recently used first
+///
+/// Entries `claimed` by running builds are never pruned, but count toward
+/// the size all the same
+pub fn evict<'a>(entries: &'a [Entry], claimed: &[PathBuf], policy: Policy, now: SystemTime) -> Vec<&'a Entry> {
+    let mut total = entries.iter().map(|entry| entry.bytes).sum::<u64>();
+
+    let mut candidates = entries
+        .iter()
+        .filter(|entry| !entry.is_claimed(claimed))
+        .collect::<Vec<_>>();
+    candidates.sort_by(|a, b| a.used.cmp(&b.used).then_with(|| a.name.cmp(&b.name)));
+
+    candidates
+        .into_iter()
+        .filter(|entry| {
+            let stale = policy
+                .older_than
+                .is_some_and(|older_than| now.duration_since(entry.used).unwrap_or_default() > older_than);
+            let over = policy.max_size.is_some_and(|max_size| total > max_size);
+
+            if stale || over {
+                total = total.saturating_sub(entry.bytes);
+            }
+            stale || over
+        })
+        .collect()
+}
+
+/// Remove `entry` from the cache
+pub fn remove(entry: &Entry) -> io::Result<()> {
+    for path in &entry.paths {
+        if fs::symlink_metadata(path)?.is_dir() {
+            fs::remove_dir_all(path)?;
+        } else {
+            fs::remove_file(path)?;
+        }
+
+        // Leave no empty `fetched/<prefix>/<suffix>` dirs behind
+        if entry.kind == Kind::Plain {
+            for parent in path.ancestors().skip(1).take(2) {
+                if fs::remove_dir(parent).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Paths of the cache used by a running build, protected from pruning
+/// until dropped
+#[derive(Debug)]
+pub struct Claim {
+    path: PathBuf,
+    _file: fs::File,
+}
+
+impl Claim {
+    /// Claim `paths` of the cache at `cache_dir` for this process, marking
+    /// them as used now
+    ///
+    /// Stored canonicalized, as the entries of the [`inventory`] they're compared to are
+    pub fn new(cache_dir: &Path, paths: Vec<PathBuf>) -> io::Result<Self> {
+        let paths = paths.iter().map(|path| canonical(path)).collect::<Vec<_>>();
+        let dir = cache_dir.join(IN_USE);
+        fs::create_dir_all(&dir)?;
+
+        // Unique to each build of a batch
+        static CLAIMS: AtomicUsize = AtomicUsize::new(0);
+        let name = format!("{}-{}", process::id(), CLAIMS.fetch_add(1, Ordering::Relaxed));
+
+        // Locked before it's named, so it's never seen as stale
+        let path = dir.join(&name);
+        let staging = dir.join(format!(".{name}"));
+        let file = fs::File::create(&staging)?;
+        flock(file.as_raw_fd(), FlockArg::LockExclusive)?;
+        fs::write(&staging, serde_json::to_vec(&paths).map_err(io::Error::other)?)?;
+        fs::rename(&staging, &path)?;
+
+        let now = FileTime::now();
+        for path in paths.iter().filter(|path| path.exists()) {
+            filetime::set_symlink_file_times(path, now, now)?;
+        }
+
+        Ok(Self { path, _file: file })
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// `path` with its longest existing ancestor canonicalized, as claimed paths
+/// may not exist yet
+fn canonical(path: &Path) -> PathBuf {
+    path.ancestors()
+        .find_map(|ancestor| {
+            let canonical = fs::canonicalize(ancestor).ok()?;
+            Some(canonical.join(path.strip_prefix(ancestor).ok()?))
+        })
+        .unwrap_or_else(|| path.to_owned())
+}
+
+/// Paths claimed by running builds, removing the claims of builds which
+/// didn't exit cleanly
+pub fn claimed(cache_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut claimed = vec![];
+
+    for path in read_dirs_and_files(&cache_dir.join(IN_USE))? {
+        if file_name(&path).starts_with('.') {
+            continue;
+        }
+
+        let file = fs::File::open(&path)?;
+        match flock(file.as_raw_fd(), FlockArg::LockSharedNonblock) {
+            Ok(()) => fs::remove_file(&path)?,
+            Err(Errno::EWOULDBLOCK) => {
+                let paths = serde_json::from_slice::<Vec<PathBuf>>(&fs::read(&path)?).unwrap_or_default();
+                claimed.extend(paths.iter().map(|path| canonical(path)));
+            }
+            Err(errno) => return Err(errno.into()),
+        }
+    }
+
+    Ok(claimed)
+}
+
+fn read_dirs(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    Ok(read_dirs_and_files(dir)?
+        .into_iter()
+        .filter(|path| path.is_dir())
+        .collect())
+}
+
+fn read_dirs_and_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+
+    fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect()
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// Writes `bytes` to `path` within `root`, last used `days` ago
+    fn fabricate(root: &Path, path: &str, bytes: usize, days: u32) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, vec![0; bytes]).unwrap();
+
+        let used = FileTime::from_system_time(SystemTime::now() - DAY * days);
+        filetime::set_file_mtime(&path, used).unwrap();
+        // Directory entries are used as a whole
+        for dir in ["upstreams/git/", "root/", "build/", "logs/"] {
+            if let Some(rest) = path
+                .strip_prefix(root.join(dir))
+                .ok()
+                .and_then(|rest| rest.iter().next())
+            {
+                filetime::set_file_mtime(root.join(dir).join(rest), used).unwrap();
+            }
+        }
+    }
+
+    /// A cache with upstreams, build roots & a compiler cache
+    fn cache() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+
+        fabricate(root, "upstreams/fetched/aaaaa/11111/aaaaa0011111", 100, 30);
+        fabricate(root, "upstreams/fetched/bbbbb/22222/bbbbb0022222", 200, 2);
+        fabricate(root, "upstreams/fetched/bbbbb/22222/bbbbb0022222.sig", 0, 2);
+        fabricate(root, "upstreams/git/example.com_repo.git/HEAD", 10, 10);
+        fabricate(root, "upstreams/git/example.com_repo.git/objects/pack", 290, 10);
+        fabricate(root, "root/example-1.0-1/usr/bin/example", 1000, 5);
+        fabricate(root, "build/example-1.0-1/Makefile", 50, 5);
+        fabricate(root, "logs/other-2.0-1/build.log", 5, 1);
+        fabricate(root, "ccache/0/cached", 400, 0);
+
+        dir
+    }
+
+    fn names<'a>(entries: impl IntoIterator<Item = &'a Entry>) -> Vec<(Kind, &'a str, u64)> {
+        entries
+            .into_iter()
+            .map(|entry| (entry.kind, entry.name.as_str(), entry.bytes))
+            .collect()
+    }
+
+    #[test]
+    fn inventory_walk() {
+        let dir = cache();
+
+        // Least recently used first
+        assert_eq!(
+            names(&inventory(dir.path(), false, false).unwrap()),
+            [
+                (Kind::Plain, "aaaaa0011111", 100),
+                (Kind::Git, "example.com_repo.git", 300),
+                (Kind::Plain, "bbbbb0022222", 200),
+            ]
+        );
+
+        let entries = inventory(dir.path(), true, true).unwrap();
+        assert_eq!(
+            names(&entries),
+            [
+                (Kind::Plain, "aaaaa0011111", 100),
+                (Kind::Git, "example.com_repo.git", 300),
+                (Kind::Build, "example-1.0-1", 1050),
+                (Kind::Plain, "bbbbb0022222", 200),
+                (Kind::Build, "other-2.0-1", 5),
+                (Kind::Compiler, "ccache", 400),
+            ]
+        );
+
+        // Build roots span their directories
+        let root = fs::canonicalize(dir.path()).unwrap();
+        assert_eq!(
+            entries[2].paths,
+            [root.join("root/example-1.0-1"), root.join("build/example-1.0-1")]
+        );
+    }
+
+    #[test]
+    fn eviction_policy() {
+        let dir = cache();
+        let entries = inventory(dir.path(), true, true).unwrap();
+        let now = SystemTime::now();
+        let evict = |claimed: &[PathBuf], older_than: Option<u32>, max_size: Option<u64>| {
+            let policy = Policy {
+                older_than: older_than.map(|days| DAY * days),
+                max_size,
+            };
+            names(evict(&entries, claimed, policy, now))
+                .into_iter()
+                .map(|(_, name, _)| name)
+                .collect::<Vec<_>>()
+        };
+
+        assert!(evict(&[], None, None).is_empty());
+        assert_eq!(evict(&[], Some(7), None), ["aaaaa0011111", "example.com_repo.git"]);
+
+        // 2055 bytes in total, least recently used are evicted until the rest fit
+        assert_eq!(evict(&[], None, Some(2055)), Vec::<&str>::new());
+        assert_eq!(evict(&[], None, Some(1700)), ["aaaaa0011111", "example.com_repo.git"]);
+        assert_eq!(
+            evict(&[], None, Some(600)),
+            ["aaaaa0011111", "example.com_repo.git", "example-1.0-1", "bbbbb0022222"]
+        );
+
+        // Either policy evicts
+        assert_eq!(
+            evict(&[], Some(20), Some(1700)),
+            ["aaaaa0011111", "example.com_repo.git"]
+        );
+
+        // Claimed entries are kept, even when within them, but still count
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let claimed = [
+            root.join("upstreams/git/example.com_repo.git"),
+            root.join("root/example-1.0-1/usr"),
+        ];
+        assert_eq!(evict(&claimed, Some(7), None), ["aaaaa0011111"]);
+        assert_eq!(
+            evict(&claimed, None, Some(600)),
+            ["aaaaa0011111", "bbbbb0022222", "other-2.0-1", "ccache"]
+        );
+    }
+
+    #[test]
+    fn claims() {
+        let dir = cache();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let git = root.join("upstreams/git/example.com_repo.git");
+
+        let claim = Claim::new(&root, vec![git.clone()]).unwrap();
+        assert_eq!(claimed(&root).unwrap(), std::slice::from_ref(&git));
+
+        // Claiming marks as used
+        let entries = inventory(&root, false, false).unwrap();
+        assert_eq!(entries.last().unwrap().name, "example.com_repo.git");
+
+        let entry = entries.iter().find(|entry| entry.kind == Kind::Plain).unwrap();
+        remove(entry).unwrap();
+        assert!(!root.join("upstreams/fetched/aaaaa").exists());
+        assert!(root.join("upstreams/fetched").exists());
+
+        drop(claim);
+        assert!(claimed(&root).unwrap().is_empty());
+
+        // Compared canonicalized, whether claimed through a link or not yet existing
+        let link = tempfile::tempdir().unwrap();
+        let linked = link.path().join("cache");
+        std::os::unix::fs::symlink(&root, &linked).unwrap();
+        let claim = Claim::new(
+            &linked,
+            vec![
+                linked.join("upstreams/git/example.com_repo.git"),
+                linked.join("build/new-1.0-1"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(claimed(&root).unwrap(), [git.clone(), root.join("build/new-1.0-1")]);
+        let entries = inventory(&root, false, false).unwrap();
+        let entry = entries
+            .iter()
+            .find(|entry| entry.name == "example.com_repo.git")
+            .unwrap();
+        assert!(entry.is_claimed(&claimed(&root).unwrap()));
+        drop(claim);
+
+        // Claims of builds which didn't exit cleanly are stale
+        fs::write(root.join(IN_USE).join("1"), serde_json::to_vec(&[&git]).unwrap()).unwrap();
+        assert!(claimed(&root).unwrap().is_empty());
+        assert!(!root.join(IN_USE).join("1").exists());
+    }
+}
@@ -12,6 +12,7 @@ use clap_mangen::Man;
 use fs_err::{self as fs, File};
 use thiserror::Error;
 
+mod batch;
 mod build;
 mod cache;
 mod chroot;
@@ -57,6 +58,7 @@ pub struct Global {
 
 #[derive(Debug, clap::Subcommand)]
 pub enum Subcommand {
+    Batch(batch::Command),
     Build(build::Command),
     Cache(cache::Command),
     Chroot(chroot::Command),
@@ -123,6 +125,7 @@ pub fn process() -> Result<(), Error> {
     }
 
     match subcommand {
+        Some(Subcommand::Batch(command)) => batch::handle(command, env)?,
         Some(Subcommand::Build(command)) => build::handle(command, env)?,
         Some(Subcommand::Cache(command)) => cache::handle(command, env)?,
         Some(Subcommand::Chroot(command)) => chroot::handle(command, env)?,
@@ -168,6 +171,8 @@ fn replace_aliases(args: std::env::Args) -> Vec<String> {
 
 #[derive(Debug, Error)]
 pub enum Error {
+    #[error("batch")]
+    Batch(#[source] Box<batch::Error>),
     #[error("build")]
     Build(#[source] Box<build::Error>),
     #[error("cache")]
@@ -184,6 +189,12 @@ pub enum Error {
     Io(#[from] std::io::Error),
 }
 
+impl From<batch::Error> for Error {
+    fn from(error: batch::Error) -> Self {
+        Self::Batch(Box::new(error))
+    }
+}
+
 impl From<build::Error> for Error {
     fn from(error: build::Error) -> Self {
         Self::Build(Box::new(error))
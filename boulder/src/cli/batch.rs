@@ -0,0 +1,208 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use clap::Parser;
+use itertools::Itertools;
+use moss::{repository, runtime};
+use thiserror::Error;
+use tui::Styled;
+
+use super::build;
+use crate::{
+    Env, Recipe, artifacts, batch,
+    recipe::{self, Parsed},
+};
+
+#[derive(Debug, Parser)]
+#[command(
+    about = "Build several stone recipes, ordered by their build dependencies",
+    long_about = "Build several stone recipes, ordered by their build dependencies
+
+Recipes are built after those producing their `builddeps` & `checkdeps`. Each built stone is indexed into the output directory, which following builds install from.
+
+Recipes whose build inputs match those of a stone already in the output directory aren't built again, unless --force is passed."
+)]
+pub struct Command {
+    #[command(flatten)]
+    options: build::Options,
+    #[arg(long, help = "Keep building recipes which don't depend on a failed build")]
+    keep_going: bool,
+    #[arg(
+        required = true,
+        help = "Paths to recipe files, or directories to search for stone.yaml recipes"
+    )]
+    recipes: Vec<PathBuf>,
+}
+
+pub fn handle(command: Command, env: Env) -> Result<(), Error> {
+    let Command {
+        options,
+        keep_going,
+        recipes,
+    } = command;
+
+    if !options.output.exists() {
+        return Err(Error::MissingOutput(options.output));
+    }
+
+    let (recipes, unsupported) = batch::find(&recipes)?
+        .into_iter()
+        .map(Recipe::load)
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .partition::<Vec<_>, _>(|recipe| options.ignore_arch || !recipe.build_targets().is_empty());
+
+    // Recipes for other architectures are skipped rather than failed
+    for recipe in &unsupported {
+        println!(
+            "{} {}: only supports {}",
+            "Skipped".yellow(),
+            recipe.parsed.source.name.as_str().bold(),
+            recipe.parsed.architectures.join(", ")
+        );
+    }
+    if !unsupported.is_empty() {
+        println!();
+    }
+
+    let plan = batch::Plan::new(recipes.iter().map(|recipe| &recipe.parsed))?;
+    let name = |index: &usize| recipes[*index].parsed.source.name.as_str();
+
+    println!("Build order:");
+    for (i, batch) in plan.batches.iter().enumerate() {
+        println!(" {}. {}", i + 1, batch.iter().map(name).join(", "));
+    }
+    println!();
+
+    let local = batch::Local::new(&options.output)?;
+    let mut published = false;
+    let unchanged_in = (!options.force).then_some(options.output.as_path());
+    let mut unchanged = BTreeSet::new();
+
+    let outcome = plan.run(keep_going, |index| {
+        // Only the stones built by this batch are trusted
+        let repositories = if published {
+            local.repositories()?
+        } else {
+            repository::Map::default()
+        };
+        let built = build::build(
+            &recipes[index].path,
+            None,
+            None,
+            None,
+            None,
+            &options,
+            env.clone(),
+            repositories,
+            unchanged_in,
+            Some(&options.output),
+            &[],
+        )?;
+        if let build::Built::Unchanged(_) = built {
+            unchanged.insert(index);
+        }
+
+        let stones = built_stones(&built, &recipes[index].parsed)?;
+        local.publish(stones.iter().map(PathBuf::as_path))?;
+        refresh(&env, local.repositories()?)?;
+        published = true;
+
+        Ok::<_, Error>(())
+    });
+
+    println!();
+    for index in &outcome.built {
+        if unchanged.contains(index) {
+            println!("{} {}", "Unchanged".cyan(), name(index).bold());
+        } else {
+            println!("{} {}", "Built".green(), name(index).bold());
+        }
+    }
+    for (index, error) in &outcome.failed {
+        println!("{} {}: {}", "Failed".red(), name(index).bold(), sources(error));
+    }
+    for index in &outcome.skipped {
+        println!("{} {}", "Skipped".yellow(), name(index).bold());
+    }
+
+    if !outcome.failed.is_empty() {
+        return Err(Error::Failed(outcome.failed.len()));
+    }
+
+    Ok(())
+}
+
+/// The stones of a recipe `built` by the batch, as listed by its artifacts manifest
+fn built_stones(built: &build::Built, recipe: &Parsed) -> Result<Vec<PathBuf>, Error> {
+    let manifest = match built {
+        build::Built::Stones(manifest) => manifest.clone(),
+        // Listed by the manifest of the build matched, if still around
+        build::Built::Unchanged(stone) => {
+            let source = &recipe.source;
+            let manifest = stone.with_file_name(artifacts::Manifest::file_name(
+                &source.name,
+                &source.version,
+                source.release,
+            ));
+            if !manifest.exists() {
+                return Ok(vec![stone.clone()]);
+            }
+            manifest
+        }
+    };
+    let dir = manifest.parent().unwrap_or(Path::new("."));
+
+    Ok(artifacts::Manifest::load(&manifest)?
+        .stones
+        .into_iter()
+        .map(|stone| dir.join(stone.filename))
+        .collect())
+}
+
+/// Reload the index of the batch repository into the moss root
+fn refresh(env: &Env, repositories: repository::Map) -> Result<(), Error> {
+    let installation = moss::Installation::open(&env.moss_dir, None)?.wait_for_lock(true);
+    let mut client = moss::Client::builder("boulder", installation)
+        .repositories(repositories)
+        .build()?;
+    runtime::block_on(client.refresh_repositories())?;
+
+    Ok(())
+}
+
+/// The error & its sources, separated by `: `
+fn sources(error: &Error) -> String {
+    let mut sources = vec![error.to_string()];
+    let mut source = std::error::Error::source(error);
+    while let Some(error) = source {
+        sources.push(error.to_string());
+        source = error.source();
+    }
+    sources.join(": ")
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("output directory does not exist: {0:?}")]
+    MissingOutput(PathBuf),
+    #[error("{0} recipe(s) failed to build")]
+    Failed(usize),
+    #[error("batch")]
+    Batch(#[from] batch::Error),
+    #[error("load recipe")]
+    Recipe(#[from] recipe::Error),
+    #[error("build")]
+    Build(#[from] build::Error),
+    #[error("artifacts manifest")]
+    Artifacts(#[from] artifacts::Error),
+    #[error("moss client")]
+    Client(#[from] moss::client::Error),
+    #[error("moss installation")]
+    Installation(#[from] moss::installation::Error),
+}
@@ -1,25 +1,90 @@
 // SPDX-FileCopyrightText: 2024 AerynOS Developers
 // SPDX-License-Identifier: MPL-2.0
 
-use std::io;
-use std::num::NonZeroU64;
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::error::Error as _;
+use std::io::{self, IsTerminal};
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::path::{Path, PathBuf};
 
-use crate::build::{self, Builder};
-use crate::package::Packager;
-use crate::{Env, Timing, container, package, profile, timing};
+use crate::build::{
+    self, Builder,
+    cancel::{self, Cleanup},
+    environment, inputs,
+    log::Logs,
+    report::BuildReport,
+    resume, warnings,
+};
+use crate::package::{Packager, diff, sbom};
+use crate::{
+    Env, Paths, Recipe, Timing, artifacts, compiler_cache,
+    container::{self, network::Allowlist},
+    package, profile, timing,
+};
 use chrono::Local;
-use clap::Parser;
-use moss::signal::inhibit;
+use clap::{Args, Parser};
+use config::Config;
+use fs_err as fs;
+use itertools::Itertools;
+use moss::{repository, signal::inhibit, util};
+use nix::sys::signal::Signal;
+use serde::Deserialize;
 use thiserror::Error;
 use thread_priority::{NormalThreadSchedulePolicy, ThreadPriority, ThreadSchedulePolicy, thread_native_id};
 use tui::Styled;
 use version_parse::VersionExtractor;
 
+/// File name of the JSON build report, within the build dir
+const REPORT_FILE: &str = "report.json";
+
 #[derive(Debug, Parser)]
 #[command(about = "Build stone package(s) from a stone recipe file")]
 pub struct Command {
-    #[arg(short, long, default_value = "default-x86_64")]
+    #[command(flatten)]
+    options: Options,
+    #[arg(default_value = "./stone.yaml", help = "Path to recipe file")]
+    recipe: PathBuf,
+    /// Verify the built manifest against the provided [MANIFEST] file and fail the build if they don't match
+    ///
+    /// If supplied & the manifests do match, the existing manifests are preserved instead of being overwritten
+    #[arg(long = "verify", value_name = "MANIFEST")]
+    verify_against: Option<PathBuf>,
+    /// Write the time & resources spent by each build phase as JSON to [PATH]
+    #[arg(long, value_name = "PATH")]
+    report: Option<PathBuf>,
+    /// Highlight the compiler warnings not in the build report, or JSON list of warnings, at [PATH]
+    #[arg(long, value_name = "PATH")]
+    warnings_baseline: Option<PathBuf>,
+    /// Archive the build root to [PATH] once populated, to be entered by `boulder chroot --import-root`
+    #[arg(long, value_name = "PATH")]
+    export_root: Option<PathBuf>,
+    /// Print the logs of [PHASE] stored by the last build of the recipe, of each target & PGO stage, instead of building
+    #[arg(
+        long,
+        value_name = "PHASE",
+        value_parser = ["prepare", "setup", "build", "install", "check", "workload"]
+    )]
+    show_log: Option<String>,
+    /// Move the stones built into the local moss repository [NAME] of the host, i.e. `local` for the overlay moss prefers
+    #[arg(long, value_name = "NAME")]
+    mv_to_repo: Option<String>,
+    /// Index the local repository once the stones built are moved into it
+    #[arg(long, requires = "mv_to_repo")]
+    re_index: bool,
+    /// Skip the build when its inputs match those of a stone in the output directory, or the repository of --mv-to-repo
+    #[arg(long)]
+    if_changed: bool,
+    /// Only emit the stone of the (sub-)package [NAME], marking the build as partial. Can be passed multiple times
+    ///
+    /// Every phase still runs. Manifests of the recipe aren't written, and the stones aren't moved by --mv-to-repo without --force
+    #[arg(long, value_name = "NAME", conflicts_with = "verify_against")]
+    only_package: Vec<String>,
+}
+
+/// Options shared by every recipe built
+#[derive(Debug, Args)]
+pub struct Options {
+    #[arg(short, long, default_value = profile::DEFAULT)]
     profile: profile::Id,
     #[arg(
         short,
@@ -35,16 +100,59 @@ pub struct Command {
         help = "Update profile repositories before building"
     )]
     update: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with = "update",
+        help = "Build without network access, using only cached upstreams & stones and local repositories"
+    )]
+    offline: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Resume the previous build of the recipe, skipping the phases it completed"
+    )]
+    resume: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Open an interactive shell in the environment of a failed build phase, aborting the build once exited"
+    )]
+    shell_on_failure: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with = "shell_on_failure",
+        help = "Never open a shell when a build phase fails, whatever the configured default"
+    )]
+    no_shell_on_failure: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Build recipes which don't list the host in their architectures"
+    )]
+    pub ignore_arch: bool,
     #[arg(
         long = "normal-priority",
         help = "Run the build without lowering the process priority",
         default_value_t = false
     )]
     normal_priority: bool,
-    #[arg(short, long, default_value = ".", help = "Directory to store build results")]
-    output: PathBuf,
-    #[arg(default_value = "./stone.yaml", help = "Path to recipe file")]
-    recipe: PathBuf,
+    #[arg(
+        short,
+        long,
+        default_value_t = false,
+        help = "Only show build progress, writing the output of each phase to its log"
+    )]
+    quiet: bool,
+    #[arg(
+        short,
+        long = "output-dir",
+        visible_alias = "output",
+        default_value = ".",
+        help = "Directory to write the stones built & their manifests to"
+    )]
+    pub output: PathBuf,
     #[arg(
         short,
         long,
@@ -58,32 +166,134 @@ pub struct Command {
         default_value_t = false
     )]
     cleanup: bool,
-    /// Verify the built manifest against the provided [MANIFEST] file and fail the build if they don't match
-    ///
-    /// If supplied & the manifests do match, the existing manifests are preserved instead of being overwritten
-    #[arg(long = "verify", value_name = "MANIFEST")]
-    verify_against: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        help = "Write a software bill of materials alongside each stone built"
+    )]
+    sbom: Option<sbom::Format>,
+    #[arg(
+        long = "env",
+        value_name = "KEY=VALUE",
+        value_parser = environment::parse_var,
+        help = "Set a variable in the build environment, overriding that of the profile. Can be passed multiple times"
+    )]
+    environment: Vec<(String, String)>,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Print why each package of the build root was installed, by the dependency pulling it in"
+    )]
+    explain_deps: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Fail the build when a shared library or interpreter the packages need at runtime is provided by \
+                neither them nor the repositories of the profile, rather than warning"
+    )]
+    strict_deps: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Fail the build when it installs paths outside /usr not allowed by the recipe, rather than warning"
+    )]
+    strict_layout: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Replace stone.lock with the upstreams fetched, rather than failing when they no longer match it"
+    )]
+    update_lock: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Build even when the inputs of the build match those of a stone already built, and move the stones \
+                of a partial build with --mv-to-repo"
+    )]
+    pub force: bool,
+    #[arg(
+        short,
+        long,
+        value_name = "N",
+        help = "Run N jobs in parallel, overriding the CPUs of the host & the limits of the recipe"
+    )]
+    jobs: Option<NonZeroUsize>,
+    #[arg(
+        long,
+        value_name = "STONE",
+        help = "Diff the contents of the packages built against those of these stones, rather than of their previous \
+                release in the output directory or the repository of --mv-to-repo. Can be passed multiple times"
+    )]
+    compare_to: Vec<PathBuf>,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Build even when the scripts of the recipe use macros that aren't defined, rather than failing ahead of time"
+    )]
+    skip_validation: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Omit the check phase & its dependencies, whatever the recipe"
+    )]
+    skip_check: bool,
+}
+
+/// What [`build`] did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Built {
+    /// Listed by the artifacts manifest at the path, see [`artifacts::Manifest`]
+    Stones(PathBuf),
+    /// Skipped, as the stone at the path was built from the same inputs
+    Unchanged(PathBuf),
+}
+
+/// Defaults of the build options, from the `build` config domain
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+struct Defaults {
+    /// Open a shell when a build phase fails, as `--shell-on-failure`
+    #[serde(default)]
+    shell_on_failure: bool,
+}
+
+impl Defaults {
+    /// The defaults of `config`, later files overriding earlier ones
+    fn load(config: &config::Manager) -> Self {
+        config
+            .load::<Self>()
+            .pop()
+            .map(|loaded| loaded.value)
+            .unwrap_or_default()
+    }
+}
+
+impl Config for Defaults {
+    fn domain() -> String {
+        "build".into()
+    }
 }
 
 pub fn handle(command: Command, env: Env) -> Result<(), Error> {
-    let output = command.output.clone();
     let Command {
-        profile,
+        options,
         recipe: recipe_path,
-        ccache,
-        update,
-        normal_priority,
-        build_release,
-        cleanup,
         verify_against,
-        ..
+        report,
+        warnings_baseline,
+        export_root,
+        show_log,
+        mv_to_repo,
+        re_index,
+        if_changed,
+        only_package,
     } = command;
 
-    let mut timing = Timing::default();
-    let timer = timing.begin(timing::Kind::Initialize);
+    if let Some(phase) = show_log {
+        return print_log(&recipe_path, &phase, env);
+    }
 
-    if !output.exists() {
-        return Err(Error::MissingOutput(output));
+    if !options.output.exists() {
+        return Err(Error::MissingOutput(options.output));
     }
 
     // Ensure verify against path isn't json/jsonc since
@@ -97,17 +307,208 @@ pub fn handle(command: Command, env: Env) -> Result<(), Error> {
         return Err(Error::VerifyBinaryManifestRequired(path.to_owned()));
     }
 
-    let builder = Builder::new(&recipe_path, verify_against.clone(), env, profile, ccache, output)?;
+    // Resolved ahead of building, so a misconfigured repository fails early
+    let repo = mv_to_repo
+        .map(|name| artifacts::resolve_local_repo(&config::Manager::system("/", "moss"), &name))
+        .transpose()?;
+
+    let warnings_baseline = warnings_baseline
+        .map(|path| warnings::Baseline::load(&path))
+        .transpose()?;
+
+    let unchanged_in = (if_changed && !options.force).then(|| repo.as_deref().unwrap_or(&options.output));
+
+    let built = build(
+        &recipe_path,
+        verify_against,
+        report.as_deref(),
+        warnings_baseline.as_ref(),
+        export_root.as_deref(),
+        &options,
+        env,
+        repository::Map::default(),
+        unchanged_in,
+        Some(repo.as_deref().unwrap_or(&options.output)),
+        &only_package,
+    )?;
+
+    if let Some(repo) = repo
+        && let Built::Stones(manifest) = built
+    {
+        let moved = artifacts::move_stones(&manifest, &repo, options.force)?;
+        println!("Moved {} stones to {}", moved.len(), repo.display());
+
+        if re_index {
+            artifacts::reindex(&repo)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Build & package the recipe at `recipe_path`, installing build dependencies
+/// from `repositories` alongside those of the profile
+///
+/// The [`BuildReport`] is printed once built, and written as JSON to `report_path`.
+/// Compiler warnings not in `warnings_baseline` are highlighted as new.
+/// The build root is archived to `export_root` once populated. The build is skipped
+/// when a stone of `unchanged_in` was built from the same inputs, see [`inputs`].
+/// The contents of the packages built are diffed against those of their previous
+/// release in `previous_in`, see [`diff`]. Only the packages named by `only_packages`
+/// are emitted when given, see [`Packager::restrict_to`].
+#[allow(clippy::too_many_arguments)]
+pub fn build(
+    recipe_path: &Path,
+    verify_against: Option<PathBuf>,
+    report_path: Option<&Path>,
+    warnings_baseline: Option<&warnings::Baseline>,
+    export_root: Option<&Path>,
+    options: &Options,
+    env: Env,
+    repositories: repository::Map,
+    unchanged_in: Option<&Path>,
+    previous_in: Option<&Path>,
+    only_packages: &[String],
+) -> Result<Built, Error> {
+    let Options {
+        profile,
+        ccache,
+        update,
+        offline,
+        resume,
+        shell_on_failure,
+        no_shell_on_failure,
+        ignore_arch,
+        normal_priority,
+        quiet,
+        output,
+        build_release,
+        cleanup,
+        sbom,
+        environment,
+        explain_deps,
+        strict_deps,
+        strict_layout,
+        update_lock,
+        force: _,
+        jobs,
+        compare_to,
+        skip_validation,
+        skip_check,
+    } = options;
+
+    let mut timing = Timing::default();
+    let timer = timing.begin(timing::Kind::Initialize);
+
+    let mut builder = match Builder::new(
+        recipe_path,
+        verify_against,
+        env,
+        profile.clone(),
+        *ccache,
+        *ignore_arch,
+        output,
+        *jobs,
+        *skip_check,
+        *skip_validation,
+    ) {
+        Err(build::Error::UndefinedMacros(undefined)) => {
+            for issue in &undefined {
+                println!("{} | {issue}", "Error".red());
+            }
+            return Err(Error::UndefinedMacros(undefined.len()));
+        }
+        result => result?,
+    };
+    builder.add_repositories(repositories);
+
+    if *resume {
+        builder.resume = builder.can_resume();
+    }
+    // The shell needs a terminal, so the configured default only applies with one
+    builder.shell_on_failure = !*no_shell_on_failure
+        && (*shell_on_failure || (Defaults::load(&builder.env.config).shell_on_failure && io::stdin().is_terminal()));
+    builder.update_lock = *update_lock;
+    for (key, value) in environment {
+        builder.environment.set(key, value);
+    }
     let pkg_name = format!(
         "{}-{}-{}",
         builder.recipe.parsed.source.name, builder.recipe.parsed.source.version, builder.recipe.parsed.source.release
     );
     println!("boulder {}", tools_buildinfo::get_simple_version());
-    println!("└─ building {pkg_name}-{build_release}\n");
-    builder.setup(&mut timing, timer, update)?;
+    println!("└─ building {pkg_name}-{build_release}");
+    println!("└─ jobs: {}", builder.jobs);
+    if !builder.environment.is_empty() {
+        println!("└─ environment: {}", builder.environment);
+    }
+    println!();
+    match (resume, builder.resume) {
+        (true, true) => println!("Resuming the previous build\n"),
+        (true, false) => println!("No previous build to resume, building from scratch\n"),
+        _ => {}
+    }
+
+    // Held until the build exits, cleaning up after any build which crashed
+    let (_lock, stale) = cancel::Lock::acquire(&builder.paths)?;
+    let markers = resume::markers(&builder.recipe, &builder.paths.build().host);
+    if stale {
+        match cancel::cleanup(&builder.paths, &markers) {
+            Ok(cleanup) => println!(
+                "{} | the previous build didn't exit cleanly, {}\n",
+                "Cleaned up".yellow(),
+                render_cleanup(&cleanup)
+            ),
+            Err(error) => warn_cleanup_failed(&error),
+        }
+    }
+
+    builder.setup(&mut timing, timer, *update, *offline)?;
+
+    let input_hash = builder.input_hash()?;
+    let index_path = builder.env.cache_dir.join(inputs::INDEX_FILE);
+    if let Some(dir) = unchanged_in
+        && let Some(stone) = inputs::find(dir, &input_hash, &inputs::Index::load(&index_path))
+    {
+        println!(
+            "{} | inputs match those {} was built from, skipping the build\n",
+            "Unchanged".cyan(),
+            stone.display()
+        );
+        if *cleanup {
+            builder.cleanup().map_err(Error::Cleanup)?;
+        }
+        return Ok(Built::Unchanged(stone));
+    }
+
+    if *explain_deps {
+        println!("Build dependencies:\n{}", builder.dependencies.render());
+    }
+
+    if let Some(path) = export_root {
+        let manifest = builder.export_root(path)?;
+        println!(
+            "Exported build root of {} packages to {}\n",
+            manifest.packages.len(),
+            path.display()
+        );
+    }
+
+    let networking = builder.recipe.parsed.options.networking && !offline;
+    if builder.allowlist.restricts(networking) {
+        Allowlist::set_proxy(&mut builder.environment);
+    }
+    // Read ahead of building, as the stones built replace those of the same release
+    let previous = if compare_to.is_empty() {
+        let source = &builder.recipe.parsed.source;
+        previous_in
+            .map(|dir| diff::previous_in(dir, &source.name, (source.release, build_release.get())))
+            .unwrap_or_default()
+    } else {
+        diff::contents_of(compare_to)?
+    };
 
     let paths = &builder.paths;
-    let networking = builder.recipe.parsed.options.networking;
 
     // Set the current thread priority to SCHED_BATCH so that it's inherited by all child processes
     if !normal_priority {
@@ -137,38 +538,246 @@ pub fn handle(command: Command, env: Env) -> Result<(), Error> {
         "block".into(),
     );
 
+    if *quiet {
+        println!("Writing build output to {}\n", paths.logs().host.display());
+    }
+
     // Build & package from within container
-    container::exec::<Error>(paths, networking, || {
-        builder.build(&mut timing)?;
+    let result =
+        container::exec_cancellable::<Error>(paths, networking, &builder.allowlist, &builder.environment, || {
+            let mut report = BuildReport::default();
+            report.record_dependencies(builder.dependencies.clone());
+            let mut logs = Logs::new(&paths.logs().guest, *quiet);
+
+            // ccache counts across builds, so those of this build are the difference
+            let ccache_before = ccache.then(|| compiler_cache_stats(paths, &[compiler_cache::Tool::Ccache]));
+
+            builder.build(&mut timing, &mut report, &mut logs)?;
+
+            let mut collector = warnings::Collector::new(&paths.build().guest);
+            collector.add_logs(&paths.logs().guest).map_err(Error::Log)?;
+            let mut compiler_warnings = collector.into_warnings();
+            if let Some(baseline) = warnings_baseline {
+                baseline.mark(&mut compiler_warnings);
+            }
+            report.record_warnings(compiler_warnings);
+
+            if let Some(before) = ccache_before {
+                let tools = [compiler_cache::Tool::Ccache, compiler_cache::Tool::Sccache];
+                for (tool, stats) in compiler_cache_stats(paths, &tools) {
+                    let stats = before.get(&tool).map_or(stats, |before| stats.since(before));
+                    report.record_compiler_cache(tool, stats);
+                }
+            }
+
+            let mut packager = Packager::new(
+                &builder.paths,
+                &builder.recipe,
+                &builder.macros,
+                &builder.targets,
+                *build_release,
+                &input_hash,
+                builder.verifications(),
+                profile,
+            )?;
+            if !only_packages.is_empty() {
+                packager.restrict_to(only_packages)?;
+            }
+            let stones = packager.package(&mut timing, &mut report, &builder.repository_providers)?;
+
+            let label = if *strict_deps {
+                "Error".red()
+            } else {
+                "Warning".yellow()
+            };
+            for unresolved in report.unresolved() {
+                println!("{label} | {unresolved}");
+            }
 
-        let packager = Packager::new(
-            &builder.paths,
-            &builder.recipe,
-            &builder.macros,
-            &builder.targets,
-            build_release,
-        )?;
-        packager.package(&mut timing)?;
+            let strict_layout = *strict_layout || builder.strict_layout;
+            let label = if strict_layout {
+                "Error".red()
+            } else {
+                "Warning".yellow()
+            };
+            for stray in report.strays() {
+                println!("{label} | {stray}");
+            }
+
+            if let Some(format) = sbom {
+                sbom::write(&paths.artefacts().guest, *format, &builder.sbom_inputs(), &stones)?;
+            }
 
-        timing.print_table();
+            if !previous.is_empty() {
+                let built = stones
+                    .iter()
+                    .map(|stone| paths.artefacts().guest.join(&stone.filename))
+                    .collect::<Vec<_>>();
+                report.record_diffs(diff::diff(&previous, &diff::contents_of(&built)?));
+            }
 
-        Ok(())
-    })?;
+            timing.print_table();
+            println!();
+            print!("{}", report.render());
+            for warning in report.warnings().iter().filter(|warning| warning.new) {
+                println!("{} | {warning}", "New warning".yellow());
+            }
+
+            // Written within the build dir, as only it's shared with the host
+            if report_path.is_some() {
+                report
+                    .save(&paths.build().guest.join(REPORT_FILE))
+                    .map_err(Error::Report)?;
+            }
+
+            if *strict_deps && !report.unresolved().is_empty() {
+                return Err(Error::UnresolvedDependencies(report.unresolved().len()));
+            }
+            if strict_layout && !report.strays().is_empty() {
+                return Err(Error::PathsOutsideUsr(report.strays().len()));
+            }
+
+            Ok(())
+        });
+    if let Err(error) = result {
+        // Reported as a warning, so it doesn't hide why the build failed
+        let cleanup = cancel::cleanup(paths, &markers).inspect_err(warn_cleanup_failed).ok();
+        if let Some(signal) = ::container::received_signal() {
+            match cleanup {
+                Some(cleanup) => println!("\n{} | {signal}, {}", "Cancelled".red(), render_cleanup(&cleanup)),
+                None => println!("\n{} | {signal}", "Cancelled".red()),
+            }
+            return Err(Error::Cancelled(signal));
+        }
+        return Err(error.into());
+    }
 
     // Copy artefacts to host recipe dir
     package::sync_artefacts(paths).map_err(Error::SyncArtefacts)?;
 
-    if cleanup {
+    let stones = util::enumerate_files(&paths.artefacts().host, |path| {
+        path.extension().is_some_and(|extension| extension == "stone")
+    })
+    .map_err(Error::SyncArtefacts)?;
+    // Partial builds don't stand in for a full one of the same inputs
+    if only_packages.is_empty() {
+        let mut index = inputs::Index::load(&index_path);
+        index.record(
+            &input_hash,
+            stones
+                .iter()
+                .filter_map(|stone| Some(stone.file_name()?.to_string_lossy().into_owned())),
+        );
+        index.save(&index_path).map_err(Error::InputIndex)?;
+    }
+
+    if let Some(report_path) = report_path {
+        fs::copy(paths.build().host.join(REPORT_FILE), report_path).map_err(Error::Report)?;
+    }
+
+    if *cleanup {
         builder.cleanup().map_err(Error::Cleanup)?;
     }
 
     verify_versions_match(&builder)?;
 
+    builder.save_lock()?;
+
     println!(
         "Build finished successfully at {}",
         Local::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
     );
 
+    let source = &builder.recipe.parsed.source;
+    Ok(Built::Stones(paths.output_dir().join(artifacts::Manifest::file_name(
+        &source.name,
+        &source.version,
+        source.release,
+    ))))
+}
+
+/// What was cleaned up after a build, i.e. `removed 2 partial stones`
+fn render_cleanup(cleanup: &Cleanup) -> String {
+    if cleanup.is_empty() {
+        return "nothing to clean up".to_owned();
+    }
+
+    [
+        (cleanup.unmounted.len(), "unmounted", "stale mounts"),
+        (cleanup.removed.len(), "removed", "partial artefacts"),
+        (cleanup.failed.len(), "marked", "phases as failed"),
+    ]
+    .into_iter()
+    .filter(|(count, _, _)| *count > 0)
+    .map(|(count, action, what)| format!("{action} {count} {what}"))
+    .join(", ")
+}
+
+/// Warn cleaning up after a build failed with `error`, along with its sources
+fn warn_cleanup_failed(error: &cancel::Error) {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        message.push_str(&format!(": {error}"));
+        source = error.source();
+    }
+    println!("{} | failed to clean up after the build: {message}", "Warning".yellow());
+}
+
+/// Stats of the compiler caches of `tools` within the build container, warning of those that can't be queried
+fn compiler_cache_stats(
+    paths: &Paths,
+    tools: &[compiler_cache::Tool],
+) -> BTreeMap<compiler_cache::Tool, compiler_cache::Stats> {
+    tools
+        .iter()
+        .filter_map(|&tool| {
+            let dir = match tool {
+                compiler_cache::Tool::Ccache => paths.ccache().guest,
+                compiler_cache::Tool::Sccache => paths.sccache().guest,
+            };
+
+            match tool.stats(&dir) {
+                Ok(stats) => Some((tool, stats)),
+                Err(error) => {
+                    println!("{} | {tool} stats: {error}", "Warning".yellow());
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Print the log of `phase` from the last build of the recipe at `recipe_path`
+fn print_log(recipe_path: &Path, phase: &str, env: Env) -> Result<(), Error> {
+    let recipe = Recipe::load(recipe_path).map_err(build::Error::from)?;
+    let paths = Paths::new(&recipe, None, env.cache_dir, "/mason", ".").map_err(Error::Log)?;
+    let dir = paths.logs().host;
+    let entries = build::log::index(&dir).unwrap_or_default();
+
+    let logs = entries
+        .iter()
+        .filter(|entry| entry.phase == phase)
+        .map(|entry| &entry.log)
+        .unique()
+        .collect::<Vec<_>>();
+    if logs.is_empty() {
+        let ran = entries.into_iter().map(|entry| entry.phase).unique().join(", ");
+        return Err(Error::MissingLog(phase.to_owned(), ran));
+    }
+
+    for log in &logs {
+        // Each target & PGO stage ran the phase separately
+        if logs.len() > 1 {
+            println!("{}", format!("==> {log} <==").bold());
+        }
+        io::copy(
+            &mut fs::File::open(dir.join(log)).map_err(Error::Log)?,
+            &mut io::stdout(),
+        )
+        .map_err(Error::Log)?;
+    }
+
     Ok(())
 }
 
@@ -182,7 +791,7 @@ fn verify_versions_match(builder: &Builder) -> Result<(), Error> {
         return Ok(());
     };
 
-    // We won't attempt to parse git upstreams for now
+    // We won't attempt to parse repository upstreams for now
     match &first_upstream.props {
         stone_recipe::upstream::Props::Git { git_ref, .. } => {
             // If we have a git ref, we have a git upstream and version parsing
@@ -191,6 +800,7 @@ fn verify_versions_match(builder: &Builder) -> Result<(), Error> {
                 return Ok(());
             }
         }
+        stone_recipe::upstream::Props::Vcs { .. } => return Ok(()),
         stone_recipe::upstream::Props::Plain { .. } => {}
     }
 
@@ -223,8 +833,20 @@ pub enum Error {
     Build(#[from] build::Error),
     #[error("package artifacts")]
     Package(#[from] package::Error),
+    #[error("diff contents against the previous release")]
+    Diff(#[from] diff::Error),
+    #[error("write software bill of materials")]
+    Sbom(#[from] sbom::Error),
     #[error("sync artefacts")]
     SyncArtefacts(#[source] io::Error),
+    #[error("write index of build inputs")]
+    InputIndex(#[source] io::Error),
+    #[error("write build report")]
+    Report(#[source] io::Error),
+    #[error("no {0} log, phases run by the last build: {1}")]
+    MissingLog(String, String),
+    #[error("read log")]
+    Log(#[source] io::Error),
     #[error("container")]
     Container(#[from] container::Error),
     #[error("setting thread priority")]
@@ -235,4 +857,18 @@ pub enum Error {
     VerifyBinaryManifestRequired(PathBuf),
     #[error("version parse")]
     Upstreams(#[from] version_parse::VersionError),
+    #[error("publish stones")]
+    Artifacts(#[from] artifacts::Error),
+    #[error("warnings baseline")]
+    WarningsBaseline(#[from] warnings::Error),
+    #[error("{0} runtime dependencies of the packages are unresolved")]
+    UnresolvedDependencies(usize),
+    #[error("{0} macros used by the scripts of the recipe aren't defined, pass --skip-validation to build anyway")]
+    UndefinedMacros(usize),
+    #[error("{0} paths are installed outside /usr")]
+    PathsOutsideUsr(usize),
+    #[error("cancelled by {0}")]
+    Cancelled(Signal),
+    #[error("clean up after the build")]
+    Cancel(#[from] cancel::Error),
 }
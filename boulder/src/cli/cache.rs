@@ -2,19 +2,23 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use std::{
+    collections::BTreeMap,
     io,
     path::{Path, PathBuf},
+    time::{Duration, SystemTime},
 };
 
+use chrono::{DateTime, Local};
 use clap::{Args, Parser};
 use container::Container;
+use fs_err as fs;
 use humansize::BINARY;
-use moss::util;
+use moss::{client::quota, util};
 use rayon::iter::{ParallelBridge, ParallelIterator};
 use thiserror::Error;
 use walkdir::WalkDir;
 
-use crate::Env;
+use crate::{Env, cache, compiler_cache, paths, profile};
 
 #[derive(Debug, Parser)]
 #[command(about = "Manage boulder caches")]
@@ -45,6 +49,68 @@ pub enum Subcommand {
     Clean,
     #[command(about = "Show the cache size(s) for the current environment")]
     Size,
+    #[command(about = "List the upstreams stored in the boulder cache, least recently used first")]
+    List {
+        #[command(flatten)]
+        include: Include,
+    },
+    #[command(
+        about = "Remove the least recently used upstreams from the boulder cache",
+        long_about = "Remove the least recently used upstreams from the boulder cache
+
+Entries used by a running build are never removed."
+    )]
+    Prune {
+        #[arg(
+            long,
+            value_name = "DURATION",
+            value_parser = parse_duration,
+            required_unless_present = "max_size",
+            help = "Remove entries not used for DURATION, e.g. 90m, 12h, 30d or 2w"
+        )]
+        older_than: Option<Duration>,
+        #[arg(
+            long,
+            value_name = "SIZE",
+            value_parser = parse_size,
+            help = "Remove the least recently used entries until the rest fit in SIZE, e.g. 10G"
+        )]
+        max_size: Option<u64>,
+        #[command(flatten)]
+        include: Include,
+    },
+    #[command(
+        about = "Show the hits, misses & size of the ccache shared by builds",
+        long_about = "Show the hits, misses & size of the ccache shared by builds
+
+Requires ccache to be installed on the host. sccache only counts while its server runs, so is
+reported at the end of each build instead."
+    )]
+    CcacheStats {
+        #[arg(long, help = "Print as JSON")]
+        json: bool,
+    },
+    #[command(
+        about = "Limit the ccache of each compiler to the size set by the profile",
+        long_about = "Limit the ccache of each compiler to the size set by the profile
+
+Builds keep a ccache per toolchain & compiler version. Each is limited to the `ccache_max_size`
+of the profile, evicting the least recently used objects above it. Requires ccache to be
+installed on the host."
+    )]
+    CcacheGc {
+        #[arg(short, long, default_value = profile::DEFAULT, help = "Profile setting `ccache_max_size`")]
+        profile: profile::Id,
+    },
+}
+
+/// Entries of the boulder cache listed & pruned besides upstreams
+#[derive(Debug, Args)]
+pub struct Include {
+    #[arg(long, help = "Include the build root of each recipe", default_value_t = false)]
+    build: bool,
+    #[arg(long, help = "Include compiler caches, such as ccache", default_value_t = false)]
+    ccache: bool,
 }
 
 pub fn handle(command: Command, env: Env) -> Result<(), Error> {
@@ -54,6 +120,14 @@ pub fn handle(command: Command, env: Env) -> Result<(), Error> {
     match command.subcommand {
         Subcommand::Clean => clean(env, boulder_cache, moss_cache),
         Subcommand::Size => size(env, boulder_cache, moss_cache),
+        Subcommand::List { include } => list(env, include),
+        Subcommand::Prune {
+            older_than,
+            max_size,
+            include,
+        } => prune(env, cache::Policy { older_than, max_size }, include),
+        Subcommand::CcacheStats { json } => ccache_stats(env, json),
+        Subcommand::CcacheGc { profile } => ccache_gc(env, &profile),
     }
 }
 
@@ -80,10 +154,180 @@ fn size(env: Env, boulder_cache: bool, moss_cache: bool) -> Result<(), Error> {
             .map(|m| m.len())
             .sum();
         println!("{name} ({}): {}", path.display(), humansize::format_size(size, BINARY));
+
+        if path == env.cache_dir {
+            let entries = cache::inventory(&path, false, false)?;
+            for kind in [cache::Kind::Plain, cache::Kind::Git] {
+                let (count, bytes) = entries
+                    .iter()
+                    .filter(|entry| entry.kind == kind)
+                    .fold((0, 0), |(count, bytes), entry| (count + 1, bytes + entry.bytes));
+                println!(
+                    "  {kind} upstreams: {count} ({})",
+                    humansize::format_size(bytes, BINARY)
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn list(env: Env, include: Include) -> Result<(), Error> {
+    let entries = cache::inventory(&env.cache_dir, include.build, include.ccache)?;
+
+    if entries.is_empty() {
+        println!("No upstreams stored in {}", env.cache_dir.display());
+        return Ok(());
+    }
+
+    let rows = entries
+        .iter()
+        .map(|entry| {
+            [
+                entry.name.clone(),
+                entry.kind.to_string(),
+                humansize::format_size(entry.bytes, BINARY),
+                DateTime::<Local>::from(entry.used).format("%Y-%m-%d %H:%M").to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let header = ["Name", "Type", "Size", "Last used"];
+    let width = |column: usize| {
+        rows.iter()
+            .map(|row| row[column].len())
+            .chain([header[column].len()])
+            .max()
+            .unwrap_or_default()
+    };
+    let (name, kind, size) = (width(0), width(1), width(2));
+
+    for [n, k, s, u] in [header.map(ToOwned::to_owned)].into_iter().chain(rows) {
+        println!("{n:<name$}  {k:<kind$}  {s:>size$}  {u}");
     }
+
+    let total = entries.iter().map(|entry| entry.bytes).sum::<u64>();
+    println!("\n{} entries, {}", entries.len(), humansize::format_size(total, BINARY));
+
     Ok(())
 }
 
+fn prune(env: Env, policy: cache::Policy, include: Include) -> Result<(), Error> {
+    let cache_dir = fs::canonicalize(&env.cache_dir)?;
+    let entries = cache::inventory(&cache_dir, include.build, include.ccache)?;
+    let claimed = cache::claimed(&cache_dir)?;
+    let evicted = cache::evict(&entries, &claimed, policy, SystemTime::now());
+
+    if evicted.is_empty() {
+        println!("Nothing to prune");
+        return Ok(());
+    }
+
+    // Build roots hold files owned by the container's users
+    let tmpdir = tempfile::tempdir()?;
+    let guest = Path::new("/cache");
+    Container::new(tmpdir.path()).bind_rw(&cache_dir, guest).run(|| {
+        for entry in &evicted {
+            let paths = entry
+                .paths
+                .iter()
+                .map(|path| guest.join(path.strip_prefix(&cache_dir).unwrap_or(path)))
+                .collect();
+            cache::remove(&cache::Entry {
+                paths,
+                ..(*entry).clone()
+            })?;
+        }
+        Ok::<_, io::Error>(())
+    })?;
+
+    for entry in &evicted {
+        println!(
+            "Removed {} {} ({})",
+            entry.kind,
+            entry.name,
+            humansize::format_size(entry.bytes, BINARY)
+        );
+    }
+    let freed = evicted.iter().map(|entry| entry.bytes).sum::<u64>();
+    println!("\nFreed {}", humansize::format_size(freed, BINARY));
+
+    Ok(())
+}
+
+fn ccache_stats(env: Env, json: bool) -> Result<(), Error> {
+    let caches = paths::compiler_caches(&env.cache_dir.join("ccache"))?;
+
+    let mut stats = BTreeMap::new();
+    for (key, dir) in &caches {
+        stats.insert(key.as_str(), compiler_cache::Tool::Ccache.stats(dir)?);
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stats).map_err(io::Error::other)?);
+    } else if stats.is_empty() {
+        println!("No ccache in {}", env.cache_dir.join("ccache").display());
+    } else {
+        let width = stats.keys().map(|key| key.len()).max().unwrap_or_default();
+        for (key, stats) in &stats {
+            println!("{key:<width$}  {stats}");
+        }
+    }
+
+    Ok(())
+}
+
+fn ccache_gc(env: Env, profile: &profile::Id) -> Result<(), Error> {
+    let profiles = profile::Manager::new(&env);
+    let max_size = profiles
+        .profile(profile)?
+        .ccache_max_size
+        .clone()
+        .ok_or_else(|| Error::NoCcacheMaxSize(profile.clone()))?;
+    parse_size(&max_size).map_err(|_| Error::InvalidCcacheMaxSize(max_size.clone()))?;
+
+    let caches = paths::compiler_caches(&env.cache_dir.join("ccache"))?;
+    if caches.is_empty() {
+        println!("No ccache in {}", env.cache_dir.join("ccache").display());
+        return Ok(());
+    }
+
+    let width = caches.iter().map(|(key, _)| key.len()).max().unwrap_or_default();
+    for (key, dir) in &caches {
+        let tool = compiler_cache::Tool::Ccache;
+        tool.cleanup(dir, &max_size)?;
+        println!("{key:<width$}  {}", tool.stats(dir)?);
+    }
+
+    Ok(())
+}
+
+/// Parse a duration such as `90m`, `12h`, `30d` or `2w`
+fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" | "" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(format!("unknown unit `{unit}`, use s, m, h, d or w")),
+    };
+
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(seconds))
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("invalid duration `{text}`"))
+}
+
+fn parse_size(text: &str) -> Result<u64, String> {
+    quota::parse_size(text).ok_or_else(|| format!("invalid size `{text}`"))
+}
+
 fn selected_caches(env: &Env, boulder_cache: bool, moss_cache: bool) -> Vec<(&'static str, PathBuf)> {
     let select_all = !boulder_cache && !moss_cache;
     let mut v = Vec::new();
@@ -100,8 +344,32 @@ fn selected_caches(env: &Env, boulder_cache: bool, moss_cache: bool) -> Vec<(&'s
 pub enum Error {
     #[error("container")]
     Container(#[from] container::Error),
+    #[error("profile")]
+    Profile(#[from] profile::Error),
+    #[error("profile {0} sets no ccache_max_size")]
+    NoCcacheMaxSize(profile::Id),
+    #[error("invalid ccache_max_size `{0}`, expected a size such as `5G`")]
+    InvalidCcacheMaxSize(String),
     #[error("moss installation")]
     MossInstallation(#[from] moss::installation::Error),
+    #[error("compiler cache")]
+    CompilerCache(#[from] compiler_cache::Error),
     #[error("io")]
     Io(#[from] io::Error),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("90m"), Ok(Duration::from_secs(90 * 60)));
+        assert_eq!(parse_duration("12h"), Ok(Duration::from_secs(12 * 60 * 60)));
+        assert_eq!(parse_duration("30"), parse_duration("30d"));
+        assert_eq!(parse_duration("2w"), parse_duration("14d"));
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("3y").is_err());
+        assert!(parse_duration("99999999999999999w").is_err());
+    }
+}
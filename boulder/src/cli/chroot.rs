@@ -1,12 +1,21 @@
 // SPDX-FileCopyrightText: 2024 AerynOS Developers
 // SPDX-License-Identifier: MPL-2.0
 
-use std::{io, path::PathBuf, process};
+use std::{
+    io,
+    os::unix::process::ExitStatusExt,
+    path::PathBuf,
+    process::{self, ExitStatus},
+};
 
 use crate::{
     Env, Macros, Paths, Recipe,
     architecture::{self, BuildTarget},
-    build, container, macros, recipe,
+    build::{self, environment::Environment},
+    container::{self, Bind, network::Allowlist},
+    macros,
+    package::sbom,
+    profile, recipe,
 };
 use clap::Parser;
 use fs_err as fs;
@@ -17,22 +26,83 @@ use thiserror::Error;
 pub struct Command {
     #[arg(default_value = "./stone.yaml", help = "Path to recipe file")]
     recipe: PathBuf,
+    #[arg(
+        short,
+        long,
+        help = "Profile to set the environment of [default: default-x86_64, if it exists]"
+    )]
+    profile: Option<profile::Id>,
+    #[arg(
+        long = "env",
+        value_name = "KEY[=VALUE]",
+        value_parser = build::environment::parse_passthrough_var,
+        help = "Set a variable in the environment, overriding that of the profile, \
+                or pass it through from the host without a value. Can be passed multiple times"
+    )]
+    environment: Vec<(String, String)>,
+    #[arg(
+        long = "bind",
+        value_name = "HOST:GUEST[:ro]",
+        value_parser = Bind::parse,
+        help = "Bind mount a host dir into the chroot, read-only with `:ro`. Can be passed multiple times"
+    )]
+    binds: Vec<Bind>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Dir of the chroot to start in [default: the build dir]"
+    )]
+    workdir: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Replace the build root by one exported with `boulder build --export-root`"
+    )]
+    import_root: Option<PathBuf>,
+    #[arg(
+        last = true,
+        value_name = "COMMAND",
+        help = "Run a command instead of an interactive shell, exiting with its status"
+    )]
+    command: Vec<String>,
 }
 
 pub fn handle(command: Command, env: Env) -> Result<(), Error> {
-    let Command { recipe: recipe_path } = command;
+    let Command {
+        recipe: recipe_path,
+        profile: profile_id,
+        environment: overrides,
+        binds,
+        workdir,
+        import_root,
+        command,
+    } = command;
 
     let recipe = Recipe::load(recipe_path)?;
     let macros = Macros::load(&env)?;
-    let paths = Paths::new(&recipe, None, env.cache_dir, "/mason", ".")?;
+    let mut paths = Paths::new(&recipe, None, &env.cache_dir, "/mason", ".")?;
 
     let rootfs = paths.rootfs().host;
 
+    if let Some(path) = import_root {
+        let manifest = build::archive::import(&path, &rootfs).map_err(Error::ImportRoot)?;
+        println!(
+            "Imported build root of {} packages from {}",
+            manifest.packages.len(),
+            path.display()
+        );
+    }
+
     // Has rootfs been setup?
     if !rootfs.join("usr").exists() {
         return Err(Error::MissingRootFs);
     }
 
+    // Share the compiler caches of the build, unless it predates saved materials
+    if let Ok(materials) = sbom::Materials::load(&paths.materials()) {
+        paths.set_compiler(build::compiler_key(&recipe, &materials.installed));
+    }
+
     // Generate a script so we can inject a .profile
     // to the container environment with all actions
     // and definitions
@@ -48,44 +118,152 @@ pub fn handle(command: Command, env: Env) -> Result<(), Error> {
             &paths,
             &macros,
             false,
+            build::jobs::Jobs::new(&recipe, None)?.count,
+            true,
         )
         .map_err(Error::BuildScript)?
         .expect("script always available for prepare phase");
     let profile = &build::format_profile(&script);
 
+    let profiles = profile::Manager::new(&env);
+    let selected = match &profile_id {
+        Some(id) => Some(profiles.profile(id)?),
+        None => profiles.profile(&profile::Id::new(profile::DEFAULT)).ok(),
+    };
+    let mut environment = selected
+        .as_ref()
+        .map(|profile| Environment::new(&profile.environment))
+        .unwrap_or_default();
+    for (key, value) in overrides {
+        environment.set(key, value);
+    }
+
+    let networking = recipe.parsed.options.networking;
+    let allowlist = selected
+        .map(|profile| Allowlist::new(&profile.network_allowlist))
+        .unwrap_or_default()
+        .merge(Allowlist::new(&recipe.parsed.options.network_allowlist));
+    if allowlist.restricts(networking) {
+        Allowlist::set_proxy(&mut environment);
+    }
+
     let home = &paths.build().guest;
 
-    container::exec(&paths, recipe.parsed.options.networking, || {
-        fs::write(home.join(".profile"), profile)?;
+    let result = container::exec(
+        &paths,
+        networking,
+        &allowlist,
+        &environment,
+        &binds,
+        workdir.as_deref(),
+        || {
+            fs::write(home.join(".profile"), profile)?;
 
-        let mut child = process::Command::new("/bin/bash")
-            .arg("--login")
-            .env_clear()
-            .env("HOME", home)
-            .env("PATH", "/usr/bin:/usr/sbin")
-            .env("TERM", "xterm-256color")
-            .spawn()?;
+            let (program, args) = match command.split_first() {
+                Some((program, args)) => (program.as_str(), args),
+                None => ("/bin/bash", &["--login".to_owned()][..]),
+            };
 
-        child.wait()?;
+            let status = process::Command::new(program)
+                .args(args)
+                .env_clear()
+                .envs(environment.iter())
+                .env("HOME", home)
+                .env("PATH", "/usr/bin:/usr/sbin")
+                .env("TERM", "xterm-256color")
+                .status()?;
 
-        Ok(()) as io::Result<_>
-    })?;
+            // Exit the container with the status of the command, see below
+            if !command.is_empty() && !status.success() {
+                process::exit(exit_code(status));
+            }
 
-    Ok(())
+            Ok(()) as io::Result<_>
+        },
+    );
+
+    match result {
+        Err(container::Error::Container(::container::Error::Failure { code, message }))
+            if !command.is_empty() && message.is_empty() =>
+        {
+            process::exit(code)
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Exit code of a process exiting with `status`, as a shell would report it
+fn exit_code(status: ExitStatus) -> i32 {
+    status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1)
 }
 
 #[derive(Debug, Error)]
 pub enum Error {
+    #[error("profile")]
+    Profile(#[from] profile::Error),
     #[error("build root doesn't exist, make sure to run build first")]
     MissingRootFs,
+    #[error("import build root")]
+    ImportRoot(#[source] io::Error),
     #[error("container")]
     Container(#[from] container::Error),
     #[error("macros")]
     Macros(#[from] macros::Error),
     #[error("build script")]
     BuildScript(#[source] build::job::Error),
+    #[error("jobs")]
+    Jobs(#[from] build::jobs::Error),
     #[error("recipe")]
     Recipe(#[from] recipe::Error),
     #[error("io")]
     Io(#[from] io::Error),
 }
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn parse_command() {
+        let parse = |args: &[&str]| Command::try_parse_from([&["chroot"], args].concat());
+
+        let command = parse(&[
+            "pkg/stone.yaml",
+            "--bind",
+            "/srv/corpus:/mason/corpus:ro",
+            "--bind",
+            "/srv/out:/mason/out",
+            "--env",
+            "LANG=C.UTF-8",
+            "--workdir",
+            "/mason/corpus",
+            "--",
+            "make",
+            "-C",
+            "/mason/corpus",
+        ])
+        .unwrap();
+        assert_eq!(command.recipe, Path::new("pkg/stone.yaml"));
+        assert_eq!(
+            command.binds.iter().map(|bind| bind.read_only).collect::<Vec<_>>(),
+            [true, false]
+        );
+        assert_eq!(command.environment, [("LANG".to_owned(), "C.UTF-8".to_owned())]);
+        assert_eq!(command.workdir.as_deref(), Some(Path::new("/mason/corpus")));
+        assert_eq!(command.command, ["make", "-C", "/mason/corpus"]);
+
+        // Interactive by default
+        let command = parse(&[]).unwrap();
+        assert_eq!(command.recipe, Path::new("./stone.yaml"));
+        assert!(command.binds.is_empty() && command.command.is_empty());
+
+        assert!(parse(&["--bind", "/srv/corpus"]).is_err());
+        assert!(parse(&["--bind", "/srv/corpus:corpus"]).is_err());
+        assert!(parse(&["--env", "MY-KEY=value"]).is_err());
+    }
+}
@@ -45,9 +45,41 @@ pub enum Subcommand {
     },
     #[command(about = "Update a profiles repositories")]
     Update {
-        #[arg(short, long, default_value = "default-x86_64")]
+        #[arg(short, long, default_value = profile::DEFAULT)]
         profile: profile::Id,
     },
+    #[command(about = "Add a repository to a profile, replacing any of the same name")]
+    AddRepo {
+        #[arg(help = "profile name")]
+        profile: profile::Id,
+        #[arg(help = "repository name")]
+        name: String,
+        #[arg(help = "URI of the repository's stone.index")]
+        uri: Url,
+        #[arg(
+            short,
+            long,
+            default_value_t = 0,
+            help = "Packages of higher priority repositories are preferred"
+        )]
+        priority: u64,
+    },
+    #[command(about = "Remove a repository from a profile")]
+    RemoveRepo {
+        #[arg(help = "profile name")]
+        profile: profile::Id,
+        #[arg(help = "repository name")]
+        name: String,
+    },
+    #[command(about = "Set the priority of a profile's repository")]
+    SetPriority {
+        #[arg(help = "profile name")]
+        profile: profile::Id,
+        #[arg(help = "repository name")]
+        name: String,
+        #[arg(help = "Packages of higher priority repositories are preferred")]
+        priority: u64,
+    },
 }
 
 /// Parse a single key-value pair
@@ -118,12 +150,48 @@ fn parse_repository(s: &str) -> Result<(repository::Id, Repository), String> {
 }
 
 pub fn handle(command: Command, env: Env) -> Result<(), Error> {
-    let manager = profile::Manager::new(&env);
+    let mut manager = profile::Manager::new(&env);
 
     match command.subcommand {
         Subcommand::List => list(manager),
         Subcommand::Add { name, repos } => add(&env, manager, name, repos),
         Subcommand::Update { profile } => update(&env, manager, &profile),
+        Subcommand::AddRepo {
+            profile,
+            name,
+            uri,
+            priority,
+        } => {
+            let id = repository::Id::new(&name);
+            manager.add_repository(
+                &profile,
+                id.clone(),
+                Repository {
+                    description: String::default(),
+                    source: repository::Source::DirectIndex(uri),
+                    priority: repository::Priority::new(priority),
+                    active: true,
+                },
+            )?;
+            println!("Repository {id} added to profile {profile}");
+            Ok(())
+        }
+        Subcommand::RemoveRepo { profile, name } => {
+            let id = repository::Id::new(&name);
+            manager.remove_repository(&profile, &id)?;
+            println!("Repository {id} removed from profile {profile}");
+            Ok(())
+        }
+        Subcommand::SetPriority {
+            profile,
+            name,
+            priority,
+        } => {
+            let id = repository::Id::new(&name);
+            manager.set_priority(&profile, &id, repository::Priority::new(priority))?;
+            println!("Repository {id} of profile {profile} now has priority {priority}");
+            Ok(())
+        }
     }
 }
 
@@ -180,6 +248,11 @@ pub fn add<'a>(
         id.clone(),
         Profile {
             repositories: repository::Map::with(repos),
+            system_triggers: false,
+            environment: BTreeMap::new(),
+            network_allowlist: vec![],
+            strict_layout: false,
+            ccache_max_size: None,
         },
     )?;
 
@@ -193,7 +266,8 @@ pub fn add<'a>(
 pub fn update<'a>(env: &'a Env, manager: profile::Manager<'a>, profile: &profile::Id) -> Result<(), Error> {
     let repos = manager.repositories(profile)?.clone();
 
-    let installation = Installation::open(&env.moss_dir, None)?;
+    // Parallel builds share the moss root, so take turns
+    let installation = Installation::open(&env.moss_dir, None)?.wait_for_lock(true);
     let mut moss_client = moss::Client::builder("boulder", installation)
         .repositories(repos)
         .build()?;
@@ -217,3 +291,76 @@ pub enum Error {
     #[error("io")]
     Io(#[from] io::Error),
 }
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use fs_err as fs;
+
+    use super::*;
+
+    fn env(root: &Path) -> Env {
+        Env::new(
+            Some(root.join("cache")),
+            Some(root.join("config")),
+            Some(root.join("data")),
+            Some(root.join("moss")),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn repository_commands() {
+        let parse =
+            |args: &[&str]| Command::try_parse_from([&["profile"], args].concat()).map(|command| command.subcommand);
+        let uri = "file:///var/lib/boulder/repo/stone.index";
+
+        assert!(matches!(
+            parse(&["add-repo", "local-x86_64", "local", uri, "--priority", "100"]).unwrap(),
+            Subcommand::AddRepo { profile, name, uri: parsed, priority: 100 }
+                if profile == profile::Id::new("local-x86_64") && name == "local" && parsed.as_str() == uri
+        ));
+        assert!(matches!(
+            parse(&["add-repo", "local-x86_64", "local", uri]).unwrap(),
+            Subcommand::AddRepo { priority: 0, .. }
+        ));
+        assert!(matches!(
+            parse(&["set-priority", "local-x86_64", "volatile", "10"]).unwrap(),
+            Subcommand::SetPriority { priority: 10, .. }
+        ));
+        assert!(parse(&["add-repo", "local-x86_64", "local", "not a uri"]).is_err());
+        assert!(parse(&["set-priority", "local-x86_64", "volatile"]).is_err());
+        assert!(parse(&["remove-repo", "local-x86_64"]).is_err());
+
+        // Failing before anything is saved
+        let dir = tempfile::tempdir().unwrap();
+        let env = env(dir.path());
+        let profiles = dir.path().join("config/profile.d");
+        fs::create_dir_all(&profiles).unwrap();
+        fs::write(
+            profiles.join("local-x86_64.yaml"),
+            "local-x86_64:\n  repositories: {}\n",
+        )
+        .unwrap();
+
+        let run = |args: &[&str]| {
+            handle(
+                Command::try_parse_from([&["profile"], args].concat()).unwrap(),
+                env.clone(),
+            )
+        };
+        assert!(matches!(
+            run(&["remove-repo", "local-x86_64", "volatile"]),
+            Err(Error::Profile(profile::Error::MissingRepository(..)))
+        ));
+        assert!(matches!(
+            run(&["set-priority", "local-x86_64", "volatile", "10"]),
+            Err(Error::Profile(profile::Error::MissingRepository(..)))
+        ));
+        assert!(matches!(
+            run(&["add-repo", "missing", "local", uri]),
+            Err(Error::Profile(profile::Error::MissingProfile(_)))
+        ));
+    }
+}
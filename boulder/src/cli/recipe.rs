@@ -1,29 +1,38 @@
 // SPDX-FileCopyrightText: 2024 AerynOS Developers
 // SPDX-License-Identifier: MPL-2.0
 use std::{
+    collections::BTreeMap,
+    error::Error as _,
+    fmt::Write as _,
     io,
     path::{Path, PathBuf},
     time::Duration,
 };
 
 use crate::{
-    Env, Macros, architecture,
+    Env, Macros, Recipe,
+    architecture::{self, BuildTarget},
+    build::{job, validate},
     draft::{self, Drafter, upstream::fetched_upstream_cache_path},
-    macros, recipe,
+    macros, recipe, updates,
 };
 use clap::Parser;
 use ent_core::{data::updates::get_latest_version, recipes::ParserRegistration};
 use fs_err::{self as fs};
 use itertools::Itertools;
 use moss::{request, runtime, util};
+use serde::Serialize;
 use similar::TextDiff;
-use stone_recipe::upstream;
+use stone_recipe::{
+    tuning::{self, Toolchain},
+    upstream,
+};
+use strum::VariantArray as _;
 use tempfile::NamedTempFile;
 use thiserror::Error;
 use tui::{
     MultiProgress, ProgressBar, ProgressStyle, Styled,
     dialoguer::{Confirm, theme::ColorfulTheme},
-    pretty::{self, ColumnDisplay},
 };
 use url::Url;
 use version_parse::VersionExtractor;
@@ -33,9 +42,12 @@ const LONG_UPDATE_ABOUT: &str = concat!(
     "If no version or upstreams are provided, boulder will attempt to autoupdate the\n",
     "recipe, using the release information supplied in the monitoring.yaml file.\n\n",
     "If a version is passed but no upstream is passed, boulder will attempt to guess\n",
-    "the new url from the existing url.\n\n",
+    "the new urls from the existing urls naming the current version, and move git\n",
+    "upstreams to the tag of the new version.\n\n",
     "If an upstream is passed but no version is passed, boulder will parse the new\n",
-    "version from the new upstream."
+    "version from the new upstream.\n\n",
+    "New plain upstreams are fetched to update their hashes. The recipe is left\n",
+    "untouched if any of them can't be fetched."
 );
 
 #[derive(Debug, Parser)]
@@ -59,12 +71,28 @@ pub enum Subcommand {
         )]
         release: Option<u64>,
     },
-    #[command(about = "Create skeletal stone.yaml recipe from source archive URIs")]
+    #[command(about = "Rewrite a recipe in the canonical layout, keeping its comments")]
+    Format {
+        #[arg(default_value = "./stone.yaml", help = "The recipe file to format")]
+        recipe: PathBuf,
+        #[arg(long, help = "Don't write the recipe, fail if it isn't formatted instead")]
+        check: bool,
+    },
+    #[command(about = "Check the scripts of a recipe for macros that aren't defined, as builds do before starting")]
+    Lint {
+        #[arg(default_value = "./stone.yaml", help = "The recipe file to check")]
+        recipe: PathBuf,
+    },
+    #[command(about = "Create skeletal stone.yaml recipe from source archive URIs or Git repositories")]
     New {
         #[arg(short, long, default_value = ".", help = "Location to output generated files")]
         output: PathBuf,
-        #[arg(required = true, value_name = "URI", help = "Source archive URIs")]
-        upstreams: Vec<Url>,
+        #[arg(
+            required = true,
+            value_name = "URI",
+            help = "Source archive URIs, pages of crates.io, PyPI or GitHub projects, or Git repository URLs using the \"git|url\" syntax"
+        )]
+        upstreams: Vec<upstream::SourceUri>,
     },
     #[command(about = LONG_UPDATE_ABOUT)]
     Update {
@@ -91,10 +119,85 @@ pub enum Subcommand {
         #[arg(long, default_value = "false", help = "Don't increment the release number")]
         no_bump: bool,
     },
-    #[command(about = "Print macro definitions")]
+    #[command(about = "Check recipes for new upstream versions")]
+    CheckUpdates {
+        #[arg(default_value = "./stone.yaml", help = "The recipe files to check")]
+        recipes: Vec<PathBuf>,
+        #[arg(long, help = "Print the result of every check as JSON")]
+        json: bool,
+    },
+    #[command(about = "List the macros available to recipes, or expand a snippet using them")]
     Macros {
-        #[arg(name = "macro", help = "Print definition and example for the provided macro")]
+        #[arg(
+            name = "macro",
+            help = "Print the description, example & expansion of the provided macro"
+        )]
         _macro: Option<String>,
+        #[arg(
+            short,
+            long,
+            value_name = "SUBSTR",
+            help = "Only list macros whose name or description contains [SUBSTR]"
+        )]
+        filter: Option<String>,
+        #[arg(
+            short,
+            long,
+            value_name = "TARGET",
+            help = "Build target to list the macros of, i.e. x86_64 or emul32/x86_64 [default: host]"
+        )]
+        arch: Option<BuildTarget>,
+        #[arg(
+            short,
+            long,
+            value_name = "SNIPPET",
+            conflicts_with_all = ["macro", "filter"],
+            help = "Expand [SNIPPET] as a build script would, recipe specific values left as <placeholders>"
+        )]
+        expand: Option<String>,
+        #[arg(long, help = "Print as JSON")]
+        json: bool,
+    },
+    #[command(about = "Inspect the tuning groups available to recipes")]
+    Tuning {
+        #[command(subcommand)]
+        subcommand: Tuning,
+    },
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum Tuning {
+    #[command(about = "List the tuning groups, and which are enabled by default")]
+    List {
+        #[arg(
+            short,
+            long,
+            value_name = "TARGET",
+            help = "Build target to list the groups of, i.e. x86_64 or emul32/x86_64 [default: host]"
+        )]
+        arch: Option<BuildTarget>,
+        #[arg(
+            short,
+            long,
+            help = "Show the groups enabled by this recipe instead, and the flags its builds use"
+        )]
+        recipe: Option<PathBuf>,
+        #[arg(long, help = "Print as JSON")]
+        json: bool,
+    },
+    #[command(about = "Show the flags a tuning group adds for each toolchain")]
+    Show {
+        #[arg(help = "The tuning group to show")]
+        group: String,
+        #[arg(
+            short,
+            long,
+            value_name = "TARGET",
+            help = "Build target to show the group of, i.e. x86_64 or emul32/x86_64 [default: host]"
+        )]
+        arch: Option<BuildTarget>,
+        #[arg(long, help = "Print as JSON")]
+        json: bool,
     },
 }
 
@@ -119,6 +222,8 @@ fn parse_updated_source(s: &str) -> Result<UpdatedSource, String> {
 pub fn handle(command: Command, env: Env, yes: bool, verbose: bool) -> Result<(), Error> {
     match command.subcommand {
         Subcommand::Bump { recipe, release } => bump(recipe, release),
+        Subcommand::Format { recipe, check } => format(&recipe, check),
+        Subcommand::Lint { recipe } => lint(&recipe, env),
         Subcommand::New { output, upstreams } => new(env, output, upstreams),
         Subcommand::Update {
             recipe,
@@ -136,7 +241,18 @@ pub fn handle(command: Command, env: Env, yes: bool, verbose: bool) -> Result<()
             yes,
             verbose,
         ),
-        Subcommand::Macros { _macro } => macros(_macro, env),
+        Subcommand::CheckUpdates { recipes, json } => check_updates(&recipes, json),
+        Subcommand::Macros {
+            _macro,
+            filter,
+            arch,
+            expand,
+            json,
+        } => macros(_macro, filter, arch, expand, json, env),
+        Subcommand::Tuning { subcommand } => match subcommand {
+            Tuning::List { arch, recipe, json } => list_tuning(arch, recipe.as_deref(), json, env),
+            Tuning::Show { group, arch, json } => show_tuning(&group, arch, json, env),
+        },
     }
 }
 
@@ -186,8 +302,10 @@ fn detect_update(recipe_path: &Path, parsed_recipe: &recipe::Parsed, verbose: bo
         .split_first()
         .expect("upstreams must not be empty");
 
-    if matches!(first_upstream.props, upstream::Props::Git { .. }) {
-        return Err(Error::GitUpstreamMustProvideVersion);
+    match first_upstream.props {
+        upstream::Props::Git { .. } => return Err(Error::GitUpstreamMustProvideVersion),
+        upstream::Props::Vcs { vcs, .. } => return Err(Error::VcsUpstream(0, vcs)),
+        upstream::Props::Plain { .. } => {}
     }
 
     let new_url = guess_new_url(newest.as_str(), first_upstream.url.as_str(), verbose)?;
@@ -281,7 +399,48 @@ fn bump(recipe: PathBuf, release: Option<u64>) -> Result<(), Error> {
     Ok(())
 }
 
-fn new(env: Env, output: PathBuf, upstreams: Vec<Url>) -> Result<(), Error> {
+fn format(recipe: &Path, check: bool) -> Result<(), Error> {
+    let path = recipe::resolve_path(recipe).map_err(Error::ResolvePath)?;
+    let input = fs::read_to_string(&path).map_err(Error::Read)?;
+
+    let formatted = recipe::format::format(&input)?;
+
+    if formatted == input {
+        println!("{}: already formatted", path.display());
+    } else if check {
+        print_diff(&input, &formatted);
+        return Err(Error::Unformatted(path));
+    } else {
+        fs::write(&path, formatted.as_bytes()).map_err(Error::Write)?;
+        println!("{}: formatted", path.display());
+    }
+
+    Ok(())
+}
+
+fn lint(recipe: &Path, env: Env) -> Result<(), Error> {
+    let macros = Macros::load(&env)?;
+    let recipe = Recipe::load(recipe).map_err(Error::Load)?;
+
+    // Recipes not built for the host are checked as if they were
+    let mut targets = recipe.build_targets();
+    if targets.is_empty() {
+        targets.push(BuildTarget::Native(architecture::host()));
+    }
+
+    let issues = validate::check(&recipe, &macros, &targets, false)?;
+    for issue in &issues {
+        println!("{} | {issue}", "Error".red());
+    }
+    if !issues.is_empty() {
+        return Err(Error::UndefinedMacros(issues.len()));
+    }
+
+    println!("{}: no undefined macros", recipe.path.display());
+    Ok(())
+}
+
+fn new(env: Env, output: PathBuf, upstreams: Vec<upstream::SourceUri>) -> Result<(), Error> {
     const RECIPE_FILE: &str = "stone.yaml";
     const MONITORING_FILE: &str = "monitoring.yaml";
 
@@ -363,12 +522,23 @@ fn update(
         GitUpstream(usize, serde_yaml::Value, String),
     }
 
+    // Without new upstreams, those of the recipe follow the new version
+    let sources = if sources.is_empty() {
+        follow_version(&recipe, &version, verbose)?
+    } else {
+        sources.into_iter().map(Some).collect()
+    };
+
     let mut updates = vec![Update::Version(version)];
     if !no_bump {
         updates.push(Update::Release(recipe.source.release + 1));
     }
 
     for (i, (original, update)) in recipe.upstreams.into_iter().zip(sources).enumerate() {
+        let Some(update) = update else {
+            continue;
+        };
+
         match (original.props, update) {
             (upstream::Props::Plain { .. }, UpdatedSource::Git(_)) => {
                 return Err(Error::UpstreamMismatch(i, "Plain", "Git"));
@@ -376,6 +546,7 @@ fn update(
             (upstream::Props::Git { .. }, UpdatedSource::Plain(_)) => {
                 return Err(Error::UpstreamMismatch(i, "Git", "Plain"));
             }
+            (upstream::Props::Vcs { vcs, .. }, _) => return Err(Error::VcsUpstream(i, vcs)),
             (upstream::Props::Plain { .. }, UpdatedSource::Plain(new_uri)) => {
                 let key = recipe_yaml["upstreams"][i]
                     .as_mapping()
@@ -452,6 +623,43 @@ fn update(
     Ok(())
 }
 
+/// The upstreams of `recipe` moved to `version`, [`None`] for those left as is
+///
+/// Plain upstreams naming the current version are rewritten to name the new
+/// one. Git upstreams move to the tag of the new version, pinned to its commit
+/// if they were pinned to one.
+fn follow_version(recipe: &recipe::Parsed, version: &str, verbose: bool) -> Result<Vec<Option<UpdatedSource>>, Error> {
+    let current = recipe.source.version.as_str();
+
+    recipe
+        .upstreams
+        .iter()
+        .enumerate()
+        .map(|(index, upstream)| match &upstream.props {
+            upstream::Props::Plain { .. } => {
+                if !upstream.url.as_str().contains(current) {
+                    return Ok(None);
+                }
+                let url = guess_new_url(version, upstream.url.as_str(), verbose)?;
+                Ok(Some(UpdatedSource::Plain(url.parse()?)))
+            }
+            upstream::Props::Git { git_ref, .. } => {
+                let tag = runtime::block_on(gitwrap::ls_remote_tags(&upstream.url))?
+                    .into_iter()
+                    .find(|tag| {
+                        updates::version::Version::from_tag(&tag.name, &recipe.source.name)
+                            .is_some_and(|tagged| tagged.to_string() == version)
+                    })
+                    .ok_or_else(|| Error::MissingTag(upstream.url.clone(), version.to_owned()))?;
+
+                let pinned = git_ref.len() == 40 && git_ref.chars().all(|c| c.is_ascii_hexdigit());
+                Ok(Some(UpdatedSource::Git(if pinned { tag.commit } else { tag.name })))
+            }
+            upstream::Props::Vcs { vcs, .. } => Err(Error::VcsUpstream(index, *vcs)),
+        })
+        .collect()
+}
+
 /// Fetches the upstream at `uri` and caches it so it doesn't need to be refetched
 /// when this recipe is finally built.
 ///
@@ -501,85 +709,392 @@ async fn fetch_and_cache_upstream(env: &Env, uri: Url, mpb: &MultiProgress) -> R
     Ok(hash)
 }
 
-fn macros(_macro: Option<String>, env: Env) -> Result<(), Error> {
-    let macros = Macros::load(&env)?;
+fn check_updates(recipes: &[PathBuf], json: bool) -> Result<(), Error> {
+    let releases = updates::Remote;
+
+    let mut checks = vec![];
+    let mut failed = 0;
+    for path in recipes {
+        let recipe = Recipe::load(path).map_err(Error::Load)?;
+
+        match updates::check(&recipe, &releases) {
+            Ok(check) => {
+                if check.latest.is_none() {
+                    eprintln!(
+                        "{} | {}: no releases found from {}",
+                        "Warning".yellow(),
+                        check.name,
+                        check.origin
+                    );
+                }
+                checks.push(check);
+            }
+            Err(error) => {
+                let reason = error.source().map(|source| format!(": {source}")).unwrap_or_default();
+                eprintln!(
+                    "{} | {}: {error}{reason}",
+                    "Warning".yellow(),
+                    recipe.parsed.source.name
+                );
+                failed += 1;
+            }
+        }
+    }
 
-    let mut items = macros
-        .actions
+    if json {
+        println!("{}", serde_json::to_string_pretty(&checks).map_err(io::Error::other)?);
+    } else {
+        print_outdated(&checks);
+    }
+
+    if failed > 0 {
+        return Err(Error::CheckUpdates(failed));
+    }
+    Ok(())
+}
+
+fn print_outdated(checks: &[updates::Check]) {
+    let outdated = checks.iter().filter(|check| check.outdated).collect::<Vec<_>>();
+    if outdated.is_empty() {
+        println!("All recipes are up-to-date");
+        return;
+    }
+
+    let header = ["Name", "Current", "Latest", "Source"];
+    let rows = outdated
         .iter()
-        .flat_map(|m| {
-            m.actions.iter().map(|action| PrintMacro {
-                name: format!("%{}", action.key),
-                // Multi-line strings need to be in `example`
-                description: action.value.description.lines().next().unwrap_or_default(),
-                example: action.value.example.as_deref(),
-            })
+        .map(|check| {
+            [
+                check.name.clone(),
+                check.current.clone(),
+                check.latest.clone().unwrap_or_default(),
+                check.origin.to_string(),
+            ]
         })
-        .sorted()
         .collect::<Vec<_>>();
+    let width = |column: usize| {
+        rows.iter()
+            .map(|row| row[column].len())
+            .chain([header[column].len()])
+            .max()
+            .unwrap_or_default()
+    };
+    let (name, current, latest) = (width(0), width(1), width(2));
+
+    for [n, c, l, s] in [header.map(ToOwned::to_owned)].into_iter().chain(rows) {
+        println!("{n:<name$}  {c:<current$}  {l:<latest$}  {s}");
+    }
+}
+
+fn macros(
+    _macro: Option<String>,
+    filter: Option<String>,
+    arch: Option<BuildTarget>,
+    expand: Option<String>,
+    json: bool,
+    env: Env,
+) -> Result<(), Error> {
+    let macros = Macros::load(&env)?;
+    let target = arch.unwrap_or(BuildTarget::Native(architecture::host()));
+    let parser = job::builtin_parser(target, &macros)?;
+
+    if let Some(snippet) = expand {
+        let expansion = parser.parse_content(&snippet)?;
 
-    let mut definitions = vec![];
-    for arch in ["base", &architecture::host().to_string()] {
-        if let Some(macros) = macros.arch.get(arch) {
-            definitions.extend(macros.definitions.iter().map(|def| PrintMacro {
-                name: format!("%({})", def.key),
-                description: &def.value,
-                example: None,
-            }));
+        if json {
+            let expanded = serde_json::json!({ "snippet": snippet, "expansion": expansion });
+            println!("{}", serde_json::to_string_pretty(&expanded).map_err(io::Error::other)?);
+        } else {
+            println!("{expansion}");
         }
+        return Ok(());
     }
-    definitions.sort();
-    definitions.dedup();
 
-    items.extend(definitions);
+    let entries = macros::entries(&parser)?;
 
-    match _macro {
-        Some(name) => {
-            let Some(action) = items
-                .into_iter()
-                .find(|a| a.name == format!("%{name}") || a.name == format!("%({name})"))
-            else {
-                return Err(Error::MacroNotFound(name));
-            };
+    if let Some(name) = _macro {
+        let Some(entry) = entries
+            .into_iter()
+            .find(|entry| [format!("%{name}"), format!("%({name})"), name.clone()].contains(&entry.name))
+        else {
+            return Err(Error::MacroNotFound(name));
+        };
 
-            println!("{} - {}", action.name.bold(), action.description);
+        if json {
+            println!("{}", serde_json::to_string_pretty(&entry).map_err(io::Error::other)?);
+        } else {
+            tui::pager::page(&render_macro(&entry));
+        }
+        return Ok(());
+    }
 
-            if let Some(example) = action.example {
-                println!("\n{}", "Example:".bold());
-                for line in example.lines() {
-                    println!("  {line}");
-                }
-            }
+    let entries = entries
+        .iter()
+        .filter(|entry| filter.as_deref().is_none_or(|filter| matches_filter(entry, filter)))
+        .collect::<Vec<_>>();
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entries).map_err(io::Error::other)?);
+    } else {
+        tui::pager::page(&render_macros(&entries));
+    }
+
+    Ok(())
+}
+
+/// Whether the name or description of `entry` contains `filter`, ignoring case
+fn matches_filter(entry: &macros::Entry, filter: &str) -> bool {
+    let filter = filter.to_lowercase();
+
+    [Some(&entry.name), entry.description.as_ref()]
+        .into_iter()
+        .flatten()
+        .any(|text| text.to_lowercase().contains(&filter))
+}
+
+/// One line per entry, actions summarized by their description & definitions by their expansion
+fn render_macros(entries: &[&macros::Entry]) -> String {
+    let width = entries.iter().map(|entry| entry.name.len()).max().unwrap_or_default();
+    let mut rendered = String::new();
+
+    for (kind, heading) in [
+        (macros::Kind::Action, "Actions"),
+        (macros::Kind::Definition, "Definitions"),
+    ] {
+        let entries = entries.iter().filter(|entry| entry.kind == kind).collect::<Vec<_>>();
+        if entries.is_empty() {
+            continue;
+        }
+
+        let _ = writeln!(rendered, "{}", heading.bold());
+        for entry in entries {
+            let summary = entry.description.as_ref().unwrap_or(&entry.expansion);
+            let _ = writeln!(
+                rendered,
+                "  {}{}  {}",
+                entry.name.clone().bold(),
+                " ".repeat(width - entry.name.len()),
+                summary.lines().next().unwrap_or_default()
+            );
+        }
+        rendered.push('\n');
+    }
+
+    rendered
+}
+
+fn render_macro(entry: &macros::Entry) -> String {
+    let mut rendered = String::new();
+    let indented = |text: &str| text.lines().map(|line| format!("  {line}\n")).collect::<String>();
+
+    match &entry.description {
+        Some(description) => {
+            let _ = writeln!(rendered, "{} - {description}", entry.name.clone().bold());
         }
         None => {
-            pretty::print_columns(&items, 1);
+            let _ = writeln!(rendered, "{}", entry.name.clone().bold());
         }
     }
 
-    Ok(())
+    let _ = write!(rendered, "\n{}\n{}", "Expands to:".bold(), indented(&entry.expansion));
+
+    if !entry.dependencies.is_empty() {
+        let _ = writeln!(
+            rendered,
+            "\n{} {}",
+            "Dependencies:".bold(),
+            entry.dependencies.join(", ")
+        );
+    }
+
+    if let Some(example) = &entry.example {
+        let _ = write!(rendered, "\n{}\n{}", "Example:".bold(), indented(example));
+    }
+
+    rendered
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord)]
-struct PrintMacro<'a> {
+/// A tuning group, as enabled for a recipe
+#[derive(Debug, Serialize)]
+struct TuningGroup {
     name: String,
-    description: &'a str,
-    example: Option<&'a str>,
+    enabled: bool,
+    /// The option it's set to, if enabled
+    option: Option<String>,
+    options: Vec<String>,
 }
 
-impl ColumnDisplay for PrintMacro<'_> {
-    fn get_display_width(&self) -> usize {
-        self.name.len()
+/// The flags added to builds by a tuning group, when set to an option or disabled
+#[derive(Debug, Serialize)]
+struct TuningFlags {
+    /// The option, or `enabled` / `disabled`
+    name: String,
+    /// Values of each compiler flag, i.e. `c`, by toolchain
+    flags: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+fn list_tuning(arch: Option<BuildTarget>, recipe: Option<&Path>, json: bool, env: Env) -> Result<(), Error> {
+    let macros = Macros::load(&env)?;
+    let target = arch.unwrap_or(BuildTarget::Native(architecture::host()));
+    let recipe = recipe.map(Recipe::load).transpose().map_err(Error::Load)?;
+
+    let (tuning, definitions) = job::recipe_tuning(target, recipe.as_ref(), &macros)?;
+    let groups = tuning_groups(&tuning);
+    // Only a recipe decides the toolchain
+    let definitions = recipe
+        .as_ref()
+        .map(|recipe| (recipe.parsed.options.toolchain, definitions));
+
+    if json {
+        let listed = serde_json::json!({
+            "groups": groups,
+            "flags": definitions.as_ref().map(|(_, definitions)| definitions.iter().cloned().collect::<BTreeMap<_, _>>()),
+        });
+        println!("{}", serde_json::to_string_pretty(&listed).map_err(io::Error::other)?);
+        return Ok(());
     }
 
-    fn display_column(&self, writer: &mut impl io::prelude::Write, _col: pretty::Column, width: usize) {
-        let _ = write!(
-            writer,
-            "{}{}  {}",
-            self.name.clone().bold(),
-            " ".repeat(width),
-            self.description,
-        );
+    let mut rendered = render_tuning_groups(&groups);
+    if let Some((toolchain, definitions)) = definitions {
+        let width = definitions.iter().map(|(name, _)| name.len()).max().unwrap_or_default();
+
+        let _ = writeln!(rendered, "\nFlags ({toolchain})");
+        for (name, value) in definitions {
+            let _ = writeln!(rendered, "  {name:<width$}  {value}");
+        }
+    }
+    tui::pager::page(&rendered);
+
+    Ok(())
+}
+
+fn show_tuning(group: &str, arch: Option<BuildTarget>, json: bool, env: Env) -> Result<(), Error> {
+    let macros = Macros::load(&env)?;
+    let target = arch.unwrap_or(BuildTarget::Native(architecture::host()));
+
+    let (tuning, _) = job::recipe_tuning(target, None, &macros)?;
+    let Some(default) = tuning_groups(&tuning).into_iter().find(|entry| entry.name == group) else {
+        return Err(Error::TuningGroupNotFound(group.to_owned()));
+    };
+    let flags = tuning_flags(&tuning, group)?;
+
+    if json {
+        let shown = serde_json::json!({ "group": default, "flags": flags });
+        println!("{}", serde_json::to_string_pretty(&shown).map_err(io::Error::other)?);
+    } else {
+        tui::pager::page(&render_tuning_group(&default, &flags));
+    }
+
+    Ok(())
+}
+
+/// Every group of `tuning`, and whether it's enabled
+fn tuning_groups(tuning: &tuning::Builder) -> Vec<TuningGroup> {
+    tuning
+        .groups()
+        .map(|(name, group)| {
+            let enabled = tuning.enabled().find(|(enabled, _)| *enabled == name);
+
+            TuningGroup {
+                name: name.to_owned(),
+                enabled: enabled.is_some(),
+                option: enabled.and_then(|(_, option)| option).map(ToOwned::to_owned),
+                options: group.choices.iter().map(|kv| kv.key.clone()).collect(),
+            }
+        })
+        .collect()
+}
+
+/// The flags of `group` when set to each of its options, or disabled
+fn tuning_flags(tuning: &tuning::Builder, group: &str) -> Result<Vec<TuningFlags>, Error> {
+    let group = tuning.group(group)?;
+
+    let enabled =
+        (group.choices.is_empty() || !group.root.enabled.is_empty()).then_some(("enabled", &group.root.enabled));
+    let options = group.choices.iter().map(|kv| (kv.key.as_str(), &kv.value.enabled));
+    let disabled = (!group.root.disabled.is_empty()).then_some(("disabled", &group.root.disabled));
+
+    enabled
+        .into_iter()
+        .chain(options)
+        .chain(disabled)
+        .map(|(name, flags)| {
+            let flags = tuning.flags(flags)?;
+            let by_toolchain = Toolchain::VARIANTS
+                .iter()
+                .map(|&toolchain| {
+                    let values = tuning::CompilerFlag::VARIANTS
+                        .iter()
+                        .map(|&flag| (flag.to_string(), tuning::join(flags.iter().copied(), flag, toolchain)))
+                        .filter(|(_, value)| !value.is_empty())
+                        .collect();
+                    (toolchain.to_string(), values)
+                })
+                .collect();
+
+            Ok(TuningFlags {
+                name: name.to_owned(),
+                flags: by_toolchain,
+            })
+        })
+        .collect()
+}
+
+/// One line per group, with the option it's enabled with
+fn render_tuning_groups(groups: &[TuningGroup]) -> String {
+    let header = ["Group", "Enabled", "Options"];
+    let rows = groups
+        .iter()
+        .map(|group| {
+            let enabled = match (&group.option, group.enabled) {
+                (Some(option), _) => option.clone(),
+                (None, true) => "yes".to_owned(),
+                (None, false) => "no".to_owned(),
+            };
+            [group.name.clone(), enabled, group.options.join(", ")]
+        })
+        .collect::<Vec<_>>();
+    let width = |column: usize| {
+        rows.iter()
+            .map(|row| row[column].len())
+            .chain([header[column].len()])
+            .max()
+            .unwrap_or_default()
+    };
+    let (name, enabled) = (width(0), width(1));
+
+    let mut rendered = String::new();
+    for [n, e, o] in [header.map(ToOwned::to_owned)].into_iter().chain(rows) {
+        let _ = writeln!(rendered, "{}", format!("{n:<name$}  {e:<enabled$}  {o}").trim_end());
+    }
+
+    rendered
+}
+
+fn render_tuning_group(group: &TuningGroup, flags: &[TuningFlags]) -> String {
+    let mut rendered = match (&group.option, group.enabled) {
+        (Some(option), _) => format!("{}: enabled by default, set to {option}\n", group.name),
+        (None, true) => format!("{}: enabled by default\n", group.name),
+        (None, false) => format!("{}: disabled by default\n", group.name),
+    };
+
+    for state in flags {
+        let _ = writeln!(rendered, "\n{}", state.name);
+
+        for (toolchain, values) in &state.flags {
+            let _ = writeln!(rendered, "  {toolchain}");
+            if values.is_empty() {
+                let _ = writeln!(rendered, "    none");
+            }
+
+            let width = values.keys().map(String::len).max().unwrap_or_default();
+            for (flag, value) in values {
+                let _ = writeln!(rendered, "    {flag:<width$}  {value}");
+            }
+        }
     }
+
+    rendered
 }
 
 fn print_diff(a: &str, b: &str) {
@@ -610,8 +1125,24 @@ pub enum Error {
     LoadMacros(#[from] macros::Error),
     #[error("Macro doesn't exist: {0}")]
     MacroNotFound(String),
+    #[error("macros of build")]
+    BuiltinMacros(#[from] job::Error),
+    #[error("{0} macros used by the scripts of the recipe aren't defined")]
+    UndefinedMacros(usize),
+    #[error("expand macros")]
+    Expand(#[from] stone_recipe::script::Error),
     #[error("resolve recipe path")]
     ResolvePath(#[source] recipe::Error),
+    #[error("load recipe")]
+    Load(#[source] recipe::Error),
+    #[error("format recipe")]
+    Format(#[from] recipe::format::Error),
+    #[error("{0:?} isn't formatted, run `boulder recipe format` on it")]
+    Unformatted(PathBuf),
+    #[error("check updates")]
+    Updates(#[from] updates::Error),
+    #[error("{0} recipe(s) couldn't be checked for updates")]
+    CheckUpdates(usize),
     #[error("reading recipe")]
     Read(#[source] io::Error),
     #[error("writing recipe")]
@@ -634,18 +1165,32 @@ pub enum Error {
     StatusCode(#[from] reqwest::Error),
     #[error("version parse")]
     Upstreams(#[from] version_parse::VersionError),
+    #[error("No tag of {0} matches version {1}, pass its ref with --upstream \"git|<ref>\"")]
+    MissingTag(Url, String),
+    #[error("list git tags")]
+    Git(#[from] gitwrap::Error),
+    #[error("invalid upstream url")]
+    Url(#[from] url::ParseError),
     #[error("Must provide version if first upstream provided is of type git")]
     GitUpstreamMustProvideVersion,
+    #[error("upstream[{0}] is a {1} repository, update its revision in the recipe by hand")]
+    VcsUpstream(usize, upstream::Vcs),
     #[error("ent recipe parse failure")]
     Ent(#[from] ent_core::recipes::RecipeError),
     #[error("string processing")]
     Dialog(#[from] tui::dialoguer::Error),
+    #[error("tuning")]
+    Tuning(#[from] tuning::Error),
+    #[error("Tuning group doesn't exist: {0}")]
+    TuningGroupNotFound(String),
     #[error("io")]
     Io(#[from] io::Error),
 }
 
 #[cfg(test)]
 mod tests {
+    use sha2::{Digest, Sha256};
+
     use super::*;
 
     #[test]
@@ -694,4 +1239,249 @@ mod tests {
             "https://github.com/systemd/systemd/archive/refs/tags/v260.1.tar.gz"
         );
     }
+
+    fn git(repo: &Path, args: &[&str]) -> String {
+        let output = std::process::Command::new("git")
+            .arg("-C")
+            .arg(repo)
+            .args(args)
+            .env("GIT_AUTHOR_NAME", "boulder")
+            .env("GIT_AUTHOR_EMAIL", "boulder@example.com")
+            .env("GIT_COMMITTER_NAME", "boulder")
+            .env("GIT_COMMITTER_EMAIL", "boulder@example.com")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap().trim().to_owned()
+    }
+
+    fn env(root: &Path) -> Env {
+        Env::new(
+            Some(root.join("cache")),
+            Some(root.join("config")),
+            Some(root.join("data")),
+            Some(root.join("moss")),
+        )
+        .unwrap()
+    }
+
+    const RECIPE: &str = "\
+# Example recipe
+name        : example
+version     : 1.0
+release     : 3
+homepage    : https://example.com
+upstreams   :
+    # Release tarball
+    - {url}example-1.0.tar.xz : aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa
+    - {url}fixes.patch :
+        hash   : bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb
+        unpack : false
+summary     : Example
+description : |
+    Example
+license     : MPL-2.0
+";
+
+    #[test]
+    fn update_follows_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let url = Url::from_directory_path(root).unwrap();
+
+        fs::write(root.join("example-1.1.tar.xz"), "example 1.1").unwrap();
+        let hash = hex::encode(Sha256::digest("example 1.1"));
+
+        let repo = root.join("example.git");
+        fs::create_dir(&repo).unwrap();
+        git(&repo, &["init", "-q"]);
+        for version in ["1.0", "1.1"] {
+            git(&repo, &["commit", "-q", "--allow-empty", "-m", version]);
+            git(&repo, &["tag", "-a", "-m", version, &format!("v{version}")]);
+        }
+        let commit = |tag: &str| git(&repo, &["rev-parse", &format!("{tag}^{{commit}}")]);
+
+        let recipe = RECIPE.replace("{url}", url.as_str()).replace(
+            "summary",
+            &format!(
+                "    - git|{url}example.git : v1.0
+    - git|{url}example.git :
+        ref      : {}
+        clonedir : pinned
+summary",
+                commit("v1.0")
+            ),
+        );
+        let path = root.join("stone.yaml");
+        fs::write(&path, &recipe).unwrap();
+
+        update(
+            env(root),
+            &path,
+            None,
+            Some("1.1".to_owned()),
+            vec![],
+            false,
+            true,
+            false,
+        )
+        .unwrap();
+
+        let expected = recipe
+            .replace("version     : 1.0", "version     : \"1.1\"")
+            .replace("release     : 3", "release     : 4")
+            .replace(
+                "example-1.0.tar.xz : aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+                &format!("example-1.1.tar.xz : {hash}"),
+            )
+            .replace("example.git : v1.0", "example.git : v1.1")
+            .replace(&commit("v1.0"), &commit("v1.1"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+
+        // Fetched upstreams are cached for the build
+        let cached = fetched_upstream_cache_path(&env(root), &url.join("example-1.1.tar.xz").unwrap(), &hash);
+        assert!(cached.exists());
+    }
+
+    #[test]
+    fn update_missing_upstream() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let url = Url::from_directory_path(root).unwrap();
+
+        let recipe = RECIPE.replace("{url}", url.as_str());
+        let path = root.join("stone.yaml");
+        fs::write(&path, &recipe).unwrap();
+
+        let result = update(
+            env(root),
+            &path,
+            None,
+            Some("2.0".to_owned()),
+            vec![],
+            false,
+            true,
+            false,
+        );
+        assert!(matches!(result, Err(Error::Fetch(_))), "{result:?}");
+        assert_eq!(fs::read_to_string(&path).unwrap(), recipe);
+    }
+
+    fn bundled_macros() -> Macros {
+        let dir = tempfile::tempdir().unwrap();
+        let env = Env::new(
+            Some(dir.path().join("cache")),
+            Some(dir.path().join("config")),
+            Some(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("data")),
+            Some(dir.path().join("moss")),
+        )
+        .unwrap();
+
+        Macros::load(&env).unwrap()
+    }
+
+    /// `boulder recipe tuning show <group>` for x86_64
+    fn shown_tuning(macros: &Macros, group: &str) -> String {
+        let target = BuildTarget::Native(architecture::Architecture::X86_64);
+        let (tuning, _) = job::recipe_tuning(target, None, macros).unwrap();
+        let default = tuning_groups(&tuning)
+            .into_iter()
+            .find(|entry| entry.name == group)
+            .unwrap();
+
+        render_tuning_group(&default, &tuning_flags(&tuning, group).unwrap())
+    }
+
+    #[test]
+    fn tuning_snapshots() {
+        let macros = bundled_macros();
+
+        assert_eq!(
+            shown_tuning(&macros, "harden"),
+            include_str!("../../../test/tuning/harden.txt")
+        );
+        assert_eq!(
+            shown_tuning(&macros, "lto"),
+            include_str!("../../../test/tuning/lto.txt")
+        );
+    }
+
+    #[test]
+    fn recipe_tuning() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stone.yaml");
+        fs::write(
+            &path,
+            "\
+name: example
+version: 1.0
+release: 1
+homepage: https://example.com
+license: MPL-2.0
+summary: example
+description: example
+options:
+    - toolchain: gnu
+tuning:
+    - harden: lvl2
+    - lto: false
+",
+        )
+        .unwrap();
+        let recipe = Recipe::load(&path).unwrap();
+
+        let macros = bundled_macros();
+        let target = BuildTarget::Native(architecture::Architecture::X86_64);
+        let (tuning, definitions) = job::recipe_tuning(target, Some(&recipe), &macros).unwrap();
+        let groups = tuning_groups(&tuning);
+        let group = |name: &str| groups.iter().find(|group| group.name == name).unwrap();
+
+        assert_eq!(group("harden").option.as_deref(), Some("lvl2"));
+        assert!(!group("lto").enabled);
+        assert!(group("architecture").enabled);
+
+        let cflags = &definitions.iter().find(|(name, _)| name == "cflags").unwrap().1;
+        assert!(cflags.contains("-fstack-protector-strong"), "{cflags}");
+        assert!(!cflags.contains("-flto"), "{cflags}");
+
+        let rendered = render_tuning_groups(&groups);
+        assert!(
+            rendered
+                .lines()
+                .any(|line| line.starts_with("harden ") && line.contains(" lvl2 "))
+        );
+        assert!(
+            rendered
+                .lines()
+                .any(|line| line.starts_with("lto ") && line.contains(" no "))
+        );
+    }
+
+    #[test]
+    fn filter_macros() {
+        let entry = |name: &str, kind, description: Option<&str>| macros::Entry {
+            name: name.to_owned(),
+            kind,
+            description: description.map(ToOwned::to_owned),
+            example: None,
+            dependencies: vec![],
+            expansion: format!("expanded {name}"),
+        };
+        let cmake = entry("%cmake", macros::Kind::Action, Some("Perform CMake configuration"));
+        let libdir = entry("%(libdir)", macros::Kind::Definition, None);
+
+        assert!(matches_filter(&cmake, "CMAKE"));
+        assert!(matches_filter(&cmake, "configuration"));
+        assert!(!matches_filter(&libdir, "cmake"));
+        assert!(matches_filter(&libdir, "LIB"));
+
+        let rendered = render_macros(&[&libdir, &cmake]);
+        let actions = rendered.find("Actions").unwrap();
+        let definitions = rendered.find("Definitions").unwrap();
+        assert!(actions < rendered.find("Perform CMake configuration").unwrap());
+        assert!(definitions > actions);
+        assert!(rendered[definitions..].contains("expanded %(libdir)"));
+
+        assert!(!render_macros(&[&libdir]).contains("Actions"));
+    }
 }
@@ -0,0 +1,397 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Statistics of the compiler caches shared by builds
+//!
+//! ccache keeps its counters within the cache, so those of a build are the
+//! difference of its counters before & after it. sccache only counts while
+//! its server runs, which lives as long as the build container.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{self, Read, Seek},
+    path::Path,
+    process,
+};
+
+use humansize::BINARY;
+use serde::Serialize;
+use thiserror::Error;
+
+/// A compiler cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, strum::Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Tool {
+    Ccache,
+    Sccache,
+}
+
+impl Tool {
+    /// Arguments printing the stats, tried in order until one succeeds
+    ///
+    /// Only ccache 4.x can print them machine readable.
+    fn args(&self) -> &'static [&'static [&'static str]] {
+        match self {
+            Tool::Ccache => &[&["--print-stats"], &["--show-stats", "--verbose"], &["--show-stats"]],
+            Tool::Sccache => &[&["--show-stats"]],
+        }
+    }
+
+    fn dir_var(&self) -> &'static str {
+        match self {
+            Tool::Ccache => "CCACHE_DIR",
+            Tool::Sccache => "SCCACHE_DIR",
+        }
+    }
+
+    /// Query the stats of the cache in `dir`
+    pub fn stats(&self, dir: &Path) -> Result<Stats, Error> {
+        for args in self.args() {
+            // Written to a file, as sccache may start a server holding on to its output
+            let mut output = tempfile::tempfile()?;
+
+            let status = process::Command::new(self.to_string())
+                .args(*args)
+                .env(self.dir_var(), dir)
+                .stdin(process::Stdio::null())
+                .stdout(output.try_clone()?)
+                .stderr(process::Stdio::null())
+                .status()
+                .map_err(|error| match error.kind() {
+                    io::ErrorKind::NotFound => Error::NotInstalled(*self),
+                    _ => Error::Io(error),
+                })?;
+
+            if !status.success() {
+                continue;
+            }
+
+            let mut text = String::new();
+            output.rewind()?;
+            output.read_to_string(&mut text)?;
+
+            return self.parse(&text).ok_or(Error::Parse(*self));
+        }
+
+        Err(Error::Failed(*self))
+    }
+
+    /// Limit the cache in `dir` to `max_size`, i.e. `5G`, evicting the least recently used
+    /// objects above it
+    ///
+    /// The limit is kept in the config of the cache, so applies to later builds too.
+    pub fn cleanup(&self, dir: &Path, max_size: &str) -> Result<(), Error> {
+        let args: &[&str] = match self {
+            Tool::Ccache => &["--max-size", max_size, "--cleanup"],
+            Tool::Sccache => return Err(Error::Unsupported(*self)),
+        };
+
+        let status = process::Command::new(self.to_string())
+            .args(args)
+            .env(self.dir_var(), dir)
+            .stdin(process::Stdio::null())
+            .stdout(process::Stdio::null())
+            .status()
+            .map_err(|error| match error.kind() {
+                io::ErrorKind::NotFound => Error::NotInstalled(*self),
+                _ => Error::Io(error),
+            })?;
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err(Error::CleanupFailed(*self))
+        }
+    }
+
+    /// Parse the stats printed by the tool
+    pub fn parse(&self, output: &str) -> Option<Stats> {
+        match self {
+            Tool::Ccache => parse_ccache(output),
+            Tool::Sccache => parse_sccache(output),
+        }
+    }
+}
+
+/// Counters & size of a compiler cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Stats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Bytes stored
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// Bytes the cache is limited to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<u64>,
+}
+
+impl Stats {
+    /// Percentage of cacheable compilations found in the cache
+    pub fn hit_rate(&self) -> Option<f64> {
+        let total = self.hits + self.misses;
+        (total > 0).then(|| self.hits as f64 * 100.0 / total as f64)
+    }
+
+    /// Counters since `before` was queried, sized as now
+    pub fn since(&self, before: &Stats) -> Self {
+        Self {
+            hits: self.hits.saturating_sub(before.hits),
+            misses: self.misses.saturating_sub(before.misses),
+            ..*self
+        }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} hits, {} misses", self.hits, self.misses)?;
+
+        if let Some(rate) = self.hit_rate() {
+            write!(f, " ({rate:.1}% hit rate)")?;
+        }
+
+        match (self.size, self.max_size) {
+            (Some(size), Some(max_size)) => write!(
+                f,
+                ", {} of {}",
+                humansize::format_size(size, BINARY),
+                humansize::format_size(max_size, BINARY)
+            ),
+            (Some(size), None) => write!(f, ", {}", humansize::format_size(size, BINARY)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Render the stats of each cache, one line each
+pub fn render(caches: &BTreeMap<Tool, Stats>) -> String {
+    let width = caches
+        .keys()
+        .map(|tool| tool.to_string().len())
+        .max()
+        .unwrap_or_default();
+
+    caches
+        .iter()
+        .map(|(tool, stats)| format!("{:<width$}  {stats}\n", tool.to_string()))
+        .collect()
+}
+
+/// Parse `ccache --print-stats`, or else `ccache --show-stats` of ccache 3.x & 4.x
+fn parse_ccache(output: &str) -> Option<Stats> {
+    let machine = output
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .filter_map(|(key, value)| Some((key.trim(), value.trim().parse::<u64>().ok()?)))
+        .collect::<BTreeMap<_, _>>();
+
+    if machine.contains_key("cache_miss") {
+        return Some(Stats {
+            hits: machine.get("direct_cache_hit").copied().unwrap_or_default()
+                + machine.get("preprocessed_cache_hit").copied().unwrap_or_default(),
+            misses: machine["cache_miss"],
+            size: machine.get("cache_size_kibibyte").map(|kibibytes| kibibytes * 1024),
+            max_size: None,
+        });
+    }
+
+    let fields = fields(output);
+    let field = |key: &str| {
+        fields
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, value)| *value)
+    };
+    let count = |key: &str| field(key).and_then(|value| value.split_whitespace().next()?.parse::<u64>().ok());
+
+    // ccache 4.x, sizes in the unit of the key i.e. `Cache size (GiB): 2.1 / 5.0 (42.66%)`
+    if let (Some(hits), Some(misses)) = (count("Hits"), count("Misses")) {
+        let sized = fields.iter().find_map(|(key, value)| {
+            let unit = key.strip_prefix("Cache size (")?.strip_suffix(')')?;
+            let (size, max_size) = match value.split_once('/') {
+                Some((size, max_size)) => (size, max_size.split_whitespace().next()),
+                None => (*value, None),
+            };
+            Some((
+                parse_size(size.trim(), unit),
+                max_size.and_then(|max_size| parse_size(max_size, unit)),
+            ))
+        });
+        let (size, max_size) = sized.unwrap_or_default();
+
+        return Some(Stats {
+            hits,
+            misses,
+            size,
+            max_size,
+        });
+    }
+
+    // ccache 3.x, sizes suffixed by their unit i.e. `cache size 2.1 GB`
+    Some(Stats {
+        hits: count("cache hit (direct)")? + count("cache hit (preprocessed)").unwrap_or_default(),
+        misses: count("cache miss")?,
+        size: field("cache size").and_then(parse_suffixed_size),
+        max_size: field("max cache size").and_then(parse_suffixed_size),
+    })
+}
+
+/// Parse `sccache --show-stats`
+fn parse_sccache(output: &str) -> Option<Stats> {
+    let fields = fields(output);
+    let field = |key: &str| fields.iter().find(|(k, _)| *k == key).map(|(_, value)| *value);
+    let count = |key: &str| field(key)?.parse::<u64>().ok();
+
+    Some(Stats {
+        hits: count("Cache hits")?,
+        misses: count("Cache misses")?,
+        size: field("Cache size").and_then(parse_suffixed_size),
+        max_size: field("Max cache size").and_then(parse_suffixed_size),
+    })
+}
+
+/// Keys & values of plain text stats, aligned into columns
+///
+/// Keys are followed by a colon or at least two spaces, whichever comes first.
+fn fields(output: &str) -> Vec<(&str, &str)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            let split = [line.find(": "), line.find("  ")].into_iter().flatten().min()?;
+            let (key, value) = line.split_at(split);
+            Some((key.trim_end_matches(':'), value.trim_start_matches(':').trim()))
+        })
+        .collect()
+}
+
+/// Parse a size such as `2.1 GB` or `734 MiB`
+fn parse_suffixed_size(text: &str) -> Option<u64> {
+    let (number, unit) = text.trim().split_once(char::is_whitespace)?;
+    parse_size(number, unit.trim())
+}
+
+/// Parse a `number` of `unit`s, which may be fractional
+fn parse_size(number: &str, unit: &str) -> Option<u64> {
+    let multiplier = match unit {
+        "B" | "bytes" => 1,
+        "kB" | "KB" => 1000,
+        "KiB" => 1 << 10,
+        "MB" => 1000_u64.pow(2),
+        "MiB" => 1 << 20,
+        "GB" => 1000_u64.pow(3),
+        "GiB" => 1 << 30,
+        "TB" => 1000_u64.pow(4),
+        "TiB" => 1 << 40,
+        _ => return None,
+    };
+
+    Some((number.parse::<f64>().ok()? * multiplier as f64).round() as u64)
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{0} isn't installed")]
+    NotInstalled(Tool),
+    #[error("{0} failed to print its stats")]
+    Failed(Tool),
+    #[error("unrecognized stats printed by {0}")]
+    Parse(Tool),
+    #[error("{0} failed to clean up")]
+    CleanupFailed(Tool),
+    #[error("{0} can't be cleaned up by boulder")]
+    Unsupported(Tool),
+    #[error("io")]
+    Io(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const GIB: u64 = 1 << 30;
+
+    #[test]
+    fn ccache() {
+        let print = Tool::Ccache.parse(include_str!("../../test/compiler_cache/ccache-4-print.txt"));
+        assert_eq!(
+            print,
+            Some(Stats {
+                hits: 1210,
+                misses: 492,
+                size: Some(2_254_860_288),
+                max_size: None,
+            })
+        );
+
+        let verbose = Tool::Ccache
+            .parse(include_str!("../../test/compiler_cache/ccache-4-verbose.txt"))
+            .unwrap();
+        assert_eq!((verbose.hits, verbose.misses), (1210, 492));
+        assert_eq!(verbose.size, Some((2.1 * GIB as f64).round() as u64));
+        assert_eq!(verbose.max_size, Some(5 * GIB));
+
+        let legacy = Tool::Ccache
+            .parse(include_str!("../../test/compiler_cache/ccache-3.txt"))
+            .unwrap();
+        assert_eq!((legacy.hits, legacy.misses), (1210, 492));
+        assert_eq!(legacy.size, Some(2_100_000_000));
+        assert_eq!(legacy.max_size, Some(5_000_000_000));
+
+        assert_eq!(Tool::Ccache.parse("ccache: invalid option -- 'print-stats'"), None);
+    }
+
+    #[test]
+    fn sccache() {
+        let stats = Tool::Sccache
+            .parse(include_str!("../../test/compiler_cache/sccache.txt"))
+            .unwrap();
+
+        assert_eq!(
+            stats,
+            Stats {
+                hits: 911,
+                misses: 52,
+                size: Some(734 << 20),
+                max_size: Some(10 * GIB),
+            }
+        );
+        assert_eq!(Tool::Sccache.parse(""), None);
+    }
+
+    #[test]
+    fn since() {
+        let before = Stats {
+            hits: 1000,
+            misses: 400,
+            size: Some(GIB),
+            max_size: Some(5 * GIB),
+        };
+        let after = Stats {
+            hits: 1210,
+            misses: 492,
+            size: Some(2 * GIB),
+            max_size: Some(5 * GIB),
+        };
+
+        let build = after.since(&before);
+        assert_eq!(
+            build,
+            Stats {
+                hits: 210,
+                misses: 92,
+                ..after
+            }
+        );
+        assert_eq!(
+            render(&BTreeMap::from([
+                (Tool::Ccache, build),
+                (Tool::Sccache, Stats::default())
+            ])),
+            "ccache   210 hits, 92 misses (69.5% hit rate), 2 GiB of 5 GiB\nsccache  0 hits, 0 misses\n"
+        );
+    }
+}
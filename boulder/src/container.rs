@@ -1,25 +1,76 @@
 // SPDX-FileCopyrightText: 2024 AerynOS Developers
 // SPDX-License-Identifier: MPL-2.0
 
+use std::{
+    io,
+    net::{Ipv4Addr, TcpListener},
+    path::{Path, PathBuf},
+};
+
 use container::Container;
+use moss::util;
 use thiserror::Error;
 
-use crate::Paths;
+use self::network::{Allowlist, Proxy};
+use crate::{Paths, build::environment::Environment};
+
+pub mod network;
+
+/// Run `f` within the container of the build, reaching only the hosts of
+/// `allowlist` with `networking`, if any
+///
+/// The proxy variables of `environment` must then be set by [`Allowlist::set_proxy`].
+/// `binds` are mounted over those of the build, and `work_dir` replaces its build dir.
+pub fn exec<E>(
+    paths: &Paths,
+    networking: bool,
+    allowlist: &Allowlist,
+    environment: &Environment,
+    binds: &[Bind],
+    work_dir: Option<&Path>,
+    f: impl FnMut() -> Result<(), E>,
+) -> Result<(), Error>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    run(paths, networking, allowlist, environment, binds, work_dir, false, f)
+}
 
-pub fn exec<E>(paths: &Paths, networking: bool, f: impl FnMut() -> Result<(), E>) -> Result<(), Error>
+/// Run `f` within the container of the build like [`exec`], forwarding `SIGINT` &
+/// `SIGTERM` to it until it exits, see [`::container::forward_signals`]
+///
+/// Whether it was cancelled by them is then told by [`::container::received_signal`].
+pub fn exec_cancellable<E>(
+    paths: &Paths,
+    networking: bool,
+    allowlist: &Allowlist,
+    environment: &Environment,
+    f: impl FnMut() -> Result<(), E>,
+) -> Result<(), Error>
 where
     E: std::error::Error + Send + Sync + 'static,
 {
-    run(paths, networking, f)
+    run(paths, networking, allowlist, environment, &[], None, true, f)
 }
 
-fn run<E>(paths: &Paths, networking: bool, f: impl FnMut() -> Result<(), E>) -> Result<(), Error>
+#[allow(clippy::too_many_arguments)]
+fn run<E>(
+    paths: &Paths,
+    networking: bool,
+    allowlist: &Allowlist,
+    environment: &Environment,
+    binds: &[Bind],
+    work_dir: Option<&Path>,
+    forward_signals: bool,
+    mut f: impl FnMut() -> Result<(), E>,
+) -> Result<(), Error>
 where
     E: std::error::Error + Send + Sync + 'static,
 {
     let rootfs = paths.rootfs().host;
     let artefacts = paths.artefacts();
     let build = paths.build();
+    let logs = paths.logs();
     let compiler = paths.ccache();
     let gocache = paths.gocache();
     let gomodcache = paths.gomodcache();
@@ -29,13 +80,27 @@ where
     let recipe = paths.recipe();
     let ccache_conf = paths.ccache_config();
 
+    for cache in [&compiler.host, &rustc_wrapper.host] {
+        util::ensure_dir_exists(cache).map_err(Error::CompilerCache)?;
+    }
+
+    // Dropped once the container exits
+    let proxy = allowlist
+        .restricts(networking)
+        .then(|| Proxy::spawn(allowlist.clone(), &build.host.join(network::SOCKET)))
+        .transpose()
+        .map_err(Error::Proxy)?;
+    let relay = proxy.as_ref().map(|_| build.guest.join(network::SOCKET));
+
     let mut container = Container::new(rootfs)
         .hostname("boulder")
-        .networking(networking)
+        .networking(networking && proxy.is_none())
         .ignore_host_sigint(true)
-        .work_dir(&build.guest)
+        .forward_host_signals(forward_signals)
+        .work_dir(work_dir.unwrap_or(&build.guest))
         .bind_rw(&artefacts.host, &artefacts.guest)
         .bind_rw(&build.host, &build.guest)
+        .bind_rw(&logs.host, &logs.guest)
         .bind_rw(&compiler.host, &compiler.guest)
         .bind_rw(&gocache.host, &gocache.guest)
         .bind_rw(&gomodcache.host, &gomodcache.guest)
@@ -49,13 +114,106 @@ where
         container = container.bind_ro(&manifest.host, &manifest.guest);
     }
 
-    container.run::<E>(f)?;
+    for bind in binds {
+        container = if bind.read_only {
+            container.bind_ro(&bind.host, &bind.guest)
+        } else {
+            container.bind_rw(&bind.host, &bind.guest)
+        };
+    }
+
+    for (key, value) in environment.iter() {
+        container = container.env(key, value);
+    }
+
+    container.run::<Payload<E>>(|| {
+        if let Some(socket) = &relay {
+            let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, network::PROXY_PORT)).map_err(Payload::Relay)?;
+            network::relay(listener, socket.clone());
+        }
+
+        f().map_err(Payload::Run)
+    })?;
 
     Ok(())
 }
 
+/// A host dir mounted into the container, besides those of the build
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bind {
+    pub host: PathBuf,
+    pub guest: PathBuf,
+    pub read_only: bool,
+}
+
+impl Bind {
+    /// Parse a `HOST:GUEST[:ro]` bind passed on the command line
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (host, guest, read_only) = match text.split(':').collect::<Vec<_>>()[..] {
+            [host, guest] => (host, guest, false),
+            [host, guest, "ro"] => (host, guest, true),
+            [host, guest, "rw"] => (host, guest, false),
+            _ => return Err(format!("expected HOST:GUEST[:ro], got `{text}`")),
+        };
+
+        if host.is_empty() {
+            return Err(format!("missing host path in `{text}`"));
+        }
+        if !guest.starts_with('/') {
+            return Err(format!("guest path `{guest}` isn't absolute"));
+        }
+
+        Ok(Self {
+            host: host.into(),
+            guest: guest.into(),
+            read_only,
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+enum Payload<E> {
+    #[error("listen for proxied requests")]
+    Relay(#[source] io::Error),
+    #[error(transparent)]
+    Run(E),
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error(transparent)]
     Container(#[from] container::Error),
+    #[error("start network proxy")]
+    Proxy(#[source] io::Error),
+    #[error("create compiler cache")]
+    CompilerCache(#[source] io::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_bind() {
+        assert_eq!(
+            Bind::parse("corpus:/mason/corpus:ro").unwrap(),
+            Bind {
+                host: "corpus".into(),
+                guest: "/mason/corpus".into(),
+                read_only: true,
+            }
+        );
+        assert!(!Bind::parse("/srv/data:/data").unwrap().read_only);
+        assert!(!Bind::parse("/srv/data:/data:rw").unwrap().read_only);
+
+        for invalid in [
+            "/srv/data",
+            "/srv/data:data",
+            ":/data",
+            "/srv/data:/data:ro:rw",
+            "/srv:/data:noexec",
+        ] {
+            assert!(Bind::parse(invalid).is_err(), "{invalid}");
+        }
+    }
 }
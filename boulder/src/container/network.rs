@@ -0,0 +1,539 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Network access of builds restricted to the hosts of a `network_allowlist`
+//!
+//! With an allowlist, the container gets a network namespace of its own,
+//! only reaching out through a proxy listening on its loopback. The proxy
+//! relays each request over a socket of the build dir to boulder on the host,
+//! which only connects to the hosts allowed, on ports 80 & 443. Anything
+//! else fails fast, direct connections having no route and proxied ones
+//! being refused, the latter logged along with their destination.
+
+use std::{
+    collections::BTreeSet,
+    fmt,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use fs_err as fs;
+use tui::Styled;
+use url::Url;
+
+use crate::build::environment::Environment;
+
+/// Ports of the hosts allowed
+const PORTS: &[u16] = &[80, 443];
+
+/// Port the proxy listens on within the container
+pub const PROXY_PORT: u16 = 3128;
+
+/// Name of the socket relaying requests from the container, within the build dir
+pub const SOCKET: &str = ".network-proxy.sock";
+
+/// Longest request head accepted by the proxy
+const MAX_HEAD: usize = 64 * 1024;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Hosts a build with networking may connect to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Allowlist {
+    hosts: BTreeSet<String>,
+    ports: Vec<u16>,
+}
+
+impl Default for Allowlist {
+    fn default() -> Self {
+        Self::new(Vec::<String>::new())
+    }
+}
+
+impl Allowlist {
+    /// Allow `hosts`, matched regardless of case
+    pub fn new(hosts: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        Self {
+            hosts: hosts.into_iter().filter_map(|host| normalize(host.as_ref())).collect(),
+            ports: PORTS.to_vec(),
+        }
+    }
+
+    /// Allow the hosts of both
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            hosts: self.hosts.into_iter().chain(other.hosts).collect(),
+            ..self
+        }
+    }
+
+    /// Whether any host is allowed, no allowlist leaving networking unrestricted
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty()
+    }
+
+    /// Whether the allowlist restricts a build with `networking`
+    pub fn restricts(&self, networking: bool) -> bool {
+        networking && !self.is_empty()
+    }
+
+    pub fn permits(&self, destination: &Destination) -> bool {
+        self.ports.contains(&destination.port)
+            && normalize(&destination.host).is_some_and(|host| self.hosts.contains(&host))
+    }
+
+    /// Point the proxy variables of `environment` at the proxy of the container
+    pub fn set_proxy(environment: &mut Environment) {
+        let url = format!("http://127.0.0.1:{PROXY_PORT}");
+
+        for key in ["http_proxy", "https_proxy", "HTTP_PROXY", "HTTPS_PROXY"] {
+            environment.set(key, &url);
+        }
+        for key in ["no_proxy", "NO_PROXY"] {
+            environment.set(key, "localhost,127.0.0.1,::1");
+        }
+    }
+}
+
+/// `host` lowercased without any trailing dot or IPv6 brackets
+fn normalize(host: &str) -> Option<String> {
+    let host = host.trim().trim_end_matches('.');
+    let host = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+
+    (!host.is_empty()).then(|| host.to_lowercase())
+}
+
+/// Where a proxied request connects to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Destination {
+    pub host: String,
+    pub port: u16,
+    /// Tunneled by `CONNECT`, rather than a plain HTTP request forwarded as is
+    pub tunnel: bool,
+}
+
+impl Destination {
+    /// The destination of the proxy request starting with `line`, i.e. `CONNECT example.com:443 HTTP/1.1`
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.split_whitespace();
+        let (method, target, _version) = (parts.next()?, parts.next()?, parts.next()?);
+
+        if method.eq_ignore_ascii_case("CONNECT") {
+            // Authority only, without a default port
+            let url = Url::parse(&format!("connect://{target}")).ok()?;
+
+            Some(Self {
+                host: url.host_str()?.to_owned(),
+                port: url.port()?,
+                tunnel: true,
+            })
+        } else {
+            let url = Url::parse(target).ok()?;
+            if url.scheme() != "http" {
+                return None;
+            }
+
+            Some(Self {
+                host: url.host_str()?.to_owned(),
+                port: url.port_or_known_default()?,
+                tunnel: false,
+            })
+        }
+    }
+}
+
+impl fmt::Display for Destination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.host, self.port)
+    }
+}
+
+/// The proxy of a build container, listening on [`SOCKET`] until dropped
+pub struct Proxy {
+    socket: PathBuf,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Proxy {
+    /// Listen at `socket`, connecting to the hosts of `allowlist`
+    pub fn spawn(allowlist: Allowlist, socket: &Path) -> io::Result<Self> {
+        if socket.exists() {
+            fs::remove_file(socket)?;
+        }
+        let listener = UnixListener::bind(socket)?;
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread = thread::spawn({
+            let stop = stop.clone();
+            move || {
+                for client in listener.incoming() {
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let Ok(client) = client else {
+                        continue;
+                    };
+
+                    let allowlist = allowlist.clone();
+                    thread::spawn(move || {
+                        let _ = proxy(client, &allowlist);
+                    });
+                }
+            }
+        });
+
+        Ok(Self {
+            socket: socket.to_owned(),
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for Proxy {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+
+        // Wake the listener up to stop
+        if UnixStream::connect(&self.socket).is_ok()
+            && let Some(thread) = self.thread.take()
+        {
+            let _ = thread.join();
+        }
+        let _ = fs::remove_file(&self.socket);
+    }
+}
+
+/// Relay the connections to `listener` to the proxy at `socket`, in the background
+///
+/// Ran within the container, whose only way out it is.
+pub fn relay(listener: TcpListener, socket: PathBuf) {
+    thread::spawn(move || {
+        for client in listener.incoming() {
+            let Ok(client) = client else {
+                continue;
+            };
+
+            if let Ok(proxy) = UnixStream::connect(&socket) {
+                thread::spawn(move || splice(client, proxy, &[]));
+            }
+        }
+    });
+}
+
+/// Serve a request of `client`, if its destination is allowed
+fn proxy(client: UnixStream, allowlist: &Allowlist) -> io::Result<()> {
+    let mut reader = BufReader::new(client);
+    let mut head = vec![];
+
+    // Up to & including the empty line ending the head
+    loop {
+        let read = reader
+            .by_ref()
+            .take((MAX_HEAD - head.len()) as u64)
+            .read_until(b'\n', &mut head)?;
+        if read == 0 {
+            return Ok(());
+        }
+        if head.ends_with(b"\r\n\r\n") || head.ends_with(b"\n\n") {
+            break;
+        }
+        if head.len() >= MAX_HEAD {
+            return respond(reader.get_mut(), "431 Request Header Fields Too Large", "");
+        }
+    }
+
+    let line = String::from_utf8_lossy(head.split(|&byte| byte == b'\n').next().unwrap_or_default()).into_owned();
+    let Some(destination) = Destination::parse(&line) else {
+        return respond(reader.get_mut(), "400 Bad Request", "");
+    };
+
+    if !allowlist.permits(&destination) {
+        eprintln!(
+            "{} network access to {destination}, not in the network allowlist",
+            "Blocked".yellow()
+        );
+        return respond(
+            reader.get_mut(),
+            "403 Forbidden",
+            &format!("{destination} isn't in the network allowlist\n"),
+        );
+    }
+
+    let upstream = match connect(&destination) {
+        Ok(upstream) => upstream,
+        Err(error) => {
+            return respond(
+                reader.get_mut(),
+                "502 Bad Gateway",
+                &format!("{destination}: {error}\n"),
+            );
+        }
+    };
+
+    // Whatever the client sent past the head
+    let buffered = reader.buffer().to_vec();
+    let mut client = reader.into_inner();
+
+    let pending = if destination.tunnel {
+        client.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")?;
+        buffered
+    } else {
+        [head, buffered].concat()
+    };
+
+    splice(client, upstream, &pending);
+
+    Ok(())
+}
+
+fn connect(destination: &Destination) -> io::Result<TcpStream> {
+    let mut error = io::Error::new(io::ErrorKind::NotFound, "no address resolved");
+
+    for address in (destination.host.as_str(), destination.port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => error = e,
+        }
+    }
+
+    Err(error)
+}
+
+fn respond(client: &mut UnixStream, status: &str, body: &str) -> io::Result<()> {
+    write!(
+        client,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+/// A stream that can be copied from & to at once
+trait Stream: Read + Write + Send + Sized + 'static {
+    fn try_clone(&self) -> io::Result<Self>;
+
+    fn shutdown(&self, how: Shutdown) -> io::Result<()>;
+}
+
+impl Stream for TcpStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        TcpStream::try_clone(self)
+    }
+
+    fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        TcpStream::shutdown(self, how)
+    }
+}
+
+impl Stream for UnixStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        UnixStream::try_clone(self)
+    }
+
+    fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        UnixStream::shutdown(self, how)
+    }
+}
+
+/// Copy between `a` & `b` until both are done, first sending `pending` to `b`
+fn splice(mut a: impl Stream, mut b: impl Stream, pending: &[u8]) {
+    if b.write_all(pending).is_err() {
+        return;
+    }
+
+    let (Ok(mut a_reader), Ok(mut b_reader)) = (a.try_clone(), b.try_clone()) else {
+        return;
+    };
+
+    let upload = thread::spawn(move || {
+        let _ = io::copy(&mut a_reader, &mut b);
+        let _ = b.shutdown(Shutdown::Write);
+    });
+
+    let _ = io::copy(&mut b_reader, &mut a);
+    let _ = a.shutdown(Shutdown::Write);
+    let _ = upload.join();
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use super::*;
+
+    fn destination(host: &str, port: u16) -> Destination {
+        Destination {
+            host: host.to_owned(),
+            port,
+            tunnel: true,
+        }
+    }
+
+    #[test]
+    fn parse_destination() {
+        assert_eq!(
+            Destination::parse("CONNECT static.crates.io:443 HTTP/1.1"),
+            Some(destination("static.crates.io", 443))
+        );
+        assert_eq!(
+            Destination::parse("CONNECT [2001:db8::1]:443 HTTP/1.1"),
+            Some(destination("[2001:db8::1]", 443))
+        );
+        assert_eq!(
+            Destination::parse("GET http://proxy.golang.org/cached-only HTTP/1.1"),
+            Some(Destination {
+                host: "proxy.golang.org".to_owned(),
+                port: 80,
+                tunnel: false,
+            })
+        );
+        assert_eq!(
+            Destination::parse("GET http://example.com:8080/ HTTP/1.1")
+                .unwrap()
+                .port,
+            8080
+        );
+
+        // No port to tunnel to, a request to the proxy itself & other schemes
+        assert_eq!(Destination::parse("CONNECT example.com HTTP/1.1"), None);
+        assert_eq!(Destination::parse("GET / HTTP/1.1"), None);
+        assert_eq!(Destination::parse("GET ftp://example.com/ HTTP/1.1"), None);
+        assert_eq!(Destination::parse("CONNECT example.com:443"), None);
+    }
+
+    #[test]
+    fn permits() {
+        let allowlist = Allowlist::new(["Static.Crates.io.", "index.crates.io", "2001:db8::1", " "])
+            .merge(Allowlist::new(["proxy.golang.org"]));
+
+        assert!(allowlist.permits(&destination("static.crates.io", 443)));
+        assert!(allowlist.permits(&destination("INDEX.crates.io.", 80)));
+        assert!(allowlist.permits(&destination("[2001:db8::1]", 443)));
+        assert!(allowlist.permits(&destination("proxy.golang.org", 443)));
+
+        assert!(!allowlist.permits(&destination("static.crates.io", 22)));
+        assert!(!allowlist.permits(&destination("crates.io", 443)));
+        assert!(!allowlist.permits(&destination("evil.static.crates.io", 443)));
+
+        assert!(allowlist.restricts(true));
+        assert!(!allowlist.restricts(false));
+        assert!(!Allowlist::default().restricts(true));
+    }
+
+    #[test]
+    fn proxy_environment() {
+        let mut environment = Environment::default();
+        environment.set("https_proxy", "http://corporate:8080");
+        Allowlist::set_proxy(&mut environment);
+
+        let proxies = environment.proxies().collect::<Vec<_>>();
+        assert!(proxies.contains(&("https_proxy", "http://127.0.0.1:3128")));
+        assert!(proxies.contains(&("HTTP_PROXY", "http://127.0.0.1:3128")));
+        assert!(proxies.contains(&("no_proxy", "localhost,127.0.0.1,::1")));
+    }
+
+    fn request(socket: &Path, head: &str) -> (UnixStream, String) {
+        let mut client = UnixStream::connect(socket).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        client.write_all(head.as_bytes()).unwrap();
+
+        let mut status = String::new();
+        BufReader::new(client.try_clone().unwrap())
+            .read_line(&mut status)
+            .unwrap();
+        (client, status)
+    }
+
+    #[test]
+    fn blocked_host_fails_fast() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join(SOCKET);
+        let _proxy = Proxy::spawn(Allowlist::new(["static.crates.io"]), &socket).unwrap();
+
+        let started = Instant::now();
+        let (_, status) = request(
+            &socket,
+            "CONNECT github.com:443 HTTP/1.1\r\nHost: github.com:443\r\n\r\n",
+        );
+        assert_eq!(status, "HTTP/1.1 403 Forbidden\r\n");
+        let (_, status) = request(&socket, "GET http://github.com/ HTTP/1.1\r\nHost: github.com\r\n\r\n");
+        assert_eq!(status, "HTTP/1.1 403 Forbidden\r\n");
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        let (_, status) = request(&socket, "GET / HTTP/1.1\r\n\r\n");
+        assert_eq!(status, "HTTP/1.1 400 Bad Request\r\n");
+    }
+
+    #[test]
+    fn relay_allowed_host() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join(SOCKET);
+
+        // Echoes a line back
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (stream, _) = server.accept().unwrap();
+            let mut line = String::new();
+            BufReader::new(stream.try_clone().unwrap())
+                .read_line(&mut line)
+                .unwrap();
+            (&stream).write_all(line.as_bytes()).unwrap();
+        });
+
+        let allowlist = Allowlist {
+            ports: vec![port],
+            ..Allowlist::new(["127.0.0.1"])
+        };
+        let _proxy = Proxy::spawn(allowlist, &socket).unwrap();
+
+        // As from within the container
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let relayed = listener.local_addr().unwrap();
+        relay(listener, socket.clone());
+
+        let mut client = TcpStream::connect(relayed).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        write!(client, "CONNECT 127.0.0.1:{port} HTTP/1.1\r\n\r\n").unwrap();
+
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "HTTP/1.1 200 Connection established\r\n");
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "\r\n");
+
+        client.write_all(b"hello\n").unwrap();
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "hello\n");
+    }
+
+    #[test]
+    fn stop_once_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join(SOCKET);
+
+        drop(Proxy::spawn(Allowlist::new(["example.com"]), &socket).unwrap());
+        assert!(!socket.exists());
+        assert!(UnixStream::connect(&socket).is_err());
+
+        // A stale socket is replaced
+        fs::write(&socket, "").unwrap();
+        let _proxy = Proxy::spawn(Allowlist::new(["example.com"]), &socket).unwrap();
+        assert!(UnixStream::connect(&socket).is_ok());
+    }
+}
@@ -8,9 +8,9 @@ use chrono::{Datelike, Utc};
 use itertools::Itertools;
 use licenses::match_licences;
 use moss::{Dependency, util};
+use stone_recipe::upstream::{Kind, SourceUri};
 use thiserror::Error;
 use tui::Styled;
-use url::Url;
 
 use crate::Env;
 
@@ -22,11 +22,12 @@ mod build;
 mod licenses;
 mod metadata;
 mod monitoring;
+mod registry;
 pub mod upstream;
 
 pub struct Drafter {
     env: Env,
-    upstreams: Vec<Url>,
+    upstreams: Vec<SourceUri>,
 }
 
 pub struct Draft {
@@ -35,7 +36,7 @@ pub struct Draft {
 }
 
 impl Drafter {
-    pub fn new(env: Env, upstreams: Vec<Url>) -> Self {
+    pub fn new(env: Env, upstreams: Vec<SourceUri>) -> Self {
         Self { env, upstreams }
     }
 
@@ -43,11 +44,41 @@ impl Drafter {
         let temp_dir = tempfile::tempdir()?;
         let extract_root = temp_dir.as_ref();
 
-        // Fetch and extract all upstreams
-        let extracted = upstream::fetch_and_extract(&self.env, &self.upstreams, extract_root)?;
+        // Resolve registry pages to the archives of their releases
+        let (upstreams, releases) = resolve_releases(&registry::Remote, &self.upstreams)?;
 
-        // Build metadata from extracted upstreams
-        let metadata = Metadata::new(extracted);
+        // Fetch and extract all upstreams
+        let mut extracted = upstream::fetch_and_extract(&self.env, &upstreams, extract_root)?;
+        // Fetched out of order, so restored to that of the recipe & matched to their releases by URI
+        extracted.sort_by_key(|upstream| upstreams.iter().position(|source| source.url == upstream.uri));
+
+        for upstream in &extracted {
+            if let Some(expected) = releases
+                .iter()
+                .flatten()
+                .find(|release| release.uri == upstream.uri)
+                .and_then(|release| release.sha256.as_ref())
+                && *expected != upstream.hash
+            {
+                return Err(Error::Checksum {
+                    uri: upstream.uri.to_string(),
+                    expected: expected.clone(),
+                    actual: upstream.hash.clone(),
+                });
+            }
+        }
+
+        // Build metadata from extracted upstreams, or the release of the first
+        let mut metadata = Metadata::new(extracted);
+        let release = releases.into_iter().next().flatten();
+        if let Some(release) = &release {
+            metadata.source = metadata::Source {
+                name: release.name.clone(),
+                version: release.version.clone(),
+                homepage: release.homepage.clone(),
+                uri: release.uri.to_string(),
+            };
+        }
 
         let monitoring = Monitoring::new(&metadata.source.name, &metadata.source.homepage);
         let monitoring_result = monitoring.run()?;
@@ -65,7 +96,11 @@ impl Drafter {
 
         let year = Utc::now().year();
 
-        let licenses = format_licenses(match_licences(extract_root, licences_dir).unwrap_or_default());
+        let licenses = match release.as_ref().map(|release| &release.licenses) {
+            Some(licenses) if !licenses.is_empty() => format_licenses(licenses.clone()),
+            _ => format_licenses(match_licences(extract_root, licences_dir).unwrap_or_default()),
+        };
+        let summary = release.and_then(|release| release.summary);
 
         // Remove temp extract dir
         drop(temp_dir);
@@ -97,15 +132,17 @@ release     : 1
 homepage    : {}
 upstreams   :
 {}
-summary     : UPDATE SUMMARY
+summary     : {}
 description : |
-    UPDATE DESCRIPTION
+    {}
 license     : {licenses}
 {options}{builddeps}{environment}{phases}",
             metadata.source.name,
             metadata.source.version,
             metadata.source.homepage,
             metadata.upstreams(),
+            summary.as_deref().map(quoted).unwrap_or_else(|| "UPDATE SUMMARY".to_owned()),
+            summary.as_deref().unwrap_or("UPDATE DESCRIPTION"),
         );
 
         Ok(Draft {
@@ -115,6 +152,41 @@ license     : {licenses}
     }
 }
 
+/// The `upstreams` with registry pages replaced by the archives of their releases, and those releases
+fn resolve_releases(
+    api: &impl registry::Api,
+    upstreams: &[SourceUri],
+) -> Result<(Vec<SourceUri>, Vec<Option<registry::Release>>), Error> {
+    let mut resolved = upstreams.to_vec();
+    let mut releases = vec![];
+
+    for upstream in &mut resolved {
+        let release = match upstream.kind {
+            Kind::Archive => registry::resolve(api, &upstream.url)?,
+            Kind::Git | Kind::Vcs(_) => None,
+        };
+
+        if let Some(release) = &release {
+            println!(
+                "{} | Found {} {} on {}",
+                "Registry".green(),
+                release.name,
+                release.version,
+                release.registry
+            );
+            upstream.url = release.uri.clone();
+        }
+        releases.push(release);
+    }
+
+    Ok((resolved, releases))
+}
+
+/// `text` as a double quoted YAML scalar, which JSON strings are
+fn quoted(text: &str) -> String {
+    serde_json::to_string(text).expect("strings serialize")
+}
+
 fn builddeps(deps: impl IntoIterator<Item = Dependency>) -> String {
     let deps = deps.into_iter().map(|dep| format!("    - {dep}")).sorted().join("\n");
 
@@ -178,6 +250,14 @@ pub enum Error {
     Monitoring(#[from] monitoring::Error),
     #[error("licensing")]
     Licenses(#[from] licenses::Error),
+    #[error("registry")]
+    Registry(#[from] registry::Error),
+    #[error("{uri} has sha256 {actual}, but the registry published {expected}")]
+    Checksum {
+        uri: String,
+        expected: String,
+        actual: String,
+    },
     #[error("io")]
     Io(#[from] io::Error),
     #[error("walkdir")]
@@ -188,6 +268,8 @@ pub enum Error {
 mod test {
     use std::path::Path;
 
+    use url::Url;
+
     use super::*;
 
     #[test]
@@ -201,4 +283,45 @@ mod test {
 
         assert_eq!(file.depth(), 0);
     }
+
+    #[test]
+    fn resolve_registry_pages() {
+        /// Only crates.io, as recorded
+        struct Crates;
+
+        impl registry::Api for Crates {
+            fn get(&self, url: &Url) -> Result<serde_json::Value, registry::Error> {
+                assert_eq!(url.as_str(), "https://crates.io/api/v1/crates/ripgrep");
+                Ok(serde_json::from_str(include_str!("../../test/draft/crates-io-ripgrep.json")).unwrap())
+            }
+        }
+
+        let upstreams = [
+            "https://crates.io/crates/ripgrep",
+            "git|https://github.com/BurntSushi/ripgrep.git",
+            "https://www.nano-editor.org/dist/v8/nano-8.7.tar.xz",
+        ]
+        .map(|uri| uri.parse::<SourceUri>().unwrap());
+
+        let (resolved, releases) = resolve_releases(&Crates, &upstreams).unwrap();
+        assert_eq!(
+            resolved[0].url.as_str(),
+            "https://static.crates.io/crates/ripgrep/ripgrep-14.1.1.crate"
+        );
+        assert_eq!(resolved[0].kind, Kind::Archive);
+        assert_eq!(resolved[1..], upstreams[1..]);
+        assert_eq!(
+            releases
+                .iter()
+                .map(|release| release.as_ref().map(|release| release.registry))
+                .collect::<Vec<_>>(),
+            [Some("crates.io"), None, None]
+        );
+    }
+
+    #[test]
+    fn quote_summary() {
+        assert_eq!(quoted("GNU Text Editor"), r#""GNU Text Editor""#);
+        assert_eq!(quoted(r#"A "find": fast"#), r#""A \"find\": fast""#);
+    }
 }
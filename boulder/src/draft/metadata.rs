@@ -2,10 +2,12 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use itertools::Itertools;
+use stone_recipe::upstream::{GIT_PREFIX, Kind};
 
 use super::Upstream;
 
 mod basic;
+mod git;
 mod github;
 mod gitlab;
 mod metacpan;
@@ -33,6 +35,8 @@ impl Metadata {
         if let Some(upstream) = upstreams.first() {
             for matcher in Matcher::ALL {
                 if let Some(matched) = match matcher {
+                    Matcher::Git if upstream.kind == Kind::Git => git::source(&upstream.uri),
+                    Matcher::Git => None,
                     Matcher::Basic => basic::source(&upstream.uri),
                     Matcher::Github => github::source(&upstream.uri),
                     Matcher::Gitlab => gitlab::source(&upstream.uri),
@@ -52,13 +56,18 @@ impl Metadata {
         self.upstreams
             .iter()
             .enumerate()
-            .map(|(i, Upstream { uri, hash })| {
+            .map(|(i, Upstream { uri, hash, kind })| {
                 let uri_to_use = if i == 0 && !self.source.uri.is_empty() {
                     &self.source.uri
                 } else {
                     uri.as_str()
                 };
-                format!("    - {uri_to_use} : {hash}")
+                let prefix = match kind {
+                    Kind::Archive => "",
+                    Kind::Git => GIT_PREFIX,
+                    Kind::Vcs(vcs) => vcs.prefix(),
+                };
+                format!("    - {prefix}{uri_to_use} : {hash}")
             })
             .join("\n")
     }
@@ -66,6 +75,7 @@ impl Metadata {
 
 enum Matcher {
     Basic,
+    Git,
     Gitlab,
     Github,
     Pypi,
@@ -73,5 +83,12 @@ enum Matcher {
 }
 
 impl Matcher {
-    const ALL: &'static [Self] = &[Self::Github, Self::Gitlab, Self::Pypi, Self::Metacpan, Self::Basic];
+    const ALL: &'static [Self] = &[
+        Self::Git,
+        Self::Github,
+        Self::Gitlab,
+        Self::Pypi,
+        Self::Metacpan,
+        Self::Basic,
+    ];
 }
@@ -0,0 +1,52 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+use moss::util;
+use url::Url;
+
+use super::Source;
+
+/// Source of a Git repository, named after it
+///
+/// The version is left for the packager to fill in, as the
+/// default branch rarely matches a release.
+pub fn source(upstream: &Url) -> Option<Source> {
+    let name = util::uri_file_name(upstream).trim_end_matches(".git");
+
+    if name.is_empty() {
+        return None;
+    }
+
+    let homepage = upstream.as_str().trim_end_matches(".git");
+
+    Some(Source {
+        name: name.to_owned(),
+        version: String::new(),
+        homepage: homepage.to_owned(),
+        uri: upstream.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_git_url() {
+        let url_str = "https://github.com/AerynOS/os-tools.git";
+        let url = Url::parse(url_str).unwrap();
+
+        let source = source(&url).unwrap();
+        assert_eq!(source.name, "os-tools");
+        assert_eq!(source.version, "");
+        assert_eq!(source.homepage, "https://github.com/AerynOS/os-tools");
+        assert_eq!(source.uri, url_str);
+    }
+
+    #[test]
+    fn test_trailing_slash() {
+        let url = Url::parse("https://example.com/repos/").unwrap();
+
+        assert!(source(&url).is_none());
+    }
+}
@@ -0,0 +1,324 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Releases of packages published to registries
+//!
+//! Drafting from the page of a crate, Python package or GitHub project, such
+//! as `https://crates.io/crates/ripgrep`, looks up its latest release (or the
+//! version given by the URL) to find the archive to fetch, along with the
+//! summary and licenses the registry lists for it.
+
+use moss::{request, runtime};
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+use url::Url;
+
+mod crates_io;
+mod github;
+mod pypi;
+
+/// Where registry metadata is fetched from
+pub trait Api {
+    /// The JSON document at `url`
+    fn get(&self, url: &Url) -> Result<serde_json::Value, Error>;
+}
+
+/// [`Api`] queried over the network through the shared [`request`] client,
+/// whose user agent both crates.io & GitHub require
+pub struct Remote;
+
+impl Api for Remote {
+    fn get(&self, url: &Url) -> Result<serde_json::Value, Error> {
+        Ok(runtime::block_on(request::download_json(url.clone()))?)
+    }
+}
+
+/// A release of a package, drafted as the first upstream of a recipe
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Release {
+    /// Registry it was found on, i.e. `crates.io`
+    pub registry: &'static str,
+    /// Of the recipe, i.e. `python-requests`
+    pub name: String,
+    pub version: String,
+    pub homepage: String,
+    pub summary: Option<String>,
+    /// SPDX identifiers, empty when not listed by the registry
+    pub licenses: Vec<String>,
+    /// Of the archive, fetched & listed as the upstream
+    pub uri: Url,
+    /// Of the archive, as published by the registry
+    pub sha256: Option<String>,
+}
+
+/// The release of the package whose registry page is `url`, or `None` when it's
+/// no such page
+pub fn resolve(api: &impl Api, url: &Url) -> Result<Option<Release>, Error> {
+    if let Some(release) = crates_io::release(api, url)? {
+        return Ok(Some(release));
+    }
+    if let Some(release) = pypi::release(api, url)? {
+        return Ok(Some(release));
+    }
+    github::release(api, url)
+}
+
+/// The JSON document at `url`, deserialized
+fn get<T: DeserializeOwned>(api: &impl Api, url: &str) -> Result<T, Error> {
+    let document = api.get(&Url::parse(url).map_err(|_| Error::InvalidUrl(url.to_owned()))?)?;
+    serde_json::from_value(document).map_err(|source| Error::Decode {
+        url: url.to_owned(),
+        source,
+    })
+}
+
+/// The path segments of `url` if on `host`, ignoring trailing slashes
+fn segments<'a>(url: &'a Url, host: &str) -> Option<Vec<&'a str>> {
+    if url.host_str()? != host {
+        return None;
+    }
+    Some(url.path_segments()?.filter(|segment| !segment.is_empty()).collect())
+}
+
+/// Licenses of the SPDX `expression`, in the order listed
+///
+/// Older crates separate them with `/`. Expressions of anything but license
+/// identifiers, such as the full text of a license, list none.
+fn licenses(expression: &str) -> Vec<String> {
+    let mut licenses = vec![];
+
+    for term in expression
+        .split(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | '/'))
+        .filter(|term| !term.is_empty() && !matches!(*term, "OR" | "AND" | "WITH"))
+    {
+        if !term
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '+'))
+        {
+            return vec![];
+        }
+        if !licenses.iter().any(|license| license == term) {
+            licenses.push(term.to_owned());
+        }
+    }
+
+    licenses
+}
+
+/// `text` on a single line, unless empty
+fn summary(text: &str) -> Option<String> {
+    Some(text.split_whitespace().collect::<Vec<_>>().join(" ")).filter(|summary| !summary.is_empty())
+}
+
+/// `version` without a leading `v`, i.e. of the tag `v1.2.3`
+fn strip_v(version: &str) -> &str {
+    version
+        .strip_prefix('v')
+        .filter(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+        .unwrap_or(version)
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("request")]
+    Request(#[from] request::Error),
+    #[error("invalid url {0}")]
+    InvalidUrl(String),
+    #[error("decode {url}")]
+    Decode {
+        url: String,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("{registry} lists no version {version} of {name}")]
+    MissingVersion {
+        registry: &'static str,
+        name: String,
+        version: String,
+    },
+    #[error("{registry} lists no source archive of {name} {version}")]
+    MissingArchive {
+        registry: &'static str,
+        name: String,
+        version: String,
+    },
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// Responses recorded from the registries
+    struct Recorded(HashMap<&'static str, &'static str>);
+
+    impl Api for Recorded {
+        fn get(&self, url: &Url) -> Result<serde_json::Value, Error> {
+            let response = self
+                .0
+                .get(url.as_str())
+                .unwrap_or_else(|| panic!("no response recorded for {url}"));
+            Ok(serde_json::from_str(response).unwrap())
+        }
+    }
+
+    fn recorded() -> Recorded {
+        Recorded(HashMap::from([
+            (
+                "https://crates.io/api/v1/crates/ripgrep",
+                include_str!("../../../test/draft/crates-io-ripgrep.json"),
+            ),
+            (
+                "https://pypi.org/pypi/Flask-Login/json",
+                include_str!("../../../test/draft/pypi-flask-login.json"),
+            ),
+            (
+                "https://pypi.org/pypi/Flask-Login/0.6.2/json",
+                include_str!("../../../test/draft/pypi-flask-login-0.6.2.json"),
+            ),
+            (
+                "https://api.github.com/repos/sharkdp/fd",
+                include_str!("../../../test/draft/github-fd.json"),
+            ),
+            (
+                "https://api.github.com/repos/sharkdp/fd/releases/latest",
+                include_str!("../../../test/draft/github-fd-release.json"),
+            ),
+            (
+                "https://api.github.com/repos/sharkdp/fd/releases/tags/v9.0.0",
+                include_str!("../../../test/draft/github-fd-release-9.0.0.json"),
+            ),
+        ]))
+    }
+
+    fn resolve(url: &str) -> Result<Option<Release>, Error> {
+        super::resolve(&recorded(), &Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn crates_io() {
+        let release = resolve("https://crates.io/crates/ripgrep").unwrap().unwrap();
+        assert_eq!(
+            release,
+            Release {
+                registry: "crates.io",
+                name: "ripgrep".to_owned(),
+                version: "14.1.1".to_owned(),
+                homepage: "https://github.com/BurntSushi/ripgrep".to_owned(),
+                summary: Some(
+                    "ripgrep is a line-oriented search tool that recursively searches the current directory for a regex pattern while respecting gitignore rules."
+                        .to_owned()
+                ),
+                licenses: vec!["Unlicense".to_owned(), "MIT".to_owned()],
+                uri: Url::parse("https://static.crates.io/crates/ripgrep/ripgrep-14.1.1.crate").unwrap(),
+                sha256: Some("8c33b89d4b71d39d96221b2416801286348e277eaa40b8c72289367a9cf00748".to_owned()),
+            }
+        );
+
+        // Of the version given, with its own license
+        let release = resolve("https://crates.io/crates/ripgrep/0.10.0/").unwrap().unwrap();
+        assert_eq!(release.version, "0.10.0");
+        assert_eq!(release.licenses, ["Unlicense", "MIT"]);
+        assert_eq!(
+            release.uri.as_str(),
+            "https://static.crates.io/crates/ripgrep/ripgrep-0.10.0.crate"
+        );
+
+        assert!(matches!(
+            resolve("https://crates.io/crates/ripgrep/99.0.0"),
+            Err(Error::MissingVersion { .. })
+        ));
+        // Yanked releases aren't drafted
+        assert!(matches!(
+            resolve("https://crates.io/crates/ripgrep/14.1.0"),
+            Err(Error::MissingVersion { .. })
+        ));
+    }
+
+    #[test]
+    fn pypi() {
+        let release = resolve("https://pypi.org/project/Flask-Login/").unwrap().unwrap();
+        assert_eq!(
+            release,
+            Release {
+                registry: "PyPI",
+                name: "python-flask-login".to_owned(),
+                version: "0.6.3".to_owned(),
+                homepage: "https://pypi.org/project/Flask-Login".to_owned(),
+                summary: Some("User authentication and session management for Flask.".to_owned()),
+                licenses: vec!["MIT".to_owned()],
+                uri: Url::parse(
+                    "https://files.pythonhosted.org/packages/source/F/Flask-Login/Flask-Login-0.6.3.tar.gz"
+                )
+                .unwrap(),
+                sha256: Some("5e23d14a607ef12806c699590b89d0f0e0d67baeec599d75947bf9c147330333".to_owned()),
+            }
+        );
+
+        // The full text of a license isn't listed
+        let release = resolve("https://pypi.org/project/Flask-Login/0.6.2").unwrap().unwrap();
+        assert_eq!(release.version, "0.6.2");
+        assert_eq!(release.licenses, Vec::<String>::new());
+    }
+
+    #[test]
+    fn github() {
+        let latest = Release {
+            registry: "GitHub",
+            name: "fd".to_owned(),
+            version: "10.2.0".to_owned(),
+            homepage: "https://github.com/sharkdp/fd".to_owned(),
+            summary: Some("A simple, fast and user-friendly alternative to 'find'".to_owned()),
+            licenses: vec!["Apache-2.0".to_owned()],
+            uri: Url::parse("https://github.com/sharkdp/fd/archive/refs/tags/v10.2.0.tar.gz").unwrap(),
+            sha256: None,
+        };
+        assert_eq!(resolve("https://github.com/sharkdp/fd").unwrap(), Some(latest.clone()));
+        assert_eq!(
+            resolve("https://github.com/sharkdp/fd/releases/latest").unwrap(),
+            Some(latest)
+        );
+
+        // Source tarballs of the release are preferred over those of the tag
+        let release = resolve("https://github.com/sharkdp/fd/releases/tag/v9.0.0")
+            .unwrap()
+            .unwrap();
+        assert_eq!(release.version, "9.0.0");
+        assert_eq!(
+            release.uri.as_str(),
+            "https://github.com/sharkdp/fd/releases/download/v9.0.0/fd-v9.0.0.tar.xz"
+        );
+        assert_eq!(release.sha256, None);
+    }
+
+    #[test]
+    fn unresolved() {
+        for url in [
+            "https://github.com/sharkdp/fd/archive/refs/tags/v10.2.0.tar.gz",
+            "https://github.com/sharkdp/fd/releases/download/v9.0.0/fd-v9.0.0.tar.xz",
+            "https://files.pythonhosted.org/packages/source/F/Flask-Login/Flask-Login-0.6.3.tar.gz",
+            "https://static.crates.io/crates/ripgrep/ripgrep-14.1.1.crate",
+            "https://www.nano-editor.org/dist/v8/nano-8.7.tar.xz",
+        ] {
+            assert_eq!(resolve(url).unwrap(), None, "{url}");
+        }
+    }
+
+    #[test]
+    fn spdx_licenses() {
+        assert_eq!(licenses("MIT"), ["MIT"]);
+        assert_eq!(licenses("MIT OR Apache-2.0"), ["MIT", "Apache-2.0"]);
+        assert_eq!(licenses("MIT/Apache-2.0"), ["MIT", "Apache-2.0"]);
+        assert_eq!(
+            licenses("(MIT OR Apache-2.0) AND Unicode-DFS-2016"),
+            ["MIT", "Apache-2.0", "Unicode-DFS-2016"]
+        );
+        assert_eq!(licenses("GPL-2.0-or-later WITH Classpath-exception-2.0").len(), 2);
+        assert_eq!(
+            licenses("Permission is hereby granted, free of charge"),
+            Vec::<String>::new()
+        );
+        assert_eq!(licenses(""), Vec::<String>::new());
+    }
+}
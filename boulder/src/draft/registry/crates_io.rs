@@ -0,0 +1,80 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+use serde::Deserialize;
+use url::Url;
+
+use super::{Api, Error, Release, get, licenses, segments, summary};
+
+const REGISTRY: &str = "crates.io";
+
+#[derive(Deserialize)]
+struct Response {
+    #[serde(rename = "crate")]
+    krate: Crate,
+    versions: Vec<Version>,
+}
+
+#[derive(Deserialize)]
+struct Crate {
+    name: String,
+    max_stable_version: Option<String>,
+    max_version: String,
+    description: Option<String>,
+    homepage: Option<String>,
+    repository: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Version {
+    num: String,
+    license: Option<String>,
+    checksum: String,
+    yanked: bool,
+}
+
+/// The release of the crate at `https://crates.io/crates/<name>[/<version>]`
+pub fn release(api: &impl Api, url: &Url) -> Result<Option<Release>, Error> {
+    let Some((name, version)) = segments(url, REGISTRY).and_then(|segments| match segments[..] {
+        ["crates", name] => Some((name, None)),
+        ["crates", name, version] => Some((name, Some(version))),
+        _ => None,
+    }) else {
+        return Ok(None);
+    };
+
+    let Response { krate, versions } = get(api, &format!("https://crates.io/api/v1/crates/{name}"))?;
+
+    let version = version
+        .or(krate.max_stable_version.as_deref())
+        .unwrap_or(&krate.max_version);
+    let Some(published) = versions
+        .iter()
+        .find(|published| published.num == version && !published.yanked)
+    else {
+        return Err(Error::MissingVersion {
+            registry: REGISTRY,
+            name: krate.name,
+            version: version.to_owned(),
+        });
+    };
+
+    let uri = format!(
+        "https://static.crates.io/crates/{0}/{0}-{1}.crate",
+        krate.name, published.num
+    );
+
+    Ok(Some(Release {
+        registry: REGISTRY,
+        homepage: krate
+            .homepage
+            .or(krate.repository)
+            .unwrap_or_else(|| format!("https://crates.io/crates/{}", krate.name)),
+        summary: krate.description.as_deref().and_then(summary),
+        licenses: published.license.as_deref().map(licenses).unwrap_or_default(),
+        uri: Url::parse(&uri).map_err(|_| Error::InvalidUrl(uri))?,
+        sha256: Some(published.checksum.clone()),
+        version: published.num.clone(),
+        name: krate.name,
+    }))
+}
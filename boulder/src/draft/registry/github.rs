@@ -0,0 +1,92 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+use serde::Deserialize;
+use url::Url;
+
+use super::{Api, Error, Release, get, segments, strip_v, summary};
+
+/// Of release assets, in order of preference
+const TARBALLS: &[&str] = &[".tar.xz", ".tar.zst", ".tar.bz2", ".tar.gz", ".tgz"];
+
+#[derive(Deserialize)]
+struct Repository {
+    name: String,
+    html_url: String,
+    description: Option<String>,
+    license: Option<License>,
+}
+
+#[derive(Deserialize)]
+struct License {
+    spdx_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// The release of the project at `https://github.com/<owner>/<repo>`, its
+/// `/releases` or `/releases/latest`, or `/releases/tag/<tag>`
+pub fn release(api: &impl Api, url: &Url) -> Result<Option<Release>, Error> {
+    let Some((owner, repo, tag)) = segments(url, "github.com").and_then(|segments| match segments[..] {
+        [owner, repo] | [owner, repo, "releases"] | [owner, repo, "releases", "latest"] => Some((owner, repo, None)),
+        [owner, repo, "releases", "tag", tag] => Some((owner, repo, Some(tag))),
+        _ => None,
+    }) else {
+        return Ok(None);
+    };
+
+    let api_url = format!("https://api.github.com/repos/{owner}/{repo}");
+    let repository: Repository = get(api, &api_url)?;
+    let release: Response = match tag {
+        Some(tag) => get(api, &format!("{api_url}/releases/tags/{tag}"))?,
+        None => get(api, &format!("{api_url}/releases/latest"))?,
+    };
+
+    let version = strip_v(&release.tag_name);
+
+    // Source tarballs uploaded to the release include generated files the tag lacks, i.e.
+    // `configure`, while others named after a target, i.e. `x86_64-unknown-linux-gnu`, are builds
+    let sources = [
+        format!("{}-{}", repository.name, release.tag_name),
+        format!("{}-{version}", repository.name),
+    ];
+    let uri = TARBALLS
+        .iter()
+        .find_map(|extension| {
+            release.assets.iter().find(|asset| {
+                asset
+                    .name
+                    .strip_suffix(extension)
+                    .is_some_and(|stem| sources.iter().any(|source| source == stem))
+            })
+        })
+        .map(|asset| asset.browser_download_url.clone())
+        .unwrap_or_else(|| format!("{}/archive/refs/tags/{}.tar.gz", repository.html_url, release.tag_name));
+
+    Ok(Some(Release {
+        registry: "GitHub",
+        name: repository.name.to_lowercase(),
+        version: version.to_owned(),
+        homepage: repository.html_url,
+        summary: repository.description.as_deref().and_then(summary),
+        licenses: repository
+            .license
+            .and_then(|license| license.spdx_id)
+            .filter(|id| id != "NOASSERTION")
+            .into_iter()
+            .collect(),
+        uri: Url::parse(&uri).map_err(|_| Error::InvalidUrl(uri))?,
+        // Not published
+        sha256: None,
+    }))
+}
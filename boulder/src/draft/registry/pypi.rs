@@ -0,0 +1,98 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+use serde::Deserialize;
+use url::Url;
+
+use super::{Api, Error, Release, get, licenses, segments, summary};
+
+const REGISTRY: &str = "PyPI";
+
+#[derive(Deserialize)]
+struct Response {
+    info: Info,
+    urls: Vec<File>,
+}
+
+#[derive(Deserialize)]
+struct Info {
+    name: String,
+    version: String,
+    summary: Option<String>,
+    /// Of PEP 639, listing SPDX identifiers
+    license_expression: Option<String>,
+    /// Free form, sometimes the full text of the license
+    license: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct File {
+    filename: String,
+    packagetype: String,
+    digests: Digests,
+}
+
+#[derive(Deserialize)]
+struct Digests {
+    sha256: String,
+}
+
+/// The release of the package at `https://pypi.org/project/<name>[/<version>]`
+pub fn release(api: &impl Api, url: &Url) -> Result<Option<Release>, Error> {
+    let Some((name, version)) = segments(url, "pypi.org").and_then(|segments| match segments[..] {
+        ["project", name] => Some((name, None)),
+        ["project", name, version] => Some((name, Some(version))),
+        _ => None,
+    }) else {
+        return Ok(None);
+    };
+
+    let Response { info, urls } = match version {
+        Some(version) => get(api, &format!("https://pypi.org/pypi/{name}/{version}/json"))?,
+        None => get(api, &format!("https://pypi.org/pypi/{name}/json"))?,
+    };
+
+    let Some(sdist) = urls.iter().find(|file| file.packagetype == "sdist") else {
+        return Err(Error::MissingArchive {
+            registry: REGISTRY,
+            name: info.name,
+            version: info.version,
+        });
+    };
+
+    // Of the same form as those drafted from a download URL
+    let first_char = info.name.chars().next().unwrap_or_default();
+    let uri = format!(
+        "https://files.pythonhosted.org/packages/source/{first_char}/{}/{}",
+        info.name, sdist.filename
+    );
+    // Normalized as by PEP 503
+    let normalized = info
+        .name
+        .to_lowercase()
+        .split(['-', '_', '.'])
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    let name = if normalized.starts_with("python-") {
+        normalized
+    } else {
+        format!("python-{normalized}")
+    };
+
+    Ok(Some(Release {
+        registry: REGISTRY,
+        name,
+        homepage: format!("https://pypi.org/project/{}", info.name),
+        summary: info.summary.as_deref().and_then(summary),
+        licenses: info
+            .license_expression
+            .or(info.license)
+            .as_deref()
+            .map(licenses)
+            .unwrap_or_default(),
+        uri: Url::parse(&uri).map_err(|_| Error::InvalidUrl(uri))?,
+        sha256: Some(sdist.digests.sha256.clone()),
+        version: info.version,
+    }))
+}
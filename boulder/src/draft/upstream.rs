@@ -12,28 +12,37 @@ use fs_err::tokio::{self as fs};
 use futures_util::{StreamExt, TryStreamExt, stream};
 use moss::{environment, request, runtime, util};
 use sha2::{Digest, Sha256};
+use stone_recipe::upstream::{Kind, SourceUri};
 use tempfile::NamedTempFile;
 use thiserror::Error;
 use tokio::process::Command;
 use tui::{MultiProgress, ProgressBar, ProgressStyle, Styled};
 use url::Url;
 
-use crate::Env;
+use crate::{
+    Env,
+    upstream::{
+        git::{self, Git},
+        vcs::{self, Vcs},
+    },
+};
 
 pub struct Upstream {
     pub uri: Url,
+    /// Hash of the archive, or commit of the repository
     pub hash: String,
+    pub kind: Kind,
 }
 
 /// Fetch and extract the provided upstreams under `extract_root`
-pub fn fetch_and_extract(env: &Env, upstreams: &[Url], extract_root: &Path) -> Result<Vec<Upstream>, Error> {
+///
+/// Repositories are cloned at the latest commit of their default branch
+pub fn fetch_and_extract(env: &Env, upstreams: &[SourceUri], extract_root: &Path) -> Result<Vec<Upstream>, Error> {
     let mpb = MultiProgress::new();
 
     let ret = runtime::block_on(
         stream::iter(upstreams)
-            .map(|uri| async {
-                let temp_path = NamedTempFile::with_prefix("boulder-")?.into_temp_path();
-
+            .map(|SourceUri { kind, url: uri }| async {
                 let pb = mpb.add(
                     ProgressBar::new_spinner()
                         .with_style(
@@ -45,30 +54,29 @@ pub fn fetch_and_extract(env: &Env, upstreams: &[Url], extract_root: &Path) -> R
                 );
                 pb.enable_steady_tick(Duration::from_millis(150));
 
-                let hash = request::download_with_sha256(uri.clone(), &temp_path).await?;
-
-                // Hardlink or copy fetched asset to cache dir so we don't need
-                // to refetch it when the user finally builds this new recipe
-                {
-                    let cache_path = fetched_upstream_cache_path(env, uri, &hash);
+                let hash = match kind {
+                    Kind::Archive => fetch_archive(env, uri, extract_root, &pb).await?,
+                    Kind::Git => {
+                        let name = util::uri_file_name(uri).trim_end_matches(".git");
+                        let dest_dir = extract_root.join(if name.is_empty() { "source" } else { name });
 
-                    if let Some(parent) = cache_path.parent() {
-                        fs::create_dir_all(parent).await?;
+                        Git::fetch_new(uri, &dest_dir, &pb).await?.commit
                     }
+                    Kind::Vcs(vcs) => {
+                        let name = util::uri_file_name(uri);
+                        let dest_dir = extract_root.join(if name.is_empty() { "source" } else { name });
 
-                    util::async_hardlink_or_copy(&temp_path, &cache_path).await?;
-                }
-
-                pb.set_message(format!("{} {}", "Extracting".yellow(), *uri));
-
-                extract(&temp_path, extract_root).await?;
-
-                // Cleanup temp path
-                drop(temp_path);
+                        Vcs::fetch_new(*vcs, uri, &dest_dir).await?.revision.to_string()
+                    }
+                };
 
                 pb.suspend(|| println!("{} {}", "Fetched".green(), *uri));
 
-                Ok(Upstream { uri: uri.clone(), hash })
+                Ok(Upstream {
+                    uri: uri.clone(),
+                    hash,
+                    kind: kind.clone(),
+                })
             })
             .buffer_unordered(environment::MAX_NETWORK_CONCURRENCY)
             .try_collect(),
@@ -79,6 +87,34 @@ pub fn fetch_and_extract(env: &Env, upstreams: &[Url], extract_root: &Path) -> R
     ret
 }
 
+/// Fetch and extract the archive at `uri`, returning its hash
+async fn fetch_archive(env: &Env, uri: &Url, extract_root: &Path, pb: &ProgressBar) -> Result<String, Error> {
+    let temp_path = NamedTempFile::with_prefix("boulder-")?.into_temp_path();
+
+    let hash = request::download_with_sha256(uri.clone(), &temp_path).await?;
+
+    // Hardlink or copy fetched asset to cache dir so we don't need
+    // to refetch it when the user finally builds this new recipe
+    {
+        let cache_path = fetched_upstream_cache_path(env, uri, &hash);
+
+        if let Some(parent) = cache_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        util::async_hardlink_or_copy(&temp_path, &cache_path).await?;
+    }
+
+    pb.set_message(format!("{} {}", "Extracting".yellow(), *uri));
+
+    extract(&temp_path, extract_root).await?;
+
+    // Cleanup temp path
+    drop(temp_path);
+
+    Ok(hash)
+}
+
 async fn extract(archive: &Path, destination: &Path) -> Result<(), Error> {
     let result = Command::new("bsdtar")
         .arg("xf")
@@ -122,4 +158,8 @@ pub enum Error {
     Request(#[from] request::Error),
     #[error("extract failed with code {0}")]
     Extract(ExitStatus),
+    #[error("git")]
+    Git(#[from] git::Error),
+    #[error("vcs")]
+    Vcs(#[from] vcs::Error),
 }
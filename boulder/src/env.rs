@@ -10,6 +10,7 @@ use moss::util;
 use nix::NixPath;
 use thiserror::Error;
 
+#[derive(Clone)]
 pub struct Env {
     pub cache_dir: PathBuf,
     pub data_dir: PathBuf,
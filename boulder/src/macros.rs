@@ -6,9 +6,12 @@ use std::{io, path::Path};
 
 use fs_err as fs;
 use moss::util;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use stone_recipe::{script, tuning};
 use thiserror::Error;
 
-use crate::Env;
+use crate::{Env, architecture::BuildTarget};
 
 #[derive(Debug)]
 pub struct Macros {
@@ -50,10 +53,143 @@ impl Macros {
 
         Ok(Self { arch, actions })
     }
+
+    /// Digest of every macro file of the data dir, changing with the macros recipes are built with
+    pub fn version(env: &Env) -> Result<String, Error> {
+        let macros_dir = env.data_dir.join("macros");
+
+        let mut files = util::enumerate_files(&macros_dir, |p: &Path| {
+            p.extension().and_then(|s| s.to_str()) == Some("yaml")
+        })?;
+        files.sort();
+
+        let mut hasher = Sha256::new();
+        for file in files {
+            let relative = file.strip_prefix(&macros_dir).unwrap_or_else(|_| unreachable!());
+            let bytes = fs::read(&file)?;
+
+            for field in [relative.as_os_str().as_encoded_bytes(), &bytes] {
+                hasher.update((field.len() as u64).to_le_bytes());
+                hasher.update(field);
+            }
+        }
+
+        Ok(hex::encode(hasher.finalize()))
+    }
+
+    /// A parser with the macros of `target`, its arch overriding the base
+    pub fn parser(&self, target: BuildTarget) -> Result<script::Parser, Error> {
+        let mut parser = script::Parser::new();
+
+        for arch in ["base", &target.to_string()] {
+            let macros = self
+                .arch
+                .get(arch)
+                .cloned()
+                .ok_or_else(|| Error::MissingArch(arch.to_owned()))?;
+
+            parser.add_macros(macros);
+        }
+
+        for macros in self.actions.clone() {
+            parser.add_macros(macros);
+        }
+
+        Ok(parser)
+    }
+
+    /// The tuning groups & flags of `target`, none enabled yet
+    pub fn tuning(&self, target: BuildTarget) -> Result<tuning::Builder, Error> {
+        let mut tuning = tuning::Builder::new();
+
+        for arch in ["base", &target.to_string()] {
+            let macros = self
+                .arch
+                .get(arch)
+                .cloned()
+                .ok_or_else(|| Error::MissingArch(arch.to_owned()))?;
+
+            tuning.add_macros(macros);
+        }
+
+        for macros in self.actions.clone() {
+            tuning.add_macros(macros);
+        }
+
+        Ok(tuning)
+    }
+
+    /// Tuning groups enabled unless a recipe says otherwise, the arch of `target` overriding the base
+    pub fn default_tuning_groups(&self, target: BuildTarget) -> &[String] {
+        let build_target = target.to_string();
+
+        for arch in [build_target.as_str(), "base"] {
+            let Some(arch_macros) = self.arch.get(arch) else {
+                continue;
+            };
+
+            if arch_macros.default_tuning_groups.is_empty() {
+                continue;
+            }
+
+            return &arch_macros.default_tuning_groups;
+        }
+
+        &[]
+    }
+}
+
+/// A macro usable by recipe scripts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Entry {
+    /// As used by scripts, i.e. `%cmake` or `%(prefix)`
+    pub name: String,
+    pub kind: Kind,
+    pub description: Option<String>,
+    pub example: Option<String>,
+    /// Packages needed by the commands of an action
+    pub dependencies: Vec<String>,
+    /// What it expands to, with every nested macro expanded
+    pub expansion: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Action,
+    Definition,
+}
+
+/// Every action & definition of `parser`, as it expands
+pub fn entries(parser: &script::Parser) -> Result<Vec<Entry>, script::Error> {
+    let actions = parser.actions().map(|(identifier, action)| {
+        Ok(Entry {
+            name: format!("%{identifier}"),
+            kind: Kind::Action,
+            description: Some(action.description.trim().to_owned()).filter(|description| !description.is_empty()),
+            example: action.example.as_ref().map(|example| example.trim_end().to_owned()),
+            dependencies: action.dependencies.clone(),
+            expansion: parser.parse_content(&action.command)?,
+        })
+    });
+    let definitions = parser.definitions().map(|(identifier, definition)| {
+        Ok(Entry {
+            name: format!("%({identifier})"),
+            kind: Kind::Definition,
+            description: None,
+            example: None,
+            dependencies: vec![],
+            expansion: parser.parse_content(definition)?,
+        })
+    });
+
+    actions.chain(definitions).collect()
 }
 
 #[derive(Debug, Error)]
 pub enum Error {
+    #[error("no macros for arch {0}")]
+    MissingArch(String),
     #[error("loading macros from arch data dir")]
     ArchFiles(#[source] io::Error),
     #[error("loading macros from actions data dir")]
@@ -63,3 +199,69 @@ pub enum Error {
     #[error("io")]
     Io(#[from] io::Error),
 }
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{
+        architecture::Architecture,
+        build::job::{self, builtin_parser},
+    };
+
+    /// The macros bundled with boulder
+    fn bundled() -> Macros {
+        let dir = tempfile::tempdir().unwrap();
+        let data = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("data");
+        let env = Env::new(
+            Some(dir.path().join("cache")),
+            Some(dir.path().join("config")),
+            Some(data),
+            Some(dir.path().join("moss")),
+        )
+        .unwrap();
+
+        Macros::load(&env).unwrap()
+    }
+
+    fn find<'a>(entries: &'a [Entry], name: &str) -> &'a Entry {
+        entries.iter().find(|entry| entry.name == name).unwrap()
+    }
+
+    #[test]
+    fn bundled_entries() {
+        let macros = bundled();
+        let native = builtin_parser(BuildTarget::Native(Architecture::X86_64), &macros).unwrap();
+        let entries = entries(&native).unwrap();
+
+        let cmake = find(&entries, "%cmake");
+        assert_eq!(cmake.kind, Kind::Action);
+        assert!(cmake.description.as_deref().unwrap().starts_with("Perform cmake"));
+        assert!(cmake.expansion.starts_with("cmake "), "{}", cmake.expansion);
+        assert!(cmake.dependencies.contains(&"binary(cmake)".to_owned()));
+
+        let libdir = find(&entries, "%(libdir)");
+        assert_eq!(libdir.kind, Kind::Definition);
+        assert_eq!(libdir.expansion, "/usr/lib");
+
+        // Set by boulder, or left to the recipe
+        assert_eq!(find(&entries, "%(compiler_c)").expansion, "clang");
+        assert_eq!(find(&entries, "%(name)").expansion, "<name>");
+        assert!(!find(&entries, "%(cflags)").expansion.is_empty());
+
+        // Nothing is left to expand
+        for name in ["%cmake", "%(cflags)", "%(libdir)"] {
+            assert!(!find(&entries, name).expansion.contains("%("), "{name}");
+        }
+
+        // The arch of the target overrides the base
+        let emul32 = builtin_parser(BuildTarget::Emul32(Architecture::X86_64), &macros).unwrap();
+        assert_eq!(emul32.parse_content("%(libdir)").unwrap(), "/usr/lib32");
+
+        assert!(matches!(
+            builtin_parser(BuildTarget::Emul32(Architecture::Riscv64), &macros),
+            Err(job::Error::Macros(Error::MissingArch(arch))) if arch == "emul32/riscv64"
+        ));
+    }
+}
@@ -14,8 +14,12 @@ pub use self::recipe::Recipe;
 pub use self::timing::Timing;
 
 mod architecture;
+mod artifacts;
+mod batch;
 mod build;
+mod cache;
 mod cli;
+mod compiler_cache;
 mod container;
 mod draft;
 mod env;
@@ -25,6 +29,7 @@ mod paths;
 mod profile;
 mod recipe;
 mod timing;
+mod updates;
 mod upstream;
 
 fn main() {
@@ -1,24 +1,33 @@
 // SPDX-FileCopyrightText: 2024 AerynOS Developers
 // SPDX-License-Identifier: MPL-2.0
-use std::collections::{BTreeMap, btree_map};
-use std::{io, num::NonZeroU64};
+use std::collections::{BTreeMap, BTreeSet, btree_map};
+use std::{io, num::NonZeroU64, time::Instant};
 
 use fs_err as fs;
 use itertools::Itertools;
-use stone::StoneDigestWriterHasher;
 use thiserror::Error;
 
-use moss::util;
+use moss::{Provider, package::Provenance, util};
 use stone_recipe::{Package, script};
 
-use crate::{Macros, Paths, Recipe, Timing, build, container, timing};
+use crate::{
+    Macros, Paths, Recipe, Timing, architecture, build,
+    build::report::{BuildReport, Packaging},
+    container, profile, timing,
+    upstream::verify::Verification,
+};
 
 use self::collect::Collector;
 use self::emit::emit;
 
 mod analysis;
 mod collect;
+mod compressman;
+pub mod diff;
 mod emit;
+pub mod layout;
+pub mod sbom;
+pub mod unresolved;
 
 pub struct Packager<'a> {
     paths: &'a Paths,
@@ -26,15 +35,27 @@ pub struct Packager<'a> {
     packages: BTreeMap<String, Package>,
     collector: Collector,
     build_release: NonZeroU64,
+    /// Recorded in the meta of each stone, see [`build::inputs`]
+    input_hash: &'a str,
+    /// Recorded in the manifest, see [`crate::upstream::verify`]
+    verifications: &'a [Verification],
+    /// Recorded in the meta of each stone, see [`provenance`]
+    provenance: Provenance,
+    /// Packages emitted, rather than all of them, see [`Packager::restrict_to`]
+    only: Option<BTreeSet<String>>,
 }
 
 impl<'a> Packager<'a> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         paths: &'a Paths,
         recipe: &'a Recipe,
         macros: &'a Macros,
         targets: &'a [build::Target],
         build_release: NonZeroU64,
+        input_hash: &'a str,
+        verifications: &'a [Verification],
+        profile: &profile::Id,
     ) -> Result<Self, Error> {
         let mut collector = Collector::new(paths.install().guest);
 
@@ -55,28 +76,66 @@ impl<'a> Packager<'a> {
             collector,
             packages,
             build_release,
+            input_hash,
+            verifications,
+            provenance: provenance(recipe, profile),
+            only: None,
         })
     }
 
-    pub fn package(&self, timing: &mut Timing) -> Result<(), Error> {
-        // Hasher used for calculating file digests
-        let mut hasher = StoneDigestWriterHasher::new();
+    /// Only emit the packages named `only`, marking the build as partial
+    ///
+    /// Paths are still analyzed for every package, so each of those emitted
+    /// holds the same paths as it would when emitting all of them.
+    pub fn restrict_to(&mut self, only: &[String]) -> Result<(), Error> {
+        self.only = Some(restrict(&self.packages, only)?);
+        Ok(())
+    }
 
+    /// Emit the stones of the packages built, returning those emitted
+    ///
+    /// Paths are analyzed & stones emitted across the rayon pool, which
+    /// is recorded to `report` along with how long each took. So are the
+    /// runtime dependencies of the packages neither they nor those of the
+    /// repositories, providing those `available`, provide.
+    pub fn package(
+        &self,
+        timing: &mut Timing,
+        report: &mut BuildReport,
+        available: &BTreeSet<Provider>,
+    ) -> Result<Vec<sbom::Stone>, Error> {
+        let started = Instant::now();
         let timer = timing.begin(timing::Kind::Analyze);
 
+        if self.recipe.parsed.options.compressman {
+            let compressed = compressman::compress(&self.paths.install().guest).map_err(Error::CompressMan)?;
+            println!(
+                "│Compressed {} man & info pages, relinking {} aliases\n",
+                compressed.pages, compressed.links
+            );
+        }
+
+        let strays =
+            layout::check(&self.paths.install().guest, self.recipe, report.finished()).map_err(Error::Layout)?;
+        report.record_strays(strays);
+
         // Collect all paths under install root
-        let paths = self
-            .collector
-            .enumerate_paths(None, &mut hasher)
-            .map_err(Error::CollectPaths)?;
+        let paths = self.collector.enumerate_paths().map_err(Error::CollectPaths)?;
+        let num_paths = paths.len();
 
         // Process all paths with the analysis chain
         // This will determine which files get included
         // and what deps / provides they produce
-        let mut analysis = analysis::Chain::new(self.paths, self.recipe, &self.collector, &mut hasher);
+        let nostrip = self
+            .packages
+            .iter()
+            .map(|(name, package)| (name.clone(), package.nostrip.clone()))
+            .collect();
+        let mut analysis = analysis::Chain::new(self.paths, self.recipe, &self.collector, &nostrip);
         analysis.process(paths).map_err(Error::Analysis)?;
 
         timing.finish(timer);
+        let analyzed = Instant::now();
 
         let timer = timing.begin(timing::Kind::Emit);
 
@@ -88,6 +147,7 @@ impl<'a> Packager<'a> {
         let packages = self
             .packages
             .iter()
+            .filter(|(name, _)| self.only.as_ref().is_none_or(|only| only.contains(*name)))
             .filter_map(|(name, package)| {
                 let bucket = analysis.buckets.remove(name)?;
 
@@ -97,19 +157,72 @@ impl<'a> Packager<'a> {
                     package,
                     bucket,
                     self.build_release,
+                    self.input_hash,
+                    &self.provenance,
                 ))
             })
             .collect::<Vec<_>>();
 
         // Emit package stones and manifest files to artefact directory
-        emit(self.paths, self.recipe, &packages).map_err(Error::Emit)?;
+        emit(
+            self.paths,
+            self.recipe,
+            &packages,
+            self.verifications,
+            self.only.is_some(),
+        )
+        .map_err(Error::Emit)?;
 
         timing.finish(timer);
 
-        Ok(())
+        report.record_unresolved(unresolved::find(&packages, available));
+
+        report.record_packaging(Packaging {
+            paths: num_paths,
+            stones: packages.len(),
+            threads: rayon::current_num_threads(),
+            analysis: analyzed - started,
+            emit: analyzed.elapsed(),
+        });
+
+        Ok(packages
+            .iter()
+            .map(|package| sbom::Stone {
+                filename: package.filename(),
+                meta: package.meta(),
+            })
+            .collect())
+    }
+}
+
+/// Who builds the stones of `recipe` with `profile`, on this host
+///
+/// The time is that of the recipe, honoring `SOURCE_DATE_EPOCH`, so rebuilds are reproducible.
+pub fn provenance(recipe: &Recipe, profile: &profile::Id) -> Provenance {
+    Provenance {
+        builder: Some(format!("boulder {}", tools_buildinfo::get_full_version())),
+        host: Some(architecture::host().to_string()),
+        time: Some(recipe.build_time.timestamp().max(0) as u64),
+        profile: Some(profile.to_string()),
     }
 }
 
+/// The packages of `packages` named by `only`, failing for names it doesn't have
+fn restrict(packages: &BTreeMap<String, Package>, only: &[String]) -> Result<BTreeSet<String>, Error> {
+    only.iter()
+        .map(|name| {
+            if packages.contains_key(name) {
+                Ok(name.clone())
+            } else {
+                Err(Error::UnknownPackage {
+                    name: name.clone(),
+                    packages: packages.keys().join(", "),
+                })
+            }
+        })
+        .collect()
+}
+
 /// Resolve all package templates from the arch macros and
 /// incoming recipe. Package templates may have variables so
 /// they are fully expanded before returned.
@@ -152,6 +265,11 @@ fn resolve_packages(
             .map(|dep| parser.parse_content(&dep))
             .collect::<Result<_, _>>()?;
         package.run_deps_exclude = package.run_deps_exclude.into_iter().collect();
+        package.nostrip = package
+            .nostrip
+            .into_iter()
+            .map(|pattern| parser.parse_content(&pattern))
+            .collect::<Result<_, _>>()?;
         package.paths = package
             .paths
             .into_iter()
@@ -166,6 +284,7 @@ fn resolve_packages(
             collector.add_rule(collect::Rule {
                 pattern: path.path.clone(),
                 package: name.clone(),
+                retain: path.retain,
             });
         }
 
@@ -183,6 +302,7 @@ fn resolve_packages(
                     .chain(prev.run_deps_exclude)
                     .sorted()
                     .collect();
+                package.nostrip = package.nostrip.into_iter().chain(prev.nostrip).sorted().collect();
                 package.paths = package
                     .paths
                     .into_iter()
@@ -216,6 +336,23 @@ fn resolve_packages(
         .iter()
         .try_for_each(|entry| add_package(entry.key.clone(), entry.value.clone()))?;
 
+    // Paths of the main package never stripped aren't stripped from any sub-package either
+    if let Some(nostrip) = packages
+        .get(&recipe.parsed.source.name)
+        .map(|package| package.nostrip.clone())
+    {
+        for package in packages.values_mut() {
+            package.nostrip = package
+                .nostrip
+                .iter()
+                .chain(&nostrip)
+                .cloned()
+                .sorted()
+                .dedup()
+                .collect();
+        }
+    }
+
     Ok(packages)
 }
 
@@ -240,10 +377,351 @@ pub enum Error {
     Script(#[from] script::Error),
     #[error("collect install paths")]
     CollectPaths(#[source] collect::Error),
+    #[error("compress man & info pages")]
+    CompressMan(#[source] io::Error),
+    #[error("find paths outside /usr")]
+    Layout(#[source] io::Error),
     #[error("analyzing paths")]
     Analysis(#[source] analysis::BoxError),
     #[error("emit packages")]
     Emit(#[from] emit::Error),
     #[error("container")]
     Container(#[from] container::Error),
+    #[error("the recipe has no package {name}, only {packages}")]
+    UnknownPackage { name: String, packages: String },
+}
+
+#[cfg(test)]
+mod test {
+    use std::{os::unix::fs::symlink, path::Path};
+
+    use super::*;
+
+    const RECIPE: &str = "\
+name: nano
+version: 8.7
+release: 1
+homepage: https://nano-editor.org
+license: GPL-3.0-or-later
+summary: GNU Text Editor
+description: GNU Text Editor
+paths:
+    - /etc
+    - /usr
+packages:
+    - \"%(name)-devel\":
+        paths:
+            - /usr/include
+            - /usr/lib/cmake
+    - \"%(name)-docs\":
+        paths:
+            - /usr/share/doc
+";
+
+    const INPUT_HASH: &str = "cbf684b5a37a3a433e0526beb04a7b3419b71e81b3709c3b0d7ed6a1987d3dcb";
+
+    /// An install root of many files, some sharing content or linked
+    fn install_root(root: &Path) {
+        for dir in [
+            "usr/bin",
+            "usr/include/nano",
+            "usr/lib/cmake/nano",
+            "usr/share/doc/nano",
+            "etc",
+        ] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::create_dir_all(root.join("usr/share/nano/empty")).unwrap();
+
+        for i in 0..64 {
+            fs::write(
+                root.join(format!("usr/include/nano/header{i}.h")),
+                format!("#define N {i}\n"),
+            )
+            .unwrap();
+            fs::write(
+                root.join(format!("usr/share/doc/nano/page{i}.html")),
+                "duplicate\n".repeat(i % 4),
+            )
+            .unwrap();
+        }
+        fs::write(root.join("usr/bin/nano"), "#!/bin/sh\n").unwrap();
+        fs::hard_link(root.join("usr/bin/nano"), root.join("usr/bin/rnano")).unwrap();
+        symlink("nano", root.join("usr/bin/pico")).unwrap();
+        symlink("header0.h", root.join("usr/include/nano/nano.h")).unwrap();
+        fs::write(root.join("usr/lib/cmake/nano/NanoConfig.cmake"), "").unwrap();
+        fs::write(root.join("etc/nanorc"), "set linenumbers\n").unwrap();
+    }
+
+    /// Of the recipes packaged, as those outside git are otherwise built when loaded
+    const BUILD_TIME: i64 = 1_700_000_000;
+
+    /// The stones packaged from `dir` by a rayon pool of `threads`
+    fn package(dir: &Path, threads: usize) -> (BTreeMap<String, Vec<u8>>, serde_json::Value) {
+        package_only(dir, threads, None)
+    }
+
+    /// The stones packaged from `dir`, only of the packages named `only` if given
+    fn package_only(
+        dir: &Path,
+        threads: usize,
+        only: Option<&[String]>,
+    ) -> (BTreeMap<String, Vec<u8>>, serde_json::Value) {
+        let mut recipe = Recipe::load(dir.join("stone.yaml")).unwrap();
+        recipe.build_time = chrono::DateTime::from_timestamp(BUILD_TIME, 0).unwrap();
+        let paths = Paths::new(&recipe, None, dir, dir, dir).unwrap();
+        fs::create_dir_all(paths.artefacts().guest).unwrap();
+        let macros = Macros {
+            arch: BTreeMap::new(),
+            actions: vec![],
+        };
+
+        let mut packager = Packager::new(
+            &paths,
+            &recipe,
+            &macros,
+            &[],
+            NonZeroU64::MIN,
+            INPUT_HASH,
+            &[],
+            &profile::Id::new("default-x86_64"),
+        )
+        .unwrap();
+        if let Some(only) = only {
+            packager.restrict_to(only).unwrap();
+        }
+        let mut report = BuildReport::default();
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        let stones = pool
+            .install(|| packager.package(&mut Timing::default(), &mut report, &BTreeSet::new()))
+            .unwrap();
+
+        let stones = stones
+            .into_iter()
+            .map(|stone| {
+                let path = paths.artefacts().guest.join(&stone.filename);
+                assert_eq!(build::inputs::stone_hash(&path).as_deref(), Some(INPUT_HASH));
+                (stone.filename, fs::read(path).unwrap())
+            })
+            .collect();
+
+        let report = serde_json::from_str::<serde_json::Value>(&report.to_json().unwrap()).unwrap();
+        (stones, report["packaging"].clone())
+    }
+
+    #[test]
+    fn nostrip_carried_to_sub_packages() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("stone.yaml"),
+            format!("{RECIPE}nostrip:\n    - /usr/bin/nano\n").replace(
+                "            - /usr/share/doc\n",
+                "            - /usr/share/doc\n        nostrip:\n            - /usr/share/doc/%(name)/*.so\n",
+            ),
+        )
+        .unwrap();
+        let recipe = Recipe::load(dir.path().join("stone.yaml")).unwrap();
+        let paths = Paths::new(&recipe, None, dir.path(), dir.path(), dir.path()).unwrap();
+        let macros = Macros {
+            arch: BTreeMap::new(),
+            actions: vec![],
+        };
+
+        let packager = Packager::new(
+            &paths,
+            &recipe,
+            &macros,
+            &[],
+            NonZeroU64::MIN,
+            INPUT_HASH,
+            &[],
+            &profile::Id::new("default-x86_64"),
+        )
+        .unwrap();
+
+        assert_eq!(packager.packages["nano"].nostrip, ["/usr/bin/nano"]);
+        assert_eq!(packager.packages["nano-devel"].nostrip, ["/usr/bin/nano"]);
+        assert_eq!(
+            packager.packages["nano-docs"].nostrip,
+            ["/usr/bin/nano", "/usr/share/doc/nano/*.so"]
+        );
+    }
+
+    #[test]
+    fn parallel_matches_serial() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("stone.yaml"), RECIPE).unwrap();
+        install_root(&dir.path().join("install"));
+
+        let (serial, packaging) = package(dir.path(), 1);
+        assert_eq!(
+            serial.keys().collect::<Vec<_>>(),
+            [
+                "nano-8.7-1-1-x86_64.stone",
+                "nano-devel-8.7-1-1-x86_64.stone",
+                "nano-docs-8.7-1-1-x86_64.stone",
+            ]
+        );
+        assert_eq!(
+            [&packaging["paths"], &packaging["stones"], &packaging["threads"]],
+            [135, 3, 1]
+        );
+
+        let (parallel, packaging) = package(dir.path(), 8);
+        assert_eq!(packaging["threads"], 8);
+        assert!(serial == parallel, "stones differ from those packaged serially");
+    }
+
+    #[test]
+    fn artifacts_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("stone.yaml"), RECIPE).unwrap();
+        install_root(&dir.path().join("install"));
+
+        let (stones, _) = package(dir.path(), 2);
+
+        let recipe = Recipe::load(dir.path().join("stone.yaml")).unwrap();
+        let paths = Paths::new(&recipe, None, dir.path(), dir.path(), dir.path()).unwrap();
+        let manifest =
+            crate::artifacts::Manifest::load(&paths.artefacts().guest.join("nano-8.7-1.manifest.json")).unwrap();
+
+        assert_eq!(
+            (
+                manifest.source_name.as_str(),
+                manifest.source_version.as_str(),
+                manifest.source_release,
+                manifest.build_release,
+                manifest.architecture.as_str()
+            ),
+            ("nano", "8.7", 1, 1, "x86_64")
+        );
+        assert_eq!(
+            manifest
+                .stones
+                .iter()
+                .map(|stone| (stone.package.as_str(), stone.filename.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                ("nano", "nano-8.7-1-1-x86_64.stone"),
+                ("nano-devel", "nano-devel-8.7-1-1-x86_64.stone"),
+                ("nano-docs", "nano-docs-8.7-1-1-x86_64.stone"),
+            ]
+        );
+        for stone in &manifest.stones {
+            let content = &stones[&stone.filename];
+            assert_eq!(stone.size, content.len() as u64);
+            assert_eq!(stone.sha256, util::sha256_hash(&mut content.as_slice()).unwrap());
+        }
+        assert_eq!(
+            manifest
+                .stones
+                .iter()
+                .map(|stone| (stone.layouts, stone.dependencies, stone.providers))
+                .collect::<Vec<_>>(),
+            // Binaries of nano & the cmake config of nano-devel are provided
+            [(4, 0, 3), (66, 0, 1), (64, 0, 0)]
+        );
+    }
+
+    #[test]
+    fn only_package() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("stone.yaml"), RECIPE).unwrap();
+        install_root(&dir.path().join("install"));
+
+        let (stones, packaging) = package_only(dir.path(), 1, Some(&["nano-devel".to_owned()]));
+        assert_eq!(stones.keys().collect::<Vec<_>>(), ["nano-devel-8.7-1-1-x86_64.stone"]);
+        // Every path is still analyzed
+        assert_eq!(packaging["paths"], 135);
+
+        let recipe = Recipe::load(dir.path().join("stone.yaml")).unwrap();
+        let paths = Paths::new(&recipe, None, dir.path(), dir.path(), dir.path()).unwrap();
+        let artefacts = paths.artefacts().guest;
+        let manifest = crate::artifacts::Manifest::load(&artefacts.join("nano-8.7-1.manifest.json")).unwrap();
+        assert!(manifest.partial);
+        assert_eq!(manifest.stones[0].layouts, 66);
+        // Those of the recipe aren't replaced by a subset
+        assert!(!artefacts.join("manifest.x86_64.bin").exists());
+        assert!(!artefacts.join("manifest.x86_64.jsonc").exists());
+    }
+
+    #[test]
+    fn restrict_packages() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("stone.yaml"), RECIPE).unwrap();
+        let recipe = Recipe::load(dir.path().join("stone.yaml")).unwrap();
+        let macros = Macros {
+            arch: BTreeMap::new(),
+            actions: vec![],
+        };
+        let packages =
+            resolve_packages(["base".to_owned()], &macros, &recipe, &mut Collector::new(dir.path())).unwrap();
+        let only = |names: &[&str]| {
+            restrict(
+                &packages,
+                &names.iter().map(|name| (*name).to_owned()).collect::<Vec<_>>(),
+            )
+        };
+
+        assert_eq!(only(&["nano-docs"]).unwrap(), BTreeSet::from(["nano-docs".to_owned()]));
+        // The main package is only emitted when named
+        assert_eq!(
+            only(&["nano-devel", "nano"]).unwrap(),
+            BTreeSet::from(["nano".to_owned(), "nano-devel".to_owned()])
+        );
+        assert!(matches!(
+            only(&["nano-devel", "nano-dev"]),
+            Err(Error::UnknownPackage { name, packages })
+                if name == "nano-dev" && packages == "nano, nano-devel, nano-docs"
+        ));
+    }
+
+    #[test]
+    fn provenance_recorded() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("stone.yaml"), RECIPE).unwrap();
+        install_root(&dir.path().join("install"));
+
+        let (stones, _) = package(dir.path(), 1);
+
+        let mut recipe = Recipe::load(dir.path().join("stone.yaml")).unwrap();
+        recipe.build_time = chrono::DateTime::from_timestamp(BUILD_TIME, 0).unwrap();
+        let metas = |bytes: &[u8]| {
+            let mut reader = stone::read_bytes(bytes).unwrap();
+            reader
+                .payloads()
+                .unwrap()
+                .filter_map(|payload| payload.unwrap().meta().cloned())
+                .map(|payload| moss::package::Meta::from_stone_payload(&payload.body).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        let meta = &metas(&stones["nano-8.7-1-1-x86_64.stone"])[0];
+        assert_eq!(
+            meta.provenance,
+            provenance(&recipe, &profile::Id::new("default-x86_64"))
+        );
+        assert!(
+            meta.provenance
+                .builder
+                .as_ref()
+                .unwrap()
+                .starts_with("boulder version v")
+        );
+        assert_eq!(meta.provenance.time, Some(BUILD_TIME as u64));
+        assert_eq!(meta.provenance.profile.as_deref(), Some("default-x86_64"));
+
+        // Left out of the binary manifest, so manifests only differ by what was built
+        let paths = Paths::new(&recipe, None, dir.path(), dir.path(), dir.path()).unwrap();
+        let manifest = fs::read(
+            paths
+                .artefacts()
+                .guest
+                .join(format!("manifest.{}.bin", architecture::host())),
+        )
+        .unwrap();
+        let metas = metas(&manifest);
+        assert_eq!(metas.len(), 3);
+        assert!(metas.iter().all(|meta| meta.provenance.is_empty()));
+    }
 }
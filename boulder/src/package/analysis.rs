@@ -1,21 +1,20 @@
 // SPDX-FileCopyrightText: 2024 AerynOS Developers
 // SPDX-License-Identifier: MPL-2.0
 
-use std::collections::BTreeMap;
-use std::{
-    collections::{BTreeSet, VecDeque},
-    path::PathBuf,
-};
+use std::collections::{BTreeMap, HashMap};
+use std::{collections::BTreeSet, path::PathBuf};
 
+use fs_err as fs;
 use moss::{Dependency, Provider};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use stone::StoneDigestWriterHasher;
 use tui::{ProgressBar, ProgressStyle, Styled};
 
 use crate::{Paths, Recipe};
 
-use super::collect::{Collector, PathInfo};
+use super::collect::{self, Collector, PathInfo};
 
-mod handler;
+pub(super) mod handler;
 
 pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
 
@@ -24,8 +23,11 @@ pub struct Chain<'a> {
     recipe: &'a Recipe,
     paths: &'a Paths,
     collector: &'a Collector,
-    hasher: &'a mut StoneDigestWriterHasher,
+    /// Patterns of the paths never stripped, by package
+    nostrip: &'a BTreeMap<String, Vec<String>>,
     pub buckets: BTreeMap<String, Bucket>,
+    /// ELF files left unstripped as matched by `nostrip`
+    pub unstripped: Vec<PathBuf>,
 }
 
 impl<'a> Chain<'a> {
@@ -33,7 +35,7 @@ impl<'a> Chain<'a> {
         paths: &'a Paths,
         recipe: &'a Recipe,
         collector: &'a Collector,
-        hasher: &'a mut StoneDigestWriterHasher,
+        nostrip: &'a BTreeMap<String, Vec<String>>,
     ) -> Self {
         Self {
             handlers: vec![
@@ -43,22 +45,23 @@ impl<'a> Chain<'a> {
                 Box::new(handler::pkg_config),
                 Box::new(handler::python),
                 Box::new(handler::cmake),
-                Box::new(handler::compressman),
                 // Catch-all if not excluded
                 Box::new(handler::include_any),
             ],
             paths,
             recipe,
             collector,
-            hasher,
+            nostrip,
             buckets: Default::default(),
+            unstripped: vec![],
         }
     }
 
+    /// Analyze `paths` across the rayon pool, adding them to their buckets in order
     pub fn process(&mut self, paths: impl IntoIterator<Item = PathInfo>) -> Result<(), BoxError> {
-        println!("│Analyzing artefacts (» = Include, × = Ignore, ^ = Replace)");
+        println!("│Analyzing artefacts (» = Include, × = Ignore)");
 
-        let mut queue = paths.into_iter().collect::<VecDeque<_>>();
+        let mut queue = paths.into_iter().collect::<Vec<_>>();
 
         let pb = ProgressBar::new(queue.len() as u64)
             .with_message("Analyzing")
@@ -69,35 +72,33 @@ impl<'a> Chain<'a> {
             );
         pb.tick();
 
-        'paths: while let Some(mut path) = queue.pop_front() {
-            let bucket = self.buckets.entry(path.package.clone()).or_default();
+        // Paths generated by handlers, i.e. split debug info, are
+        // analyzed once all those queued before them are
+        while !queue.is_empty() {
+            let mut generated = vec![];
 
-            pb.set_message(format!("Analyzing {}", path.target_path.display()));
+            for analyzed in self.analyze(queue, &pb)? {
+                let Analyzed {
+                    path,
+                    providers,
+                    dependencies,
+                    generated_paths,
+                    unstripped,
+                    outcome,
+                } = analyzed;
 
-            'handlers: for handler in &self.handlers {
-                // Only give handlers ability to update
-                // certain bucket fields
-                let mut bucket_mut = BucketMut {
-                    providers: &mut bucket.providers,
-                    dependencies: &mut bucket.dependencies,
-                    hasher: self.hasher,
-                    recipe: self.recipe,
-                    paths: self.paths,
-                };
-
-                let response = handler.handle(&mut bucket_mut, &mut path)?;
-
-                response.generated_paths.into_iter().try_for_each(|path| {
-                    let info = self.collector.path(&path, self.hasher)?;
-
-                    queue.push_back(info);
+                if unstripped {
+                    self.unstripped.push(path.target_path.clone());
+                }
 
-                    Ok(()) as Result<(), BoxError>
-                })?;
+                let bucket = self.buckets.entry(path.package.clone()).or_default();
+                bucket.providers.extend(providers);
+                bucket.dependencies.extend(dependencies);
+                generated.extend(generated_paths);
 
-                match response.decision {
-                    Decision::NextHandler => continue 'handlers,
-                    Decision::IgnoreFile { reason } => {
+                match outcome {
+                    None => {}
+                    Some(Outcome::Ignore { reason }) => {
                         pb.suspend(|| {
                             println!(
                                 "│A{} {} {}",
@@ -106,36 +107,163 @@ impl<'a> Chain<'a> {
                                 format!("({reason})").yellow()
                             );
                         });
-                        pb.inc(1);
-                        continue 'paths;
                     }
-                    Decision::IncludeFile => {
+                    Some(Outcome::Include) => {
                         pb.suspend(|| println!("│A{} {}", "│ »".green(), path.target_path.display()));
-                        pb.inc(1);
                         bucket.paths.push(path);
-                        continue 'paths;
-                    }
-                    Decision::ReplaceFile { newpath } => {
-                        let newpathinfo = self.collector.path(&newpath, self.hasher)?;
-                        pb.println(format!(
-                            "│A{} {} » {}",
-                            "│ ^".dark_magenta(),
-                            format!("{}", path.target_path.display()).dim(),
-                            newpathinfo.target_path.display()
-                        ));
-                        pb.inc(1);
-                        bucket.paths.push(newpathinfo);
-                        continue 'paths;
                     }
                 }
             }
+
+            queue = generated;
         }
 
         pb.finish_and_clear();
         println!();
 
+        if !self.unstripped.is_empty() {
+            println!("│Left {} files unstripped (nostrip)", self.unstripped.len());
+            for path in &self.unstripped {
+                println!("│A{} {}", "│ ~".cyan(), path.display());
+            }
+            println!();
+        }
+
         Ok(())
     }
+
+    /// Analyze `paths` in parallel, returning them in order
+    ///
+    /// Handlers modify files in place, i.e. stripping them, so paths of the same
+    /// content or symlinks to it are analyzed in order by the same task.
+    fn analyze(&self, paths: Vec<PathInfo>, pb: &ProgressBar) -> Result<Vec<Analyzed>, BoxError> {
+        let hashes = paths
+            .iter()
+            .filter_map(|info| Some((info.path.clone(), info.file_hash()?)))
+            .collect::<HashMap<_, _>>();
+
+        let mut groups = BTreeMap::<Key, Vec<(usize, PathInfo)>>::new();
+        for (index, info) in paths.into_iter().enumerate() {
+            groups.entry(Key::new(&info, &hashes)).or_default().push((index, info));
+        }
+
+        let mut analyzed = groups
+            .into_values()
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .map_init(StoneDigestWriterHasher::new, |hasher, group| {
+                group
+                    .into_iter()
+                    .map(|(index, info)| Ok((index, self.analyze_path(info, hasher, pb)?)))
+                    .collect::<Result<Vec<_>, BoxError>>()
+            })
+            .collect::<Result<Vec<_>, BoxError>>()?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        analyzed.sort_by_key(|(index, _)| *index);
+
+        Ok(analyzed.into_iter().map(|(_, analyzed)| analyzed).collect())
+    }
+
+    /// Pass `path` through the handlers until one decides on it
+    fn analyze_path(
+        &self,
+        mut path: PathInfo,
+        hasher: &mut StoneDigestWriterHasher,
+        pb: &ProgressBar,
+    ) -> Result<Analyzed, BoxError> {
+        let mut providers = BTreeSet::new();
+        let mut dependencies = BTreeSet::new();
+        let mut generated_paths = vec![];
+        let mut unstripped = false;
+        let nostrip = self.nostrip.get(&path.package).map(Vec::as_slice).unwrap_or_default();
+
+        pb.set_message(format!("Analyzing {}", path.target_path.display()));
+
+        for handler in &self.handlers {
+            // Only give handlers ability to update
+            // certain bucket fields
+            let mut bucket_mut = BucketMut {
+                providers: &mut providers,
+                dependencies: &mut dependencies,
+                hasher,
+                recipe: self.recipe,
+                paths: self.paths,
+                nostrip,
+            };
+
+            let response = handler.handle(&mut bucket_mut, &mut path)?;
+            unstripped |= response.unstripped;
+
+            for path in response.generated_paths {
+                generated_paths.push(self.collector.path(&path, hasher)?);
+            }
+
+            let outcome = match response.decision {
+                Decision::NextHandler => continue,
+                Decision::IgnoreFile { reason } => Outcome::Ignore { reason },
+                Decision::IncludeFile => Outcome::Include,
+            };
+            pb.inc(1);
+
+            return Ok(Analyzed {
+                path,
+                providers,
+                dependencies,
+                generated_paths,
+                unstripped,
+                outcome: Some(outcome),
+            });
+        }
+
+        Ok(Analyzed {
+            path,
+            providers,
+            dependencies,
+            generated_paths,
+            unstripped,
+            outcome: None,
+        })
+    }
+}
+
+/// A path as analyzed, yet to be added to its bucket
+struct Analyzed {
+    path: PathInfo,
+    providers: BTreeSet<Provider>,
+    dependencies: BTreeSet<Dependency>,
+    generated_paths: Vec<PathInfo>,
+    unstripped: bool,
+    /// Unless no handler decided on the path
+    outcome: Option<Outcome>,
+}
+
+enum Outcome {
+    Ignore { reason: String },
+    Include,
+}
+
+/// Paths analyzed by the same task
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Key {
+    Content(u128),
+    Path(PathBuf),
+}
+
+impl Key {
+    /// Files by their content, symlinks by that of the file they resolve to
+    fn new(info: &PathInfo, hashes: &HashMap<PathBuf, u128>) -> Self {
+        if let Some(hash) = info.file_hash() {
+            return Self::Content(hash);
+        }
+
+        info.is_symlink()
+            .then(|| fs::canonicalize(&info.path).ok())
+            .flatten()
+            .and_then(|target| hashes.get(&target).copied())
+            .map_or_else(|| Self::Path(info.path.clone()), Self::Content)
+    }
 }
 
 #[derive(Debug, Default)]
@@ -164,18 +292,29 @@ pub struct BucketMut<'a> {
     pub hasher: &'a mut StoneDigestWriterHasher,
     pub recipe: &'a Recipe,
     pub paths: &'a Paths,
+    /// Patterns of the paths of the package never stripped
+    pub nostrip: &'a [String],
+}
+
+impl BucketMut<'_> {
+    /// Whether `info` is never to be stripped nor split into `-dbginfo`
+    pub fn is_nostrip(&self, info: &PathInfo) -> bool {
+        let path = info.target_path.to_str().unwrap_or_default();
+        self.nostrip.iter().any(|pattern| collect::matches(pattern, path))
+    }
 }
 
 pub struct Response {
     pub decision: Decision,
     pub generated_paths: Vec<PathBuf>,
+    /// An ELF file left unstripped as matched by `nostrip`
+    pub unstripped: bool,
 }
 
 pub enum Decision {
     NextHandler,
     IgnoreFile { reason: String },
     IncludeFile,
-    ReplaceFile { newpath: PathBuf },
 }
 
 impl From<Decision> for Response {
@@ -183,19 +322,144 @@ impl From<Decision> for Response {
         Self {
             decision,
             generated_paths: vec![],
+            unstripped: false,
         }
     }
 }
 
-pub trait Handler {
+pub trait Handler: Send + Sync {
     fn handle(&self, bucket: &mut BucketMut<'_>, path: &mut PathInfo) -> Result<Response, BoxError>;
 }
 
 impl<T> Handler for T
 where
-    T: Fn(&mut BucketMut<'_>, &mut PathInfo) -> Result<Response, BoxError>,
+    T: Fn(&mut BucketMut<'_>, &mut PathInfo) -> Result<Response, BoxError> + Send + Sync,
 {
     fn handle(&self, bucket: &mut BucketMut<'_>, path: &mut PathInfo) -> Result<Response, BoxError> {
         (self)(bucket, path)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use fs_err as fs;
+
+    use super::*;
+
+    /// A minimal ELF file of only a GNU build ID note
+    fn fake_elf() -> Vec<u8> {
+        const NOTE: u64 = 64;
+        const SHSTRTAB: u64 = 100;
+        const SECTIONS: u64 = 136;
+        let names = b"\0.note.gnu.build-id\0.shstrtab\0";
+
+        let mut elf = vec![0x7f, b'E', b'L', b'F', 2, 1, 1];
+        elf.resize(16, 0);
+        for (value, size) in [
+            (2, 2),        // e_type: executable
+            (0x3e, 2),     // e_machine: x86_64
+            (1, 4),        // e_version
+            (0, 8),        // e_entry
+            (0, 8),        // e_phoff
+            (SECTIONS, 8), // e_shoff
+            (0, 4),        // e_flags
+            (64, 2),       // e_ehsize
+            (56, 2),       // e_phentsize
+            (0, 2),        // e_phnum
+            (64, 2),       // e_shentsize
+            (3, 2),        // e_shnum
+            (2, 2),        // e_shstrndx
+            (4, 4),        // n_namesz
+            (20, 4),       // n_descsz
+            (3, 4),        // n_type: NT_GNU_BUILD_ID
+        ] {
+            elf.extend_from_slice(&u64::to_le_bytes(value)[..size]);
+        }
+        elf.extend_from_slice(b"GNU\0");
+        elf.extend_from_slice(&[0xab; 20]);
+        elf.extend_from_slice(names);
+        elf.resize(SECTIONS as usize + 64, 0);
+
+        // name, type, offset, size & alignment of each section after the null one
+        for (name, kind, offset, size, align) in [(1, 7, NOTE, 36, 4), (21, 3, SHSTRTAB, names.len() as u64, 1)] {
+            for (value, size) in [
+                (name, 4),
+                (kind, 4),
+                (0, 8),
+                (0, 8),
+                (offset, 8),
+                (size, 8),
+                (0, 4),
+                (0, 4),
+                (align, 8),
+                (0, 8),
+            ] {
+                elf.extend_from_slice(&u64::to_le_bytes(value)[..size]);
+            }
+        }
+
+        elf
+    }
+
+    #[test]
+    fn nostrip() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("stone.yaml"),
+            "\
+name: go-tool
+version: 1.0
+release: 1
+homepage: https://example.com
+license: MIT
+summary: Example
+description: Example
+",
+        )
+        .unwrap();
+        let recipe = Recipe::load(dir.path().join("stone.yaml")).unwrap();
+        let paths = Paths::new(&recipe, None, dir.path(), dir.path(), dir.path()).unwrap();
+
+        let install = paths.install().guest;
+        for (path, content) in [
+            ("usr/bin/go-tool", fake_elf()),
+            ("usr/lib/go-tool/plugins/sqlite.so", fake_elf()),
+            ("usr/share/go-tool/README", b"Not an ELF".to_vec()),
+        ] {
+            let path = install.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        let mut collector = Collector::new(&install);
+        collector.add_rule(collect::Rule {
+            pattern: "/usr".to_owned(),
+            package: "go-tool".to_owned(),
+            retain: false,
+        });
+        let nostrip = BTreeMap::from([(
+            "go-tool".to_owned(),
+            [
+                "/usr/bin/go-tool",
+                "/usr/lib/go-tool/plugins/*.so",
+                "/usr/share/go-tool",
+            ]
+            .map(String::from)
+            .to_vec(),
+        )]);
+
+        let mut chain = Chain::new(&paths, &recipe, &collector, &nostrip);
+        chain.process(collector.enumerate_paths().unwrap()).unwrap();
+
+        assert_eq!(
+            chain.unstripped,
+            [
+                PathBuf::from("/usr/bin/go-tool"),
+                PathBuf::from("/usr/lib/go-tool/plugins/sqlite.so"),
+            ]
+        );
+        assert_eq!(chain.buckets["go-tool"].paths.len(), 3);
+        assert_eq!(fs::read(install.join("usr/bin/go-tool")).unwrap(), fake_elf());
+        assert!(!install.join("usr/lib/debug").exists());
+    }
+}
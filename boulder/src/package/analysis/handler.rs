@@ -1,16 +1,8 @@
 // SPDX-FileCopyrightText: 2024 AerynOS Developers
 // SPDX-License-Identifier: MPL-2.0
 
-use filetime::FileTime;
-use itertools::Itertools;
-use std::{
-    io::{BufReader, BufWriter, Write},
-    os::unix::fs::symlink,
-    path::{Component, Path, PathBuf},
-    process::Command,
-};
-
-use fs_err::{self as fs, File};
+use std::{path::PathBuf, process::Command};
+
 use moss::{Dependency, Provider, dependency};
 
 use crate::package::collect::PathInfo;
@@ -19,7 +11,7 @@ pub use self::elf::elf;
 pub use self::python::python;
 use super::{BoxError, BucketMut, Decision, Response};
 
-mod elf;
+pub(in crate::package) mod elf;
 mod python;
 
 pub fn include_any(_bucket: &mut BucketMut<'_>, _info: &mut PathInfo) -> Result<Response, BoxError> {
@@ -150,92 +142,3 @@ pub fn cmake(bucket: &mut BucketMut<'_>, info: &mut PathInfo) -> Result<Response
 
     Ok(Decision::NextHandler.into())
 }
-
-/// Ensure that man and info files are zst compressed for on-disk space savings.
-pub fn compressman(bucket: &mut BucketMut<'_>, info: &mut PathInfo) -> Result<Response, BoxError> {
-    /* if the compressman option is turned off, exit early */
-    if !bucket.recipe.parsed.options.compressman {
-        return Ok(Decision::NextHandler.into());
-    }
-
-    let is_man_file = info.path.components().contains(&Component::Normal("man".as_ref()))
-        && info.file_name().ends_with(|c| ('1'..'9').contains(&c));
-    let is_info_file =
-        info.path.components().contains(&Component::Normal("info".as_ref())) && info.file_name().ends_with(".info");
-
-    /* we only care about compressing man and info files here */
-    if !(is_man_file || is_info_file) {
-        return Ok(Decision::NextHandler.into());
-    }
-
-    pub fn compress_file_zstd(path: &Path) -> Result<PathBuf, BoxError> {
-        let output_path = path.with_added_extension(".zst");
-        let mut reader = BufReader::new(File::open(path)?);
-        let mut writer = BufWriter::new(File::create(&output_path)?);
-
-        zstd::stream::copy_encode(&mut reader, &mut writer, 16)?;
-
-        writer.flush()?;
-
-        Ok(output_path)
-    }
-
-    let mut generated_path = PathBuf::new();
-
-    let metadata = fs::metadata(&info.path)?;
-    let atime = metadata.accessed()?;
-    let mtime = metadata.modified()?;
-
-    let uncompressed_file = fs::canonicalize(&info.path)?;
-    /* we are deducing this in advance to have something against which to symlink */
-    let compressed_zst_file = uncompressed_file.with_added_extension(".zst");
-
-    /* If we have a man/info symlink then update the link to the compressed file */
-    if info.path.is_symlink() {
-        let new_zst_symlink = info.path.with_added_extension(".zst");
-
-        /*
-         * Depending on the order in which the files get analysed,
-         * the new compressed file may not yet exist, so compress it _now_
-         * in order that the correct metadata src info is returned to the binary writer.
-         */
-        if !fs::exists(&new_zst_symlink)? {
-            compress_file_zstd(&uncompressed_file)?;
-            let _ = bucket.paths.install().guest.join(&compressed_zst_file);
-        }
-
-        symlink(&compressed_zst_file, &new_zst_symlink)?;
-
-        /* Restore the original {a,m}times for reproducibility */
-        filetime::set_symlink_file_times(
-            &new_zst_symlink,
-            FileTime::from_system_time(atime),
-            FileTime::from_system_time(mtime),
-        )?;
-
-        generated_path.push(bucket.paths.install().guest.join(new_zst_symlink));
-        return Ok(Decision::ReplaceFile {
-            newpath: generated_path,
-        }
-        .into());
-    }
-
-    /* We already know what the returned filename will be, so just ignore the return value */
-    if !compressed_zst_file.try_exists()? {
-        compress_file_zstd(&uncompressed_file)?;
-    }
-
-    /* Restore the original {a,m}times for reproducibility */
-    filetime::set_file_handle_times(
-        &File::open(&compressed_zst_file)?.into_file(),
-        Some(FileTime::from_system_time(atime)),
-        Some(FileTime::from_system_time(mtime)),
-    )?;
-
-    generated_path.push(bucket.paths.install().guest.join(compressed_zst_file));
-
-    Ok(Decision::ReplaceFile {
-        newpath: generated_path,
-    }
-    .into())
-}
@@ -39,10 +39,7 @@ pub fn elf(bucket: &mut BucketMut<'_>, info: &mut PathInfo) -> Result<Response,
         return Ok(Decision::NextHandler.into());
     };
 
-    let machine_isa = to_str::e_machine_to_str(elf.ehdr.e_machine)
-        .map(|s| s.strip_prefix("EM_").unwrap_or(s))
-        .unwrap_or_default()
-        .to_lowercase();
+    let machine_isa = machine_isa(&elf);
     let bit_size = elf.ehdr.class;
 
     parse_dynamic_section(&mut elf, bucket, &machine_isa, bit_size, info, file_name);
@@ -51,8 +48,9 @@ pub fn elf(bucket: &mut BucketMut<'_>, info: &mut PathInfo) -> Result<Response,
     let build_id = parse_build_id(&mut elf);
 
     let mut generated_paths = vec![];
+    let unstripped = build_id.is_some() && bucket.is_nostrip(info);
 
-    if let Some(build_id) = build_id {
+    if let Some(build_id) = build_id.filter(|_| !unstripped) {
         match split_debug(bucket, info, bit_size, &build_id) {
             Ok(Some(debug_path)) => {
                 // Add new split file to be analyzed
@@ -77,14 +75,52 @@ pub fn elf(bucket: &mut BucketMut<'_>, info: &mut PathInfo) -> Result<Response,
     Ok(Response {
         decision: Decision::IncludeFile,
         generated_paths,
+        unstripped,
     })
 }
 
-fn parse_elf(path: &Path) -> Result<elf::ElfStream<AnyEndian, File>, BoxError> {
+pub(crate) fn parse_elf(path: &Path) -> Result<elf::ElfStream<AnyEndian, File>, BoxError> {
     let file = File::open(path)?;
     Ok(elf::ElfStream::open_stream(file)?)
 }
 
+/// The lowercase ISA `elf` was built for, i.e. `x86_64`
+pub(crate) fn machine_isa(elf: &elf::ElfStream<AnyEndian, File>) -> String {
+    to_str::e_machine_to_str(elf.ehdr.e_machine)
+        .map(|s| s.strip_prefix("EM_").unwrap_or(s))
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/// Sonames of the `DT_NEEDED` entries of `elf`, in order
+pub(crate) fn needed(elf: &mut elf::ElfStream<AnyEndian, File>) -> Vec<String> {
+    let offsets = match elf.dynamic() {
+        Ok(Some(table)) => table
+            .iter()
+            .filter(|entry| entry.d_tag == DT_NEEDED)
+            .map(|entry| entry.d_val() as usize)
+            .collect(),
+        _ => vec![],
+    };
+
+    match elf.dynamic_symbol_table() {
+        Ok(Some((_, strtab))) => offsets
+            .into_iter()
+            .filter_map(|offset| strtab.get(offset).ok())
+            .map(str::to_owned)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// The interpreter `elf` requests in its `.interp` section, if any
+pub(crate) fn interpreter(elf: &mut elf::ElfStream<AnyEndian, File>) -> Option<String> {
+    let section = elf.section_header_by_name(".interp").ok().flatten().copied()?;
+    let (data, _) = elf.section_data(&section).ok()?;
+
+    CStr::from_bytes_until_nul(data).ok()?.to_str().ok().map(str::to_owned)
+}
+
 fn parse_dynamic_section(
     elf: &mut elf::ElfStream<AnyEndian, File>,
     bucket: &mut BucketMut<'_>,
@@ -93,7 +129,7 @@ fn parse_dynamic_section(
     info: &PathInfo,
     file_name: &str,
 ) {
-    let mut needed_offsets = vec![];
+    let needed = needed(elf);
     let mut soname_offset = None;
     let mut rpath_offset = vec![];
     let mut runpath_offset = vec![];
@@ -105,9 +141,6 @@ fn parse_dynamic_section(
     if let Ok(Some(table)) = elf.dynamic() {
         for entry in table.iter() {
             match entry.d_tag {
-                DT_NEEDED => {
-                    needed_offsets.push(entry.d_val() as usize);
-                }
                 DT_SONAME => {
                     soname_offset = Some(entry.d_val() as usize);
                 }
@@ -148,40 +181,38 @@ fn parse_dynamic_section(
         }
 
         // needed = dependency
-        for offset in needed_offsets {
-            if let Ok(name) = strtab.get(offset) {
-                let rpath_name = rpaths.iter().find_map(|rpath| {
-                    let local_p = root_dir.to_owned() + "/" + rpath + "/" + name;
-                    let native_p = rpath.to_owned() + "/" + name;
-                    let path = Path::new(&local_p);
-                    let native_path = Path::new(&native_p);
-                    if path.exists() {
-                        Some(
-                            Path::new("/")
-                                .join(rpath)
-                                .join(name)
-                                .components()
-                                .skip(3)
-                                .collect::<PathBuf>(),
-                        )
-                    } else if native_path.exists() {
-                        Some(Path::new(rpath).join(name).components().skip(3).collect::<PathBuf>())
-                    } else {
-                        None
-                    }
-                });
-
-                let picked = if let Some(rpath_name) = &rpath_name {
-                    &rpath_name.to_string_lossy().to_string()
+        for name in &needed {
+            let rpath_name = rpaths.iter().find_map(|rpath| {
+                let local_p = root_dir.to_owned() + "/" + rpath + "/" + name;
+                let native_p = rpath.to_owned() + "/" + name;
+                let path = Path::new(&local_p);
+                let native_path = Path::new(&native_p);
+                if path.exists() {
+                    Some(
+                        Path::new("/")
+                            .join(rpath)
+                            .join(name)
+                            .components()
+                            .skip(3)
+                            .collect::<PathBuf>(),
+                    )
+                } else if native_path.exists() {
+                    Some(Path::new(rpath).join(name).components().skip(3).collect::<PathBuf>())
                 } else {
-                    name
-                };
+                    None
+                }
+            });
 
-                bucket.dependencies.insert(Dependency {
-                    kind: dependency::Kind::SharedLibrary,
-                    name: format!("{picked}({machine_isa})"),
-                });
-            }
+            let picked = if let Some(rpath_name) = &rpath_name {
+                &rpath_name.to_string_lossy().to_string()
+            } else {
+                name
+            };
+
+            bucket.dependencies.insert(Dependency {
+                kind: dependency::Kind::SharedLibrary,
+                name: format!("{picked}({machine_isa})"),
+            });
         }
 
         // soname exposed, let's share it
@@ -255,15 +286,7 @@ fn parse_dynamic_section(
 }
 
 fn parse_interp_section(elf: &mut elf::ElfStream<AnyEndian, File>, bucket: &mut BucketMut<'_>, machine_isa: &str) {
-    let Some(section) = elf.section_header_by_name(".interp").ok().flatten().copied() else {
-        return;
-    };
-
-    let Ok((data, _)) = elf.section_data(&section) else {
-        return;
-    };
-
-    if let Some(content) = CStr::from_bytes_until_nul(data).ok().and_then(|s| s.to_str().ok()) {
+    if let Some(content) = interpreter(elf) {
         bucket.dependencies.insert(Dependency {
             kind: dependency::Kind::Interpreter,
             name: format!("{content}({machine_isa})"),
@@ -12,6 +12,7 @@ use astr::AStr;
 use fs_err as fs;
 use glob::Pattern;
 use nix::libc::{S_IFDIR, S_IRGRP, S_IROTH, S_IRWXU, S_IXGRP, S_IXOTH};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use snafu::{ResultExt as _, Snafu};
 use stone::{StoneDigestWriter, StoneDigestWriterHasher, StonePayloadLayoutFile, StonePayloadLayoutRecord};
 
@@ -19,26 +20,33 @@ use stone::{StoneDigestWriter, StoneDigestWriterHasher, StonePayloadLayoutFile,
 pub struct Rule {
     pub pattern: String,
     pub package: String,
+    /// Matching paths are kept on disk when the package is removed
+    pub retain: bool,
 }
 
 impl Rule {
     pub fn matches(&self, path: &str) -> bool {
-        if self.pattern == path {
-            return true;
-        }
+        matches(&self.pattern, path)
+    }
+}
 
-        // Escape the directory in case it contains characters that have special
-        // meaning in glob patterns (e.g., `[` or `]`).
-        let escaped_path = Pattern::escape(path);
-        Pattern::new(&self.pattern)
-                .map(|pattern| pattern.matches(&escaped_path))
-                .unwrap_or_default()
-            // If the supplied pattern is for a directory we want to match anything that's inside said directory,
-            // Do this by creating a recursive glob pattern by appending `**` if the pattern already ends in a `/` or `/**` if not
-            || Pattern::new(format!("{}/**", self.pattern.strip_suffix("/").unwrap_or(&self.pattern)).as_str())
-                .map(|pattern| pattern.matches(&escaped_path))
-                .unwrap_or_default()
+/// Whether `path` is matched by `pattern`, either exactly, as a glob or as a directory containing it
+pub fn matches(pattern: &str, path: &str) -> bool {
+    if pattern == path {
+        return true;
     }
+
+    // Escape the directory in case it contains characters that have special
+    // meaning in glob patterns (e.g., `[` or `]`).
+    let escaped_path = Pattern::escape(path);
+    Pattern::new(pattern)
+            .map(|pattern| pattern.matches(&escaped_path))
+            .unwrap_or_default()
+        // If the supplied pattern is for a directory we want to match anything that's inside said directory,
+        // Do this by creating a recursive glob pattern by appending `**` if the pattern already ends in a `/` or `/**` if not
+        || Pattern::new(format!("{}/**", pattern.strip_suffix("/").unwrap_or(pattern)).as_str())
+            .map(|pattern| pattern.matches(&escaped_path))
+            .unwrap_or_default()
 }
 
 #[derive(Debug)]
@@ -61,12 +69,9 @@ impl Collector {
         self.rules.push(rule);
     }
 
-    fn matching_package(&self, path: &str) -> Option<&str> {
+    fn matching_rule(&self, path: &str) -> Option<&Rule> {
         // Rev = check highest priority rules first
-        self.rules
-            .iter()
-            .rev()
-            .find_map(|rule| rule.matches(path).then_some(rule.package.as_str()))
+        self.rules.iter().rev().find(|rule| rule.matches(path))
     }
 
     /// Produce a [`PathInfo`] from the provided [`Path`]
@@ -83,38 +88,57 @@ impl Collector {
     ) -> Result<PathInfo, Error> {
         let target_path = Path::new("/").join(path.strip_prefix(&self.root).expect("path is ancestor of root"));
 
-        let package = self
-            .matching_package(target_path.to_str().unwrap_or_default())
+        let rule = self
+            .matching_rule(target_path.to_str().unwrap_or_default())
             .ok_or(Error::NoMatchingRule)?;
 
-        PathInfo::new(path, target_path, metadata, hasher, package.to_owned())
+        let mut info = PathInfo::new(path, target_path, metadata, hasher, rule.package.clone())?;
+        if rule.retain {
+            info.layout.tag |= StonePayloadLayoutRecord::TAG_RETAIN;
+        }
+        Ok(info)
     }
 
-    /// Enumerates all paths from the filesystem starting at root or subdir of root, if provided
-    pub fn enumerate_paths(
+    /// Enumerates all paths from the filesystem under root, in order
+    ///
+    /// Files are hashed across the rayon pool.
+    pub fn enumerate_paths(&self) -> Result<Vec<PathInfo>, Error> {
+        let mut entries = vec![];
+        self.enumerate_entries(None, &mut entries)?;
+
+        entries
+            .into_par_iter()
+            .map_init(StoneDigestWriterHasher::new, |hasher, (path, metadata)| {
+                self.path_with_metadata(path, &metadata, hasher)
+            })
+            .collect()
+    }
+
+    /// Enumerates all entries from the filesystem starting at root or subdir of root, if provided
+    fn enumerate_entries(
         &self,
         subdir: Option<(PathBuf, Metadata)>,
-        hasher: &mut StoneDigestWriterHasher,
-    ) -> Result<Vec<PathInfo>, Error> {
-        let mut paths = vec![];
+        entries: &mut Vec<(PathBuf, Metadata)>,
+    ) -> Result<(), Error> {
+        let start = entries.len();
 
         let dir = subdir.as_ref().map(|t| t.0.as_path()).unwrap_or(&self.root);
-        let mut entries: Vec<_> = fs::read_dir(dir)
+        let mut dir_entries: Vec<_> = fs::read_dir(dir)
             .context(IoSnafu)?
             .collect::<Result<Vec<_>, _>>()
             .context(IoSnafu)?;
 
-        entries.sort_by_key(|entry| entry.file_name());
+        dir_entries.sort_by_key(|entry| entry.file_name());
 
-        for entry in entries {
+        for entry in dir_entries {
             let metadata = entry.metadata().context(IoSnafu)?;
 
             let host_path = entry.path();
 
             if metadata.is_dir() {
-                paths.extend(self.enumerate_paths(Some((host_path, metadata)), hasher)?);
+                self.enumerate_entries(Some((host_path, metadata)), entries)?;
             } else {
-                paths.push(self.path_with_metadata(host_path, &metadata, hasher)?);
+                entries.push((host_path, metadata));
             }
         }
 
@@ -127,12 +151,12 @@ impl Collector {
 
             let is_special = meta.mode() != REGULAR_DIR_MODE;
 
-            if meta.is_dir() && (paths.is_empty() || is_special) {
-                paths.push(self.path_with_metadata(dir, &meta, hasher)?);
+            if meta.is_dir() && (entries.len() == start || is_special) {
+                entries.push((dir, meta));
             }
         }
 
-        Ok(paths)
+        Ok(())
     }
 }
 
@@ -166,7 +190,11 @@ impl PathInfo {
 
     pub fn restat(&mut self, hasher: &mut StoneDigestWriterHasher) -> Result<(), Error> {
         let metadata = fs::metadata(&self.path).context(IoSnafu)?;
-        self.layout = layout_from_metadata(&self.path, &self.target_path, &metadata, hasher)?;
+        let tag = self.layout.tag;
+        self.layout = StonePayloadLayoutRecord {
+            tag,
+            ..layout_from_metadata(&self.path, &self.target_path, &metadata, hasher)?
+        };
         self.size = metadata.size();
         Ok(())
     }
@@ -175,6 +203,10 @@ impl PathInfo {
         matches!(self.layout.file, StonePayloadLayoutFile::Regular(..))
     }
 
+    pub fn is_symlink(&self) -> bool {
+        matches!(self.layout.file, StonePayloadLayoutFile::Symlink(..))
+    }
+
     pub fn file_hash(&self) -> Option<u128> {
         if let StonePayloadLayoutFile::Regular(hash, _) = &self.layout.file {
             Some(*hash)
@@ -255,3 +287,47 @@ pub enum Error {
     #[snafu(display("io"))]
     Io { source: io::Error },
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn retained_paths() {
+        let root = tempfile::tempdir().unwrap();
+        let state = root.path().join("usr/share/nano/state");
+        fs::create_dir_all(&state).unwrap();
+        fs::write(state.join("history"), "").unwrap();
+        fs::create_dir_all(root.path().join("usr/bin")).unwrap();
+        fs::write(root.path().join("usr/bin/nano"), "").unwrap();
+
+        let mut collector = Collector::new(root.path());
+        for (pattern, retain) in [("/usr", false), ("/usr/share/nano/state", true)] {
+            collector.add_rule(Rule {
+                pattern: pattern.to_owned(),
+                package: "nano".to_owned(),
+                retain,
+            });
+        }
+
+        let mut hasher = StoneDigestWriterHasher::new();
+        let mut paths = collector.enumerate_paths().unwrap();
+        let retained = |paths: &[PathInfo]| {
+            paths
+                .iter()
+                .map(|info| (info.layout.file.target().to_owned(), info.layout.is_retained()))
+                .collect::<Vec<_>>()
+        };
+        let expected = [
+            ("bin/nano".to_owned(), false),
+            ("share/nano/state/history".to_owned(), true),
+        ];
+        assert_eq!(retained(&paths), expected);
+
+        // Restating keeps the tag
+        for info in &mut paths {
+            info.restat(&mut hasher).unwrap();
+        }
+        assert_eq!(retained(&paths), expected);
+    }
+}
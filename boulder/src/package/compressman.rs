@@ -0,0 +1,258 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Compression of man & info pages of the install root, per the `compressman` option
+//!
+//! Pages are replaced by their zstd compressed `.zst` before the paths of the
+//! install root are collected, along with the symlinks aliasing them.
+
+use std::{
+    collections::BTreeSet,
+    ffi::OsString,
+    fs::Permissions,
+    io::{self, BufReader, BufWriter, Write},
+    os::unix::fs::{PermissionsExt, symlink},
+    path::{Component, Path, PathBuf},
+};
+
+use filetime::FileTime;
+use fs_err::{self as fs, File};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use walkdir::WalkDir;
+
+/// Dirs of the install root holding man & info pages
+const DIRS: &[&str] = &["usr/share/man", "usr/share/info"];
+
+/// Extensions of pages already compressed
+const COMPRESSED: &[&str] = &["zst", "gz", "xz", "bz2", "lzma", "Z"];
+
+const LEVEL: i32 = 16;
+
+/// Symlinks followed resolving one, as the kernel does
+const MAX_LINKS: usize = 40;
+
+/// How many of each kind of path were compressed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Compressed {
+    pub pages: usize,
+    /// Symlinks aliasing a page
+    pub links: usize,
+}
+
+/// Compress the man & info pages of the install `root`, relinking their aliases
+pub fn compress(root: &Path) -> io::Result<Compressed> {
+    let mut pages = BTreeSet::new();
+    let mut links = vec![];
+
+    for dir in DIRS {
+        let dir = root.join(dir);
+        if !dir.exists() {
+            continue;
+        }
+
+        for entry in WalkDir::new(dir).sort_by_file_name() {
+            let entry = entry?;
+            let path = entry.path();
+
+            if !is_page(path) {
+                continue;
+            }
+            if entry.path_is_symlink() {
+                links.push(path.to_owned());
+            } else if entry.file_type().is_file() {
+                pages.insert(path.to_owned());
+            }
+        }
+    }
+
+    // Resolved before any page is replaced by its `.zst`
+    let links = links
+        .into_iter()
+        .filter_map(|link| match resolve(root, &link) {
+            Ok(Some(target)) if pages.contains(&target) => Some(Ok(link)),
+            Ok(_) => None,
+            Err(error) => Some(Err(error)),
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+
+    pages.par_iter().try_for_each(|page| compress_page(page))?;
+    for link in &links {
+        relink(link)?;
+    }
+
+    Ok(Compressed {
+        pages: pages.len(),
+        links: links.len(),
+    })
+}
+
+/// Whether `path` names a page yet to be compressed
+fn is_page(path: &Path) -> bool {
+    let compressed = path
+        .extension()
+        .is_some_and(|extension| COMPRESSED.iter().any(|compressed| extension == *compressed));
+    let in_info = path.components().any(|component| component.as_os_str() == "info");
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+    // The info `dir` index is generated by triggers rather than packaged pages
+    !compressed && (!in_info || name.contains(".info"))
+}
+
+/// The file `link` finally resolves to within `root`, if any
+fn resolve(root: &Path, link: &Path) -> io::Result<Option<PathBuf>> {
+    let mut path = link.to_owned();
+
+    for _ in 0..MAX_LINKS {
+        if !path.is_symlink() {
+            return Ok(path.is_file().then_some(path));
+        }
+
+        let target = fs::read_link(&path)?;
+        path = match target.strip_prefix("/") {
+            Ok(absolute) => normalize(root, absolute),
+            Err(_) => normalize(root, &path.parent().unwrap_or(root).join(target)),
+        };
+    }
+
+    Ok(None)
+}
+
+/// `path` within `root` with `.` & `..` components resolved, never escaping `root`
+/// so aliases such as `../man1/nano.1` match the pages they name
+fn normalize(root: &Path, path: &Path) -> PathBuf {
+    let mut normalized = root.to_owned();
+
+    for component in path.strip_prefix(root).unwrap_or(path).components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::ParentDir => {
+                if normalized != root {
+                    normalized.pop();
+                }
+            }
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+
+    normalized
+}
+
+/// `path` with `.zst` appended
+fn zst(path: &Path) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(".zst");
+    path.into()
+}
+
+/// Replace `page` by its `.zst`, keeping its mode & times for reproducibility
+fn compress_page(page: &Path) -> io::Result<()> {
+    let metadata = fs::metadata(page)?;
+    let output = zst(page);
+
+    {
+        let mut reader = BufReader::new(File::open(page)?);
+        let mut writer = BufWriter::new(File::create(&output)?);
+        zstd::stream::copy_encode(&mut reader, &mut writer, LEVEL)?;
+        writer.flush()?;
+    }
+
+    fs::set_permissions(&output, Permissions::from_mode(metadata.permissions().mode()))?;
+    filetime::set_file_times(
+        &output,
+        FileTime::from_last_access_time(&metadata),
+        FileTime::from_last_modification_time(&metadata),
+    )?;
+
+    fs::remove_file(page)
+}
+
+/// Replace `link` by its `.zst`, linked to the `.zst` of its target
+fn relink(link: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(link)?;
+    let target = fs::read_link(link)?;
+    let output = zst(link);
+
+    symlink(zst(&target), &output)?;
+    filetime::set_symlink_file_times(
+        &output,
+        FileTime::from_last_access_time(&metadata),
+        FileTime::from_last_modification_time(&metadata),
+    )?;
+
+    fs::remove_file(link)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compress_install_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let man = root.join("usr/share/man");
+        let info = root.join("usr/share/info");
+        for dir in [
+            man.join("man1"),
+            man.join("man8"),
+            info.clone(),
+            root.join("usr/share/doc"),
+        ] {
+            fs::create_dir_all(dir).unwrap();
+        }
+
+        fs::write(man.join("man1/nano.1"), ".TH NANO 1\n").unwrap();
+        fs::set_permissions(man.join("man1/nano.1"), Permissions::from_mode(0o600)).unwrap();
+        filetime::set_file_mtime(man.join("man1/nano.1"), FileTime::from_unix_time(1_700_000_000, 0)).unwrap();
+        // Aliases, relative, absolute & chained
+        symlink("nano.1", man.join("man1/rnano.1")).unwrap();
+        symlink("/usr/share/man/man1/nano.1", man.join("man1/pico.1")).unwrap();
+        symlink("rnano.1", man.join("man1/rpico.1")).unwrap();
+        symlink("../man1/./nano.1", man.join("man8/nano.8")).unwrap();
+        symlink("/usr/../../usr/share/man/man1/nano.1", man.join("man8/pico.8")).unwrap();
+        symlink("missing.1", man.join("man1/dangling.1")).unwrap();
+        // Already compressed
+        fs::write(man.join("man8/nanod.8.gz"), "gzip").unwrap();
+        symlink("nanod.8.gz", man.join("man8/nanoctl.8.gz")).unwrap();
+        fs::write(info.join("nano.info"), "info").unwrap();
+        fs::write(info.join("dir"), "index").unwrap();
+        fs::write(root.join("usr/share/doc/nano.1"), "not a page").unwrap();
+
+        assert_eq!(compress(root).unwrap(), Compressed { pages: 2, links: 5 });
+
+        let page = man.join("man1/nano.1.zst");
+        assert!(!man.join("man1/nano.1").exists());
+        assert_eq!(zstd::decode_all(File::open(&page).unwrap()).unwrap(), b".TH NANO 1\n");
+        let metadata = fs::metadata(&page).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        assert_eq!(
+            FileTime::from_last_modification_time(&metadata).unix_seconds(),
+            1_700_000_000
+        );
+
+        for (link, target) in [
+            ("man1/rnano.1.zst", "nano.1.zst"),
+            ("man1/pico.1.zst", "/usr/share/man/man1/nano.1.zst"),
+            ("man1/rpico.1.zst", "rnano.1.zst"),
+            ("man8/nano.8.zst", "../man1/./nano.1.zst"),
+            ("man8/pico.8.zst", "/usr/../../usr/share/man/man1/nano.1.zst"),
+            ("man1/dangling.1", "missing.1"),
+            ("man8/nanoctl.8.gz", "nanod.8.gz"),
+        ] {
+            assert_eq!(fs::read_link(man.join(link)).unwrap(), Path::new(target), "{link}");
+        }
+        assert!(!man.join("man1/rnano.1").is_symlink());
+        assert_eq!(
+            fs::read(man.join("man1/rpico.1.zst")).unwrap(),
+            fs::read(&page).unwrap()
+        );
+
+        assert!(man.join("man8/nanod.8.gz").exists());
+        assert!(info.join("nano.info.zst").exists());
+        assert!(info.join("dir").exists());
+        assert!(root.join("usr/share/doc/nano.1").exists());
+
+        // Nothing left to compress
+        assert_eq!(compress(root).unwrap(), Compressed::default());
+    }
+}
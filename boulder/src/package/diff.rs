@@ -0,0 +1,430 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Contents of the stones built versus those of the previous release
+//!
+//! The layouts of each package are compared by path to those of the stone of
+//! its previous release, listing the paths added, removed & whose mode changed,
+//! along with how much the size of its files changed.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    io,
+    path::{Path, PathBuf},
+};
+
+use fs_err as fs;
+use humansize::BINARY;
+use itertools::Itertools;
+use moss::package::{Meta, MissingMetaFieldError};
+use serde::Serialize;
+use stone::{StonePayloadIndexRecord, StonePayloadLayoutFile, StonePayloadLayoutRecord, StoneReadError};
+use thiserror::Error;
+
+/// The layout of a stone, by path
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Contents {
+    /// i.e. `8.7-1-1`
+    pub release: String,
+    /// Mode & size of each path, relative to `/usr`
+    pub paths: BTreeMap<String, Entry>,
+}
+
+/// A path of a [`Contents`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Entry {
+    pub mode: u32,
+    /// Of regular files, zero for everything else
+    pub size: u64,
+}
+
+impl Contents {
+    /// Contents of the `layouts` of a stone released as `release`, sizing files by its `indices`
+    pub fn new(release: String, layouts: &[StonePayloadLayoutRecord], indices: &[StonePayloadIndexRecord]) -> Self {
+        let sizes = indices
+            .iter()
+            .map(|index| (index.digest, index.end - index.start))
+            .collect::<HashMap<_, _>>();
+
+        let paths = layouts
+            .iter()
+            .map(|layout| {
+                let size = match &layout.file {
+                    StonePayloadLayoutFile::Regular(hash, _) => sizes.get(hash).copied().unwrap_or_default(),
+                    _ => 0,
+                };
+                (
+                    layout.file.target().to_owned(),
+                    Entry {
+                        mode: layout.mode,
+                        size,
+                    },
+                )
+            })
+            .collect();
+
+        Self { release, paths }
+    }
+
+    /// Meta & contents of the stone at `path`
+    pub fn read(path: &Path) -> Result<(Meta, Self), Error> {
+        let mut reader = stone::read(fs::File::open(path)?)?;
+        let payloads = reader.payloads()?.collect::<Result<Vec<_>, _>>()?;
+
+        let meta = payloads
+            .iter()
+            .find_map(|payload| payload.meta())
+            .ok_or_else(|| Error::MissingMeta(path.to_owned()))?;
+        let meta = Meta::from_stone_payload(&meta.body)?;
+        let layouts = payloads
+            .iter()
+            .filter_map(|payload| payload.layout())
+            .flat_map(|payload| &payload.body)
+            .cloned()
+            .collect::<Vec<_>>();
+        let indices = payloads
+            .iter()
+            .filter_map(|payload| payload.index())
+            .flat_map(|payload| &payload.body)
+            .copied()
+            .collect::<Vec<_>>();
+
+        let contents = Self::new(release(&meta), &layouts, &indices);
+        Ok((meta, contents))
+    }
+
+    /// Size of the files
+    pub fn size(&self) -> u64 {
+        self.paths.values().map(|entry| entry.size).sum()
+    }
+}
+
+/// i.e. `8.7-1-1`
+fn release(meta: &Meta) -> String {
+    format!(
+        "{}-{}-{}",
+        meta.version_identifier, meta.source_release, meta.build_release
+    )
+}
+
+/// Contents of stones, by package name & architecture
+pub type Packages = BTreeMap<(String, String), Contents>;
+
+/// Contents of the latest stones of `dir` built from the source `source_id` before
+/// the release `(source_release, build_release)`, by package
+///
+/// Stones that can't be read are ignored, as the directory is shared by every build.
+pub fn previous_in(dir: &Path, source_id: &str, release: (u64, u64)) -> Packages {
+    let stones = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "stone"))
+        .sorted();
+
+    let mut latest = BTreeMap::<(String, String), ((u64, u64), PathBuf)>::new();
+    for path in stones {
+        let Some(meta) = read_meta(&path) else {
+            continue;
+        };
+        let released = (meta.source_release, meta.build_release);
+        if meta.source_id != source_id || released >= release {
+            continue;
+        }
+        let key = (meta.name.to_string(), meta.architecture);
+        if latest.get(&key).is_none_or(|(newest, _)| released > *newest) {
+            latest.insert(key, (released, path));
+        }
+    }
+
+    latest
+        .into_iter()
+        .filter_map(|(key, (_, path))| Some((key, Contents::read(&path).ok()?.1)))
+        .collect()
+}
+
+/// Contents of each of the `stones`, by package
+pub fn contents_of(stones: &[PathBuf]) -> Result<Packages, Error> {
+    stones
+        .iter()
+        .map(|path| {
+            let (meta, contents) = Contents::read(path)?;
+            Ok(((meta.name.to_string(), meta.architecture), contents))
+        })
+        .collect()
+}
+
+/// Meta of the stone at `path`, without decoding the payloads that follow it
+fn read_meta(path: &Path) -> Option<Meta> {
+    let mut reader = stone::read(fs::File::open(path).ok()?).ok()?;
+    let payload = reader
+        .payloads()
+        .ok()?
+        .find_map(|payload| payload.ok()?.meta().cloned())?;
+    Meta::from_stone_payload(&payload.body).ok()
+}
+
+/// Changes to the contents of a package since its previous release
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Diff {
+    pub package: String,
+    pub previous: String,
+    pub current: String,
+    /// Paths relative to `/usr`, as are all others
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed_mode: Vec<ModeChange>,
+    pub previous_size: u64,
+    pub current_size: u64,
+}
+
+/// A path whose mode changed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ModeChange {
+    pub path: String,
+    pub previous: u32,
+    pub current: u32,
+}
+
+impl Diff {
+    /// Changes from the `previous` contents of `package` to its `current` ones
+    pub fn new(package: &str, previous: &Contents, current: &Contents) -> Self {
+        let mut diff = Self {
+            package: package.to_owned(),
+            previous: previous.release.clone(),
+            current: current.release.clone(),
+            previous_size: previous.size(),
+            current_size: current.size(),
+            ..Self::default()
+        };
+
+        for entry in previous
+            .paths
+            .iter()
+            .merge_join_by(&current.paths, |(a, _), (b, _)| a.cmp(b))
+        {
+            match entry {
+                itertools::EitherOrBoth::Left((path, _)) => diff.removed.push(path.clone()),
+                itertools::EitherOrBoth::Right((path, _)) => diff.added.push(path.clone()),
+                itertools::EitherOrBoth::Both((path, previous), (_, current)) => {
+                    if previous.mode != current.mode {
+                        diff.changed_mode.push(ModeChange {
+                            path: path.clone(),
+                            previous: previous.mode,
+                            current: current.mode,
+                        });
+                    }
+                }
+            }
+        }
+
+        diff
+    }
+
+    /// Whether no path was added, removed or changed mode
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed_mode.is_empty()
+    }
+}
+
+/// Diffs of the packages of `current` with a release in `previous`, by package
+pub fn diff(previous: &Packages, current: &Packages) -> Vec<Diff> {
+    current
+        .iter()
+        .filter_map(|(key, contents)| Some(Diff::new(&key.0, previous.get(key)?, contents)))
+        .collect()
+}
+
+/// Render the `diffs`, a line per path & one sizing each package
+pub fn render(diffs: &[Diff]) -> String {
+    let mut text = String::new();
+
+    for diff in diffs {
+        let _ = writeln!(text, "{} ({} -> {})", diff.package, diff.previous, diff.current);
+        for path in &diff.added {
+            let _ = writeln!(text, "  + /usr/{path}");
+        }
+        for path in &diff.removed {
+            let _ = writeln!(text, "  - /usr/{path}");
+        }
+        for change in &diff.changed_mode {
+            let _ = writeln!(
+                text,
+                "  ~ /usr/{} ({:o} -> {:o})",
+                change.path, change.previous, change.current
+            );
+        }
+        if diff.is_empty() {
+            let _ = writeln!(text, "  No paths added, removed or changed");
+        }
+
+        let (sign, delta) = if diff.current_size >= diff.previous_size {
+            ('+', diff.current_size - diff.previous_size)
+        } else {
+            ('-', diff.previous_size - diff.current_size)
+        };
+        let _ = writeln!(
+            text,
+            "  Size {} -> {} ({sign}{})",
+            humansize::format_size(diff.previous_size, BINARY),
+            humansize::format_size(diff.current_size, BINARY),
+            humansize::format_size(delta, BINARY),
+        );
+    }
+
+    text
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("io")]
+    Io(#[from] io::Error),
+    #[error("read stone")]
+    Read(#[from] StoneReadError),
+    #[error("stone has no meta payload {0:?}")]
+    MissingMeta(PathBuf),
+    #[error("stone meta")]
+    Meta(#[from] MissingMetaFieldError),
+}
+
+#[cfg(test)]
+mod test {
+    use moss::package::{Meta, fixture};
+
+    use super::*;
+
+    fn layout(mode: u32, file: StonePayloadLayoutFile) -> StonePayloadLayoutRecord {
+        StonePayloadLayoutRecord {
+            uid: 0,
+            gid: 0,
+            mode,
+            tag: 0,
+            file,
+        }
+    }
+
+    fn regular(hash: u128, path: &str, mode: u32) -> StonePayloadLayoutRecord {
+        layout(0o100_000 | mode, StonePayloadLayoutFile::Regular(hash, path.into()))
+    }
+
+    fn index(digest: u128, start: u64, end: u64) -> StonePayloadIndexRecord {
+        StonePayloadIndexRecord { start, end, digest }
+    }
+
+    fn previous() -> Contents {
+        Contents::new(
+            "8.6-1-1".to_owned(),
+            &[
+                layout(0o40755, StonePayloadLayoutFile::Directory("bin".into())),
+                regular(1, "bin/nano", 0o755),
+                regular(2, "bin/nano-helper", 0o755),
+                layout(
+                    0o120_777,
+                    StonePayloadLayoutFile::Symlink("nano".into(), "bin/rnano".into()),
+                ),
+                regular(3, "share/nano/c.nanorc", 0o644),
+            ],
+            &[index(1, 0, 2048), index(2, 2048, 3072), index(3, 3072, 3584)],
+        )
+    }
+
+    fn current() -> Contents {
+        Contents::new(
+            "8.7-1-1".to_owned(),
+            &[
+                layout(0o40755, StonePayloadLayoutFile::Directory("bin".into())),
+                regular(4, "bin/nano", 0o755),
+                layout(
+                    0o120_777,
+                    StonePayloadLayoutFile::Symlink("nano".into(), "bin/rnano".into()),
+                ),
+                regular(3, "share/nano/c.nanorc", 0o755),
+                regular(5, "share/nano/rust.nanorc", 0o644),
+            ],
+            &[index(4, 0, 4096), index(3, 4096, 4608), index(5, 4608, 5120)],
+        )
+    }
+
+    #[test]
+    fn diff_layouts() {
+        let diff = Diff::new("nano", &previous(), &current());
+
+        assert_eq!(diff.added, ["share/nano/rust.nanorc"]);
+        assert_eq!(diff.removed, ["bin/nano-helper"]);
+        assert_eq!(
+            diff.changed_mode,
+            [ModeChange {
+                path: "share/nano/c.nanorc".to_owned(),
+                previous: 0o100_644,
+                current: 0o100_755,
+            }]
+        );
+        assert_eq!((diff.previous_size, diff.current_size), (3584, 5120));
+        assert!(!diff.is_empty());
+        assert!(Diff::new("nano", &current(), &current()).is_empty());
+    }
+
+    #[test]
+    fn render_diff() {
+        let key = |name: &str| (name.to_owned(), "x86_64".to_owned());
+        let previous = Packages::from([(key("nano"), previous()), (key("nano-devel"), current())]);
+        // Packages without a previous release aren't diffed
+        let current = Packages::from([
+            (key("nano"), current()),
+            (key("nano-devel"), current()),
+            (key("nano-docs"), current()),
+        ]);
+
+        assert_eq!(
+            render(&diff(&previous, &current)).lines().collect::<Vec<_>>(),
+            [
+                "nano (8.6-1-1 -> 8.7-1-1)",
+                "  + /usr/share/nano/rust.nanorc",
+                "  - /usr/bin/nano-helper",
+                "  ~ /usr/share/nano/c.nanorc (100644 -> 100755)",
+                "  Size 3.50 KiB -> 5 KiB (+1.50 KiB)",
+                "nano-devel (8.7-1-1 -> 8.7-1-1)",
+                "  No paths added, removed or changed",
+                "  Size 5 KiB -> 5 KiB (+0 B)",
+            ]
+        );
+    }
+
+    #[test]
+    fn latest_previous_release() {
+        let dir = tempfile::tempdir().unwrap();
+        // Built from the nano source, with a single directory
+        let fabricate = |name: &str, source_release| {
+            let meta = Meta {
+                version_identifier: "8.7".to_owned(),
+                source_release,
+                source_id: "nano".to_owned(),
+                ..fixture::meta(name)
+            };
+            let layouts = [layout(0o40755, StonePayloadLayoutFile::Directory("bin".into()))];
+            fixture::stone(dir.path(), &meta.to_stone_payload(), &layouts)
+        };
+        fabricate("nano", 1);
+        fabricate("nano", 3);
+        fabricate("nano-devel", 2);
+        // Not previous releases
+        fabricate("nano", 4);
+        fabricate("nano", 5);
+        fs::write(dir.path().join("broken.stone"), "").unwrap();
+
+        let previous = previous_in(dir.path(), "nano", (4, 1));
+        assert_eq!(
+            previous
+                .iter()
+                .map(|((name, _), contents)| (name.as_str(), contents.release.as_str()))
+                .collect::<Vec<_>>(),
+            [("nano", "8.7-3-1"), ("nano-devel", "8.7-2-1")]
+        );
+        assert_eq!(previous_in(dir.path(), "vim", (4, 1)), Packages::new());
+
+        let stone = fabricate("nano", 2);
+        let previous = contents_of(&[stone]).unwrap();
+        assert_eq!(previous[&("nano".to_owned(), "x86_64".to_owned())].release, "8.7-2-1");
+    }
+}
@@ -9,15 +9,23 @@ use std::{
 
 use fs_err::{self as fs, File};
 use itertools::Itertools;
-use moss::{Dependency, Provider, package::Meta, util};
+use moss::{
+    Dependency, Provider,
+    package::{Meta, Provenance},
+    util,
+};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use regex::Regex;
 use snafu::{ResultExt, Snafu};
-use stone::{StoneHeaderV1FileType, StoneWriteError, StoneWriter};
-use tui::{ProgressBar, ProgressStyle, Styled};
+use stone::{
+    StoneHeaderV1FileType, StonePayloadMetaPrimitive, StonePayloadMetaRecord, StonePayloadMetaTag, StoneWriteError,
+    StoneWriter,
+};
+use tui::{MultiProgress, ProgressBar, ProgressStyle, Styled};
 
 use self::manifest::Manifest;
 use super::analysis;
-use crate::{Architecture, Paths, Recipe, architecture};
+use crate::{Architecture, Paths, Recipe, architecture, artifacts, upstream::verify::Verification};
 
 mod manifest;
 
@@ -29,6 +37,10 @@ pub struct Package<'a> {
     pub source: &'a stone_recipe::Source,
     pub definition: &'a stone_recipe::Package,
     pub analysis: analysis::Bucket,
+    /// Hash of the inputs of the build, see [`crate::build::inputs`]
+    pub input_hash: &'a str,
+    /// Who built the package, recorded in its meta
+    pub provenance: &'a Provenance,
 }
 
 impl<'a> Package<'a> {
@@ -38,6 +50,8 @@ impl<'a> Package<'a> {
         template: &'a stone_recipe::Package,
         analysis: analysis::Bucket,
         build_release: NonZeroU64,
+        input_hash: &'a str,
+        provenance: &'a Provenance,
     ) -> Self {
         Self {
             name,
@@ -46,6 +60,8 @@ impl<'a> Package<'a> {
             definition: template,
             analysis,
             build_release,
+            input_hash,
+            provenance,
         }
     }
 
@@ -117,6 +133,7 @@ impl<'a> Package<'a> {
             uri: None,
             hash: None,
             download_size: None,
+            provenance: self.provenance.clone(),
         }
     }
 }
@@ -141,9 +158,17 @@ impl Ord for Package<'_> {
     }
 }
 
-pub fn emit(paths: &Paths, recipe: &Recipe, packages: &[Package<'_>]) -> Result<(), Error> {
-    let mut manifest = Manifest::new(paths, recipe, architecture::host());
-    let mut emit_manifests = true;
+/// Emit the stones of `packages` & their manifests, those of the recipe only unless `partial`
+pub fn emit(
+    paths: &Paths,
+    recipe: &Recipe,
+    packages: &[Package<'_>],
+    verifications: &[Verification],
+    partial: bool,
+) -> Result<(), Error> {
+    let mut manifest = Manifest::new(paths, recipe, architecture::host(), verifications);
+    // Those of a partial build would replace the manifests of the recipe with a subset
+    let mut emit_manifests = !partial;
 
     for package in packages {
         if !package.is_dbginfo() {
@@ -186,21 +211,34 @@ pub fn emit(paths: &Paths, recipe: &Recipe, packages: &[Package<'_>]) -> Result<
 
     println!("Packaging");
 
-    for package in packages {
-        emit_package(paths, package)?;
+    // Stones are written concurrently, each from its own bucket
+    let progress = MultiProgress::new();
+    let stones = packages
+        .par_iter()
+        .map(|package| emit_package(paths, package, &progress))
+        .collect::<Result<Vec<_>, _>>()?;
+    for stone in stones {
+        manifest.add_stone(stone);
     }
 
     if emit_manifests {
         manifest.write_binary().context(ManifestSnafu)?;
         manifest.write_json().context(ManifestSnafu)?;
     }
+    // Always written, as it lists what was built rather than being kept with the recipe
+    if let Some(package) = packages.first() {
+        manifest
+            .write_artifacts(package.build_release.get(), partial)
+            .context(ManifestSnafu)?;
+    }
 
     println!();
 
     Ok(())
 }
 
-fn emit_package(paths: &Paths, package: &Package<'_>) -> Result<(), Error> {
+/// Emit the stone of `package`, returning its summary for the artifacts manifest
+fn emit_package(paths: &Paths, package: &Package<'_>, progress: &MultiProgress) -> Result<artifacts::Stone, Error> {
     let filename = package.filename();
 
     // Filter for all files -> dedupe by hash -> sort largest to smallest
@@ -212,20 +250,22 @@ fn emit_package(paths: &Paths, package: &Package<'_>) -> Result<(), Error> {
         .filter_map(|info| info.file_hash().map(|hash| (hash, info)))
         // Dedupe by hash
         .unique_by(|(hash, _)| *hash)
-        // Sort largest to smallest
-        .sorted_by(|(_, a), (_, b)| a.size.cmp(&b.size).reverse())
+        // Sort largest to smallest, then by path so equal sizes are emitted in a stable order
+        .sorted_by(|(_, a), (_, b)| b.size.cmp(&a.size).then_with(|| a.target_path.cmp(&b.target_path)))
         .map(|(_, info)| info)
         .collect::<Vec<_>>();
 
     let total_file_size = files.iter().map(|info| info.size).sum();
 
-    let pb = ProgressBar::new(total_file_size)
-        .with_message(format!("Generating {filename}"))
-        .with_style(
-            ProgressStyle::with_template(" {spinner} |{percent:>3}%| {wide_msg} {binary_bytes_per_sec:>.dim} ")
-                .unwrap()
-                .tick_chars("--=≡■≡=--"),
-        );
+    let pb = progress.add(
+        ProgressBar::new(total_file_size)
+            .with_message(format!("Generating {filename}"))
+            .with_style(
+                ProgressStyle::with_template(" {spinner} |{percent:>3}%| {wide_msg} {binary_bytes_per_sec:>.dim} ")
+                    .unwrap()
+                    .tick_chars("--=≡■≡=--"),
+            ),
+    );
     pb.enable_steady_tick(Duration::from_millis(150));
 
     // Output file to artefacts directory
@@ -233,17 +273,22 @@ fn emit_package(paths: &Paths, package: &Package<'_>) -> Result<(), Error> {
     if out_path.exists() {
         fs::remove_file(&out_path).context(IoSnafu)?;
     }
-    let mut out_file = File::create(out_path).context(IoSnafu)?;
+    let mut out_file = File::create(&out_path).context(IoSnafu)?;
 
     // Create stone binary writer
     let mut writer = StoneWriter::new(&mut out_file, StoneHeaderV1FileType::Binary).context(StoneBinaryWriterSnafu)?;
 
+    let meta = package.meta();
+    let (dependencies, providers) = (meta.dependencies.len(), meta.providers.len());
+
     // Add metadata
     {
-        let meta = package.meta();
-        writer
-            .add_payload(meta.to_stone_payload().as_slice())
-            .context(StoneBinaryWriterSnafu)?;
+        let mut meta = meta.to_stone_payload();
+        meta.push(StonePayloadMetaRecord {
+            tag: StonePayloadMetaTag::BuildInputs,
+            primitive: StonePayloadMetaPrimitive::String(package.input_hash.to_owned()),
+        });
+        writer.add_payload(meta.as_slice()).context(StoneBinaryWriterSnafu)?;
     }
 
     // Add layouts
@@ -297,7 +342,18 @@ fn emit_package(paths: &Paths, package: &Package<'_>) -> Result<(), Error> {
     pb.suspend(|| println!("{} {filename}", "Emitted".green()));
     pb.finish_and_clear();
 
-    Ok(())
+    let size = fs::metadata(&out_path).context(IoSnafu)?.len();
+    let sha256 = util::sha256_hash(&mut File::open(&out_path).context(IoSnafu)?).context(IoSnafu)?;
+
+    Ok(artifacts::Stone {
+        package: package.name.to_owned(),
+        filename,
+        size,
+        sha256,
+        layouts: package.analysis.paths.len(),
+        dependencies,
+        providers,
+    })
 }
 
 #[derive(Debug, Snafu)]
@@ -16,7 +16,7 @@ use snafu::{ResultExt, Snafu};
 use stone::{StoneDecodedPayload, StoneReadError, StoneWriteError};
 use tempfile::NamedTempFile;
 
-use crate::{Architecture, Paths, Recipe};
+use crate::{Architecture, Paths, Recipe, artifacts, upstream::verify};
 
 use super::Package;
 
@@ -30,10 +30,19 @@ pub struct Manifest<'a> {
     output_dir: PathBuf,
     build_deps: BTreeSet<String>,
     packages: BTreeSet<&'a Package<'a>>,
+    /// Signatures of the upstreams verified, only written to the JSON manifest
+    verifications: &'a [verify::Verification],
+    /// Every stone emitted, including those of debug info
+    stones: Vec<artifacts::Stone>,
 }
 
 impl<'a> Manifest<'a> {
-    pub fn new(paths: &Paths, recipe: &'a Recipe, arch: Architecture) -> Self {
+    pub fn new(
+        paths: &Paths,
+        recipe: &'a Recipe,
+        arch: Architecture,
+        verifications: &'a [verify::Verification],
+    ) -> Self {
         let output_dir = paths.artefacts().guest;
 
         let build_deps = recipe
@@ -51,6 +60,8 @@ impl<'a> Manifest<'a> {
             arch,
             build_deps,
             packages: BTreeSet::new(),
+            verifications,
+            stones: vec![],
         }
     }
 
@@ -58,6 +69,34 @@ impl<'a> Manifest<'a> {
         self.packages.insert(package);
     }
 
+    /// Record a stone emitted, listed by the artifacts manifest
+    pub fn add_stone(&mut self, stone: artifacts::Stone) {
+        self.stones.push(stone);
+    }
+
+    /// Write the [`artifacts::Manifest`] of the stones emitted, at `build_release`,
+    /// `partial` if only some of the packages were
+    pub fn write_artifacts(&self, build_release: u64, partial: bool) -> Result<(), Error> {
+        let source = &self.recipe.parsed.source;
+        let manifest = artifacts::Manifest {
+            source_name: source.name.clone(),
+            source_version: source.version.clone(),
+            source_release: source.release,
+            build_release,
+            architecture: self.arch.to_string(),
+            stones: self.stones.clone(),
+            partial,
+        };
+
+        manifest
+            .save(&self.output_dir.join(artifacts::Manifest::file_name(
+                &source.name,
+                &source.version,
+                source.release,
+            )))
+            .context(ArtifactsSnafu)
+    }
+
     pub fn write_binary(&self) -> Result<(), Error> {
         let mut output =
             fs::File::create(self.output_dir.join(format!("manifest.{}.bin", self.arch))).context(IoSnafu)?;
@@ -71,6 +110,7 @@ impl<'a> Manifest<'a> {
             self.recipe,
             &self.packages,
             &self.build_deps,
+            self.verifications,
         )
     }
 
@@ -165,6 +205,8 @@ pub enum Error {
     ReadStonePayloads { source: StoneReadError },
     #[snafu(display("manifest missing meta field"))]
     ManifestMissingMetaField { source: MissingMetaFieldError },
+    #[snafu(display("artifacts manifest"))]
+    Artifacts { source: artifacts::Error },
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -3,7 +3,7 @@
 
 use std::{collections::BTreeSet, io::Write};
 
-use moss::Dependency;
+use moss::{Dependency, package::Provenance};
 use stone::{
     StoneHeaderV1FileType, StonePayloadMetaPrimitive, StonePayloadMetaRecord, StonePayloadMetaTag, StoneWriteError,
     StoneWriter,
@@ -23,6 +23,8 @@ pub fn write<W: Write>(
         let mut meta = package.meta();
         // deliberately override .stone package metadata and set build_release to zero for binary manifests
         meta.build_release = 0;
+        // likewise, who built the packages doesn't change what was built
+        meta.provenance = Provenance::default();
         let mut payload = meta.to_stone_payload();
 
         // Add build deps
@@ -14,13 +14,14 @@ use serde::Serialize;
 use snafu::ResultExt;
 
 use super::{Error, IoSnafu, JsonSnafu};
-use crate::{Recipe, package::emit};
+use crate::{Recipe, package::emit, upstream::verify::Verification};
 
 pub fn write(
     path: &Path,
     recipe: &Recipe,
     packages: &BTreeSet<&emit::Package<'_>>,
     build_deps: &BTreeSet<String>,
+    verifications: &[Verification],
 ) -> Result<(), Error> {
     let packages = packages
         .iter()
@@ -89,6 +90,7 @@ pub fn write(
         source_name: recipe.parsed.source.name.clone(),
         source_release: recipe.parsed.source.release.to_string(),
         source_version: recipe.parsed.source.version.clone(),
+        upstream_verifications: verifications.to_vec(),
     };
 
     let mut file = File::create(path).context(IoSnafu)?;
@@ -116,6 +118,8 @@ struct Content {
     source_name: String,
     source_release: String,
     source_version: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    upstream_verifications: Vec<Verification>,
 }
 
 #[derive(Serialize)]
@@ -0,0 +1,232 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Paths the build installed outside `/usr`
+//!
+//! The stateless layout only ships `/usr`, so paths installed to `/etc`, `/var`
+//! or `/opt` are left out of the stones. Rather than dropping them silently,
+//! they're reported with the phase most likely to have installed them, judged
+//! by their modification time against when each phase finished. Recipes list
+//! those known to be harmless with `allow_paths`.
+
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::Serialize;
+
+use super::collect;
+use crate::{Recipe, build, build::report::PhaseKey};
+
+/// A path installed outside `/usr`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Stray {
+    /// As installed, i.e. `/etc/foo.conf`
+    pub path: PathBuf,
+    /// Most likely to have installed it, i.e. `x86_64 install`
+    pub phase: Option<String>,
+    /// Of the recipe, where the script of the phase starts
+    pub line: Option<usize>,
+}
+
+impl fmt::Display for Stray {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is outside /usr and isn't packaged", self.path.display())?;
+
+        match (&self.phase, self.line) {
+            (Some(phase), Some(line)) => write!(f, ", likely installed by {phase} at line {line} of the recipe"),
+            (Some(phase), None) => write!(f, ", likely installed by {phase}"),
+            (None, _) => Ok(()),
+        }
+    }
+}
+
+/// Paths of `install_root` outside `/usr` not allowed by the `allow_paths` of
+/// `recipe`, attributed to the phases `finished`, in the order run
+pub fn check(install_root: &Path, recipe: &Recipe, finished: &[(PhaseKey, SystemTime)]) -> io::Result<Vec<Stray>> {
+    Ok(find(install_root, &recipe.parsed.allow_paths)?
+        .into_iter()
+        .map(|(path, modified)| {
+            let origin = origin(modified, finished);
+
+            Stray {
+                path,
+                phase: origin.map(|(target, pgo_stage, phase)| {
+                    let stage = pgo_stage
+                        .as_ref()
+                        .map(|stage| format!(" pgo-{stage}"))
+                        .unwrap_or_default();
+                    format!("{target}{stage} {}", phase.to_string().to_lowercase())
+                }),
+                line: origin.and_then(|(target, _, phase)| build::script_line(recipe, *target, *phase)),
+            }
+        })
+        .collect())
+}
+
+/// Paths of `install_root` outside `/usr`, as installed, with their modification
+/// time, unless matched by a pattern of `allowed`
+///
+/// Directories are only listed when empty, as their contents are listed otherwise.
+pub fn find(install_root: &Path, allowed: &[String]) -> io::Result<Vec<(PathBuf, SystemTime)>> {
+    let mut strays = vec![];
+
+    let entries = walkdir::WalkDir::new(install_root)
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| entry.depth() > 1 || entry.file_name() != "usr");
+
+    for entry in entries {
+        let entry = entry.map_err(io::Error::other)?;
+        let relative = entry
+            .path()
+            .strip_prefix(install_root)
+            .expect("walked from install root");
+        let path = Path::new("/").join(relative);
+
+        if entry.file_type().is_dir() && entry.path().read_dir()?.next().is_some() {
+            continue;
+        }
+        if is_allowed(&path, allowed) {
+            continue;
+        }
+
+        strays.push((path, entry.metadata().map_err(io::Error::other)?.modified()?));
+    }
+
+    Ok(strays)
+}
+
+/// Whether `path`, as installed, matches a pattern of `allowed` or is within a directory it lists
+fn is_allowed(path: &Path, allowed: &[String]) -> bool {
+    let path = path.to_string_lossy();
+
+    allowed
+        .iter()
+        // Directories listed as `/run/` are allowed themselves too
+        .map(|pattern| pattern.strip_suffix('/').unwrap_or(pattern))
+        .any(|pattern| collect::matches(pattern, &path))
+}
+
+/// The first of the phases `finished`, in the order run, to finish no earlier than `modified`
+///
+/// Phases run one after another, so a path was last modified by the phase running at the time.
+/// Those modified later were modified by boulder itself, and those with preserved modification
+/// times, i.e. extracted from an archive, are attributed to the first phase.
+fn origin<T>(modified: SystemTime, finished: &[(T, SystemTime)]) -> Option<&T> {
+    finished
+        .iter()
+        .find(|(_, finished)| *finished >= modified)
+        .map(|(phase, _)| phase)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs::FileTimes, time::Duration};
+
+    use fs_err as fs;
+
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    /// An install root of the `files` & empty `dirs`, each modified at the time given
+    fn install_root(files: &[(&str, u64)], dirs: &[&str]) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+
+        for dir in dirs {
+            fs::create_dir_all(root.path().join(dir)).unwrap();
+        }
+        for (path, modified) in files {
+            let path = root.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, path.display().to_string()).unwrap();
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_times(FileTimes::new().set_modified(at(*modified)))
+                .unwrap();
+        }
+
+        root
+    }
+
+    #[test]
+    fn find_outside_usr() {
+        let root = install_root(
+            &[
+                ("usr/bin/nano", 10),
+                ("usr/share/defaults/etc/nanorc", 10),
+                ("etc/nanorc", 20),
+                ("etc/nano/extra.conf", 30),
+                ("opt/nano/bin/nano", 10),
+                ("nano.conf", 40),
+            ],
+            &["usr/share/nano", "var/lib/nano", "run"],
+        );
+        let paths = |allowed: &[&str]| {
+            let allowed = allowed.iter().map(|pattern| pattern.to_string()).collect::<Vec<_>>();
+            find(root.path(), &allowed).unwrap()
+        };
+
+        assert_eq!(
+            paths(&[]),
+            [
+                (PathBuf::from("/etc/nano/extra.conf"), at(30)),
+                (PathBuf::from("/etc/nanorc"), at(20)),
+                (PathBuf::from("/nano.conf"), at(40)),
+                (PathBuf::from("/opt/nano/bin/nano"), at(10)),
+            ]
+            .into_iter()
+            .chain(["/run", "/var/lib/nano"].into_iter().map(|dir| (
+                PathBuf::from(dir),
+                fs::metadata(root.path().join(&dir[1..])).unwrap().modified().unwrap()
+            )))
+            .collect::<Vec<_>>()
+        );
+
+        // Exactly, as globs & by the directories containing them
+        let allowed = paths(&["/etc/nanorc", "/etc/nano", "/opt/*/bin/*", "/run/", "/var/lib/nano"]);
+        assert_eq!(
+            allowed.into_iter().map(|(path, _)| path).collect::<Vec<_>>(),
+            [PathBuf::from("/nano.conf")]
+        );
+
+        let root = install_root(&[("usr/bin/nano", 10)], &["usr/share/nano"]);
+        assert_eq!(find(root.path(), &[]).unwrap(), []);
+    }
+
+    #[test]
+    fn attribute_to_phase() {
+        let finished = [("setup", at(100)), ("build", at(200)), ("install", at(300))];
+
+        assert_eq!(origin(at(50), &finished), Some(&"setup"));
+        assert_eq!(origin(at(100), &finished), Some(&"setup"));
+        assert_eq!(origin(at(150), &finished), Some(&"build"));
+        assert_eq!(origin(at(250), &finished), Some(&"install"));
+        assert_eq!(origin(at(350), &finished), None);
+        assert_eq!(origin::<&str>(at(50), &[]), None);
+    }
+
+    #[test]
+    fn display() {
+        let stray = Stray {
+            path: PathBuf::from("/etc/nanorc"),
+            phase: Some("x86_64 install".to_owned()),
+            line: Some(24),
+        };
+        assert_eq!(
+            stray.to_string(),
+            "/etc/nanorc is outside /usr and isn't packaged, likely installed by x86_64 install at line 24 of the recipe"
+        );
+
+        let stray = Stray { phase: None, ..stray };
+        assert_eq!(stray.to_string(), "/etc/nanorc is outside /usr and isn't packaged");
+    }
+}
@@ -0,0 +1,734 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Software bills of materials of the stones built
+//!
+//! Each stone gets a document naming the source it was built from, the
+//! upstreams of that source & the packages installed into the build root.
+//! Documents are built from their inputs alone & dated by the build time of
+//! the recipe, so rebuilding a recipe against the same packages produces the
+//! same documents.
+
+use std::{io, path::Path};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use fs_err as fs;
+use moss::{package::Meta, util};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use url::Url;
+
+use crate::upstream;
+
+/// Format of the documents written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// CycloneDX 1.5 JSON
+    #[value(name = "cyclonedx")]
+    CycloneDx,
+    /// SPDX 2.3 JSON
+    SpdxJson,
+}
+
+impl Format {
+    /// Extension of the document of a stone
+    fn extension(self) -> &'static str {
+        match self {
+            Format::CycloneDx => "cdx.json",
+            Format::SpdxJson => "spdx.json",
+        }
+    }
+}
+
+/// An upstream of the source, as fetched
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Upstream {
+    Plain {
+        url: Url,
+        sha256: String,
+    },
+    Git {
+        url: Url,
+        commit: String,
+    },
+    /// A Mercurial or Fossil repository, at the id of a changeset or check-in
+    Vcs {
+        vcs: stone_recipe::upstream::Vcs,
+        url: Url,
+        id: String,
+    },
+}
+
+impl From<&upstream::Upstream> for Upstream {
+    fn from(upstream: &upstream::Upstream) -> Self {
+        match upstream {
+            upstream::Upstream::Plain(plain) => Upstream::Plain {
+                url: plain.url.clone(),
+                sha256: String::from(&*plain.hash),
+            },
+            upstream::Upstream::Git(git) => Upstream::Git {
+                url: git.url.clone(),
+                commit: git.commit.clone(),
+            },
+            upstream::Upstream::Vcs(vcs) => Upstream::Vcs {
+                vcs: vcs.kind,
+                url: vcs.url.clone(),
+                id: vcs.revision.to_string(),
+            },
+        }
+    }
+}
+
+/// A package installed into the build root
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Installed {
+    pub name: String,
+    pub version: String,
+    pub source_release: u64,
+    pub build_release: u64,
+    pub architecture: String,
+    /// SHA256 of the stone, if installed from a repository
+    pub sha256: Option<String>,
+}
+
+impl From<&Meta> for Installed {
+    fn from(meta: &Meta) -> Self {
+        Self {
+            name: meta.name.to_string(),
+            version: meta.version_identifier.clone(),
+            source_release: meta.source_release,
+            build_release: meta.build_release,
+            architecture: meta.architecture.clone(),
+            sha256: meta.hash.clone(),
+        }
+    }
+}
+
+/// What the build root was set up from
+///
+/// Saved within the build dir, as a resumed build reuses the root.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Materials {
+    pub upstreams: Vec<Upstream>,
+    pub installed: Vec<Installed>,
+    /// Signatures of the upstreams verified, see [`upstream::verify`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub verifications: Vec<upstream::verify::Verification>,
+}
+
+impl Materials {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        serde_json::from_slice(&fs::read(path)?).map_err(io::Error::other)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self).map_err(io::Error::other)?)
+    }
+}
+
+/// Everything a document is built from, besides the stone
+#[derive(Debug, Clone)]
+pub struct Inputs<'a> {
+    pub source: &'a stone_recipe::Source,
+    pub materials: &'a Materials,
+    /// When the stones were built
+    pub created: DateTime<Utc>,
+    /// Version of boulder
+    pub tool_version: String,
+}
+
+/// A stone emitted by the packager
+#[derive(Debug, Clone)]
+pub struct Stone {
+    pub filename: String,
+    pub meta: Meta,
+}
+
+/// Write the document of each of the `stones` alongside it, within `dir`
+pub fn write(dir: &Path, format: Format, inputs: &Inputs<'_>, stones: &[Stone]) -> Result<(), Error> {
+    for stone in stones {
+        let mut hasher = Sha256::new();
+        io::copy(&mut fs::File::open(dir.join(&stone.filename))?, &mut hasher)?;
+        let sha256 = hex::encode(hasher.finalize());
+
+        let document = document(format, inputs, stone, &sha256);
+
+        let name = stone.filename.strip_suffix(".stone").unwrap_or(&stone.filename);
+        fs::write(
+            dir.join(format!("{name}.{}", format.extension())),
+            serde_json::to_vec_pretty(&document)?,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// The document of `stone`, whose file hashes to `sha256`
+pub fn document(format: Format, inputs: &Inputs<'_>, stone: &Stone, sha256: &str) -> Value {
+    match format {
+        Format::CycloneDx => cyclonedx(inputs, stone, sha256),
+        Format::SpdxJson => spdx(inputs, stone, sha256),
+    }
+}
+
+/// A CycloneDX 1.5 document
+fn cyclonedx(inputs: &Inputs<'_>, stone: &Stone, sha256: &str) -> Value {
+    let source = inputs.source;
+    let meta = &stone.meta;
+    let stone_ref = purl(meta.name.as_str(), &version(meta), &meta.architecture);
+    let source_ref = format!("source:{}", source.name);
+
+    let mut stone_component = json!({
+        "type": "application",
+        "bom-ref": stone_ref,
+        "name": meta.name.to_string(),
+        "version": version(meta),
+        "description": meta.summary,
+        "hashes": [{ "alg": "SHA-256", "content": sha256 }],
+        "purl": stone_ref,
+    });
+    let mut source_component = json!({
+        "type": "application",
+        "bom-ref": source_ref,
+        "name": source.name,
+        "version": source.version,
+        "externalReferences": [{ "type": "website", "url": source.homepage }],
+    });
+    if let Some(expression) = license_expression(&source.license) {
+        stone_component["licenses"] = json!([{ "expression": expression }]);
+        source_component["licenses"] = json!([{ "expression": expression }]);
+    }
+
+    let upstreams = inputs.materials.upstreams.iter().map(|upstream| match upstream {
+        Upstream::Plain { url, sha256 } => json!({
+            "type": "file",
+            "bom-ref": url.as_str(),
+            "name": util::uri_file_name(url),
+            "hashes": [{ "alg": "SHA-256", "content": sha256 }],
+            "externalReferences": [{ "type": "distribution", "url": url.as_str() }],
+        }),
+        Upstream::Git { url, commit } => json!({
+            "type": "file",
+            "bom-ref": format!("{url}#{commit}"),
+            "name": util::uri_file_name(url).trim_end_matches(".git"),
+            "version": commit,
+            "externalReferences": [{ "type": "vcs", "url": url.as_str() }],
+        }),
+        Upstream::Vcs { url, id, .. } => json!({
+            "type": "file",
+            "bom-ref": format!("{url}#{id}"),
+            "name": util::uri_file_name(url),
+            "version": id,
+            "externalReferences": [{ "type": "vcs", "url": url.as_str() }],
+        }),
+    });
+    let installed = inputs.materials.installed.iter().map(|installed| {
+        let purl = installed_purl(installed);
+        let mut component = json!({
+            "type": "application",
+            "bom-ref": purl,
+            "name": installed.name,
+            "version": installed_version(installed),
+            "scope": "excluded",
+            "purl": purl,
+        });
+        if let Some(sha256) = &installed.sha256 {
+            component["hashes"] = json!([{ "alg": "SHA-256", "content": sha256 }]);
+        }
+        component
+    });
+
+    let components = [source_component]
+        .into_iter()
+        .chain(upstreams)
+        .chain(installed)
+        .collect::<Vec<_>>();
+    // Built from the source, which needs its upstreams & the build root
+    let source_dependencies = components[1..]
+        .iter()
+        .map(|component| component["bom-ref"].clone())
+        .collect::<Vec<_>>();
+
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "serialNumber": format!("urn:uuid:{}", uuid(sha256, Format::CycloneDx)),
+        "version": 1,
+        "metadata": {
+            "timestamp": timestamp(inputs.created),
+            "tools": {
+                "components": [{ "type": "application", "name": "boulder", "version": inputs.tool_version }],
+            },
+            "component": stone_component,
+        },
+        "components": components,
+        "dependencies": [
+            { "ref": stone_ref, "dependsOn": [source_ref] },
+            { "ref": source_ref, "dependsOn": source_dependencies },
+        ],
+    })
+}
+
+/// An SPDX 2.3 document
+fn spdx(inputs: &Inputs<'_>, stone: &Stone, sha256: &str) -> Value {
+    let source = inputs.source;
+    let meta = &stone.meta;
+    let license = license_expression(&source.license).unwrap_or_else(|| "NOASSERTION".to_owned());
+    let relationship = |element: &str, kind: &str, related: &str| json!({ "spdxElementId": element, "relationshipType": kind, "relatedSpdxElement": related });
+
+    let mut packages = vec![
+        json!({
+            "SPDXID": "SPDXRef-Stone",
+            "name": meta.name.to_string(),
+            "versionInfo": version(meta),
+            "packageFileName": stone.filename,
+            "downloadLocation": "NOASSERTION",
+            "filesAnalyzed": false,
+            "checksums": [{ "algorithm": "SHA256", "checksumValue": sha256 }],
+            "homepage": source.homepage,
+            "licenseConcluded": "NOASSERTION",
+            "licenseDeclared": license,
+            "copyrightText": "NOASSERTION",
+            "summary": meta.summary,
+            "primaryPackagePurpose": "APPLICATION",
+            "externalRefs": [purl_ref(&purl(meta.name.as_str(), &version(meta), &meta.architecture))],
+        }),
+        json!({
+            "SPDXID": "SPDXRef-Source",
+            "name": source.name,
+            "versionInfo": source.version,
+            "downloadLocation": "NOASSERTION",
+            "filesAnalyzed": false,
+            "homepage": source.homepage,
+            "licenseConcluded": "NOASSERTION",
+            "licenseDeclared": license,
+            "copyrightText": "NOASSERTION",
+            "primaryPackagePurpose": "SOURCE",
+        }),
+    ];
+    let mut relationships = vec![
+        relationship("SPDXRef-DOCUMENT", "DESCRIBES", "SPDXRef-Stone"),
+        relationship("SPDXRef-Stone", "GENERATED_FROM", "SPDXRef-Source"),
+    ];
+
+    for (index, upstream) in inputs.materials.upstreams.iter().enumerate() {
+        let id = format!("SPDXRef-Upstream-{index}");
+
+        packages.push(match upstream {
+            Upstream::Plain { url, sha256 } => json!({
+                "SPDXID": id,
+                "name": util::uri_file_name(url),
+                "downloadLocation": url.as_str(),
+                "filesAnalyzed": false,
+                "checksums": [{ "algorithm": "SHA256", "checksumValue": sha256 }],
+                "licenseConcluded": "NOASSERTION",
+                "licenseDeclared": "NOASSERTION",
+                "copyrightText": "NOASSERTION",
+                "primaryPackagePurpose": "ARCHIVE",
+            }),
+            Upstream::Git { url, commit } => json!({
+                "SPDXID": id,
+                "name": util::uri_file_name(url).trim_end_matches(".git"),
+                "versionInfo": commit,
+                "downloadLocation": format!("git+{url}@{commit}"),
+                "filesAnalyzed": false,
+                "licenseConcluded": "NOASSERTION",
+                "licenseDeclared": "NOASSERTION",
+                "copyrightText": "NOASSERTION",
+                "primaryPackagePurpose": "SOURCE",
+            }),
+            Upstream::Vcs {
+                vcs,
+                url,
+                id: changeset,
+            } => json!({
+                "SPDXID": id,
+                "name": util::uri_file_name(url),
+                "versionInfo": changeset,
+                // SPDX has no scheme for Fossil
+                "downloadLocation": match vcs {
+                    stone_recipe::upstream::Vcs::Mercurial => format!("hg+{url}@{changeset}"),
+                    stone_recipe::upstream::Vcs::Fossil => url.to_string(),
+                },
+                "filesAnalyzed": false,
+                "licenseConcluded": "NOASSERTION",
+                "licenseDeclared": "NOASSERTION",
+                "copyrightText": "NOASSERTION",
+                "primaryPackagePurpose": "SOURCE",
+            }),
+        });
+        relationships.push(relationship("SPDXRef-Source", "CONTAINS", &id));
+    }
+
+    for (index, installed) in inputs.materials.installed.iter().enumerate() {
+        let id = format!("SPDXRef-Installed-{index}");
+
+        let mut package = json!({
+            "SPDXID": id,
+            "name": installed.name,
+            "versionInfo": installed_version(installed),
+            "downloadLocation": "NOASSERTION",
+            "filesAnalyzed": false,
+            "licenseConcluded": "NOASSERTION",
+            "licenseDeclared": "NOASSERTION",
+            "copyrightText": "NOASSERTION",
+            "externalRefs": [purl_ref(&installed_purl(installed))],
+        });
+        if let Some(sha256) = &installed.sha256 {
+            package["checksums"] = json!([{ "algorithm": "SHA256", "checksumValue": sha256 }]);
+        }
+        packages.push(package);
+        relationships.push(relationship(&id, "BUILD_DEPENDENCY_OF", "SPDXRef-Stone"));
+    }
+
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": stone.filename,
+        "documentNamespace": format!(
+            "https://aerynos.com/spdx/{}-{}",
+            stone.filename.trim_end_matches(".stone"),
+            uuid(sha256, Format::SpdxJson)
+        ),
+        "creationInfo": {
+            "created": timestamp(inputs.created),
+            "creators": [format!("Tool: boulder-{}", inputs.tool_version)],
+        },
+        "packages": packages,
+        "relationships": relationships,
+    })
+}
+
+fn version(meta: &Meta) -> String {
+    format!(
+        "{}-{}-{}",
+        meta.version_identifier, meta.source_release, meta.build_release
+    )
+}
+
+fn installed_version(installed: &Installed) -> String {
+    format!(
+        "{}-{}-{}",
+        installed.version, installed.source_release, installed.build_release
+    )
+}
+
+fn installed_purl(installed: &Installed) -> String {
+    purl(&installed.name, &installed_version(installed), &installed.architecture)
+}
+
+/// A generic package URL, as stones have no type of their own
+fn purl(name: &str, version: &str, architecture: &str) -> String {
+    let encode = |text: &str| {
+        text.chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '~') {
+                    c.to_string()
+                } else {
+                    format!("%{:02X}", c as u32)
+                }
+            })
+            .collect::<String>()
+    };
+
+    format!(
+        "pkg:generic/{}@{}?arch={}",
+        encode(name),
+        encode(version),
+        encode(architecture)
+    )
+}
+
+fn purl_ref(purl: &str) -> Value {
+    json!({ "referenceCategory": "PACKAGE-MANAGER", "referenceType": "purl", "referenceLocator": purl })
+}
+
+fn license_expression(licenses: &[String]) -> Option<String> {
+    (!licenses.is_empty()).then(|| licenses.join(" AND "))
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// A name based UUID of the document of the stone hashing to `sha256`
+fn uuid(sha256: &str, format: Format) -> String {
+    let mut bytes = Sha256::new()
+        .chain_update(sha256)
+        .chain_update(format.extension())
+        .finalize();
+    // Version 5 & RFC 4122 variant, though hashed by SHA256
+    bytes[6] = (bytes[6] & 0x0f) | 0x50;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex = hex::encode(&bytes[..16]);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("io")]
+    Io(#[from] io::Error),
+    #[error("serialize")]
+    Serialize(#[from] serde_json::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use chrono::TimeZone;
+    use moss::package::fixture;
+    use regex::Regex;
+
+    use super::*;
+
+    const STONE_SHA256: &str = "1111111111111111111111111111111111111111111111111111111111111111";
+
+    fn source() -> stone_recipe::Source {
+        stone_recipe::Source {
+            name: "nano".to_owned(),
+            version: "8.7".to_owned(),
+            release: 3,
+            homepage: "https://nano-editor.org".to_owned(),
+            license: vec!["GPL-3.0-or-later".to_owned(), "GFDL-1.2-or-later".to_owned()],
+            monitoring: None,
+        }
+    }
+
+    fn materials() -> Materials {
+        Materials {
+            upstreams: vec![
+                Upstream::Plain {
+                    url: "https://nano-editor.org/dist/v8/nano-8.7.tar.xz".parse().unwrap(),
+                    sha256: "afd287aa672c48b8e1a93fdb6c6588453d527510d966822b687f2835f0d986e9".to_owned(),
+                },
+                Upstream::Git {
+                    url: "https://example.com/syntax.git".parse().unwrap(),
+                    commit: "aaaa1111bbbb2222cccc3333dddd4444eeee5555".to_owned(),
+                },
+            ],
+            installed: vec![
+                Installed {
+                    name: "glibc-devel".to_owned(),
+                    version: "2.42".to_owned(),
+                    source_release: 12,
+                    build_release: 1,
+                    architecture: "x86_64".to_owned(),
+                    sha256: Some("2222222222222222222222222222222222222222222222222222222222222222".to_owned()),
+                },
+                Installed {
+                    name: "libstdc++".to_owned(),
+                    version: "15.2.0".to_owned(),
+                    source_release: 4,
+                    build_release: 1,
+                    architecture: "x86_64".to_owned(),
+                    sha256: None,
+                },
+            ],
+            verifications: vec![upstream::verify::Verification {
+                upstream: "syntax.git".to_owned(),
+                method: upstream::verify::Method::Commit,
+                object: "aaaa1111bbbb2222cccc3333dddd4444eeee5555".to_owned(),
+                signer: upstream::verify::Signer {
+                    key: "SHA256:GsXeXABiunzzQJuNY89CyLSr+Mwo1J2p578m5tcnFzA".to_owned(),
+                    identity: Some("maintainer@example.com".to_owned()),
+                },
+            }],
+        }
+    }
+
+    fn stone() -> Stone {
+        Stone {
+            filename: "nano-8.7-3-1-x86_64.stone".to_owned(),
+            meta: Meta {
+                version_identifier: "8.7".to_owned(),
+                source_release: 3,
+                summary: "GNU Text Editor".to_owned(),
+                description: "GNU Text Editor".to_owned(),
+                homepage: "https://nano-editor.org".to_owned(),
+                licenses: vec!["GFDL-1.2-or-later".to_owned(), "GPL-3.0-or-later".to_owned()],
+                ..fixture::meta("nano")
+            },
+        }
+    }
+
+    fn inputs<'a>(source: &'a stone_recipe::Source, materials: &'a Materials) -> Inputs<'a> {
+        Inputs {
+            source,
+            materials,
+            created: Utc.with_ymd_and_hms(2026, 1, 2, 3, 4, 5).unwrap(),
+            tool_version: "0.26.6".to_owned(),
+        }
+    }
+
+    fn matches(pattern: &str, value: &Value) -> bool {
+        value
+            .as_str()
+            .is_some_and(|value| Regex::new(pattern).unwrap().is_match(value))
+    }
+
+    fn assert_sha256(value: &Value) {
+        assert!(matches("^[0-9a-f]{64}$", value), "{value}");
+    }
+
+    /// Assert the constraints of the CycloneDX 1.5 JSON schema on what's emitted
+    fn validate_cyclonedx(document: &Value) {
+        assert_eq!(document["bomFormat"], "CycloneDX");
+        assert_eq!(document["specVersion"], "1.5");
+        assert!(matches(
+            "^urn:uuid:[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}$",
+            &document["serialNumber"]
+        ));
+        assert!(document["version"].as_u64().is_some_and(|version| version >= 1));
+        assert!(DateTime::parse_from_rfc3339(document["metadata"]["timestamp"].as_str().unwrap()).is_ok());
+
+        let components = [&document["metadata"]["component"]]
+            .into_iter()
+            .chain(document["components"].as_array().unwrap())
+            .collect::<Vec<_>>();
+        let mut refs = BTreeSet::new();
+        for component in &components {
+            assert!(["application", "library", "file"].contains(&component["type"].as_str().unwrap()));
+            assert!(component["name"].is_string());
+            assert!(refs.insert(component["bom-ref"].as_str().unwrap()), "unique bom-ref");
+            if let Some(scope) = component.get("scope") {
+                assert!(["required", "optional", "excluded"].contains(&scope.as_str().unwrap()));
+            }
+            for hash in component["hashes"].as_array().into_iter().flatten() {
+                assert_eq!(hash["alg"], "SHA-256");
+                assert_sha256(&hash["content"]);
+            }
+            for reference in component["externalReferences"].as_array().into_iter().flatten() {
+                assert!(["website", "distribution", "vcs"].contains(&reference["type"].as_str().unwrap()));
+                assert!(Url::parse(reference["url"].as_str().unwrap()).is_ok());
+            }
+            if let Some(purl) = component.get("purl") {
+                assert!(matches("^pkg:generic/[^/@?]+@[^/@?]+\\?arch=[^/@?&]+$", purl));
+            }
+        }
+
+        for dependency in document["dependencies"].as_array().unwrap() {
+            assert!(refs.contains(dependency["ref"].as_str().unwrap()));
+            for depends_on in dependency["dependsOn"].as_array().unwrap() {
+                assert!(refs.contains(depends_on.as_str().unwrap()));
+            }
+        }
+    }
+
+    /// Assert the constraints of the SPDX 2.3 JSON schema on what's emitted
+    fn validate_spdx(document: &Value) {
+        assert_eq!(document["spdxVersion"], "SPDX-2.3");
+        assert_eq!(document["dataLicense"], "CC0-1.0");
+        assert_eq!(document["SPDXID"], "SPDXRef-DOCUMENT");
+        assert!(document["name"].is_string());
+        assert!(Url::parse(document["documentNamespace"].as_str().unwrap()).is_ok());
+        assert!(matches(
+            "^\\d{4}-\\d{2}-\\d{2}T\\d{2}:\\d{2}:\\d{2}Z$",
+            &document["creationInfo"]["created"]
+        ));
+        for creator in document["creationInfo"]["creators"].as_array().unwrap() {
+            assert!(matches("^(Tool|Organization|Person): .+", creator));
+        }
+
+        let mut ids = BTreeSet::from(["SPDXRef-DOCUMENT"]);
+        for package in document["packages"].as_array().unwrap() {
+            assert!(matches("^SPDXRef-[A-Za-z0-9.-]+$", &package["SPDXID"]));
+            assert!(ids.insert(package["SPDXID"].as_str().unwrap()), "unique SPDXID");
+            assert!(package["name"].is_string());
+            assert!(package["downloadLocation"].is_string());
+            assert_eq!(package["filesAnalyzed"], false);
+            for checksum in package["checksums"].as_array().into_iter().flatten() {
+                assert_eq!(checksum["algorithm"], "SHA256");
+                assert_sha256(&checksum["checksumValue"]);
+            }
+            if let Some(purpose) = package.get("primaryPackagePurpose") {
+                assert!(["APPLICATION", "SOURCE", "ARCHIVE"].contains(&purpose.as_str().unwrap()));
+            }
+            for reference in package["externalRefs"].as_array().into_iter().flatten() {
+                assert_eq!(reference["referenceCategory"], "PACKAGE-MANAGER");
+                assert_eq!(reference["referenceType"], "purl");
+            }
+        }
+
+        for relationship in document["relationships"].as_array().unwrap() {
+            assert!(ids.contains(relationship["spdxElementId"].as_str().unwrap()));
+            assert!(ids.contains(relationship["relatedSpdxElement"].as_str().unwrap()));
+            assert!(
+                ["DESCRIBES", "GENERATED_FROM", "CONTAINS", "BUILD_DEPENDENCY_OF"]
+                    .contains(&relationship["relationshipType"].as_str().unwrap())
+            );
+        }
+    }
+
+    #[test]
+    fn cyclonedx() {
+        let (source, materials) = (source(), materials());
+        let document = document(Format::CycloneDx, &inputs(&source, &materials), &stone(), STONE_SHA256);
+
+        validate_cyclonedx(&document);
+        assert_eq!(
+            document,
+            serde_json::from_str::<Value>(include_str!("../../../test/sbom/nano-8.7-3-1-x86_64.cdx.json")).unwrap()
+        );
+    }
+
+    #[test]
+    fn spdx() {
+        let (source, materials) = (source(), materials());
+        let document = document(Format::SpdxJson, &inputs(&source, &materials), &stone(), STONE_SHA256);
+
+        validate_spdx(&document);
+        assert_eq!(
+            document,
+            serde_json::from_str::<Value>(include_str!("../../../test/sbom/nano-8.7-3-1-x86_64.spdx.json")).unwrap()
+        );
+    }
+
+    #[test]
+    fn written() {
+        let dir = tempfile::tempdir().unwrap();
+        let (source, materials) = (source(), materials());
+        let inputs = inputs(&source, &materials);
+        let stone = stone();
+        fs::write(dir.path().join(&stone.filename), "stone").unwrap();
+
+        for format in [Format::CycloneDx, Format::SpdxJson] {
+            write(dir.path(), format, &inputs, std::slice::from_ref(&stone)).unwrap();
+        }
+
+        let read = |name: &str| serde_json::from_slice::<Value>(&fs::read(dir.path().join(name)).unwrap()).unwrap();
+        let cyclonedx = read("nano-8.7-3-1-x86_64.cdx.json");
+        let spdx = read("nano-8.7-3-1-x86_64.spdx.json");
+
+        // Describing the stone as written
+        let sha256 = hex::encode(Sha256::digest("stone"));
+        assert_eq!(cyclonedx["metadata"]["component"]["hashes"][0]["content"], sha256);
+        assert_eq!(spdx["packages"][0]["checksums"][0]["checksumValue"], sha256);
+        assert_eq!(cyclonedx, document(Format::CycloneDx, &inputs, &stone, &sha256));
+
+        // Documents of another stone are told apart
+        let other = document(Format::CycloneDx, &inputs, &stone, STONE_SHA256);
+        assert_ne!(cyclonedx["serialNumber"], other["serialNumber"]);
+    }
+
+    #[test]
+    fn materials_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("materials.json");
+
+        materials().save(&path).unwrap();
+        assert_eq!(Materials::load(&path).unwrap(), materials());
+    }
+}
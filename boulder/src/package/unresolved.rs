@@ -0,0 +1,281 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Runtime dependencies of the packaged ELF files that nothing provides
+//!
+//! moss only resolves the shared libraries & interpreter an ELF file needs once
+//! its package is installed, so those neither provided by the packages of the
+//! build nor by the repositories of its profile are reported after packaging.
+
+use std::{
+    collections::BTreeSet,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use moss::{Dependency, Provider, dependency};
+use rayon::prelude::*;
+use serde::Serialize;
+
+use super::{analysis::handler::elf, emit};
+
+/// The shared libraries & interpreter an ELF file needs at runtime
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Needed {
+    /// i.e. `x86_64`
+    pub machine: String,
+    /// Sonames of its `DT_NEEDED` entries, in order
+    pub libraries: Vec<String>,
+    pub interpreter: Option<String>,
+}
+
+impl Needed {
+    /// What the file at `path` needs, if it's an ELF file
+    pub fn read(path: &Path) -> Option<Self> {
+        let mut file = elf::parse_elf(path).ok()?;
+
+        Some(Self {
+            machine: elf::machine_isa(&file),
+            libraries: elf::needed(&mut file),
+            interpreter: elf::interpreter(&mut file),
+        })
+    }
+
+    /// As depended on by the package of the file, see [`crate::package::analysis`]
+    pub fn dependencies(&self) -> impl Iterator<Item = Dependency> + '_ {
+        let dependency = |kind, name: &str| Dependency {
+            kind,
+            name: format!("{name}({})", self.machine),
+        };
+
+        self.libraries
+            .iter()
+            .map(move |library| dependency(dependency::Kind::SharedLibrary, library))
+            .chain(
+                self.interpreter
+                    .iter()
+                    .map(move |interpreter| dependency(dependency::Kind::Interpreter, interpreter)),
+            )
+    }
+}
+
+/// A runtime dependency of a packaged file that nothing provides
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Unresolved {
+    pub package: String,
+    /// Of the file, as installed
+    pub path: PathBuf,
+    /// i.e. `soname(libfoo.so.1(x86_64))`
+    pub dependency: String,
+}
+
+impl fmt::Display for Unresolved {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} needs {}, which no package provides",
+            self.path.display(),
+            self.package,
+            self.dependency
+        )
+    }
+}
+
+/// What the runtime dependencies of the packages built may resolve to
+#[derive(Debug)]
+pub struct Resolver<'a> {
+    /// Providers of the packages built
+    built: BTreeSet<&'a Provider>,
+    /// Paths of the packages built, as installed
+    paths: BTreeSet<&'a Path>,
+    /// Providers of the packages of the repositories
+    available: &'a BTreeSet<Provider>,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(available: &'a BTreeSet<Provider>) -> Self {
+        Self {
+            built: BTreeSet::new(),
+            paths: BTreeSet::new(),
+            available,
+        }
+    }
+
+    /// Add the `providers` & `paths` of a package built
+    pub fn add_package(
+        &mut self,
+        providers: impl IntoIterator<Item = &'a Provider>,
+        paths: impl IntoIterator<Item = &'a Path>,
+    ) {
+        self.built.extend(providers);
+        self.paths.extend(paths);
+    }
+
+    /// Whether a package built or one of the repositories provides `dependency`
+    pub fn resolves(&self, dependency: &Dependency) -> bool {
+        let provider = Provider {
+            kind: dependency.kind,
+            name: dependency.name.clone(),
+        };
+        if self.built.contains(&provider) || self.available.contains(&provider) {
+            return true;
+        }
+
+        // Without the machine, i.e. `libfoo.so.1`
+        let name = dependency
+            .name
+            .rsplit_once('(')
+            .map_or(dependency.name.as_str(), |(name, _)| name);
+
+        // Built outside of the library dirs, found through the rpath of the file
+        match dependency.kind {
+            dependency::Kind::SharedLibrary => self
+                .paths
+                .iter()
+                .any(|path| path.file_name().is_some_and(|file_name| file_name == name)),
+            dependency::Kind::Interpreter => self.paths.contains(Path::new(name)),
+            _ => true,
+        }
+    }
+}
+
+/// The runtime dependencies of the ELF files of `packages` that neither they
+/// nor the packages of the repositories, providing those `available`, provide
+pub fn find(packages: &[emit::Package<'_>], available: &BTreeSet<Provider>) -> Vec<Unresolved> {
+    let mut resolver = Resolver::new(available);
+    for package in packages {
+        resolver.add_package(
+            package.analysis.providers(),
+            package.analysis.paths.iter().map(|info| info.target_path.as_path()),
+        );
+    }
+
+    let files = packages
+        .iter()
+        .flat_map(|package| {
+            package
+                .analysis
+                .paths
+                .iter()
+                // Split debug info isn't loaded at runtime
+                .filter(|info| info.is_file() && !(info.file_name().ends_with(".debug") && info.has_component("debug")))
+                .map(move |info| (package.name, info))
+        })
+        .collect::<Vec<_>>();
+
+    let mut unresolved = files
+        .into_par_iter()
+        .flat_map_iter(|(package, info)| {
+            let needed = Needed::read(&info.path);
+            let resolver = &resolver;
+
+            needed
+                .into_iter()
+                .flat_map(|needed| needed.dependencies().collect::<Vec<_>>())
+                .filter(move |dependency| !resolver.resolves(dependency))
+                .map(move |dependency| Unresolved {
+                    package: package.to_owned(),
+                    path: info.target_path.clone(),
+                    dependency: dependency.to_string(),
+                })
+        })
+        .collect::<Vec<_>>();
+    unresolved.sort();
+
+    unresolved
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("../test/elf").join(name)
+    }
+
+    fn provider(kind: dependency::Kind, name: &str) -> Provider {
+        Provider {
+            kind,
+            name: name.to_owned(),
+        }
+    }
+
+    #[test]
+    fn read_needed() {
+        assert_eq!(
+            Needed::read(&fixture("needs-fixture")).unwrap(),
+            Needed {
+                machine: "x86_64".to_owned(),
+                libraries: vec!["libfixture.so.1".to_owned(), "libc.so.6".to_owned()],
+                interpreter: Some("/lib64/ld-linux-x86-64.so.2".to_owned()),
+            }
+        );
+
+        let library = Needed::read(&fixture("libfixture.so.1")).unwrap();
+        assert!(library.libraries.is_empty());
+        assert_eq!(library.interpreter, None);
+
+        assert_eq!(
+            Needed::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml").as_path()),
+            None
+        );
+        assert_eq!(Needed::read(&fixture("missing")), None);
+    }
+
+    #[test]
+    fn resolve() {
+        let needed = Needed::read(&fixture("needs-fixture")).unwrap();
+        assert_eq!(
+            needed
+                .dependencies()
+                .map(|dependency| dependency.to_string())
+                .collect::<Vec<_>>(),
+            [
+                "soname(libfixture.so.1(x86_64))",
+                "soname(libc.so.6(x86_64))",
+                "interpreter(/lib64/ld-linux-x86-64.so.2(x86_64))",
+            ]
+        );
+
+        let available = BTreeSet::from([
+            provider(dependency::Kind::SharedLibrary, "libc.so.6(x86_64)"),
+            provider(dependency::Kind::Interpreter, "/lib64/ld-linux-x86-64.so.2(x86_64)"),
+        ]);
+        let unresolved = |resolver: &Resolver<'_>| {
+            needed
+                .dependencies()
+                .filter(|dependency| !resolver.resolves(dependency))
+                .map(|dependency| dependency.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // Nothing built provides the library
+        let mut resolver = Resolver::new(&available);
+        assert_eq!(unresolved(&resolver), ["soname(libfixture.so.1(x86_64))"]);
+
+        // Built for another machine
+        let provided = [provider(dependency::Kind::SharedLibrary, "libfixture.so.1(aarch64)")];
+        resolver.add_package(&provided, []);
+        assert_eq!(unresolved(&resolver), ["soname(libfixture.so.1(x86_64))"]);
+
+        // Built in a private dir, found through the rpath
+        resolver.add_package([], [Path::new("/usr/lib/fixture/libfixture.so.1")]);
+        assert!(unresolved(&resolver).is_empty());
+
+        let provided = [provider(dependency::Kind::SharedLibrary, "libfixture.so.1(x86_64)")];
+        let mut resolver = Resolver::new(&available);
+        resolver.add_package(&provided, []);
+        assert!(unresolved(&resolver).is_empty());
+
+        // Neither is the interpreter without the repositories, unless built
+        let none = BTreeSet::new();
+        let mut resolver = Resolver::new(&none);
+        resolver.add_package(&provided, [Path::new("/usr/lib/libc.so.6")]);
+        assert_eq!(
+            unresolved(&resolver),
+            ["interpreter(/lib64/ld-linux-x86-64.so.2(x86_64))"]
+        );
+        resolver.add_package([], [Path::new("/lib64/ld-linux-x86-64.so.2")]);
+        assert!(unresolved(&resolver).is_empty());
+    }
+}
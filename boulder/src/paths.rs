@@ -1,10 +1,14 @@
 // SPDX-FileCopyrightText: 2024 AerynOS Developers
 // SPDX-License-Identifier: MPL-2.0
 
-use std::{io, path::PathBuf};
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+};
 
 use derive_more::Debug;
 use moss::util;
+use stone_recipe::tuning::Toolchain;
 
 use crate::Recipe;
 
@@ -29,6 +33,7 @@ pub struct Paths {
     recipe_dir: PathBuf,
     output_dir: PathBuf,
     verify_against_manifest: Option<PathBuf>,
+    compiler: CompilerKey,
 }
 
 impl Paths {
@@ -50,17 +55,17 @@ impl Paths {
             recipe_dir,
             output_dir: output_dir.into(),
             verify_against_manifest,
+            compiler: CompilerKey::new(recipe.parsed.options.toolchain, None),
         };
 
         util::ensure_dir_exists(&job.rootfs().host)?;
         util::ensure_dir_exists(&job.artefacts().host)?;
         util::ensure_dir_exists(&job.build().host)?;
-        util::ensure_dir_exists(&job.ccache().host)?;
+        util::ensure_dir_exists(&job.logs().host)?;
         util::ensure_dir_exists(&job.gocache().host)?;
         util::ensure_dir_exists(&job.gomodcache().host)?;
         util::ensure_dir_exists(&job.cargocache().host)?;
         util::ensure_dir_exists(&job.zigcache().host)?;
-        util::ensure_dir_exists(&job.sccache().host)?;
         util::ensure_dir_exists(&job.upstreams().host)?;
 
         Ok(job)
@@ -87,9 +92,29 @@ impl Paths {
         }
     }
 
+    /// Logs of each phase of the build, see [`crate::build::log`]
+    pub fn logs(&self) -> Mapping {
+        Mapping {
+            host: self.host_root.join("logs").join(&self.id.0),
+            guest: self.guest_root.join("logs"),
+        }
+    }
+
+    /// Key the compiler caches by the compiler installed to the rootfs, see [`CompilerKey`]
+    pub fn set_compiler(&mut self, key: CompilerKey) {
+        self.compiler = key;
+    }
+
+    pub fn compiler(&self) -> &CompilerKey {
+        &self.compiler
+    }
+
+    /// The ccache of the compiler, created on demand by [`crate::container`]
+    ///
+    /// Mounted at the same guest path whatever the key, so build scripts needn't know it.
     pub fn ccache(&self) -> Mapping {
         Mapping {
-            host: self.host_root.join("ccache"),
+            host: self.compiler.dir(&self.host_root.join("ccache")),
             guest: self.guest_root.join("ccache"),
         }
     }
@@ -130,9 +155,10 @@ impl Paths {
         }
     }
 
+    /// The sccache of the compiler, as [`Paths::ccache`]
     pub fn sccache(&self) -> Mapping {
         Mapping {
-            host: self.host_root.join("sccache"),
+            host: self.compiler.dir(&self.host_root.join("sccache")),
             guest: self.guest_root.join("sccache"),
         }
     }
@@ -158,6 +184,11 @@ impl Paths {
         }
     }
 
+    /// Materials of the last build, see [`crate::package::sbom::Materials`]
+    pub fn materials(&self) -> PathBuf {
+        self.build().host.join("materials.json")
+    }
+
     /// For the provided [`Mapping`], return the guest
     /// path as it lives on the host fs
     ///
@@ -171,6 +202,11 @@ impl Paths {
         self.rootfs().host.join(relative)
     }
 
+    /// Guest dirs the container bind mounts over the rootfs, rather than being part of it
+    pub fn bind_mounted(&self) -> Vec<PathBuf> {
+        vec![self.guest_root.clone(), self.ccache_config().guest]
+    }
+
     /// Returns the output directory used for artefact syncing
     pub fn output_dir(&self) -> &PathBuf {
         &self.output_dir
@@ -191,3 +227,173 @@ pub struct Mapping {
     pub host: PathBuf,
     pub guest: PathBuf,
 }
+
+/// The compiler a compiler cache is kept for, i.e. `gnu-14.2.0`
+///
+/// Objects cached by one toolchain are mis-hit by the other, and those of a
+/// compiler since upgraded are never hit again, so each gets a cache of its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompilerKey {
+    toolchain: Toolchain,
+    /// Unknown until the compiler is installed
+    version: Option<String>,
+}
+
+impl CompilerKey {
+    pub fn new(toolchain: Toolchain, version: Option<&str>) -> Self {
+        Self {
+            toolchain,
+            version: version.map(|version| {
+                version
+                    .chars()
+                    .map(|c| {
+                        if c.is_ascii_alphanumeric() || matches!(c, '.' | '+' | '-') {
+                            c
+                        } else {
+                            '_'
+                        }
+                    })
+                    .collect()
+            }),
+        }
+    }
+
+    /// Of the compiler of `toolchain` among the `installed` packages, by name & version
+    pub fn installed<'a>(toolchain: Toolchain, installed: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+        let compiler = match toolchain {
+            Toolchain::Llvm => "clang",
+            Toolchain::Gnu => "gcc",
+        };
+        let version = installed
+            .into_iter()
+            .find_map(|(name, version)| (name == compiler).then_some(version));
+
+        Self::new(toolchain, version)
+    }
+
+    /// The cache of this compiler within `root`
+    pub fn dir(&self, root: &Path) -> PathBuf {
+        root.join(self.to_string())
+    }
+}
+
+impl fmt::Display for CompilerKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{}-{version}", self.toolchain),
+            None => write!(f, "{}", self.toolchain),
+        }
+    }
+}
+
+/// The caches of each compiler within `root`, by key, i.e. `<cache>/ccache`
+///
+/// The dirs of a cache shared by every compiler, from before caches were keyed,
+/// are skipped, see [`is_unkeyed`].
+pub fn compiler_caches(root: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    if !root.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut caches = vec![];
+    for entry in root.read_dir()? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_dir() && !is_unkeyed(&name) {
+            caches.push((name, entry.path()));
+        }
+    }
+    caches.sort();
+
+    Ok(caches)
+}
+
+/// Whether `name` is a dir of ccache itself, i.e. `0`-`f` & `tmp`, rather than a [`CompilerKey`]
+fn is_unkeyed(name: &str) -> bool {
+    name == "tmp" || (name.len() == 1 && name.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+#[cfg(test)]
+mod test {
+    use fs_err as fs;
+
+    use super::*;
+
+    #[test]
+    fn compiler_key() {
+        let installed = [
+            ("glibc", "2.41"),
+            ("gcc", "14.2.0"),
+            ("clang", "19.1.7"),
+            ("ccache", "4.10"),
+        ];
+
+        assert_eq!(
+            CompilerKey::installed(Toolchain::Gnu, installed).to_string(),
+            "gnu-14.2.0"
+        );
+        assert_eq!(
+            CompilerKey::installed(Toolchain::Llvm, installed).to_string(),
+            "llvm-19.1.7"
+        );
+        // Not installed yet
+        assert_eq!(CompilerKey::installed(Toolchain::Llvm, []).to_string(), "llvm");
+        // Kept to a single dir
+        assert_eq!(
+            CompilerKey::new(Toolchain::Gnu, Some("15.0/git 1")).to_string(),
+            "gnu-15.0_git_1"
+        );
+    }
+
+    #[test]
+    fn keyed_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let recipe_path = dir.path().join("stone.yaml");
+        fs::write(
+            &recipe_path,
+            "\
+name: nano
+version: 8.7
+release: 1
+homepage: https://nano-editor.org
+license: GPL-3.0-or-later
+summary: GNU Text Editor
+description: GNU Text Editor
+toolchain: gnu
+",
+        )
+        .unwrap();
+        let recipe = Recipe::load(&recipe_path).unwrap();
+        let mut paths = Paths::new(&recipe, None, dir.path(), "/mason", dir.path()).unwrap();
+
+        assert_eq!(
+            paths.ccache().host,
+            dir.path().canonicalize().unwrap().join("ccache/gnu")
+        );
+
+        paths.set_compiler(CompilerKey::installed(Toolchain::Gnu, [("gcc", "14.2.0")]));
+        let root = dir.path().canonicalize().unwrap();
+        assert_eq!(paths.ccache().host, root.join("ccache/gnu-14.2.0"));
+        assert_eq!(paths.ccache().guest, Path::new("/mason/ccache"));
+        assert_eq!(paths.sccache().host, root.join("sccache/gnu-14.2.0"));
+        assert_eq!(paths.sccache().guest, Path::new("/mason/sccache"));
+
+        // Created on demand
+        assert!(compiler_caches(&root.join("ccache")).unwrap().is_empty());
+        for key in ["llvm-19.1.7", "gnu-14.2.0"] {
+            fs::create_dir_all(root.join("ccache").join(key)).unwrap();
+        }
+        fs::write(root.join("ccache/ccache.conf"), "max_size = 5G\n").unwrap();
+        // Left by the cache shared by every compiler
+        for legacy in ["0", "a", "f", "tmp"] {
+            fs::create_dir_all(root.join("ccache").join(legacy)).unwrap();
+        }
+        assert_eq!(
+            compiler_caches(&root.join("ccache")).unwrap(),
+            [
+                ("gnu-14.2.0".to_owned(), root.join("ccache/gnu-14.2.0")),
+                ("llvm-19.1.7".to_owned(), root.join("ccache/llvm-19.1.7")),
+            ]
+        );
+    }
+}
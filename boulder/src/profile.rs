@@ -4,14 +4,17 @@
 use derive_more::Debug;
 use derive_more::Display;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, io};
 use thiserror::Error;
 
 use config::Config;
-use moss::repository;
+use moss::{Repository, repository};
 
 use crate::Env;
 
+/// Identifier of the profile used unless another is passed
+pub const DEFAULT: &str = "default-x86_64";
+
 /// A unique [`Profile`] identifier
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Ord, PartialOrd, Display)]
 #[debug("{_0:?}")]
@@ -39,6 +42,23 @@ impl From<String> for Id {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Profile {
     pub repositories: repository::Map,
+    /// Run system-scope triggers when populating build roots
+    ///
+    /// These are skipped by default as builds rarely depend on them
+    #[serde(default)]
+    pub system_triggers: bool,
+    /// Variables set in the environment of build containers, i.e. `https_proxy`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub environment: BTreeMap<String, String>,
+    /// Hosts builds with networking may connect to, any if empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub network_allowlist: Vec<String>,
+    /// Fail builds installing paths outside `/usr`, rather than warning
+    #[serde(default)]
+    pub strict_layout: bool,
+    /// Size each ccache is limited to by `boulder cache ccache-gc`, i.e. `5G`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ccache_max_size: Option<String>,
 }
 
 /// A map of profiles
@@ -104,14 +124,20 @@ impl<'a> Manager<'a> {
         Self { env, profiles }
     }
 
-    pub fn repositories(&self, profile: &Id) -> Result<&repository::Map, Error> {
+    pub fn profile(&self, profile: &Id) -> Result<&Profile, Error> {
         self.profiles
             .get(profile)
-            .map(|profile| &profile.repositories)
             .ok_or_else(|| Error::MissingProfile(profile.clone()))
     }
 
+    pub fn repositories(&self, profile: &Id) -> Result<&repository::Map, Error> {
+        self.profile(profile).map(|profile| &profile.repositories)
+    }
+
     pub fn save_profile(&mut self, id: Id, profile: Profile) -> Result<(), Error> {
+        // Replace the config saved, whichever format it was written in
+        self.env.config.delete::<Map>(&id).map_err(Error::ReplaceProfile)?;
+
         // Save config
         let map = Map::with([(id.clone(), profile.clone())]);
         self.env.config.save(id.clone(), &map)?;
@@ -121,12 +147,148 @@ impl<'a> Manager<'a> {
 
         Ok(())
     }
+
+    /// Add `repository` to `profile`, replacing any of the same `id`
+    pub fn add_repository(&mut self, profile: &Id, id: repository::Id, repository: Repository) -> Result<(), Error> {
+        self.edit_repositories(profile, |repositories| {
+            repositories.add(id, repository);
+            Ok(())
+        })
+    }
+
+    /// Remove the repository `id` from `profile`
+    pub fn remove_repository(&mut self, profile: &Id, id: &repository::Id) -> Result<Repository, Error> {
+        self.edit_repositories(profile, |repositories| {
+            repositories
+                .remove(id)
+                .ok_or_else(|| Error::MissingRepository(profile.clone(), id.clone()))
+        })
+    }
+
+    /// Set the `priority` of the repository `id` of `profile`
+    pub fn set_priority(
+        &mut self,
+        profile: &Id,
+        id: &repository::Id,
+        priority: repository::Priority,
+    ) -> Result<(), Error> {
+        self.edit_repositories(profile, |repositories| {
+            let repository = repositories
+                .get_mut(id)
+                .ok_or_else(|| Error::MissingRepository(profile.clone(), id.clone()))?;
+            repository.priority = priority;
+            Ok(())
+        })
+    }
+
+    /// Save `profile` once its repositories are edited by `f`
+    fn edit_repositories<T>(
+        &mut self,
+        profile: &Id,
+        f: impl FnOnce(&mut repository::Map) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let mut edited = self.profile(profile)?.clone();
+        let output = f(&mut edited.repositories)?;
+        self.save_profile(profile.clone(), edited)?;
+        Ok(output)
+    }
 }
 
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("cannot find the provided profile: {0}")]
     MissingProfile(Id),
+    #[error("profile {0} has no repository {1}")]
+    MissingRepository(Id, repository::Id),
     #[error("save profiles")]
     SaveProfile(#[from] config::SaveError),
+    #[error("replace saved profile")]
+    ReplaceProfile(#[source] io::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use fs_err as fs;
+    use url::Url;
+
+    use super::*;
+
+    fn env(root: &Path) -> Env {
+        Env::new(
+            Some(root.join("cache")),
+            Some(root.join("config")),
+            Some(root.join("data")),
+            Some(root.join("moss")),
+        )
+        .unwrap()
+    }
+
+    fn repository(uri: &str, priority: u64) -> Repository {
+        Repository {
+            description: String::default(),
+            source: repository::Source::DirectIndex(Url::parse(uri).unwrap()),
+            priority: repository::Priority::new(priority),
+            active: true,
+        }
+    }
+
+    #[test]
+    fn repository_priorities() {
+        let dir = tempfile::tempdir().unwrap();
+        let env = env(dir.path());
+        let id = Id::new("local-x86_64");
+        let (volatile, local) = (repository::Id::new("volatile"), repository::Id::new("local"));
+
+        // As written by hand, without a priority
+        let profiles = dir.path().join("config/profile.d");
+        fs::create_dir_all(&profiles).unwrap();
+        fs::write(
+            profiles.join("local-x86_64.yaml"),
+            "\
+local-x86_64:
+  repositories:
+    volatile:
+      description: ''
+      uri: https://cdn.aerynos.dev/unstable/x86_64/stone.index
+    local:
+      description: ''
+      uri: file:///var/lib/boulder/repo/stone.index
+      priority: 100
+",
+        )
+        .unwrap();
+
+        let mut manager = Manager::new(&env);
+        let repositories = manager.repositories(&id).unwrap().clone();
+        assert_eq!(
+            repositories.get(&volatile),
+            Some(&repository("https://cdn.aerynos.dev/unstable/x86_64/stone.index", 0))
+        );
+        assert_eq!(
+            repositories.get(&local),
+            Some(&repository("file:///var/lib/boulder/repo/stone.index", 100))
+        );
+
+        let saved = serde_yaml::to_string(&Map::with([(id.clone(), manager.profile(&id).unwrap().clone())])).unwrap();
+        let loaded = serde_yaml::from_str::<Map>(&saved).unwrap();
+        assert_eq!(loaded.get(&id).unwrap().repositories, repositories);
+
+        // Edits fail before anything is saved
+        let missing = repository::Id::new("missing");
+        assert!(matches!(
+            manager.remove_repository(&id, &missing),
+            Err(Error::MissingRepository(_, name)) if name == missing
+        ));
+        assert!(matches!(
+            manager.set_priority(&id, &missing, repository::Priority::new(1)),
+            Err(Error::MissingRepository(..))
+        ));
+        assert!(matches!(
+            manager.add_repository(&Id::new("missing"), local, repository("file:///repo/stone.index", 1)),
+            Err(Error::MissingProfile(_))
+        ));
+        assert!(profiles.join("local-x86_64.yaml").exists());
+    }
 }
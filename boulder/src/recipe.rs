@@ -15,6 +15,8 @@ use tui::Styled;
 
 use crate::architecture::{self, BuildTarget};
 
+pub mod format;
+
 pub type Parsed = stone_recipe::Recipe;
 
 #[derive(Debug)]
@@ -70,6 +72,15 @@ impl Recipe {
             )));
         }
 
+        // Definitions are used as `%(identifier)`, which can't name others
+        if let Some(identifier) = parsed.definitions.keys().find(|identifier| {
+            identifier.is_empty() || !identifier.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }) {
+            return Err(Error::Value(format!(
+                "definition names may only contain letters, digits & underscores (found '{identifier}')"
+            )));
+        }
+
         // Invariant checks done
 
         Ok(Self {
@@ -80,34 +91,23 @@ impl Recipe {
         })
     }
 
+    /// Targets of the host this recipe builds, none if it doesn't support the host
     pub fn build_targets(&self) -> Vec<BuildTarget> {
         let host = architecture::host();
-        let host_string = host.to_string();
 
-        let mut targets = vec![];
         if self.parsed.architectures.is_empty() {
+            let mut targets = vec![];
             if self.parsed.emul32 {
                 targets.push(BuildTarget::Emul32(host));
             }
-
             targets.push(BuildTarget::Native(host));
+            targets
         } else {
-            let emul32 = BuildTarget::Emul32(host);
-            let emul32_string = emul32.to_string();
-
-            if self.parsed.architectures.contains(&emul32_string)
-                || self.parsed.architectures.contains(&"emul32".into())
-            {
-                targets.push(emul32);
-            }
-
-            if self.parsed.architectures.contains(&host_string) || self.parsed.architectures.contains(&"native".into())
-            {
-                targets.push(BuildTarget::Native(host));
-            }
+            [BuildTarget::Emul32(host), BuildTarget::Native(host)]
+                .into_iter()
+                .filter(|target| architecture::supports(&self.parsed.architectures, *target))
+                .collect()
         }
-
-        targets
     }
 
     pub fn build_target_profile_key(&self, target: BuildTarget) -> Option<String> {
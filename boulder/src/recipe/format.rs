@@ -0,0 +1,660 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Canonical layout of recipes, as written by `boulder recipe format`
+//!
+//! Recipes are formatted line by line instead of being serialized again, so
+//! their comments are kept. Top level keys are ordered by [`ORDER`] & aligned,
+//! nested content is indented by two spaces and upstreams use their shorthand
+//! form where possible.
+
+use serde_yaml::Value;
+use stone_recipe::upstream::{FOSSIL_PREFIX, GIT_PREFIX, HG_PREFIX};
+use thiserror::Error;
+
+/// Top level keys in the order our recipes use, unknown keys follow in the order found
+const ORDER: &[&str] = &[
+    "name",
+    "version",
+    "release",
+    "homepage",
+    "upstreams",
+    "summary",
+    "description",
+    "license",
+    "monitoring",
+    "architectures",
+    "toolchain",
+    "emul32",
+    "emul32_deps_auto",
+    "mold",
+    "cspgo",
+    "samplepgo",
+    "pgo_stages",
+    "debug",
+    "strip",
+    "lastrip",
+    "compressman",
+    "networking",
+    "network_allowlist",
+    "max_jobs",
+    "max_memory_per_job",
+    "tuning",
+    "builddeps",
+    "checkdeps",
+    "rundeps",
+    "rundeps-exclude",
+    "provides-exclude",
+    "conflicts",
+    "paths",
+    "nostrip",
+    "allow_paths",
+    "definitions",
+    "environment",
+    "setup",
+    "build",
+    "install",
+    "check",
+    "workload",
+    "workloads",
+    "profiles",
+    "packages",
+];
+
+const INDENT: usize = 2;
+
+/// Top level keys are padded so their values line up, as with `description : `
+const KEY_WIDTH: usize = "description".len();
+
+/// `source` in the canonical layout
+///
+/// Fails rather than returning a recipe whose values differ from those of `source`.
+pub fn format(source: &str) -> Result<String, Error> {
+    let before = normalized(serde_yaml::from_str(source)?);
+
+    let formatted = Document::parse(source)?.render();
+
+    let after = serde_yaml::from_str(&formatted).ok().map(normalized);
+    if after.as_ref() != Some(&before) {
+        return Err(Error::Altered);
+    }
+
+    Ok(formatted)
+}
+
+struct Document<'a> {
+    /// Comments atop the recipe, i.e. its license
+    header: Vec<&'a str>,
+    entries: Vec<Entry<'a>>,
+    /// Comments after the last entry
+    footer: Vec<&'a str>,
+}
+
+/// A top level key & its value
+struct Entry<'a> {
+    key: &'a str,
+    /// Directly above the key
+    comments: Vec<&'a str>,
+    /// Of the key, followed by those of its value
+    lines: Vec<&'a str>,
+}
+
+impl<'a> Document<'a> {
+    fn parse(source: &'a str) -> Result<Self, Error> {
+        let mut header = vec![];
+        let mut entries = Vec::<Entry<'a>>::new();
+        // Blank & comment lines of the top level, until the next line tells what they belong to
+        let mut pending = vec![];
+
+        for (index, line) in source.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                pending.push(line);
+            } else if line.starts_with("---") && entries.is_empty() {
+                header.append(&mut pending);
+                header.push(line);
+            } else if line.starts_with(' ') || line == "-" || line.starts_with("- ") {
+                let entry = entries.last_mut().ok_or(Error::Unsupported(index + 1))?;
+                entry.lines.append(&mut pending);
+                entry.lines.push(line);
+            } else {
+                let (key, _) = split_key(line).ok_or(Error::Unsupported(index + 1))?;
+
+                // Comments atop the recipe only belong to the first key without a blank line between
+                if entries.is_empty() {
+                    let start = pending
+                        .iter()
+                        .rposition(|line| line.trim().is_empty())
+                        .map_or(pending.len(), |blank| blank + 1);
+                    let comments = pending.split_off(start);
+                    header.append(&mut pending);
+                    pending = comments;
+                }
+
+                entries.push(Entry {
+                    key,
+                    comments: collapse(pending.drain(..)),
+                    lines: vec![line],
+                });
+            }
+        }
+
+        Ok(Self {
+            header: collapse(header),
+            entries,
+            footer: collapse(pending),
+        })
+    }
+
+    fn render(mut self) -> String {
+        let position = |key: &str| ORDER.iter().position(|known| *known == key).unwrap_or(ORDER.len());
+        self.entries.sort_by_key(|entry| position(entry.key));
+
+        let mut lines = self.header.iter().map(|line| line.to_string()).collect::<Vec<_>>();
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+
+        for entry in &self.entries {
+            lines.extend(entry.comments.iter().map(|line| line.to_string()));
+
+            let mut rendered = reindent(&entry.lines);
+            if let Some((key, value)) = split_key(entry.lines[0]) {
+                rendered[0] = format!("{key:<KEY_WIDTH$} : {value}").trim_end().to_owned();
+            }
+            if entry.key == "upstreams" {
+                rendered = shorthand_upstreams(&rendered);
+            }
+            lines.extend(rendered);
+        }
+
+        lines.extend(self.footer.iter().map(|line| line.to_string()));
+
+        lines.into_iter().map(|line| line + "\n").collect()
+    }
+}
+
+/// Indentation of a line before & after formatting
+#[derive(Debug, Clone, Copy)]
+struct Level {
+    old: usize,
+    new: usize,
+    /// A sequence written at the indentation of the key it's the value of
+    flush: bool,
+}
+
+/// A literal or folded scalar whose lines keep their indentation relative to each other
+#[derive(Debug, Clone, Copy)]
+struct BlockScalar {
+    /// Indentation of the node owning the scalar, before formatting
+    owner: usize,
+    /// Indentation of the least indented line, after formatting
+    base: usize,
+    /// Indentation of the least indented line, before formatting
+    start: Option<usize>,
+}
+
+/// `lines` indented by [`INDENT`] per level, with sequences indented under their key
+fn reindent(lines: &[&str]) -> Vec<String> {
+    let mut levels = vec![Level {
+        old: 0,
+        new: 0,
+        flush: false,
+    }];
+    let mut scalar = None::<BlockScalar>;
+    // Indentation of the previous key, if its value follows on the next lines
+    let mut open_key = None;
+    let mut output = vec![];
+
+    for line in lines {
+        let indent = line.len() - line.trim_start_matches(' ').len();
+        let content = line[indent..].trim_end();
+
+        if content.is_empty() {
+            output.push(String::new());
+            continue;
+        }
+
+        if let Some(block) = &mut scalar {
+            let start = *block.start.get_or_insert(indent);
+            if indent > block.owner && indent >= start {
+                // Trailing whitespace is content of the scalar
+                output.push(format!("{:width$}{}", "", &line[start..], width = block.base));
+                continue;
+            }
+            scalar = None;
+        }
+
+        if content.starts_with('#') {
+            let top = levels[levels.len() - 1];
+            let new = if indent > top.old {
+                top.new + INDENT
+            } else {
+                levels
+                    .iter()
+                    .rev()
+                    .find(|level| level.old <= indent)
+                    .map_or(0, |level| level.new)
+            };
+            output.push(format!("{:new$}{content}", ""));
+            continue;
+        }
+
+        let is_item = content == "-" || content.starts_with("- ");
+        while let Some(top) = levels.last().copied().filter(|_| levels.len() > 1) {
+            if top.old > indent || (top.old == indent && top.flush && !is_item) {
+                levels.pop();
+            } else {
+                break;
+            }
+        }
+
+        let top = levels[levels.len() - 1];
+        let flush = is_item && indent == top.old && open_key == Some(indent);
+        let new = if indent > top.old || flush {
+            levels.push(Level {
+                old: indent,
+                new: top.new + INDENT,
+                flush,
+            });
+            top.new + INDENT
+        } else {
+            top.new
+        };
+
+        let mut text = format!("{:new$}", "");
+        // Indentation of the innermost node of the line, before & after formatting
+        let (mut old_column, mut new_column) = (indent, new);
+        let mut node = content;
+        while let Some(item) = node
+            .strip_prefix('-')
+            .filter(|rest| rest.is_empty() || rest.starts_with(' '))
+        {
+            let item = item.trim_start_matches(' ');
+            text.push('-');
+            if item.is_empty() {
+                node = item;
+                break;
+            }
+            text.push(' ');
+
+            old_column += node.len() - item.len();
+            new_column += INDENT;
+            levels.push(Level {
+                old: old_column,
+                new: new_column,
+                flush: false,
+            });
+            node = item;
+        }
+        text.push_str(node);
+        output.push(text);
+
+        open_key = None;
+        let (owner, value) = match split_key(node) {
+            Some((_, value)) => ((old_column, new_column), value),
+            // The scalar of a sequence item belongs to the sequence
+            None => ((indent, new), node),
+        };
+        let value = strip_comment(value);
+        if let Some(indicator) = block_scalar(value) {
+            scalar = Some(BlockScalar {
+                owner: owner.0,
+                base: owner.1 + indicator.unwrap_or(INDENT),
+                start: indicator.map(|indicator| owner.0 + indicator),
+            });
+        } else if value.is_empty() && split_key(node).is_some() {
+            open_key = Some(old_column);
+        }
+    }
+
+    // Blank lines ending a value are dropped along with those between top level keys
+    while output.last().is_some_and(String::is_empty) {
+        output.pop();
+    }
+
+    output
+}
+
+/// Upstreams with nothing but a hash, git ref or revision written as `- uri : hash`
+fn shorthand_upstreams(lines: &[String]) -> Vec<String> {
+    let item = format!("{:INDENT$}- ", "");
+    let nested = format!("{:width$}", "", width = INDENT * 2);
+    let mut output = vec![];
+
+    let mut index = 0;
+    while index < lines.len() {
+        let line = &lines[index];
+        index += 1;
+
+        let Some((uri, value)) = line
+            .strip_prefix(&item)
+            .filter(|node| !node.contains(" #"))
+            .and_then(split_key)
+        else {
+            output.push(line.clone());
+            continue;
+        };
+
+        if !value.is_empty() {
+            output.push(format!("{item}{uri} : {value}"));
+            continue;
+        }
+
+        let key = if uri.starts_with(GIT_PREFIX) {
+            "ref"
+        } else if uri.starts_with(HG_PREFIX) || uri.starts_with(FOSSIL_PREFIX) {
+            "rev"
+        } else {
+            "hash"
+        };
+        let option = lines
+            .get(index)
+            .filter(|option| option.starts_with(&nested) && !option.contains(" #"))
+            .and_then(|option| split_key(option.trim_start()))
+            .filter(|(option, value)| *option == key && !value.is_empty());
+        let is_only_option = lines.get(index + 1).is_none_or(|next| !next.starts_with(&nested));
+
+        match option {
+            Some((_, value)) if is_only_option => {
+                output.push(format!("{item}{uri} : {value}"));
+                index += 1;
+            }
+            _ => output.push(line.clone()),
+        }
+    }
+
+    output
+}
+
+/// The key & value of a mapping entry in `node`, if it is one
+fn split_key(node: &str) -> Option<(&str, &str)> {
+    if node.starts_with(['{', '[', '#']) {
+        return None;
+    }
+
+    let mut quote = None;
+    for (index, c) in node.char_indices() {
+        match quote {
+            Some(open) if c == open => quote = None,
+            Some(_) => {}
+            None if index == 0 && matches!(c, '"' | '\'') => quote = Some(c),
+            None if c == '#' && node[..index].ends_with(' ') => return None,
+            None if c == ':' && (node[index + 1..].is_empty() || node[index + 1..].starts_with(' ')) => {
+                return Some((node[..index].trim_end(), node[index + 1..].trim_start()));
+            }
+            None => {}
+        }
+    }
+
+    None
+}
+
+/// `value` without a trailing comment
+fn strip_comment(value: &str) -> &str {
+    if value.starts_with('#') {
+        ""
+    } else if value.starts_with(['"', '\'']) {
+        value
+    } else {
+        value.find(" #").map_or(value, |index| &value[..index]).trim_end()
+    }
+}
+
+/// The explicit indentation of a block scalar header, if `value` is one
+fn block_scalar(value: &str) -> Option<Option<usize>> {
+    let indicators = value.strip_prefix(['|', '>'])?;
+
+    indicators
+        .chars()
+        .all(|c| matches!(c, '+' | '-') || c.is_ascii_digit())
+        .then(|| {
+            indicators
+                .chars()
+                .find_map(|c| c.to_digit(10))
+                .map(|digit| digit as usize)
+        })
+}
+
+/// Comments without surrounding & repeated blank lines
+fn collapse<'a>(lines: impl IntoIterator<Item = &'a str>) -> Vec<&'a str> {
+    let mut collapsed = Vec::<&str>::new();
+
+    for line in lines {
+        let is_blank = line.trim().is_empty();
+        if is_blank && collapsed.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        collapsed.push(if is_blank { "" } else { line.trim_end() });
+    }
+    if collapsed.last().is_some_and(|last| last.is_empty()) {
+        collapsed.pop();
+    }
+
+    collapsed
+}
+
+/// `recipe` with its upstreams in the shorthand form, so both forms compare equal
+fn normalized(mut recipe: Value) -> Value {
+    if let Some(Value::Sequence(upstreams)) = recipe.get_mut("upstreams") {
+        for options in upstreams
+            .iter_mut()
+            .filter_map(Value::as_mapping_mut)
+            .flat_map(|upstream| upstream.values_mut())
+        {
+            let shorthand = options
+                .as_mapping()
+                .filter(|options| options.len() == 1)
+                .and_then(|options| ["hash", "ref", "rev"].into_iter().find_map(|key| options.get(key)))
+                .cloned();
+
+            if let Some(shorthand) = shorthand {
+                *options = shorthand;
+            }
+        }
+    }
+
+    recipe
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("parse recipe")]
+    Parse(#[from] serde_yaml::Error),
+    #[error("line {0} isn't part of a top level key, only recipes of a single block mapping can be formatted")]
+    Unsupported(usize),
+    #[error("formatting would alter the values of the recipe")]
+    Altered,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn order() {
+        let formatted = format(
+            "\
+release: 2
+version: 1.0
+name: nano
+extra: kept
+setup: |
+    %configure
+homepage: https://nano-editor.org
+other: last
+license: GPL-3.0-or-later
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            formatted,
+            "\
+name        : nano
+version     : 1.0
+release     : 2
+homepage    : https://nano-editor.org
+license     : GPL-3.0-or-later
+setup       : |
+  %configure
+extra       : kept
+other       : last
+"
+        );
+    }
+
+    #[test]
+    fn comments() {
+        let formatted = format(
+            "\
+# SPDX-FileCopyrightText: 2026 AerynOS Developers
+
+# Bumped by hand
+release     : 2
+name        : nano
+version     : 1.0
+
+builddeps   :
+    # For the docs
+    - binary(groff)
+    - pkgconfig(ncursesw) # wide chars
+setup       : |
+    # Not a comment of the recipe
+    %configure
+
+
+# Trailing
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            formatted,
+            "\
+# SPDX-FileCopyrightText: 2026 AerynOS Developers
+
+name        : nano
+version     : 1.0
+# Bumped by hand
+release     : 2
+builddeps   :
+  # For the docs
+  - binary(groff)
+  - pkgconfig(ncursesw) # wide chars
+setup       : |
+  # Not a comment of the recipe
+  %configure
+# Trailing
+"
+        );
+    }
+
+    #[test]
+    fn indent() {
+        let formatted = format(
+            "\
+name: nano
+environment: |
+    export FLAGS=\"
+        -O2
+    \"
+
+    unset LD_PRELOAD
+rundeps:
+- nano-docs
+packages:
+    - \"%(name)-docs\":
+          summary: Docs
+          paths:
+              - /usr/share/doc
+          rundeps:
+          -   nano
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            formatted,
+            "\
+name        : nano
+rundeps     :
+  - nano-docs
+environment : |
+  export FLAGS=\"
+      -O2
+  \"
+
+  unset LD_PRELOAD
+packages    :
+  - \"%(name)-docs\":
+      summary: Docs
+      paths:
+        - /usr/share/doc
+      rundeps:
+        - nano
+"
+        );
+    }
+
+    #[test]
+    fn upstreams() {
+        let formatted = format(
+            "\
+name: nano
+upstreams:
+    - https://example.com/nano-1.0.tar.xz:
+        hash: cbf684b5a37a3a433e0526beb04a7b3419b71e81b3709c3b0d7ed6a1987d3dcb
+    - git|https://example.com/nano.git:
+        ref: v1.0
+    - https://example.com/extra.tar.xz:
+        hash: 0e0526beb04a7b3419b71e81b3709c3b0d7ed6a1987d3dcbcbf684b5a37a3a43
+        unpackdir: extra
+    - git|https://example.com/other.git: v2.0
+    - hg|https://example.com/hg/nano:
+        rev: a1b2c3d4e5f6
+    - fossil|https://example.com/fossil/nano:
+        tag: v1.0
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            formatted,
+            "\
+name        : nano
+upstreams   :
+  - https://example.com/nano-1.0.tar.xz : cbf684b5a37a3a433e0526beb04a7b3419b71e81b3709c3b0d7ed6a1987d3dcb
+  - git|https://example.com/nano.git : v1.0
+  - https://example.com/extra.tar.xz:
+      hash: 0e0526beb04a7b3419b71e81b3709c3b0d7ed6a1987d3dcbcbf684b5a37a3a43
+      unpackdir: extra
+  - git|https://example.com/other.git : v2.0
+  - hg|https://example.com/hg/nano : a1b2c3d4e5f6
+  - fossil|https://example.com/fossil/nano:
+      tag: v1.0
+"
+        );
+    }
+
+    #[test]
+    fn idempotent() {
+        let inputs = [
+            include_str!("../../../test/llvm-stone.yml"),
+            include_str!("../../../test/boulder-stone.yml"),
+        ];
+
+        for input in inputs {
+            let formatted = format(input).unwrap();
+            stone_recipe::from_str(&formatted).unwrap();
+
+            assert_ne!(formatted, input);
+            assert_eq!(format(&formatted).unwrap(), formatted);
+        }
+    }
+
+    #[test]
+    fn unsupported() {
+        assert!(matches!(format("- name: nano\n"), Err(Error::Unsupported(1))));
+        assert!(matches!(format("name: [nano"), Err(Error::Parse(_))));
+    }
+}
@@ -40,19 +40,13 @@ impl Timing {
                 self.populate.insert(populate, elapsed);
             }
             Kind::Fetch => self.fetch = elapsed,
-            Kind::Build(
-                build @ Build {
-                    target,
-                    pgo_stage,
-                    phase,
-                },
-            ) => {
+            Kind::Build(build) => {
                 self.build
-                    .entry(target)
+                    .entry(build.target)
                     .or_default()
-                    .entry(pgo_stage)
+                    .entry(build.pgo_stage.clone())
                     .or_default()
-                    .insert(phase, BuildEntry { build, elapsed });
+                    .insert(build.phase, BuildEntry { build, elapsed });
             }
             Kind::Analyze => self.analyze = elapsed,
             Kind::Emit => self.emit = elapsed,
@@ -125,7 +119,7 @@ impl Timing {
 
             for (stage, phases) in stages {
                 if let Some(stage) = stage {
-                    println!("│{}", build::pgo_stage_prefix(*stage, 0));
+                    println!("│{}", build::pgo_stage_prefix(stage, 0));
                 }
 
                 for (phase, entry) in phases {
@@ -211,7 +205,7 @@ impl From<Populate> for Kind {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Build {
     /// Build target (arch)
     pub target: BuildTarget,
@@ -235,6 +229,7 @@ impl BuildEntry {
             .max(
                 self.build
                     .pgo_stage
+                    .as_ref()
                     .map(|stage| stage.to_string().len() + 1)
                     .unwrap_or_default(),
             )
@@ -245,7 +240,7 @@ impl BuildEntry {
 /// Format a template of `000h00m00.00s`, removing
 /// leading zeros for spaces if the duration is
 /// too small
-fn fmt_elapsed(duration: Duration) -> String {
+pub fn fmt_elapsed(duration: Duration) -> String {
     let _seconds = duration.as_secs_f32() % 60.0;
     let _minutes = (duration.as_secs() / 60) % 60;
     let _hours = duration.as_secs() / 3600;
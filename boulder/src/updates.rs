@@ -0,0 +1,390 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Detection of new upstream versions of recipes
+//!
+//! The first upstream of a recipe decides where its releases are found. Plain
+//! upstreams are looked up on release-monitoring.org, by the project id of the
+//! `monitoring.yaml` beside the recipe, else its `monitoring` key or its name,
+//! while Git upstreams list the tags of their repository. Pre-releases &
+//! versions of another [`Scheme`] are ignored.
+
+use std::{fmt, io, path::PathBuf};
+
+use fs_err as fs;
+use moss::{request, runtime};
+use serde::{Deserialize, Serialize};
+use stone_recipe::upstream::Props;
+use thiserror::Error;
+use url::Url;
+
+use self::version::{Scheme, Version};
+use crate::Recipe;
+
+pub mod version;
+
+const PROJECTS_URL: &str = "https://release-monitoring.org/api/v2/projects/";
+const VERSIONS_URL: &str = "https://release-monitoring.org/api/v2/versions/";
+
+/// File beside a recipe listing its release-monitoring.org project id
+const MONITORING_FILE: &str = "monitoring.yaml";
+
+/// A project on release-monitoring.org
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Project {
+    Id(u64),
+    Name(String),
+}
+
+impl fmt::Display for Project {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Project::Id(id) => write!(f, "project {id}"),
+            Project::Name(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+/// Where upstream releases are found
+pub trait Releases {
+    /// Versions released by the release-monitoring.org `project`
+    fn monitored(&self, project: &Project) -> Result<Vec<String>, Error>;
+
+    /// Tags of the Git repository at `url`
+    fn tags(&self, url: &Url) -> Result<Vec<String>, Error>;
+}
+
+/// [`Releases`] queried over the network through the shared [`request`] client
+pub struct Remote;
+
+impl Releases for Remote {
+    fn monitored(&self, project: &Project) -> Result<Vec<String>, Error> {
+        #[derive(Deserialize)]
+        struct Projects {
+            items: Vec<Item>,
+        }
+
+        #[derive(Deserialize)]
+        struct Item {
+            name: String,
+            #[serde(default)]
+            stable_versions: Vec<String>,
+            version: Option<String>,
+        }
+
+        #[derive(Deserialize)]
+        struct Versions {
+            #[serde(default)]
+            stable_versions: Vec<String>,
+            latest_version: Option<String>,
+        }
+
+        let stable_or_latest = |stable: Vec<String>, latest: Option<String>| {
+            if stable.is_empty() {
+                latest.into_iter().collect()
+            } else {
+                stable
+            }
+        };
+
+        match project {
+            Project::Id(id) => {
+                let mut url = Url::parse(VERSIONS_URL).expect("valid url");
+                url.query_pairs_mut().append_pair("project_id", &id.to_string());
+
+                let response = runtime::block_on(request::download_json::<Versions>(url))?;
+                Ok(stable_or_latest(response.stable_versions, response.latest_version))
+            }
+            Project::Name(name) => {
+                let mut url = Url::parse(PROJECTS_URL).expect("valid url");
+                url.query_pairs_mut().append_pair("name", name);
+
+                let response = runtime::block_on(request::download_json::<Projects>(url))?;
+
+                // The same name may be used by several ecosystems
+                Ok(response
+                    .items
+                    .into_iter()
+                    .find(|item| item.name.eq_ignore_ascii_case(name))
+                    .map(|item| stable_or_latest(item.stable_versions, item.version))
+                    .unwrap_or_default())
+            }
+        }
+    }
+
+    fn tags(&self, url: &Url) -> Result<Vec<String>, Error> {
+        let tags = runtime::block_on(gitwrap::ls_remote_tags(url))?;
+        Ok(tags.into_iter().map(|tag| tag.name).collect())
+    }
+}
+
+/// Where the releases of a recipe were looked up
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Origin {
+    Monitoring { project: Project },
+    Git { url: Url },
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Monitoring { project } => write!(f, "release-monitoring.org ({project})"),
+            Origin::Git { url } => write!(f, "git ({url})"),
+        }
+    }
+}
+
+/// Latest upstream version of a recipe
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub recipe: PathBuf,
+    pub name: String,
+    pub current: String,
+    /// Newest release found, if any
+    pub latest: Option<String>,
+    pub outdated: bool,
+    pub origin: Origin,
+}
+
+/// Look up the latest upstream version of `recipe`
+pub fn check(recipe: &Recipe, releases: &impl Releases) -> Result<Check, Error> {
+    let source = &recipe.parsed.source;
+    let upstream = recipe.parsed.upstreams.first().ok_or(Error::NoUpstreams)?;
+
+    let (origin, candidates) = match &upstream.props {
+        Props::Plain { .. } | Props::Vcs { .. } => {
+            let project = match project_id(recipe)? {
+                Some(id) => Project::Id(id),
+                None => Project::Name(source.monitoring.clone().unwrap_or_else(|| source.name.clone())),
+            };
+            let candidates = releases
+                .monitored(&project)?
+                .iter()
+                .filter_map(|version| Version::parse(version))
+                .collect();
+            (Origin::Monitoring { project }, candidates)
+        }
+        Props::Git { .. } => {
+            let candidates = releases
+                .tags(&upstream.url)?
+                .iter()
+                .filter_map(|tag| Version::from_tag(tag, &source.name))
+                .collect();
+            (
+                Origin::Git {
+                    url: upstream.url.clone(),
+                },
+                candidates,
+            )
+        }
+    };
+
+    let current = Version::parse(&source.version);
+    let latest = latest(current.as_ref(), candidates);
+
+    Ok(Check {
+        recipe: recipe.path.clone(),
+        name: source.name.clone(),
+        current: source.version.clone(),
+        latest: latest.as_ref().map(ToString::to_string),
+        outdated: current.zip(latest).is_some_and(|(current, latest)| latest > current),
+        origin,
+    })
+}
+
+/// The release-monitoring.org project id of the [`MONITORING_FILE`] beside `recipe`, if listed
+fn project_id(recipe: &Recipe) -> Result<Option<u64>, Error> {
+    #[derive(Deserialize)]
+    struct Monitoring {
+        releases: Option<Listed>,
+    }
+
+    #[derive(Deserialize)]
+    struct Listed {
+        id: Option<u64>,
+    }
+
+    let path = recipe.path.with_file_name(MONITORING_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let monitoring = serde_yaml::from_str::<Monitoring>(&fs::read_to_string(&path)?)?;
+    Ok(monitoring.releases.and_then(|releases| releases.id))
+}
+
+/// Newest of the `candidates` comparable to the `current` version
+///
+/// Pre-releases are only considered while `current` is one.
+fn latest(current: Option<&Version>, candidates: Vec<Version>) -> Option<Version> {
+    let prerelease = current.is_some_and(Version::is_prerelease);
+    let scheme = current.map(Version::scheme).unwrap_or(Scheme::Numbered);
+
+    candidates
+        .into_iter()
+        .filter(|candidate| prerelease || !candidate.is_prerelease())
+        .filter(|candidate| candidate.scheme() == scheme)
+        .max()
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("recipe has no upstreams")]
+    NoUpstreams,
+    #[error("query release-monitoring.org")]
+    Monitoring(#[from] request::Error),
+    #[error("read {MONITORING_FILE}")]
+    ReadMonitoring(#[from] io::Error),
+    #[error("parse {MONITORING_FILE}")]
+    ParseMonitoring(#[from] serde_yaml::Error),
+    #[error("list git tags")]
+    Git(#[from] gitwrap::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, path::Path};
+
+    use fs_err as fs;
+
+    use super::*;
+
+    /// Releases known ahead of time
+    #[derive(Default)]
+    struct Stub {
+        monitored: HashMap<&'static str, Vec<&'static str>>,
+        tags: HashMap<&'static str, Vec<&'static str>>,
+    }
+
+    impl Releases for Stub {
+        fn monitored(&self, project: &Project) -> Result<Vec<String>, Error> {
+            Ok(self.monitored[project.to_string().as_str()]
+                .iter()
+                .map(|version| version.to_string())
+                .collect())
+        }
+
+        fn tags(&self, url: &Url) -> Result<Vec<String>, Error> {
+            Ok(self.tags[url.as_str()].iter().map(|tag| tag.to_string()).collect())
+        }
+    }
+
+    fn recipe(dir: &Path, name: &str, version: &str, extra: &str) -> Recipe {
+        let path = dir.join(format!("{name}.yaml"));
+        fs::write(
+            &path,
+            format!(
+                "\
+name: {name}
+version: {version}
+release: 1
+homepage: https://example.com
+license: MPL-2.0
+summary: example
+description: example
+{extra}"
+            ),
+        )
+        .unwrap();
+        Recipe::load(&path).unwrap()
+    }
+
+    #[test]
+    fn monitored() {
+        let dir = tempfile::tempdir().unwrap();
+        let releases = Stub {
+            monitored: HashMap::from([
+                ("nano", vec!["8.7.1", "9.0", "9.1-rc1", "8.7"]),
+                ("gtk4", vec!["4.16.0", "4.14.5"]),
+                ("project 2046", vec!["4.18.0", "4.16.0"]),
+            ]),
+            ..Default::default()
+        };
+        let upstream = "upstreams:\n  - https://example.com/source.tar.xz: 0000\n";
+
+        let check = check(&recipe(dir.path(), "nano", "8.7.1", upstream), &releases).unwrap();
+        assert_eq!(check.latest.as_deref(), Some("9.0"));
+        assert!(check.outdated);
+        assert_eq!(
+            check.origin,
+            Origin::Monitoring {
+                project: Project::Name("nano".to_owned())
+            }
+        );
+
+        // Pre-releases are followed once packaged
+        let check = super::check(&recipe(dir.path(), "nano", "9.1-beta2", upstream), &releases).unwrap();
+        assert_eq!(check.latest.as_deref(), Some("9.1-rc1"));
+        assert!(check.outdated);
+
+        // Project name overridden
+        let gtk = recipe(dir.path(), "gtk", "4.16.0", &format!("monitoring: gtk4\n{upstream}"));
+        let check = super::check(&gtk, &releases).unwrap();
+        assert_eq!(check.latest.as_deref(), Some("4.16.0"));
+        assert!(!check.outdated);
+
+        // The project id of monitoring.yaml is preferred
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join(MONITORING_FILE),
+            "releases:\n  id: 2046\n  rss: ~\nsecurity:\n  cpe: ~\n",
+        )
+        .unwrap();
+        let gtk = recipe(dir.path(), "gtk", "4.14.5", &format!("monitoring: gtk4\n{upstream}"));
+        let check = super::check(&gtk, &releases).unwrap();
+        assert_eq!(check.latest.as_deref(), Some("4.18.0"));
+        assert_eq!(
+            check.origin,
+            Origin::Monitoring {
+                project: Project::Id(2046)
+            }
+        );
+
+        let check = super::check(&recipe(dir.path(), "nano", "8.7.1", ""), &releases);
+        assert!(matches!(check, Err(Error::NoUpstreams)));
+    }
+
+    #[test]
+    fn tagged() {
+        let dir = tempfile::tempdir().unwrap();
+        let releases = Stub {
+            tags: HashMap::from([
+                (
+                    "https://example.com/curl.git",
+                    vec![
+                        "curl-8_5_0",
+                        "curl-8_10_1",
+                        "curl-8_11_0-rc1",
+                        "tiny-curl-8_4_0",
+                        "latest",
+                    ],
+                ),
+                (
+                    "https://example.com/calendar.git",
+                    vec!["2024.01.05", "2024.10.01", "v1.0", "nightly"],
+                ),
+            ]),
+            ..Default::default()
+        };
+        let upstream = |name: &str| format!("upstreams:\n  - git|https://example.com/{name}.git: v1.0\n");
+
+        let curl = recipe(dir.path(), "curl", "8.5.0", &upstream("curl"));
+        let check = check(&curl, &releases).unwrap();
+        assert_eq!(check.latest.as_deref(), Some("8.10.1"));
+        assert!(check.outdated);
+        assert_eq!(
+            check.origin,
+            Origin::Git {
+                url: "https://example.com/curl.git".parse().unwrap()
+            }
+        );
+
+        // Releases numbered otherwise are ignored
+        let calendar = recipe(dir.path(), "calendar", "2024.10.01", &upstream("calendar"));
+        let check = super::check(&calendar, &releases).unwrap();
+        assert_eq!(check.latest.as_deref(), Some("2024.10.01"));
+        assert!(!check.outdated);
+    }
+}
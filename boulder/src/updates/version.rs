@@ -0,0 +1,251 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Comparison of upstream versions, as named by releases & tags
+//!
+//! Versions are split into numbers & words at any separator, then compared
+//! component by component, numbers numerically. Pre-release words such as
+//! `rc` sort before the release they precede, i.e. `1.0rc1 < 1.0`, while any
+//! other word sorts after it, i.e. `9.6 < 9.6p1`.
+
+use std::{cmp::Ordering, fmt, ops::RangeInclusive};
+
+/// Words marking a pre-release, least mature first
+const PRE_RELEASES: &[&[&str]] = &[
+    &["dev", "snapshot"],
+    &["alpha"],
+    &["beta"],
+    &["pre", "preview"],
+    &["rc"],
+];
+
+/// Years of date based versions
+const YEARS: RangeInclusive<u64> = 1900..=2100;
+
+/// Prefixes of tags naming a version, besides the project name
+const TAG_PREFIXES: &[&str] = &["release", "version", "rel", "ver", "v"];
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Part {
+    /// Pre-release marker, ranked by maturity
+    Pre(usize),
+    Word(String),
+    Number(u64),
+}
+
+/// Version of an upstream project
+#[derive(Debug, Clone)]
+pub struct Version {
+    text: String,
+    parts: Vec<Part>,
+}
+
+/// How a project numbers its versions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    /// Numbered by release date, i.e. `2024.05` or `20240512`
+    Date,
+    /// Numbered by release, i.e. `1.2.3`
+    Numbered,
+}
+
+impl Version {
+    /// Parse a version, which must start with a number
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        if !text.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+
+        let mut parts = vec![];
+        let mut rest = text;
+        while let Some(start) = rest.find(|c: char| c.is_ascii_alphanumeric()) {
+            rest = &rest[start..];
+
+            let numeric = rest.starts_with(|c: char| c.is_ascii_digit());
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() || c.is_ascii_digit() != numeric)
+                .unwrap_or(rest.len());
+            let (token, remainder) = rest.split_at(end);
+            rest = remainder;
+
+            parts.push(if numeric {
+                Part::Number(token.parse().ok()?)
+            } else {
+                let word = token.to_ascii_lowercase();
+                match PRE_RELEASES.iter().position(|words| words.contains(&word.as_str())) {
+                    Some(rank) => Part::Pre(rank),
+                    None => Part::Word(word),
+                }
+            });
+
+            // Build metadata doesn't order versions
+            if rest.starts_with('+') {
+                break;
+            }
+        }
+
+        Some(Self {
+            text: text.to_owned(),
+            parts,
+        })
+    }
+
+    /// Parse the version named by a tag of project `name`
+    ///
+    /// Tags may be prefixed, i.e. `v1.2` or `name-1.2`, and separate
+    /// components by underscores, i.e. `name-1_2_0`.
+    pub fn from_tag<'a>(tag: &'a str, name: &str) -> Option<Self> {
+        // Case insensitive, as tags like `CURL-8_5_0` shout
+        let strip = |text: &'a str, prefix: &str| {
+            text.get(..prefix.len())
+                .filter(|start| start.eq_ignore_ascii_case(prefix))
+                .map(|_| &text[prefix.len()..])
+        };
+
+        let mut rest = tag.trim();
+        if let Some(stripped) = strip(rest, name) {
+            rest = stripped.trim_start_matches(['-', '_']);
+        }
+        if let Some(stripped) = TAG_PREFIXES.iter().find_map(|prefix| strip(rest, prefix)) {
+            rest = stripped.trim_start_matches(['-', '_', '.']);
+        }
+
+        if rest.contains('.') {
+            Self::parse(rest)
+        } else {
+            Self::parse(&rest.replace('_', "."))
+        }
+    }
+
+    /// Whether this is a pre-release, i.e. `1.0-rc1`
+    pub fn is_prerelease(&self) -> bool {
+        self.parts.iter().any(|part| matches!(part, Part::Pre(_)))
+    }
+
+    pub fn scheme(&self) -> Scheme {
+        match self.parts.first() {
+            // A year, or a `YYYYMMDD` date
+            Some(Part::Number(number)) if YEARS.contains(number) || YEARS.contains(&(number / 10_000)) => Scheme::Date,
+            _ => Scheme::Numbered,
+        }
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // How a version compares to the same one without `part` appended
+        let appended = |part: &Part| match part {
+            Part::Pre(_) => Ordering::Less,
+            Part::Number(0) => Ordering::Equal,
+            Part::Number(_) | Part::Word(_) => Ordering::Greater,
+        };
+
+        (0..self.parts.len().max(other.parts.len()))
+            .map(|index| match (self.parts.get(index), other.parts.get(index)) {
+                (Some(a), Some(b)) => a.cmp(b),
+                (Some(a), None) => appended(a),
+                (None, Some(b)) => appended(b).reverse(),
+                (None, None) => Ordering::Equal,
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Version {}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn version(text: &str) -> Version {
+        Version::parse(text).unwrap()
+    }
+
+    /// Assert each version is older than the next
+    fn assert_ascending(versions: &[&str]) {
+        for pair in versions.windows(2) {
+            assert!(version(pair[0]) < version(pair[1]), "{} < {}", pair[0], pair[1]);
+            assert!(version(pair[1]) > version(pair[0]), "{} > {}", pair[1], pair[0]);
+        }
+    }
+
+    #[test]
+    fn semver() {
+        assert_ascending(&[
+            "0.9",
+            "0.10",
+            "1.0.0-alpha",
+            "1.0.0-alpha.1",
+            "1.0.0-beta.2",
+            "1.0.0-beta.11",
+            "1.0.0-rc.1",
+            "1.0.0",
+            "1.0.1",
+            "1.2",
+            "1.10.0",
+            "10.0",
+        ]);
+        assert_eq!(version("1.0"), version("1.0.0"));
+        assert_eq!(version("1.0.0+build.5"), version("1.0.0"));
+        assert_ascending(&["2.0dev", "2.0alpha1", "2.0beta1", "2.0pre1", "2.0rc1", "2.0"]);
+    }
+
+    #[test]
+    fn suffixed() {
+        // OpenSSH & OpenSSL style releases
+        assert_ascending(&["9.6", "9.6p1", "9.7p1"]);
+        assert_ascending(&["1.1.1", "1.1.1a", "1.1.1w", "1.1.2"]);
+        assert!(!version("9.6p1").is_prerelease());
+        assert!(version("6.9-rc3").is_prerelease());
+    }
+
+    #[test]
+    fn dates() {
+        assert_ascending(&["2023.12.01", "2024.01.05", "2024.1.20", "2024.10"]);
+        assert_ascending(&["20231231", "20240101"]);
+        assert_eq!(version("2024.05").scheme(), Scheme::Date);
+        assert_eq!(version("20240512").scheme(), Scheme::Date);
+        assert_eq!(version("1.2.3").scheme(), Scheme::Numbered);
+        assert_eq!(version("256.7").scheme(), Scheme::Numbered);
+    }
+
+    #[test]
+    fn tags() {
+        let tag = |tag: &str| Version::from_tag(tag, "curl").map(|version| version.to_string());
+
+        assert_eq!(tag("v1.2.3").as_deref(), Some("1.2.3"));
+        assert_eq!(tag("V1.2").as_deref(), Some("1.2"));
+        assert_eq!(tag("release-2.0").as_deref(), Some("2.0"));
+        assert_eq!(tag("curl-8_5_0").as_deref(), Some("8.5.0"));
+        assert_eq!(tag("curl_8_5_0").as_deref(), Some("8.5.0"));
+        assert_eq!(tag("CURL-8.5.0").as_deref(), Some("8.5.0"));
+        assert_eq!(tag("1_0_rc1").as_deref(), Some("1.0.rc1"));
+        assert_eq!(tag("2024.01.05_1").as_deref(), Some("2024.01.05_1"));
+        assert_eq!(tag("latest"), None);
+        assert_eq!(tag("curl"), None);
+        assert_eq!(tag("vendor-1.0"), None);
+
+        assert!(Version::from_tag("curl-8_5_0", "curl") < Version::from_tag("curl-8_10_0", "curl"));
+        assert!(Version::from_tag("1_0_rc1", "curl").unwrap().is_prerelease());
+    }
+}
@@ -1,11 +1,15 @@
 // SPDX-FileCopyrightText: 2026 AerynOS Developers
 // SPDX-License-Identifier: MPL-2.0
 
-use std::{io, path::Path, time::Duration};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use crate::recipe::Recipe;
 use fs_err as fs;
-use futures_util::{StreamExt, TryStreamExt, stream};
+use futures_util::{FutureExt, StreamExt, TryStreamExt, stream};
 use moss::runtime;
 use stone_recipe::upstream;
 use thiserror::Error;
@@ -14,10 +18,14 @@ use tui::{MultiProgress, ProgressBar, ProgressStyle, Styled};
 use crate::upstream::{
     git::{Git, StoredGit},
     plain::{Plain, StoredPlain},
+    vcs::{StoredVcs, Vcs},
 };
 
-mod git;
+pub mod git;
+pub mod lock;
 mod plain;
+pub mod vcs;
+pub mod verify;
 
 /// An upstream is a backend where
 /// to get source code from.
@@ -29,21 +37,46 @@ pub enum Upstream {
     Plain(Plain),
     /// The source code is from a Git repository.
     Git(Git),
+    /// The source code is from a Mercurial or Fossil repository.
+    Vcs(Vcs),
 }
 
 impl Upstream {
     /// Constructs an [Upstream] based on the information provided
-    /// in the `upstream` section of a Stone recipe.
-    pub fn from_recipe_upstream(upstream: upstream::Upstream, original_index: usize) -> Result<Self, Error> {
+    /// in the `upstream` section of a Stone recipe within `recipe_dir`.
+    pub fn from_recipe_upstream(
+        upstream: upstream::Upstream,
+        original_index: usize,
+        recipe_dir: &Path,
+    ) -> Result<Self, Error> {
         match upstream.props {
-            upstream::Props::Plain { hash, rename, .. } => Ok(Self::Plain(Plain {
+            upstream::Props::Plain {
+                hash, rename, verify, ..
+            } => Ok(Self::Plain(Plain {
                 url: upstream.url,
                 hash: hash.parse().map_err(plain::Error::from)?,
                 rename,
+                verify: verify.map(|verify| verify::Detached::from_recipe(verify, recipe_dir)),
             })),
-            upstream::Props::Git { git_ref, .. } => Ok(Self::Git(Git {
+            upstream::Props::Git {
+                git_ref,
+                depth,
+                filter,
+                verify,
+                ..
+            } => Ok(Self::Git(Git {
                 url: upstream.url,
                 commit: git_ref,
+                partial: gitwrap::Partial { depth, filter },
+                verify: verify
+                    .map(|verify| verify::Signed::from_recipe(verify, recipe_dir))
+                    .transpose()?,
+                original_index,
+            })),
+            upstream::Props::Vcs { vcs, revision, .. } => Ok(Self::Vcs(Vcs {
+                kind: vcs,
+                url: upstream.url,
+                revision,
                 original_index,
             })),
         }
@@ -56,6 +89,7 @@ impl Upstream {
         match self {
             Upstream::Plain(plain) => plain.name(),
             Upstream::Git(git) => git.name(),
+            Upstream::Vcs(vcs) => vcs.name(),
         }
     }
 
@@ -67,9 +101,29 @@ impl Upstream {
         Ok(match self {
             Upstream::Plain(plain) => Stored::Plain(plain.store(storage_dir, pb).await?),
             Upstream::Git(git) => Stored::Git(git.store(storage_dir, pb).await?),
+            Upstream::Vcs(vcs) => Stored::Vcs(vcs.store(storage_dir).await?),
         })
     }
 
+    /// Whether the upstream is already stored in the storage directory,
+    /// so storing it won't need the network
+    async fn is_stored(&self, storage_dir: &Path) -> bool {
+        match self {
+            Upstream::Plain(plain) => plain.stored(storage_dir).is_ok() && plain.signature_stored(storage_dir),
+            Upstream::Git(git) => git.stored(storage_dir).await.is_ok_and(|(_, has_commit)| has_commit),
+            Upstream::Vcs(vcs) => vcs.stored(storage_dir).await.is_ok_and(|id| id.is_some()),
+        }
+    }
+
+    /// Returns the path where the upstream is stored within the storage directory.
+    pub fn stored_path(&self, storage_dir: &Path) -> PathBuf {
+        match self {
+            Upstream::Plain(plain) => plain.stored_path(storage_dir),
+            Upstream::Git(git) => git.stored_path(storage_dir),
+            Upstream::Vcs(vcs) => vcs.stored_path(storage_dir),
+        }
+    }
+
     /// Unconditionally removes this Upstream's resources within the storage directory.
     /// If the resources do not exist, this function returns successfully
     /// (it is idempotent).
@@ -80,6 +134,7 @@ impl Upstream {
         match self {
             Upstream::Plain(plain) => plain.remove(storage_dir).map_err(Error::from),
             Upstream::Git(git) => git.remove(storage_dir).map_err(Error::from),
+            Upstream::Vcs(vcs) => vcs.remove(storage_dir).map_err(Error::from),
         }
     }
 }
@@ -88,6 +143,7 @@ impl Upstream {
 pub(crate) enum Stored {
     Plain(StoredPlain),
     Git(StoredGit),
+    Vcs(StoredVcs),
 }
 
 impl Stored {
@@ -97,6 +153,16 @@ impl Stored {
         match self {
             Stored::Plain(plain) => plain.was_cached,
             Stored::Git(git) => git.was_cached,
+            Stored::Vcs(vcs) => vcs.was_cached,
+        }
+    }
+
+    /// The verification of the signature of the upstream, if verified.
+    pub fn verification(&self) -> Option<&verify::Verification> {
+        match self {
+            Stored::Plain(plain) => plain.verification.as_ref(),
+            Stored::Git(git) => git.verification.as_ref(),
+            Stored::Vcs(_) => None,
         }
     }
 
@@ -104,10 +170,12 @@ impl Stored {
     ///
     /// This function tries to be as efficient as possible in terms
     /// of actual bytes written/copied, by linking files from the storage directory.
-    async fn share(&self, dest_dir: &Path) -> Result<(), Error> {
+    /// Git repositories are verified once checked out.
+    async fn share(&mut self, dest_dir: &Path) -> Result<(), Error> {
         match self {
             Stored::Plain(plain) => plain.share(dest_dir)?,
-            Stored::Git(git) => git.share(&dest_dir.join(&git.name)).await?,
+            Stored::Git(git) => git.verification = git.share(&dest_dir.join(&git.name)).await?,
+            Stored::Vcs(vcs) => vcs.share(&dest_dir.join(&vcs.name)).await?,
         }
         Ok(())
     }
@@ -115,16 +183,40 @@ impl Stored {
 
 /// Returns a list of upstream from a Stone recipe.
 pub fn parse_recipe(recipe: &Recipe) -> Result<Vec<Upstream>, Error> {
+    let recipe_dir = recipe.path.parent().unwrap_or(Path::new("."));
+
     recipe
         .parsed
         .upstreams
         .iter()
         .cloned()
         .enumerate()
-        .map(|(index, upstream)| Upstream::from_recipe_upstream(upstream, index))
+        .map(|(index, upstream)| Upstream::from_recipe_upstream(upstream, index, recipe_dir))
         .collect()
 }
 
+/// Returns the [Upstream]s which aren't stored in the storage directory yet.
+pub fn missing<'a>(upstreams: &'a [Upstream], storage_dir: &Path) -> Vec<&'a Upstream> {
+    runtime::block_on(
+        stream::iter(upstreams)
+            .filter(|upstream| upstream.is_stored(storage_dir).map(|stored| !stored))
+            .collect(),
+    )
+}
+
+/// Ensures all [Upstream]s are already stored in the storage directory, so
+/// they can be synced offline. Returns an error listing those missing otherwise.
+pub fn ensure_stored(upstreams: &[Upstream], storage_dir: &Path) -> Result<(), Error> {
+    let missing = missing(upstreams, storage_dir);
+    if !missing.is_empty() {
+        return Err(Error::Offline(
+            missing.into_iter().map(|upstream| upstream.name().to_owned()).collect(),
+        ));
+    }
+
+    Ok(())
+}
+
 /// Helper that stores and shares a list of [Upstream]s.
 pub fn sync(
     recipe: &Recipe,
@@ -158,7 +250,7 @@ pub fn sync(
                 );
                 pb.enable_steady_tick(Duration::from_millis(150));
 
-                let stored = upstream.store(storage_dir, &pb).await?;
+                let mut stored = upstream.store(storage_dir, &pb).await?;
 
                 pb.set_message(format!("{} {}", "Copying".yellow(), upstream.name().bold()));
                 pb.set_style(
@@ -169,6 +261,10 @@ pub fn sync(
 
                 stored.share(share_dir).await?;
 
+                let verified_tag = stored
+                    .verification()
+                    .map(|verification| format!(" {}", format!("(verified {})", verification.method).green()))
+                    .unwrap_or_default();
                 let cached_tag = stored
                     .was_cached()
                     .then_some(format!("{}", " (cached)".dim()))
@@ -176,7 +272,13 @@ pub fn sync(
 
                 pb.finish();
                 mp.remove(&pb);
-                mp.suspend(|| println!("{} {}{cached_tag}", "Shared".green(), upstream.name().bold()));
+                mp.suspend(|| {
+                    println!(
+                        "{} {}{cached_tag}{verified_tag}",
+                        "Shared".green(),
+                        upstream.name().bold()
+                    );
+                });
                 tp.inc(1);
 
                 Ok(stored) as Result<_, Error>
@@ -216,9 +318,18 @@ pub enum Error {
     /// An error occurred while dealing with a Git-based [Upstream].
     #[error("git")]
     Git(#[from] git::Error),
+    /// An error occurred while dealing with a Mercurial or Fossil based [Upstream].
+    #[error("vcs")]
+    Vcs(#[from] vcs::Error),
+    /// The verification of an [Upstream] is misconfigured.
+    #[error("verify")]
+    Verify(#[from] verify::Error),
     #[error("io")]
     // A generic I/O error occurred.
     Io(#[from] io::Error),
+    /// Upstreams aren't stored and can't be fetched offline.
+    #[error("upstreams not cached, which can't be fetched offline: {}", .0.join(", "))]
+    Offline(Vec<String>),
 }
 
 /// Process git upstreams after cloning and return updated YAML if refs differ from resolved hashes.
@@ -310,6 +421,8 @@ upstreams:
                 original_ref: "main".to_owned(),
                 resolved_hash: "1111222233334444555566667777888899990000".to_owned(),
                 original_index: 0,
+                verify: None,
+                verification: None,
             }),
             Stored::Git(StoredGit {
                 name: "repo2.git".to_owned(),
@@ -319,6 +432,8 @@ upstreams:
                 original_ref: "main".to_owned(),
                 resolved_hash: "aaaa1111bbbb2222cccc3333dddd4444eeee5555".to_owned(),
                 original_index: 1,
+                verify: None,
+                verification: None,
             }),
             Stored::Git(StoredGit {
                 name: "repo3.git".to_owned(),
@@ -328,6 +443,8 @@ upstreams:
                 original_ref: "abcd1234567890abcdef1234567890abcdef1234".to_owned(),
                 resolved_hash: "abcd1234567890abcdef1234567890abcdef1234".to_owned(),
                 original_index: 2,
+                verify: None,
+                verification: None,
             }),
             Stored::Git(StoredGit {
                 name: "repo4.git".to_owned(),
@@ -337,6 +454,8 @@ upstreams:
                 original_ref: "abc123d".to_owned(),
                 resolved_hash: "abc123d567890abcdef1234567890abcdef12345".to_owned(),
                 original_index: 3,
+                verify: None,
+                verification: None,
             }),
             Stored::Git(StoredGit {
                 name: "file.tar.gz".to_owned(),
@@ -347,6 +466,8 @@ upstreams:
                 original_ref: String::new(),
                 resolved_hash: String::new(),
                 original_index: 0,
+                verify: None,
+                verification: None,
             }),
         ];
 
@@ -392,11 +513,16 @@ upstreams:
                 original_ref: "abcd1234567890abcdef1234567890abcdef1234".to_owned(),
                 resolved_hash: "abcd1234567890abcdef1234567890abcdef1234".to_owned(),
                 original_index: 0,
+                verify: None,
+                verification: None,
             }),
             Stored::Plain(StoredPlain {
                 name: "file.tar.gz".to_owned(),
                 path: "/tmp/file.tar.gz".into(),
                 was_cached: false,
+                url: Url::parse("https://example.com/file.tar.gz").unwrap(),
+                hash: "some-hash".parse().unwrap(),
+                verification: None,
             }),
         ];
 
@@ -404,4 +530,69 @@ upstreams:
 
         assert!(result.is_none());
     }
+
+    #[test]
+    fn test_missing_upstreams() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = dir.path().join("cache");
+        fs::create_dir_all(&cache_dir).unwrap();
+
+        let archive = dir.path().join("local-1.0.tar.gz");
+        fs::write(&archive, "source").unwrap();
+        let hash = moss::util::sha256_hash(&mut fs::File::open(&archive).unwrap()).unwrap();
+        let local = Url::from_file_path(&archive).unwrap();
+
+        let recipe_path = dir.path().join("recipe").join("stone.yaml");
+        fs::create_dir_all(recipe_path.parent().unwrap()).unwrap();
+        fs::write(
+            &recipe_path,
+            format!(
+                "\
+name: example
+version: 1.0.0
+release: 1
+homepage: https://example.com
+license: MPL-2.0
+summary: example
+description: example
+upstreams:
+  - {local}: {hash}
+  - https://example.com/remote-1.0.tar.gz: {hash}
+  - git|https://github.com/example/repo.git: abcd1234567890abcdef1234567890abcdef1234
+"
+            ),
+        )
+        .unwrap();
+
+        let recipe = Recipe::load(&recipe_path).unwrap();
+        let paths = crate::Paths::new(&recipe, None, &cache_dir, "/mason", dir.path().join("output")).unwrap();
+        let storage_dir = paths.upstreams().host;
+        let upstreams = parse_recipe(&recipe).unwrap();
+
+        let names = |upstreams: Vec<&Upstream>| {
+            upstreams
+                .into_iter()
+                .map(|upstream| upstream.name().to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(missing(&upstreams, &storage_dir)),
+            ["local-1.0.tar.gz", "remote-1.0.tar.gz", "repo.git"]
+        );
+
+        // Only what's left to fetch is reported, all at once
+        runtime::block_on(upstreams[0].store(&storage_dir, &ProgressBar::hidden())).unwrap();
+        assert_eq!(
+            names(missing(&upstreams, &storage_dir)),
+            ["remote-1.0.tar.gz", "repo.git"]
+        );
+
+        let Err(error) = ensure_stored(&upstreams, &storage_dir) else {
+            panic!("missing upstreams ensured stored");
+        };
+        assert_eq!(
+            error.to_string(),
+            "upstreams not cached, which can't be fetched offline: remote-1.0.tar.gz, repo.git"
+        );
+    }
 }
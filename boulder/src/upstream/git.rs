@@ -12,6 +12,8 @@ use thiserror::Error;
 use tui::{ProgressBar, ProgressStyle};
 use url::Url;
 
+use super::verify::{self, Verification};
+
 /// Upstream based on a Git repository.
 #[derive(Clone, Debug)]
 pub struct Git {
@@ -19,6 +21,10 @@ pub struct Git {
     pub url: Url,
     /// Hash of the commit to be considered as source.
     pub commit: String,
+    /// Limits on the history & objects of the clone.
+    pub partial: gitwrap::Partial,
+    /// Verification of the signature of the commit or its tag, once checked out.
+    pub verify: Option<verify::Signed>,
     pub original_index: usize,
 }
 
@@ -28,6 +34,31 @@ impl Git {
         util::uri_file_name(&self.url)
     }
 
+    /// Clones the latest commit of the default branch of the Git repository
+    /// at `url` into `dest_dir`, returning the upstream pinned to its hash,
+    /// so new recipes are reproducible.
+    pub async fn fetch_new(url: &Url, dest_dir: &Path, pb: &ProgressBar) -> Result<Self, Error> {
+        let cb = set_progress_bar_style(pb);
+
+        let result = gitwrap::Repository::clone_shallow_progress(dest_dir, url, cb).await;
+        pb.finish_and_clear();
+
+        let repo = result.map_err(|error| match error.remote() {
+            Some(gitwrap::Remote::Authentication) => Error::Authentication(url.clone()),
+            Some(gitwrap::Remote::NotFound) => Error::NotFound(url.clone()),
+            None => Error::Git(error),
+        })?;
+        let commit = repo.peel_commit("HEAD").await?;
+
+        Ok(Self {
+            url: url.clone(),
+            commit,
+            partial: gitwrap::Partial::default(),
+            verify: None,
+            original_index: 0,
+        })
+    }
+
     /// Stores the upstream into the storage directory.
     /// If the upstream was already stored but does not include [Self::commit],
     /// it is updated contextually. If it does not exist, the Git repository is cloned.
@@ -45,9 +76,13 @@ impl Git {
             Err(Error::Git(_)) => {
                 cached = false;
                 self.remove(storage_dir)?;
-                repo = clone(&self.url, &self.stored_path(storage_dir), pb).await?;
+                repo = clone(&self.url, &self.stored_path(storage_dir), &self.partial, pb).await?;
             }
-            Err(Error::Io(e)) => return Err(Error::from(e)),
+            Err(e) => return Err(e),
+        }
+
+        if let Some(depth) = self.partial.depth {
+            deepen(&repo, &self.commit, depth).await?;
         }
 
         let resolved_hash = repo.peel_commit(&self.commit).await?;
@@ -60,6 +95,8 @@ impl Git {
             original_ref: self.commit.to_owned(),
             resolved_hash,
             original_index: self.original_index,
+            verify: self.verify.clone(),
+            verification: None,
         })
     }
 
@@ -94,6 +131,8 @@ impl Git {
                 original_ref: self.commit.to_owned(),
                 resolved_hash,
                 original_index: self.original_index,
+                verify: self.verify.clone(),
+                verification: None,
             },
             has_ref,
         ))
@@ -101,7 +140,7 @@ impl Git {
 
     /// Returns a relative PathBuf where this Git repository
     /// should be stored within the storage directory.
-    fn stored_path(&self, storage_dir: &Path) -> PathBuf {
+    pub fn stored_path(&self, storage_dir: &Path) -> PathBuf {
         storage_dir.join("git").join(self.directory_name())
     }
 
@@ -110,17 +149,23 @@ impl Git {
     /// It is a composition of the hostname and the repository name
     /// so that it's unique.
     fn directory_name(&self) -> PathBuf {
-        let host = self.url.host_str();
-        let path = self.url.path();
+        directory_name(&self.url)
+    }
+}
 
-        let mut name = String::with_capacity(host.unwrap_or("").len() + 1 + path.len());
-        if let Some(host) = host {
-            name.push_str(host);
-            name.push('_');
-        }
-        name.push_str(&path.trim_start_matches('/').replace('/', "."));
-        name.into()
+/// Returns a name unique to the repository at `url`, composed
+/// of the hostname and the path of the repository.
+pub(super) fn directory_name(url: &Url) -> PathBuf {
+    let host = url.host_str();
+    let path = url.path();
+
+    let mut name = String::with_capacity(host.unwrap_or("").len() + 1 + path.len());
+    if let Some(host) = host {
+        name.push_str(host);
+        name.push('_');
     }
+    name.push_str(&path.trim_start_matches('/').replace('/', "."));
+    name.into()
 }
 
 /// Information available after [Git] is stored on disk.
@@ -136,11 +181,16 @@ pub struct StoredGit {
     pub resolved_hash: String,
     pub original_index: usize,
     pub repo: gitwrap::Repository,
+    /// As [Git::verify].
+    pub verify: Option<verify::Signed>,
+    /// Of the commit or its tag, once shared if verified.
+    pub verification: Option<Verification>,
 }
 
 impl StoredGit {
-    /// Shares the Git repository in preparation of a build.
-    pub async fn share(&self, dest_dir: &Path) -> Result<(), Error> {
+    /// Shares the Git repository in preparation of a build,
+    /// returning the verification of its signature if verified.
+    pub async fn share(&self, dest_dir: &Path) -> Result<Option<Verification>, Error> {
         if let Some(parent) = dest_dir.parent() {
             fs::create_dir_all(parent)?;
         }
@@ -156,7 +206,25 @@ impl StoredGit {
         // Finally checkout the desired commit
         cloned.checkout(&self.resolved_hash).await?;
 
-        Ok(())
+        // Submodules of a partial clone are left for after the checkout,
+        // which fetches the objects omitted
+        if cloned.partial_clone_filter().await?.is_some() {
+            cloned.update_submodules().await?;
+        }
+
+        let Some(verify) = &self.verify else {
+            return Ok(None);
+        };
+        let verification = verify::git(
+            &verify::GitCli,
+            &self.name,
+            verify,
+            dest_dir,
+            &self.original_ref,
+            &self.resolved_hash,
+        )?;
+
+        Ok(Some(verification))
     }
 }
 
@@ -169,12 +237,26 @@ pub enum Error {
     /// A generic I/O error occurred.
     #[error("{0}")]
     Io(#[from] io::Error),
+    /// The Git repository requires credentials which are missing or were rejected.
+    #[error("authentication failed for {0}, is the repository private?")]
+    Authentication(Url),
+    /// There is no Git repository at the URL.
+    #[error("no Git repository found at {0}")]
+    NotFound(Url),
+    /// The signature of the commit or its tag could not be verified.
+    #[error("verify signature: {0}")]
+    Verify(#[from] verify::Error),
 }
 
-async fn clone(url: &Url, path: &Path, pb: &ProgressBar) -> Result<gitwrap::Repository, gitwrap::Error> {
+async fn clone(
+    url: &Url,
+    path: &Path,
+    partial: &gitwrap::Partial,
+    pb: &ProgressBar,
+) -> Result<gitwrap::Repository, gitwrap::Error> {
     let cb = set_progress_bar_style(pb);
 
-    let result = gitwrap::Repository::clone_mirror_progress(path, url, cb).await;
+    let result = gitwrap::Repository::clone_mirror_progress(path, url, partial, cb).await;
     pb.finish_and_clear();
 
     result
@@ -189,6 +271,30 @@ async fn fetch(repo: &gitwrap::Repository, pb: &ProgressBar) -> Result<(), gitwr
     result
 }
 
+/// Deepens the history of a shallow `repo` until it contains `commit`,
+/// doubling the commits fetched each time before fetching all of it.
+async fn deepen(repo: &gitwrap::Repository, commit: &str, depth: u32) -> Result<(), gitwrap::Error> {
+    const ATTEMPTS: usize = 4;
+
+    // Tags are peeled, missing objects fail
+    let contains = async || repo.has_commit(&format!("{commit}^{{commit}}")).await.unwrap_or(false);
+
+    let mut by = depth;
+    for _ in 0..ATTEMPTS {
+        if contains().await || !repo.is_shallow().await? {
+            return Ok(());
+        }
+        repo.deepen(by).await?;
+        by = by.saturating_mul(2);
+    }
+
+    if !contains().await && repo.is_shallow().await? {
+        repo.unshallow().await?;
+    }
+
+    Ok(())
+}
+
 fn set_progress_bar_style(pb: &ProgressBar) -> impl Fn(gitwrap::FetchProgress) {
     pb.set_length(100);
     pb.set_style(
@@ -202,3 +308,149 @@ fn set_progress_bar_style(pb: &ProgressBar) -> impl Fn(gitwrap::FetchProgress) {
         pb.set_prefix(prog.speed);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::process;
+
+    use moss::runtime;
+
+    use super::*;
+
+    /// Versions committed & tagged by [`setup_test_repo`], in order
+    const VERSIONS: [&str; 4] = ["1.0", "1.1", "1.2", "1.3"];
+
+    /// Creates a Git repository in `dir` with a commit tagged for each of
+    /// the [`VERSIONS`], returning the hash of the last one
+    fn setup_test_repo(dir: &Path) -> String {
+        let git = |args: &[&str]| {
+            let output = process::Command::new("git")
+                .arg("-C")
+                .arg(dir)
+                .args(["-c", "user.name=boulder", "-c", "user.email=boulder@example.com"])
+                .args(args)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+            String::from_utf8(output.stdout).unwrap().trim().to_owned()
+        };
+
+        fs::create_dir_all(dir).unwrap();
+        git(&["init", "--initial-branch=trunk"]);
+        // Allow partial clones over file://
+        git(&["config", "uploadpack.allowfilter", "true"]);
+        for version in VERSIONS {
+            fs::write(dir.join("VERSION"), version).unwrap();
+            git(&["add", "VERSION"]);
+            git(&["commit", "-m", version]);
+            git(&["tag", &format!("v{version}")]);
+        }
+
+        git(&["rev-parse", "HEAD"])
+    }
+
+    #[test]
+    fn fetch_new() {
+        let dir = tempfile::tempdir().unwrap();
+        let head = setup_test_repo(&dir.path().join("origin"));
+        let url = Url::from_directory_path(dir.path().join("origin")).unwrap();
+
+        let dest_dir = dir.path().join("extract").join("origin");
+        let git = runtime::block_on(Git::fetch_new(&url, &dest_dir, &ProgressBar::hidden())).unwrap();
+
+        // Pinned to the full hash of the default branch
+        assert_eq!(git.url, url);
+        assert_eq!(git.commit, head);
+        assert_eq!(git.commit.len(), 40);
+        assert_eq!(fs::read_to_string(dest_dir.join("VERSION")).unwrap(), "1.3");
+
+        // Only the latest commit is cloned
+        let output = process::Command::new("git")
+            .arg("-C")
+            .arg(&dest_dir)
+            .args(["rev-list", "--count", "HEAD"])
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "1");
+    }
+
+    /// Stores `git` like a build, returning the version shared at its commit
+    fn store_and_share(git: &Git, dir: &Path) -> (StoredGit, String) {
+        let stored = runtime::block_on(git.store(&dir.join("storage"), &ProgressBar::hidden())).unwrap();
+
+        let dest_dir = dir.join("share");
+        util::remove_dir_all(&dest_dir).unwrap();
+        runtime::block_on(stored.share(&dest_dir)).unwrap();
+
+        let version = fs::read_to_string(dest_dir.join("VERSION")).unwrap();
+        (stored, version)
+    }
+
+    #[test]
+    fn shallow_deepened() {
+        let dir = tempfile::tempdir().unwrap();
+        setup_test_repo(&dir.path().join("origin"));
+        let url = Url::from_directory_path(dir.path().join("origin")).unwrap();
+
+        let git = |commit: &str| Git {
+            url: url.clone(),
+            commit: commit.to_owned(),
+            partial: gitwrap::Partial {
+                depth: Some(1),
+                filter: None,
+            },
+            verify: None,
+            original_index: 0,
+        };
+
+        let (stored, version) = store_and_share(&git("v1.3"), dir.path());
+        assert_eq!(version, "1.3");
+        assert!(runtime::block_on(stored.repo.is_shallow()).unwrap());
+
+        // The oldest tag isn't within the depth, so history is deepened to reach it
+        let (stored, version) = store_and_share(&git("v1.0"), dir.path());
+        assert_eq!(version, "1.0");
+        assert!(runtime::block_on(stored.repo.has_commit("v1.0^{commit}")).unwrap());
+    }
+
+    #[test]
+    fn filtered() {
+        let dir = tempfile::tempdir().unwrap();
+        setup_test_repo(&dir.path().join("origin"));
+        let url = Url::from_directory_path(dir.path().join("origin")).unwrap();
+
+        let git = Git {
+            url,
+            commit: "v1.1".to_owned(),
+            partial: gitwrap::Partial {
+                depth: None,
+                filter: Some("blob:none".to_owned()),
+            },
+            verify: None,
+            original_index: 0,
+        };
+
+        // Blobs omitted by the mirror are fetched from the origin once shared
+        let (stored, version) = store_and_share(&git, dir.path());
+        assert_eq!(version, "1.1");
+        assert_eq!(
+            runtime::block_on(stored.repo.partial_clone_filter())
+                .unwrap()
+                .as_deref(),
+            Some("blob:none")
+        );
+    }
+
+    #[test]
+    fn fetch_new_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = Url::from_directory_path(dir.path().join("missing")).unwrap();
+
+        let result = runtime::block_on(Git::fetch_new(
+            &url,
+            &dir.path().join("extract"),
+            &ProgressBar::hidden(),
+        ));
+        assert!(matches!(result, Err(Error::NotFound(missing)) if missing == url));
+    }
+}
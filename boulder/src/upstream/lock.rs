@@ -0,0 +1,383 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Provenance of the upstreams a recipe was last built from
+//!
+//! A `stone.lock` next to the recipe records the commit each Git upstream's
+//! ref resolved to, likewise the id each Mercurial or Fossil upstream's revision
+//! resolved to, and the SHA256 of each plain upstream. Builds fail once
+//! an upstream no longer matches, i.e. a release was silently retagged,
+//! unless the lock is explicitly updated.
+
+use std::{fmt, io, path::Path};
+
+use chrono::{DateTime, Utc};
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use stone_recipe::upstream;
+use thiserror::Error;
+use url::Url;
+
+use super::Stored;
+
+/// File name of the lock, within the recipe dir
+pub const FILE_NAME: &str = "stone.lock";
+
+/// Version of the lock format written
+pub const VERSION: u32 = 1;
+
+/// The upstreams of a recipe, as fetched
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lock {
+    pub version: u32,
+    /// Sorted by URL & ref
+    pub upstreams: Vec<Locked>,
+}
+
+/// An upstream, as fetched
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Locked {
+    pub url: Url,
+    #[serde(flatten)]
+    pub source: Source,
+    /// When first fetched
+    pub fetched_at: DateTime<Utc>,
+    /// Bytes of an archive, a clone having no fixed size
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+}
+
+/// What an upstream is verified by
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Source {
+    Plain {
+        sha256: String,
+    },
+    Git {
+        /// As given by the recipe
+        #[serde(rename = "ref")]
+        git_ref: String,
+        /// Hash of the commit the ref resolved to
+        commit: String,
+    },
+    /// A Mercurial or Fossil repository
+    Vcs {
+        vcs: upstream::Vcs,
+        /// As given by the recipe
+        rev: String,
+        /// Of the changeset or check-in the revision resolved to
+        id: String,
+    },
+}
+
+impl Locked {
+    /// Lock a `stored` upstream, first fetched at `fetched_at`
+    fn new(stored: &Stored, fetched_at: DateTime<Utc>) -> Result<Self, Error> {
+        Ok(match stored {
+            Stored::Plain(plain) => Self {
+                url: plain.url.clone(),
+                source: Source::Plain {
+                    sha256: plain.hash.to_string(),
+                },
+                fetched_at,
+                size: Some(fs::metadata(&plain.path)?.len()),
+            },
+            Stored::Git(git) => Self {
+                url: git.url.clone(),
+                source: Source::Git {
+                    git_ref: git.original_ref.clone(),
+                    commit: git.resolved_hash.clone(),
+                },
+                fetched_at,
+                size: None,
+            },
+            Stored::Vcs(vcs) => Self {
+                url: vcs.url.clone(),
+                source: Source::Vcs {
+                    vcs: vcs.kind,
+                    rev: vcs.revision.to_string(),
+                    id: vcs.id.clone(),
+                },
+                fetched_at,
+                size: None,
+            },
+        })
+    }
+
+    /// Identifies the upstream across builds, a repository by its ref
+    fn key(&self) -> (&Url, Option<&str>) {
+        match &self.source {
+            Source::Plain { .. } => (&self.url, None),
+            Source::Git { git_ref, .. } => (&self.url, Some(git_ref)),
+            Source::Vcs { rev, .. } => (&self.url, Some(rev)),
+        }
+    }
+
+    /// What the upstream must still match
+    fn pinned(&self) -> &str {
+        match &self.source {
+            Source::Plain { sha256 } => sha256,
+            Source::Git { commit, .. } => commit,
+            Source::Vcs { id, .. } => id,
+        }
+    }
+}
+
+impl Lock {
+    /// Lock the `stored` upstreams, fetched at `now` unless already locked by `previous`
+    pub fn new(stored: &[Stored], previous: Option<&Lock>, now: DateTime<Utc>) -> Result<Self, Error> {
+        let mut upstreams = stored
+            .iter()
+            .map(|stored| {
+                let locked = Locked::new(stored, now)?;
+
+                // Unchanged upstreams weren't fetched again
+                let fetched_at = previous
+                    .and_then(|previous| previous.find(&locked))
+                    .filter(|previous| previous.source == locked.source)
+                    .map_or(now, |previous| previous.fetched_at);
+
+                Ok(Locked { fetched_at, ..locked })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        upstreams.sort_by(|a, b| a.key().cmp(&b.key()));
+
+        Ok(Self {
+            version: VERSION,
+            upstreams,
+        })
+    }
+
+    /// Load the lock of the recipe in `dir`, if there's one
+    pub fn load(dir: &Path) -> Result<Option<Self>, Error> {
+        let path = dir.join(FILE_NAME);
+        let file = match fs::File::open(&path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+
+        let lock = serde_yaml::from_reader::<_, Self>(file)?;
+        if lock.version > VERSION {
+            return Err(Error::UnsupportedVersion(lock.version));
+        }
+
+        Ok(Some(lock))
+    }
+
+    /// Save as the lock of the recipe in `dir`
+    pub fn save(&self, dir: &Path) -> Result<(), Error> {
+        fs::write(dir.join(FILE_NAME), serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Upstreams of `current` which no longer match what they're locked to
+    ///
+    /// Upstreams added since or no longer used aren't drift.
+    pub fn drift(&self, current: &Lock) -> Vec<Drift> {
+        current
+            .upstreams
+            .iter()
+            .filter_map(|fetched| {
+                let locked = self.find(fetched)?;
+
+                (locked.pinned() != fetched.pinned()).then(|| Drift {
+                    url: fetched.url.clone(),
+                    locked: locked.pinned().to_owned(),
+                    fetched: fetched.pinned().to_owned(),
+                })
+            })
+            .collect()
+    }
+
+    fn find(&self, upstream: &Locked) -> Option<&Locked> {
+        self.upstreams.iter().find(|locked| locked.key() == upstream.key())
+    }
+}
+
+/// An upstream fetched as other than it's locked to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Drift {
+    pub url: Url,
+    /// The commit or SHA256 locked
+    pub locked: String,
+    /// The commit or SHA256 fetched instead
+    pub fetched: String,
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is locked to {} but was {}", self.url, self.locked, self.fetched)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{FILE_NAME} version {0} is newer than supported")]
+    UnsupportedVersion(u32),
+    #[error("serialize {FILE_NAME}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("io")]
+    Io(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::upstream::{git::StoredGit, plain::StoredPlain, vcs::StoredVcs};
+
+    const COMMIT: &str = "1111222233334444555566667777888899990000";
+    const RETAGGED: &str = "aaaa1111bbbb2222cccc3333dddd4444eeee5555";
+
+    fn time(day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 3, day, 12, 0, 0).unwrap()
+    }
+
+    fn git(git_ref: &str, commit: &str) -> Stored {
+        Stored::Git(StoredGit {
+            name: "repo.git".to_owned(),
+            repo: gitwrap::null_repository(),
+            was_cached: false,
+            url: Url::parse("https://github.com/example/repo.git").unwrap(),
+            original_ref: git_ref.to_owned(),
+            resolved_hash: commit.to_owned(),
+            original_index: 1,
+            verify: None,
+            verification: None,
+        })
+    }
+
+    fn plain(dir: &Path, content: &str, hash: &str) -> Stored {
+        let path = dir.join("file-1.0.tar.gz");
+        fs::write(&path, content).unwrap();
+
+        Stored::Plain(StoredPlain {
+            name: "file-1.0.tar.gz".to_owned(),
+            path,
+            was_cached: true,
+            url: Url::parse("https://example.com/file-1.0.tar.gz").unwrap(),
+            hash: hash.parse().unwrap(),
+            verification: None,
+        })
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let stored = [git("v1.0", COMMIT), plain(dir.path(), "source", "5b4f1a")];
+
+        let lock = Lock::new(&stored, None, time(1)).unwrap();
+        assert_eq!(lock.version, VERSION);
+        assert_eq!(
+            lock.upstreams[0],
+            Locked {
+                url: Url::parse("https://example.com/file-1.0.tar.gz").unwrap(),
+                source: Source::Plain {
+                    sha256: "5b4f1a".to_owned()
+                },
+                fetched_at: time(1),
+                size: Some(6),
+            }
+        );
+
+        lock.save(dir.path()).unwrap();
+        let yaml = fs::read_to_string(dir.path().join(FILE_NAME)).unwrap();
+        assert!(yaml.contains("kind: git\n  ref: v1.0\n  commit: '1111222233334444555566667777888899990000'\n"));
+
+        assert_eq!(Lock::load(dir.path()).unwrap(), Some(lock));
+        assert_eq!(Lock::load(&dir.path().join("missing")).unwrap(), None);
+
+        fs::write(dir.path().join(FILE_NAME), "version: 2\nupstreams: []\n").unwrap();
+        assert!(matches!(Lock::load(dir.path()), Err(Error::UnsupportedVersion(2))));
+    }
+
+    #[test]
+    fn drift() {
+        let dir = tempfile::tempdir().unwrap();
+        let locked = Lock::new(
+            &[git("v1.0", COMMIT), plain(dir.path(), "source", "5b4f1a")],
+            None,
+            time(1),
+        )
+        .unwrap();
+
+        // Fetched again as locked, keeping when first fetched
+        let unchanged = Lock::new(
+            &[git("v1.0", COMMIT), plain(dir.path(), "source", "5b4f1a")],
+            Some(&locked),
+            time(2),
+        )
+        .unwrap();
+        assert!(locked.drift(&unchanged).is_empty());
+        assert_eq!(unchanged, locked);
+
+        // The tag was moved & the archive replaced
+        let retagged = Lock::new(
+            &[git("v1.0", RETAGGED), plain(dir.path(), "changed", "9c0e7d")],
+            Some(&locked),
+            time(2),
+        )
+        .unwrap();
+        assert_eq!(
+            locked.drift(&retagged),
+            [
+                Drift {
+                    url: Url::parse("https://example.com/file-1.0.tar.gz").unwrap(),
+                    locked: "5b4f1a".to_owned(),
+                    fetched: "9c0e7d".to_owned(),
+                },
+                Drift {
+                    url: Url::parse("https://github.com/example/repo.git").unwrap(),
+                    locked: COMMIT.to_owned(),
+                    fetched: RETAGGED.to_owned(),
+                },
+            ]
+        );
+        assert!(retagged.upstreams.iter().all(|upstream| upstream.fetched_at == time(2)));
+        assert_eq!(
+            locked.drift(&retagged)[1].to_string(),
+            format!("https://github.com/example/repo.git is locked to {COMMIT} but was {RETAGGED}")
+        );
+
+        // Another ref is a new upstream rather than drift
+        let bumped = Lock::new(&[git("v1.1", RETAGGED)], Some(&locked), time(3)).unwrap();
+        assert!(locked.drift(&bumped).is_empty());
+        assert_eq!(bumped.upstreams[0].fetched_at, time(3));
+    }
+
+    #[test]
+    fn vcs() {
+        let hg = |id: &str| {
+            Stored::Vcs(StoredVcs {
+                name: "hello".to_owned(),
+                was_cached: false,
+                kind: upstream::Vcs::Mercurial,
+                url: Url::parse("https://hg.example.com/hello").unwrap(),
+                path: "/var/cache/boulder/upstreams/hg/hg.example.com_hello".into(),
+                revision: upstream::Revision::Tag("v1.0".to_owned()),
+                id: id.to_owned(),
+                original_index: 0,
+            })
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let locked = Lock::new(&[hg(COMMIT)], None, time(1)).unwrap();
+        locked.save(dir.path()).unwrap();
+        let yaml = fs::read_to_string(dir.path().join(FILE_NAME)).unwrap();
+        assert!(
+            yaml.contains(
+                "kind: vcs\n  vcs: mercurial\n  rev: v1.0\n  id: '1111222233334444555566667777888899990000'\n"
+            )
+        );
+        assert_eq!(Lock::load(dir.path()).unwrap(), Some(locked.clone()));
+
+        let retagged = Lock::new(&[hg(RETAGGED)], Some(&locked), time(2)).unwrap();
+        assert_eq!(
+            locked.drift(&retagged)[0].to_string(),
+            format!("https://hg.example.com/hello is locked to {COMMIT} but was {RETAGGED}")
+        );
+    }
+}
@@ -15,6 +15,8 @@ use thiserror::Error;
 use tui::{ProgressBar, ProgressStyle};
 use url::Url;
 
+use super::verify::{self, Verification};
+
 /// Upstream based on an archive (typically a tarball).
 #[derive(Debug, Clone)]
 pub struct Plain {
@@ -25,6 +27,8 @@ pub struct Plain {
     /// Name of the upstream when stored in the storage
     /// directory. If None, a default name is implied from [Self::url].
     pub rename: Option<String>,
+    /// Verification of the detached signature of the archive.
+    pub verify: Option<verify::Detached>,
 }
 
 impl Plain {
@@ -43,11 +47,17 @@ impl Plain {
     /// If the upstream was already stored and [Self::hash] matches,
     /// no write operation takes place. If the source archive was
     /// not stored or the hash does not match, it is overwritten.
+    ///
+    /// With [Self::verify], its signature is verified before the hash,
+    /// and the source archive removed if invalid.
     pub async fn store(&self, storage_dir: &Path, pb: &ProgressBar) -> Result<StoredPlain, Error> {
         use fs_err::tokio as fs;
 
         match self.stored(storage_dir) {
-            Ok(stored) => return Ok(stored),
+            Ok(mut stored) => {
+                stored.verification = self.verify(&stored.path, storage_dir).await?;
+                return Ok(stored);
+            }
             Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => {}
             Err(Error::HashMismatch { .. }) => {}
             Err(err) => return Err(err),
@@ -59,6 +69,13 @@ impl Plain {
         }
 
         let hash = fetch(self.url.clone(), &path, pb).await?;
+        let verification = match self.verify(&path, storage_dir).await {
+            Ok(verification) => verification,
+            Err(error) => {
+                fs::remove_file(&path).await?;
+                return Err(error);
+            }
+        };
         if hash != self.hash {
             fs::remove_file(&path).await?;
 
@@ -73,9 +90,34 @@ impl Plain {
             name: self.name().to_owned(),
             path,
             was_cached: false,
+            url: self.url.clone(),
+            hash: self.hash.clone(),
+            verification,
         })
     }
 
+    /// Verifies the source archive at `path` against its signature, fetched
+    /// into the storage directory unless already stored.
+    async fn verify(&self, path: &Path, storage_dir: &Path) -> Result<Option<Verification>, Error> {
+        let Some(verify) = &self.verify else {
+            return Ok(None);
+        };
+
+        let signature = self.signature_path(storage_dir);
+        if !signature.exists() {
+            request::download(verify.signature.clone(), &signature).await?;
+        }
+
+        match verify::detached(&verify::Gpgv, self.name(), verify, path, &signature) {
+            Ok(verification) => Ok(Some(verification)),
+            Err(error) => {
+                // Fetched again, should the signature be replaced upstream
+                fs::remove_file(&signature)?;
+                Err(error.into())
+            }
+        }
+    }
+
     /// Unconditionally removes the source archive, and the parent
     /// directories if they are empty, within the storage directory.
     /// If the source archive does not exist, this function returns
@@ -87,6 +129,12 @@ impl Plain {
         let dir = self.stored_path(storage_dir);
 
         fs::remove_file(&dir)?;
+        if self.verify.is_some() {
+            let signature = self.signature_path(storage_dir);
+            if signature.exists() {
+                fs::remove_file(signature)?;
+            }
+        }
         if let Some(parent) = dir.parent() {
             Ok(util::remove_empty_dirs(parent, storage_dir)?)
         } else {
@@ -115,15 +163,32 @@ impl Plain {
             name: self.name().to_owned(),
             path,
             was_cached: true,
+            url: self.url.clone(),
+            hash: self.hash.clone(),
+            verification: None,
         })
     }
 
+    /// Whether the signature of the source archive, if verified, is
+    /// stored in the storage directory.
+    pub fn signature_stored(&self, storage_dir: &Path) -> bool {
+        self.verify.is_none() || self.signature_path(storage_dir).exists()
+    }
+
     /// Returns a relative PathBuf where this source archive
     /// should be stored within the storage directory.
-    fn stored_path(&self, storage_dir: &Path) -> PathBuf {
+    pub fn stored_path(&self, storage_dir: &Path) -> PathBuf {
         storage_dir.join("fetched").join(self.file_path())
     }
 
+    /// Returns the path where the signature of this source archive
+    /// is stored, alongside it, within the storage directory.
+    fn signature_path(&self, storage_dir: &Path) -> PathBuf {
+        let mut path = self.stored_path(storage_dir).into_os_string();
+        path.push(".sig");
+        path.into()
+    }
+
     /// Returns a relative PathBuf based on the hashes of [Self::url]
     /// and [Self::hash].
     ///
@@ -149,6 +214,12 @@ pub struct StoredPlain {
     pub path: PathBuf,
     /// Whether the source archived was already stored with valid hash.
     pub was_cached: bool,
+    /// URL the source archive was fetched from.
+    pub url: Url,
+    /// SHA256 hash of the source archive.
+    pub hash: Hash,
+    /// Of the signature of the source archive, if verified.
+    pub verification: Option<Verification>,
 }
 
 impl StoredPlain {
@@ -226,6 +297,9 @@ pub enum Error {
     #[error("io")]
     /// A generic I/O error occurred.
     Io(#[from] io::Error),
+    /// The signature of the source archive could not be verified.
+    #[error("verify signature")]
+    Verify(#[from] verify::Error),
 }
 
 async fn fetch(url: Url, dest: &Path, pb: &ProgressBar) -> Result<Hash, Error> {
@@ -241,3 +315,56 @@ async fn fetch(url: Url, dest: &Path, pb: &ProgressBar) -> Result<Hash, Error> {
         .try_into()
         .map_err(Error::from)
 }
+
+#[cfg(test)]
+mod test {
+    use moss::runtime;
+
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../test/signatures")
+            .join(name)
+    }
+
+    #[test]
+    fn store_verified() {
+        if std::process::Command::new("gpgv").arg("--version").output().is_err() {
+            eprintln!("gpgv isn't installed, skipping");
+            return;
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let archive = fixture("source-1.0.txt");
+        let hash = util::sha256_hash(&mut fs::File::open(&archive).unwrap()).unwrap();
+        let plain = |key: &str| Plain {
+            url: Url::from_file_path(&archive).unwrap(),
+            hash: hash.clone().try_into().unwrap(),
+            rename: None,
+            verify: Some(verify::Detached {
+                signature: Url::from_file_path(fixture("source-1.0.txt.asc")).unwrap(),
+                key: fixture(key),
+            }),
+        };
+
+        // Signed by another key, so neither is kept
+        let untrusted = plain("other.asc");
+        let result = runtime::block_on(untrusted.store(dir.path(), &ProgressBar::hidden()));
+        assert!(matches!(result, Err(Error::Verify(verify::Error::Invalid { .. }))));
+        assert!(!untrusted.stored_path(dir.path()).exists());
+        assert!(!untrusted.signature_stored(dir.path()));
+
+        let trusted = plain("release.asc");
+        let stored = runtime::block_on(trusted.store(dir.path(), &ProgressBar::hidden())).unwrap();
+        let verification = stored.verification.unwrap();
+        assert_eq!(verification.upstream, "source-1.0.txt");
+        assert_eq!(verification.signer.key, "95B27936929186B1F794FDC17E64185874C6FCC1");
+        assert!(trusted.signature_stored(dir.path()));
+
+        // Verified again once cached
+        let stored = runtime::block_on(trusted.store(dir.path(), &ProgressBar::hidden())).unwrap();
+        assert!(stored.was_cached);
+        assert!(stored.verification.is_some());
+    }
+}
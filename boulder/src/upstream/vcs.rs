@@ -0,0 +1,438 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Upstreams of Mercurial & Fossil repositories
+//!
+//! Repositories are cloned with the `hg` or `fossil` of the host into the
+//! storage directory, without a working copy, & pulled once they lack the
+//! revision of the recipe. The revision is resolved to the id of its changeset
+//! or check-in, which is what's checked out when shared & locked.
+
+use std::{
+    ffi::OsStr,
+    io,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use fs_err as fs;
+use moss::util;
+use stone_recipe::upstream::{Revision, Vcs as Kind};
+use thiserror::Error;
+use tokio::process::Command;
+use url::Url;
+
+use super::git;
+
+/// Upstream based on a Mercurial or Fossil repository.
+#[derive(Clone, Debug)]
+pub struct Vcs {
+    pub kind: Kind,
+    /// URL of origin.
+    pub url: Url,
+    /// Revision, tag or branch to be considered as source.
+    pub revision: Revision,
+    pub original_index: usize,
+}
+
+impl Vcs {
+    /// Returns the name of the upstream. It is implied from the URL.
+    pub fn name(&self) -> &str {
+        util::uri_file_name(&self.url)
+    }
+
+    /// Clones the head of the default branch of the repository at `url`
+    /// into `dest_dir`, returning the upstream pinned to its id, so new
+    /// recipes are reproducible.
+    pub async fn fetch_new(kind: Kind, url: &Url, dest_dir: &Path) -> Result<Self, Error> {
+        let default_branch = match kind {
+            Kind::Mercurial => "default",
+            Kind::Fossil => "trunk",
+        };
+        let vcs = Self {
+            kind,
+            url: url.clone(),
+            revision: Revision::Branch(default_branch.to_owned()),
+            original_index: 0,
+        };
+
+        // Only the working copy is of use to a draft
+        let storage_dir = tempfile::tempdir()?;
+        let stored = vcs.store(storage_dir.path()).await?;
+        stored.share(dest_dir).await?;
+
+        Ok(Self {
+            revision: Revision::Rev(stored.id),
+            ..vcs
+        })
+    }
+
+    /// Stores the upstream into the storage directory.
+    /// If the upstream was already stored but does not include [Self::revision],
+    /// it is pulled. If it does not exist, the repository is cloned.
+    pub async fn store(&self, storage_dir: &Path) -> Result<StoredVcs, Error> {
+        let path = self.stored_path(storage_dir);
+
+        let (id, cached) = match self.stored(storage_dir).await? {
+            Some(id) => (id, true),
+            None if path.exists() => {
+                // Both pull from the URL cloned
+                self.run(["pull", "-R"], [path.as_os_str()]).await?;
+                (self.resolve(&path).await?, false)
+            }
+            None => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let args = match self.kind {
+                    Kind::Mercurial => ["clone", "--noupdate"].as_slice(),
+                    Kind::Fossil => ["clone"].as_slice(),
+                };
+                self.run(args.iter().copied(), [OsStr::new(self.url.as_str()), path.as_os_str()])
+                    .await?;
+                (self.resolve(&path).await?, false)
+            }
+        };
+
+        Ok(StoredVcs {
+            name: self.name().to_owned(),
+            was_cached: cached,
+            kind: self.kind,
+            url: self.url.clone(),
+            path,
+            revision: self.revision.clone(),
+            id,
+            original_index: self.original_index,
+        })
+    }
+
+    /// Returns the id [Self::revision] resolves to, if the stored
+    /// repository exists and contains it.
+    pub async fn stored(&self, storage_dir: &Path) -> Result<Option<String>, Error> {
+        let path = self.stored_path(storage_dir);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        match self.resolve(&path).await {
+            Ok(id) => Ok(Some(id)),
+            Err(Error::Command { .. }) => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Unconditionally removes the stored repository within the storage
+    /// directory. If it does not exist, this function returns
+    /// successfully (it is idempotent).
+    pub fn remove(&self, storage_dir: &Path) -> Result<(), Error> {
+        let path = self.stored_path(storage_dir);
+        match self.kind {
+            Kind::Mercurial => util::remove_dir_all(&path)?,
+            Kind::Fossil => match fs::remove_file(&path) {
+                Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error.into()),
+                _ => {}
+            },
+        }
+        Ok(())
+    }
+
+    /// Returns a relative PathBuf where this repository should be
+    /// stored within the storage directory: a directory for Mercurial,
+    /// a single file for Fossil.
+    pub fn stored_path(&self, storage_dir: &Path) -> PathBuf {
+        let mut name = git::directory_name(&self.url).into_os_string();
+        if self.kind == Kind::Fossil {
+            name.push(".fossil");
+        }
+        storage_dir.join(self.kind.command()).join(name)
+    }
+
+    /// The id of the changeset or check-in [Self::revision] resolves to
+    /// within the repository at `path`.
+    async fn resolve(&self, path: &Path) -> Result<String, Error> {
+        let revision = OsStr::new(self.revision.as_str());
+        match self.kind {
+            Kind::Mercurial => {
+                let output = self
+                    .run(
+                        ["log", "--template", "{node}", "-R"],
+                        [path.as_os_str(), OsStr::new("-r"), revision],
+                    )
+                    .await?;
+                Ok(output.trim().to_owned())
+            }
+            Kind::Fossil => {
+                let output = self.run(["info", "-R"], [path.as_os_str(), revision]).await?;
+                fossil_hash(&output).ok_or_else(|| Error::Command {
+                    command: "fossil info".to_owned(),
+                    stderr: format!("no hash of {} in {output:?}", self.revision),
+                })
+            }
+        }
+    }
+
+    /// Runs the command of [Self::kind] with `args` followed by `paths`,
+    /// returning its output.
+    async fn run<'a>(
+        &self,
+        args: impl IntoIterator<Item = &'a str>,
+        paths: impl IntoIterator<Item = &'a OsStr>,
+    ) -> Result<String, Error> {
+        run(self.kind, None, args.into_iter().map(OsStr::new).chain(paths)).await
+    }
+}
+
+/// Information available after [Vcs] is stored on disk.
+#[derive(Debug)]
+pub struct StoredVcs {
+    /// Name of the upstream, as returned by [Vcs::name].
+    pub name: String,
+    /// Whether the stored repository already contained [Vcs::revision].
+    pub was_cached: bool,
+    pub kind: Kind,
+    pub url: Url,
+    /// Of the stored repository.
+    pub path: PathBuf,
+    pub revision: Revision,
+    /// Id of the changeset or check-in the revision resolved to.
+    pub id: String,
+    pub original_index: usize,
+}
+
+impl StoredVcs {
+    /// Shares the repository, checked out at [Self::id], in preparation of a build.
+    pub async fn share(&self, dest_dir: &Path) -> Result<(), Error> {
+        if let Some(parent) = dest_dir.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        match self.kind {
+            Kind::Mercurial => {
+                let args = [OsStr::new("clone"), OsStr::new("--updaterev"), OsStr::new(&self.id)];
+                run(
+                    self.kind,
+                    None,
+                    args.into_iter().chain([self.path.as_os_str(), dest_dir.as_os_str()]),
+                )
+                .await?;
+
+                // Pull from the original remote, rather than the stored repository
+                fs::write(
+                    dest_dir.join(".hg").join("hgrc"),
+                    format!("[paths]\ndefault = {}\n", self.url),
+                )?;
+            }
+            Kind::Fossil => {
+                fs::create_dir_all(dest_dir)?;
+                let args = [OsStr::new("open"), self.path.as_os_str(), OsStr::new(&self.id)];
+                run(self.kind, Some(dest_dir), args).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Possible errors returned by functions in this module.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The executable of the version control system isn't installed on the host.
+    #[error("`{}` not found, install {vcs} on the host to fetch {vcs} upstreams", .0.command(), vcs = .0)]
+    MissingTool(Kind),
+    /// The executable of the version control system failed.
+    #[error("`{command}` failed: {stderr}")]
+    Command { command: String, stderr: String },
+    /// A generic I/O error occurred.
+    #[error("{0}")]
+    Io(#[from] io::Error),
+}
+
+/// Runs the command of `kind` with `args` in `current_dir`, returning its output.
+async fn run<'a>(
+    kind: Kind,
+    current_dir: Option<&Path>,
+    args: impl IntoIterator<Item = &'a OsStr>,
+) -> Result<String, Error> {
+    let args = args.into_iter().collect::<Vec<_>>();
+
+    let mut command = Command::new(kind.command());
+    command
+        .args(&args)
+        // Output isn't affected by the configuration of the user
+        .env("HGPLAIN", "1")
+        // Cloning a Fossil repository needs a user to own it
+        .env("FOSSIL_USER", "boulder")
+        .stdin(Stdio::null());
+    if let Some(dir) = current_dir {
+        command.current_dir(dir);
+    }
+
+    let output = command.output().await.map_err(|error| match error.kind() {
+        io::ErrorKind::NotFound => Error::MissingTool(kind),
+        _ => Error::Io(error),
+    })?;
+    if !output.status.success() {
+        return Err(Error::Command {
+            command: format!("{} {}", kind.command(), args[0].to_string_lossy()),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        });
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The hash of the check-in of `fossil info` output, `uuid` before Fossil 2.10
+fn fossil_hash(info: &str) -> Option<String> {
+    info.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        matches!(key, "hash" | "uuid")
+            .then(|| value.split_whitespace().next())
+            .flatten()
+            .map(str::to_owned)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::process;
+
+    use moss::runtime;
+
+    use super::*;
+
+    /// Whether `hg` is installed, or the tests using it are skipped
+    fn has_hg() -> bool {
+        process::Command::new("hg").arg("--version").output().is_ok()
+    }
+
+    /// Creates a Mercurial repository in `dir` with a changeset tagged
+    /// for each version, returning the id of that of the last one
+    fn setup_test_repo(dir: &Path, versions: &[&str]) -> String {
+        let hg = |args: &[&str]| {
+            let output = process::Command::new("hg")
+                .arg("-R")
+                .arg(dir)
+                .args(["--config", "ui.username=boulder <boulder@example.com>"])
+                .args(args)
+                .env("HGPLAIN", "1")
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+            String::from_utf8(output.stdout).unwrap().trim().to_owned()
+        };
+
+        if !dir.join(".hg").exists() {
+            let status = process::Command::new("hg").arg("init").arg(dir).status().unwrap();
+            assert!(status.success());
+        }
+        for version in versions {
+            fs::write(dir.join("VERSION"), version).unwrap();
+            hg(&["commit", "--addremove", "-m", version]);
+            hg(&["tag", &format!("v{version}")]);
+        }
+
+        hg(&[
+            "log",
+            "-r",
+            &format!("v{}", versions[versions.len() - 1]),
+            "--template",
+            "{node}",
+        ])
+    }
+
+    #[test]
+    fn store_mercurial() {
+        if !has_hg() {
+            return;
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let origin = dir.path().join("origin");
+        let head = setup_test_repo(&origin, &["1.0", "1.1"]);
+        let url = Url::from_directory_path(&origin).unwrap();
+        let storage_dir = dir.path().join("storage");
+
+        let vcs = |revision: Revision| Vcs {
+            kind: Kind::Mercurial,
+            url: url.clone(),
+            revision,
+            original_index: 0,
+        };
+        let store_and_share = |vcs: &Vcs| {
+            let stored = runtime::block_on(vcs.store(&storage_dir)).unwrap();
+            let dest_dir = dir.path().join("share");
+            util::remove_dir_all(&dest_dir).unwrap();
+            runtime::block_on(stored.share(&dest_dir)).unwrap();
+            let version = fs::read_to_string(dest_dir.join("VERSION")).unwrap();
+            (stored, version)
+        };
+
+        let (stored, version) = store_and_share(&vcs(Revision::Tag("v1.1".to_owned())));
+        assert!(!stored.was_cached);
+        assert_eq!(stored.id, head);
+        assert_eq!(version, "1.1");
+        assert!(stored.path.starts_with(storage_dir.join("hg")));
+
+        // Already stored
+        let (stored, version) = store_and_share(&vcs(Revision::Tag("v1.0".to_owned())));
+        assert!(stored.was_cached);
+        assert_eq!(version, "1.0");
+
+        // Pulled once committed upstream
+        let head = setup_test_repo(&origin, &["1.2"]);
+        let vcs = vcs(Revision::Rev(head[..12].to_owned()));
+        assert_eq!(runtime::block_on(vcs.stored(&storage_dir)).unwrap(), None);
+        let (stored, version) = store_and_share(&vcs);
+        assert!(!stored.was_cached);
+        assert_eq!(stored.id, head);
+        assert_eq!(version, "1.2");
+
+        vcs.remove(&storage_dir).unwrap();
+        assert!(!stored.path.exists());
+        vcs.remove(&storage_dir).unwrap();
+    }
+
+    #[test]
+    fn missing_tool() {
+        let dir = tempfile::tempdir().unwrap();
+        let result = runtime::block_on(run(Kind::Fossil, None, [OsStr::new("version")]));
+        if let Err(error) = result {
+            assert!(matches!(error, Error::MissingTool(Kind::Fossil)));
+            assert_eq!(
+                error.to_string(),
+                "`fossil` not found, install fossil on the host to fetch fossil upstreams"
+            );
+        }
+
+        let vcs = Vcs {
+            kind: Kind::Fossil,
+            url: Url::parse("https://sqlite.org/src").unwrap(),
+            revision: Revision::Tag("version-3.45.0".to_owned()),
+            original_index: 0,
+        };
+        assert_eq!(
+            vcs.stored_path(dir.path()),
+            dir.path().join("fossil/sqlite.org_src.fossil")
+        );
+        assert_eq!(runtime::block_on(vcs.stored(dir.path())).unwrap(), None);
+    }
+
+    #[test]
+    fn parse_fossil_info() {
+        let info = "\
+hash:         4c0a3f6e6a2a9c5e8f0d1b2c3d4e5f60718293a4b5c6d7e8f9a0b1c2d3e4f5a6 2024-01-15 14:13:19 UTC
+parent:       0b1c2d3e4f5a60718293a4b5c6d7e8f9a0b1c2d3e4f5a6b7c8d9e0f1a2b3c4d5 2024-01-15 13:49:01 UTC
+tags:         release, version-3.45.0
+comment:      Version 3.45.0 (user: drh)
+";
+        assert_eq!(
+            fossil_hash(info).as_deref(),
+            Some("4c0a3f6e6a2a9c5e8f0d1b2c3d4e5f60718293a4b5c6d7e8f9a0b1c2d3e4f5a6")
+        );
+        assert_eq!(
+            fossil_hash("uuid:         0123456789abcdef0123456789abcdef01234567 2019-01-01 00:00:00 UTC").as_deref(),
+            Some("0123456789abcdef0123456789abcdef01234567")
+        );
+        assert_eq!(fossil_hash("comment:      no hash"), None);
+    }
+}
@@ -0,0 +1,676 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Verification of the signatures of upstreams
+//!
+//! The detached signature of an archive is verified with `gpgv` before its
+//! hash is checked, while the tag or commit of a Git repository is verified
+//! with `git` once checked out. Both are behind a trait, so what's verified
+//! doesn't depend on the tools of the host.
+
+use std::{
+    ffi::OsStr,
+    io,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use stone_recipe::upstream::{VerifyGit, VerifyPlain};
+use thiserror::Error;
+use url::Url;
+
+/// Verification of the detached signature of an archive
+#[derive(Debug, Clone)]
+pub struct Detached {
+    /// URL of the signature
+    pub signature: Url,
+    /// OpenPGP public key of the signer
+    pub key: PathBuf,
+}
+
+impl Detached {
+    /// As configured in a recipe within `recipe_dir`
+    pub fn from_recipe(verify: VerifyPlain, recipe_dir: &Path) -> Self {
+        Self {
+            signature: verify.sig,
+            key: recipe_dir.join(verify.pgp_key),
+        }
+    }
+}
+
+/// Verification of the signature of a Git repository
+#[derive(Debug, Clone)]
+pub struct Signed {
+    /// Verify a tag of the commit rather than the commit itself
+    pub signed_tag: bool,
+    pub trust: Trust,
+}
+
+impl Signed {
+    /// As configured in a recipe within `recipe_dir`
+    pub fn from_recipe(verify: VerifyGit, recipe_dir: &Path) -> Result<Self, Error> {
+        let trust = match (verify.allowed_signers, verify.keyring) {
+            (Some(allowed_signers), None) => Trust::AllowedSigners(recipe_dir.join(allowed_signers)),
+            (None, Some(keyring)) => Trust::Keyring(recipe_dir.join(keyring)),
+            _ => return Err(Error::Trust),
+        };
+
+        Ok(Self {
+            signed_tag: verify.signed_tag,
+            trust,
+        })
+    }
+}
+
+/// Whose signatures of a Git repository are trusted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trust {
+    /// SSH keys of an allowed signers file, see `ssh-keygen(1)`
+    AllowedSigners(PathBuf),
+    /// OpenPGP keys of a keyring
+    Keyring(PathBuf),
+}
+
+/// Who made a valid signature
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Signer {
+    /// Fingerprint of the key
+    pub key: String,
+    /// User ID or principal of the key, if known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+}
+
+/// What was signed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, strum::Display)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum Method {
+    /// The detached signature of an archive
+    Signature,
+    /// A tag of a Git repository
+    Tag,
+    /// A commit of a Git repository
+    Commit,
+}
+
+/// A signature of an upstream that was verified
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Verification {
+    /// Name of the upstream
+    pub upstream: String,
+    pub method: Method,
+    /// URL of the signature, the tag or hash of the commit
+    pub object: String,
+    pub signer: Signer,
+}
+
+/// Verifies detached OpenPGP signatures
+pub trait DetachedVerifier {
+    /// Verifies `signature` of `data` was made by `key`
+    fn verify(&self, data: &Path, signature: &Path, key: &Path) -> Result<Signer, Error>;
+}
+
+/// Verifies the signatures of Git repositories
+pub trait GitVerifier {
+    /// Verifies `tag` of the repository at `repo` was signed by a signer `trust`ed
+    fn verify_tag(&self, repo: &Path, tag: &str, trust: &Trust) -> Result<Signer, Error>;
+
+    /// Verifies `commit` of the repository at `repo` was signed by a signer `trust`ed
+    fn verify_commit(&self, repo: &Path, commit: &str, trust: &Trust) -> Result<Signer, Error>;
+
+    /// Tags of the repository at `repo` pointing at `commit`
+    fn tags(&self, repo: &Path, commit: &str) -> Result<Vec<String>, Error>;
+}
+
+/// Verifies the detached `signature` of the archive of `upstream` at `data`
+pub fn detached(
+    verifier: &impl DetachedVerifier,
+    upstream: &str,
+    verify: &Detached,
+    data: &Path,
+    signature: &Path,
+) -> Result<Verification, Error> {
+    let signer = verifier.verify(data, signature, &verify.key)?;
+
+    Ok(Verification {
+        upstream: upstream.to_owned(),
+        method: Method::Signature,
+        object: verify.signature.to_string(),
+        signer,
+    })
+}
+
+/// Verifies the Git repository of `upstream` at `repo`, checked out at `commit`
+/// from `original_ref`
+///
+/// With [`Signed::signed_tag`], `original_ref` must be a signed tag or, once
+/// pinned to a hash, a signed tag must point at `commit`.
+pub fn git(
+    verifier: &impl GitVerifier,
+    upstream: &str,
+    verify: &Signed,
+    repo: &Path,
+    original_ref: &str,
+    commit: &str,
+) -> Result<Verification, Error> {
+    let verification = |method, object: &str, signer| Verification {
+        upstream: upstream.to_owned(),
+        method,
+        object: object.to_owned(),
+        signer,
+    };
+
+    if !verify.signed_tag {
+        let signer = verifier.verify_commit(repo, commit, &verify.trust)?;
+        return Ok(verification(Method::Commit, commit, signer));
+    }
+
+    let tags = verifier.tags(repo, commit)?;
+    let candidates = if tags.iter().any(|tag| tag == original_ref) {
+        vec![original_ref.to_owned()]
+    } else {
+        tags
+    };
+
+    let mut error = Error::Unsigned(commit.to_owned());
+    for tag in candidates {
+        match verifier.verify_tag(repo, &tag, &verify.trust) {
+            Ok(signer) => return Ok(verification(Method::Tag, &tag, signer)),
+            Err(err) => error = err,
+        }
+    }
+
+    Err(error)
+}
+
+/// Verifies detached signatures with `gpgv`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Gpgv;
+
+impl DetachedVerifier for Gpgv {
+    fn verify(&self, data: &Path, signature: &Path, key: &Path) -> Result<Signer, Error> {
+        let dir = tempfile::tempdir()?;
+        let keyring = keyring(key, dir.path())?;
+
+        let output = run(
+            "gpgv",
+            Command::new("gpgv")
+                .args(["--status-fd", "1", "--keyring"])
+                .arg(&keyring)
+                .arg(signature)
+                .arg(data),
+        )?;
+
+        let signer = gpg_signer(&String::from_utf8_lossy(&output.stdout));
+
+        signer
+            .filter(|_| output.status.success())
+            .ok_or_else(|| Error::Invalid {
+                object: signature.display().to_string(),
+                reason: stderr(&output),
+            })
+    }
+}
+
+/// Verifies signatures of Git repositories with `git`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GitCli;
+
+impl GitCli {
+    fn verify(&self, repo: &Path, subcommand: &str, object: &str, trust: &Trust) -> Result<Signer, Error> {
+        // Keys are only trusted for this verification
+        let home = tempfile::tempdir()?;
+
+        let mut command = Command::new("git");
+        command.arg("-C").arg(repo);
+        match trust {
+            Trust::AllowedSigners(path) => {
+                command.arg("-c").arg(config("gpg.ssh.allowedSignersFile", path));
+            }
+            Trust::Keyring(path) => {
+                let keyring = keyring(path, home.path())?;
+                let imported = run(
+                    "gpg",
+                    Command::new("gpg")
+                        .env("GNUPGHOME", home.path())
+                        .args(["--batch", "--quiet", "--import"])
+                        .arg(keyring),
+                )?;
+                if !imported.status.success() {
+                    return Err(Error::Invalid {
+                        object: path.display().to_string(),
+                        reason: stderr(&imported),
+                    });
+                }
+                command.env("GNUPGHOME", home.path());
+            }
+        }
+
+        let output = run("git", command.args([subcommand, "--raw", object]))?;
+        // Raw status is reported to stderr
+        let status = String::from_utf8_lossy(&output.stderr);
+        let signer = match trust {
+            Trust::AllowedSigners(_) => ssh_signer(&status),
+            Trust::Keyring(_) => gpg_signer(&status),
+        };
+
+        signer
+            .filter(|_| output.status.success())
+            .ok_or_else(|| Error::Invalid {
+                object: object.to_owned(),
+                reason: stderr(&output),
+            })
+    }
+}
+
+impl GitVerifier for GitCli {
+    fn verify_tag(&self, repo: &Path, tag: &str, trust: &Trust) -> Result<Signer, Error> {
+        self.verify(repo, "verify-tag", tag, trust)
+    }
+
+    fn verify_commit(&self, repo: &Path, commit: &str, trust: &Trust) -> Result<Signer, Error> {
+        self.verify(repo, "verify-commit", commit, trust)
+    }
+
+    fn tags(&self, repo: &Path, commit: &str) -> Result<Vec<String>, Error> {
+        let output = run(
+            "git",
+            Command::new("git")
+                .arg("-C")
+                .arg(repo)
+                .args(["tag", "--points-at", commit]),
+        )?;
+        if !output.status.success() {
+            return Err(Error::Invalid {
+                object: commit.to_owned(),
+                reason: stderr(&output),
+            });
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::to_owned)
+            .collect())
+    }
+}
+
+/// Possible errors returned by functions in this module.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// A tool verifying signatures isn't installed on the host.
+    #[error("`{0}` not found, install it on the host to verify signatures of upstreams")]
+    MissingTool(&'static str),
+    /// The signature is missing, invalid or not made by a signer trusted.
+    #[error("invalid signature of {object}: {reason}")]
+    Invalid { object: String, reason: String },
+    /// With a signed tag required, no tag points at the commit.
+    #[error("no signed tag points at {0}")]
+    Unsigned(String),
+    /// Signers of a Git repository are trusted through neither or both of an
+    /// allowed signers file & a keyring.
+    #[error("verify must have exactly one of allowed_signers or keyring")]
+    Trust,
+    /// A generic I/O error occurred.
+    #[error("{0}")]
+    Io(#[from] io::Error),
+}
+
+/// `key` as a keyring `gpgv` can read, which doesn't read ASCII armor,
+/// dearmored into `dir` if needed
+fn keyring(key: &Path, dir: &Path) -> Result<PathBuf, Error> {
+    if !fs::read(key)?.starts_with(b"-----BEGIN") {
+        return Ok(key.to_owned());
+    }
+
+    let path = dir.join("keyring.gpg");
+    let output = run(
+        "gpg",
+        Command::new("gpg")
+            .args(["--batch", "--yes", "--output"])
+            .arg(&path)
+            .arg("--dearmor")
+            .arg(key),
+    )?;
+    if !output.status.success() {
+        return Err(Error::Invalid {
+            object: key.display().to_string(),
+            reason: stderr(&output),
+        });
+    }
+
+    Ok(path)
+}
+
+fn run(tool: &'static str, command: &mut Command) -> Result<Output, Error> {
+    command.output().map_err(|error| match error.kind() {
+        io::ErrorKind::NotFound => Error::MissingTool(tool),
+        _ => Error::Io(error),
+    })
+}
+
+fn config(key: &str, path: &Path) -> std::ffi::OsString {
+    let mut config = OsStr::new(key).to_owned();
+    config.push("=");
+    config.push(path);
+    config
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).trim().to_owned()
+}
+
+/// Signer of a good signature, as reported by the `--status-fd` of gpg
+fn gpg_signer(status: &str) -> Option<Signer> {
+    let field = |keyword: &str| {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix("[GNUPG:] ")?.strip_prefix(keyword)?.strip_prefix(' '))
+    };
+
+    let key = field("VALIDSIG")?.split_whitespace().next()?.to_owned();
+    let identity = field("GOODSIG")
+        .and_then(|goodsig| goodsig.split_once(' '))
+        .map(|(_, identity)| identity.to_owned());
+
+    Some(Signer { key, identity })
+}
+
+/// Signer of a good SSH signature, as reported by git, i.e.
+/// `Good "git" signature for <principal> with ED25519 key SHA256:...`
+fn ssh_signer(stderr: &str) -> Option<Signer> {
+    let good = stderr
+        .lines()
+        .find_map(|line| line.strip_prefix("Good \"git\" signature"))?;
+    let (principal, key) = good.split_once(" key ")?;
+    let identity = principal
+        .strip_prefix(" for ")
+        .and_then(|principal| principal.rsplit_once(" with "))
+        .map(|(principal, _)| principal.to_owned());
+
+    Some(Signer {
+        key: key.trim().to_owned(),
+        identity,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../test/signatures")
+            .join(name)
+    }
+
+    fn installed(tool: &str) -> bool {
+        Command::new(tool).arg("--version").output().is_ok()
+    }
+
+    fn signer(key: &str) -> Signer {
+        Signer {
+            key: key.to_owned(),
+            identity: None,
+        }
+    }
+
+    /// Trusts the tags & commits signed, recording those verified
+    #[derive(Default)]
+    struct Stub {
+        tags: Vec<String>,
+        signed: Vec<String>,
+        verified: RefCell<Vec<String>>,
+    }
+
+    impl Stub {
+        fn check(&self, object: &str) -> Result<Signer, Error> {
+            self.verified.borrow_mut().push(object.to_owned());
+            if self.signed.iter().any(|signed| signed == object) {
+                Ok(signer("SHA256:stub"))
+            } else {
+                Err(Error::Invalid {
+                    object: object.to_owned(),
+                    reason: "no signature".to_owned(),
+                })
+            }
+        }
+    }
+
+    impl GitVerifier for Stub {
+        fn verify_tag(&self, _repo: &Path, tag: &str, _trust: &Trust) -> Result<Signer, Error> {
+            self.check(tag)
+        }
+
+        fn verify_commit(&self, _repo: &Path, commit: &str, _trust: &Trust) -> Result<Signer, Error> {
+            self.check(commit)
+        }
+
+        fn tags(&self, _repo: &Path, _commit: &str) -> Result<Vec<String>, Error> {
+            Ok(self.tags.clone())
+        }
+    }
+
+    impl DetachedVerifier for Stub {
+        fn verify(&self, data: &Path, _signature: &Path, _key: &Path) -> Result<Signer, Error> {
+            self.check(&data.display().to_string())
+        }
+    }
+
+    const COMMIT: &str = "1111222233334444555566667777888899990000";
+
+    fn signed(signed_tag: bool) -> Signed {
+        Signed {
+            signed_tag,
+            trust: Trust::AllowedSigners("allowed_signers".into()),
+        }
+    }
+
+    #[test]
+    fn verify_git() {
+        let repo = Path::new("repo");
+        let stub = |tags: &[&str], signed: &[&str]| Stub {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            signed: signed.iter().map(|signed| signed.to_string()).collect(),
+            ..Stub::default()
+        };
+
+        // The commit itself, regardless of its tags
+        let verifier = stub(&["v1.0"], &[COMMIT]);
+        let verification = git(&verifier, "repo.git", &signed(false), repo, "v1.0", COMMIT).unwrap();
+        assert_eq!(verification.method, Method::Commit);
+        assert_eq!(verification.object, COMMIT);
+        assert_eq!(verification.upstream, "repo.git");
+
+        let verifier = stub(&[], &[]);
+        assert!(matches!(
+            git(&verifier, "repo.git", &signed(false), repo, COMMIT, COMMIT),
+            Err(Error::Invalid { object, .. }) if object == COMMIT
+        ));
+
+        // The tag of the ref only
+        let verifier = stub(&["v1.0", "latest"], &["v1.0", "latest"]);
+        let verification = git(&verifier, "repo.git", &signed(true), repo, "v1.0", COMMIT).unwrap();
+        assert_eq!(
+            (verification.method, verification.object.as_str()),
+            (Method::Tag, "v1.0")
+        );
+        assert_eq!(*verifier.verified.borrow(), ["v1.0"]);
+
+        let verifier = stub(&["v1.0"], &[COMMIT]);
+        assert!(git(&verifier, "repo.git", &signed(true), repo, "v1.0", COMMIT).is_err());
+
+        // Once pinned, any signed tag of the commit
+        let verifier = stub(&["latest", "v1.0"], &["v1.0"]);
+        let verification = git(&verifier, "repo.git", &signed(true), repo, COMMIT, COMMIT).unwrap();
+        assert_eq!(verification.object, "v1.0");
+        assert_eq!(*verifier.verified.borrow(), ["latest", "v1.0"]);
+
+        let verifier = stub(&[], &[COMMIT]);
+        assert!(matches!(
+            git(&verifier, "repo.git", &signed(true), repo, COMMIT, COMMIT),
+            Err(Error::Unsigned(commit)) if commit == COMMIT
+        ));
+    }
+
+    #[test]
+    fn verify_detached() {
+        let verify = Detached {
+            signature: "https://example.com/source-1.0.txt.asc".parse().unwrap(),
+            key: fixture("release.asc"),
+        };
+        let stub = Stub {
+            signed: vec!["source-1.0.txt".to_owned()],
+            ..Stub::default()
+        };
+
+        let verification = detached(
+            &stub,
+            "source-1.0.txt",
+            &verify,
+            Path::new("source-1.0.txt"),
+            Path::new("source-1.0.txt.asc"),
+        )
+        .unwrap();
+        assert_eq!(verification.method, Method::Signature);
+        assert_eq!(verification.object, "https://example.com/source-1.0.txt.asc");
+
+        assert!(
+            detached(
+                &stub,
+                "other.txt",
+                &verify,
+                Path::new("other.txt"),
+                Path::new("other.txt.asc")
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn gpgv() {
+        if !installed("gpgv") || !installed("gpg") {
+            eprintln!("gpgv isn't installed, skipping");
+            return;
+        }
+
+        let data = fixture("source-1.0.txt");
+        let signature = fixture("source-1.0.txt.asc");
+
+        let signer = Gpgv.verify(&data, &signature, &fixture("release.asc")).unwrap();
+        assert_eq!(signer.key, "95B27936929186B1F794FDC17E64185874C6FCC1");
+        assert_eq!(
+            signer.identity.as_deref(),
+            Some("Boulder Release <release@example.com>")
+        );
+
+        // Signed by another key
+        assert!(matches!(
+            Gpgv.verify(&data, &signature, &fixture("other.asc")),
+            Err(Error::Invalid { .. })
+        ));
+
+        // Tampered with
+        let dir = tempfile::tempdir().unwrap();
+        let tampered = dir.path().join("source-1.0.txt");
+        fs::write(&tampered, "source-1.1\n").unwrap();
+        assert!(matches!(
+            Gpgv.verify(&tampered, &signature, &fixture("release.asc")),
+            Err(Error::Invalid { .. })
+        ));
+    }
+
+    #[test]
+    fn git_ssh() {
+        if !installed("ssh-keygen") {
+            eprintln!("ssh-keygen isn't installed, skipping");
+            return;
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let key = dir.path().join("key");
+        let repo = dir.path().join("repo");
+        let run = |command: &mut Command| {
+            let output = command.output().unwrap();
+            assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+            String::from_utf8(output.stdout).unwrap().trim().to_owned()
+        };
+        let git = |args: &[&str]| {
+            run(Command::new("git")
+                .arg("-C")
+                .arg(&repo)
+                .args(["-c", "user.name=boulder", "-c", "user.email=boulder@example.com"])
+                .args(["-c", "gpg.format=ssh"])
+                .arg("-c")
+                .arg(config("user.signingKey", &key))
+                .args(args))
+        };
+
+        run(Command::new("ssh-keygen")
+            .args(["-q", "-t", "ed25519", "-N", "", "-C", "boulder", "-f"])
+            .arg(&key));
+        let public_key = fs::read_to_string(key.with_extension("pub")).unwrap();
+        let allowed_signers = dir.path().join("allowed_signers");
+        fs::write(&allowed_signers, format!("boulder@example.com {public_key}")).unwrap();
+
+        fs::create_dir_all(&repo).unwrap();
+        git(&["init", "--initial-branch=trunk"]);
+        fs::write(repo.join("VERSION"), "1.0").unwrap();
+        git(&["add", "VERSION"]);
+        git(&["commit", "-S", "-m", "1.0"]);
+        git(&["tag", "-s", "-m", "1.0", "v1.0"]);
+        git(&["tag", "unsigned"]);
+        let commit = git(&["rev-parse", "HEAD"]);
+
+        let trust = Trust::AllowedSigners(allowed_signers);
+        let signer = GitCli.verify_tag(&repo, "v1.0", &trust).unwrap();
+        assert!(signer.key.starts_with("SHA256:"));
+        assert_eq!(signer.identity.as_deref(), Some("boulder@example.com"));
+        assert_eq!(GitCli.verify_commit(&repo, &commit, &trust).unwrap(), signer);
+
+        assert!(GitCli.verify_tag(&repo, "unsigned", &trust).is_err());
+        assert_eq!(GitCli.tags(&repo, &commit).unwrap(), ["unsigned", "v1.0"]);
+
+        // Not an allowed signer
+        let others = dir.path().join("others");
+        fs::write(&others, "").unwrap();
+        assert!(matches!(
+            GitCli.verify_tag(&repo, "v1.0", &Trust::AllowedSigners(others)),
+            Err(Error::Invalid { object, .. }) if object == "v1.0"
+        ));
+    }
+
+    #[test]
+    fn parse_signers() {
+        let status = "\
+[GNUPG:] NEWSIG
+[GNUPG:] GOODSIG 7E64185874C6FCC1 Boulder Release <release@example.com>
+[GNUPG:] VALIDSIG 95B27936929186B1F794FDC17E64185874C6FCC1 2026-10-16 1792166572 0 4 0 22 8 00 95B27936929186B1F794FDC17E64185874C6FCC1
+";
+        assert_eq!(
+            gpg_signer(status),
+            Some(Signer {
+                key: "95B27936929186B1F794FDC17E64185874C6FCC1".to_owned(),
+                identity: Some("Boulder Release <release@example.com>".to_owned()),
+            })
+        );
+        assert_eq!(gpg_signer("[GNUPG:] BADSIG 7E64185874C6FCC1 Boulder\n"), None);
+
+        assert_eq!(
+            ssh_signer("Good \"git\" signature for boulder@example.com with ED25519 key SHA256:GsXeXABi\n"),
+            Some(Signer {
+                key: "SHA256:GsXeXABi".to_owned(),
+                identity: Some("boulder@example.com".to_owned()),
+            })
+        );
+        assert_eq!(
+            ssh_signer("Good \"git\" signature with ED25519 key SHA256:GsXeXABi\nNo principal matched.\n"),
+            Some(signer("SHA256:GsXeXABi"))
+        );
+        assert_eq!(ssh_signer("error: no signature found\n"), None);
+    }
+}
@@ -0,0 +1,68 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! `boulder chroot -- COMMAND` against a build root borrowing the `/usr` of the host
+
+use std::{
+    os::unix::fs::symlink,
+    path::Path,
+    process::{Command, ExitStatus},
+};
+
+use fs_err as fs;
+
+const RECIPE: &str = "\
+name: chroot-test
+version: 1.0
+release: 1
+homepage: https://example.com
+license: MPL-2.0
+summary: Example
+description: Example
+upstreams:
+    - https://example.com/chroot-test-1.0.tar.xz : cbf684b5a37a3a433e0526beb04a7b3419b71e81b3709c3b0d7ed6a1987d3dcb
+build: make
+";
+
+/// Run `command` in the chroot of a recipe, with a build root in `dir`
+fn chroot(dir: &Path, command: &[&str]) -> ExitStatus {
+    let rootfs = dir.join("cache/root/chroot-test-1.0-1");
+    for dir in ["usr", "dev", "proc", "sys", "tmp"] {
+        fs::create_dir_all(rootfs.join(dir)).unwrap();
+    }
+    for dir in ["bin", "lib", "lib64"] {
+        if !rootfs.join(dir).is_symlink() {
+            symlink(format!("usr/{dir}"), rootfs.join(dir)).unwrap();
+        }
+    }
+
+    let recipe = dir.join("recipe/stone.yaml");
+    fs::create_dir_all(recipe.parent().unwrap()).unwrap();
+    fs::write(&recipe, RECIPE).unwrap();
+
+    Command::new(env!("CARGO_BIN_EXE_boulder"))
+        .arg("--cache-dir")
+        .arg(dir.join("cache"))
+        .arg("--config-dir")
+        .arg(dir.join("config"))
+        .arg("--data-dir")
+        .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("data"))
+        .arg("--moss-root")
+        .arg(dir.join("moss"))
+        .arg("chroot")
+        .arg(&recipe)
+        .args(["--bind", "/usr:/usr:ro", "--"])
+        .args(command)
+        .status()
+        .unwrap()
+}
+
+#[test]
+#[ignore = "creating the container needs user namespaces"]
+fn exit_status() {
+    let dir = tempfile::tempdir().unwrap();
+
+    assert_eq!(chroot(dir.path(), &["/bin/true"]).code(), Some(0));
+    assert_eq!(chroot(dir.path(), &["/bin/false"]).code(), Some(1));
+    assert_eq!(chroot(dir.path(), &["/bin/sh", "-c", "exit 42"]).code(), Some(42));
+}
@@ -6,7 +6,7 @@ use std::os::fd::AsRawFd;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::ptr::addr_of_mut;
-use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
 
 use fs_err::{self as fs, PathExt as _};
 use nc::syscalls::syscall5;
@@ -37,6 +37,8 @@ pub struct Container {
     networking: bool,
     hostname: Option<String>,
     ignore_host_sigint: bool,
+    forward_host_signals: bool,
+    env: Vec<(String, String)>,
 }
 
 impl Container {
@@ -49,6 +51,8 @@ impl Container {
             networking: false,
             hostname: None,
             ignore_host_sigint: false,
+            forward_host_signals: false,
+            env: vec![],
         }
     }
 
@@ -120,6 +124,22 @@ impl Container {
         }
     }
 
+    /// Forward `SIGINT` & `SIGTERM` from the parent process to the container process
+    /// while it runs, killing it on the second one, see [`forward_signals`]
+    pub fn forward_host_signals(self, forward: bool) -> Self {
+        Self {
+            forward_host_signals: forward,
+            ..self
+        }
+    }
+
+    /// Set an environment variable of the container process, inherited by
+    /// the processes it spawns
+    pub fn env(mut self, key: impl ToString, value: impl ToString) -> Self {
+        self.env.push((key.to_string(), value.to_string()));
+        self
+    }
+
     /// Run `f` as a container process payload
     pub fn run<E>(self, mut f: impl FnMut() -> Result<(), E>) -> Result<(), Error>
     where
@@ -175,19 +195,28 @@ impl Container {
         // Write no longer needed
         close(sync.1).context(NixSnafu)?;
 
-        if self.ignore_host_sigint {
+        if self.forward_host_signals {
+            forward_signals(pid).context(NixSnafu)?;
+        } else if self.ignore_host_sigint {
             ignore_sigint().context(NixSnafu)?;
         }
 
-        let status = waitpid(pid, None).context(NixSnafu)?;
+        let status = wait(pid);
 
-        if self.ignore_host_sigint {
-            default_sigint().context(NixSnafu)?;
-        }
+        // Restored however waiting ended, so the handlers don't outlive the container
+        let restored = if self.forward_host_signals {
+            default_signals()
+        } else if self.ignore_host_sigint {
+            default_sigint()
+        } else {
+            Ok(())
+        };
+        let status = status.context(NixSnafu)?;
+        restored.context(NixSnafu)?;
 
         match status {
             WaitStatus::Exited(_, 0) => Ok(()),
-            WaitStatus::Exited(..) => {
+            WaitStatus::Exited(_, code) => {
                 let mut error = String::new();
                 let mut buffer = [0u8; 1024];
 
@@ -201,7 +230,7 @@ impl Container {
                     error.push_str(String::from_utf8_lossy(&buffer[..len]).as_ref());
                 }
 
-                Err(Error::Failure { message: error })
+                Err(Error::Failure { code, message: error })
             }
             WaitStatus::Signaled(_, signal, _) => Err(Error::Signaled { signal }),
             WaitStatus::Stopped(..)
@@ -213,6 +242,17 @@ impl Container {
     }
 }
 
+/// Wait for the [`Pid`] process to exit, retrying when a handled signal
+/// interrupts the wait
+fn wait(pid: Pid) -> Result<WaitStatus, nix::Error> {
+    loop {
+        match waitpid(pid, None) {
+            Err(Errno::EINTR) => continue,
+            result => return result,
+        }
+    }
+}
+
 /// Reenter the container
 fn enter<E>(container: &Container, sync: (i32, i32), mut f: impl FnMut() -> Result<(), E>) -> Result<(), ContainerError>
 where
@@ -252,6 +292,11 @@ fn setup(container: &Container) -> Result<(), ContainerError> {
         set_current_dir(dir)?;
     }
 
+    for (key, value) in &container.env {
+        // SAFETY: The cloned process only runs this thread
+        unsafe { std::env::set_var(key, value) };
+    }
+
     Ok(())
 }
 
@@ -417,6 +462,53 @@ fn default_sigint() -> Result<(), nix::Error> {
     Ok(())
 }
 
+fn default_signals() -> Result<(), nix::Error> {
+    let action = SigAction::new(SigHandler::SigDfl, SaFlags::empty(), SigSet::empty());
+    for signal in [Signal::SIGINT, Signal::SIGTERM] {
+        unsafe { sigaction(signal, &action)? };
+    }
+    Ok(())
+}
+
+/// The first signal received since [`forward_signals`] was last called, zero if none
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+/// Forwards `SIGINT` & `SIGTERM` from the current process to the [`Pid`] process as
+/// `SIGINT`, killing it once another is received
+///
+/// The init process of a PID namespace only receives the signals it handles, as
+/// processes forwarding `SIGINT` with [`forward_sigint`] do.
+pub fn forward_signals(pid: Pid) -> Result<(), nix::Error> {
+    static PID: AtomicI32 = AtomicI32::new(0);
+
+    PID.store(pid.as_raw(), Ordering::Relaxed);
+    RECEIVED.store(0, Ordering::Relaxed);
+
+    extern "C" fn on_signal(signal: i32) {
+        let pid = Pid::from_raw(PID.load(Ordering::Relaxed));
+        if RECEIVED
+            .compare_exchange(0, signal, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            let _ = kill(pid, Signal::SIGINT);
+        } else {
+            let _ = kill(pid, Signal::SIGKILL);
+        }
+    }
+
+    let action = SigAction::new(SigHandler::Handler(on_signal), SaFlags::empty(), SigSet::empty());
+    for signal in [Signal::SIGINT, Signal::SIGTERM] {
+        unsafe { sigaction(signal, &action)? };
+    }
+
+    Ok(())
+}
+
+/// The first signal forwarded since [`forward_signals`] was last called, if any
+pub fn received_signal() -> Option<Signal> {
+    Signal::try_from(RECEIVED.load(Ordering::Relaxed)).ok()
+}
+
 pub fn set_term_fg(pgid: Pid) -> Result<(), nix::Error> {
     // Ignore SIGTTOU and get previous handler
     let prev_handler = unsafe {
@@ -440,15 +532,22 @@ pub fn set_term_fg(pgid: Pid) -> Result<(), nix::Error> {
     Ok(())
 }
 
-/// Forwards `SIGINT` from the current process to the [`Pid`] process
+/// Forwards `SIGINT` from the current process to the [`Pid`] process, killing it
+/// once another is received
 pub fn forward_sigint(pid: Pid) -> Result<(), nix::Error> {
     static PID: AtomicI32 = AtomicI32::new(0);
+    static FORWARDED: AtomicBool = AtomicBool::new(false);
 
     PID.store(pid.as_raw(), Ordering::Relaxed);
+    FORWARDED.store(false, Ordering::Relaxed);
 
     extern "C" fn on_int(_: i32) {
         let pid = Pid::from_raw(PID.load(Ordering::Relaxed));
-        let _ = kill(pid, Signal::SIGINT);
+        if FORWARDED.swap(true, Ordering::Relaxed) {
+            let _ = kill(pid, Signal::SIGKILL);
+        } else {
+            let _ = kill(pid, Signal::SIGINT);
+        }
     }
 
     let action = SigAction::new(SigHandler::Handler(on_int), SaFlags::empty(), SigSet::empty());
@@ -480,8 +579,9 @@ struct Bind {
 
 #[derive(Debug, Snafu)]
 pub enum Error {
+    /// The payload failed with `message`, or exited the process with `code` itself
     #[snafu(display("exited with failure: {message}"))]
-    Failure { message: String },
+    Failure { code: i32, message: String },
     #[snafu(display("stopped by signal: {signal}"))]
     Signaled { signal: Signal },
     #[snafu(display("unknown exit reason"))]
@@ -525,3 +625,101 @@ enum ContainerError {
 enum Message {
     Continue = 1,
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use nix::sys::pthread::{pthread_kill, pthread_self};
+
+    use super::*;
+
+    /// Signals the calling thread, waiting on the container, like a Ctrl-C once the payload
+    /// created `started`, followed by any others after a while
+    fn interrupt(started: PathBuf, signals: &'static [Signal]) -> thread::JoinHandle<()> {
+        let waiting = pthread_self();
+        thread::spawn(move || {
+            let start = Instant::now();
+            while !started.exists() {
+                assert!(start.elapsed() < Duration::from_secs(10), "payload didn't start");
+                thread::sleep(Duration::from_millis(10));
+            }
+            for signal in signals {
+                pthread_kill(waiting, *signal).unwrap();
+                thread::sleep(Duration::from_millis(200));
+            }
+        })
+    }
+
+    // Both cases run in one test, as the handlers are process wide
+    #[test]
+    fn interrupt_run() {
+        let root = tempfile::tempdir().unwrap();
+        let shared = tempfile::tempdir().unwrap();
+
+        let interrupted = interrupt(shared.path().join("started"), &[Signal::SIGINT]);
+        let result = Container::new(root.path())
+            .bind_rw(shared.path(), "/shared")
+            .forward_host_signals(true)
+            .run(|| -> io::Result<()> {
+                static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+                extern "C" fn on_int(_: i32) {
+                    INTERRUPTED.store(true, Ordering::Relaxed);
+                }
+
+                let action = SigAction::new(SigHandler::Handler(on_int), SaFlags::empty(), SigSet::empty());
+                unsafe { sigaction(Signal::SIGINT, &action) }?;
+                fs::write("/shared/started", "")?;
+
+                while !INTERRUPTED.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_millis(10));
+                }
+                // Still running for a while once interrupted
+                thread::sleep(Duration::from_millis(200));
+                fs::write("/shared/exited", "")
+            });
+
+        interrupted.join().unwrap();
+        result.unwrap();
+
+        // Returned once the payload exited, so cleaning up after it is safe
+        assert!(shared.path().join("exited").exists());
+        assert_eq!(received_signal(), Some(Signal::SIGINT));
+
+        // A payload ignoring the forwarded signal is killed by the next one
+        fs::remove_file(shared.path().join("started")).unwrap();
+        let interrupted = interrupt(shared.path().join("started"), &[Signal::SIGINT, Signal::SIGTERM]);
+        let result = Container::new(root.path())
+            .bind_rw(shared.path(), "/shared")
+            .forward_host_signals(true)
+            .run(|| -> io::Result<()> {
+                let action = SigAction::new(SigHandler::SigIgn, SaFlags::empty(), SigSet::empty());
+                unsafe { sigaction(Signal::SIGINT, &action) }?;
+                fs::write("/shared/started", "")?;
+
+                loop {
+                    thread::sleep(Duration::from_millis(10));
+                }
+            });
+
+        interrupted.join().unwrap();
+        assert!(matches!(
+            result,
+            Err(Error::Signaled {
+                signal: Signal::SIGKILL
+            })
+        ));
+        assert_eq!(received_signal(), Some(Signal::SIGINT));
+
+        // With the default handlers restored
+        let action = SigAction::new(SigHandler::SigDfl, SaFlags::empty(), SigSet::empty());
+        for signal in [Signal::SIGINT, Signal::SIGTERM] {
+            let previous = unsafe { sigaction(signal, &action) }.unwrap();
+            assert!(matches!(previous.handler(), SigHandler::SigDfl), "{signal}");
+        }
+    }
+}
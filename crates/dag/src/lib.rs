@@ -89,6 +89,11 @@ where
         self.0.node_weights()
     }
 
+    /// Returns the nodes which `node` has an edge to
+    pub fn successors(&self, node: NodeIndex) -> impl Iterator<Item = &'_ N> {
+        self.0.neighbors_directed(node, Direction::Outgoing).map(|i| &self.0[i])
+    }
+
     /// Perform a depth-first search, given the start index
     pub fn dfs(&self, start: NodeIndex) -> impl Iterator<Item = &'_ N> {
         let dfs = Dfs::new(&self.0, start);
@@ -238,4 +243,24 @@ mod tests {
         let batches = graph.batched_topo();
         assert_eq!(batches.len(), 0);
     }
+
+    #[test]
+    fn test_successors_without_cycles() {
+        let mut graph: Dag<char> = Dag::new();
+
+        let a = graph.add_node_or_get_index(&'A');
+        let b = graph.add_node_or_get_index(&'B');
+        let c = graph.add_node_or_get_index(&'C');
+
+        assert!(graph.add_edge(a, b));
+        assert!(graph.add_edge(a, c));
+        assert!(graph.add_edge(b, c));
+        // Would close the cycle A -> B -> C -> A
+        assert!(!graph.add_edge(c, a));
+
+        let mut successors = graph.successors(a).copied().collect::<Vec<_>>();
+        successors.sort();
+        assert_eq!(successors, ['B', 'C']);
+        assert_eq!(graph.successors(c).count(), 0);
+    }
 }
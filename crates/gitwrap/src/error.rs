@@ -37,6 +37,44 @@ impl Error {
             None
         }
     }
+
+    /// Returns why the remote repository couldn't be accessed,
+    /// if `git` reported it. Otherwise, it returns [None].
+    pub fn remote(&self) -> Option<Remote> {
+        let InnerError::Run {
+            stderr: Some(stderr), ..
+        } = &self.0
+        else {
+            return None;
+        };
+
+        if Remote::AUTHENTICATION.iter().any(|msg| stderr.contains(msg)) {
+            Some(Remote::Authentication)
+        } else if Remote::NOT_FOUND.iter().any(|msg| stderr.contains(msg)) {
+            Some(Remote::NotFound)
+        } else {
+            None
+        }
+    }
+}
+
+/// Why a remote repository couldn't be accessed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Remote {
+    /// Credentials are missing or were rejected.
+    Authentication,
+    /// There is no repository at the URL.
+    NotFound,
+}
+
+impl Remote {
+    const AUTHENTICATION: &[&str] = &[
+        "Authentication failed",
+        "could not read Username",
+        "could not read Password",
+        "Permission denied (publickey",
+    ];
+    const NOT_FOUND: &[&str] = &["not found", "does not exist", "does not appear to be a git repository"];
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -15,14 +15,33 @@
 use std::ffi::OsStr;
 use std::path::{self, Path, PathBuf};
 use std::process::Stdio;
+use std::sync::OnceLock;
 
 use tokio::{io, process};
 use url::Url;
 
 pub mod error;
-pub use self::error::Error;
+pub use self::error::{Error, Remote};
 use error::{Constraint, InnerError};
 
+/// Variables set in the environment of every `git` process.
+static ENVIRONMENT: OnceLock<Vec<(String, String)>> = OnceLock::new();
+
+/// Sets variables in the environment of every `git` process run,
+/// such as `https_proxy`, on top of that of the current process.
+///
+/// Only the first call takes effect.
+pub fn set_environment(vars: impl IntoIterator<Item = (String, String)>) {
+    let _ = ENVIRONMENT.set(vars.into_iter().collect());
+}
+
+/// A `git` command in the configured environment.
+fn git() -> process::Command {
+    let mut command = process::Command::new("git");
+    command.envs(ENVIRONMENT.get().into_iter().flatten().map(|(key, value)| (key, value)));
+    command
+}
+
 /// An uninitialized repository, useful for unit tests.
 pub fn null_repository() -> Repository {
     Repository { path: PathBuf::new() }
@@ -69,10 +88,36 @@ impl Repository {
     }
 
     /// Clones a local or remote Git repository as bare into `path`.
-    /// The clone is performed with Git's `--mirror` flag.
+    /// The clone is performed with Git's `--mirror` flag, limited
+    /// to the history & objects allowed by `partial`.
     /// A callback is fired repeatedly to track the cloning
     /// process in real time.
-    pub async fn clone_mirror_progress<F>(path: &Path, url: &Url, callback: F) -> Result<Self, Error>
+    pub async fn clone_mirror_progress<F>(path: &Path, url: &Url, partial: &Partial, callback: F) -> Result<Self, Error>
+    where
+        F: Fn(FetchProgress),
+    {
+        let path = path::absolute(path).map_err(InnerError::from)?;
+        let depth = partial.depth.map(|depth| format!("--depth={depth}"));
+        let filter = partial.filter.as_ref().map(|filter| format!("--filter={filter}"));
+
+        let mut args = vec![
+            OsStr::new("clone"),
+            OsStr::new("--mirror"),
+            OsStr::new("--recurse-submodules"),
+            OsStr::new("--progress"),
+        ];
+        args.extend(depth.iter().chain(&filter).map(OsStr::new));
+        args.extend([OsStr::new(url.as_str()), path.as_os_str()]);
+
+        run_git_progress(&args, callback).await?;
+        Ok(Self { path })
+    }
+
+    /// Clones only the latest commit of the default branch of a local or
+    /// remote Git repository into `path`, along with its submodules.
+    /// A callback is fired repeatedly to track the cloning
+    /// process in real time.
+    pub async fn clone_shallow_progress<F>(path: &Path, url: &Url, callback: F) -> Result<Self, Error>
     where
         F: Fn(FetchProgress),
     {
@@ -80,8 +125,9 @@ impl Repository {
         run_git_progress(
             &[
                 OsStr::new("clone"),
-                OsStr::new("--mirror"),
+                OsStr::new("--depth=1"),
                 OsStr::new("--recurse-submodules"),
+                OsStr::new("--shallow-submodules"),
                 OsStr::new("--progress"),
                 OsStr::new(&url.as_str()),
                 path.as_os_str(),
@@ -129,7 +175,7 @@ impl Repository {
             OsStr::new(remote),
         ])
         .await?;
-        Ok(str::from_utf8(&output.stdout).unwrap_or("").to_owned())
+        Ok(str::from_utf8(output.stdout.trim_ascii_end()).unwrap_or("").to_owned())
     }
 
     /// Sets the remote URL for the provided `remote` to `url`
@@ -180,21 +226,131 @@ impl Repository {
         Ok(())
     }
 
+    /// Whether this repository only has part of the history of its remote,
+    /// i.e. it was cloned with a depth.
+    pub async fn is_shallow(&self) -> Result<bool, Error> {
+        let output = run_git(&[
+            OsStr::new("-C"),
+            self.path.as_os_str(),
+            OsStr::new("rev-parse"),
+            OsStr::new("--is-shallow-repository"),
+        ])
+        .await?;
+        Ok(output.stdout.starts_with(b"true"))
+    }
+
+    /// Returns the filter objects were omitted by, if this repository
+    /// is a partial clone of its `origin`.
+    pub async fn partial_clone_filter(&self) -> Result<Option<String>, Error> {
+        let output = git()
+            .arg("-C")
+            .arg(&self.path)
+            .args(["config", "--get", "remote.origin.partialclonefilter"])
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .map_err(InnerError::from)?;
+
+        // Exits with 1 if unset
+        match output.status.code() {
+            Some(0) => Ok(Some(
+                str::from_utf8(output.stdout.trim_ascii_end()).unwrap_or("").to_owned(),
+            )),
+            Some(1) => Ok(None),
+            code => Err(InnerError::Run {
+                code,
+                stderr: Some(String::from_utf8_lossy(&output.stderr).into_owned()),
+            })?,
+        }
+    }
+
+    /// Fetches `by` more commits of history into a shallow repository.
+    pub async fn deepen(&self, by: u32) -> Result<(), Error> {
+        run_git(&[
+            OsStr::new("-C"),
+            self.path.as_os_str(),
+            OsStr::new("fetch"),
+            OsStr::new("--tags"),
+            OsStr::new(&format!("--deepen={by}")),
+        ])
+        .await?;
+        Ok(())
+    }
+
+    /// Fetches the remaining history of a shallow repository.
+    pub async fn unshallow(&self) -> Result<(), Error> {
+        run_git(&[
+            OsStr::new("-C"),
+            self.path.as_os_str(),
+            OsStr::new("fetch"),
+            OsStr::new("--tags"),
+            OsStr::new("--unshallow"),
+        ])
+        .await?;
+        Ok(())
+    }
+
+    /// Initializes and checks out the submodules of the current commit.
+    pub async fn update_submodules(&self) -> Result<(), Error> {
+        run_git(&[
+            OsStr::new("-C"),
+            self.path.as_os_str(),
+            OsStr::new("submodule"),
+            OsStr::new("update"),
+            OsStr::new("--init"),
+            OsStr::new("--recursive"),
+        ])
+        .await?;
+        Ok(())
+    }
+
     /// Clone the current [`Repository`] to the provided `path` and return
     /// the cloned to [`Repository`].
+    ///
+    /// If the current repository is a partial clone, the clone is left
+    /// without a checkout and becomes a partial clone of the same `origin`:
+    /// objects missing from both are fetched from it once checked out.
     pub async fn clone_to(&self, path: &Path) -> Result<Self, Error> {
         let path = path::absolute(path).map_err(InnerError::from)?;
 
-        // Clone it to `path`
+        let Some(filter) = self.partial_clone_filter().await? else {
+            // Clone it to `path`
+            run_git(&[
+                OsStr::new("clone"),
+                OsStr::new("--recurse-submodules"),
+                self.path.as_os_str(),
+                path.as_os_str(),
+            ])
+            .await?;
+
+            return Ok(Self { path: path.to_owned() });
+        };
+
         run_git(&[
             OsStr::new("clone"),
-            OsStr::new("--recurse-submodules"),
+            OsStr::new("--no-checkout"),
             self.path.as_os_str(),
             path.as_os_str(),
         ])
         .await?;
 
-        Ok(Self { path: path.to_owned() })
+        let cloned = Self { path: path.to_owned() };
+        cloned.set_config("remote.origin.promisor", "true").await?;
+        cloned.set_config("remote.origin.partialclonefilter", &filter).await?;
+
+        Ok(cloned)
+    }
+
+    async fn set_config(&self, key: &str, value: &str) -> Result<(), Error> {
+        run_git(&[
+            OsStr::new("-C"),
+            self.path.as_os_str(),
+            OsStr::new("config"),
+            OsStr::new(key),
+            OsStr::new(value),
+        ])
+        .await?;
+        Ok(())
     }
 
     pub fn path(&self) -> &Path {
@@ -202,6 +358,16 @@ impl Repository {
     }
 }
 
+/// Limits on what is cloned of a repository, to save time & space
+/// with large repositories.
+#[derive(Debug, Clone, Default)]
+pub struct Partial {
+    /// Number of commits of history cloned, if shallow.
+    pub depth: Option<u32>,
+    /// Filter of the objects omitted, e.g. `blob:none`, fetched on demand.
+    pub filter: Option<String>,
+}
+
 /// The argument of callbacks when they are invoked
 /// for reporting a Git operation's progress.
 pub struct FetchProgress {
@@ -211,15 +377,53 @@ pub struct FetchProgress {
     pub speed: String,
 }
 
+/// A tag of a Git repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    pub name: String,
+    /// The commit tagged, peeled from annotated tags.
+    pub commit: String,
+}
+
+/// Lists the tags of the local or remote Git repository at `url`,
+/// without cloning it.
+pub async fn ls_remote_tags(url: &Url) -> Result<Vec<Tag>, Error> {
+    let output = run_git(["ls-remote", "--tags", url.as_str()]).await?;
+
+    let mut tags: Vec<Tag> = vec![];
+    for (oid, reference) in String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+    {
+        let Some(name) = reference.strip_prefix("refs/tags/") else {
+            continue;
+        };
+        // Annotated tags are followed by the commit they point to
+        match name.strip_suffix("^{}") {
+            Some(name) => {
+                if let Some(tag) = tags.last_mut().filter(|tag| tag.name == name) {
+                    oid.clone_into(&mut tag.commit);
+                }
+            }
+            None => tags.push(Tag {
+                name: name.to_owned(),
+                commit: oid.to_owned(),
+            }),
+        }
+    }
+    Ok(tags)
+}
+
 /// Runs git and waits for it to terminate.
 async fn run_git<I, S>(args: I) -> Result<std::process::Output, Error>
 where
     I: IntoIterator<Item = S>,
     S: AsRef<OsStr>,
 {
-    let output = process::Command::new("git")
+    let output = git()
         .args(args)
         .stdin(Stdio::null())
+        .env("GIT_TERMINAL_PROMPT", "0")
         .output()
         .await
         .map_err(InnerError::from)?;
@@ -246,14 +450,14 @@ where
         prog.parse(callback).await
     };
 
-    let (_, result) = tokio::join!(parser, git.wait());
+    let (diagnostics, result) = tokio::join!(parser, git.wait());
     let result = result.map_err(InnerError::from)?;
     if result.success() {
         Ok(())
     } else {
         Err(InnerError::Run {
             code: result.code(),
-            stderr: None,
+            stderr: diagnostics.ok().filter(|diagnostics| !diagnostics.is_empty()),
         })?
     }
 }
@@ -263,11 +467,13 @@ where
     I: IntoIterator<Item = S>,
     S: AsRef<OsStr>,
 {
-    let mut child = process::Command::new("git")
+    let mut child = git()
         .args(args)
         .stdin(Stdio::null())
         .stdout(Stdio::null())
         .stderr(Stdio::piped())
+        // Fail instead of prompting for credentials, as stderr is captured
+        .env("GIT_TERMINAL_PROMPT", "0")
         .spawn()
         .map_err(InnerError::from)?;
     let stderr = child.stderr.take().unwrap();
@@ -281,6 +487,7 @@ struct ProgressParser<R: io::AsyncRead> {
 impl<R: io::AsyncRead + Unpin> ProgressParser<R> {
     const TERMINATOR: u8 = b'\r';
     const PREFIX: &[u8] = b"Receiving objects:";
+    const DIAGNOSTICS: &[&str] = &["fatal:", "error:", "ERROR:", "remote: Repository not found"];
 
     pub fn new(stderr: R) -> Self {
         Self {
@@ -292,13 +499,23 @@ impl<R: io::AsyncRead + Unpin> ProgressParser<R> {
     // "Receiving objects:  26% (163045/627093), 52.57 MiB | 34.99 MiB/s"
     // And we want the percentage and the speed, which are conveniently
     // the first and the last tokens of the line.
+    //
+    // Errors reported by git are collected and returned, to diagnose
+    // why it failed.
 
-    pub async fn parse(self, callback: impl Fn(FetchProgress)) -> Result<(), Error> {
+    pub async fn parse(self, callback: impl Fn(FetchProgress)) -> Result<String, Error> {
         use tokio::io::AsyncBufReadExt;
 
+        let mut diagnostics = String::new();
         let mut lines = self.reader.split(Self::TERMINATOR);
         while let Some(line) = lines.next_segment().await.map_err(InnerError::from)? {
             if !line.starts_with(Self::PREFIX) {
+                for line in String::from_utf8_lossy(&line).lines() {
+                    if Self::DIAGNOSTICS.iter().any(|prefix| line.starts_with(prefix)) {
+                        diagnostics.push_str(line);
+                        diagnostics.push('\n');
+                    }
+                }
                 continue;
             }
             let line = &str::from_utf8(&line[Self::PREFIX.len()..]).unwrap_or("");
@@ -306,7 +523,7 @@ impl<R: io::AsyncRead + Unpin> ProgressParser<R> {
                 callback(progress);
             }
         }
-        Ok(())
+        Ok(diagnostics)
     }
 
     fn parse_progress(line: &str) -> Option<FetchProgress> {
@@ -29,6 +29,7 @@ mod test {
     use std::{io::Cursor, thread};
 
     use super::*;
+    use crate::payload::Record;
 
     #[test]
     fn roundtrip() {
@@ -104,4 +105,49 @@ mod test {
             out_stone.len()
         );
     }
+
+    #[test]
+    fn build_metadata_roundtrip() {
+        let string = |tag, value: &str| StonePayloadMetaRecord {
+            tag,
+            primitive: StonePayloadMetaPrimitive::String(value.to_owned()),
+        };
+        let meta = vec![
+            string(StonePayloadMetaTag::Name, "nano"),
+            string(StonePayloadMetaTag::Builder, "boulder version v0.26.6"),
+            string(StonePayloadMetaTag::BuildHost, "x86_64"),
+            StonePayloadMetaRecord {
+                tag: StonePayloadMetaTag::BuildTime,
+                primitive: StonePayloadMetaPrimitive::Uint64(1_700_000_000),
+            },
+            string(StonePayloadMetaTag::BuildProfile, "default-x86_64"),
+        ];
+
+        let mut stone = vec![];
+        let mut writer = StoneWriter::new(&mut stone, StoneHeaderV1FileType::Binary).unwrap();
+        writer.add_payload(meta.as_slice()).unwrap();
+        writer.finalize().unwrap();
+
+        let mut reader = read_bytes(&stone).unwrap();
+        let payloads = reader.payloads().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        let read = payloads.iter().find_map(StoneDecodedPayload::meta).unwrap();
+        assert_eq!(read.body, meta);
+
+        // Readers predating a tag decode it as unknown, rather than failing
+        let mut record = vec![];
+        StonePayloadMetaRecord {
+            tag: StonePayloadMetaTag::Unknown,
+            primitive: StonePayloadMetaPrimitive::String("future".to_owned()),
+        }
+        .encode(&mut record)
+        .unwrap();
+        record[4..6].copy_from_slice(&1000u16.to_be_bytes());
+        assert_eq!(
+            StonePayloadMetaRecord::decode(record.as_slice()).unwrap(),
+            StonePayloadMetaRecord {
+                tag: StonePayloadMetaTag::Unknown,
+                primitive: StonePayloadMetaPrimitive::String("future".to_owned()),
+            }
+        );
+    }
 }
@@ -103,6 +103,17 @@ pub struct StonePayloadLayoutRecord {
     pub file: StonePayloadLayoutFile,
 }
 
+impl StonePayloadLayoutRecord {
+    /// Bit of [`StonePayloadLayoutRecord::tag`] marking an entry which is
+    /// kept on disk when its package is removed
+    pub const TAG_RETAIN: u32 = 1;
+
+    /// Returns `true` if the entry is kept on disk when its package is removed
+    pub fn is_retained(&self) -> bool {
+        self.tag & Self::TAG_RETAIN != 0
+    }
+}
+
 impl Record for StonePayloadLayoutRecord {
     fn decode<R: Read>(mut reader: R) -> Result<Self, StonePayloadDecodeError> {
         let uid = reader.read_u32()?;
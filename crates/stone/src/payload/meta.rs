@@ -135,6 +135,16 @@ pub enum StonePayloadMetaTag {
     SourcePath = 19,
     // Ref/commit of the upstream source
     SourceRef = 20,
+    // Hash of the inputs the package was built from
+    BuildInputs = 21,
+    // Name & version of the tool that built the package
+    Builder = 22,
+    // Architecture of the host the package was built on
+    BuildHost = 23,
+    // Build timestamp in seconds since the epoch, honoring SOURCE_DATE_EPOCH
+    BuildTime = 24,
+    // Name of the profile the package was built with
+    BuildProfile = 25,
 
     Unknown = u16::MAX,
 }
@@ -180,6 +190,11 @@ impl Record for StonePayloadMetaRecord {
             18 => StonePayloadMetaTag::SourceURI,
             19 => StonePayloadMetaTag::SourcePath,
             20 => StonePayloadMetaTag::SourceRef,
+            21 => StonePayloadMetaTag::BuildInputs,
+            22 => StonePayloadMetaTag::Builder,
+            23 => StonePayloadMetaTag::BuildHost,
+            24 => StonePayloadMetaTag::BuildTime,
+            25 => StonePayloadMetaTag::BuildProfile,
             _ => StonePayloadMetaTag::Unknown,
         };
 
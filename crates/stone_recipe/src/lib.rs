@@ -1,7 +1,7 @@
 // SPDX-FileCopyrightText: 2023 AerynOS Developers
 // SPDX-License-Identifier: MPL-2.0
 
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, num::NonZeroUsize};
 
 use serde::Deserialize;
 pub use serde_yaml::Error;
@@ -48,12 +48,23 @@ pub struct Recipe {
     pub upstreams: Vec<Upstream>,
     #[serde(default)]
     pub architectures: Vec<String>,
+    /// Definitions usable by the scripts of the recipe, as `%(identifier)`
+    #[serde(default)]
+    pub definitions: BTreeMap<String, String>,
     #[serde(default)]
     pub tuning: Vec<KeyValue<Tuning>>,
     #[serde(default, deserialize_with = "stringy_bool")]
     pub emul32: bool,
+    /// Also install the `-32bit-devel` counterparts of the `-devel` build
+    /// dependencies available, when building `emul32`
+    #[serde(default = "default_true", deserialize_with = "stringy_bool")]
+    pub emul32_deps_auto: bool,
     #[serde(default, deserialize_with = "stringy_bool")]
     pub mold: bool,
+    /// Paths outside `/usr` the build is known to install, i.e. `/etc/foo`,
+    /// which boulder doesn't report
+    #[serde(default)]
+    pub allow_paths: Vec<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -71,6 +82,9 @@ pub struct Source {
     pub homepage: String,
     #[serde(deserialize_with = "single_as_sequence")]
     pub license: Vec<String>,
+    /// Project name on release-monitoring.org, when it differs from `name`
+    #[serde(default)]
+    pub monitoring: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -80,6 +94,9 @@ pub struct Build {
     pub install: Option<String>,
     pub check: Option<String>,
     pub workload: Option<String>,
+    /// Named workloads profiled by the stages of `pgo_stages`, instead of `workload`
+    #[serde(default)]
+    pub workloads: BTreeMap<String, String>,
     pub environment: Option<String>,
     #[serde(default, rename = "builddeps")]
     pub build_deps: Vec<String>,
@@ -95,16 +112,29 @@ pub struct Options {
     pub cspgo: bool,
     #[serde(default, deserialize_with = "stringy_bool")]
     pub samplepgo: bool,
+    /// Order the named `workloads` are profiled in, each by a PGO stage of its own
+    #[serde(default)]
+    pub pgo_stages: Vec<String>,
     #[serde(default = "default_true", deserialize_with = "stringy_bool")]
     pub debug: bool,
     #[serde(default = "default_true", deserialize_with = "stringy_bool")]
     pub strip: bool,
     #[serde(default, deserialize_with = "stringy_bool")]
     pub networking: bool,
+    /// Hosts reachable with `networking`, alongside those of the profile
+    #[serde(default)]
+    pub network_allowlist: Vec<String>,
     #[serde(default, deserialize_with = "stringy_bool")]
     pub compressman: bool,
     #[serde(default = "default_true", deserialize_with = "stringy_bool")]
     pub lastrip: bool,
+    /// Most jobs builds run in parallel, as `%(jobs)`
+    #[serde(default)]
+    pub max_jobs: Option<NonZeroUsize>,
+    /// Memory each job of a build needs, i.e. `2G`, limiting `%(jobs)` to
+    /// those fitting in the memory of the host
+    #[serde(default)]
+    pub max_memory_per_job: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -121,12 +151,18 @@ pub struct Package {
     pub paths: Vec<Path>,
     #[serde(default)]
     pub conflicts: Vec<String>,
+    /// Patterns of paths never stripped nor split into `-dbginfo`, also
+    /// applying to sub-packages when given for the main package
+    #[serde(default)]
+    pub nostrip: Vec<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Path {
     pub path: String,
     pub kind: PathKind,
+    /// Keep matching files on disk when the package is removed
+    pub retain: bool,
 }
 
 impl<'de> Deserialize<'de> for Path {
@@ -138,21 +174,37 @@ impl<'de> Deserialize<'de> for Path {
         #[serde(untagged)]
         enum Inner {
             String(String),
-            KeyValue(BTreeMap<String, PathKind>),
+            KeyValue(BTreeMap<String, Value>),
+        }
+
+        /// Either `- /path: exe` or `- /path: { kind: exe, retain: true }`
+        #[derive(Debug, Deserialize)]
+        #[serde(untagged)]
+        enum Value {
+            Kind(PathKind),
+            Options {
+                #[serde(default)]
+                kind: PathKind,
+                #[serde(default, deserialize_with = "stringy_bool")]
+                retain: bool,
+            },
         }
 
         match Inner::deserialize(deserializer)? {
             Inner::String(path) => Ok(Path {
                 path,
                 kind: PathKind::default(),
+                retain: false,
             }),
-            Inner::KeyValue(map) => {
-                if let Some((path, kind)) = map.into_iter().next() {
-                    Ok(Path { path, kind })
-                } else {
-                    Err(serde::de::Error::custom("missing path entry"))
-                }
-            }
+            Inner::KeyValue(map) => match map.into_iter().next() {
+                Some((path, Value::Kind(kind))) => Ok(Path {
+                    path,
+                    kind,
+                    retain: false,
+                }),
+                Some((path, Value::Options { kind, retain })) => Ok(Path { path, kind, retain }),
+                None => Err(serde::de::Error::custom("missing path entry")),
+            },
         }
     }
 }
@@ -235,4 +287,164 @@ mod test {
             dbg!(&recipe);
         }
     }
+
+    #[test]
+    fn deserialize_paths() {
+        let package: Package = serde_yaml::from_str(
+            "
+paths:
+    - /usr/bin/nano
+    - /usr/bin/rnano: symlink
+    - /usr/share/nano/state:
+        retain: true
+    - /usr/share/nano/cache:
+        kind: special
+        retain: 'true'
+",
+        )
+        .unwrap();
+
+        let path = |path: &str, kind, retain| Path {
+            path: path.to_owned(),
+            kind,
+            retain,
+        };
+        assert_eq!(
+            package.paths,
+            [
+                path("/usr/bin/nano", PathKind::Any, false),
+                path("/usr/bin/rnano", PathKind::Symlink, false),
+                path("/usr/share/nano/state", PathKind::Any, true),
+                path("/usr/share/nano/cache", PathKind::Special, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn deserialize_nostrip() {
+        let recipe = from_str(
+            "
+name: go-tool
+version: 1.0
+release: 1
+homepage: https://example.com
+license: MIT
+summary: Example
+description: Example
+nostrip:
+    - /usr/bin/go-tool
+packages:
+    - \"%(name)-plugins\":
+        nostrip:
+            - /usr/lib/go-tool/plugins/*.so
+",
+        )
+        .unwrap();
+
+        assert_eq!(recipe.package.nostrip, ["/usr/bin/go-tool"]);
+        assert_eq!(recipe.sub_packages[0].value.nostrip, ["/usr/lib/go-tool/plugins/*.so"]);
+
+        let package: Package = serde_yaml::from_str("summary: No patterns").unwrap();
+        assert!(package.nostrip.is_empty());
+    }
+
+    #[test]
+    fn deserialize_network_allowlist() {
+        let recipe = from_str(
+            "
+name: cargo-tool
+version: 1.0
+release: 1
+homepage: https://example.com
+license: MIT
+summary: Example
+description: Example
+networking: true
+network_allowlist:
+    - static.crates.io
+    - index.crates.io
+",
+        )
+        .unwrap();
+
+        assert!(recipe.options.networking);
+        assert_eq!(
+            recipe.options.network_allowlist,
+            ["static.crates.io", "index.crates.io"]
+        );
+    }
+
+    #[test]
+    fn deserialize_job_limits() {
+        let recipe = from_str(
+            "
+name: llvm
+version: 21.1.0
+release: 1
+homepage: https://example.com
+license: Apache-2.0
+summary: Example
+description: Example
+max_jobs: 16
+max_memory_per_job: 4G
+",
+        )
+        .unwrap();
+
+        assert_eq!(recipe.options.max_jobs, NonZeroUsize::new(16));
+        assert_eq!(recipe.options.max_memory_per_job.as_deref(), Some("4G"));
+    }
+
+    #[test]
+    fn deserialize_workloads() {
+        let recipe = from_str(
+            "
+name: zstd
+version: 1.5.7
+release: 1
+homepage: https://example.com
+license: BSD-3-Clause
+summary: Example
+description: Example
+pgo_stages: [training, sampling]
+workloads:
+    training: |
+        zstd -19 corpus
+    sampling: |
+        zstd -d corpus.zst
+",
+        )
+        .unwrap();
+
+        assert_eq!(recipe.options.pgo_stages, ["training", "sampling"]);
+        assert_eq!(recipe.build.workloads["training"], "zstd -19 corpus\n");
+        assert_eq!(recipe.build.workload, None);
+    }
+
+    #[test]
+    fn deserialize_definitions() {
+        let recipe = from_str(
+            "
+name: nano
+version: 8.7
+release: 1
+homepage: https://nano-editor.org
+license: GPL-3.0-or-later
+summary: GNU Text Editor
+description: GNU Text Editor
+definitions:
+    nano_flags: --enable-utf8 %(extra_flags)
+    extra_flags: --disable-libmagic
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            recipe.definitions,
+            BTreeMap::from([
+                ("extra_flags".to_owned(), "--disable-libmagic".to_owned()),
+                ("nano_flags".to_owned(), "--enable-utf8 %(extra_flags)".to_owned()),
+            ])
+        );
+    }
 }
@@ -11,8 +11,11 @@ use nom::{
     multi::{many_till, many1},
     sequence::{delimited, preceded, terminated},
 };
-use snafu::{OptionExt, ResultExt, Snafu};
-use std::collections::{BTreeMap, BTreeSet};
+use snafu::{ResultExt, Snafu};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
 
 use crate::{Macros, macros::Action};
 
@@ -21,6 +24,7 @@ pub struct Parser {
     actions: BTreeMap<String, Action>,
     definitions: BTreeMap<String, String>,
     env: Option<String>,
+    lenient: bool,
 }
 
 impl Parser {
@@ -37,6 +41,12 @@ impl Parser {
         }
     }
 
+    /// Keep macros that aren't defined as written, rather than failing to parse,
+    /// see [`Parser::undefined`]
+    pub fn lenient(self) -> Self {
+        Self { lenient: true, ..self }
+    }
+
     pub fn add_action(&mut self, identifier: impl ToString, action: Action) {
         self.actions.insert(identifier.to_string(), action);
     }
@@ -45,6 +55,25 @@ impl Parser {
         self.definitions.insert(identifier.to_string(), definition.to_string());
     }
 
+    /// Actions added, by identifier
+    pub fn actions(&self) -> impl Iterator<Item = (&str, &Action)> {
+        self.actions
+            .iter()
+            .map(|(identifier, action)| (identifier.as_str(), action))
+    }
+
+    /// Definitions added, by identifier, before expansion
+    pub fn definitions(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.definitions
+            .iter()
+            .map(|(identifier, definition)| (identifier.as_str(), definition.as_str()))
+    }
+
+    /// Whether `identifier` is defined, i.e. usable as `%(identifier)`
+    pub fn has_definition(&self, identifier: &str) -> bool {
+        self.definitions.contains_key(identifier)
+    }
+
     pub fn add_macros(&mut self, macros: Macros) {
         for kv in macros.actions {
             self.add_action(kv.key, kv.value);
@@ -55,23 +84,17 @@ impl Parser {
     }
 
     pub fn parse(&self, input: &str) -> Result<Script, Error> {
+        check_recursion(&self.definitions)?;
+
         let mut dependencies = BTreeSet::new();
 
-        let Parsed { commands, env } = parse(
-            input,
-            self.env.as_deref(),
-            &self.actions,
-            &self.definitions,
-            &mut dependencies,
-        )?;
+        let Parsed { commands, env } = parse(input, self.env.as_deref(), &self.lookup(), &mut dependencies)?;
 
         let resolved_actions = self
             .actions
             .iter()
             .filter_map(|(identifier, action)| {
-                let result =
-                    parse_content_only(&action.command, &self.actions, &self.definitions, &mut BTreeSet::new())
-                        .transpose()?;
+                let result = parse_content_only(&action.command, &self.lookup(), &mut BTreeSet::new()).transpose()?;
 
                 Some(result.map(|resolved| (identifier.clone(), resolved)))
             })
@@ -80,8 +103,7 @@ impl Parser {
             .definitions
             .iter()
             .filter_map(|(identifier, definition)| {
-                let result = parse_content_only(definition, &self.actions, &self.definitions, &mut BTreeSet::new())
-                    .transpose()?;
+                let result = parse_content_only(definition, &self.lookup(), &mut BTreeSet::new()).transpose()?;
 
                 Some(result.map(|resolved| (identifier.clone(), resolved)))
             })
@@ -97,8 +119,92 @@ impl Parser {
     }
 
     pub fn parse_content(&self, input: &str) -> Result<String, Error> {
-        parse_content_only(input, &self.actions, &self.definitions, &mut Default::default())
-            .map(Option::unwrap_or_default)
+        check_recursion(&self.definitions)?;
+
+        parse_content_only(input, &self.lookup(), &mut Default::default()).map(Option::unwrap_or_default)
+    }
+
+    /// Macros referenced by `input` & its environment that aren't defined, by line
+    ///
+    /// Those referenced by the actions & definitions used are listed with the
+    /// line using them. Actions take their arguments as plain text, so only
+    /// their identifiers are checked.
+    pub fn undefined(&self, input: &str) -> Result<Vec<Undefined>, Error> {
+        check_recursion(&self.definitions)?;
+
+        let lookup = self.lookup();
+        let mut undefined = BTreeSet::new();
+
+        if let Some(env) = &self.env {
+            collect_undefined(env, None, true, &lookup, &mut undefined)?;
+        }
+        collect_undefined(input, Some(1), false, &lookup, &mut undefined)?;
+
+        Ok(undefined.into_iter().collect())
+    }
+
+    fn lookup(&self) -> Lookup<'_> {
+        Lookup {
+            actions: &self.actions,
+            definitions: &self.definitions,
+            lenient: self.lenient,
+        }
+    }
+}
+
+/// The macros a script is parsed with
+struct Lookup<'a> {
+    actions: &'a BTreeMap<String, Action>,
+    definitions: &'a BTreeMap<String, String>,
+    /// See [`Parser::lenient`]
+    lenient: bool,
+}
+
+/// A macro referenced by a script that isn't defined
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Undefined {
+    /// Of the script, from 1, or `None` when referenced by its environment
+    pub line: Option<usize>,
+    pub reference: Reference,
+    /// Whether a macro of the other kind has the identifier, i.e. `%(make)` of the action `%make`
+    pub other_kind: bool,
+}
+
+/// A macro, as referenced by a script
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Reference {
+    /// `%identifier`
+    Action(String),
+    /// `%(identifier)`
+    Definition(String),
+}
+
+impl fmt::Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reference::Action(identifier) => write!(f, "%{identifier}"),
+            Reference::Definition(identifier) => write!(f, "%({identifier})"),
+        }
+    }
+}
+
+impl fmt::Display for Undefined {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.reference {
+            Reference::Action(identifier) => {
+                write!(f, "unknown action macro {}", self.reference)?;
+                if self.other_kind {
+                    write!(f, ", did you mean the definition %({identifier})?")?;
+                }
+            }
+            Reference::Definition(identifier) => {
+                write!(f, "unknown definition macro {}", self.reference)?;
+                if self.other_kind {
+                    write!(f, ", did you mean the action %{identifier}?")?;
+                }
+            }
+        }
+        Ok(())
     }
 }
 
@@ -135,8 +241,7 @@ struct Parsed {
 fn parse(
     input: &str,
     env: Option<&str>,
-    actions: &BTreeMap<String, Action>,
-    definitions: &BTreeMap<String, String>,
+    macros: &Lookup<'_>,
     dependencies: &mut BTreeSet<String>,
 ) -> Result<Parsed, Error> {
     let mut line_num = 0;
@@ -145,7 +250,7 @@ fn parse(
 
     // Parse `env` since it can contain macros
     let env = env
-        .map(|env| parse_content_only(env, actions, definitions, dependencies))
+        .map(|env| parse_content_only(env, macros, dependencies))
         .transpose()?
         .flatten();
 
@@ -162,19 +267,29 @@ fn parse(
     tokens(input, |token| {
         match token {
             Token::Action(identifier) => {
-                let action = actions.get(identifier).context(UnknownActionSnafu { identifier })?;
+                let Some(action) = macros.actions.get(identifier) else {
+                    if macros.lenient {
+                        content.push_str(&Reference::Action(identifier.to_owned()).to_string());
+                        return Ok(());
+                    }
+                    return UnknownActionSnafu { identifier }.fail();
+                };
                 dependencies.extend(action.dependencies.clone());
 
-                if let Some(nested) = parse_content_only(&action.command, actions, definitions, dependencies)? {
+                if let Some(nested) = parse_content_only(&action.command, macros, dependencies)? {
                     content.push_str(&nested);
                 }
             }
             Token::Definition(identifier) => {
-                let definition = definitions
-                    .get(identifier)
-                    .context(UnknownDefinitionSnafu { identifier })?;
-
-                if let Some(nested) = parse_content_only(definition, actions, definitions, dependencies)? {
+                let Some(definition) = macros.definitions.get(identifier) else {
+                    if macros.lenient {
+                        content.push_str(&Reference::Definition(identifier.to_owned()).to_string());
+                        return Ok(());
+                    }
+                    return UnknownDefinitionSnafu { identifier }.fail();
+                };
+
+                if let Some(nested) = parse_content_only(definition, macros, dependencies)? {
                     content.push_str(&nested);
                 }
             }
@@ -205,11 +320,10 @@ fn parse(
 /// Extract the `parse` call as content only, used for parsing nested macros
 fn parse_content_only(
     input: &str,
-    actions: &BTreeMap<String, Action>,
-    definitions: &BTreeMap<String, String>,
+    macros: &Lookup<'_>,
     dependencies: &mut BTreeSet<String>,
 ) -> Result<Option<String>, Error> {
-    Ok(parse(input, None, actions, definitions, dependencies)?
+    Ok(parse(input, None, macros, dependencies)?
         .commands
         .into_iter()
         .next()
@@ -222,6 +336,90 @@ fn parse_content_only(
         }))
 }
 
+/// Add the macros referenced by `input` that aren't defined to `undefined`
+///
+/// References are on `line` of the script, counting the lines of `input` unless
+/// `nested` within a macro or the environment.
+fn collect_undefined(
+    input: &str,
+    mut line: Option<usize>,
+    nested: bool,
+    macros: &Lookup<'_>,
+    undefined: &mut BTreeSet<Undefined>,
+) -> Result<(), Error> {
+    tokens(input, |token| {
+        match token {
+            Token::Action(identifier) => match macros.actions.get(identifier) {
+                Some(action) => collect_undefined(&action.command, line, true, macros, undefined)?,
+                None => {
+                    undefined.insert(Undefined {
+                        line,
+                        reference: Reference::Action(identifier.to_owned()),
+                        other_kind: macros.definitions.contains_key(identifier),
+                    });
+                }
+            },
+            Token::Definition(identifier) => match macros.definitions.get(identifier) {
+                Some(definition) => collect_undefined(definition, line, true, macros, undefined)?,
+                None => {
+                    undefined.insert(Undefined {
+                        line,
+                        reference: Reference::Definition(identifier.to_owned()),
+                        other_kind: macros.actions.contains_key(identifier),
+                    });
+                }
+            },
+            Token::Newline if !nested => line = line.map(|line| line + 1),
+            Token::Newline | Token::Plain(_) | Token::Break { .. } => {}
+        }
+        Ok(())
+    })
+}
+
+/// Fail on definitions expanding to themselves, which would never finish
+fn check_recursion(definitions: &BTreeMap<String, String>) -> Result<(), Error> {
+    fn visit(
+        identifier: &str,
+        definitions: &BTreeMap<String, String>,
+        expanding: &mut Vec<String>,
+        checked: &mut BTreeSet<String>,
+    ) -> Result<(), Error> {
+        if checked.contains(identifier) {
+            return Ok(());
+        }
+        if expanding.iter().any(|expanded| expanded == identifier) {
+            return RecursiveDefinitionSnafu { identifier }.fail();
+        }
+        let Some(definition) = definitions.get(identifier) else {
+            return Ok(());
+        };
+
+        let mut referenced = vec![];
+        tokens(definition, |token| {
+            if let Token::Definition(identifier) = token {
+                referenced.push(identifier.to_owned());
+            }
+            Ok(())
+        })?;
+
+        expanding.push(identifier.to_owned());
+        for nested in referenced {
+            visit(&nested, definitions, expanding, checked)?;
+        }
+        expanding.pop();
+        checked.insert(identifier.to_owned());
+
+        Ok(())
+    }
+
+    let mut checked = BTreeSet::new();
+    for identifier in definitions.keys() {
+        visit(identifier, definitions, &mut vec![], &mut checked)?;
+    }
+
+    Ok(())
+}
+
 #[derive(Debug)]
 enum Token<'a> {
     Action(&'a str),
@@ -279,6 +477,8 @@ pub enum Error {
     UnknownAction { identifier: String },
     #[snafu(display("unknown definition macro: %({identifier})"))]
     UnknownDefinition { identifier: String },
+    #[snafu(display("definition macro %({identifier}) expands to itself"))]
+    RecursiveDefinition { identifier: String },
     #[snafu(display("parse script"))]
     Parser {
         source: nom::Err<nom::error::Error<String>>,
@@ -373,4 +573,104 @@ mod test {
             })
         );
     }
+
+    #[test]
+    fn recursive_definition() {
+        let mut parser = Parser::new();
+        parser.add_definition("a", "%(b) -x");
+        parser.add_definition("b", "%(c)");
+        parser.add_definition("c", "--%(a)");
+        parser.add_definition("d", "%(d)");
+
+        assert!(matches!(
+            parser.parse("%(d)"),
+            Err(Error::RecursiveDefinition { identifier }) if identifier == "a"
+        ));
+        assert!(matches!(
+            parser.parse_content("%(c)"),
+            Err(Error::RecursiveDefinition { .. })
+        ));
+    }
+
+    fn parser() -> Parser {
+        let mut parser = Parser::new().env("export CC=%(compiler_c)\nexport LD=%(compiler_ld)");
+        parser.add_action(
+            "make",
+            Action {
+                description: "test".into(),
+                example: None,
+                command: "make -j %(jobs)".into(),
+                dependencies: vec![],
+            },
+        );
+        parser.add_action(
+            "make_install",
+            Action {
+                description: "test".into(),
+                example: None,
+                command: "%make install DESTDIR=%(instaldir)".into(),
+                dependencies: vec![],
+            },
+        );
+        for (id, definition) in [
+            ("compiler_c", "clang"),
+            ("installroot", "/mason/install"),
+            ("jobs", "4"),
+        ] {
+            parser.add_definition(id, definition);
+        }
+        parser
+    }
+
+    #[test]
+    fn undefined_macros() {
+        let input = "%make\n%cmake_build\n\n%make_install\ncp x %(instalroot)/ %(make) %%(escaped)\n%break_continue";
+
+        let undefined = |line, reference, other_kind| Undefined {
+            line,
+            reference,
+            other_kind,
+        };
+        assert_eq!(
+            parser().undefined(input).unwrap(),
+            [
+                undefined(None, Reference::Definition("compiler_ld".to_owned()), false),
+                undefined(Some(2), Reference::Action("cmake_build".to_owned()), false),
+                // Nested within the action used
+                undefined(Some(4), Reference::Definition("instaldir".to_owned()), false),
+                undefined(Some(5), Reference::Definition("instalroot".to_owned()), false),
+                // An action used as a definition
+                undefined(Some(5), Reference::Definition("make".to_owned()), true),
+            ]
+        );
+        assert_eq!(
+            parser().undefined("%make\n%(installroot)").unwrap(),
+            [undefined(None, Reference::Definition("compiler_ld".to_owned()), false)]
+        );
+
+        assert_eq!(
+            undefined(Some(5), Reference::Definition("make".to_owned()), true).to_string(),
+            "unknown definition macro %(make), did you mean the action %make?"
+        );
+        assert_eq!(
+            undefined(Some(1), Reference::Action("jobs".to_owned()), true).to_string(),
+            "unknown action macro %jobs, did you mean the definition %(jobs)?"
+        );
+    }
+
+    #[test]
+    fn lenient() {
+        let input = "%make\ncp x %(instalroot)/";
+
+        assert!(matches!(
+            parser().parse(input),
+            Err(Error::UnknownDefinition { identifier }) if identifier == "compiler_ld"
+        ));
+        assert_eq!(
+            parser().lenient().parse(input).unwrap().commands,
+            [Command::Content(
+                "export CC=clang\nexport LD=%(compiler_ld)\nmake -j 4\ncp x %(instalroot)/".to_owned()
+            )]
+        );
+    }
 }
@@ -78,7 +78,8 @@ impl TuningFlag {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display, strum::VariantArray)]
+#[strum(serialize_all = "lowercase")]
 pub enum CompilerFlag {
     C,
     Cxx,
@@ -117,8 +118,9 @@ impl CompilerFlags {
     }
 }
 
-#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, strum::Display, strum::VariantArray)]
 #[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
 pub enum Toolchain {
     #[default]
     Llvm,
@@ -202,6 +204,35 @@ impl Builder {
         Ok(())
     }
 
+    /// Every group that can be enabled, by name
+    pub fn groups(&self) -> impl Iterator<Item = (&str, &TuningGroup)> {
+        self.groups.iter().map(|(name, group)| (name.as_str(), group))
+    }
+
+    pub fn group(&self, name: &str) -> Result<&TuningGroup, Error> {
+        self.groups.get(name).context(UnknownGroupSnafu { name })
+    }
+
+    /// Groups enabled so far, with the option set for each
+    pub fn enabled(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.enabled
+            .iter()
+            .map(|name| (name.as_str(), self.option_sets.get(name).map(String::as_str)))
+    }
+
+    /// Groups disabled so far
+    pub fn disabled(&self) -> impl Iterator<Item = &str> {
+        self.disabled.iter().map(String::as_str)
+    }
+
+    /// The flags named by `names`, as a group enables or disables them
+    pub fn flags<'a>(&'a self, names: &[String]) -> Result<Vec<&'a TuningFlag>, Error> {
+        names
+            .iter()
+            .map(|name| self.flags.get(name).context(UnknownFlagSnafu { name }))
+            .collect()
+    }
+
     pub fn build(&self) -> Result<Vec<TuningFlag>, Error> {
         let mut enabled_flags = BTreeSet::new();
         let mut disabled_flags = BTreeSet::new();
@@ -243,6 +274,19 @@ impl Builder {
     }
 }
 
+/// The `flag` values of `flags` for `toolchain`, deduplicated & space separated
+pub fn join<'a>(flags: impl IntoIterator<Item = &'a TuningFlag>, flag: CompilerFlag, toolchain: Toolchain) -> String {
+    flags
+        .into_iter()
+        .filter_map(|tuning| tuning.get(flag, toolchain))
+        .map(str::trim)
+        .filter(|value| value.len() > 1)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 #[derive(Debug, Snafu)]
 pub enum Error {
     #[snafu(display("unknown flag {name}"))]
@@ -252,3 +296,37 @@ pub enum Error {
     #[snafu(display("unknown value {value} for group {group}"))]
     UnknownGroupValue { value: String, group: String },
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn inspect() {
+        let mut builder = Builder::new();
+        builder.add_macros(crate::macros::from_slice(include_bytes!("../../../test/base.yml")).unwrap());
+
+        assert!(builder.groups().any(|(name, _)| name == "harden"));
+        assert!(matches!(builder.group("hardened"), Err(Error::UnknownGroup { .. })));
+
+        builder.enable("harden", None).unwrap();
+        builder.enable("optimize", Some("fast".to_owned())).unwrap();
+        builder.disable("optimize").unwrap();
+        assert_eq!(builder.enabled().collect::<Vec<_>>(), [("harden", Some("lvl1"))]);
+        assert_eq!(builder.disabled().collect::<Vec<_>>(), ["optimize"]);
+
+        let harden = builder.group("harden").unwrap();
+        let lvl2 = &harden.choices.iter().find(|kv| kv.key == "lvl2").unwrap().value;
+        let flags = builder.flags(&lvl2.enabled).unwrap();
+        assert_eq!(
+            join(flags.iter().copied(), CompilerFlag::C, Toolchain::Gnu),
+            "-fstack-protector-strong -fstack-clash-protection -fPIE --param ssp-buffer-size=4"
+        );
+        assert_eq!(join(flags, CompilerFlag::Rust, Toolchain::Llvm), "");
+
+        assert!(matches!(
+            builder.flags(&["missing".to_owned()]),
+            Err(Error::UnknownFlag { .. })
+        ));
+    }
+}
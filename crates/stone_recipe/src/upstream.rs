@@ -4,11 +4,15 @@
 use std::{borrow::Borrow, collections::BTreeMap, fmt::Display, path::PathBuf, str::FromStr};
 
 use crate::serde_util::{default_true, stringy_bool};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use url::Url;
 
 /// Prefix applied to URLs to report they point to a Git repository.
 pub static GIT_PREFIX: &str = "git|";
+/// Prefix applied to URLs to report they point to a Mercurial repository.
+pub static HG_PREFIX: &str = "hg|";
+/// Prefix applied to URLs to report they point to a Fossil repository.
+pub static FOSSIL_PREFIX: &str = "fossil|";
 
 #[derive(Debug, Clone)]
 pub struct Upstream {
@@ -26,6 +30,18 @@ impl<'de> Deserialize<'de> for Upstream {
         enum Fields {
             String(String),
             Props(Props),
+            Vcs(VcsFields),
+        }
+
+        /// Of a [`Props::Vcs`] upstream, only one of `rev`, `tag` or `branch` being given
+        #[derive(Debug, Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct VcsFields {
+            rev: Option<String>,
+            tag: Option<String>,
+            branch: Option<String>,
+            #[serde(rename = "clonedir")]
+            clone_dir: Option<PathBuf>,
         }
 
         let (uri, fields) = BTreeMap::<SourceUri, Fields>::deserialize(deserializer)?
@@ -36,13 +52,36 @@ impl<'de> Deserialize<'de> for Upstream {
             Fields::String(hash) => match &uri.kind {
                 Kind::Archive => Props::default_plain(hash),
                 Kind::Git => Props::default_git(hash),
+                Kind::Vcs(vcs) => Props::Vcs {
+                    vcs: *vcs,
+                    revision: Revision::Rev(hash),
+                    clone_dir: None,
+                },
             },
             Fields::Props(props) => match (&props, &uri.kind) {
-                (Props::Git { .. }, Kind::Archive) | (Props::Plain { .. }, Kind::Git) => {
+                (Props::Plain { .. }, Kind::Archive) | (Props::Git { .. }, Kind::Git) => props,
+                _ => return Err(serde::de::Error::custom("mismatched URL type and upstream properties")),
+            },
+            Fields::Vcs(fields) => {
+                let Kind::Vcs(vcs) = uri.kind else {
                     return Err(serde::de::Error::custom("mismatched URL type and upstream properties"));
+                };
+                let revision = match (fields.rev, fields.tag, fields.branch) {
+                    (Some(rev), None, None) => Revision::Rev(rev),
+                    (None, Some(tag), None) => Revision::Tag(tag),
+                    (None, None, Some(branch)) => Revision::Branch(branch),
+                    _ => {
+                        return Err(serde::de::Error::custom(format!(
+                            "{vcs} upstream must have exactly one of rev, tag or branch"
+                        )));
+                    }
+                };
+                Props::Vcs {
+                    vcs,
+                    revision,
+                    clone_dir: fields.clone_dir,
                 }
-                _ => props,
-            },
+            }
         };
 
         Ok(Self { url: uri.into(), props })
@@ -56,6 +95,67 @@ pub enum Kind {
     Archive,
     /// The upstream is a git repository.
     Git,
+    /// The upstream is a repository of another version control system.
+    Vcs(Vcs),
+}
+
+/// Version control systems, other than Git, an upstream can be cloned with.
+#[derive(Clone, Copy, Debug, Eq, PartialOrd, Ord, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Vcs {
+    Mercurial,
+    Fossil,
+}
+
+impl Vcs {
+    /// Prefix applied to URLs of its repositories.
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Vcs::Mercurial => HG_PREFIX,
+            Vcs::Fossil => FOSSIL_PREFIX,
+        }
+    }
+
+    /// Executable its repositories are cloned with.
+    pub fn command(&self) -> &'static str {
+        match self {
+            Vcs::Mercurial => "hg",
+            Vcs::Fossil => "fossil",
+        }
+    }
+}
+
+impl Display for Vcs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Vcs::Mercurial => write!(f, "mercurial"),
+            Vcs::Fossil => write!(f, "fossil"),
+        }
+    }
+}
+
+/// The revision of a [`Vcs`] repository to build from.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Revision {
+    /// A changeset or check-in, by its id or a prefix of it.
+    Rev(String),
+    Tag(String),
+    Branch(String),
+}
+
+impl Revision {
+    /// As understood by the [`Vcs`], each taking a tag or branch name in place of an id.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Revision::Rev(name) | Revision::Tag(name) | Revision::Branch(name) => name,
+        }
+    }
+}
+
+impl Display for Revision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 /// A URI from where to download source code.
@@ -68,7 +168,8 @@ pub enum Kind {
 /// In the case of an archive, a regular URL is used.
 ///
 /// For a git repository, the URI is parsed and unparsed
-/// with the format `git|<regular_url>`.
+/// with the format `git|<regular_url>`, likewise `hg|` & `fossil|`
+/// for Mercurial & Fossil repositories.
 #[derive(Clone, Debug, Deserialize, Eq, PartialOrd, Ord, PartialEq)]
 #[serde(try_from = "&str")]
 pub struct SourceUri {
@@ -87,6 +188,14 @@ impl FromStr for SourceUri {
                 kind: Kind::Git,
                 url: git_url.parse()?,
             })
+        } else if let Some((vcs, url)) = [Vcs::Mercurial, Vcs::Fossil]
+            .into_iter()
+            .find_map(|vcs| Some((vcs, s.strip_prefix(vcs.prefix())?)))
+        {
+            Ok(SourceUri {
+                kind: Kind::Vcs(vcs),
+                url: url.parse()?,
+            })
         } else {
             Ok(SourceUri {
                 kind: Kind::Archive,
@@ -111,6 +220,7 @@ impl Display for SourceUri {
             Kind::Git => {
                 write!(f, "{GIT_PREFIX}{}", self.url.as_str())
             }
+            Kind::Vcs(vcs) => write!(f, "{}{}", vcs.prefix(), self.url.as_str()),
         }
     }
 }
@@ -139,12 +249,25 @@ pub enum Props {
         unpack: bool,
         #[serde(rename = "unpackdir")]
         unpack_dir: Option<PathBuf>,
+        verify: Option<VerifyPlain>,
     },
     Git {
         #[serde(rename = "ref")]
         git_ref: String,
         #[serde(rename = "clonedir")]
         clone_dir: Option<PathBuf>,
+        /// Only clone this many commits of history, deepened as needed to reach `ref`
+        depth: Option<u32>,
+        /// Omit objects from the clone, fetched on demand, e.g. `blob:none`
+        filter: Option<String>,
+        verify: Option<VerifyGit>,
+    },
+    /// Of a [`Kind::Vcs`] upstream, validated when the upstream is parsed
+    #[serde(skip_deserializing)]
+    Vcs {
+        vcs: Vcs,
+        revision: Revision,
+        clone_dir: Option<PathBuf>,
     },
 }
 
@@ -156,6 +279,7 @@ impl Props {
             strip_dirs: None,
             unpack: true,
             unpack_dir: None,
+            verify: None,
         }
     }
 
@@ -163,10 +287,40 @@ impl Props {
         Self::Git {
             git_ref,
             clone_dir: None,
+            depth: None,
+            filter: None,
+            verify: None,
         }
     }
 }
 
+/// Verification of the detached signature of a [`Props::Plain`] upstream,
+/// before its hash is checked
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct VerifyPlain {
+    /// URL of the detached signature of the archive
+    pub sig: Url,
+    /// OpenPGP public key of the signer, relative to the recipe
+    pub pgp_key: PathBuf,
+}
+
+/// Verification of the signature of a [`Props::Git`] upstream, once checked out
+///
+/// Signers are trusted through exactly one of `allowed_signers`, for SSH
+/// signatures, or `keyring`, for OpenPGP ones.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct VerifyGit {
+    /// Verify the tag of `ref` rather than its commit
+    #[serde(default, deserialize_with = "stringy_bool")]
+    pub signed_tag: bool,
+    /// SSH allowed signers file, relative to the recipe
+    pub allowed_signers: Option<PathBuf>,
+    /// OpenPGP keyring, relative to the recipe
+    pub keyring: Option<PathBuf>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -198,4 +352,153 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn parse_git_partial() {
+        let upstreams: Vec<Upstream> = serde_yaml::from_str(&format!(
+            "
+- {GIT_PREFIX}{SRC_URL}:
+    ref: v1.0
+    depth: 1
+    filter: blob:none
+- {GIT_PREFIX}{SRC_URL}: v1.0
+"
+        ))
+        .unwrap();
+
+        let Props::Git { depth, filter, .. } = &upstreams[0].props else {
+            panic!("not a git upstream");
+        };
+        assert_eq!(*depth, Some(1));
+        assert_eq!(filter.as_deref(), Some("blob:none"));
+
+        let Props::Git { depth, filter, .. } = &upstreams[1].props else {
+            panic!("not a git upstream");
+        };
+        assert_eq!(*depth, None);
+        assert_eq!(*filter, None);
+    }
+
+    #[test]
+    fn parse_verify() {
+        let upstreams: Vec<Upstream> = serde_yaml::from_str(&format!(
+            "
+- {SRC_URL}/source-1.0.tar.xz:
+    hash: 0123456789abcdef
+    verify:
+      sig: {SRC_URL}/source-1.0.tar.xz.sig
+      pgp_key: keys/release.asc
+- {GIT_PREFIX}{SRC_URL}:
+    ref: v1.0
+    verify:
+      signed_tag: true
+      allowed_signers: keys/allowed_signers
+- {GIT_PREFIX}{SRC_URL}: v1.0
+"
+        ))
+        .unwrap();
+
+        let Props::Plain { verify, .. } = &upstreams[0].props else {
+            panic!("not a plain upstream");
+        };
+        assert_eq!(
+            verify.as_ref(),
+            Some(&VerifyPlain {
+                sig: Url::from_str(&format!("{SRC_URL}/source-1.0.tar.xz.sig")).unwrap(),
+                pgp_key: "keys/release.asc".into(),
+            })
+        );
+
+        let Props::Git { verify, .. } = &upstreams[1].props else {
+            panic!("not a git upstream");
+        };
+        assert_eq!(
+            verify.as_ref(),
+            Some(&VerifyGit {
+                signed_tag: true,
+                allowed_signers: Some("keys/allowed_signers".into()),
+                keyring: None,
+            })
+        );
+
+        let Props::Git { verify, .. } = &upstreams[2].props else {
+            panic!("not a git upstream");
+        };
+        assert_eq!(*verify, None);
+    }
+
+    #[test]
+    fn parse_vcs() -> Result<(), url::ParseError> {
+        for (prefix, vcs) in [(HG_PREFIX, Vcs::Mercurial), (FOSSIL_PREFIX, Vcs::Fossil)] {
+            let src: SourceUri = format!("{prefix}{SRC_URL}").parse()?;
+            assert_eq!(
+                src,
+                SourceUri {
+                    kind: Kind::Vcs(vcs),
+                    url: Url::from_str(SRC_URL)?
+                }
+            );
+            assert_eq!(src.to_string(), format!("{prefix}{SRC_URL}"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn parse_vcs_props() {
+        let upstreams: Vec<Upstream> = serde_yaml::from_str(&format!(
+            "
+- {HG_PREFIX}{SRC_URL}: a1b2c3d4e5f6
+- {HG_PREFIX}{SRC_URL}:
+    branch: stable
+    clonedir: hg
+- {FOSSIL_PREFIX}{SRC_URL}:
+    tag: version-3.45.0
+- {FOSSIL_PREFIX}{SRC_URL}:
+    rev: 8c1a3b2d
+"
+        ))
+        .unwrap();
+
+        let props = upstreams
+            .iter()
+            .map(|upstream| match &upstream.props {
+                Props::Vcs {
+                    vcs,
+                    revision,
+                    clone_dir,
+                } => (*vcs, revision.clone(), clone_dir.clone()),
+                _ => panic!("not a vcs upstream"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            props,
+            [
+                (Vcs::Mercurial, Revision::Rev("a1b2c3d4e5f6".to_owned()), None),
+                (
+                    Vcs::Mercurial,
+                    Revision::Branch("stable".to_owned()),
+                    Some(PathBuf::from("hg"))
+                ),
+                (Vcs::Fossil, Revision::Tag("version-3.45.0".to_owned()), None),
+                (Vcs::Fossil, Revision::Rev("8c1a3b2d".to_owned()), None),
+            ]
+        );
+        assert_eq!(upstreams[0].url.as_str(), SRC_URL);
+
+        let parse = |yaml: &str| serde_yaml::from_str::<Vec<Upstream>>(yaml).map_err(|error| error.to_string());
+        assert!(
+            parse(&format!("- {HG_PREFIX}{SRC_URL}:\n    tag: v1.0\n    branch: stable\n"))
+                .unwrap_err()
+                .contains("mercurial upstream must have exactly one of rev, tag or branch")
+        );
+        assert!(
+            parse(&format!("- {FOSSIL_PREFIX}{SRC_URL}:\n    clonedir: src\n"))
+                .unwrap_err()
+                .contains("fossil upstream must have exactly one of rev, tag or branch")
+        );
+        // Properties of another kind of upstream
+        assert!(parse(&format!("- {HG_PREFIX}{SRC_URL}:\n    ref: v1.0\n")).is_err());
+        assert!(parse(&format!("- {GIT_PREFIX}{SRC_URL}:\n    rev: v1.0\n")).is_err());
+        assert!(parse(&format!("- {SRC_URL}:\n    tag: v1.0\n")).is_err());
+    }
 }
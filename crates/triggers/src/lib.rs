@@ -14,7 +14,8 @@ pub mod format;
 pub struct Collection<'a> {
     handlers: Vec<ExtractedHandler<'a>>,
     triggers: BTreeMap<String, &'a Trigger>,
-    hits: BTreeMap<String, BTreeSet<format::CompiledHandler>>,
+    /// Paths matched by each handler, per trigger
+    hits: BTreeMap<String, BTreeMap<format::CompiledHandler, BTreeSet<String>>>,
 }
 
 #[derive(Debug)]
@@ -63,22 +64,35 @@ impl<'a> Collection<'a> {
     /// Process a batch set of paths and record the "hit"
     pub fn process_paths(&mut self, paths: impl Iterator<Item = String>) {
         let results = paths.into_iter().flat_map(|p| {
-            self.handlers
-                .iter()
-                .filter_map(move |h| h.pattern.match_path(&p).map(|m| (h.id, h.handler.compiled(&m))))
+            self.handlers.iter().filter_map(move |h| {
+                h.pattern
+                    .match_path(&p)
+                    .map(|m| (h.id, h.handler.compiled(&m), p.clone()))
+            })
         });
 
-        for (id, handler) in results {
-            if let Some(map) = self.hits.get_mut(id) {
-                map.insert(handler);
-            } else {
-                self.hits.insert(id.into(), BTreeSet::from_iter([handler]));
-            }
+        for (id, handler, path) in results {
+            self.hits
+                .entry(id.into())
+                .or_default()
+                .entry(handler)
+                .or_default()
+                .insert(path);
         }
     }
 
     /// Bake the trigger collection into a sane dependency order
     pub fn bake(&mut self) -> Result<Vec<format::CompiledHandler>, Error> {
+        Ok(self
+            .bake_with_paths()?
+            .into_iter()
+            .map(|(handler, _)| handler)
+            .collect())
+    }
+
+    /// Bake the trigger collection like [`Collection::bake`], along with
+    /// the paths matched by each handler
+    pub fn bake_with_paths(&mut self) -> Result<Vec<(format::CompiledHandler, BTreeSet<String>)>, Error> {
         let mut graph = dag::Dag::new();
 
         // ensure all keys are in place
@@ -1,13 +1,38 @@
 // SPDX-FileCopyrightText: 2023 AerynOS Developers
 // SPDX-License-Identifier: MPL-2.0
 
+use std::sync::atomic::{AtomicBool, Ordering};
+
 pub use self::styled::Styled;
 pub use dialoguer;
 pub use indicatif::*;
 
+pub mod pager;
 pub mod pretty;
 mod styled;
 
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Suppress progress output process-wide, i.e. when stdout carries
+/// machine-readable output
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/// Returns `true` if progress output has been suppressed with [`set_quiet`]
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Returns the draw target for progress bars, honoring [`set_quiet`]
+pub fn draw_target() -> ProgressDrawTarget {
+    if is_quiet() {
+        ProgressDrawTarget::hidden()
+    } else {
+        ProgressDrawTarget::stderr()
+    }
+}
+
 /// The size of a terminal emulator window.
 pub struct TermSize {
     /// Width (or number of columns) of the terminal, in characters.
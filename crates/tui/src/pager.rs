@@ -0,0 +1,47 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Paging of output too long for the terminal
+
+use std::{
+    env,
+    io::{self, IsTerminal, Write},
+    process::{Command, Stdio},
+};
+
+use crate::TermSize;
+
+/// Print `text`, through `$PAGER` or else `less` if it doesn't fit the terminal
+///
+/// Printed as is when stdout isn't a terminal or no pager can be run.
+pub fn page(text: &str) {
+    if !io::stdout().is_terminal() || text.lines().count() < TermSize::get().height {
+        print!("{text}");
+        return;
+    }
+
+    let pager = env::var("PAGER")
+        .ok()
+        .filter(|pager| !pager.trim().is_empty())
+        .unwrap_or_else(|| "less".to_owned());
+    let mut words = pager.split_whitespace();
+    let program = words.next().unwrap_or("less");
+
+    let child = Command::new(program)
+        .args(words)
+        // Keep colors, and quit when the output fits after all
+        .env("LESS", env::var("LESS").unwrap_or_else(|_| "FRX".to_owned()))
+        .stdin(Stdio::piped())
+        .spawn();
+
+    match child {
+        Ok(mut child) => {
+            if let Some(mut stdin) = child.stdin.take() {
+                // The pager may be quit before reading everything
+                let _ = stdin.write_all(text.as_bytes());
+            }
+            let _ = child.wait();
+        }
+        Err(_) => print!("{text}"),
+    }
+}
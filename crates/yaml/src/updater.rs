@@ -155,10 +155,13 @@ impl Operation {
         let mut sequence_index = 0;
         // If match is found
         let mut matched_substr = None;
-        // Updated w/ current nesting level and referenced
-        // to prevent looking at lines with a smaller indent
-        // (only move down, never back up)
-        let mut current_indent = 0;
+        // Column & kind (map or not) of the last matched node,
+        // which following lines must be nested under to prevent
+        // walking into its siblings (only move down, never back up)
+        let mut parent: Option<(usize, bool)> = None;
+        // Line of the last matched sequence item, which
+        // can hold a map on the same line
+        let mut sequence_line = None;
         // What line are we checking
         let mut current_line = 0;
 
@@ -176,12 +179,30 @@ impl Operation {
             while current_line < lines.len() {
                 let line = &lines[current_line];
 
+                // Blank lines don't end a node
+                if line.trim().is_empty() {
+                    current_line += 1;
+                    continue;
+                }
+
                 // Prevent bubbling back up the yaml document if
                 // a match isn't found at this level, to prevent
                 // matching at higher levels which don't match the
-                // walked path
+                // walked path. Sequences may share the indent of
+                // the map key holding them.
                 let indent = indent(line);
-                if indent < current_indent {
+                let nested = match parent {
+                    _ if sequence_line == Some(current_line) => true,
+                    None => true,
+                    Some((column, is_map)) => {
+                        indent > column
+                            || (is_map
+                                && indent == column
+                                && matches!(segment, Segment::Sequence(_))
+                                && line.trim_start().starts_with('-'))
+                    }
+                };
+                if !nested {
                     break;
                 }
 
@@ -195,9 +216,10 @@ impl Operation {
                                 if is_last_segment {
                                     matched_substr = Some((current_line, substr));
                                 }
-                                current_indent = indent;
+                                parent = Some((indent, false));
                                 // We don't increment line count since a map
                                 // can exist on same line as a sequence
+                                sequence_line = Some(current_line);
                                 break;
                             } else {
                                 sequence_index += 1;
@@ -209,6 +231,7 @@ impl Operation {
                         if let Some(key_substr) = map_key_scalar(line) {
                             // Is it the key we want
                             if key_substr.value(line) == key {
+                                parent = Some((key_substr.start, true));
                                 if is_last_segment {
                                     match self.update {
                                         Update::Key(_) => matched_substr = Some((current_line, key_substr)),
@@ -219,7 +242,6 @@ impl Operation {
                                         }
                                     }
                                 }
-                                current_indent = indent;
                                 current_line += 1;
                                 break;
                             }
@@ -289,4 +311,37 @@ some:
 
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn test_update_nested_only() {
+        let raw = r#"
+list:
+- a: 1
+- b:
+    c: 2
+
+    d: 3
+- e: 4
+  c: 5
+"#;
+        let expected = r#"
+list:
+- a: 1
+- b:
+    c: 6
+
+    d: 7
+- e: 4
+  c: 5
+"#;
+
+        let mut updater = Updater::new();
+        // Missing from its item, so left unmatched instead of matching a sibling
+        updater.update_value(8, |p| p / "list" / 0 / "c");
+        updater.update_value(9, |p| p / "list" / 1 / "e");
+        updater.update_value(6, |p| p / "list" / 1 / "b" / "c");
+        updater.update_value(7, |p| p / "list" / 1 / "b" / "d");
+
+        assert_eq!(updater.apply(raw), expected);
+    }
 }
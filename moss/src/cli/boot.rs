@@ -1,10 +1,10 @@
 // SPDX-FileCopyrightText: 2024 AerynOS Developers
 // SPDX-License-Identifier: MPL-2.0
 
-use clap::{ArgMatches, Command};
+use clap::{ArgAction, ArgMatches, Command, arg};
 use thiserror::Error;
 
-use moss::{Client, Installation, client, environment};
+use moss::{Client, client};
 
 pub fn command() -> Command {
     Command::new("boot")
@@ -13,28 +13,35 @@ pub fn command() -> Command {
         .subcommand_required(true)
         .subcommand(Command::new("status").about("Status of boot configuration"))
         .subcommand(Command::new("sync").about("Synchronize boot configuration"))
+        .subcommand(
+            Command::new("cleanup")
+                .about("Remove boot entries of pruned states")
+                .long_about(
+                    "Remove boot entries & kernels of states which no longer exist
+
+Only entries written by moss are considered, any other entries are left untouched.",
+                )
+                .arg(arg!(--"dry-run" "Show what would be removed without removing it").action(ArgAction::SetTrue)),
+        )
 }
 
 /// Handle status for now
-pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+pub fn handle(args: &ArgMatches, client: &mut Client) -> Result<(), Error> {
     match args.subcommand() {
-        Some(("status", args)) => status(args, installation),
-        Some(("sync", args)) => sync(args, installation),
+        Some(("status", args)) => status(args, client),
+        Some(("sync", args)) => sync(args, client),
+        Some(("cleanup", args)) => cleanup(args, client),
         _ => unreachable!(),
     }
 }
 
-fn status(_args: &ArgMatches, installation: Installation) -> Result<(), Error> {
-    let client = Client::new(environment::NAME, installation).map_err(Error::Client)?;
-
+fn status(_args: &ArgMatches, client: &Client) -> Result<(), Error> {
     client.print_boot_status()?;
 
     Ok(())
 }
 
-fn sync(_args: &ArgMatches, installation: Installation) -> Result<(), Error> {
-    let client = Client::new(environment::NAME, installation)?;
-
+fn sync(_args: &ArgMatches, client: &Client) -> Result<(), Error> {
     client.synchronize_boot()?;
 
     println!("Boot updated\n");
@@ -44,6 +51,23 @@ fn sync(_args: &ArgMatches, installation: Installation) -> Result<(), Error> {
     Ok(())
 }
 
+fn cleanup(args: &ArgMatches, client: &Client) -> Result<(), Error> {
+    let dry_run = args.get_flag("dry-run");
+    let paths = client.cleanup_boot(dry_run)?;
+
+    if paths.is_empty() {
+        println!("No stale boot entries");
+        return Ok(());
+    }
+
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+    for path in &paths {
+        println!("{verb} {}", path.display());
+    }
+
+    Ok(())
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("client")]
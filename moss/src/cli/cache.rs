@@ -1,48 +1,82 @@
 // SPDX-FileCopyrightText: 2025 AerynOS Developers
 // SPDX-License-Identifier: MPL-2.0
 
-use clap::{ArgMatches, Command};
-use moss::{Client, Installation, client, environment};
+use clap::{ArgAction, ArgMatches, Command, arg};
+use humansize::BINARY;
+use moss::{Client, client};
 use thiserror::Error;
+use tui::Styled;
 
 pub fn command() -> Command {
     Command::new("cache")
         .about("Manage cached data")
         .subcommand_required(true)
-        .subcommand(Command::new("prune").about("Prune cached artefacts").long_about(
-            "Prune cached artefacts
+        .subcommand(
+            Command::new("prune")
+                .about("Prune cached artefacts")
+                .long_about(
+                    "Prune cached artefacts
 
-This will remove all downloaded stones & unpacked asset data for packages not in any state or active repository.",
-        ))
+This will remove all downloaded stones & unpacked asset data for packages not in any state or active repository.
+
+Unreferenced assets still hardlinked from elsewhere are kept. With --repair, links left in the staging tree by an interrupted blit are removed first, so the assets only they link to are removed too.",
+                )
+                .arg(
+                    arg!(--"dry-run" "Report what would be removed without removing anything")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    arg!(--repair "Also remove what an interrupted blit left linked to unreferenced assets")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
 }
 
-pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+pub fn handle(args: &ArgMatches, client: &mut Client) -> Result<(), Error> {
     match args.subcommand() {
-        Some(("prune", args)) => handle_prune(args, installation),
+        Some(("prune", args)) => handle_prune(args, client),
         _ => unreachable!(),
     }
 }
 
-fn handle_prune(_args: &ArgMatches, installation: Installation) -> Result<(), Error> {
-    let client = Client::new(environment::NAME, installation).map_err(Error::SetupClient)?;
+fn handle_prune(args: &ArgMatches, client: &Client) -> Result<(), Error> {
+    let dry_run = args.get_flag("dry-run");
+    let repair = args.get_flag("repair");
 
-    let num_removed_files = client.prune_cache().map_err(Error::PruneCache)?;
+    let report = client.prune_cache(dry_run, repair).map_err(Error::PruneCache)?;
+    let num_files = report.removed_assets;
 
-    if num_removed_files > 0 {
-        let s = if num_removed_files > 1 { "s" } else { "" };
+    if num_files > 0 {
+        let s = if num_files > 1 { "s" } else { "" };
+        let action = if dry_run { "would be removed" } else { "removed" };
 
-        println!("{num_removed_files} file{s} removed");
+        println!(
+            "{num_files} file{s} {action} {}",
+            format!("({} reclaimed)", humansize::format_size(report.bytes, BINARY)).dim()
+        );
     } else {
         println!("No files to remove");
     }
 
+    if report.linked_assets > 0 {
+        println!(
+            "{}: {} unreferenced asset(s) kept as they're still linked from elsewhere",
+            "Warning".yellow(),
+            report.linked_assets
+        );
+    }
+    if report.unlinked_assets > 0 {
+        println!(
+            "{} asset(s) referenced by the layout database aren't linked into any state",
+            report.unlinked_assets
+        );
+    }
+
     Ok(())
 }
 
 #[derive(Debug, Error)]
 pub enum Error {
-    #[error("failed to setup moss client")]
-    SetupClient(#[source] client::Error),
     #[error("failed to prune cache")]
     PruneCache(#[source] client::Error),
 }
@@ -0,0 +1,242 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Dynamic completion of package names, called by the generated shell scripts
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use clap::{ArgMatches, Command, arg, value_parser};
+use clap_complete::{Generator, Shell, generate};
+use fs_err as fs;
+use moss::{Installation, client, environment, installation};
+use thiserror::Error;
+
+/// Most candidates printed, to keep completion responsive
+const MAX_CANDIDATES: usize = 200;
+
+/// Global options taking a value, which mustn't be mistaken for a subcommand
+const VALUE_OPTIONS: &[&str] = &["-D", "--directory", "--cache", "--log", "--format"];
+
+/// Parsed apart from the other subcommands, as clap's bash completions can't
+/// represent a subcommand named with `__`
+pub fn command() -> Command {
+    Command::new("__complete")
+        .about("Print package names completing the current word")
+        .arg(arg!(<SHELL> "shell requesting completions").value_parser(value_parser!(Shell)))
+        .arg(arg!(<CURRENT> "word being completed").allow_hyphen_values(true))
+        .arg(
+            arg!([WORDS] ... "preceding words of the command line, without `moss`")
+                .last(true)
+                .allow_hyphen_values(true),
+        )
+}
+
+/// Which packages complete an argument
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Context {
+    Installed,
+    Available,
+}
+
+/// Detect whether the preceding `words` of a command line expect package names
+pub fn context<'a>(words: impl IntoIterator<Item = &'a str>) -> Option<Context> {
+    let mut words = words.into_iter();
+
+    while let Some(word) = words.next() {
+        if word.starts_with('-') {
+            // Skip the value of a global option, unless given inline
+            if VALUE_OPTIONS.contains(&word) {
+                words.next();
+            }
+            continue;
+        }
+
+        // The first remaining word is the subcommand
+        return match word {
+            "install" | "it" => Some(Context::Available),
+            "remove" | "rm" | "info" => Some(Context::Installed),
+            _ => None,
+        };
+    }
+
+    None
+}
+
+/// Root directory given on the command line being completed
+fn root<'a>(words: impl IntoIterator<Item = &'a str>) -> PathBuf {
+    let mut words = words.into_iter();
+
+    while let Some(word) = words.next() {
+        if let Some(root) = word.strip_prefix("--directory=") {
+            return root.into();
+        }
+        if (word == "-D" || word == "--directory")
+            && let Some(root) = words.next()
+        {
+            return root.into();
+        }
+    }
+
+    "/".into()
+}
+
+/// Handle `moss __complete <SHELL> <CURRENT> [-- <WORDS>...]`, printing the
+/// candidates one per line for every [`Shell`]
+pub fn process(args: &[String]) -> Result<(), Error> {
+    let args = command().get_matches_from(args);
+    handle(&args)
+}
+
+fn handle(args: &ArgMatches) -> Result<(), Error> {
+    let current = args.get_one::<String>("CURRENT").unwrap();
+    let words = args
+        .get_many::<String>("WORDS")
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+
+    if current.starts_with('-') {
+        return Ok(());
+    }
+    let Some(context) = context(words.iter().copied()) else {
+        return Ok(());
+    };
+
+    let installation = Installation::open(root(words), None)?;
+    let client = client::Client::new(environment::NAME, installation)?;
+    for name in client.complete_package_names(current, context == Context::Installed, MAX_CANDIDATES)? {
+        println!("{name}");
+    }
+
+    Ok(())
+}
+
+/// Bash wrapper completing package names, falling back to the static completions
+const BASH_HOOK: &str = r#"
+_moss_packages() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    local packages
+    packages=$(moss __complete bash "$cur" -- "${COMP_WORDS[@]:1:COMP_CWORD-1}" 2>/dev/null)
+    if [[ -n "$packages" ]]; then
+        mapfile -t COMPREPLY < <(compgen -W "$packages" -- "$cur")
+        return 0
+    fi
+    _moss "$@"
+}
+
+complete -F _moss_packages -o nosort -o bashdefault -o default moss
+"#;
+
+/// Zsh wrapper completing package names, falling back to the static completions
+const ZSH_HOOK: &str = r#"
+_moss_packages() {
+    local -a packages
+    packages=(${(f)"$(moss __complete zsh "$PREFIX" -- ${words[2,CURRENT-1]} 2>/dev/null)"})
+    if (( ${#packages} )); then
+        compadd -a packages
+    else
+        _moss "$@"
+    fi
+}
+
+if [ "$funcstack[1]" = "_moss" ]; then
+    _moss_packages "$@"
+else
+    compdef _moss_packages moss
+fi
+"#;
+
+/// The dispatch generated by clap for zsh, replaced by [`ZSH_HOOK`]
+const ZSH_DISPATCH: &str = r#"
+if [ "$funcstack[1]" = "_moss" ]; then
+    _moss "$@"
+else
+    compdef _moss moss
+fi
+"#;
+
+/// Fish completion of package names, in addition to the static completions
+const FISH_HOOK: &str = r#"
+complete -c moss -a '(moss __complete fish (commandline -ct) -- (commandline -opc)[2..-1])'
+"#;
+
+/// Generate the completion script of `shell` for `cmd` into `dir`, including
+/// the completion of package names
+pub fn generate_script(shell: Shell, cmd: &mut Command, dir: &Path) -> io::Result<()> {
+    let mut script = vec![];
+    generate(shell, cmd, "moss", &mut script);
+    let script = String::from_utf8(script).map_err(io::Error::other)?;
+
+    fs::write(dir.join(shell.file_name("moss")), with_hook(shell, script))
+}
+
+fn with_hook(shell: Shell, script: String) -> String {
+    match shell {
+        Shell::Bash => script + BASH_HOOK,
+        // Call the wrapper when autoloaded, rather than clap's completions
+        Shell::Zsh => match script.rfind(ZSH_DISPATCH) {
+            Some(pos) => format!("{}{ZSH_HOOK}", &script[..pos]),
+            None => script + ZSH_HOOK,
+        },
+        Shell::Fish => script + FISH_HOOK,
+        _ => script,
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("client")]
+    Client(#[from] client::Error),
+
+    #[error("installation")]
+    Installation(#[from] installation::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detect_context() {
+        let context = |line: &str| context(line.split_whitespace());
+
+        assert_eq!(context("install"), Some(Context::Available));
+        assert_eq!(context("it nano"), Some(Context::Available));
+        assert_eq!(context("remove"), Some(Context::Installed));
+        assert_eq!(context("rm -y"), Some(Context::Installed));
+        assert_eq!(context("info --files"), Some(Context::Installed));
+
+        // Values of global options aren't subcommands
+        assert_eq!(context("-D install remove"), Some(Context::Installed));
+        assert_eq!(context("--directory=/ -y install"), Some(Context::Available));
+        assert_eq!(context("--format json info"), Some(Context::Installed));
+
+        assert_eq!(context("repo add"), None);
+        assert_eq!(context("-v"), None);
+        assert_eq!(context(""), None);
+    }
+
+    #[test]
+    fn root_directory() {
+        let root = |line: &str| root(line.split_whitespace());
+
+        assert_eq!(root("install"), Path::new("/"));
+        assert_eq!(root("-D /tmp/root remove"), Path::new("/tmp/root"));
+        assert_eq!(root("remove --directory=/tmp/root"), Path::new("/tmp/root"));
+    }
+
+    #[test]
+    fn zsh_hook() {
+        let mut cmd = super::super::command();
+        let mut script = vec![];
+        generate(Shell::Zsh, &mut cmd, "moss", &mut script);
+
+        let script = with_hook(Shell::Zsh, String::from_utf8(script).unwrap());
+        assert!(script.contains("_moss_packages \"$@\""));
+        assert!(!script.contains("compdef _moss moss"));
+    }
+}
@@ -0,0 +1,128 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+use std::{io, path::Path};
+
+use clap::{ArgMatches, Command};
+use humansize::BINARY;
+use moss::{Installation, db};
+use thiserror::Error;
+use tui::Styled;
+
+/// Databases of the installation, by file name
+const DATABASES: &[&str] = &["install", "state", "layout"];
+
+pub fn command() -> Command {
+    Command::new("db")
+        .about("Maintain the moss databases")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("check")
+                .about("Check the databases for corruption")
+                .long_about("Run sqlite's integrity & foreign key checks against each database"),
+        )
+        .subcommand(Command::new("vacuum").about("Rebuild the databases, reclaiming unused space"))
+}
+
+pub fn handle(args: &ArgMatches, installation: &Installation) -> Result<(), Error> {
+    match args.subcommand() {
+        Some(("check", _)) => check(installation),
+        Some(("vacuum", _)) => vacuum(installation),
+        _ => unreachable!(),
+    }
+}
+
+fn check(installation: &Installation) -> Result<(), Error> {
+    let mut failed = 0;
+
+    for name in DATABASES {
+        let path = installation.db_path(name);
+
+        let result = Database::open(name, &path).and_then(|db| db.check());
+        let size = humansize::format_size(installation.db_size(name)?, BINARY);
+
+        match result {
+            Ok(()) => println!("{} {name} {}", "»".green(), format!("({size})").dim()),
+            Err(db::Error::Corrupt { details, .. }) => {
+                failed += 1;
+                println!("{} {name} is corrupt {}", "×".red(), format!("({size})").dim());
+                for detail in details {
+                    println!("    {detail}");
+                }
+            }
+            Err(error) => {
+                failed += 1;
+                println!("{} {name} couldn't be checked: {error}", "×".red());
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(Error::Failed(failed));
+    }
+
+    Ok(())
+}
+
+fn vacuum(installation: &Installation) -> Result<(), Error> {
+    for name in DATABASES {
+        let path = installation.db_path(name);
+
+        let before = installation.db_size(name)?;
+        Database::open(name, &path)?.vacuum()?;
+        let after = installation.db_size(name)?;
+
+        println!(
+            "{} {name} {} -> {}",
+            "»".green(),
+            humansize::format_size(before, BINARY).dim(),
+            humansize::format_size(after, BINARY)
+        );
+    }
+
+    Ok(())
+}
+
+enum Database {
+    Meta(db::meta::Database),
+    State(db::state::Database),
+    Layout(db::layout::Database),
+}
+
+impl Database {
+    fn open(name: &str, path: &Path) -> Result<Self, db::Error> {
+        let url = path.to_str().unwrap_or_default();
+
+        Ok(match name {
+            "state" => Self::State(db::state::Database::new(url)?),
+            "layout" => Self::Layout(db::layout::Database::new(url)?),
+            _ => Self::Meta(db::meta::Database::new(url)?),
+        })
+    }
+
+    fn check(&self) -> Result<(), db::Error> {
+        match self {
+            Self::Meta(db) => db.check(),
+            Self::State(db) => db.check(),
+            Self::Layout(db) => db.check(),
+        }
+    }
+
+    fn vacuum(&self) -> Result<(), db::Error> {
+        match self {
+            Self::Meta(db) => db.vacuum(),
+            Self::State(db) => db.vacuum(),
+            Self::Layout(db) => db.vacuum(),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{0} database(s) failed the check")]
+    Failed(usize),
+    #[error("db")]
+    Db(#[from] db::Error),
+    #[error("io")]
+    Io(#[from] io::Error),
+}
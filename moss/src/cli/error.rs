@@ -0,0 +1,410 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Exit codes of errors, & their report with `--format json`
+//!
+//! Codes are those of [`ErrorCode`], mapped from each subcommand error with an
+//! exhaustive match so new variants have to be classified.
+
+use std::{collections::BTreeMap, error::Error as _};
+
+use moss::{
+    Coded, ErrorCode,
+    client::{self, install},
+    installation, repository,
+};
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use super::{
+    Error, boot, cache, complete, db, info, inspect, list, model, output, query, repo, rollback, search, search_file,
+    shell, state, stats, sync,
+};
+
+impl Coded for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Error::Boot(error) => error.code(),
+            Error::Cache(error) => error.code(),
+            Error::Complete(error) => error.code(),
+            Error::Client(error) => error.code(),
+            Error::Db(error) => error.code(),
+            Error::Index(error) => error.code(),
+            Error::Info(error) => error.code(),
+            Error::Install(error) => error.code(),
+            Error::List(error) => error.code(),
+            Error::Model(error) => error.code(),
+            Error::Inspect(error) => error.code(),
+            Error::Extract(error) => error.code(),
+            Error::Fetch(error) => error.code(),
+            Error::Query(error) => error.code(),
+            Error::Remove(error) => error.code(),
+            Error::Repo(error) => error.code(),
+            Error::Rollback(error) => error.code(),
+            Error::Search(error) => error.code(),
+            Error::SearchFile(error) => error.code(),
+            Error::State(error) => error.code(),
+            Error::Shell(error) => error.code(),
+            Error::Stats(error) => error.code(),
+            Error::Sync(error) => error.code(),
+            Error::Installation(error) => error.code(),
+            Error::Io(_) => ErrorCode::Generic,
+        }
+    }
+}
+
+impl Coded for boot::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            boot::Error::Client(error) => error.code(),
+        }
+    }
+}
+
+impl Coded for cache::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            cache::Error::PruneCache(error) => error.code(),
+        }
+    }
+}
+
+impl Coded for complete::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            complete::Error::Client(error) => error.code(),
+            complete::Error::Installation(error) => error.code(),
+        }
+    }
+}
+
+impl Coded for db::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            db::Error::Failed(_) => ErrorCode::Verification,
+            db::Error::Db(_) | db::Error::Io(_) => ErrorCode::Generic,
+        }
+    }
+}
+
+impl Coded for info::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            info::Error::NotFound(_) => ErrorCode::Resolution,
+            info::Error::Client(error) => error.code(),
+        }
+    }
+}
+
+impl Coded for inspect::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            inspect::Error::ValidationFailed => ErrorCode::Verification,
+            inspect::Error::IO(_) | inspect::Error::Format(_) => ErrorCode::Generic,
+        }
+    }
+}
+
+impl Coded for list::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            list::Error::NoneFound => ErrorCode::Resolution,
+            list::Error::Client(error) => error.code(),
+        }
+    }
+}
+
+impl Coded for model::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            model::Error::Client(error) => error.code(),
+            model::Error::Load(_) | model::Error::NotFound(_) => ErrorCode::Generic,
+        }
+    }
+}
+
+impl Coded for query::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            query::Error::Client(error) => error.code(),
+        }
+    }
+}
+
+impl Coded for repo::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            repo::Error::RepositoryManager(error) => error.code(),
+            repo::Error::NotFound(_) => ErrorCode::Resolution,
+            repo::Error::LoadSystemModel(_)
+            | repo::Error::SystemModelDisallowed { .. }
+            | repo::Error::ConfigNotDeleted(_) => ErrorCode::Generic,
+        }
+    }
+}
+
+impl Coded for rollback::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            rollback::Error::Client(error) => error.code(),
+            rollback::Error::State(_) | rollback::Error::Dialog(_) => ErrorCode::Generic,
+        }
+    }
+}
+
+impl Coded for search::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            search::Error::Client(error) => error.code(),
+            search::Error::ParseError(_) => ErrorCode::Generic,
+        }
+    }
+}
+
+impl Coded for search_file::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            search_file::Error::Client(error) => error.code(),
+            search_file::Error::DB(_) => ErrorCode::Generic,
+        }
+    }
+}
+
+impl Coded for shell::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            shell::Error::Line { source, .. } => match source.as_ref() {
+                shell::LineError::Command(error) => error.code(),
+                shell::LineError::Quoting
+                | shell::LineError::Parse(_)
+                | shell::LineError::SessionArgument(_)
+                | shell::LineError::Nested
+                | shell::LineError::Io(_) => ErrorCode::Generic,
+            },
+            shell::Error::Failed(_) | shell::Error::Prompt(_) | shell::Error::Io(_) => ErrorCode::Generic,
+        }
+    }
+}
+
+impl Coded for state::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            state::Error::Client(error) => error.code(),
+            state::Error::InvalidRange(_) => ErrorCode::Resolution,
+            state::Error::DB(_) | state::Error::Io(_) | state::Error::Manifest(_) | state::Error::NoActiveState => {
+                ErrorCode::Generic
+            }
+        }
+    }
+}
+
+impl Coded for sync::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            sync::Error::Client(error) => error.code(),
+            sync::Error::Pending(_) => ErrorCode::Pending,
+        }
+    }
+}
+
+impl Coded for stats::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            stats::Error::Client(error) => error.code(),
+        }
+    }
+}
+
+/// An error, as reported with `--format json`
+#[derive(Debug, Serialize)]
+pub struct Report {
+    /// The exit code, see [`ErrorCode`]
+    pub code: i32,
+    pub kind: ErrorCode,
+    /// The `chain` joined as printed without `--format json`
+    pub message: String,
+    /// Message of the error & each of its sources
+    pub chain: Vec<String>,
+    /// Fields of the errors of the chain, i.e. the `package` not found
+    pub context: BTreeMap<&'static str, Value>,
+}
+
+impl Report {
+    pub fn new(error: &Error) -> Self {
+        let mut chain = vec![error.to_string()];
+        let mut context = BTreeMap::new();
+
+        let mut source = error.source();
+        while let Some(error) = source {
+            chain.push(error.to_string());
+            add_context(error, &mut context);
+            source = error.source();
+        }
+
+        let code = error.code();
+        Self {
+            code: code.exit_code(),
+            kind: code,
+            message: chain.join(": "),
+            chain,
+            context,
+        }
+    }
+}
+
+/// Add the fields of `error` to `context`, keeping those of the outermost errors
+fn add_context(error: &(dyn std::error::Error + 'static), context: &mut BTreeMap<&'static str, Value>) {
+    let mut add = |key, value| {
+        context.entry(key).or_insert(value);
+    };
+
+    if let Some(error) = downcast::<client::Error>(error) {
+        match error {
+            client::Error::Locked { pid, since } => {
+                add("pid", json!(pid));
+                add("since", json!(since.to_rfc3339()));
+            }
+            client::Error::CacheFetch(_, package) | client::Error::CacheUnpack(_, package, _) => {
+                add("package", json!(package.to_string()));
+            }
+            client::Error::StateDoesntExist(id) | client::Error::StateAlreadyActive(id) => {
+                add("state", json!(i32::from(*id)));
+            }
+            client::Error::PackageNotSelected(package) | client::Error::NotInState(package, _) => {
+                add("package", json!(package.to_string()));
+            }
+            _ => {}
+        }
+    } else if let Some(install::Error::NoPackage(package, suggestions)) = downcast::<install::Error>(error) {
+        add("package", json!(package));
+        add("suggestions", json!(suggestions));
+    } else if let Some(installation::Error::Locked(Some(holder))) = downcast::<installation::Error>(error) {
+        add("pid", json!(holder.pid));
+        add("since", json!(holder.since.to_rfc3339()));
+        add("command", json!(holder.command));
+    } else if let Some(info::Error::NotFound(package)) = downcast::<info::Error>(error) {
+        add("package", json!(package));
+    } else if let Some(repository::manager::Error::OutdatedRepos(_, repos)) =
+        downcast::<repository::manager::Error>(error)
+    {
+        let ids = repos
+            .iter()
+            .map(|repo| repo.repository.id.to_string())
+            .collect::<Vec<_>>();
+        add("repositories", json!(ids));
+    }
+}
+
+/// `error` as `E`, whether boxed or not
+fn downcast<'a, E: std::error::Error + 'static>(error: &'a (dyn std::error::Error + 'static)) -> Option<&'a E> {
+    error
+        .downcast_ref::<E>()
+        .or_else(|| error.downcast_ref::<Box<E>>().map(AsRef::as_ref))
+}
+
+/// Whether `error` was reported by the command that failed, as are the changes
+/// pending a sync, & so isn't reported again
+pub fn reported(error: &Error) -> bool {
+    matches!(error, Error::Sync(sync::Error::Pending(_)))
+}
+
+/// Print `error` as a [`Report`] to stdout if `--format json` was given,
+/// returning whether it was
+pub fn report_json(error: &Error) -> bool {
+    if !output::Format::selected().is_json() {
+        return false;
+    }
+
+    output::print_json(&json!({ "error": Report::new(error) }));
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+
+    use moss::{package, state::Id};
+
+    use super::*;
+
+    #[test]
+    fn error_codes() {
+        let client = |error| client::Error::Install(Box::new(error));
+
+        for (error, code) in [
+            (Error::Io(io::Error::other("io")), ErrorCode::Generic),
+            (
+                Error::Install(client(install::Error::NoPackage("nan".to_owned(), vec![]))),
+                ErrorCode::Resolution,
+            ),
+            (
+                Error::Info(info::Error::NotFound("nano".to_owned())),
+                ErrorCode::Resolution,
+            ),
+            (
+                Error::Installation(installation::Error::Locked(None)),
+                ErrorCode::LockHeld,
+            ),
+            (
+                Error::Rollback(rollback::Error::Client(client::Error::Cancelled)),
+                ErrorCode::Cancelled,
+            ),
+            (
+                Error::Inspect(inspect::Error::ValidationFailed),
+                ErrorCode::Verification,
+            ),
+            (Error::Db(db::Error::Failed(1)), ErrorCode::Verification),
+            (
+                Error::State(state::Error::InvalidRange("3..".to_owned())),
+                ErrorCode::Resolution,
+            ),
+            (Error::List(list::Error::NoneFound), ErrorCode::Resolution),
+            (
+                Error::Cache(cache::Error::PruneCache(client::Error::NoActiveState)),
+                ErrorCode::Generic,
+            ),
+            (Error::Sync(sync::Error::Pending(3)), ErrorCode::Pending),
+            (
+                Error::Repo(repo::Error::NotFound(repository::Id::new("volatile"))),
+                ErrorCode::Resolution,
+            ),
+        ] {
+            assert_eq!(error.code(), code, "{error:?}");
+        }
+
+        // Pending changes were listed by the dry run, exiting with its code alone
+        assert!(reported(&Error::Sync(sync::Error::Pending(3))));
+        assert!(!reported(&Error::List(list::Error::NoneFound)));
+    }
+
+    #[test]
+    fn json_report() {
+        let error = Error::Install(client::Error::Install(Box::new(install::Error::NoPackage(
+            "nan".to_owned(),
+            vec!["nano".to_owned()],
+        ))));
+
+        let report = serde_json::to_value(Report::new(&error)).unwrap();
+        assert_eq!(
+            report,
+            json!({
+                "code": 2,
+                "kind": "resolution",
+                "message": "install: install: no package found: nan, did you mean nano?",
+                "chain": ["install", "install", "no package found: nan, did you mean nano?"],
+                "context": {
+                    "package": "nan",
+                    "suggestions": ["nano"],
+                },
+            })
+        );
+
+        let error = Error::Remove(client::Error::NotInState(
+            package::Name::from("nano".to_owned()),
+            Id::from(4),
+        ));
+        let report = Report::new(&error);
+        assert_eq!(report.code, 2);
+        assert_eq!(report.context["package"], json!("nano"));
+    }
+}
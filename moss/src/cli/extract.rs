@@ -3,20 +3,33 @@
 
 use std::path::PathBuf;
 
-use clap::{ArgMatches, Command, arg};
+use clap::{ArgAction, ArgMatches, Command, arg};
+use fnmatch::Pattern;
+use tui::Styled;
 
 pub use moss::client::extract::Error;
 
 pub fn command() -> Command {
     Command::new("extract")
         .about("Extract a `.stone` content to disk")
-        .long_about("For all valid content-bearing archives, extract to disk")
+        .long_about(
+            "For all valid content-bearing archives, extract to disk
+
+With `--path`, only files whose installed path matches one of the globs are extracted, i.e. `--path '/usr/share/defaults/**'`",
+        )
         .arg(arg!(<PATH> ... "files to extract").value_parser(clap::value_parser!(PathBuf)))
         .arg(
             arg!(-o --"output-dir" <OUTPUT_DIR> "directory to extract the stone(s) to")
+                .visible_alias("output")
                 .default_value(".")
                 .value_parser(clap::value_parser!(PathBuf)),
         )
+        .arg(
+            arg!(-p --path <GLOB> "only extract files whose installed path matches this glob")
+                .action(ArgAction::Append)
+                .value_parser(|s: &str| s.parse::<Pattern>()),
+        )
+        .arg(arg!(-l --list "print matching paths instead of extracting").action(ArgAction::SetTrue))
 }
 
 /// Handle the `extract` command
@@ -28,8 +41,32 @@ pub fn handle(args: &ArgMatches) -> Result<(), Error> {
         .collect::<Vec<_>>();
 
     let output_dir = args.get_one::<PathBuf>("output-dir").unwrap();
+    let patterns = args
+        .get_many::<Pattern>("path")
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect::<Vec<_>>();
 
-    moss::client::extract(paths, output_dir)?;
+    if args.get_flag("list") {
+        for path in paths {
+            for file in moss::client::extract::list(path, &patterns)? {
+                println!("{file}");
+            }
+        }
+    } else if !patterns.is_empty() {
+        for path in paths {
+            let extracted = moss::client::extract::extract_matching(path, &patterns, output_dir)?;
+            for file in &extracted {
+                println!("{} {}", "Extracted".green(), file.display());
+            }
+            if extracted.is_empty() {
+                println!("{path:?}: No matching files found");
+            }
+        }
+    } else {
+        moss::client::extract(paths, output_dir)?;
+    }
 
     Ok(())
 }
@@ -4,7 +4,7 @@
 use std::path::PathBuf;
 
 use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
-use moss::{Installation, client::Client, environment};
+use moss::client::Client;
 use tracing::instrument;
 
 pub use moss::client::Error;
@@ -31,13 +31,11 @@ struct Command {
 
 /// Handle execution of `moss fetch`
 #[instrument(skip_all)]
-pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+pub fn handle(args: &ArgMatches, client: &mut Client) -> Result<(), Error> {
     let Command { output_dir, packages } = Command::from_arg_matches(args).unwrap();
 
     let verbose = args.get_flag("verbose");
 
-    let mut client = Client::new(environment::NAME, installation)?;
-
     let packages = packages.iter().map(String::as_str).collect::<Vec<_>>();
 
     client.fetch(&packages, &output_dir, verbose)?;
@@ -2,7 +2,8 @@
 // SPDX-License-Identifier: MPL-2.0
 use std::path::PathBuf;
 
-use clap::{ArgMatches, Command, arg, value_parser};
+use clap::{ArgAction, ArgMatches, Command, arg, value_parser};
+use moss::client::index::Options;
 
 pub use moss::client::index::Error;
 
@@ -15,6 +16,21 @@ pub fn command() -> Command {
             arg!(-o --"output-dir" [output_dir] "directory to write the stone.index to (defaults to INDEX_DIR)")
                 .value_parser(value_parser!(PathBuf)),
         )
+        .arg(
+            arg!(--keep <N> "number of newest releases to index per package & architecture")
+                .default_value("1")
+                .value_parser(value_parser!(u64).range(1..)),
+        )
+        .arg(arg!(--prune "delete stones of releases excluded from the index").action(ArgAction::SetTrue))
+        .arg(
+            arg!(--incremental "only read the stones added or changed since the existing stone.index")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(--verify "hash the stones reused by --incremental, reindexing those which changed")
+                .action(ArgAction::SetTrue)
+                .requires("incremental"),
+        )
 }
 
 pub fn handle(args: &ArgMatches) -> Result<(), Error> {
@@ -24,7 +40,15 @@ pub fn handle(args: &ArgMatches) -> Result<(), Error> {
         .map(|dir| dir.canonicalize())
         .transpose()?;
 
-    moss::client::index(&index_dir, output_dir.as_deref())?;
+    let options = Options {
+        keep: *args.get_one::<u64>("keep").unwrap() as usize,
+        prune: args.get_flag("prune"),
+        yes: *args.get_one::<bool>("yes").unwrap(),
+        incremental: args.get_flag("incremental"),
+        verify: args.get_flag("verify"),
+    };
+
+    moss::client::index(&index_dir, output_dir.as_deref(), &options)?;
 
     Ok(())
 }
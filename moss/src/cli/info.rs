@@ -1,18 +1,23 @@
 // SPDX-FileCopyrightText: 2023 AerynOS Developers
 // SPDX-License-Identifier: MPL-2.0
 
+use std::fmt::{self, Write};
+
+use chrono::DateTime;
 use clap::{ArgMatches, Command, arg};
-use itertools::Itertools;
+use humansize::BINARY;
 use moss::{
-    Installation, Package, Provider,
+    Provider,
     client::{self, Client},
-    environment, package,
+    package,
 };
 use stone::StonePayloadLayoutFile;
 use thiserror::Error;
 use tui::{Styled, TermSize};
 use vfs::tree::BlitFile;
 
+use super::output;
+
 const COLUMN_WIDTH: usize = 20;
 
 pub fn command() -> Command {
@@ -21,10 +26,11 @@ pub fn command() -> Command {
         .long_about("List detailed package information from all available sources")
         .arg(arg!(<NAME> ... "Packages to query").value_parser(clap::value_parser!(String)))
         .arg(arg!(-f --files ... "Show files provided by package").action(clap::ArgAction::SetTrue))
+        .arg(arg!(--tree "Show the transitive dependency tree").action(clap::ArgAction::SetTrue))
 }
 
 /// For all arguments, try to match a package
-pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+pub fn handle(args: &ArgMatches, client: &mut Client) -> Result<(), Error> {
     let pkgs = args
         .get_many::<String>("NAME")
         .into_iter()
@@ -32,8 +38,12 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
         .cloned()
         .collect::<Vec<_>>();
     let show_files = args.get_flag("files");
+    let show_tree = args.get_flag("tree");
+    let format = output::Format::get(args);
 
-    let client = Client::new(environment::NAME, installation)?;
+    let protected = client.protected_packages();
+
+    let mut infos = vec![];
 
     for pkg in pkgs {
         let lookup = Provider::from_name(&pkg).unwrap();
@@ -44,29 +54,55 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
         }
 
         for candidate in resolved {
-            print_package(&candidate);
+            let files = if candidate.flags.installed && show_files {
+                Some(files(client.vfs([&candidate.id])?))
+            } else {
+                None
+            };
+            let tree = show_tree.then(|| output::DependencyTree::new(&client.dependency_tree(&candidate)));
+
+            let info = output::PackageInfo {
+                files,
+                tree,
+                protected: client::protected::is_protected(&protected, &candidate),
+                ..output::PackageInfo::new(&client.package_details(&candidate)?)
+            };
+
+            if format.is_json() {
+                infos.push(info);
+                continue;
+            }
 
-            if candidate.flags.installed && show_files {
-                let vfs = client.vfs([&candidate.id])?;
-                print_files(vfs);
+            let width = TermSize::default().width;
+            print!("{}", format_package(&info, width));
+            if let Some(files) = &info.files {
+                print!("{}", format_files(files));
+            }
+            if let Some(tree) = &info.tree {
+                println!();
+                print!("{}", format_tree(tree));
             }
             println!();
         }
     }
 
+    if format.is_json() {
+        output::print_json(&infos);
+    }
+
     Ok(())
 }
 
-/// Print the title for each metadata section
-fn print_titled(title: &'static str) {
-    let display_width = COLUMN_WIDTH - title.len();
-    print!("{}{:display_width$} ", title.bold(), " ");
+/// Title of each metadata section
+fn titled(title: &str) -> String {
+    let display_width = COLUMN_WIDTH.saturating_sub(title.len());
+    format!("{}{:display_width$} ", title.bold(), " ")
 }
 
-/// Print paragraph with breaks
-fn print_paragraph(p: &str) {
-    let term_width = TermSize::default().width;
-    let available_width = term_width - COLUMN_WIDTH;
+/// Paragraphs wrapped to the terminal `width`, indented past the titles
+fn paragraph(p: &str, width: usize) -> String {
+    let available_width = width.saturating_sub(COLUMN_WIDTH);
+    let mut out = String::new();
 
     // Split into paragraphs by empty lines
     let paragraphs = p.lines().collect::<Vec<_>>();
@@ -75,10 +111,17 @@ fn print_paragraph(p: &str) {
         .filter(|para| !para.is_empty());
 
     let mut first_paragraph = true;
+    let push_line = |out: &mut String, line: &str, first: bool| {
+        if first {
+            let _ = writeln!(out, "{}", line.dim());
+        } else {
+            let _ = writeln!(out, "{:COLUMN_WIDTH$} {}", " ", line.dim());
+        }
+    };
 
     for paragraph in paragraphs {
         if !first_paragraph {
-            println!(); // Add blank line between paragraphs
+            out.push('\n'); // Add blank line between paragraphs
         }
 
         // Join the lines and split into words for wrapping
@@ -87,112 +130,193 @@ fn print_paragraph(p: &str) {
         let mut first_line = true;
 
         for word in text.split_whitespace() {
-            if current_line.len() + word.len() < available_width {
+            if current_line.is_empty() || current_line.len() + word.len() < available_width {
                 if !current_line.is_empty() {
                     current_line.push(' ');
                 }
                 current_line.push_str(word);
             } else {
-                // Print current line
-                if first_line && first_paragraph {
-                    println!("{}", current_line.dim());
-                    first_line = false;
-                } else {
-                    println!("{:COLUMN_WIDTH$} {}", " ", current_line.dim());
-                }
+                push_line(&mut out, &current_line, first_line && first_paragraph);
+                first_line = false;
                 current_line = word.to_owned();
             }
         }
 
         // Print any remaining content
         if !current_line.is_empty() {
-            if first_line && first_paragraph {
-                println!("{}", current_line.dim());
-            } else {
-                println!("{:COLUMN_WIDTH$} {}", " ", current_line.dim());
-            }
+            push_line(&mut out, &current_line, first_line && first_paragraph);
         }
 
         first_paragraph = false;
     }
+
+    if out.is_empty() {
+        out.push('\n');
+    }
+    out
 }
 
-fn print_list<T>(items: impl IntoIterator<Item = T>)
+fn list<T>(items: impl IntoIterator<Item = T>) -> String
 where
-    T: ToString,
+    T: fmt::Display,
 {
+    let mut out = String::new();
     for (idx, item) in items.into_iter().enumerate() {
-        match idx {
-            0 => println!("• {}", item.to_string()),
-            _ => println!("{:COLUMN_WIDTH$} • {}", " ", item.to_string()),
-        }
+        let _ = match idx {
+            0 => writeln!(out, "• {item}"),
+            _ => writeln!(out, "{:COLUMN_WIDTH$} • {item}", " "),
+        };
     }
+    out
 }
 
-/// Pretty print a package
-fn print_package(pkg: &Package) {
-    print_titled("Name");
-    println!("{}", pkg.meta.name);
-    print_titled("Status");
-    if pkg.flags.installed {
-        println!("Installed");
+/// Render a package for a terminal `width` columns wide
+fn format_package(info: &output::PackageInfo, width: usize) -> String {
+    let mut out = String::new();
+    let mut field = |title: &str, value: &dyn fmt::Display| {
+        let _ = writeln!(out, "{}{value}", titled(title));
+    };
+
+    field("Name", &info.package.name);
+    let marker = if info.protected {
+        format!(" {}", "(protected)".yellow())
     } else {
-        println!("Not installed");
-    }
-    print_titled("Version");
-    println!("{}", pkg.meta.version_identifier);
-    print_titled("Release number");
-    println!("{}", pkg.meta.source_release);
-    if pkg.meta.build_release > 1 {
-        print_titled("Build Release");
-        println!("{}", pkg.meta.build_release);
-    }
-    print_titled("Homepage");
-    println!("{}", pkg.meta.homepage);
-    print_titled("Summary");
-    println!("{}", pkg.meta.summary);
-    print_titled("Description");
-    print_paragraph(&pkg.meta.description);
-    if !pkg.meta.dependencies.is_empty() {
-        println!();
-        print_titled("Dependencies");
-        print_list(pkg.meta.dependencies.iter().sorted());
-    }
-    if !pkg.meta.providers.is_empty() {
-        println!();
-        print_titled("Providers");
-        print_list(pkg.meta.providers.iter().sorted());
+        String::new()
+    };
+    let status = if info.package.installed {
+        "Installed"
+    } else {
+        "Not installed"
+    };
+    field("Status", &format_args!("{status}{marker}"));
+    field("Version", &info.package.version);
+    field("Release number", &info.package.release);
+    if info.build_release > 1 {
+        field("Build Release", &info.build_release);
     }
-}
+    if let Some(origin) = &info.origin {
+        field("Repository", origin);
+    }
+    if !info.licenses.is_empty() {
+        field("Licenses", &info.licenses.join(", "));
+    }
+    field("Homepage", &info.homepage);
+    if let Some(size) = info.download_size {
+        field("Download size", &humansize::format_size(size, BINARY));
+    }
+    if let Some(size) = info.installed_size {
+        field("Installed size", &humansize::format_size(size, BINARY));
+    }
+    let provenance = &info.provenance;
+    if let Some(builder) = &provenance.builder {
+        field("Built by", builder);
+    }
+    if let Some(host) = &provenance.host {
+        field("Build host", host);
+    }
+    if let Some(time) = provenance
+        .time
+        .and_then(|time| DateTime::from_timestamp(time as i64, 0))
+    {
+        field("Build time", &time.format("%Y-%m-%d %H:%M:%S UTC"));
+    }
+    if let Some(profile) = &provenance.profile {
+        field("Build profile", profile);
+    }
+    field("Summary", &info.package.summary);
 
-fn print_files(vfs: vfs::Tree<client::PendingFile>) {
-    let files = vfs
-        .iter()
-        .filter_map(|file| {
-            if file.kind().is_directory() {
-                return None;
+    out.push_str(&titled("Description"));
+    out.push_str(&paragraph(&info.description, width));
+
+    if !info.rundeps.is_empty() {
+        let dependencies = info.rundeps.iter().map(|dependency| match &dependency.package {
+            Some(package) => format!("{} → {package}", dependency.dependency),
+            None => format!("{} {}", dependency.dependency, "(unresolved)".red()),
+        });
+        out.push('\n');
+        out.push_str(&titled("Dependencies"));
+        out.push_str(&list(dependencies));
+    }
+    if !info.providers_by_kind.is_empty() {
+        out.push('\n');
+        out.push_str(&titled("Providers"));
+        for (idx, (kind, names)) in info.providers_by_kind.iter().enumerate() {
+            if idx > 0 {
+                let _ = write!(out, "{:COLUMN_WIDTH$} ", " ");
             }
+            let _ = writeln!(out, "{}", kind.as_str().dim());
+            for name in names {
+                let _ = writeln!(out, "{:COLUMN_WIDTH$}   • {name}", " ");
+            }
+        }
+    }
+    if !info.conflicts.is_empty() {
+        out.push('\n');
+        out.push_str(&titled("Conflicts"));
+        out.push_str(&list(&info.conflicts));
+    }
+
+    out
+}
+
+/// Render the dependency tree, marking packages whose dependencies
+/// are listed further up with `(*)`
+fn format_tree(tree: &output::DependencyTree) -> String {
+    fn walk(out: &mut String, node: &output::DependencyTree, prefix: &str) {
+        for (idx, dependency) in node.dependencies.iter().enumerate() {
+            let last = idx + 1 == node.dependencies.len();
+            let (branch, indent) = if last { ("└─ ", "   ") } else { ("├─ ", "│  ") };
+            let marker = if dependency.repeated {
+                format!(" {}", "(*)".dim())
+            } else {
+                String::new()
+            };
+
+            let _ = writeln!(out, "{prefix}{branch}{}{marker}", dependency.name);
+            walk(out, dependency, &format!("{prefix}{indent}"));
+        }
+    }
 
-            let path = file.path();
-            let meta = match &file.layout.file {
-                StonePayloadLayoutFile::Regular(hash, _) => Some(format!(" ({hash:2x})")),
-                StonePayloadLayoutFile::Symlink(source, _) => Some(format!(" -> {source}")),
-                _ => None,
+    let mut out = format!("{}\n", tree.name.as_str().bold());
+    walk(&mut out, tree, "");
+    out
+}
+
+/// Collect the non-directory files of a package
+fn files(vfs: vfs::Tree<client::PendingFile>) -> Vec<output::File> {
+    vfs.iter()
+        .filter(|file| !file.kind().is_directory())
+        .map(|file| {
+            let (hash, target) = match &file.layout.file {
+                StonePayloadLayoutFile::Regular(hash, _) => (Some(format!("{hash:2x}")), None),
+                StonePayloadLayoutFile::Symlink(source, _) => (None, Some(source.to_string())),
+                _ => (None, None),
             };
 
-            Some((path, meta))
+            output::File {
+                path: file.path().to_string(),
+                hash,
+                target,
+            }
         })
-        .collect::<Vec<_>>();
+        .collect()
+}
 
+fn format_files(files: &[output::File]) -> String {
     if files.is_empty() {
-        return;
+        return String::new();
     }
 
-    print_titled("Files");
-    println!();
-    for (path, meta) in files {
-        println!("  {path}{}", meta.unwrap_or_default().dim());
+    let mut out = format!("{}\n", titled("Files"));
+    for file in files {
+        let meta = match (&file.hash, &file.target) {
+            (Some(hash), _) => format!(" ({hash})"),
+            (None, Some(target)) => format!(" -> {target}"),
+            (None, None) => String::new(),
+        };
+        let _ = writeln!(out, "  {}{}", file.path, meta.dim());
     }
+    out
 }
 
 #[derive(Debug, Error)]
@@ -202,3 +326,134 @@ pub enum Error {
     #[error("client")]
     Client(#[from] client::Error),
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn info() -> output::PackageInfo {
+        output::PackageInfo {
+            package: output::Package {
+                name: "nano".to_owned(),
+                version: "8.2".to_owned(),
+                release: 14,
+                repo: Some("volatile".to_owned()),
+                installed: true,
+                explicit: true,
+                summary: "Small & friendly text editor".to_owned(),
+                sync: None,
+                size: None,
+            },
+            origin: Some("volatile".to_owned()),
+            build_release: 1,
+            homepage: "https://nano-editor.org".to_owned(),
+            description: "GNU nano is a small and friendly text editor.\n\nIt supports syntax highlighting.".to_owned(),
+            licenses: vec!["GPL-3.0-or-later".to_owned()],
+            download_size: Some(1024 * 512),
+            installed_size: Some(1024 * 1024 * 3),
+            dependencies: vec!["binary(sh)".to_owned(), "soname(libncursesw.so.6(x86_64))".to_owned()],
+            rundeps: vec![
+                output::Dependency {
+                    dependency: "binary(sh)".to_owned(),
+                    package: None,
+                },
+                output::Dependency {
+                    dependency: "soname(libncursesw.so.6(x86_64))".to_owned(),
+                    package: Some("ncurses".to_owned()),
+                },
+            ],
+            providers: vec![
+                "binary(nano)".to_owned(),
+                "binary(rnano)".to_owned(),
+                "name(nano)".to_owned(),
+            ],
+            providers_by_kind: BTreeMap::from([
+                ("binary".to_owned(), vec!["nano".to_owned(), "rnano".to_owned()]),
+                ("name".to_owned(), vec!["nano".to_owned()]),
+            ]),
+            conflicts: vec![],
+            protected: true,
+            files: None,
+            tree: None,
+            provenance: package::Provenance {
+                builder: Some("boulder 1.0.0".to_owned()),
+                host: Some("x86_64".to_owned()),
+                time: Some(1_700_000_000),
+                profile: Some("default-x86_64".to_owned()),
+            },
+        }
+    }
+
+    #[test]
+    fn package() {
+        assert_eq!(
+            format_package(&info(), 60),
+            "\
+Name                 nano
+Status               Installed (protected)
+Version              8.2
+Release number       14
+Repository           volatile
+Licenses             GPL-3.0-or-later
+Homepage             https://nano-editor.org
+Download size        512 KiB
+Installed size       3 MiB
+Built by             boulder 1.0.0
+Build host           x86_64
+Build time           2023-11-14 22:13:20 UTC
+Build profile        default-x86_64
+Summary              Small & friendly text editor
+Description          GNU nano is a small and friendly text
+                     editor.
+
+                     It supports syntax highlighting.
+
+Dependencies         • binary(sh) (unresolved)
+                     • soname(libncursesw.so.6(x86_64)) → ncurses
+
+Providers            binary
+                       • nano
+                       • rnano
+                     name
+                       • nano
+"
+        );
+    }
+
+    #[test]
+    fn tree() {
+        let node = |name: &str, repeated, dependencies| output::DependencyTree {
+            name: name.to_owned(),
+            repeated,
+            dependencies,
+        };
+        let tree = node(
+            "nano",
+            false,
+            vec![
+                node("glibc", false, vec![]),
+                node(
+                    "ncurses",
+                    false,
+                    vec![node("glibc", true, vec![]), node("libgcc", false, vec![])],
+                ),
+                node("zlib", false, vec![node("glibc", true, vec![])]),
+            ],
+        );
+
+        assert_eq!(
+            format_tree(&tree),
+            "\
+nano
+├─ glibc
+├─ ncurses
+│  ├─ glibc (*)
+│  └─ libgcc
+└─ zlib
+   └─ glibc (*)
+"
+        );
+    }
+}
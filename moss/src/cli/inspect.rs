@@ -1,6 +1,7 @@
 // SPDX-FileCopyrightText: 2023 AerynOS Developers
 // SPDX-License-Identifier: MPL-2.0
 
+use chrono::DateTime;
 use clap::{ArgMatches, Command, arg};
 use fs_err::File;
 use std::io::{Read, Seek, sink};
@@ -126,6 +127,14 @@ fn handle_detailed(paths: Vec<PathBuf>) -> Result<(), Error> {
                             StonePayloadMetaPrimitive::Int64(i) => {
                                 println!("{name:COLUMN_WIDTH$} : {i}");
                             }
+                            StonePayloadMetaPrimitive::Uint64(i) if record.tag == StonePayloadMetaTag::BuildTime => {
+                                match DateTime::from_timestamp(*i as i64, 0) {
+                                    Some(time) => {
+                                        println!("{name:COLUMN_WIDTH$} : {}", time.format("%Y-%m-%d %H:%M:%S UTC"));
+                                    }
+                                    None => println!("{name:COLUMN_WIDTH$} : {i}"),
+                                }
+                            }
                             StonePayloadMetaPrimitive::Uint64(i) => {
                                 println!("{name:COLUMN_WIDTH$} : {i}");
                             }
@@ -5,7 +5,7 @@ use std::path::PathBuf;
 
 use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
 
-use moss::{Installation, client::Client, environment};
+use moss::{client::Client, environment, manifest::Manifest};
 use tracing::instrument;
 
 pub use moss::client::Error;
@@ -23,8 +23,27 @@ pub fn command() -> clap::Command {
 )]
 pub struct Command {
     /// Packages to install
+    #[arg(conflicts_with = "from_manifest")]
     packages: Vec<String>,
 
+    /// Install the explicit packages of a manifest from `moss state export`
+    ///
+    /// Pinned versions which are no longer available are replaced with the
+    /// latest version, with a warning
+    #[arg(value_name = "file", long)]
+    from_manifest: Option<PathBuf>,
+
+    /// Fail if any pinned manifest version is no longer available
+    #[arg(long, requires = "from_manifest")]
+    strict: bool,
+
+    /// Package to install when several satisfy a requested provider
+    ///
+    /// Without it, moss asks which to install, or picks the highest
+    /// priority candidate with `--yes`
+    #[arg(value_name = "name", long)]
+    choose: Vec<String>,
+
     /// Simulate the operation (dry-run)
     #[arg(long)]
     dry_run: bool,
@@ -38,22 +57,30 @@ pub struct Command {
 
 /// Handle execution of `moss install`
 #[instrument(skip_all)]
-pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+pub fn handle(args: &ArgMatches, client: &mut Client) -> Result<(), Error> {
     let command = Command::from_arg_matches(args).expect("validated by clap");
 
     let pkgs = command.packages.iter().map(String::as_str).collect::<Vec<_>>();
     let yes = *args.get_one::<bool>("yes").unwrap();
     let simulate = command.dry_run;
 
-    // Grab a client for the root
-    let mut client = Client::new(environment::NAME, installation)?;
+    // Blit to the target with a dedicated ephemeral client, if one was provided
+    let mut ephemeral;
+    let client = match command.blit_target {
+        Some(blit_target) => {
+            ephemeral = Client::new(environment::NAME, client.installation().clone())?.ephemeral(blit_target)?;
+            &mut ephemeral
+        }
+        None => client,
+    };
 
-    // Make ephemeral if a blit target was provided
-    if let Some(blit_target) = command.blit_target {
-        client = client.ephemeral(blit_target)?;
+    if let Some(path) = &command.from_manifest {
+        let manifest = Manifest::load(path)?;
+        client.install_manifest(&manifest, command.strict, yes, simulate)?;
+    } else {
+        let choices = command.choose.iter().map(String::as_str).collect::<Vec<_>>();
+        client.install(&pkgs, &choices, yes, simulate)?;
     }
 
-    client.install(&pkgs, yes, simulate)?;
-
     Ok(())
 }
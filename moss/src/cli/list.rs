@@ -1,23 +1,33 @@
 // SPDX-FileCopyrightText: 2023 AerynOS Developers
 // SPDX-License-Identifier: MPL-2.0
 
-use clap::{ArgMatches, Command, arg};
+use std::{cmp::Reverse, collections::BTreeMap};
+
+use clap::{ArgMatches, Command, ValueEnum, arg};
+use humansize::BINARY;
 use itertools::Itertools;
 use thiserror::Error;
 
 use moss::{
-    Installation,
     client::{self, Client},
-    environment,
     package::Flags,
+    repository,
 };
 use tui::Styled;
 
+use super::output;
+
 pub fn command() -> Command {
     Command::new("list")
         .about("List packages")
         .long_about("List packages according to a filter")
         .subcommand_required(true)
+        .arg(
+            arg!(--sort <KEY> "Order of the listed packages")
+                .value_parser(clap::value_parser!(Sort))
+                .default_value("name")
+                .global(true),
+        )
         .subcommand(
             Command::new("installed")
                 .about("List all installed packages")
@@ -27,7 +37,8 @@ pub fn command() -> Command {
         .subcommand(
             Command::new("available")
                 .about("List all available packages")
-                .visible_alias("la"),
+                .visible_alias("la")
+                .arg(arg!(--repo <ID> "List packages of repository ID only")),
         )
         .subcommand(
             Command::new("sync")
@@ -42,10 +53,26 @@ enum Sync {
     Upgrades,
 }
 
+/// Order of the listed packages, selected with `--sort`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Sort {
+    /// By name
+    #[default]
+    Name,
+    /// Largest first, by download size when listing available packages, otherwise by installed size
+    Size,
+    /// By repository, then name, with packages of no repository last
+    Repo,
+}
+
 /// Handle listing by filter
-pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+pub fn handle(args: &ArgMatches, client: &mut Client) -> Result<(), Error> {
+    let mut repo = None;
     let (filter_flags, sync) = match args.subcommand() {
-        Some(("available", _)) => (Flags::new().with_available(), None),
+        Some(("available", args)) => {
+            repo = args.get_one::<String>("repo").map(|id| repository::Id::new(id));
+            (Flags::new().with_available(), None)
+        }
         Some(("installed", args)) => {
             let flags = if *args.get_one::<bool>("explicit").unwrap() {
                 Flags::new().with_installed().with_explicit()
@@ -66,8 +93,14 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
         _ => unreachable!(),
     };
 
-    // Grab a client for the target, enumerate packages
-    let client = Client::new(environment::NAME, installation)?;
+    let format = output::Format::get(args);
+    let sort = args.get_one::<Sort>("sort").copied().unwrap_or_default();
+
+    // Enumerate packages, of one repository if requested
+    if let Some(repo) = &repo {
+        client.restrict_to_repository(repo)?;
+    }
+
     let pkgs = client.list_packages(filter_flags).collect::<Vec<_>>();
 
     let sync_available = if sync.is_some() {
@@ -76,10 +109,18 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
         vec![]
     };
 
-    if pkgs.is_empty() {
+    if pkgs.is_empty() && !format.is_json() {
         return Err(Error::NoneFound);
     }
 
+    // One measure for all listed packages, as installed & download sizes aren't comparable
+    let by_download_size = filter_flags.available;
+    let installed_sizes = if sort == Sort::Size && !by_download_size {
+        client.installed_sizes(pkgs.iter().map(|p| &p.id))?
+    } else {
+        BTreeMap::new()
+    };
+
     // map to renderable state
     let mut set = pkgs
         .into_iter()
@@ -97,63 +138,73 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
                         u.meta.source_release != p.meta.source_release
                     }
                 })
-                .map(|u| Revision {
-                    version: u.meta.version_identifier.clone(),
-                    release: u.meta.source_release.to_string(),
-                });
-
-            Format {
-                name: p.meta.name.to_string(),
-                revision: Revision {
-                    version: p.meta.version_identifier,
-                    release: p.meta.source_release.to_string(),
-                },
-                summary: p.meta.summary,
-                explicit: if filter_flags == Flags::new().with_installed() {
-                    p.flags.explicit
+                .map(output::Revision::new);
+
+            // Repository lookups are only needed for machine-readable output & sorting
+            let repo = if format.is_json() || sort == Sort::Repo {
+                client.package_repository(&p.id)
+            } else {
+                None
+            };
+
+            let size = (sort == Sort::Size).then(|| {
+                if by_download_size {
+                    p.meta.download_size.unwrap_or_default()
                 } else {
-                    true
-                },
+                    installed_sizes.get(&p.id).copied().unwrap_or_default()
+                }
+            });
+
+            output::Package {
                 sync,
+                size,
+                ..output::Package::new(&p, repo)
             }
         })
         .filter(|item| if sync.is_some() { item.sync.is_some() } else { true })
         .collect_vec();
 
-    // Thanks to priorities, first in list is the winning candidate in list available.
-    // Therefore sort by name and dedupe is safe as we mask the lower priority items out.
-    set.sort_by_key(|s| s.name.clone());
-    set.dedup_by_key(|s| s.name.clone());
+    sort_packages(&mut set, sort);
+
+    if format.is_json() {
+        output::print_json(&set);
+        return Ok(());
+    }
 
     // Grab maximum length
-    let max_length = set.iter().map(Format::size).max().unwrap_or_default() + 2;
+    let max_length = set.iter().map(size).max().unwrap_or_default() + 2;
 
     // render
     for item in set {
-        let width = max_length - item.size() + 2;
-        let name = if item.explicit {
+        let width = max_length - size(&item) + 2;
+        // Only installed listings distinguish explicit packages
+        let name = if item.explicit || filter_flags != Flags::new().with_installed() {
             item.name.bold()
         } else {
             item.name.dim()
         };
         print!("{name} {:width$} ", " ");
 
-        let print_revision = |rev: Revision, is_sync| {
-            let version = if is_sync {
-                rev.version.green()
-            } else {
-                rev.version.magenta()
-            };
-            print!("{version}-{}", rev.release.dim());
+        let print_revision = |version: String, release: u64, is_sync| {
+            let version = if is_sync { version.green() } else { version.magenta() };
+            print!("{version}-{}", release.to_string().dim());
         };
 
         // Print revision
-        print_revision(item.revision, false);
+        print_revision(item.version, item.release, false);
 
         // Print sync version
         if let Some(sync) = item.sync {
             print!(" => ");
-            print_revision(sync, true);
+            print_revision(sync.version, sync.release, true);
+        }
+
+        // Print what the packages are sorted by, if not shown already
+        if let Some(size) = item.size {
+            print!(" {}", format!("({})", humansize::format_size(size, BINARY)).dim());
+        }
+        if sort == Sort::Repo {
+            print!(" {}", format!("[{}]", item.repo.as_deref().unwrap_or("-")).dim());
         }
 
         println!(" - {}", item.summary);
@@ -162,31 +213,31 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
     Ok(())
 }
 
-#[derive(Debug)]
-struct Format {
-    name: String,
-    summary: String,
-    revision: Revision,
-    explicit: bool,
-    sync: Option<Revision>,
-}
+/// Sort `packages` by `sort`, keeping only the first package of each name
+fn sort_packages(packages: &mut Vec<output::Package>, sort: Sort) {
+    // Thanks to priorities, first in list is the winning candidate in list available.
+    // Therefore a stable sort by name and dedupe is safe as we mask the lower priority items out.
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+    packages.dedup_by(|a, b| a.name == b.name);
 
-impl Format {
-    fn size(&self) -> usize {
-        self.name.len() + self.revision.size() + self.sync.as_ref().map(Revision::size).unwrap_or_default()
+    // Stable, so ties stay sorted by name
+    match sort {
+        Sort::Name => {}
+        Sort::Size => packages.sort_by_key(|package| Reverse(package.size)),
+        Sort::Repo => packages.sort_by(|a, b| (a.repo.is_none(), &a.repo).cmp(&(b.repo.is_none(), &b.repo))),
     }
 }
 
-#[derive(Debug)]
-struct Revision {
-    version: String,
-    release: String,
-}
+fn size(item: &output::Package) -> usize {
+    let revision = |version: &str, release: u64| version.len() + release.to_string().len();
 
-impl Revision {
-    fn size(&self) -> usize {
-        self.version.len() + self.release.len()
-    }
+    item.name.len()
+        + revision(&item.version, item.release)
+        + item
+            .sync
+            .as_ref()
+            .map(|sync| revision(&sync.version, sync.release))
+            .unwrap_or_default()
 }
 
 #[derive(Debug, Error)]
@@ -196,3 +247,72 @@ pub enum Error {
     #[error("client")]
     Client(#[from] client::Error),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn package(name: &str, repo: Option<&str>, size: Option<u64>) -> output::Package {
+        output::Package {
+            name: name.to_owned(),
+            version: "1.0".to_owned(),
+            release: 1,
+            repo: repo.map(str::to_owned),
+            installed: false,
+            explicit: false,
+            summary: String::new(),
+            sync: None,
+            size,
+        }
+    }
+
+    fn sorted(sort: Sort) -> Vec<output::Package> {
+        // Listed by priority, as the registry does, with `bash` offered twice
+        let mut packages = vec![
+            package("nano", Some("volatile"), Some(2048)),
+            package("bash", Some("local"), Some(1024)),
+            package("zlib", None, Some(4096)),
+            package("bash", Some("volatile"), Some(8192)),
+            package("curl", Some("core"), Some(1024)),
+        ];
+        sort_packages(&mut packages, sort);
+        packages
+    }
+
+    fn names(sorted: &[output::Package]) -> Vec<&str> {
+        sorted.iter().map(|p| p.name.as_str()).collect()
+    }
+
+    #[test]
+    fn sort_by_name() {
+        let sorted = sorted(Sort::Name);
+        assert_eq!(names(&sorted), ["bash", "curl", "nano", "zlib"]);
+        assert!(sorted.is_sorted_by_key(|p| &p.name));
+        // The highest priority candidate is kept
+        assert_eq!(sorted[0].repo.as_deref(), Some("local"));
+    }
+
+    #[test]
+    fn sort_by_size() {
+        let sorted = sorted(Sort::Size);
+        // Largest first, ties by name
+        assert_eq!(names(&sorted), ["zlib", "nano", "bash", "curl"]);
+        assert_eq!(
+            sorted.iter().map(|p| p.size).collect::<Vec<_>>(),
+            [Some(4096), Some(2048), Some(1024), Some(1024)]
+        );
+        assert!(sorted.is_sorted_by_key(|p| (Reverse(p.size), p.name.clone())));
+    }
+
+    #[test]
+    fn sort_by_repo() {
+        let sorted = sorted(Sort::Repo);
+        assert_eq!(names(&sorted), ["curl", "bash", "nano", "zlib"]);
+        // Packages of no repository last
+        assert_eq!(
+            sorted.iter().map(|p| p.repo.as_deref()).collect::<Vec<_>>(),
+            [Some("core"), Some("local"), Some("volatile"), None]
+        );
+        assert!(sorted.is_sorted_by_key(|p| (p.repo.is_none(), p.repo.clone(), p.name.clone())));
+    }
+}
@@ -1,22 +1,27 @@
 // SPDX-FileCopyrightText: 2023 AerynOS Developers
 // SPDX-License-Identifier: MPL-2.0
 
-use std::{env, io, path::Path, path::PathBuf};
-
-use clap::{Arg, ArgAction, Command};
-use clap_complete::{
-    generate_to,
-    shells::{Bash, Fish, Zsh},
+use std::{
+    env,
+    io::{self, IsTerminal},
+    path::Path,
+    path::PathBuf,
 };
+
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use clap_complete::Shell;
 use clap_mangen::Man;
 use fs_err as fs;
-use moss::{Installation, installation};
+use moss::{Client, Installation, client, environment, installation};
 use thiserror::Error;
 use tracing_common::{self, logging::LogConfig, logging::init_log_with_config};
 use tui::Styled;
 
 mod boot;
 mod cache;
+mod complete;
+mod db;
+mod error;
 mod extract;
 mod fetch;
 mod index;
@@ -24,14 +29,22 @@ mod info;
 mod inspect;
 mod install;
 mod list;
+mod model;
+mod output;
+mod query;
 mod remove;
 mod repo;
+mod rollback;
 mod search;
 mod search_file;
+mod shell;
 mod state;
+mod stats;
 mod sync;
 mod version;
 
+pub use self::error::{report_json, reported};
+
 /// Generate the CLI command structure
 fn command() -> Command {
     Command::new("moss")
@@ -78,6 +91,39 @@ fn command() -> Command {
                 .global(true)
                 .value_parser(clap::value_parser!(LogConfig)),
         )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .global(true)
+                .help("Output format for list, info, search & state commands")
+                .action(ArgAction::Set)
+                .default_value("text")
+                .value_parser(clap::value_parser!(output::Format)),
+        )
+        .arg(
+            Arg::new("no-progress")
+                .long("no-progress")
+                .global(true)
+                .help("Print plain progress lines instead of progress bars")
+                .long_help(
+                    "Print plain progress lines instead of progress bars\n\nImplied when stdout isn't a terminal",
+                )
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("wait")
+                .long("wait")
+                .global(true)
+                .help("Wait for another moss process changing the root to finish, instead of failing")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("force-triggers")
+                .long("force-triggers")
+                .global(true)
+                .help("Run system triggers even when the paths they match are unchanged")
+                .action(ArgAction::SetTrue),
+        )
         .arg(
             Arg::new("yes")
                 .short('y')
@@ -86,6 +132,24 @@ fn command() -> Command {
                 .help("Assume yes for all questions")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("batch")
+                .long("batch")
+                .help("Run the subcommands read from FILE, one per line, sharing one client")
+                .long_help(
+                    "Run the subcommands read from FILE, one per line, sharing one client\n\nSee `moss shell --help` for the grammar of the lines",
+                )
+                .action(ArgAction::Set)
+                .value_name("FILE")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("keep-going")
+                .long("keep-going")
+                .help("Continue with the next subcommand of --batch when one fails")
+                .action(ArgAction::SetTrue)
+                .requires("batch"),
+        )
         .arg(
             Arg::new("generate-manpages")
                 .long("generate-manpages")
@@ -105,6 +169,7 @@ fn command() -> Command {
         .arg_required_else_help(true)
         .subcommand(boot::command())
         .subcommand(cache::command())
+        .subcommand(db::command())
         .subcommand(extract::command())
         .subcommand(fetch::command())
         .subcommand(index::command())
@@ -112,11 +177,16 @@ fn command() -> Command {
         .subcommand(inspect::command())
         .subcommand(install::command())
         .subcommand(list::command())
+        .subcommand(model::command())
+        .subcommand(query::command())
         .subcommand(remove::command())
         .subcommand(repo::command())
+        .subcommand(rollback::command())
         .subcommand(search::command())
         .subcommand(search_file::command())
+        .subcommand(shell::command())
         .subcommand(state::command())
+        .subcommand(stats::command())
         .subcommand(sync::command())
         .subcommand(version::command())
 }
@@ -149,15 +219,21 @@ fn generate_manpages(cmd: &Command, dir: &Path, prefix: Option<&str>) -> io::Res
 
 /// Generate shell completions
 fn generate_completions(cmd: &mut Command, dir: &Path) -> io::Result<()> {
-    generate_to(Bash, cmd, "moss", dir)?;
-    generate_to(Fish, cmd, "moss", dir)?;
-    generate_to(Zsh, cmd, "moss", dir)?;
+    complete::generate_script(Shell::Bash, cmd, dir)?;
+    complete::generate_script(Shell::Fish, cmd, dir)?;
+    complete::generate_script(Shell::Zsh, cmd, dir)?;
     Ok(())
 }
 
 /// Process all CLI arguments
 pub fn process() -> Result<(), Error> {
-    let args = replace_aliases(env::args());
+    let args = replace_aliases(env::args().collect());
+
+    // Hidden entry point of the generated completion scripts
+    if args.get(1).is_some_and(|arg| arg == "__complete") {
+        return complete::process(&args[1..]).map_err(Error::Complete);
+    }
+
     let matches = command().get_matches_from(args);
 
     let show_version = matches.get_one::<bool>("version").is_some_and(|v| *v);
@@ -167,6 +243,13 @@ pub fn process() -> Result<(), Error> {
         println!("moss {}", tools_buildinfo::get_full_version());
     }
 
+    output::Format::get(&matches).select();
+
+    // Bars garble logs & pipes, so only draw them for a terminal
+    if matches.get_flag("no-progress") || !io::stdout().is_terminal() {
+        moss::progress::set_plain(true);
+    }
+
     if let Some(log_config) = matches.get_one::<LogConfig>("log") {
         init_log_with_config(log_config.clone());
     }
@@ -196,7 +279,9 @@ pub fn process() -> Result<(), Error> {
     let root = matches.get_one::<PathBuf>("root").unwrap();
     let cache = matches.get_one::<PathBuf>("cache");
 
-    let installation = Installation::open(root, cache.cloned())?;
+    let installation = Installation::open(root, cache.cloned())?
+        .wait_for_lock(matches.get_flag("wait"))
+        .force_triggers(matches.get_flag("force-triggers"));
 
     if let Some(system_model) = installation.system_model.as_ref() {
         if !system_model.disable_warning {
@@ -206,37 +291,108 @@ pub fn process() -> Result<(), Error> {
         }
     }
 
+    let mut session = Session::new(installation);
+
+    if let Some(path) = matches.get_one::<PathBuf>("batch") {
+        let file = io::BufReader::new(fs::File::open(path)?);
+        return shell::batch(file, &mut session, matches.get_flag("keep-going")).map_err(Error::Shell);
+    }
+
     match matches.subcommand() {
-        Some(("boot", args)) => boot::handle(args, installation).map_err(Error::Boot),
-        Some(("cache", args)) => cache::handle(args, installation).map_err(Error::Cache),
-        Some(("extract", args)) => extract::handle(args).map_err(Error::Extract),
-        Some(("fetch", args)) => fetch::handle(args, installation).map_err(Error::Fetch),
-        Some(("index", args)) => index::handle(args).map_err(Error::Index),
-        Some(("info", args)) => info::handle(args, installation).map_err(Error::Info),
-        Some(("inspect", args)) => inspect::handle(args).map_err(Error::Inspect),
-        Some(("install", args)) => install::handle(args, installation).map_err(Error::Install),
-        Some(("list", args)) => list::handle(args, installation).map_err(Error::List),
-        Some(("remove", args)) => remove::handle(args, installation).map_err(Error::Remove),
-        Some(("repo", args)) => repo::handle(args, installation).map_err(Error::Repo),
-        Some(("search", args)) => search::handle(args, installation).map_err(Error::Search),
-        Some(("search-file", args)) => search_file::handle(args, installation).map_err(Error::SearchFile),
-        Some(("state", args)) => state::handle(args, installation).map_err(Error::State),
-        Some(("sync", args)) => sync::handle(args, installation).map_err(Error::Sync),
-        Some(("version", args)) => {
-            version::handle(args);
-            Ok(())
-        }
+        Some(("shell", args)) => shell::handle(args, &mut session).map_err(Error::Shell),
+        Some(_) => session.run(&matches),
         None => {
             if !show_version {
                 command().print_help().unwrap();
             }
             Ok(())
         }
-        _ => unreachable!(),
     }
 }
 
-fn replace_aliases(args: env::Args) -> Vec<String> {
+/// The installation subcommands run against, with the [`Client`] they share
+///
+/// The client is constructed by the first subcommand needing one, so those
+/// which don't, i.e. `moss db`, don't pay for it.
+pub struct Session {
+    installation: Installation,
+    client: Option<Client>,
+}
+
+impl Session {
+    pub fn new(installation: Installation) -> Self {
+        Self {
+            installation,
+            client: None,
+        }
+    }
+
+    /// Run the subcommands of `moss shell` with `client`
+    #[cfg(test)]
+    fn with_client(client: Client) -> Self {
+        Self {
+            installation: client.installation().clone(),
+            client: Some(client),
+        }
+    }
+
+    /// The shared client, constructed on first use. Stones since moved into the
+    /// local overlay repository are picked up by later uses.
+    fn client(&mut self) -> Result<&mut Client, Error> {
+        match &mut self.client {
+            Some(client) => {
+                client.refresh_overlay().map_err(Error::Client)?;
+                Ok(client)
+            }
+            client @ None => {
+                let new = Client::new(environment::NAME, self.installation.clone()).map_err(Error::Client)?;
+                Ok(client.insert(new))
+            }
+        }
+    }
+
+    /// Reload the state & repositories of the shared client, if constructed,
+    /// see [`Client::reload`]
+    fn reload(&mut self) -> Result<(), Error> {
+        if let Some(client) = &mut self.client {
+            client.reload().map_err(Error::Client)?;
+        }
+        Ok(())
+    }
+
+    /// Run the subcommand of `matches`
+    fn run(&mut self, matches: &ArgMatches) -> Result<(), Error> {
+        match matches.subcommand() {
+            Some(("boot", args)) => boot::handle(args, self.client()?).map_err(Error::Boot),
+            Some(("cache", args)) => cache::handle(args, self.client()?).map_err(Error::Cache),
+            Some(("db", args)) => db::handle(args, &self.installation).map_err(Error::Db),
+            Some(("extract", args)) => extract::handle(args).map_err(Error::Extract),
+            Some(("fetch", args)) => fetch::handle(args, self.client()?).map_err(Error::Fetch),
+            Some(("index", args)) => index::handle(args).map_err(Error::Index),
+            Some(("info", args)) => info::handle(args, self.client()?).map_err(Error::Info),
+            Some(("inspect", args)) => inspect::handle(args).map_err(Error::Inspect),
+            Some(("install", args)) => install::handle(args, self.client()?).map_err(Error::Install),
+            Some(("list", args)) => list::handle(args, self.client()?).map_err(Error::List),
+            Some(("model", args)) => model::handle(args, self.client()?).map_err(Error::Model),
+            Some(("query", args)) => query::handle(args, self.client()?).map_err(Error::Query),
+            Some(("remove", args)) => remove::handle(args, self.client()?).map_err(Error::Remove),
+            Some(("repo", args)) => repo::handle(args, &self.installation).map_err(Error::Repo),
+            Some(("rollback", args)) => rollback::handle(args, self.client()?).map_err(Error::Rollback),
+            Some(("search", args)) => search::handle(args, self.client()?).map_err(Error::Search),
+            Some(("search-file", args)) => search_file::handle(args, self.client()?).map_err(Error::SearchFile),
+            Some(("state", args)) => state::handle(args, self.client()?).map_err(Error::State),
+            Some(("stats", args)) => stats::handle(args, self.client()?).map_err(Error::Stats),
+            Some(("sync", args)) => sync::handle(args, self.client()?).map_err(Error::Sync),
+            Some(("version", args)) => {
+                version::handle(args);
+                Ok(())
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+fn replace_aliases(mut args: Vec<String>) -> Vec<String> {
     const ALIASES: &[(&str, &[&str])] = &[
         ("li", &["list", "installed"]),
         ("la", &["list", "available"]),
@@ -255,8 +411,6 @@ fn replace_aliases(args: env::Args) -> Vec<String> {
         ("up", &["sync"]),
     ];
 
-    let mut args = args.collect::<Vec<_>>();
-
     for (alias, replacements) in ALIASES {
         let Some(pos) = args.iter().position(|a| a == *alias) else {
             continue;
@@ -297,6 +451,15 @@ pub enum Error {
     #[error("cache")]
     Cache(#[source] cache::Error),
 
+    #[error("complete")]
+    Complete(#[source] complete::Error),
+
+    #[error("client")]
+    Client(#[source] client::Error),
+
+    #[error("db")]
+    Db(#[source] db::Error),
+
     #[error("index")]
     Index(#[source] index::Error),
 
@@ -309,6 +472,9 @@ pub enum Error {
     #[error("list")]
     List(#[source] list::Error),
 
+    #[error("model")]
+    Model(#[source] model::Error),
+
     #[error("inspect")]
     Inspect(#[source] inspect::Error),
 
@@ -318,12 +484,18 @@ pub enum Error {
     #[error("fetch")]
     Fetch(#[source] fetch::Error),
 
+    #[error("query")]
+    Query(#[source] query::Error),
+
     #[error("remove")]
     Remove(#[source] remove::Error),
 
     #[error("repo")]
     Repo(#[source] repo::Error),
 
+    #[error("rollback")]
+    Rollback(#[source] rollback::Error),
+
     #[error("search")]
     Search(#[source] search::Error),
 
@@ -333,6 +505,12 @@ pub enum Error {
     #[error("state")]
     State(#[source] state::Error),
 
+    #[error("stats")]
+    Stats(#[source] stats::Error),
+
+    #[error("shell")]
+    Shell(#[source] shell::Error),
+
     #[error("sync")]
     Sync(#[source] sync::Error),
 
@@ -0,0 +1,126 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+use std::path::PathBuf;
+
+use clap::{ArgMatches, Command, arg, value_parser};
+use moss::{
+    Client, SystemModel,
+    client::{
+        self,
+        model::{Drift, RepositoryDrift},
+    },
+    system_model,
+};
+use thiserror::Error;
+use tui::Styled;
+
+pub fn command() -> Command {
+    Command::new("model")
+        .about("System model management")
+        .long_about("Manage the system through its system-model.kdl")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("apply")
+                .about("Make the system match a system model")
+                .long_about(
+                    "Make the system match a system model
+
+Configures the repositories declared by the model and creates a new state with exactly its packages. Defaults to the model recorded for the active state, /usr/lib/system-model.kdl",
+                )
+                .arg(arg!([path] "Path to the system-model.kdl").value_parser(value_parser!(PathBuf))),
+        )
+        .subcommand(
+            Command::new("status")
+                .about("Show how the system drifted from its model")
+                .long_about(
+                    "Show how the system drifted from its model
+
+Compares the model recorded for the active state, /usr/lib/system-model.kdl, with the explicitly installed packages & the configured repositories",
+                ),
+        )
+}
+
+pub fn handle(args: &ArgMatches, client: &mut Client) -> Result<(), Error> {
+    match args.subcommand() {
+        Some(("apply", args)) => apply(args, client),
+        Some(("status", _)) => status(client),
+        _ => unreachable!(),
+    }
+}
+
+fn apply(args: &ArgMatches, client: &mut Client) -> Result<(), Error> {
+    let yes = *args.get_one::<bool>("yes").unwrap();
+    let path = args
+        .get_one::<PathBuf>("path")
+        .cloned()
+        .unwrap_or_else(|| client.installation().root.join("usr/lib/system-model.kdl"));
+
+    let model = system_model::load(&path)?.ok_or(Error::NotFound(path))?;
+
+    client.apply_system_model(SystemModel::from(model), yes)?;
+
+    Ok(())
+}
+
+fn status(client: &Client) -> Result<(), Error> {
+    let drift = client
+        .model_status()?
+        .ok_or_else(|| Error::NotFound(client.installation().root.join("usr/lib/system-model.kdl")))?;
+
+    if drift.is_empty() {
+        println!("System matches its model");
+        return Ok(());
+    }
+
+    print_drift(&drift);
+
+    Ok(())
+}
+
+/// Print the packages & repositories which drifted
+fn print_drift(drift: &Drift) {
+    if !drift.only_in_model.is_empty() {
+        println!("Packages of the model which aren't installed explicitly:");
+        for provider in &drift.only_in_model {
+            println!(" {} {}", "-".red(), provider.to_string().bold());
+        }
+        println!();
+    }
+
+    if !drift.only_installed.is_empty() {
+        println!("Explicitly installed packages missing from the model:");
+        for name in &drift.only_installed {
+            println!(" {} {}", "+".green(), name.to_string().bold());
+        }
+        println!();
+    }
+
+    if !drift.repositories.is_empty() {
+        println!("Repositories configured differently to the model:");
+        for repository in &drift.repositories {
+            let (id, how) = match repository {
+                RepositoryDrift::OnlyInModel(id) => (id, "not configured or disabled"),
+                RepositoryDrift::OnlyConfigured(id) => (id, "not in the model"),
+                RepositoryDrift::Mismatch(id) => (id, "different source or priority"),
+                RepositoryDrift::EnabledOutsideModel(id) => (id, "enabled, but disabled in the model"),
+            };
+            println!(" {} {}", id.to_string().bold(), how.dim());
+        }
+        println!();
+    }
+
+    println!("Run `moss model apply` to make the system match its model");
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("client")]
+    Client(#[from] client::Error),
+
+    #[error("load system model")]
+    Load(#[from] system_model::LoadError),
+
+    #[error("system model doesn't exist at {0:?}")]
+    NotFound(PathBuf),
+}
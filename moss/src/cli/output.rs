@@ -0,0 +1,809 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Machine-readable output for `--format json`
+//!
+//! Each subcommand converts the data it already queried into these
+//! types so text and JSON output never diverge in content.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use clap::{ArgMatches, ValueEnum};
+use itertools::Itertools;
+use moss::{client, package, registry::plugin::Origin, repository, state};
+use serde::{Serialize, Serializer};
+use stone::{StonePayloadLayoutFile, StonePayloadLayoutRecord};
+
+/// Output format selected with the global `--format` flag
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Human readable output
+    #[default]
+    Text,
+    /// JSON written to stdout
+    Json,
+}
+
+impl Format {
+    /// Returns the [`Format`] requested via `args`
+    pub fn get(args: &ArgMatches) -> Self {
+        args.try_get_one::<Format>("format")
+            .ok()
+            .flatten()
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn is_json(self) -> bool {
+        self == Self::Json
+    }
+
+    /// Select `self` as the format of errors, reported once the subcommand returns,
+    /// keeping stdout clean of progress output for JSON
+    pub fn select(self) {
+        JSON_SELECTED.store(self.is_json(), Ordering::Relaxed);
+        tui::set_quiet(self.is_json());
+    }
+
+    /// The format selected, see [`Format::select`]
+    pub fn selected() -> Self {
+        if JSON_SELECTED.load(Ordering::Relaxed) {
+            Self::Json
+        } else {
+            Self::Text
+        }
+    }
+}
+
+/// Whether [`Format::Json`] was selected, see [`Format::select`]
+static JSON_SELECTED: AtomicBool = AtomicBool::new(false);
+
+/// Print `value` as pretty JSON to stdout
+pub fn print_json(value: &impl Serialize) {
+    println!("{}", to_json(value));
+}
+
+fn to_json(value: &impl Serialize) -> String {
+    serde_json::to_string_pretty(value).expect("output types are serializable")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Revision {
+    pub version: String,
+    pub release: u64,
+}
+
+impl Revision {
+    pub fn new(package: &moss::Package) -> Self {
+        Self {
+            version: package.meta.version_identifier.clone(),
+            release: package.meta.source_release,
+        }
+    }
+}
+
+/// A package listing entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    pub release: u64,
+    pub repo: Option<String>,
+    pub installed: bool,
+    pub explicit: bool,
+    pub summary: String,
+    /// Candidate revision, when listing sync changes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sync: Option<Revision>,
+    /// Download size when listing available packages, otherwise installed size, if listed by size
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+}
+
+impl Package {
+    pub fn new(package: &moss::Package, repo: Option<repository::Id>) -> Self {
+        Self {
+            name: package.meta.name.to_string(),
+            version: package.meta.version_identifier.clone(),
+            release: package.meta.source_release,
+            repo: repo.map(|id| id.to_string()),
+            installed: package.flags.installed,
+            explicit: package.flags.explicit,
+            summary: package.meta.summary.clone(),
+            sync: None,
+            size: None,
+        }
+    }
+}
+
+/// Detailed package information for `moss info`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PackageInfo {
+    #[serde(flatten)]
+    pub package: Package,
+    /// Where the package is offered from, i.e. `installed`, `local` or the repository
+    pub origin: Option<String>,
+    pub build_release: u64,
+    pub homepage: String,
+    pub description: String,
+    pub licenses: Vec<String>,
+    pub download_size: Option<u64>,
+    pub installed_size: Option<u64>,
+    pub dependencies: Vec<String>,
+    /// Direct dependencies with the package providing each, if any
+    pub rundeps: Vec<Dependency>,
+    pub providers: Vec<String>,
+    /// Names of the `providers`, by their kind
+    pub providers_by_kind: BTreeMap<String, Vec<String>>,
+    pub conflicts: Vec<String>,
+    /// Guarded against removal, see `moss remove --allow-essential`
+    pub protected: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub files: Option<Vec<File>>,
+    /// Transitive dependencies, with `moss info --tree`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tree: Option<DependencyTree>,
+    /// Who built the package, when recorded
+    #[serde(skip_serializing_if = "package::Provenance::is_empty")]
+    pub provenance: package::Provenance,
+}
+
+impl PackageInfo {
+    pub fn new(details: &client::info::Details) -> Self {
+        let package = &details.package;
+        let repo = match &details.origin {
+            Some(Origin::Repository(id)) => Some(id.clone()),
+            _ => None,
+        };
+
+        Self {
+            package: Package::new(package, repo),
+            origin: details.origin.as_ref().map(ToString::to_string),
+            build_release: package.meta.build_release,
+            homepage: package.meta.homepage.clone(),
+            description: package.meta.description.clone(),
+            licenses: package.meta.licenses.clone(),
+            download_size: package.meta.download_size,
+            installed_size: details.installed_size,
+            dependencies: package
+                .meta
+                .dependencies
+                .iter()
+                .sorted()
+                .map(ToString::to_string)
+                .collect(),
+            rundeps: details
+                .dependencies
+                .iter()
+                .sorted_by(|a, b| a.dependency.cmp(&b.dependency))
+                .map(|dependency| Dependency {
+                    dependency: dependency.dependency.to_string(),
+                    package: dependency.package.as_ref().map(ToString::to_string),
+                })
+                .collect(),
+            providers: package
+                .meta
+                .providers
+                .iter()
+                .sorted()
+                .map(ToString::to_string)
+                .collect(),
+            providers_by_kind: package
+                .meta
+                .providers
+                .iter()
+                .sorted()
+                .map(|provider| (provider.kind.to_string(), provider.name.clone()))
+                .into_group_map()
+                .into_iter()
+                .collect(),
+            conflicts: package
+                .meta
+                .conflicts
+                .iter()
+                .sorted()
+                .map(ToString::to_string)
+                .collect(),
+            protected: false,
+            files: None,
+            tree: None,
+            provenance: package.meta.provenance.clone(),
+        }
+    }
+}
+
+/// A dependency with the package providing it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Dependency {
+    pub dependency: String,
+    pub package: Option<String>,
+}
+
+/// A package & its transitive dependencies
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DependencyTree {
+    pub name: String,
+    /// Already listed elsewhere in the tree, so its dependencies are omitted
+    pub repeated: bool,
+    pub dependencies: Vec<DependencyTree>,
+}
+
+impl DependencyTree {
+    /// Walk `tree` depth first, listing the dependencies of each package only once
+    pub fn new(tree: &client::info::Tree) -> Self {
+        fn walk(tree: &client::info::Tree, name: &package::Name, seen: &mut BTreeSet<package::Name>) -> DependencyTree {
+            let repeated = !seen.insert(name.clone());
+            let dependencies = if repeated {
+                vec![]
+            } else {
+                tree.dependencies(name)
+                    .into_iter()
+                    .map(|dependency| walk(tree, dependency, seen))
+                    .collect()
+            };
+
+            DependencyTree {
+                name: name.to_string(),
+                repeated,
+                dependencies,
+            }
+        }
+
+        walk(tree, tree.root(), &mut BTreeSet::new())
+    }
+}
+
+/// A file provided by a package
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct File {
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+/// Layout entries of a package which differ between two states, for `moss query filediff`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileDiff {
+    pub name: String,
+    pub from: FileDiffSide,
+    pub to: FileDiffSide,
+    pub added: Vec<LayoutEntry>,
+    pub removed: Vec<LayoutEntry>,
+    pub changed: Vec<LayoutChange>,
+}
+
+/// The package selected in one state of a [`FileDiff`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FileDiffSide {
+    pub state: i32,
+    pub package: String,
+}
+
+/// A layout entry of a package, with its absolute path
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LayoutEntry {
+    pub path: String,
+    /// File type, i.e. `regular` or `symlink`
+    pub kind: String,
+    /// Permission bits in octal, i.e. `0755`
+    pub mode: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+/// An entry at the same path whose contents or mode differ
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LayoutChange {
+    pub path: String,
+    /// The file type, hash or symlink target differ
+    pub content: bool,
+    /// The permission bits differ
+    pub mode: bool,
+    pub from: LayoutEntry,
+    pub to: LayoutEntry,
+}
+
+impl FileDiff {
+    pub fn new(diff: &client::filediff::FileDiff) -> Self {
+        let side = |side: &client::filediff::Side| FileDiffSide {
+            state: side.state.into(),
+            package: side.package.to_string(),
+        };
+
+        Self {
+            name: diff.name.to_string(),
+            from: side(&diff.from),
+            to: side(&diff.to),
+            added: diff.added.iter().map(LayoutEntry::new).collect(),
+            removed: diff.removed.iter().map(LayoutEntry::new).collect(),
+            changed: diff
+                .changed
+                .iter()
+                .map(|change| LayoutChange {
+                    path: format!("/usr/{}", change.path()),
+                    content: change.content_changed(),
+                    mode: change.mode_changed(),
+                    from: LayoutEntry::new(&change.old),
+                    to: LayoutEntry::new(&change.new),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl LayoutEntry {
+    pub fn new(layout: &StonePayloadLayoutRecord) -> Self {
+        let (hash, target) = match &layout.file {
+            StonePayloadLayoutFile::Regular(hash, _) => (Some(format!("{hash:2x}")), None),
+            StonePayloadLayoutFile::Symlink(source, _) => (None, Some(source.to_string())),
+            _ => (None, None),
+        };
+
+        Self {
+            path: format!("/usr/{}", layout.file.target()),
+            kind: layout.file.file_type().to_string(),
+            mode: format!("{:04o}", layout.mode & 0o7777),
+            hash,
+            target,
+        }
+    }
+}
+
+/// A `moss search` result
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SearchResult {
+    pub name: String,
+    pub summary: String,
+    /// What the keyword matched against, i.e. `name`, `summary` or `description`
+    #[serde(rename = "match")]
+    pub match_kind: String,
+    /// Providers matching `--provider`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub providers: Vec<String>,
+}
+
+/// Number & total size of a set of files
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Usage {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+impl From<client::stats::Usage> for Usage {
+    fn from(usage: client::stats::Usage) -> Self {
+        Self {
+            files: usage.files,
+            bytes: usage.bytes,
+        }
+    }
+}
+
+/// Disk usage reported by `moss stats`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Stats {
+    pub states: usize,
+    pub archived_states: usize,
+    pub stones: Usage,
+    /// Most bytes of stones kept, if a quota is configured
+    pub cache_quota: Option<u64>,
+    pub assets: Usage,
+    pub orphaned_stones: Usage,
+    pub orphaned_assets: Usage,
+    /// Size of each database in bytes, keyed by name
+    pub databases: BTreeMap<String, u64>,
+}
+
+impl Stats {
+    pub fn new(stats: &client::stats::Stats) -> Self {
+        Self {
+            states: stats.states,
+            archived_states: stats.archived_states,
+            stones: stats.stones.into(),
+            cache_quota: stats.cache_quota,
+            assets: stats.assets.into(),
+            orphaned_stones: stats.orphaned_stones.into(),
+            orphaned_assets: stats.orphaned_assets.into(),
+            databases: stats.databases.iter().cloned().collect(),
+        }
+    }
+}
+
+/// A repository described by `moss repo info`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RepositoryInfo {
+    pub id: String,
+    pub description: String,
+    /// Index URI, `None` until a root index is resolved
+    pub index_uri: Option<String>,
+    pub priority: u64,
+    pub active: bool,
+    pub packages: usize,
+    /// RFC 3339 timestamp of the cached index
+    pub index_modified: Option<String>,
+    pub index_size: Option<u64>,
+    pub db_size: Option<u64>,
+    pub last_refresh: Option<Refresh>,
+}
+
+/// Outcome of the last refresh of a repository
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Refresh {
+    /// RFC 3339 timestamp
+    pub time: String,
+    pub succeeded: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl RepositoryInfo {
+    pub fn new(info: &repository::manager::Info) -> Self {
+        Self {
+            id: info.id.to_string(),
+            description: info.repository.description.clone(),
+            index_uri: info.index_uri.as_ref().map(ToString::to_string),
+            priority: info.repository.priority.into(),
+            active: info.repository.active,
+            packages: info.packages,
+            index_modified: info.index_modified.map(|time| time.to_rfc3339()),
+            index_size: info.index_size,
+            db_size: info.db_size,
+            last_refresh: info.last_refresh.as_ref().map(|refresh| Refresh {
+                time: refresh.time.to_rfc3339(),
+                succeeded: refresh.succeeded(),
+                error: refresh.error.clone(),
+            }),
+        }
+    }
+}
+
+/// A state entry for `moss state list/active/query`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct State {
+    pub id: i32,
+    pub summary: Option<String>,
+    pub description: Option<String>,
+    /// RFC 3339 creation timestamp
+    pub created: String,
+    pub kind: String,
+    pub packages: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selections: Option<Vec<Selection>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<StateUsage>,
+}
+
+impl State {
+    pub fn new(state: &moss::State) -> Self {
+        Self {
+            id: state.id.into(),
+            summary: state.summary.clone(),
+            description: state.description.clone(),
+            created: state.created.to_rfc3339(),
+            kind: state.kind.to_string(),
+            packages: state.selections.len(),
+            selections: None,
+            usage: None,
+        }
+    }
+}
+
+/// Asset pool usage of a state, in bytes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StateUsage {
+    pub shared: u64,
+    /// Reclaimed by pruning the state
+    pub exclusive: u64,
+}
+
+impl From<client::stats::StateUsage> for StateUsage {
+    fn from(usage: client::stats::StateUsage) -> Self {
+        Self {
+            shared: usage.shared_bytes,
+            exclusive: usage.exclusive_bytes,
+        }
+    }
+}
+
+/// A `moss state history` entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct History {
+    pub state: i32,
+    /// RFC 3339 creation timestamp
+    pub created: String,
+    pub summary: Option<String>,
+    /// Command line which created the state, `None` if unknown
+    pub command: Option<String>,
+    pub uid: Option<u32>,
+    pub username: Option<String>,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl History {
+    pub fn new(history: &state::History) -> Self {
+        Self {
+            state: history.state.into(),
+            created: history.created.to_rfc3339(),
+            summary: history.summary.clone(),
+            command: history.origin.as_ref().map(|origin| origin.command.clone()),
+            uid: history.origin.as_ref().map(|origin| origin.uid),
+            username: history.origin.as_ref().and_then(|origin| origin.username.clone()),
+            added: history.added.iter().map(ToString::to_string).collect(),
+            removed: history.removed.iter().map(ToString::to_string).collect(),
+        }
+    }
+}
+
+/// A resolved package selection within a state
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Selection {
+    pub name: String,
+    pub version: String,
+    pub release: u64,
+    pub explicit: bool,
+}
+
+impl Selection {
+    pub fn new(selection: &state::Selection, package: &moss::Package) -> Self {
+        Self {
+            name: package.meta.name.to_string(),
+            version: package.meta.version_identifier.clone(),
+            release: package.meta.source_release,
+            explicit: selection.explicit,
+        }
+    }
+
+    /// This selection with the revision of `package` offered in its place
+    pub fn with_package(&self, package: &moss::Package) -> Self {
+        Self {
+            version: package.meta.version_identifier.clone(),
+            release: package.meta.source_release,
+            ..self.clone()
+        }
+    }
+
+    fn revision(&self) -> Revision {
+        Revision {
+            version: self.version.clone(),
+            release: self.release,
+        }
+    }
+}
+
+/// What a [`StateDiff`] compares a state against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    State(i32),
+    /// Best available candidates of the active repositories
+    Repo,
+}
+
+impl From<state::Id> for Target {
+    fn from(id: state::Id) -> Self {
+        Self::State(id.into())
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::State(id) => write!(f, "#{id}"),
+            Target::Repo => f.write_str("repo"),
+        }
+    }
+}
+
+/// Serialized as the state id, or `"repo"`
+impl Serialize for Target {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Target::State(id) => serializer.serialize_i32(*id),
+            Target::Repo => serializer.serialize_str("repo"),
+        }
+    }
+}
+
+/// Package changes between two states, keyed by package name
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StateDiff {
+    pub from: i32,
+    pub to: Target,
+    pub added: Vec<Selection>,
+    pub removed: Vec<Selection>,
+    pub changed: Vec<Change>,
+}
+
+/// A package whose revision differs between two states
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Change {
+    pub name: String,
+    pub from: Revision,
+    pub to: Revision,
+}
+
+impl StateDiff {
+    /// Diff the resolved selections of state `from` against those of `to`
+    pub fn new(from: state::Id, old: Vec<Selection>, to: impl Into<Target>, new: Vec<Selection>) -> Self {
+        let old = old.into_iter().map(|s| (s.name.clone(), s)).collect::<BTreeMap<_, _>>();
+        let new = new.into_iter().map(|s| (s.name.clone(), s)).collect::<BTreeMap<_, _>>();
+
+        let added = new
+            .iter()
+            .filter(|(name, _)| !old.contains_key(*name))
+            .map(|(_, s)| s.clone())
+            .collect();
+        let removed = old
+            .iter()
+            .filter(|(name, _)| !new.contains_key(*name))
+            .map(|(_, s)| s.clone())
+            .collect();
+        let changed = old
+            .iter()
+            .filter_map(|(name, old)| {
+                let new = new.get(name)?;
+
+                (old.revision() != new.revision()).then(|| Change {
+                    name: name.clone(),
+                    from: old.revision(),
+                    to: new.revision(),
+                })
+            })
+            .collect();
+
+        Self {
+            from: from.into(),
+            to: to.into(),
+            added,
+            removed,
+            changed,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use moss::package;
+
+    use super::*;
+
+    fn package(name: &str, version: &str, release: u64, flags: package::Flags) -> moss::Package {
+        moss::Package {
+            id: package::Id::from(format!("{name}-{release}")),
+            meta: package::Meta {
+                version_identifier: version.to_owned(),
+                source_release: release,
+                summary: format!("The {name} package"),
+                ..package::fixture::meta(name)
+            },
+            flags,
+        }
+    }
+
+    fn selection(name: &str, version: &str, release: u64, explicit: bool) -> Selection {
+        Selection {
+            name: name.to_owned(),
+            version: version.to_owned(),
+            release,
+            explicit,
+        }
+    }
+
+    #[test]
+    fn list_snapshot() {
+        let installed = package::Flags::new().with_installed();
+
+        let list = vec![
+            Package::new(
+                &package("bash", "5.2.37", 12, installed.with_explicit()),
+                Some(repository::Id::new("volatile")),
+            ),
+            Package {
+                sync: Some(Revision {
+                    version: "3.5.1".to_owned(),
+                    release: 4,
+                }),
+                ..Package::new(&package("openssl", "3.5.0", 3, installed), None)
+            },
+        ];
+
+        assert_eq!(
+            to_json(&list),
+            r#"[
+  {
+    "name": "bash",
+    "version": "5.2.37",
+    "release": 12,
+    "repo": "volatile",
+    "installed": true,
+    "explicit": true,
+    "summary": "The bash package"
+  },
+  {
+    "name": "openssl",
+    "version": "3.5.0",
+    "release": 3,
+    "repo": null,
+    "installed": true,
+    "explicit": false,
+    "summary": "The openssl package",
+    "sync": {
+      "version": "3.5.1",
+      "release": 4
+    }
+  }
+]"#
+        );
+    }
+
+    #[test]
+    fn state_diff_snapshot() {
+        let old = vec![
+            selection("bash", "5.2.37", 12, true),
+            selection("nano", "8.3", 5, true),
+            selection("openssl", "3.5.0", 3, false),
+        ];
+        let new = vec![
+            selection("bash", "5.2.37", 12, true),
+            selection("helix", "25.01", 2, true),
+            selection("openssl", "3.5.1", 4, false),
+        ];
+
+        let diff = StateDiff::new(state::Id::from(3), old, state::Id::from(4), new);
+
+        assert_eq!(
+            to_json(&diff),
+            r#"{
+  "from": 3,
+  "to": 4,
+  "added": [
+    {
+      "name": "helix",
+      "version": "25.01",
+      "release": 2,
+      "explicit": true
+    }
+  ],
+  "removed": [
+    {
+      "name": "nano",
+      "version": "8.3",
+      "release": 5,
+      "explicit": true
+    }
+  ],
+  "changed": [
+    {
+      "name": "openssl",
+      "from": {
+        "version": "3.5.0",
+        "release": 3
+      },
+      "to": {
+        "version": "3.5.1",
+        "release": 4
+      }
+    }
+  ]
+}"#
+        );
+
+        let same = vec![selection("bash", "5.2.37", 12, true)];
+        assert!(StateDiff::new(state::Id::from(1), same.clone(), state::Id::from(2), same).is_empty());
+    }
+}
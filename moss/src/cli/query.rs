@@ -0,0 +1,191 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+use std::fmt::Write;
+
+use clap::{ArgAction, ArgMatches, Command, arg};
+use moss::{Client, client, package, state};
+use thiserror::Error;
+use tui::Styled;
+
+use super::output;
+
+pub fn command() -> Command {
+    Command::new("query")
+        .about("Query installed packages")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("filediff")
+                .about("Compare the files of a package between two states")
+                .arg(arg!(<NAME> "Name of the package").value_parser(clap::value_parser!(String)))
+                .arg(
+                    arg!(<FROM> "State id to compare from")
+                        .action(ArgAction::Set)
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    arg!(<TO> "State id to compare to")
+                        .action(ArgAction::Set)
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(arg!(--json "Shorthand for `--format json`").action(ArgAction::SetTrue)),
+        )
+}
+
+pub fn handle(args: &ArgMatches, client: &mut Client) -> Result<(), Error> {
+    match args.subcommand() {
+        Some(("filediff", args)) => filediff(args, client),
+        _ => unreachable!(),
+    }
+}
+
+fn filediff(args: &ArgMatches, client: &Client) -> Result<(), Error> {
+    let name = package::Name::from(args.get_one::<String>("NAME").unwrap().clone());
+    let from = state::Id::from(*args.get_one::<u64>("FROM").unwrap() as i32);
+    let to = state::Id::from(*args.get_one::<u64>("TO").unwrap() as i32);
+
+    let diff = output::FileDiff::new(&client.package_file_diff(&name, from, to)?);
+
+    if args.get_flag("json") || output::Format::get(args).is_json() {
+        output::print_json(&diff);
+    } else {
+        print!("{}", format_file_diff(&diff));
+    }
+
+    Ok(())
+}
+
+/// Render the changed files of a package as a table, one file per row
+fn format_file_diff(diff: &output::FileDiff) -> String {
+    let mut out = format!(
+        "{} #{} ({}) -> #{} ({})\n\n",
+        diff.name.as_str().bold(),
+        diff.from.state,
+        diff.from.package.as_str().dim(),
+        diff.to.state,
+        diff.to.package.as_str().dim()
+    );
+
+    if diff.is_empty() {
+        out.push_str("No file changes\n");
+        return out;
+    }
+
+    let width = diff
+        .added
+        .iter()
+        .chain(&diff.removed)
+        .map(|entry| entry.path.len())
+        .chain(diff.changed.iter().map(|change| change.path.len()))
+        .max()
+        .unwrap_or_default();
+
+    // Pad before styling, as styled text ignores the width
+    for (marker, entry) in diff
+        .added
+        .iter()
+        .map(|entry| ("+".green(), entry))
+        .chain(diff.removed.iter().map(|entry| ("-".red(), entry)))
+    {
+        let _ = writeln!(
+            out,
+            "{marker} {:<width$}  {} {}",
+            entry.path,
+            entry.kind,
+            entry.mode.as_str().dim()
+        );
+    }
+    for change in &diff.changed {
+        let mut details = vec![];
+        if change.content {
+            if change.from.kind == change.to.kind {
+                details.push("content".to_owned());
+            } else {
+                details.push(format!("{} -> {}", change.from.kind, change.to.kind));
+            }
+        }
+        if change.mode {
+            details.push(format!("mode {} -> {}", change.from.mode, change.to.mode));
+        }
+        let _ = writeln!(out, "{} {:<width$}  {}", "~".yellow(), change.path, details.join(", "));
+    }
+
+    out
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("client")]
+    Client(#[from] client::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(path: &str, kind: &str, mode: &str) -> output::LayoutEntry {
+        output::LayoutEntry {
+            path: path.to_owned(),
+            kind: kind.to_owned(),
+            mode: mode.to_owned(),
+            hash: None,
+            target: None,
+        }
+    }
+
+    #[test]
+    fn file_diff() {
+        let side = |state, package: &str| output::FileDiffSide {
+            state,
+            package: package.to_owned(),
+        };
+        let change = |path: &str, from, to, content, mode| output::LayoutChange {
+            path: path.to_owned(),
+            content,
+            mode,
+            from,
+            to,
+        };
+
+        let mut diff = output::FileDiff {
+            name: "nano".to_owned(),
+            from: side(1, "nano-1"),
+            to: side(2, "nano-2"),
+            added: vec![entry("/usr/share/nano/added.nanorc", "regular", "0644")],
+            removed: vec![entry("/usr/lib/libold.so", "symlink", "0777")],
+            changed: vec![
+                change(
+                    "/usr/bin/nano",
+                    entry("/usr/bin/nano", "regular", "0755"),
+                    entry("/usr/bin/nano", "regular", "0755"),
+                    true,
+                    false,
+                ),
+                change(
+                    "/usr/bin/rnano",
+                    entry("/usr/bin/rnano", "symlink", "0777"),
+                    entry("/usr/bin/rnano", "regular", "0755"),
+                    true,
+                    true,
+                ),
+            ],
+        };
+
+        assert_eq!(
+            format_file_diff(&diff),
+            "\
+nano #1 (nano-1) -> #2 (nano-2)
+
++ /usr/share/nano/added.nanorc  regular 0644
+- /usr/lib/libold.so            symlink 0777
+~ /usr/bin/nano                 content
+~ /usr/bin/rnano                symlink -> regular, mode 0777 -> 0755
+"
+        );
+
+        diff.added.clear();
+        diff.removed.clear();
+        diff.changed.clear();
+        assert!(format_file_diff(&diff).ends_with("No file changes\n"));
+    }
+}
@@ -3,7 +3,7 @@
 
 use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
 
-use moss::{Installation, client::Client, environment};
+use moss::client::Client;
 use tracing::instrument;
 
 pub use moss::client::Error;
@@ -26,20 +26,22 @@ pub struct Command {
     /// Simulate the operation (dry-run)
     #[arg(long)]
     dry_run: bool,
+
+    /// Allow removing protected packages, i.e. `bash` or `moss`
+    #[arg(long)]
+    allow_essential: bool,
 }
 
 /// Handle execution of `moss remove`
 #[instrument(skip_all)]
-pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+pub fn handle(args: &ArgMatches, client: &mut Client) -> Result<(), Error> {
     let command = Command::from_arg_matches(args).expect("validated by clap");
 
     let pkgs = command.packages.iter().map(String::as_str).collect::<Vec<_>>();
     let yes = *args.get_one::<bool>("yes").unwrap();
     let simulate = command.dry_run;
 
-    let mut client = Client::new(environment::NAME, installation)?;
-
-    client.remove(&pkgs, yes, simulate)?;
+    client.remove(&pkgs, yes, simulate, command.allow_essential)?;
 
     Ok(())
 }
@@ -1,9 +1,12 @@
 // SPDX-FileCopyrightText: 2023 AerynOS Developers
 // SPDX-License-Identifier: MPL-2.0
 
-use std::{collections::BTreeMap, path::PathBuf, process};
+use std::{collections::BTreeMap, fmt, path::PathBuf, time::Duration};
 
+use chrono::{DateTime, Utc};
 use clap::{Arg, ArgAction, ArgMatches, Command, arg, builder::ValueParser};
+use humansize::{BINARY, format_size};
+use humantime::format_duration;
 use itertools::Itertools;
 use moss::{
     Installation, Repository, environment,
@@ -14,10 +17,14 @@ use thiserror::Error;
 use tui::Styled;
 use url::Url;
 
+use super::output;
+
 /// Control flow for the subcommands
 enum Action {
     // Root
     List,
+    // Root, Id
+    Info(String),
     // Root, Id, Url, Comment, Root index enabled options
     Add(String, Url, String, Priority, Option<RootIndexOptions>),
     // Root, Id
@@ -80,6 +87,15 @@ pub fn command() -> Command {
                 .about("List system software repositories")
                 .long_about("List all of the system repositories and their status"),
         )
+        .subcommand(
+            Command::new("info")
+                .visible_alias("ir")
+                .about("Describe a system software repository")
+                .long_about(
+                    "Show the package count, cached index age & size and outcome of the last refresh of a repository",
+                )
+                .arg(arg!(<NAME> "repo name").value_parser(clap::value_parser!(String))),
+        )
         .subcommand(
             Command::new("remove")
                 .visible_alias("rr")
@@ -108,7 +124,7 @@ pub fn command() -> Command {
 }
 
 /// Handle subcommands to `repo`
-pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+pub fn handle(args: &ArgMatches, installation: &Installation) -> Result<(), Error> {
     let config = config::Manager::system(&installation.root, "moss");
 
     let system_model = system_model::load(&installation.system_model_path())?;
@@ -121,6 +137,7 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
 
     let handler = match args.subcommand() {
         Some(("list", _)) => Action::List,
+        Some(("info", cmd_args)) => Action::Info(cmd_args.get_one::<String>("NAME").cloned().unwrap()),
         Some(("update", cmd_args)) => Action::Update(cmd_args.get_one::<String>("NAME").cloned()),
         Some((command, _)) if system_model.is_some() => {
             return Err(Error::SystemModelDisallowed {
@@ -144,6 +161,7 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
     // dispatch to runtime handler function
     match handler {
         Action::List => list(manager),
+        Action::Info(name) => info(manager, name, output::Format::get(args)),
         Action::Add(name, uri, comment, priority, root_index_options) => {
             add(manager, name, uri, comment, priority, root_index_options)
         }
@@ -228,6 +246,55 @@ fn list(manager: repository::Manager) -> Result<(), Error> {
     Ok(())
 }
 
+/// Describe the cached state of a repo
+fn info(manager: repository::Manager, repo: String, format: output::Format) -> Result<(), Error> {
+    let info = manager.info(&repository::Id::new(&repo))?;
+
+    if format.is_json() {
+        output::print_json(&output::RepositoryInfo::new(&info));
+        return Ok(());
+    }
+
+    let field = |name: &str, value: &dyn fmt::Display| println!("{:<16}{value}", name.bold());
+    let size = |bytes: Option<u64>| bytes.map_or_else(|| "-".to_owned(), |bytes| format_size(bytes, BINARY));
+    let age = |time: DateTime<Utc>| {
+        let elapsed = (Utc::now() - time).to_std().unwrap_or_default();
+        let elapsed = Duration::from_secs(elapsed.as_secs());
+        format!(
+            "{} ({} ago)",
+            time.format("%Y-%m-%d %H:%M:%S UTC"),
+            format_duration(elapsed)
+        )
+    };
+
+    field("Repository", &info.id);
+    field("Description", &info.repository.description);
+    if let Some(uri) = &info.index_uri {
+        field("Index URI", uri);
+    }
+    field("Priority", &info.repository.priority);
+    field("Status", &if info.repository.active { "enabled" } else { "disabled" });
+    field("Packages", &info.packages);
+    field(
+        "Index fetched",
+        &info.index_modified.map_or_else(|| "never".to_owned(), age),
+    );
+    field("Index size", &size(info.index_size));
+    field("Database size", &size(info.db_size));
+    match &info.last_refresh {
+        Some(refresh) => {
+            let outcome = match &refresh.error {
+                None => "succeeded".green().to_string(),
+                Some(error) => format!("failed: {error}").red().to_string(),
+            };
+            field("Last refresh", &format_args!("{} {outcome}", age(refresh.time)));
+        }
+        None => field("Last refresh", &"unknown"),
+    }
+
+    Ok(())
+}
+
 /// Update specific repos or all
 fn update(manager: repository::Manager, which: Option<String>) -> Result<(), Error> {
     runtime::block_on(async {
@@ -245,22 +312,13 @@ fn remove(mut manager: repository::Manager, repo: String) -> Result<(), Error> {
     let id = repository::Id::new(&repo);
 
     match manager.remove(id.clone())? {
-        repository::manager::Removal::NotFound => {
-            println!("{id} not found");
-            process::exit(1);
-        }
-        repository::manager::Removal::ConfigDeleted(false) => {
-            println!(
-                "{id} configuration must be manually deleted since it doesn't exist in it's own configuration file"
-            );
-            process::exit(1);
-        }
+        repository::manager::Removal::NotFound => Err(Error::NotFound(id)),
+        repository::manager::Removal::ConfigDeleted(false) => Err(Error::ConfigNotDeleted(id)),
         repository::manager::Removal::ConfigDeleted(true) => {
             println!("{id} removed");
+            Ok(())
         }
     }
-
-    Ok(())
 }
 
 fn enable(mut manager: repository::Manager, repo: String) -> Result<(), Error> {
@@ -327,4 +385,8 @@ pub enum Error {
         "`moss repo {command}` is not allowed with system-model enabled. Repos must be manually edited from {path:?}"
     )]
     SystemModelDisallowed { command: String, path: PathBuf },
+    #[error("{0} not found")]
+    NotFound(repository::Id),
+    #[error("{0} configuration must be manually deleted since it doesn't exist in it's own configuration file")]
+    ConfigNotDeleted(repository::Id),
 }
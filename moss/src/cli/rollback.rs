@@ -0,0 +1,79 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
+use moss::client::{self, Client};
+use thiserror::Error;
+use tui::{
+    Styled,
+    dialoguer::{Confirm, theme::ColorfulTheme},
+};
+
+use super::{output, state};
+
+pub fn command() -> clap::Command {
+    Command::command()
+}
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "rollback",
+    about = "Roll back to a previous state",
+    long_about = "Activate the Nth state before the active one, skipping any states that were pruned"
+)]
+pub struct Command {
+    /// How many states to roll back
+    #[arg(default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    n: u64,
+
+    /// Reblit the state before activating it, repairing a damaged archive
+    #[arg(long)]
+    hard: bool,
+}
+
+/// Handle execution of `moss rollback`
+pub fn handle(args: &ArgMatches, client: &mut Client) -> Result<(), Error> {
+    let command = Command::from_arg_matches(args).expect("validated by clap");
+    let yes = args.get_flag("yes");
+
+    let active = client.installation().active_state.ok_or(client::Error::NoActiveState)?;
+
+    let target = client.rollback_target(command.n as usize)?;
+
+    let old = state::resolve_selections(&client.get_state(active)?, client)?;
+    let new = state::resolve_selections(&client.get_state(target)?, client)?;
+    state::print_state_diff(output::StateDiff::new(active, old, target, new));
+    println!();
+
+    let result = if yes {
+        true
+    } else {
+        Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!(" Roll back to state #{target}? "))
+            .default(false)
+            .interact()?
+    };
+    if !result {
+        return Err(client::Error::Cancelled.into());
+    }
+
+    let old_id = client.rollback(target, command.hard)?;
+
+    println!(
+        "State {} activated {}",
+        target.to_string().bold(),
+        format!("({old_id} archived)").dim()
+    );
+
+    Ok(())
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("client")]
+    Client(#[from] client::Error),
+    #[error("state")]
+    State(#[from] state::Error),
+    #[error("string processing")]
+    Dialog(#[from] tui::dialoguer::Error),
+}
@@ -3,30 +3,45 @@
 
 use std::collections::BTreeMap;
 
-use clap::builder::NonEmptyStringValueParser;
+use clap::builder::{BoolishValueParser, NonEmptyStringValueParser};
 use clap::{Arg, ArgMatches, Command};
+use itertools::Itertools;
 
 use moss::client;
 use moss::dependency;
 use moss::package::{self, Name};
-use moss::{Client, Installation, Provider, environment};
+use moss::registry::suggest;
+use moss::repository;
+use moss::{Client, Provider, ProviderGlob};
 use strum::Display;
 use tui::Styled;
 use tui::pretty::{ColumnDisplay, print_columns};
 
+use super::output;
+
 const ARG_KEYWORD: &str = "KEYWORD";
 const FLAG_INSTALLED: &str = "installed";
 const FLAG_PROVIDES: &str = "provides";
+const FLAG_PROVIDER: &str = "provider";
+const FLAG_DESCRIPTION: &str = "description";
+const FLAG_REPO: &str = "repo";
+
+/// Most names suggested when nothing matches
+const MAX_SUGGESTIONS: usize = 3;
 
 /// Returns the Clap struct for this command.
 pub fn command() -> Command {
     Command::new("search")
         .visible_alias("sr")
         .about("Search packages")
-        .long_about("Search packages by looking into package names and summaries.")
+        .long_about(
+            "Search packages by looking into package names, summaries and descriptions.\n\n\
+             Every word of KEYWORD must match. Name matches are listed first, then summary \
+             and description matches.",
+        )
         .arg(
             Arg::new(ARG_KEYWORD)
-                .required(true)
+                .required_unless_present(FLAG_PROVIDER)
                 .num_args(1)
                 .value_parser(NonEmptyStringValueParser::new()),
         )
@@ -58,6 +73,43 @@ pub fn command() -> Command {
                 ])
                 .help("Search for packages by provider"),
         )
+        .arg(
+            Arg::new(FLAG_PROVIDER)
+                .long("provider")
+                .value_name("PATTERN")
+                .num_args(1)
+                .conflicts_with_all([ARG_KEYWORD, FLAG_PROVIDES])
+                .value_parser(NonEmptyStringValueParser::new())
+                .help(
+                    "Search available & installed packages for providers matching PATTERN, e.g. `soname(libssl.so.*)`",
+                )
+                .long_help(
+                    "Search available & installed packages for providers matching PATTERN, \
+                     e.g. `soname(libssl.so.*)` or `pkgconfig(zlib)`.\n\n\
+                     `*` matches any run of characters and `?` any single character. \
+                     Patterns without a kind, i.e. `zlib*`, match package names.",
+                ),
+        )
+        .arg(
+            Arg::new(FLAG_DESCRIPTION)
+                .long("description")
+                .value_name("BOOL")
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_value("true")
+                .default_missing_value("true")
+                .value_parser(BoolishValueParser::new())
+                .help("Also search package descriptions, use `--description=false` to disable"),
+        )
+        .arg(
+            Arg::new(FLAG_REPO)
+                .long("repo")
+                .value_name("ID")
+                .num_args(1)
+                .conflicts_with(FLAG_INSTALLED)
+                .value_parser(NonEmptyStringValueParser::new())
+                .help("Search among packages of repository ID only"),
+        )
 }
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Display)]
@@ -65,6 +117,18 @@ pub fn command() -> Command {
 enum MatchKind {
     Name,
     Summary,
+    Description,
+    Provider,
+}
+
+impl From<package::SearchMatch> for MatchKind {
+    fn from(kind: package::SearchMatch) -> Self {
+        match kind {
+            package::SearchMatch::Name => MatchKind::Name,
+            package::SearchMatch::Summary => MatchKind::Summary,
+            package::SearchMatch::Description => MatchKind::Description,
+        }
+    }
 }
 
 fn map_aliases(value: &str) -> &str {
@@ -90,41 +154,101 @@ fn determine_provider(args: &ArgMatches) -> Result<Provider, Error> {
     }
 }
 
-fn query_packages(client: &Client, flags: package::Flags, provider: Provider) -> BTreeMap<MatchKind, Vec<Output>> {
-    match provider {
+fn query_packages(
+    client: &Client,
+    args: &ArgMatches,
+    flags: package::Flags,
+) -> Result<BTreeMap<MatchKind, Vec<Output>>, Error> {
+    if let Some(pattern) = args.get_one::<String>(FLAG_PROVIDER) {
+        return Ok(search_by_provider_glob(client, flags, &ProviderGlob::new(pattern)));
+    }
+
+    let description = args.get_one::<bool>(FLAG_DESCRIPTION).copied().unwrap_or(true);
+
+    Ok(match determine_provider(args)? {
+        Provider {
+            kind: dependency::Kind::PackageName,
+            name,
+        } if description => search_ranked(client, flags, &name),
         Provider {
             kind: dependency::Kind::PackageName,
             name,
         } => search_packages(client, flags, &name),
-        _ => search_by_provider(client, flags, provider),
-    }
+        provider => search_by_provider(client, flags, provider),
+    })
 }
 
-pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+pub fn handle(args: &ArgMatches, client: &mut Client) -> Result<(), Error> {
     let only_installed = args.get_flag(FLAG_INSTALLED);
-    let provider = determine_provider(args)?;
 
-    let client = Client::new(environment::NAME, installation)?;
+    if let Some(repo) = args.get_one::<String>(FLAG_REPO) {
+        client.restrict_to_repository(&repository::Id::new(repo))?;
+    }
+
     let flags = if only_installed {
         package::Flags::new().with_installed()
+    } else if args.contains_id(FLAG_PROVIDER) {
+        // Providers of installed packages may no longer be available
+        package::Flags::new()
     } else {
         package::Flags::new().with_available()
     };
 
-    let output = query_packages(&client, flags, provider);
+    let output = query_packages(client, args, flags)?;
+
+    if output::Format::get(args).is_json() {
+        let results = output
+            .into_iter()
+            .flat_map(|(kind, values)| {
+                values.into_iter().map(move |value| output::SearchResult {
+                    name: value.name.to_string(),
+                    summary: value.summary,
+                    match_kind: kind.to_string(),
+                    providers: value.providers,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        output::print_json(&results);
+        return Ok(());
+    }
 
     if output.values().all(Vec::is_empty) {
+        if !only_installed {
+            print_suggestions(client, args);
+        }
         return Ok(());
     }
 
-    for mut value in output.into_values() {
-        value.sort();
+    for value in output.into_values() {
         print_columns(&value, 1);
     }
 
     Ok(())
 }
 
+/// Point at similarly named packages when nothing matched
+fn print_suggestions(client: &Client, args: &ArgMatches) {
+    let provider = match args.get_one::<String>(FLAG_PROVIDER) {
+        // Suggestions for a glob would only be guesses
+        Some(pattern) if pattern.contains(['*', '?']) => None,
+        Some(provider) => Provider::from_name(provider).ok(),
+        None => determine_provider(args).ok(),
+    };
+    let Some(provider) = provider else {
+        return;
+    };
+
+    let names = client
+        .suggest(&provider, MAX_SUGGESTIONS)
+        .into_iter()
+        .map(|suggestion| suggestion.provider.to_name())
+        .collect::<Vec<_>>();
+    if !names.is_empty() {
+        println!("No packages found{}", suggest::did_you_mean(&names));
+    }
+}
+
 fn search_packages(client: &Client, flags: package::Flags, keyword: &str) -> BTreeMap<MatchKind, Vec<Output>> {
     let mut results: BTreeMap<MatchKind, Vec<Output>> = BTreeMap::new();
 
@@ -140,6 +264,24 @@ fn search_packages(client: &Client, flags: package::Flags, keyword: &str) -> BTr
             name: pkg.meta.name,
             summary: pkg.meta.summary,
             search_match: Some(keyword.to_owned()),
+            providers: vec![],
+        });
+    }
+    results.values_mut().for_each(|outputs| outputs.sort());
+    results
+}
+
+/// Search names, summaries & descriptions, keeping the registry's ranking
+fn search_ranked(client: &Client, flags: package::Flags, text: &str) -> BTreeMap<MatchKind, Vec<Output>> {
+    let mut results: BTreeMap<MatchKind, Vec<Output>> = BTreeMap::new();
+
+    for pkg in client.search(text, flags) {
+        let match_kind = pkg.meta.search_match(text).unwrap_or(package::SearchMatch::Description);
+        results.entry(match_kind.into()).or_default().push(Output {
+            name: pkg.meta.name,
+            summary: pkg.meta.summary,
+            search_match: Some(text.to_owned()),
+            providers: vec![],
         });
     }
     results
@@ -147,7 +289,38 @@ fn search_packages(client: &Client, flags: package::Flags, keyword: &str) -> BTr
 
 fn search_by_provider(client: &Client, flags: package::Flags, provider: Provider) -> BTreeMap<MatchKind, Vec<Output>> {
     let packages = client.lookup_packages_by_provider(&provider, flags);
-    BTreeMap::from([(MatchKind::Name, packages.into_iter().map(Output::from).collect())])
+    BTreeMap::from([(
+        MatchKind::Name,
+        packages.into_iter().map(Output::from).sorted().collect(),
+    )])
+}
+
+/// Search for packages with providers matching `glob`, listing those matched
+fn search_by_provider_glob(
+    client: &Client,
+    flags: package::Flags,
+    glob: &ProviderGlob,
+) -> BTreeMap<MatchKind, Vec<Output>> {
+    let packages = client.lookup_packages_by_provider_glob(glob, flags);
+    BTreeMap::from([(
+        MatchKind::Provider,
+        packages
+            .into_iter()
+            .map(|pkg| Output {
+                providers: pkg
+                    .meta
+                    .providers
+                    .iter()
+                    .filter(|provider| glob.matches(provider))
+                    .map(ToString::to_string)
+                    .collect(),
+                ..Output::from(pkg)
+            })
+            // Installed & available packages of the same name
+            .sorted()
+            .dedup()
+            .collect(),
+    )])
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -164,6 +337,8 @@ struct Output {
     name: Name,
     summary: String,
     search_match: Option<String>,
+    /// Providers matching `--provider`
+    providers: Vec<String>,
 }
 
 fn highlight_string(content: &str, expression: &str) -> (String, String, String) {
@@ -205,6 +380,9 @@ impl ColumnDisplay for Output {
                 self.summary
             );
         }
+        if !self.providers.is_empty() {
+            let _ = write!(writer, " {}", format!("({})", self.providers.join(", ")).dim());
+        }
     }
 }
 
@@ -214,6 +392,7 @@ impl From<package::Package> for Output {
             name: pkg.meta.name,
             summary: pkg.meta.summary,
             search_match: None,
+            providers: vec![],
         }
     }
 }
@@ -221,6 +400,7 @@ impl From<package::Package> for Output {
 #[cfg(test)]
 mod tests {
 
+    use moss::Installation;
     use moss::Registry;
     use moss::package::{self, Name, Package};
     use moss::registry::plugin;
@@ -251,6 +431,7 @@ mod tests {
                 uri: Default::default(),
                 hash: Default::default(),
                 download_size: Default::default(),
+                provenance: Default::default(),
             },
             flags: package::Flags::new().with_available(),
         }
@@ -280,7 +461,13 @@ mod tests {
                 "GNU Text Editor",
                 &[provider(binary, "nano"), provider(binary, "rnano")],
             ),
-            pkg("helix", "A post-modern text editor", &[provider(binary, "hx")]),
+            Package {
+                meta: package::Meta {
+                    description: "A Kakoune / Neovim inspired editor, written in Rust.".to_owned(),
+                    ..pkg("helix", "A post-modern text editor", &[provider(binary, "hx")]).meta
+                },
+                ..pkg("helix", "A post-modern text editor", &[provider(binary, "hx")])
+            },
             pkg("bash", "GNU Bourne-Again Shell", &[provider(binary, "bash")]),
             pkg(
                 "zsh",
@@ -340,9 +527,7 @@ mod tests {
 
     /// Test helper function that approximates the behavior of `handle()`
     fn test_handle(query: &str) -> BTreeMap<MatchKind, Vec<Output>> {
-        let args = moss(query);
-        let provider = determine_provider(&args).unwrap();
-        query_packages(client(), flags_available(), provider)
+        query_packages(client(), &moss(query), flags_available()).unwrap()
     }
 
     #[test]
@@ -403,6 +588,72 @@ mod tests {
         assert_eq!(names, vec!["nano"]);
     }
 
+    #[test]
+    fn test_keyword_description_match() {
+        let output = test_handle("search kakoune");
+        assert_eq!(collect_result_names(&output), vec!["helix"]);
+        assert!(output.contains_key(&MatchKind::Description));
+
+        let output = test_handle("search --description=false kakoune");
+        assert!(output.values().all(Vec::is_empty));
+    }
+
+    #[test]
+    fn test_keyword_match_kinds() {
+        let output = test_handle("search editor");
+        assert_eq!(
+            output.keys().collect::<Vec<_>>(),
+            vec![&MatchKind::Summary],
+            "{:?}",
+            collect_result_names(&output)
+        );
+
+        let args = command().get_matches_from(["search", "rust editor"]);
+        let output = query_packages(client(), &args, flags_available()).unwrap();
+        assert_eq!(collect_result_names(&output), vec!["helix"]);
+
+        // "yaml" is in both names, and libyaml's summary
+        let output = test_handle("search yaml");
+        assert_eq!(output.keys().collect::<Vec<_>>(), vec![&MatchKind::Name]);
+    }
+
+    #[test]
+    fn test_provider_flag() {
+        let output = test_handle("search --provider binary(hx)");
+        assert_eq!(collect_result_names(&output), vec!["helix"]);
+
+        let output = test_handle("search --provider soname(libyaml-0.so.2(x86_64))");
+        assert_eq!(collect_result_names(&output), vec!["libyaml"]);
+
+        assert!(
+            command()
+                .try_get_matches_from(["search", "--provider", "binary(hx)", "hx"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_provider_glob() {
+        let output = test_handle("search --provider binary(*nano)");
+        assert_eq!(output.keys().collect::<Vec<_>>(), vec![&MatchKind::Provider]);
+        let nano = &output[&MatchKind::Provider][0];
+        assert_eq!(nano.name.as_str(), "nano");
+        assert_eq!(nano.providers, ["binary(nano)", "binary(rnano)"]);
+
+        let output = test_handle("search --provider soname(libyaml-?.so.*)");
+        assert_eq!(collect_result_names(&output), vec!["libyaml"]);
+
+        let output = test_handle("search --provider *(*yaml*)");
+        assert_eq!(collect_result_names(&output), vec!["libyaml", "libyaml-devel"]);
+
+        // Package names without a kind
+        let output = test_handle("search --provider *sh");
+        assert_eq!(collect_result_names(&output), vec!["bash", "fish", "zsh"]);
+
+        let output = test_handle("search --provider pkgconfig(zlib*)");
+        assert!(output.values().all(Vec::is_empty));
+    }
+
     #[test]
     fn test_provider_binary_hx_finds_helix() {
         let output_provides_flag = test_handle("search --provides=binary hx");
@@ -462,4 +713,39 @@ mod tests {
         assert_eq!(names_provides_flag, names_dependency_syntax);
         assert_eq!(names_provides_flag, vec!["libyaml-devel"]);
     }
+
+    #[test]
+    fn test_repo() {
+        let volatile = repository::Id::new("volatile");
+        let unstable = repository::Id::new("unstable");
+
+        let mut registry = Registry::default();
+        registry.add_plugin(plugin::Plugin::Test(
+            plugin::Test::new(1, vec![pkg("nano", "GNU Text Editor", &[]), pkg("zsh", "Z shell", &[])])
+                .with_origin(plugin::Origin::Repository(volatile.clone())),
+        ));
+        registry.add_plugin(plugin::Plugin::Test(
+            plugin::Test::new(0, vec![pkg("fish", "The friendly interactive shell", &[])])
+                .with_origin(plugin::Origin::Repository(unstable.clone())),
+        ));
+
+        let root = tempfile::tempdir().unwrap();
+        let installation = Installation::open(root.path(), None).unwrap();
+        let mut client = Client::mocked(installation, registry).unwrap();
+
+        let args = moss("search sh --repo unstable");
+        assert_eq!(args.get_one::<String>(FLAG_REPO).map(String::as_str), Some("unstable"));
+
+        let output = query_packages(&client, &args, flags_available()).unwrap();
+        assert_eq!(collect_result_names(&output), vec!["fish", "zsh"]);
+
+        client.restrict_to_repository(&unstable).unwrap();
+        let output = query_packages(&client, &args, flags_available()).unwrap();
+        assert_eq!(collect_result_names(&output), vec!["fish"]);
+
+        assert!(matches!(
+            client.restrict_to_repository(&volatile),
+            Err(client::Error::UnknownRepository(id)) if id == volatile
+        ));
+    }
 }
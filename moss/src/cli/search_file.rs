@@ -4,8 +4,8 @@
 use clap::builder::NonEmptyStringValueParser;
 use clap::{Arg, ArgMatches, Command};
 
+use moss::client::Client;
 use moss::client::{self};
-use moss::{Installation, client::Client, environment};
 use stone::StonePayloadLayoutFile;
 use tui::Styled;
 
@@ -25,7 +25,7 @@ pub fn command() -> Command {
         )
 }
 
-pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+pub fn handle(args: &ArgMatches, client: &mut Client) -> Result<(), Error> {
     let mut keyword = String::from(args.get_one::<String>(ARG_KEYWORD).unwrap());
 
     // moss db doesn't record the /usr/ prefix so strip any combination of it
@@ -39,8 +39,6 @@ pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error
         }
     }
 
-    let client = Client::new(environment::NAME, installation)?;
-
     let layouts = client.list_layouts()?;
 
     layouts.into_iter().for_each(|(id, layout)| match layout.file {
@@ -0,0 +1,304 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! `moss shell` & `moss --batch`, running many subcommands with one [`moss::Client`]
+
+use std::io::{self, BufRead, IsTerminal};
+
+use clap::{ArgAction, ArgMatches, Command, arg, error::ErrorKind, parser::ValueSource};
+use thiserror::Error;
+use tui::{
+    Styled,
+    dialoguer::{BasicHistory, Input, theme::ColorfulTheme},
+};
+
+use super::{Session, output, replace_aliases};
+
+/// Arguments of `moss` itself, fixed for the whole session, by id & flag
+const SESSION_ARGS: &[(&str, &str)] = &[
+    ("root", "--directory"),
+    ("cache", "--cache"),
+    ("log", "--log"),
+    ("wait", "--wait"),
+    ("force-triggers", "--force-triggers"),
+    ("batch", "--batch"),
+    ("keep-going", "--keep-going"),
+    ("generate-manpages", "--generate-manpages"),
+    ("generate-completions", "--generate-completions"),
+];
+
+/// Subcommands which may change the active state or repositories of the session
+const MUTATING: &[&str] = &["install", "model", "remove", "repo", "rollback", "state", "sync"];
+
+pub fn command() -> Command {
+    Command::new("shell")
+        .about("Run subcommands read from stdin")
+        .long_about(
+            "Run subcommands read from stdin, one per line, sharing one client
+
+Lines are moss subcommands without the leading `moss`, i.e. `list installed`, quoted as in a shell. Empty lines & lines starting with `#` are skipped.
+
+When stdin is a terminal, a prompt with history reads the lines until `exit`, and failing subcommands are reported without ending the shell. Otherwise the first failing subcommand ends the shell, unless --keep-going is given.",
+        )
+        .arg(arg!(--"keep-going" "Continue with the next subcommand when one fails").action(ArgAction::SetTrue))
+}
+
+/// Handle execution of `moss shell`
+pub fn handle(args: &ArgMatches, session: &mut Session) -> Result<(), Error> {
+    let stdin = io::stdin();
+
+    if stdin.is_terminal() {
+        interactive(session)
+    } else {
+        batch(stdin.lock(), session, args.get_flag("keep-going"))
+    }
+}
+
+/// Run the subcommands read from `reader`, one per line, stopping at the first
+/// which fails unless `keep_going`
+pub fn batch(reader: impl BufRead, session: &mut Session, keep_going: bool) -> Result<(), Error> {
+    let mut failed = 0;
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+
+        if let Err(error) = run(&line, session) {
+            let error = Error::Line {
+                line: index + 1,
+                source: Box::new(error),
+            };
+
+            if !keep_going {
+                return Err(error);
+            }
+
+            report(&error);
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(Error::Failed(failed));
+    }
+
+    Ok(())
+}
+
+/// Prompt for subcommands until `exit`, reporting those which fail
+fn interactive(session: &mut Session) -> Result<(), Error> {
+    let mut history = BasicHistory::new().max_entries(100).no_duplicates(true);
+
+    loop {
+        let line: String = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("moss")
+            .allow_empty(true)
+            .history_with(&mut history)
+            .interact_text()?;
+
+        if matches!(line.trim(), "exit" | "quit") {
+            return Ok(());
+        }
+
+        if let Err(error) = run(&line, session) {
+            report(&error);
+        }
+    }
+}
+
+/// Run the subcommand of `line`, reloading the session when it may have changed
+/// the installation
+fn run(line: &str, session: &mut Session) -> Result<(), LineError> {
+    let Some(words) = shlex::split(line) else {
+        return Err(LineError::Quoting);
+    };
+    if words.first().is_none_or(|word| word.starts_with('#')) {
+        return Ok(());
+    }
+
+    let args = replace_aliases(["moss".to_owned()].into_iter().chain(words).collect());
+    let matches = match super::command().try_get_matches_from(args) {
+        Ok(matches) => matches,
+        Err(error) => {
+            return match error.kind() {
+                ErrorKind::DisplayHelp
+                | ErrorKind::DisplayVersion
+                | ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand => {
+                    error.print()?;
+                    Ok(())
+                }
+                _ => Err(LineError::Parse(error)),
+            };
+        }
+    };
+
+    if let Some((_, flag)) = SESSION_ARGS
+        .iter()
+        .find(|(id, _)| matches.value_source(id) == Some(ValueSource::CommandLine))
+    {
+        return Err(LineError::SessionArgument(flag));
+    }
+
+    let subcommand = matches.subcommand_name().unwrap_or_default();
+    if subcommand == "shell" {
+        return Err(LineError::Nested);
+    }
+
+    // Output settings of the line only apply to its subcommand
+    let selected = output::Format::selected();
+    output::Format::get(&matches).select();
+    let result = session.run(&matches);
+    selected.select();
+
+    if MUTATING.contains(&subcommand) || restricts_repository(&matches) {
+        session.reload()?;
+    }
+
+    Ok(result?)
+}
+
+/// Whether a subcommand of `matches` restricted the client to one repository
+/// with `--repo`, which later subcommands mustn't inherit
+fn restricts_repository(matches: &ArgMatches) -> bool {
+    matches.try_get_one::<String>("repo").ok().flatten().is_some()
+        || matches.subcommand().is_some_and(|(_, args)| restricts_repository(args))
+}
+
+/// Print `error` like failures of `moss` itself are
+fn report(error: &impl std::error::Error) {
+    let mut chain = vec![error.to_string()];
+    let mut source = error.source();
+    while let Some(error) = source {
+        chain.push(error.to_string());
+        source = error.source();
+    }
+
+    println!("{}: {}", "Error".red(), chain.join(": "));
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("line {line}")]
+    Line {
+        line: usize,
+        #[source]
+        source: Box<LineError>,
+    },
+
+    #[error("{0} subcommand(s) failed")]
+    Failed(usize),
+
+    #[error("prompt")]
+    Prompt(#[from] tui::dialoguer::Error),
+
+    #[error("read subcommands")]
+    Io(#[from] io::Error),
+}
+
+/// A line of the session which failed
+#[derive(Debug, Error)]
+pub enum LineError {
+    #[error("unterminated quote")]
+    Quoting,
+
+    #[error("invalid subcommand")]
+    Parse(#[source] clap::Error),
+
+    #[error("{0} can only be given to moss itself, not within a session")]
+    SessionArgument(&'static str),
+
+    #[error("moss shell can't be nested")]
+    Nested,
+
+    #[error(transparent)]
+    Command(#[from] super::Error),
+
+    #[error("print help")]
+    Io(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use moss::{
+        Client, Installation, Package, package,
+        registry::{Registry, plugin},
+    };
+
+    use super::*;
+
+    fn available(name: &str, summary: &str) -> Package {
+        Package {
+            id: package::Id::from(format!("{name}-1")),
+            meta: package::Meta {
+                summary: summary.to_owned(),
+                ..package::fixture::meta(name)
+            },
+            flags: package::Flags::new().with_available(),
+        }
+    }
+
+    fn session(root: &std::path::Path) -> Session {
+        let mut registry = Registry::default();
+        registry.add_plugin(plugin::Plugin::Test(plugin::Test::new(
+            1,
+            vec![
+                available("bash", "GNU Bourne Again Shell"),
+                available("nano", "Text editor"),
+            ],
+        )));
+
+        let installation = Installation::open(root, None).unwrap();
+        Session::with_client(Client::mocked(installation, registry).unwrap())
+    }
+
+    fn line_error(error: Error) -> (usize, LineError) {
+        match error {
+            Error::Line { line, source } => (line, *source),
+            error => panic!("expected a failing line, got {error:?}"),
+        }
+    }
+
+    #[test]
+    fn read_only_batch() {
+        let root = tempfile::tempdir().unwrap();
+        let mut session = session(root.path());
+
+        let script = "# Inspect the available packages
+list available
+la --format json
+
+search 'bourne again'
+info nano bash
+version
+help
+";
+        batch(script.as_bytes(), &mut session, false).unwrap();
+
+        // Stops at the first failure
+        let script = "list available\ninfo vim\nfrobnicate\n";
+        let (line, error) = line_error(batch(script.as_bytes(), &mut session, false).unwrap_err());
+        assert_eq!(line, 2);
+        assert!(matches!(error, LineError::Command(crate::cli::Error::Info(_))));
+
+        // Unless keeping going
+        let error = batch(script.as_bytes(), &mut session, true).unwrap_err();
+        assert!(matches!(error, Error::Failed(2)));
+
+        for (script, expected) in [
+            (
+                "frobnicate",
+                LineError::Parse(clap::Error::new(ErrorKind::InvalidSubcommand)),
+            ),
+            ("search 'bash", LineError::Quoting),
+            ("-D /tmp list available", LineError::SessionArgument("--directory")),
+            ("shell", LineError::Nested),
+        ] {
+            let (line, error) = line_error(batch(script.as_bytes(), &mut session, false).unwrap_err());
+            assert_eq!(line, 1);
+            assert_eq!(
+                std::mem::discriminant(&error),
+                std::mem::discriminant(&expected),
+                "{script}: {error}"
+            );
+        }
+    }
+}
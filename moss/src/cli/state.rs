@@ -2,29 +2,36 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use std::{
-    io,
+    fmt, io,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
-use chrono::Local;
+use chrono::{Local, TimeZone};
 use clap::{ArgAction, ArgMatches, Command, CommandFactory, FromArgMatches, Parser, arg};
 use fs_err as fs;
+use humansize::BINARY;
+use itertools::Itertools;
 use moss::{
-    Installation, State,
-    client::{self, Client, prune},
-    environment, state,
+    State,
+    client::{self, Client, prune, stats, verify},
+    manifest, package, state,
 };
 use nix::unistd::gethostname;
 use thiserror::Error;
 use tui::Styled;
 
+use super::output;
+
 pub fn command() -> Command {
     Command::new("state")
         .about("Manage state")
         .long_about("Manage state ...")
         .subcommand_required(true)
         .subcommand(Command::new("active").about("List the active state"))
-        .subcommand(Command::new("list").about("List all states"))
+        .subcommand(Command::new("list").about("List all states").arg(
+            arg!(--usage "Show the asset pool size each state shares & pins exclusively").action(ArgAction::SetTrue),
+        ))
         .subcommand(
             Command::new("activate")
                 .about("Activate a state")
@@ -36,6 +43,13 @@ pub fn command() -> Command {
                 .arg(arg!(--"skip-triggers" "Do not run triggers on activation").action(ArgAction::SetTrue))
                 .arg(arg!(--"skip-boot" "Do not sync boot on activation").action(ArgAction::SetTrue)),
         )
+        .subcommand(
+            Command::new("protect").about("Protect a state from pruning").arg(
+                arg!(<ID> "State id to be protected")
+                    .action(ArgAction::Set)
+                    .value_parser(clap::value_parser!(u64)),
+            ),
+        )
         .subcommand(
             Command::new("query").about("Query information for a state").arg(
                 arg!(<ID> "State id to query")
@@ -43,6 +57,36 @@ pub fn command() -> Command {
                     .value_parser(clap::value_parser!(u64)),
             ),
         )
+        .subcommand(
+            Command::new("history")
+                .about("Show who created each state and what changed")
+                .arg(
+                    arg!([ID] "Only show the history of this state")
+                        .action(ArgAction::Set)
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(arg!(--json "Print the history as JSON, same as `--format json`").action(ArgAction::SetTrue)),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Compare packages between two states")
+                .arg(
+                    arg!(<FROM> "State id to compare from")
+                        .action(ArgAction::Set)
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    arg!([TO] "State id to compare to, or the active state if omitted")
+                        .action(ArgAction::Set)
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    arg!(--repo "Compare to the best candidates available from the repositories")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("TO"),
+                )
+                .arg(arg!(--json "Print the diff as JSON, same as `--format json`").action(ArgAction::SetTrue)),
+        )
         .subcommand(
             Command::new("prune")
                 .about("Prune archived states")
@@ -55,17 +99,49 @@ pub fn command() -> Command {
                 .arg(
                     arg!(--"include-newer" "Include states newer than the active state when pruning")
                         .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    arg!(--"older-than" <AGE> "Remove states older than this age instead, e.g. `30d`")
+                        .action(ArgAction::Set)
+                        .conflicts_with_all(["keep", "include-newer"])
+                        .value_parser(humantime::parse_duration),
+                )
+                .arg(arg!(--"keep-protected" "Keep states marked with `moss state protect`").action(ArgAction::SetTrue))
+                .arg(
+                    arg!(--"dry-run" "Report what would be removed without removing anything")
+                        .action(ArgAction::SetTrue),
                 ),
         )
         .subcommand(
             Command::new("remove")
                 .about("Remove archived state(s)")
-                .arg(arg!(<ID> ... "State id(s) to be removed").value_parser(clap::value_parser!(String))),
+                .arg(arg!(<ID> ... "State id(s) to be removed").value_parser(clap::value_parser!(String)))
+                .arg(
+                    arg!(--"dry-run" "Report what would be removed without removing anything")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(arg!(--force "Allow removing the most recent state").action(ArgAction::SetTrue)),
         )
         .subcommand(
             Command::new("verify")
                 .about("Verify and fix system states and assets")
-                .arg(arg!(--verbose "Vebose output").action(ArgAction::SetTrue)),
+                .arg(
+                    arg!([NAME] ... "Only verify these packages in the active state")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(arg!(--verbose "Vebose output").action(ArgAction::SetTrue))
+                .arg(arg!(--quick "Only check assets exist, skip re-hashing their contents").action(ArgAction::SetTrue))
+                .arg(
+                    arg!(--full "Re-hash all assets, even if unchanged since they were last verified")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("quick"),
+                )
+                .arg(
+                    arg!(--states <IDS> "Only verify these state id(s) or ranges, e.g. `3,5-7`")
+                        .action(ArgAction::Set)
+                        .value_delimiter(',')
+                        .value_parser(clap::value_parser!(String)),
+                ),
         )
         .subcommand(Export::command())
         // For profiling only, hence hidden.
@@ -77,28 +153,38 @@ pub fn command() -> Command {
 }
 
 #[derive(Debug, Parser)]
-#[command(name = "export", about = "Export a state as a system-model.kdl file")]
+#[command(name = "export", about = "Export a state as a system-model.kdl file or manifest")]
 struct Export {
     /// State id to export or current state if omitted
     id: Option<i32>,
     /// Export to the provided path or stdout if not supplied
     ///
-    /// If supplied without a path or path is a directory, outputs to "system-model-{hostname}-fstxn-{id}.kdl"
+    /// If supplied without a path or path is a directory, outputs to "system-model-{hostname}-fstxn-{id}.kdl".
+    /// Paths ending in .yaml, .yml or .json export a manifest instead
     #[arg(short, long)]
     output: Option<Option<PathBuf>>,
+    /// Export a manifest pinning the exact version of each explicit package
+    ///
+    /// Manifests are YAML unless `--format json` is given, and can be
+    /// installed elsewhere with `moss install --from-manifest`
+    #[arg(long)]
+    manifest: bool,
 }
 
-pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+pub fn handle(args: &ArgMatches, client: &mut Client) -> Result<(), Error> {
     match args.subcommand() {
-        Some(("active", _)) => active(installation),
-        Some(("list", _)) => list(installation),
-        Some(("activate", args)) => activate(args, installation),
-        Some(("build-vfs", _)) => build_vfs(installation),
-        Some(("query", args)) => query(args, installation),
-        Some(("prune", args)) => prune(args, installation),
-        Some(("remove", args)) => remove(args, installation),
-        Some(("verify", args)) => verify(args, installation),
-        Some(("export", args)) => export(args, installation),
+        Some(("active", args)) => active(args, client),
+        Some(("list", args)) => list(args, client),
+        Some(("activate", args)) => activate(args, client),
+        Some(("build-vfs", _)) => build_vfs(client),
+        Some(("protect", args)) => protect(args, client),
+        Some(("query", args)) => query(args, client),
+        Some(("diff", args)) => diff(args, client),
+        Some(("history", args)) => history(args, client),
+        Some(("prune", args)) => prune(args, client),
+        Some(("remove", args)) => remove(args, client),
+        Some(("verify", args)) => verify(args, client),
+        Some(("export", args)) => export(args, client),
         _ => unreachable!(),
     }
 }
@@ -119,33 +205,57 @@ pub fn parse_id_or_range(s: &str) -> Result<Vec<u64>, String> {
 }
 
 /// List the active state
-pub fn active(installation: Installation) -> Result<(), Error> {
-    let client = Client::new(environment::NAME, installation)?;
+pub fn active(args: &ArgMatches, client: &Client) -> Result<(), Error> {
+    let state = client.get_active_state()?;
 
-    if let Some(state) = client.get_active_state()? {
-        print_state(state);
+    if output::Format::get(args).is_json() {
+        output::print_json(&state.as_ref().map(output::State::new));
+    } else if let Some(state) = state {
+        print_state(state, None);
     }
 
     Ok(())
 }
 
 /// List all known states, newest first
-pub fn list(installation: Installation) -> Result<(), Error> {
-    let client = Client::new(environment::NAME, installation)?;
+pub fn list(args: &ArgMatches, client: &Client) -> Result<(), Error> {
+    let states = client.list_states()?.into_iter().rev();
+    let usages = if args.get_flag("usage") {
+        Some(client.state_disk_usages()?)
+    } else {
+        None
+    };
+    let usage = |state: &State| {
+        usages
+            .as_ref()
+            .map(|usages| usages.get(&state.id).copied().unwrap_or_default())
+    };
+
+    if output::Format::get(args).is_json() {
+        output::print_json(
+            &states
+                .map(|state| output::State {
+                    usage: usage(&state).map(Into::into),
+                    ..output::State::new(&state)
+                })
+                .collect::<Vec<_>>(),
+        );
+        return Ok(());
+    }
 
-    for state in client.list_states()?.into_iter().rev() {
-        print_state(state);
+    for state in states {
+        let usage = usage(&state);
+        print_state(state, usage);
     }
 
     Ok(())
 }
 
-pub fn activate(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+pub fn activate(args: &ArgMatches, client: &Client) -> Result<(), Error> {
     let new_id = *args.get_one::<u64>("ID").unwrap() as i32;
     let skip_triggers = args.get_flag("skip-triggers");
     let skip_boot = args.get_flag("skip-boot");
 
-    let client = Client::new(environment::NAME, installation)?;
     let old_id = client.activate_state(new_id.into(), skip_triggers, skip_boot)?;
 
     println!(
@@ -157,11 +267,9 @@ pub fn activate(args: &ArgMatches, installation: Installation) -> Result<(), Err
     Ok(())
 }
 
-pub fn build_vfs(installation: Installation) -> Result<(), Error> {
-    let client = Client::new(environment::NAME, installation)?;
-
+pub fn build_vfs(client: &Client) -> Result<(), Error> {
     if let Some(state) = client.get_active_state()? {
-        let fstree = client.vfs(state.selections.iter().map(|selection| &selection.package))?;
+        let fstree = client.state_vfs(&state)?;
 
         std::hint::black_box(fstree);
     }
@@ -169,31 +277,117 @@ pub fn build_vfs(installation: Installation) -> Result<(), Error> {
     Ok(())
 }
 
-pub fn query(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+pub fn protect(args: &ArgMatches, client: &Client) -> Result<(), Error> {
     let id = *args.get_one::<u64>("ID").unwrap() as i32;
 
-    let client = Client::new(environment::NAME, installation)?;
+    client.protect_state(id.into())?;
+
+    println!("State {} protected", id.to_string().bold());
+
+    Ok(())
+}
+
+pub fn query(args: &ArgMatches, client: &Client) -> Result<(), Error> {
+    let id = *args.get_one::<u64>("ID").unwrap() as i32;
 
     let state = client.get_state(id.into())?;
+    let selections = resolve_selections(&state, client)?;
+    let usage = client.state_disk_usage(state.id)?;
+
+    if output::Format::get(args).is_json() {
+        output::print_json(&output::State {
+            selections: Some(selections),
+            usage: Some(usage.into()),
+            ..output::State::new(&state)
+        });
+        return Ok(());
+    }
 
-    print_state(state.clone());
-    print_state_selections(state, &client)?;
+    print_state(state, Some(usage));
+    print_state_selections(selections);
 
     Ok(())
 }
 
-pub fn prune(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+pub fn diff(args: &ArgMatches, client: &Client) -> Result<(), Error> {
+    let from = state::Id::from(*args.get_one::<u64>("FROM").unwrap() as i32);
+    let to = match args.get_one::<u64>("TO") {
+        Some(id) => Some(state::Id::from(*id as i32)),
+        None if args.get_flag("repo") => None,
+        None => Some(client.installation().active_state.ok_or(Error::NoActiveState)?),
+    };
+
+    let old = resolve_selections(&client.get_state(from)?, client)?;
+    let diff = match to {
+        Some(to) => {
+            let new = resolve_selections(&client.get_state(to)?, client)?;
+            output::StateDiff::new(from, old, to, new)
+        }
+        None => {
+            let new = repo_selections(&old, client);
+            output::StateDiff::new(from, old, output::Target::Repo, new)
+        }
+    };
+
+    if args.get_flag("json") || output::Format::get(args).is_json() {
+        output::print_json(&diff);
+        return Ok(());
+    }
+
+    print_state_diff(diff);
+
+    Ok(())
+}
+
+pub fn history(args: &ArgMatches, client: &Client) -> Result<(), Error> {
+    let id = args.get_one::<u64>("ID").map(|id| state::Id::from(*id as i32));
+    let json = args.get_flag("json") || output::Format::get(args).is_json();
+
+    let history = client.state_history(id)?;
+
+    if let Some(id) = id
+        && history.is_empty()
+    {
+        return Err(client::Error::StateDoesntExist(id).into());
+    }
+
+    if json {
+        output::print_json(&history.iter().map(output::History::new).collect::<Vec<_>>());
+        return Ok(());
+    }
+
+    for entry in &history {
+        println!("{}", format_history(entry, &Local));
+    }
+
+    Ok(())
+}
+
+pub fn prune(args: &ArgMatches, client: &Client) -> Result<(), Error> {
     let keep = *args.get_one::<u64>("keep").unwrap();
     let include_newer = args.get_flag("include-newer");
+    let older_than = args.get_one::<Duration>("older-than").copied();
+    let keep_protected = args.get_flag("keep-protected");
+    let dry_run = args.get_flag("dry-run");
     let yes = args.get_flag("yes");
 
-    let client = Client::new(environment::NAME, installation)?;
-    client.prune_states(prune::Strategy::KeepRecent { keep, include_newer }, yes)?;
+    let strategy = match older_than {
+        Some(age) => prune::Strategy::OlderThan(age),
+        None => prune::Strategy::KeepRecent { keep, include_newer },
+    };
+
+    let report = if keep_protected {
+        client.prune_states(prune::Strategy::KeepTagged(&strategy), yes, dry_run)?
+    } else {
+        client.prune_states(strategy, yes, dry_run)?
+    };
+
+    print_prune_report(report, dry_run);
 
     Ok(())
 }
 
-pub fn remove(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+pub fn remove(args: &ArgMatches, client: &Client) -> Result<(), Error> {
     let ids = args
         .get_many::<String>("ID")
         .into_iter()
@@ -206,42 +400,99 @@ pub fn remove(args: &ArgMatches, installation: Installation) -> Result<(), Error
         .map(|id| state::Id::from(id as i32))
         .collect::<Vec<state::Id>>();
 
+    let dry_run = args.get_flag("dry-run");
+    let force = args.get_flag("force");
     let yes = args.get_flag("yes");
 
-    let client = Client::new(environment::NAME, installation)?;
-    client.prune_states(prune::Strategy::Remove(&ids), yes)?;
+    let report = client.prune_states(prune::Strategy::Remove { ids: &ids, force }, yes, dry_run)?;
+
+    print_prune_report(report, dry_run);
 
     Ok(())
 }
 
-pub fn verify(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
-    let verbose = args.get_flag("verbose");
+pub fn verify(args: &ArgMatches, client: &Client) -> Result<(), Error> {
+    let packages = args
+        .get_many::<String>("NAME")
+        .into_iter()
+        .flatten()
+        .map(|name| package::Name::from(name.clone()))
+        .collect();
+    let states = args
+        .get_many::<String>("states")
+        .into_iter()
+        .flatten()
+        .map(|s| parse_id_or_range(s))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(Error::InvalidRange)?
+        .into_iter()
+        .flatten()
+        .map(|id| state::Id::from(id as i32))
+        .collect();
     let yes = args.get_flag("yes");
 
-    let client = Client::new(environment::NAME, installation)?;
-    client.verify(yes, verbose)?;
+    let options = verify::Options {
+        packages,
+        states,
+        quick: args.get_flag("quick"),
+        full: args.get_flag("full"),
+        verbose: args.get_flag("verbose"),
+    };
+
+    client.verify(yes, &options).inspect_err(|error| {
+        if matches!(
+            error,
+            client::Error::Db(moss::db::Error::LayoutEntryDecode | moss::db::Error::Corrupt { .. })
+        ) {
+            eprintln!("{}: the databases may be damaged, run `moss db check`", "HINT".yellow());
+        }
+    })?;
 
     Ok(())
 }
 
-fn export(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+fn export(args: &ArgMatches, client: &Client) -> Result<(), Error> {
     let export = Export::from_arg_matches(args).expect("validate by clap");
 
     let id = match export.id {
         Some(id) => state::Id::from(id),
-        None => installation.active_state.ok_or(Error::NoActiveState)?,
+        None => client.installation().active_state.ok_or(Error::NoActiveState)?,
     };
 
-    let client = Client::new(environment::NAME, installation)?;
-    let system_model = client.export_state(id)?;
+    let path = export.output.as_ref().and_then(Option::as_deref);
+
+    // Manifests are requested explicitly or by the output file extension
+    let manifest = if let Some(path) = path.filter(|path| is_manifest_path(path)) {
+        Some(manifest::Format::from_path(path))
+    } else if export.manifest {
+        Some(if output::Format::get(args).is_json() {
+            manifest::Format::Json
+        } else {
+            manifest::Format::Yaml
+        })
+    } else {
+        None
+    };
+
+    let (encoded, extension) = match manifest {
+        Some(format) => {
+            let extension = match format {
+                manifest::Format::Yaml => "yaml",
+                manifest::Format::Json => "json",
+            };
+            (client.export_manifest(id)?.encode(format)?, extension)
+        }
+        None => (client.export_state(id)?.encoded().to_owned(), "kdl"),
+    };
 
     match export.output {
         Some(maybe_path) => {
             let format_filename = || {
+                let kind = if manifest.is_some() { "manifest" } else { "system-model" };
                 if let Some(hostname) = gethostname().ok().and_then(|s| s.into_string().ok()) {
-                    format!("system-model-{hostname}-fstxn-{id}.kdl")
+                    format!("{kind}-{hostname}-fstxn-{id}.{extension}")
                 } else {
-                    format!("system-model-fstxn-{id}.kdl")
+                    format!("{kind}-fstxn-{id}.{extension}")
                 }
             };
 
@@ -256,20 +507,74 @@ fn export(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
                 None => Path::new(".").join(format_filename()),
             };
 
-            fs::write(&path, system_model.encoded())?;
+            fs::write(&path, encoded)?;
 
             println!("Exported to {path:?}");
         }
         None => {
-            println!("{}", system_model.encoded());
+            println!("{encoded}");
         }
     }
 
     Ok(())
 }
 
-/// Emit a state description for the TUI
-fn print_state(state: State) {
+/// Output paths which select a manifest instead of a system model
+fn is_manifest_path(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("yaml" | "yml" | "json")
+    )
+}
+
+/// Emit the outcome of a state prune
+fn print_prune_report(report: prune::PruneReport, dry_run: bool) {
+    if report.removed_states == 0 {
+        return;
+    }
+
+    let action = if dry_run { "would be removed" } else { "removed" };
+
+    println!(
+        "{} state(s) and {} file(s) {action} {}",
+        report.removed_states,
+        report.removed_assets,
+        format!("({} reclaimed)", humansize::format_size(report.bytes, BINARY)).dim()
+    );
+}
+
+/// Format a state history entry, with timestamps in the provided timezone
+fn format_history<Tz>(entry: &state::History, tz: &Tz) -> String
+where
+    Tz: TimeZone,
+    Tz::Offset: fmt::Display,
+{
+    let created = entry.created.with_timezone(tz).format("%Y-%m-%d %H:%M:%S %Z");
+    let summary = entry.summary.as_deref().unwrap_or("system transaction");
+
+    let (user, command) = match &entry.origin {
+        Some(origin) => (
+            format!("{} ({})", origin.username.as_deref().unwrap_or("unknown"), origin.uid),
+            origin.command.as_str(),
+        ),
+        None => ("unknown".to_owned(), "unknown"),
+    };
+
+    format!(
+        "State #{} - {summary}
+Created: {created}
+User:    {user}
+Command: {command}
+Changes: +{} -{}
+",
+        entry.state,
+        entry.added.len(),
+        entry.removed.len()
+    )
+}
+
+/// Emit a state description for the TUI, with its asset pool usage if given
+fn print_state(state: State, usage: Option<stats::StateUsage>) {
     let local_time = state.created.with_timezone(&Local);
     let formatted_time = local_time.format("%Y-%m-%d %H:%M:%S %Z");
 
@@ -283,70 +588,92 @@ fn print_state(state: State) {
         println!("{} {desc}", "Description:".bold());
     }
     println!("{} {}", "Packages:".bold(), state.selections.len());
+    for retained in &state.retained {
+        let paths = retained.paths.iter().map(|path| format!("/usr/{path}")).join(", ");
+        println!("{} {paths} ({})", "Retained:".bold(), retained.package);
+    }
+    if let Some(usage) = usage {
+        println!(
+            "{} {} exclusive, {} shared",
+            "Disk usage:".bold(),
+            humansize::format_size(usage.exclusive_bytes, BINARY),
+            humansize::format_size(usage.shared_bytes, BINARY).dim()
+        );
+    }
     println!();
 }
 
-fn print_state_selections(state: State, client: &Client) -> Result<(), Error> {
-    let set = state
+/// Resolve the package metadata of each selection in `state`
+pub(super) fn resolve_selections(state: &State, client: &Client) -> Result<Vec<output::Selection>, Error> {
+    Ok(state
         .selections
-        .into_iter()
-        .map(|s| {
-            let pkg = client.resolve_package(&s.package)?;
-
-            Ok(Format {
-                name: pkg.meta.name.to_string(),
-                revision: Revision {
-                    version: pkg.meta.version_identifier,
-                    release: pkg.meta.source_release,
-                },
-                explicit: s.explicit,
-            })
+        .iter()
+        .map(|s| Ok(output::Selection::new(s, &client.resolve_package(&s.package)?)))
+        .collect::<Result<Vec<_>, client::Error>>()?)
+}
+
+/// The best available candidate of each of the `selections`, omitting
+/// packages no longer offered by any repository
+fn repo_selections(selections: &[output::Selection], client: &Client) -> Vec<output::Selection> {
+    let names = selections.iter().map(|s| package::Name::from(s.name.clone())).collect();
+    let candidates = client.best_available(&names);
+
+    selections
+        .iter()
+        .filter_map(|s| {
+            let candidate = candidates.get(&package::Name::from(s.name.clone()))?;
+            Some(s.with_package(candidate))
         })
-        .collect::<Result<Vec<_>, client::Error>>()?;
+        .collect()
+}
 
-    let max_length = set.iter().map(Format::size).max().unwrap_or_default() + 2;
+fn print_state_selections(set: Vec<output::Selection>) {
+    let size = |item: &output::Selection| item.name.len() + item.version.len() + item.release.to_string().len();
+    let max_length = set.iter().map(size).max().unwrap_or_default() + 2;
 
-    for item in set.clone() {
-        let width = max_length - item.size() + 2;
+    for item in set {
+        let width = max_length - size(&item) + 2;
         let name = if item.explicit {
             item.name.clone().bold()
         } else {
             item.name.clone().dim()
         };
         print!("{name} {:width$} ", " ");
-        println!(
-            "{}-{}",
-            item.revision.version.magenta(),
-            item.revision.release.to_string().dim(),
-        );
+        println!("{}-{}", item.version.magenta(), item.release.to_string().dim());
     }
     println!();
-
-    Ok(())
 }
 
-#[derive(Clone, Debug)]
-struct Format {
-    name: String,
-    revision: Revision,
-    explicit: bool,
-}
+/// Emit the package changes between two states for the TUI
+pub(super) fn print_state_diff(diff: output::StateDiff) {
+    println!(
+        "State #{} -> {}",
+        diff.from.to_string().bold(),
+        diff.to.to_string().bold()
+    );
+    println!();
 
-impl Format {
-    fn size(&self) -> usize {
-        self.name.len() + self.revision.size()
+    if diff.is_empty() {
+        println!("No package changes");
+        return;
     }
-}
-
-#[derive(Clone, Debug)]
-struct Revision {
-    version: String,
-    release: u64,
-}
 
-impl Revision {
-    fn size(&self) -> usize {
-        self.version.len() + self.release.to_string().len()
+    for item in diff.added {
+        println!("{} {} {}-{}", "+".green(), item.name.bold(), item.version, item.release);
+    }
+    for item in diff.removed {
+        println!("{} {} {}-{}", "-".red(), item.name.bold(), item.version, item.release);
+    }
+    for change in diff.changed {
+        println!(
+            "{} {} {}-{} -> {}-{}",
+            "~".yellow(),
+            change.name.bold(),
+            change.from.version.dim(),
+            change.from.release.to_string().dim(),
+            change.to.version.green(),
+            change.to.release.to_string().green(),
+        );
     }
 }
 
@@ -358,8 +685,141 @@ pub enum Error {
     DB(#[from] moss::db::Error),
     #[error("io")]
     Io(#[from] io::Error),
+    #[error("manifest")]
+    Manifest(#[from] manifest::Error),
     #[error("no active state")]
     NoActiveState,
     #[error("invalid state id or range: {0}")]
     InvalidRange(String),
 }
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+    use moss::{
+        Installation, Package, package,
+        registry::{Registry, plugin},
+    };
+
+    use super::*;
+
+    #[test]
+    fn history_formatting() {
+        let created = Utc.with_ymd_and_hms(2026, 3, 1, 9, 30, 0).unwrap();
+
+        let entry = state::History {
+            state: state::Id::from(7),
+            created,
+            summary: Some("Install".to_owned()),
+            origin: Some(state::Origin {
+                command: "moss install helix".to_owned(),
+                uid: 1000,
+                username: Some("serpent".to_owned()),
+            }),
+            added: vec![
+                package::Id::from("helix".to_owned()),
+                package::Id::from("lib".to_owned()),
+            ],
+            removed: vec![package::Id::from("nano".to_owned())],
+        };
+
+        assert_eq!(
+            format_history(&entry, &Utc),
+            "State #7 - Install
+Created: 2026-03-01 09:30:00 UTC
+User:    serpent (1000)
+Command: moss install helix
+Changes: +2 -1
+"
+        );
+
+        // States created before history was recorded
+        let entry = state::History {
+            state: state::Id::from(1),
+            created,
+            summary: None,
+            origin: None,
+            added: vec![],
+            removed: vec![],
+        };
+
+        assert_eq!(
+            format_history(&entry, &Utc),
+            "State #1 - system transaction
+Created: 2026-03-01 09:30:00 UTC
+User:    unknown
+Command: unknown
+Changes: +0 -0
+"
+        );
+    }
+
+    fn available(name: &str, version: &str, release: u64) -> Package {
+        Package {
+            id: package::Id::from(format!("{name}-{release}")),
+            meta: package::Meta {
+                version_identifier: version.to_owned(),
+                source_release: release,
+                ..package::fixture::meta(name)
+            },
+            flags: package::Flags::new().with_available(),
+        }
+    }
+
+    fn selection(name: &str, version: &str, release: u64) -> output::Selection {
+        output::Selection {
+            name: name.to_owned(),
+            version: version.to_owned(),
+            release,
+            explicit: true,
+        }
+    }
+
+    #[test]
+    fn repo_diff() {
+        let mut registry = Registry::default();
+        registry.add_plugin(plugin::Plugin::Test(plugin::Test::new(
+            1,
+            vec![
+                available("bash", "5.2.37", 12),
+                available("openssl", "3.5.0", 3),
+                available("openssl", "3.6.0", 5),
+                available("helix", "25.01", 2),
+            ],
+        )));
+        // Preferred over the newer release of a lower priority repository
+        registry.add_plugin(plugin::Plugin::Test(plugin::Test::new(
+            2,
+            vec![available("openssl", "3.5.1", 4)],
+        )));
+
+        let root = tempfile::tempdir().unwrap();
+        let installation = Installation::open(root.path(), None).unwrap();
+        let client = Client::mocked(installation, registry).unwrap();
+
+        let old = vec![
+            selection("bash", "5.2.37", 12),
+            selection("nano", "8.3", 5),
+            selection("openssl", "3.5.0", 3),
+        ];
+        let new = repo_selections(&old, &client);
+        assert_eq!(new, [selection("bash", "5.2.37", 12), selection("openssl", "3.5.1", 4)]);
+
+        let diff = output::StateDiff::new(state::Id::from(42), old, output::Target::Repo, new);
+        assert_eq!(diff.to.to_string(), "repo");
+
+        // Missing from the repositories
+        assert_eq!(diff.removed, [selection("nano", "8.3", 5)]);
+        // Upgradable
+        assert_eq!(
+            diff.changed
+                .iter()
+                .map(|change| format!("{} {}-{}", change.name, change.to.version, change.to.release))
+                .collect::<Vec<_>>(),
+            ["openssl 3.5.1-4"]
+        );
+        // Equal packages & those not in the state aren't listed
+        assert!(diff.added.is_empty());
+        assert_eq!(serde_json::to_value(&diff).unwrap()["to"], "repo");
+    }
+}
@@ -0,0 +1,80 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+use clap::{ArgAction, ArgMatches, Command, arg};
+use humansize::BINARY;
+use moss::{Client, client};
+use thiserror::Error;
+use tui::Styled;
+
+use super::output;
+
+pub fn command() -> Command {
+    Command::new("stats")
+        .about("Show disk usage statistics")
+        .long_about(
+            "Show disk usage statistics
+
+Reports the number of states, cached stones, unpacked assets and database sizes. Orphaned files are those `moss cache prune` would remove. When a download cache quota is configured, the share of it used by stones is shown.",
+        )
+        .arg(arg!(--json "Shorthand for `--format json`").action(ArgAction::SetTrue))
+}
+
+pub fn handle(args: &ArgMatches, client: &mut Client) -> Result<(), Error> {
+    let stats = client.statistics()?;
+
+    if args.get_flag("json") || output::Format::get(args).is_json() {
+        output::print_json(&output::Stats::new(&stats));
+        return Ok(());
+    }
+
+    let files = |usage: client::stats::Usage| (usage.files.to_string(), Some(usage.bytes));
+    // Share of the download cache quota used by stones
+    let quota = stats.cache_quota.map(|quota| {
+        let used = stats.stones.bytes as f64 / quota.max(1) as f64;
+        ("Stone quota", (format!("{:.0}%", used * 100.0), Some(quota)))
+    });
+    let rows = [
+        ("States", (stats.states.to_string(), None)),
+        ("Archived", (stats.archived_states.to_string(), None)),
+        ("Stones", files(stats.stones)),
+    ]
+    .into_iter()
+    .chain(quota)
+    .chain([
+        ("Assets", files(stats.assets)),
+        ("Orphaned stones", files(stats.orphaned_stones)),
+        ("Orphaned assets", files(stats.orphaned_assets)),
+    ])
+    .map(|(name, (count, bytes))| (name.to_owned(), count, bytes))
+    .chain(
+        stats
+            .databases
+            .iter()
+            .map(|(name, bytes)| (format!("{name}.db"), String::new(), Some(*bytes))),
+    )
+    .collect::<Vec<_>>();
+
+    let name_width = rows.iter().map(|(name, _, _)| name.len()).max().unwrap_or_default();
+    let count_width = rows.iter().map(|(_, count, _)| count.len()).max().unwrap_or_default();
+
+    for (name, count, bytes) in rows {
+        let size = bytes
+            .map(|bytes| humansize::format_size(bytes, BINARY))
+            .unwrap_or_default();
+        // Pad before styling, as styled text ignores the width
+        println!(
+            "{}  {count:>count_width$}  {}",
+            format!("{name:<name_width$}").bold(),
+            size.dim()
+        );
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("client")]
+    Client(#[from] client::Error),
+}
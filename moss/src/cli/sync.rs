@@ -4,10 +4,15 @@
 use std::path::PathBuf;
 
 use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser};
-use moss::{Installation, client::Client, environment, runtime};
+use moss::{
+    client::{self, Client, sync::Pending},
+    environment, runtime,
+};
+use thiserror::Error;
 use tracing::instrument;
+use tui::Styled;
 
-pub use moss::client::Error;
+use super::output;
 
 pub fn command() -> clap::Command {
     Command::command()
@@ -31,44 +36,146 @@ pub struct Command {
     blit_target: Option<PathBuf>,
 
     /// Simulate the sync (dry-run)
+    ///
+    /// Lists pending changes and exits with code 100 if there are any
     #[arg(long)]
     dry_run: bool,
 
+    /// Print pending changes as JSON, same as `--format json`
+    #[arg(long, requires = "dry_run")]
+    json: bool,
+
     /// Sync against the provided system-model.kdl
     ///
     /// Only the repositories and packages from the provided file
     /// will be used to create the new state
     #[arg(value_name = "file", long)]
     import: Option<PathBuf>,
+
+    /// Keep packages matching NAME at their installed release, can be repeated
+    ///
+    /// `*` matches any run of characters and `?` any single character. Excluded
+    /// packages are still added when they're new dependencies. Exclusions can also
+    /// be configured in `sync.d`, i.e. `/etc/moss/sync.d/local.yaml`
+    #[arg(value_name = "NAME", long)]
+    exclude: Vec<String>,
 }
 
 #[instrument(skip_all)]
-pub fn handle(args: &ArgMatches, installation: Installation) -> Result<(), Error> {
+pub fn handle(args: &ArgMatches, client: &mut Client) -> Result<(), Error> {
     let command = Command::from_arg_matches(args).expect("validated by clap");
 
     let yes = *args.get_one::<bool>("yes").unwrap();
     let simulate = command.dry_run;
     let update = command.update;
 
-    let mut client_builder = Client::builder(environment::NAME, installation);
+    // Importing a system model or blitting to a target needs a dedicated client
+    let mut dedicated;
+    let client = if command.import.is_some() || command.blit_target.is_some() {
+        let mut client_builder = Client::builder(environment::NAME, client.installation().clone());
 
-    if let Some(path) = &command.import {
-        client_builder = client_builder.system_model_path(path);
-    }
+        if let Some(path) = &command.import {
+            client_builder = client_builder.system_model_path(path);
+        }
 
-    // Make ephemeral if a blit target was provided
-    if let Some(blit_target) = command.blit_target {
-        client_builder = client_builder.ephemeral(blit_target);
-    }
+        // Make ephemeral if a blit target was provided
+        if let Some(blit_target) = command.blit_target {
+            client_builder = client_builder.ephemeral(blit_target);
+        }
 
-    let mut client = client_builder.build()?;
+        dedicated = client_builder.build()?;
+        &mut dedicated
+    } else {
+        client
+    };
 
     // Update repos if requested
     if update {
         runtime::block_on(client.refresh_repositories())?;
     }
 
-    client.sync(yes, simulate)?;
+    // Pick up stones dropped into the local directories
+    client.refresh_local()?;
+
+    if simulate {
+        let pending = client.pending_sync(&command.exclude)?;
+
+        if command.json || output::Format::get(args).is_json() {
+            output::print_json(&pending);
+        } else {
+            print_pending(&pending);
+        }
+
+        if !pending.is_empty() {
+            return Err(Error::Pending(pending.len()));
+        }
+
+        return Ok(());
+    }
+
+    client.sync(yes, false, &command.exclude)?;
+
+    // The sync succeeded regardless, so the model is only checked on a best effort basis
+    if !client.is_ephemeral()
+        && !tui::is_quiet()
+        && !output::Format::get(args).is_json()
+        && let Ok(Some(drift)) = client.model_status()
+        && !drift.is_empty()
+    {
+        println!(
+            "{}: the system has drifted from its model, see `moss model status`",
+            "Warning".yellow()
+        );
+    }
 
     Ok(())
 }
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("client")]
+    Client(#[from] client::Error),
+    /// Listed by `--dry-run`, exiting with [`moss::ErrorCode::Pending`]
+    #[error("{0} packages are pending a sync")]
+    Pending(usize),
+}
+
+/// Emit a table of pending changes for the TUI
+fn print_pending(pending: &[Pending]) {
+    if pending.is_empty() {
+        println!("No packages to sync");
+        return;
+    }
+
+    let none = "-".to_owned();
+    let width = |f: fn(&Pending) -> Option<&String>| {
+        pending
+            .iter()
+            .map(|p| f(p).unwrap_or(&none).len())
+            .max()
+            .unwrap_or_default()
+    };
+    let name_width = pending.iter().map(|p| p.name.len()).max().unwrap_or_default();
+    let installed_width = width(|p| p.installed.as_ref());
+    let candidate_width = width(|p| p.candidate.as_ref());
+
+    for p in pending {
+        let installed = p.installed.as_ref().unwrap_or(&none);
+        let candidate = p.candidate.as_ref().unwrap_or(&none);
+        let repository = p.repository.as_ref().unwrap_or(&none);
+
+        println!(
+            "{}{:name_pad$}   {}{:installed_pad$} -> {}{:candidate_pad$}   {}",
+            p.name.as_str().bold(),
+            "",
+            installed.as_str().dim(),
+            "",
+            candidate.as_str().green(),
+            "",
+            repository.as_str().dim(),
+            name_pad = name_width - p.name.len(),
+            installed_pad = installed_width - installed.len(),
+            candidate_pad = candidate_width - candidate.len(),
+        );
+    }
+}
@@ -5,8 +5,10 @@
 //! Boot management integration in moss
 
 use std::{
+    collections::BTreeSet,
     io,
-    path::{Path, PathBuf},
+    os::unix::fs::MetadataExt,
+    path::{Component, Path, PathBuf},
     str::FromStr,
     vec,
 };
@@ -22,7 +24,7 @@ use itertools::Itertools;
 use stone::{StonePayloadLayoutFile, StonePayloadLayoutRecord};
 use thiserror::{self, Error};
 
-use crate::{Installation, State, db, package::Id};
+use crate::{Installation, State, db, package::Id, state};
 
 use super::Client;
 
@@ -163,9 +165,33 @@ fn os_schema_for_root(root: &Path) -> Result<Schema, Error> {
     }
 }
 
+/// Returns true if a boot partition is mounted within the alternate `root`
+///
+/// Without one there is nowhere to write entries for the target, and the
+/// partitions of the host mustn't be used in its place
+fn esp_mounted(root: &Path) -> bool {
+    BOOT_DIRS.iter().any(|dir| {
+        let path = root.join(dir);
+        match (fs::metadata(&path), path.parent().map(fs::metadata)) {
+            (Ok(dir), Some(Ok(parent))) => dir.is_dir() && dir.dev() != parent.dev(),
+            _ => false,
+        }
+    })
+}
+
+/// Write boot entries for `state` & its predecessors
+///
+/// Boot partitions are only detected & mounted for the host. For an
+/// alternate root, synchronization is skipped unless a boot partition is
+/// mounted within it.
 pub fn synchronize(client: &Client, state: &State) -> Result<(), Error> {
     let root = client.installation.root.clone();
-    let is_native = root.to_string_lossy() == "/";
+    let is_native = client.installation.is_host();
+
+    if !is_native && !esp_mounted(&root) {
+        log::info!("Skipping boot synchronization, no boot partition is mounted in {root:?}");
+        return Ok(());
+    }
     // Create an appropriate configuration
     let config = blsforme::Configuration {
         root: if is_native {
@@ -262,13 +288,195 @@ pub fn synchronize(client: &Client, state: &State) -> Result<(), Error> {
     Ok(())
 }
 
+/// Boot directories, relative to the root, which may hold loader entries
+const BOOT_DIRS: &[&str] = &["efi", "boot", "boot/efi"];
+
+/// Loader entries & kernel blobs belonging to states no longer recorded
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Cleanup {
+    /// Loader entries written by [`synchronize`] for a removed state
+    pub entries: Vec<PathBuf>,
+    /// Kernels & initrds referenced only by those entries
+    pub blobs: Vec<PathBuf>,
+}
+
+impl Cleanup {
+    /// All paths to be removed, entries first
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.entries.iter().chain(&self.blobs).map(PathBuf::as_path)
+    }
+
+    fn extend(&mut self, other: Cleanup) {
+        self.entries.extend(other.entries);
+        self.blobs.extend(other.blobs);
+    }
+}
+
+/// Remove boot entries & kernel blobs of states not in `live_state_ids`
+///
+/// Only entries carrying the `moss.fstx` snippet added by [`synchronize`] are
+/// considered, anything else in the loader directory is left untouched. When
+/// `dry_run` is set, nothing is removed.
+pub fn cleanup(client: &Client, live_state_ids: &BTreeSet<state::Id>, dry_run: bool) -> Result<Cleanup, Error> {
+    let root = &client.installation.root;
+    let is_native = client.installation.is_host();
+    let config = blsforme::Configuration {
+        root: blsforme::Root::Native(root.clone()),
+        vfs: "/".into(),
+    };
+
+    // Boot partitions are only mounted on demand for a native run
+    let manager = if is_native {
+        match blsforme::Manager::new(&config) {
+            Ok(manager) => Some(manager),
+            // Probably a topology failure, nothing we can clean
+            Err(_) => return Ok(Cleanup::default()),
+        }
+    } else {
+        None
+    };
+    let _mounts = manager.as_ref().map(|m| m.mount_partitions()).transpose()?;
+
+    let mut seen = BTreeSet::new();
+    let mut cleanup = Cleanup::default();
+
+    for dir in BOOT_DIRS {
+        let boot_dir = root.join(dir);
+        // The ESP & XBOOTLDR may be one and the same, only visit each once
+        let Ok(canonical) = fs::canonicalize(&boot_dir) else {
+            continue;
+        };
+        if !seen.insert(canonical) {
+            continue;
+        }
+
+        let stale = stale_in(&boot_dir, live_state_ids)?;
+        if !dry_run {
+            remove(&boot_dir, &stale)?;
+        }
+        cleanup.extend(stale);
+    }
+
+    Ok(cleanup)
+}
+
+/// A parsed loader entry within `loader/entries`
+#[derive(Debug)]
+struct LoaderEntry {
+    path: PathBuf,
+    /// State the entry was written for, if written by moss
+    state: Option<state::Id>,
+    /// Kernel & initrd paths, relative to the boot directory
+    blobs: Vec<String>,
+}
+
+impl LoaderEntry {
+    fn parse(path: PathBuf, contents: &str) -> Self {
+        let mut fstx = None;
+        let mut blobs = vec![];
+
+        for line in contents.lines() {
+            let Some((key, value)) = line.trim().split_once(char::is_whitespace) else {
+                continue;
+            };
+            let value = value.trim();
+
+            match key {
+                "linux" | "initrd" | "efi" => blobs.push(value.to_owned()),
+                "options" => {
+                    fstx = value
+                        .split_whitespace()
+                        .find_map(|opt| opt.strip_prefix("moss.fstx="))
+                        .and_then(|id| id.parse::<i32>().ok());
+                }
+                _ => {}
+            }
+        }
+
+        // Entries are named after the state they boot, ensure both agree so
+        // hand written entries reusing the cmdline aren't mistaken for ours
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+        let state = fstx.filter(|id| stem.ends_with(&format!("-{id}"))).map(state::Id::from);
+
+        Self { path, state, blobs }
+    }
+}
+
+/// Find loader entries & blobs under `boot_dir` for states not in `live`
+fn stale_in(boot_dir: &Path, live: &BTreeSet<state::Id>) -> Result<Cleanup, Error> {
+    let entries_dir = boot_dir.join("loader").join("entries");
+    let dir = match fs::read_dir(&entries_dir) {
+        Ok(dir) => dir,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Cleanup::default()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut entries = vec![];
+    for item in dir {
+        let path = item?.path();
+        if path.extension().is_none_or(|ext| ext != "conf") || !path.is_file() {
+            continue;
+        }
+        let contents = fs::read_to_string(&path)?;
+        entries.push(LoaderEntry::parse(path, &contents));
+    }
+
+    let (stale, kept): (Vec<_>, Vec<_>) = entries
+        .into_iter()
+        .partition(|entry| entry.state.is_some_and(|id| !live.contains(&id)));
+
+    // Kernels are shared between entries of the same version, so anything
+    // still referenced by a surviving entry, ours or not, must stay
+    let referenced = kept.iter().flat_map(|e| &e.blobs).collect::<BTreeSet<_>>();
+
+    let blobs = stale
+        .iter()
+        .flat_map(|e| &e.blobs)
+        .filter(|blob| !referenced.contains(blob))
+        .filter_map(|blob| blob_path(boot_dir, blob))
+        .filter(|path| path.is_file())
+        .collect::<BTreeSet<_>>();
+
+    Ok(Cleanup {
+        entries: stale.into_iter().map(|e| e.path).sorted().collect(),
+        blobs: blobs.into_iter().collect(),
+    })
+}
+
+/// Resolve an entry's blob path within `boot_dir`, refusing to escape it
+fn blob_path(boot_dir: &Path, blob: &str) -> Option<PathBuf> {
+    let relative = Path::new(blob.trim_start_matches('/'));
+    relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+        .then(|| boot_dir.join(relative))
+}
+
+/// Remove the `stale` files, along with any kernel directories left empty
+fn remove(boot_dir: &Path, stale: &Cleanup) -> Result<(), Error> {
+    for path in stale.paths() {
+        fs::remove_file(path)?;
+    }
+
+    for blob in &stale.blobs {
+        for dir in blob.ancestors().skip(1).take_while(|dir| *dir != boot_dir) {
+            // Fails once a directory still has content, which is fine
+            if fs::remove_dir(dir).is_err() {
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
 pub fn print_status(installation: &Installation) -> Result<(), Error> {
     fn display_optional_path(path: Option<&Path>) -> std::path::Display<'_> {
         path.unwrap_or_else(|| "none".as_ref()).display()
     }
 
     let root = &installation.root;
-    let is_native = root == Path::new("/");
+    let is_native = installation.is_host();
     let config = blsforme::Configuration {
         root: if is_native {
             blsforme::Root::Native(root.clone())
@@ -300,3 +508,143 @@ pub fn print_status(installation: &Installation) -> Result<(), Error> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write(path: PathBuf, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    fn entry(kernel: &str, state: i32) -> String {
+        format!(
+            "title AerynOS\nlinux /EFI/aerynos/{kernel}/vmlinuz\ninitrd /EFI/aerynos/{kernel}/initrd\noptions root=UUID=abc rw moss.fstx={state}\n"
+        )
+    }
+
+    #[test]
+    fn stale_entries() {
+        let esp = tempfile::tempdir().unwrap();
+        let esp = esp.path();
+        let entries = esp.join("loader").join("entries");
+
+        // State 1 boots an old kernel nothing else uses, state 2 shares
+        // its kernel with the live state 3
+        write(entries.join("aerynos-6.6.1-1.conf"), &entry("6.6.1", 1));
+        write(entries.join("aerynos-6.6.2-2.conf"), &entry("6.6.2", 2));
+        write(entries.join("aerynos-6.6.2-3.conf"), &entry("6.6.2", 3));
+        for kernel in ["6.6.1", "6.6.2"] {
+            write(esp.join("EFI/aerynos").join(kernel).join("vmlinuz"), "kernel");
+            write(esp.join("EFI/aerynos").join(kernel).join("initrd"), "initrd");
+        }
+
+        // Foreign entries, including one reusing a moss cmdline
+        write(
+            entries.join("windows.conf"),
+            "title Windows\nefi /EFI/Microsoft/Boot/bootmgfw.efi\n",
+        );
+        write(esp.join("EFI/Microsoft/Boot/bootmgfw.efi"), "windows");
+        write(entries.join("custom.conf"), &entry("6.6.1", 1));
+        write(entries.join("notes.txt"), &entry("6.6.1", 1));
+
+        let live = [3].into_iter().map(state::Id::from).collect::<BTreeSet<_>>();
+        let stale = stale_in(esp, &live).unwrap();
+
+        assert_eq!(
+            stale.entries,
+            vec![
+                entries.join("aerynos-6.6.1-1.conf"),
+                entries.join("aerynos-6.6.2-2.conf")
+            ]
+        );
+        // The custom entry still references the old kernel
+        assert!(stale.blobs.is_empty());
+
+        fs::remove_file(entries.join("custom.conf")).unwrap();
+        let stale = stale_in(esp, &live).unwrap();
+        assert_eq!(
+            stale.blobs,
+            vec![
+                esp.join("EFI/aerynos/6.6.1/initrd"),
+                esp.join("EFI/aerynos/6.6.1/vmlinuz")
+            ]
+        );
+
+        remove(esp, &stale).unwrap();
+
+        assert!(!entries.join("aerynos-6.6.1-1.conf").exists());
+        assert!(!entries.join("aerynos-6.6.2-2.conf").exists());
+        assert!(!esp.join("EFI/aerynos/6.6.1").exists());
+        assert!(entries.join("aerynos-6.6.2-3.conf").exists());
+        assert!(esp.join("EFI/aerynos/6.6.2/vmlinuz").exists());
+        assert!(entries.join("windows.conf").exists());
+        assert!(entries.join("notes.txt").exists());
+        assert!(esp.join("EFI/Microsoft/Boot/bootmgfw.efi").exists());
+
+        // Nothing left to clean
+        assert_eq!(stale_in(esp, &live).unwrap(), Cleanup::default());
+    }
+
+    #[test]
+    fn escaping_blobs() {
+        let boot = Path::new("/efi");
+        assert_eq!(
+            blob_path(boot, "/EFI/a/vmlinuz"),
+            Some(PathBuf::from("/efi/EFI/a/vmlinuz"))
+        );
+        assert_eq!(blob_path(boot, "/EFI/../../etc/passwd"), None);
+    }
+
+    #[test]
+    fn alternate_root_without_esp() {
+        use crate::{Installation, registry::Registry, state::Selection};
+
+        let root = tempfile::tempdir().unwrap();
+        let installation = Installation::open(root.path(), None).unwrap();
+        let client = Client::mocked(installation, Registry::default()).unwrap();
+
+        // Plain directories aren't mounted boot partitions
+        fs::create_dir_all(root.path().join("boot/efi")).unwrap();
+        assert!(!esp_mounted(root.path()));
+
+        // A state shipping a bootloader, which would otherwise need an os-release
+        let package = Id::from("systemd-boot");
+        client
+            .layout_db
+            .add(
+                &package,
+                &StonePayloadLayoutRecord {
+                    uid: 0,
+                    gid: 0,
+                    mode: 0o644,
+                    tag: 0,
+                    file: StonePayloadLayoutFile::Regular(1, "lib/systemd/boot/efi/systemd-bootx64.efi".into()),
+                },
+            )
+            .unwrap();
+        let state = client
+            .state_db
+            .add(&[Selection::explicit(package)], &[], None, None)
+            .unwrap();
+
+        let before = walk(root.path());
+        synchronize(&client, &state).unwrap();
+        assert_eq!(walk(root.path()), before);
+    }
+
+    /// All paths nested under `dir`
+    fn walk(dir: &Path) -> Vec<PathBuf> {
+        let mut paths = vec![];
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                paths.extend(walk(&path));
+            }
+            paths.push(path);
+        }
+        paths.sort();
+        paths
+    }
+}
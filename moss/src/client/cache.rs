@@ -9,6 +9,7 @@ use std::{
     io,
     path::PathBuf,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use snafu::{OptionExt, ResultExt as _, Snafu, ensure};
@@ -16,7 +17,7 @@ use stone::{StoneDecodedPayload, StoneDigestWriter, StoneDigestWriterHasher, Sto
 use tracing::warn;
 use url::Url;
 
-use crate::{Installation, package, request, util};
+use crate::{Installation, environment, package, request, util};
 
 /// Synchronized set of assets that are currently being
 /// unpacked. Used to prevent unpacking the same asset
@@ -77,6 +78,62 @@ impl Progress {
     }
 }
 
+/// Source of package downloads for [`super::Client::cache_packages`]
+pub(crate) trait Fetcher: Sync {
+    /// Fetch a package, see [`fetch`]
+    async fn fetch(
+        &self,
+        meta: &package::Meta,
+        installation: &Installation,
+        on_progress: impl Fn(Progress),
+    ) -> Result<Download, FetchError>;
+
+    /// Delay before retrying after the failed `attempt`, starting from 0
+    fn backoff(&self, attempt: u32) -> Duration {
+        environment::FETCH_RETRY_BACKOFF * 2u32.pow(attempt)
+    }
+}
+
+/// Fetches packages from their repository
+pub(crate) struct Network;
+
+impl Fetcher for Network {
+    async fn fetch(
+        &self,
+        meta: &package::Meta,
+        installation: &Installation,
+        on_progress: impl Fn(Progress),
+    ) -> Result<Download, FetchError> {
+        fetch(meta, installation, on_progress).await
+    }
+}
+
+/// Fetches packages without the network, from the download cache or
+/// local repositories only
+pub(crate) struct Offline;
+
+impl Fetcher for Offline {
+    async fn fetch(
+        &self,
+        meta: &package::Meta,
+        installation: &Installation,
+        on_progress: impl Fn(Progress),
+    ) -> Result<Download, FetchError> {
+        let url = meta.uri.as_deref().context(MissingUrlSnafu)?;
+        let parsed = url.parse::<Url>().context(InvalidUrlSnafu { url })?;
+        let hash = meta.hash.as_ref().context(MissingHashSnafu)?;
+
+        if parsed.scheme() != "file" && !is_cached(&download_path(installation, hash)?, hash).await? {
+            return OfflineSnafu {
+                package: meta.name.to_string(),
+            }
+            .fail();
+        }
+
+        fetch(meta, installation, on_progress).await
+    }
+}
+
 /// Fetch a package with the provided [`package::Meta`] and [`Installation`] and return a [`Download`] on success.
 pub async fn fetch(
     meta: &package::Meta,
@@ -95,19 +152,7 @@ pub async fn fetch(
         fs::create_dir_all(parent).await?;
     }
 
-    let is_cached = async || -> Result<bool, FetchError> {
-        if fs::try_exists(&destination_path).await? {
-            // Ensure content is valid before trusting it
-            let mut file = fs::File::open(&destination_path).await?;
-            let actual_hash = util::sha256_hash_async(&mut file).await?;
-
-            return Ok(*hash == actual_hash);
-        }
-
-        Ok(false)
-    };
-
-    match is_cached().await {
+    match is_cached(&destination_path, hash).await {
         Ok(true) => {
             return Ok(Download {
                 id: meta.id().into(),
@@ -153,6 +198,21 @@ pub async fn fetch(
     })
 }
 
+/// Whether a valid download with `hash` exists at `path`
+async fn is_cached(path: &Path, hash: &str) -> Result<bool, FetchError> {
+    use fs_err::tokio as fs;
+
+    if fs::try_exists(path).await? {
+        // Ensure content is valid before trusting it
+        let mut file = fs::File::open(path).await?;
+        let actual_hash = util::sha256_hash_async(&mut file).await?;
+
+        return Ok(hash == actual_hash);
+    }
+
+    Ok(false)
+}
+
 /// A package that has been downloaded to the installation
 pub struct Download {
     id: package::Id,
@@ -378,6 +438,28 @@ pub enum UnpackError {
     },
 }
 
+impl FetchError {
+    /// Whether the failure may be transient, so fetching again could succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            FetchError::Request { source } => match source {
+                // The server told us no, asking again won't change its mind
+                request::Error::Fetch(error) => !error.status().is_some_and(|status| status.is_client_error()),
+                request::Error::Read(_) => true,
+                request::Error::DecodeJson(_) => false,
+            },
+            // Most likely a truncated or corrupted transfer
+            FetchError::BinaryStoneHashMismatch { .. } => true,
+            FetchError::MissingHash
+            | FetchError::MalformedHash { .. }
+            | FetchError::MissingUrl
+            | FetchError::InvalidUrl { .. }
+            | FetchError::Offline { .. }
+            | FetchError::Io { .. } => false,
+        }
+    }
+}
+
 #[derive(Debug, Snafu)]
 pub enum FetchError {
     #[snafu(display("missing hash"))]
@@ -398,4 +480,6 @@ pub enum FetchError {
         expected: String,
         actual: String,
     },
+    #[snafu(display("{package} isn't cached and the network can't be used offline"))]
+    Offline { package: String },
 }
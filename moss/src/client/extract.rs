@@ -2,18 +2,21 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use std::{
-    io::{self, Read, Seek, SeekFrom},
+    collections::BTreeMap,
+    io::{self, Read, Seek, SeekFrom, Write},
+    os::unix::fs::{PermissionsExt, symlink},
     path::{Path, PathBuf},
 };
 
+use fnmatch::Pattern;
 use fs_err::{self as fs, File};
-use stone::{StoneDecodedPayload, StoneReadError};
+use stone::{StoneDecodedPayload, StonePayloadLayoutFile, StonePayloadLayoutRecord, StoneReadError};
 use thiserror::Error;
 use tui::{ProgressBar, ProgressStyle};
 
 use crate::{
     Installation,
-    client::{self, cache::asset_path},
+    client::{self, cache::asset_path, interaction::Terminal},
     installation,
     package::{self, MissingMetaFieldError},
     util,
@@ -115,7 +118,7 @@ pub fn extract(stones: Vec<&PathBuf>, output_dir: &Path) -> Result<(), Error> {
             .collect::<Vec<_>>();
         let vfs = client::vfs(records)?;
 
-        client::blit_root(&installation, &vfs, &extraction_root.canonicalize()?)?;
+        client::blit_root(&installation, &vfs, &extraction_root.canonicalize()?, &Terminal)?;
     }
 
     // Clean up transient .moss install
@@ -124,6 +127,198 @@ pub fn extract(stones: Vec<&PathBuf>, output_dir: &Path) -> Result<(), Error> {
     Ok(())
 }
 
+/// Installed paths of the layout entries in `stone` matching any of `patterns`,
+/// or of all entries if there are none
+pub fn list(stone: &Path, patterns: &[Pattern]) -> Result<Vec<String>, Error> {
+    let (_, payloads) = read(stone)?;
+
+    Ok(matching(&payloads, patterns).map(|(path, _)| path).collect())
+}
+
+/// Extract only the files & symlinks of `stone` matching any of `patterns`
+/// to `output_dir/<package id>`, preserving their modes
+///
+/// Matching content is split out of the content payload as it streams by,
+/// without unpacking the rest. Returns the extracted paths.
+pub fn extract_matching(stone: &Path, patterns: &[Pattern], output_dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let (mut reader, payloads) = read(stone)?;
+
+    let meta = payloads
+        .iter()
+        .find_map(StoneDecodedPayload::meta)
+        .ok_or(Error::MissingMeta)?;
+    let pkg = package::Meta::from_stone_payload(&meta.body).map_err(Error::MalformedMeta)?;
+    let extraction_root = output_dir.join(pkg.id().to_string());
+
+    let mut extracted = vec![];
+    let mut wanted = BTreeMap::<u128, Vec<(PathBuf, u32)>>::new();
+
+    for (path, record) in matching(&payloads, patterns) {
+        let target = extraction_root.join(path.trim_start_matches('/'));
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        match &record.file {
+            StonePayloadLayoutFile::Regular(hash, _) => {
+                wanted.entry(*hash).or_default().push((target.clone(), record.mode));
+            }
+            StonePayloadLayoutFile::Symlink(source, _) => {
+                if target.is_symlink() {
+                    fs::remove_file(&target)?;
+                }
+                symlink(source.as_str(), &target)?;
+            }
+            // Directories are created as parents, special files aren't extracted
+            _ => continue,
+        }
+        extracted.push(target);
+    }
+
+    if !wanted.is_empty() {
+        let content = payloads
+            .iter()
+            .find_map(StoneDecodedPayload::content)
+            .ok_or(Error::MissingContent)?;
+        let mut ranges = payloads
+            .iter()
+            .filter_map(StoneDecodedPayload::index)
+            .flat_map(|p| &p.body)
+            .filter_map(|idx| {
+                Some(Range {
+                    start: idx.start,
+                    end: idx.end,
+                    targets: wanted.remove(&idx.digest)?,
+                })
+            })
+            .collect::<Vec<_>>();
+        ranges.sort_by_key(|range| range.start);
+
+        if let Some(hash) = wanted.into_keys().next() {
+            return Err(Error::MissingAsset(format!("{hash:02x}")));
+        }
+
+        let mut splitter = Splitter::new(ranges);
+        reader.unpack_content(content, &mut splitter)?;
+        splitter.finish()?;
+    }
+
+    Ok(extracted)
+}
+
+/// Open `stone` & decode all its payloads
+fn read(stone: &Path) -> Result<(stone::StoneReader<File>, Vec<StoneDecodedPayload>), Error> {
+    let mut reader = stone::read(File::open(stone)?)?;
+    let payloads = reader.payloads()?.collect::<Result<Vec<_>, _>>()?;
+    Ok((reader, payloads))
+}
+
+/// Layout entries & their installed path, matching any of `patterns` or all if empty
+fn matching<'a>(
+    payloads: &'a [StoneDecodedPayload],
+    patterns: &'a [Pattern],
+) -> impl Iterator<Item = (String, &'a StonePayloadLayoutRecord)> + 'a {
+    payloads
+        .iter()
+        .filter_map(StoneDecodedPayload::layout)
+        .flat_map(|p| &p.body)
+        .map(|record| (format!("/usr/{}", record.file.target()), record))
+        .filter(|(path, _)| patterns.is_empty() || patterns.iter().any(|p| p.match_path(path).is_some()))
+}
+
+/// Byte range of an asset within the content payload
+struct Range {
+    start: u64,
+    end: u64,
+    /// Paths & modes to write the asset to
+    targets: Vec<(PathBuf, u32)>,
+}
+
+/// Writes the byte ranges of wanted assets to their targets as the
+/// decompressed content payload is written through it
+struct Splitter {
+    /// Sorted & non overlapping
+    ranges: Vec<Range>,
+    /// Index into `ranges` of the first range not fully written
+    next: usize,
+    /// Files of ranges being written, starting with `ranges[next]`
+    open: Vec<Vec<File>>,
+    offset: u64,
+}
+
+impl Splitter {
+    fn new(ranges: Vec<Range>) -> Self {
+        Self {
+            ranges,
+            next: 0,
+            open: vec![],
+            offset: 0,
+        }
+    }
+
+    /// Create the files of `ranges[index]`
+    fn create(&self, index: usize) -> io::Result<Vec<File>> {
+        self.ranges[index]
+            .targets
+            .iter()
+            .map(|(path, mode)| {
+                let file = File::create(path)?;
+                file.set_permissions(std::fs::Permissions::from_mode(mode & 0o7777))?;
+                Ok(file)
+            })
+            .collect()
+    }
+
+    /// Ensure every range was written in full
+    fn finish(mut self) -> Result<(), Error> {
+        // Empty assets at the very end are never reached by a write
+        while let Some(range) = self.ranges.get(self.next) {
+            if range.start != range.end || range.start != self.offset {
+                return Err(Error::TruncatedContent(range.targets[0].0.clone()));
+            }
+            self.create(self.next)?;
+            self.next += 1;
+        }
+        Ok(())
+    }
+}
+
+impl Write for Splitter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let end = self.offset + buf.len() as u64;
+
+        // Open ranges starting within this chunk
+        while self.next + self.open.len() < self.ranges.len() && self.ranges[self.next + self.open.len()].start < end {
+            let files = self.create(self.next + self.open.len())?;
+            self.open.push(files);
+        }
+
+        for (files, range) in self.open.iter_mut().zip(&self.ranges[self.next..]) {
+            let from = range.start.saturating_sub(self.offset) as usize;
+            let to = (range.end.saturating_sub(self.offset) as usize).min(buf.len());
+            for file in files {
+                file.write_all(&buf[from..to])?;
+            }
+        }
+
+        // Close ranges which ended within this chunk
+        let done = self.ranges[self.next..]
+            .iter()
+            .take(self.open.len())
+            .take_while(|range| range.end <= end)
+            .count();
+        self.open.drain(..done);
+        self.next += done;
+
+        self.offset = end;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("client")]
@@ -132,6 +327,15 @@ pub enum Error {
     #[error("Missing metadata")]
     MissingMeta,
 
+    #[error("Missing content payload")]
+    MissingContent,
+
+    #[error("Missing asset {0} in content index")]
+    MissingAsset(String),
+
+    #[error("Content ended before {0:?} was written")]
+    TruncatedContent(PathBuf),
+
     #[error("malformed meta")]
     MalformedMeta(#[from] MissingMetaFieldError),
 
@@ -144,3 +348,74 @@ pub enum Error {
     #[error("installation")]
     Installation(#[from] installation::Error),
 }
+
+#[cfg(test)]
+mod test {
+    use xxhash_rust::xxh3::xxh3_128;
+
+    use super::*;
+
+    const STONE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../test/bash-completion-2.11-1-1-x86_64.stone"
+    );
+
+    fn patterns(globs: &[&str]) -> Vec<Pattern> {
+        globs.iter().map(|glob| glob.parse().unwrap()).collect()
+    }
+
+    /// Extracted file must hash to its layout entry & keep its mode
+    fn assert_extracted(root: &Path, path: &str) {
+        let (_, payloads) = read(Path::new(STONE)).unwrap();
+        let patterns = patterns(&[path]);
+        let (_, record) = matching(&payloads, &patterns).next().unwrap();
+        let StonePayloadLayoutFile::Regular(hash, _) = &record.file else {
+            panic!("{path} isn't a regular file");
+        };
+
+        let target = root.join(path.trim_start_matches('/'));
+        assert_eq!(xxh3_128(&fs::read(&target).unwrap()), *hash);
+        assert_eq!(
+            fs::metadata(&target).unwrap().permissions().mode() & 0o7777,
+            record.mode & 0o7777
+        );
+    }
+
+    #[test]
+    fn single_file() {
+        let output = tempfile::tempdir().unwrap();
+        let path = "/usr/share/bash-completion/completions/7z";
+
+        let extracted = extract_matching(Path::new(STONE), &patterns(&[path]), output.path()).unwrap();
+
+        let root = output.path().join("bash-completion-2.11-1.x86_64");
+        assert_eq!(extracted, [root.join(path.trim_start_matches('/'))]);
+        assert_extracted(&root, path);
+
+        // Nothing else is extracted
+        let completions = fs::read_dir(root.join("usr/share/bash-completion/completions")).unwrap();
+        assert_eq!(completions.count(), 1);
+    }
+
+    #[test]
+    fn glob() {
+        let output = tempfile::tempdir().unwrap();
+        let globs = patterns(&["/usr/share/bash-completion/completions/7z*"]);
+
+        assert_eq!(
+            list(Path::new(STONE), &globs).unwrap(),
+            [
+                "/usr/share/bash-completion/completions/7z",
+                "/usr/share/bash-completion/completions/7za"
+            ]
+        );
+
+        let extracted = extract_matching(Path::new(STONE), &globs, output.path()).unwrap();
+        assert_eq!(extracted.len(), 2);
+
+        let root = output.path().join("bash-completion-2.11-1.x86_64");
+        assert_extracted(&root, "/usr/share/bash-completion/completions/7z");
+        let link = root.join("usr/share/bash-completion/completions/7za");
+        assert_eq!(fs::read_link(&link).unwrap(), Path::new("7z"));
+    }
+}
@@ -0,0 +1,278 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Files of a package which differ between two states, as shown by
+//! `moss query filediff`
+
+use std::collections::BTreeMap;
+
+use stone::StonePayloadLayoutRecord;
+
+use crate::{Client, client, db::meta, package, state};
+
+/// The package selected in one side of a [`FileDiff`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Side {
+    pub state: state::Id,
+    pub package: package::Id,
+}
+
+/// Layout entries of a package which differ between two states, each sorted
+/// by path, see [`Client::package_file_diff`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileDiff {
+    pub name: package::Name,
+    pub from: Side,
+    pub to: Side,
+    /// Entries only in the layout of `to`
+    pub added: Vec<StonePayloadLayoutRecord>,
+    /// Entries only in the layout of `from`
+    pub removed: Vec<StonePayloadLayoutRecord>,
+    /// Entries at the same path whose contents or mode differ
+    pub changed: Vec<Change>,
+}
+
+/// An entry present at the same path in both layouts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub old: StonePayloadLayoutRecord,
+    pub new: StonePayloadLayoutRecord,
+}
+
+impl Change {
+    /// Path of the entry, relative to `/usr`
+    pub fn path(&self) -> &str {
+        self.new.file.target()
+    }
+
+    /// Returns `true` if the file type, hash or symlink target differ
+    pub fn content_changed(&self) -> bool {
+        self.old.file != self.new.file
+    }
+
+    /// Returns `true` if the permission bits differ
+    pub fn mode_changed(&self) -> bool {
+        self.old.mode != self.new.mode
+    }
+}
+
+impl FileDiff {
+    /// Diff the `old` layout of a package against its `new` one, keyed by path
+    pub fn new(
+        name: package::Name,
+        from: Side,
+        old: Vec<StonePayloadLayoutRecord>,
+        to: Side,
+        new: Vec<StonePayloadLayoutRecord>,
+    ) -> Self {
+        let by_path = |layouts: Vec<StonePayloadLayoutRecord>| {
+            layouts
+                .into_iter()
+                .map(|layout| (layout.file.target().to_owned(), layout))
+                .collect::<BTreeMap<_, _>>()
+        };
+        let mut old = by_path(old);
+        let new = by_path(new);
+
+        let mut added = vec![];
+        let mut changed = vec![];
+        for (path, new) in new {
+            match old.remove(&path) {
+                None => added.push(new),
+                Some(old) => {
+                    let change = Change { old, new };
+                    if change.content_changed() || change.mode_changed() {
+                        changed.push(change);
+                    }
+                }
+            }
+        }
+
+        Self {
+            name,
+            from,
+            to,
+            added,
+            removed: old.into_values().collect(),
+            changed,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+pub fn package_file_diff(
+    client: &Client,
+    name: &package::Name,
+    from: state::Id,
+    to: state::Id,
+) -> Result<FileDiff, client::Error> {
+    // Every revision of the package ever installed
+    let revisions = client
+        .install_db
+        .query(Some(meta::Filter::Name(name.clone())))?
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect::<Vec<_>>();
+
+    let side = |state: state::Id| -> Result<Side, client::Error> {
+        let selected = client.state_db.get(state)?.selections;
+        let package = revisions
+            .iter()
+            .find(|id| selected.iter().any(|selection| selection.package == **id))
+            .ok_or_else(|| client::Error::NotInState(name.clone(), state))?;
+
+        Ok(Side {
+            state,
+            package: package.clone(),
+        })
+    };
+    let from = side(from)?;
+    let to = side(to)?;
+
+    let layouts = |side: &Side| -> Result<Vec<StonePayloadLayoutRecord>, client::Error> {
+        Ok(client
+            .layout_db
+            .query([&side.package])?
+            .into_iter()
+            .map(|(_, layout)| layout)
+            .collect())
+    };
+    let old = layouts(&from)?;
+    let new = layouts(&to)?;
+
+    Ok(FileDiff::new(name.clone(), from, old, to, new))
+}
+
+#[cfg(test)]
+mod test {
+    use stone::StonePayloadLayoutFile;
+
+    use super::*;
+    use crate::{Installation, registry::Registry};
+
+    fn regular(path: &str, hash: u128, mode: u32) -> StonePayloadLayoutRecord {
+        StonePayloadLayoutRecord {
+            uid: 0,
+            gid: 0,
+            mode,
+            tag: 0,
+            file: StonePayloadLayoutFile::Regular(hash, path.into()),
+        }
+    }
+
+    fn symlink(path: &str, target: &str) -> StonePayloadLayoutRecord {
+        StonePayloadLayoutRecord {
+            uid: 0,
+            gid: 0,
+            mode: 0o777,
+            tag: 0,
+            file: StonePayloadLayoutFile::Symlink(target.into(), path.into()),
+        }
+    }
+
+    fn old() -> Vec<StonePayloadLayoutRecord> {
+        vec![
+            regular("bin/nano", 1, 0o755),
+            regular("share/nano/removed.nanorc", 2, 0o644),
+            regular("share/nano/same.nanorc", 3, 0o644),
+            regular("share/nano/mode.nanorc", 4, 0o644),
+            symlink("bin/rnano", "nano"),
+        ]
+    }
+
+    fn new() -> Vec<StonePayloadLayoutRecord> {
+        vec![
+            regular("bin/nano", 10, 0o755),
+            regular("share/nano/added.nanorc", 20, 0o644),
+            regular("share/nano/same.nanorc", 3, 0o644),
+            regular("share/nano/mode.nanorc", 4, 0o600),
+            regular("bin/rnano", 30, 0o755),
+        ]
+    }
+
+    fn side(state: i32, package: &'static str) -> Side {
+        Side {
+            state: state::Id::from(state),
+            package: package::Id::from(package),
+        }
+    }
+
+    #[test]
+    fn change_categories() {
+        let diff = FileDiff::new(
+            package::Name::from("nano".to_owned()),
+            side(1, "nano-1"),
+            old(),
+            side(2, "nano-2"),
+            new(),
+        );
+
+        fn paths(layouts: &[StonePayloadLayoutRecord]) -> Vec<&str> {
+            layouts.iter().map(|layout| layout.file.target()).collect()
+        }
+        assert_eq!(paths(&diff.added), ["share/nano/added.nanorc"]);
+        assert_eq!(paths(&diff.removed), ["share/nano/removed.nanorc"]);
+
+        let changed = diff
+            .changed
+            .iter()
+            .map(|change| (change.path(), change.content_changed(), change.mode_changed()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changed,
+            [
+                // Hash differs
+                ("bin/nano", true, false),
+                // Symlink replaced by a regular file
+                ("bin/rnano", true, true),
+                ("share/nano/mode.nanorc", false, true),
+            ]
+        );
+
+        let same = FileDiff::new(
+            package::Name::from("nano".to_owned()),
+            side(1, "nano-1"),
+            old(),
+            side(2, "nano-1"),
+            old(),
+        );
+        assert!(same.is_empty());
+    }
+
+    #[test]
+    fn resolve_states() {
+        let root = tempfile::tempdir().unwrap();
+        let installation = Installation::open(root.path(), None).unwrap();
+        let client = Client::mocked(installation, Registry::default()).unwrap();
+
+        for (id, layouts) in [("nano-1", old()), ("nano-2", new())] {
+            let id = package::Id::from(id);
+            let meta = package::fixture::meta("nano");
+            client.install_db.add(id.clone(), meta).unwrap();
+            client
+                .layout_db
+                .batch_add(layouts.iter().map(|layout| (&id, layout)))
+                .unwrap();
+        }
+        for package in ["nano-1", "nano-2", "bash"] {
+            let selections = [state::Selection::explicit(package::Id::from(package))];
+            client.state_db.add(&selections, &[], None, None).unwrap();
+        }
+
+        let name = package::Name::from("nano".to_owned());
+        let diff = client
+            .package_file_diff(&name, state::Id::from(1), state::Id::from(2))
+            .unwrap();
+        assert_eq!(diff.from, side(1, "nano-1"));
+        assert_eq!(diff.to, side(2, "nano-2"));
+        assert_eq!(diff.changed.len(), 3);
+
+        assert!(matches!(
+            client.package_file_diff(&name, state::Id::from(1), state::Id::from(3)),
+            Err(client::Error::NotInState(_, state)) if state == state::Id::from(3)
+        ));
+    }
+}
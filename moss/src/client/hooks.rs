@@ -0,0 +1,337 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Site-specific executables run around state changes
+//!
+//! Admins drop executables into `/etc/moss/hooks/pre-transaction.d` or
+//! `/etc/moss/hooks/post-transaction.d` of the installation root, i.e. to
+//! snapshot `/etc` with etckeeper. Hooks run in lexical order of their file
+//! name and receive a JSON [`Payload`] describing the change on stdin.
+//!
+//! A failing pre-transaction hook aborts the change, while post-transaction
+//! failures are only reported.
+
+use std::{
+    io::{self, Write},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{self, ExitStatus, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use fs_err as fs;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{State, package, state};
+
+/// How often a running hook is polled for completion
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// When hooks run relative to the state change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    /// Before anything is changed, failures abort the change
+    Pre,
+    /// Once the change is applied, failures are only reported
+    Post,
+}
+
+impl Stage {
+    /// Directory of the hooks, relative to the installation root
+    fn dir(&self) -> &'static str {
+        match self {
+            Stage::Pre => "etc/moss/hooks/pre-transaction.d",
+            Stage::Post => "etc/moss/hooks/post-transaction.d",
+        }
+    }
+}
+
+/// The state change a hook is run for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Operation {
+    /// A new state is created, i.e. by `moss install`
+    NewState,
+    /// An archived state is activated
+    Activate,
+}
+
+/// Description of a state change, written to the stdin of each hook as JSON
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Payload {
+    pub operation: Operation,
+    pub stage: Stage,
+    /// Summary of a new state, i.e. `Install`
+    pub summary: Option<String>,
+    /// Root of the installation being changed
+    pub root: PathBuf,
+    /// Previously active state, if any
+    pub old_state: Option<i32>,
+    /// State being created or activated, `None` for a new state before it's recorded
+    pub new_state: Option<i32>,
+    /// Packages added relative to the previously active state
+    pub added: Vec<String>,
+    /// Packages removed relative to the previously active state
+    pub removed: Vec<String>,
+}
+
+impl Payload {
+    /// Describe the change from the `old` selections to the `new` ones
+    pub fn new(
+        operation: Operation,
+        root: &Path,
+        old: Option<&State>,
+        new: &[state::Selection],
+        new_state: Option<state::Id>,
+    ) -> Self {
+        let old_packages = old
+            .map(|state| state.selections.iter().map(|s| &s.package).collect::<Vec<_>>())
+            .unwrap_or_default();
+        let new_packages = new.iter().map(|s| &s.package).collect::<Vec<_>>();
+
+        let difference = |a: &[&package::Id], b: &[&package::Id]| {
+            let mut ids = a
+                .iter()
+                .filter(|id| !b.contains(id))
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+
+        Self {
+            operation,
+            stage: Stage::Pre,
+            summary: None,
+            root: root.to_owned(),
+            old_state: old.map(|state| state.id.into()),
+            new_state: new_state.map(Into::into),
+            added: difference(&new_packages, &old_packages),
+            removed: difference(&old_packages, &new_packages),
+        }
+    }
+
+    pub fn with_summary(self, summary: impl ToString) -> Self {
+        Self {
+            summary: Some(summary.to_string()),
+            ..self
+        }
+    }
+
+    /// The same change at another [`Stage`], once the new state is known
+    pub fn at(&self, stage: Stage, new_state: Option<state::Id>) -> Self {
+        Self {
+            stage,
+            new_state: new_state.map(Into::into).or(self.new_state),
+            ..self.clone()
+        }
+    }
+}
+
+/// Executable hooks of `stage` under `root`, in the order they run
+///
+/// Hidden files & anything not executable are ignored
+pub fn discover(root: &Path, stage: Stage) -> io::Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(root.join(stage.dir())) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+
+    let mut hooks = vec![];
+    for entry in entries {
+        let path = entry?.path();
+        let hidden = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_none_or(|name| name.starts_with('.'));
+        if hidden {
+            continue;
+        }
+
+        // Follow symlinks, i.e. to hooks shipped elsewhere
+        let Ok(metadata) = fs::metadata(&path) else {
+            continue;
+        };
+        if metadata.is_file() && metadata.permissions().mode() & 0o111 != 0 {
+            hooks.push(path);
+        }
+    }
+    hooks.sort();
+
+    Ok(hooks)
+}
+
+/// Run the hooks of `payload.stage` under `root` in order, each killed once
+/// it exceeds `timeout`, returning the failures in order
+///
+/// Pre-transaction hooks stop at the first failure, as the change is aborted
+pub fn run(root: &Path, payload: &Payload, timeout: Duration) -> Result<Vec<Error>, Error> {
+    let json = serde_json::to_vec(payload).map_err(Error::Payload)?;
+
+    let mut failures = vec![];
+    for hook in discover(root, payload.stage).map_err(Error::Discover)? {
+        if let Err(error) = execute(&hook, &json, timeout) {
+            failures.push(error);
+
+            if payload.stage == Stage::Pre {
+                break;
+            }
+        }
+    }
+
+    Ok(failures)
+}
+
+/// Run a single `hook`, writing `payload` to its stdin
+fn execute(hook: &Path, payload: &[u8], timeout: Duration) -> Result<(), Error> {
+    let mut child = process::Command::new(hook)
+        .current_dir("/")
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::Spawn(hook.to_owned(), e))?;
+
+    // Write from another thread, as a hook ignoring stdin mustn't block us
+    if let Some(mut stdin) = child.stdin.take() {
+        let payload = payload.to_vec();
+        thread::spawn(move || {
+            // A hook may exit without reading its stdin
+            let _ = stdin.write_all(&payload);
+        });
+    }
+
+    let started = Instant::now();
+    loop {
+        match child.try_wait().map_err(|e| Error::Wait(hook.to_owned(), e))? {
+            Some(status) if status.success() => return Ok(()),
+            Some(status) => return Err(Error::Failed(hook.to_owned(), status)),
+            None if started.elapsed() >= timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(Error::Timeout(hook.to_owned(), timeout));
+            }
+            None => thread::sleep(POLL_INTERVAL),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("discover hooks")]
+    Discover(#[source] io::Error),
+    #[error("serialize hook payload")]
+    Payload(#[source] serde_json::Error),
+    #[error("run hook {0:?}")]
+    Spawn(PathBuf, #[source] io::Error),
+    #[error("wait for hook {0:?}")]
+    Wait(PathBuf, #[source] io::Error),
+    #[error("hook {0:?} failed with {1}")]
+    Failed(PathBuf, ExitStatus),
+    #[error("hook {0:?} timed out after {1:?}")]
+    Timeout(PathBuf, Duration),
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::state::Selection;
+
+    fn write_hook(root: &Path, stage: Stage, name: &str, script: &str, mode: u32) -> PathBuf {
+        let dir = root.join(stage.dir());
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, format!("#!/bin/sh\n{script}\n")).unwrap();
+        fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+        path
+    }
+
+    #[test]
+    fn discovery_order() {
+        let root = tempfile::tempdir().unwrap();
+        assert!(discover(root.path(), Stage::Pre).unwrap().is_empty());
+
+        let second = write_hook(root.path(), Stage::Pre, "20-second", "true", 0o755);
+        let first = write_hook(root.path(), Stage::Pre, "10-first", "true", 0o755);
+        write_hook(root.path(), Stage::Pre, "30-not-executable", "true", 0o644);
+        write_hook(root.path(), Stage::Pre, ".hidden", "true", 0o755);
+        write_hook(root.path(), Stage::Post, "10-post", "true", 0o755);
+
+        assert_eq!(discover(root.path(), Stage::Pre).unwrap(), [first, second]);
+    }
+
+    #[test]
+    fn payload() {
+        let old = State {
+            id: state::Id::from(3),
+            summary: None,
+            description: None,
+            selections: vec![
+                Selection::explicit(package::Id::from("bash")),
+                Selection::explicit(package::Id::from("nano")),
+            ],
+            retained: vec![],
+            created: Utc::now(),
+            kind: state::Kind::Transaction,
+        };
+        let new = [
+            Selection::explicit(package::Id::from("bash")),
+            Selection::explicit(package::Id::from("vim")),
+        ];
+
+        let payload = Payload::new(Operation::NewState, Path::new("/"), Some(&old), &new, None).with_summary("Install");
+        let json = serde_json::to_value(payload.at(Stage::Post, Some(state::Id::from(4)))).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "operation": "new-state",
+                "stage": "post",
+                "summary": "Install",
+                "root": "/",
+                "old_state": 3,
+                "new_state": 4,
+                "added": ["vim"],
+                "removed": ["nano"],
+            })
+        );
+    }
+
+    #[test]
+    fn run_hooks() {
+        let root = tempfile::tempdir().unwrap();
+        let received = root.path().join("received.json");
+        let payload = Payload::new(Operation::Activate, root.path(), None, &[], Some(state::Id::from(2)));
+
+        for stage in [Stage::Pre, Stage::Post] {
+            write_hook(
+                root.path(),
+                stage,
+                "10-record",
+                &format!("cat > {}", received.display()),
+                0o755,
+            );
+            write_hook(root.path(), stage, "20-fail", "exit 3", 0o755);
+            write_hook(root.path(), stage, "30-slow", "exec sleep 10", 0o755);
+        }
+
+        // Post-transaction hooks all run
+        let failures = run(root.path(), &payload.at(Stage::Post, None), Duration::from_millis(200)).unwrap();
+        assert!(matches!(&failures[..], [Error::Failed(..), Error::Timeout(..)]));
+
+        let received = serde_json::from_slice::<serde_json::Value>(&fs::read(&received).unwrap()).unwrap();
+        assert_eq!(received["operation"], "activate");
+        assert_eq!(received["stage"], "post");
+        assert_eq!(received["new_state"], 2);
+
+        // Pre-transaction hooks stop at the first failure
+        let timer = Instant::now();
+        let failures = run(root.path(), &payload, Duration::from_secs(10)).unwrap();
+        assert!(matches!(&failures[..], [Error::Failed(..)]));
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    }
+}
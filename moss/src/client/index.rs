@@ -2,35 +2,211 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use std::{
-    collections::{BTreeMap, btree_map},
+    cmp::Reverse,
+    collections::BTreeMap,
     io,
     path::{Path, PathBuf, StripPrefixError},
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
 use camino::{Utf8Path, Utf8PathBuf};
 use fs_err as fs;
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use stone::{StoneHeaderV1FileType, StoneReadError, StoneWriteError, StoneWriter};
 use thiserror::Error;
-use tui::{MultiProgress, ProgressBar, ProgressStyle, Styled};
+use tui::{
+    MultiProgress, ProgressBar, ProgressStyle, Styled,
+    dialoguer::{Confirm, theme::ColorfulTheme},
+};
 
 use crate::{
     client,
     package::{self, Meta, MissingMetaFieldError},
 };
 
-/// Index a directory of stone files & produce a `stone.index` index file
+/// Current version of the `stone.index.json` format
+pub const MANIFEST_VERSION: u32 = 1;
+
+/// Controls what [`index`] includes
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Newest releases to index per package name & architecture
+    pub keep: usize,
+    /// Delete the stones excluded from the index
+    pub prune: bool,
+    /// Prune without asking for confirmation
+    pub yes: bool,
+    /// Reuse the entries of the existing index for stones unchanged since, see [`Previous`]
+    pub incremental: bool,
+    /// Hash the stones reused while indexing incrementally, reindexing those which differ
+    pub verify: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            keep: 1,
+            prune: false,
+            yes: false,
+            incremental: false,
+            verify: false,
+        }
+    }
+}
+
+/// A stone file & its metadata, as recorded in the index
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub meta: Meta,
+}
+
+/// Stones split by whether they are indexed, see [`select`]
+#[derive(Debug, Default)]
+pub struct Selection {
+    /// Sorted by name, architecture & then newest release first
+    pub kept: Vec<Entry>,
+    pub excluded: Vec<Entry>,
+}
+
+/// JSON mirror of `stone.index` for consumers which can't read stones
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Format version, see [`MANIFEST_VERSION`]
+    pub version: u32,
+    pub packages: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub name: String,
+    pub version: String,
+    pub release: u64,
+    /// Path of the stone, relative to the index
+    pub file: String,
+    pub sha256: String,
+    pub size: u64,
+}
+
+impl Manifest {
+    pub fn new<'a>(entries: impl IntoIterator<Item = &'a Entry>) -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            packages: entries
+                .into_iter()
+                .map(|Entry { meta, .. }| ManifestEntry {
+                    name: meta.name.to_string(),
+                    version: meta.version_identifier.clone(),
+                    release: meta.source_release,
+                    file: meta.uri.clone().unwrap_or_default(),
+                    sha256: meta.hash.clone().unwrap_or_default(),
+                    size: meta.download_size.unwrap_or_default(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Entries of an existing `stone.index`, by the path of their stone relative to it
 ///
-/// If `output_dir` is `None`, `stone.index` is output to `index_dir`
+/// Indexes don't record when their stones were modified, so a stone is unchanged
+/// when its size is that indexed & it was last modified before the index was written.
+/// Stones excluded from the index by [`Options::keep`] are never reused.
+#[derive(Debug, Clone, Default)]
+pub struct Previous {
+    entries: BTreeMap<String, Meta>,
+    written: Option<SystemTime>,
+}
+
+impl Previous {
+    /// Entries of the `stone.index` of `dir`, none if it has no index
+    pub fn load(dir: &Path) -> Result<Self, Error> {
+        let path = dir.join("stone.index");
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let read_payloads = || -> Result<Vec<_>, _> {
+            let mut file = fs::File::open(&path)?;
+            let mut reader = stone::read(&mut file)?;
+            reader.payloads()?.collect()
+        };
+        let payloads = read_payloads().map_err(|source| Error::StoneRead {
+            source,
+            path: path.clone(),
+        })?;
+
+        let metas = payloads
+            .iter()
+            .filter_map(|payload| payload.meta())
+            .map(|payload| Meta::from_stone_payload(&payload.body))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self::new(metas, fs::metadata(&path)?.modified()?))
+    }
+
+    /// Entries of an index of `metas` written at `written`
+    pub fn new(metas: impl IntoIterator<Item = Meta>, written: SystemTime) -> Self {
+        Self {
+            entries: metas
+                .into_iter()
+                .filter_map(|meta| Some((meta.uri.clone()?, meta)))
+                .collect(),
+            written: Some(written),
+        }
+    }
+
+    /// The indexed entry of the stone at `uri`, if it's unchanged given its `size`
+    /// & when it was `modified`
+    pub fn unchanged(&self, uri: &str, size: u64, modified: SystemTime) -> Option<&Meta> {
+        let meta = self.entries.get(uri)?;
+
+        (meta.download_size == Some(size) && self.written.is_some_and(|written| modified < written)).then_some(meta)
+    }
+
+    /// Paths of the indexed stones which aren't among the `uris`
+    pub fn removed(&self, uris: &[String]) -> Vec<&str> {
+        self.entries
+            .keys()
+            .filter(|uri| !uris.contains(uri))
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+/// Index a directory of stone files & produce a `stone.index` index file,
+/// alongside a `stone.index.json` [`Manifest`]
+///
+/// If `output_dir` is `None`, `stone.index` is output to `index_dir`. Indexing
+/// incrementally only reads the stones changed since the existing index, see
+/// [`Previous`], producing the same index.
 #[tracing::instrument(skip_all)]
-pub fn index(index_dir: &Path, output_dir: Option<&Path>) -> Result<(), Error> {
+pub fn index(index_dir: &Path, output_dir: Option<&Path>, options: &Options) -> Result<(), Error> {
     let output_dir = output_dir.unwrap_or(index_dir);
 
     let stone_files = enumerate_stone_files(index_dir)?;
 
-    println!("Indexing {} files\n", stone_files.len());
+    let previous = if options.incremental {
+        Previous::load(output_dir)?
+    } else {
+        Previous::default()
+    };
+    let uris = stone_files
+        .iter()
+        .map(|path| relative_uri(output_dir, path))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if options.incremental {
+        let removed = previous.removed(&uris).len();
+        println!(
+            "Indexing {} files incrementally, {removed} removed\n",
+            stone_files.len()
+        );
+    } else {
+        println!("Indexing {} files\n", stone_files.len());
+    }
 
     let multi_progress = MultiProgress::new();
 
@@ -50,45 +226,71 @@ pub fn index(index_dir: &Path, output_dir: Option<&Path>) -> Result<(), Error> {
     };
     let list = stone_files
         .par_iter()
-        .map(|path| get_meta(path, ctx))
-        .collect::<Result<Vec<_>, _>>()?;
+        .zip(&uris)
+        .map(|(path, uri)| {
+            let meta = match reuse(path, uri, &previous, options.verify, ctx)? {
+                Some(meta) => meta,
+                None => get_meta(path, ctx)?,
+            };
+            Ok(Entry {
+                path: path.clone(),
+                meta,
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let selection = select(list, options.keep)?;
+
+    write_index(output_dir, &selection.kept, &total_progress)?;
+    write_manifest(output_dir, &selection.kept)?;
 
-    let mut map = BTreeMap::new();
+    multi_progress.clear()?;
 
-    // Add each meta to the map, removing
-    // dupes by keeping the latest release
-    for meta in list {
-        match map.entry(meta.name.clone()) {
-            btree_map::Entry::Vacant(entry) => {
-                entry.insert(meta);
-            }
-            btree_map::Entry::Occupied(mut entry) => {
-                match (entry.get().source_release, meta.source_release) {
-                    // Error if dupe is same version
-                    (prev, curr) if prev == curr => {
-                        return Err(Error::DuplicateRelease(meta.name.clone(), meta.source_release));
-                    }
-                    // Update if dupe is newer version
-                    (prev, curr) if prev < curr => {
-                        entry.insert(meta);
-                    }
-                    // Otherwise prev is more recent, don't replace
-                    _ => {}
-                }
-            }
-        }
+    println!("\nIndex file written to {:?}", output_dir.join("stone.index").display());
+
+    if options.prune && !selection.excluded.is_empty() {
+        prune(&selection.excluded, options.yes)?;
     }
 
-    write_index(output_dir, map, &total_progress)?;
+    Ok(())
+}
 
-    multi_progress.clear()?;
+/// Group `entries` by package name & architecture, keeping the newest `keep`
+/// releases of each
+pub fn select(entries: Vec<Entry>, keep: usize) -> Result<Selection, Error> {
+    let mut groups = BTreeMap::<_, Vec<Entry>>::new();
+    for entry in entries {
+        groups
+            .entry((entry.meta.name.clone(), entry.meta.architecture.clone()))
+            .or_default()
+            .push(entry);
+    }
 
-    println!("\nIndex file written to {:?}", output_dir.join("stone.index").display());
+    let mut selection = Selection::default();
 
-    Ok(())
+    for (_, mut group) in groups {
+        group.sort_by_key(|entry| Reverse(entry.meta.source_release));
+
+        // Error if dupe is same version
+        if let Some(dupe) = group
+            .windows(2)
+            .find(|pair| pair[0].meta.source_release == pair[1].meta.source_release)
+        {
+            return Err(Error::DuplicateRelease(
+                dupe[0].meta.name.clone(),
+                dupe[0].meta.source_release,
+            ));
+        }
+
+        let excluded = group.split_off(keep.min(group.len()));
+        selection.kept.extend(group);
+        selection.excluded.extend(excluded);
+    }
+
+    Ok(selection)
 }
 
-fn write_index(dir: &Path, map: BTreeMap<package::Name, Meta>, total_progress: &ProgressBar) -> Result<(), Error> {
+fn write_index(dir: &Path, entries: &[Entry], total_progress: &ProgressBar) -> Result<(), Error> {
     total_progress.set_message("Writing index file");
     total_progress.set_style(
         ProgressStyle::with_template("\n {spinner} {wide_msg}")
@@ -100,11 +302,11 @@ fn write_index(dir: &Path, map: BTreeMap<package::Name, Meta>, total_progress: &
     let path = dir.join("stone.index");
     let mut file = fs::File::create(&path)?;
 
-    let write_stone_index = || {
+    let mut write_stone_index = || {
         let mut writer = StoneWriter::new(&mut file, StoneHeaderV1FileType::Repository)?;
 
-        for (_, meta) in map {
-            let payload = meta.to_stone_payload();
+        for entry in entries {
+            let payload = entry.meta.clone().to_stone_payload();
             writer.add_payload(payload.as_slice())?;
         }
 
@@ -114,6 +316,42 @@ fn write_index(dir: &Path, map: BTreeMap<package::Name, Meta>, total_progress: &
     write_stone_index().map_err(|source| Error::StoneWrite { source, path })
 }
 
+fn write_manifest(dir: &Path, entries: &[Entry]) -> Result<(), Error> {
+    let mut json = serde_json::to_string_pretty(&Manifest::new(entries))?;
+    json.push('\n');
+    fs::write(dir.join("stone.index.json"), json)?;
+    Ok(())
+}
+
+/// Delete the stones of superseded releases, after confirmation
+fn prune(excluded: &[Entry], yes: bool) -> Result<(), Error> {
+    println!("\nThe following superseded releases will be deleted: \n");
+    for entry in excluded {
+        println!("  {}", entry.path.display());
+    }
+    println!();
+
+    let result = if yes {
+        true
+    } else {
+        Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(" Do you wish to continue? ")
+            .default(false)
+            .interact()?
+    };
+    if !result {
+        return Ok(());
+    }
+
+    for entry in excluded {
+        fs::remove_file(&entry.path)?;
+    }
+
+    println!("Deleted {} files", excluded.len());
+
+    Ok(())
+}
+
 #[derive(Clone, Copy)]
 struct GetMetaCtx<'a> {
     output_dir: &'a Path,
@@ -121,11 +359,51 @@ struct GetMetaCtx<'a> {
     total_progress: &'a ProgressBar,
 }
 
-fn get_meta(path: &Path, ctx: GetMetaCtx<'_>) -> Result<Meta, Error> {
-    let relative_path: Utf8PathBuf = rel_path_from_to(ctx.output_dir, path)
+/// Path of the stone at `path` relative to the index in `output_dir`, as its uri
+fn relative_uri(output_dir: &Path, path: &Path) -> Result<String, Error> {
+    let relative_path: Utf8PathBuf = rel_path_from_to(output_dir, path)
         .try_into()
         .map_err(|_| Error::NonUtf8Path { path: path.to_owned() })?;
 
+    Ok(relative_path.into_string())
+}
+
+/// The entry of `previous` for the stone at `path` if it's unchanged, hashing it
+/// first if `verify`
+fn reuse(
+    path: &Path,
+    uri: &str,
+    previous: &Previous,
+    verify: bool,
+    ctx: GetMetaCtx<'_>,
+) -> Result<Option<Meta>, Error> {
+    let metadata = fs::metadata(path)?;
+    let Some(meta) = previous.unchanged(uri, metadata.len(), metadata.modified()?) else {
+        return Ok(None);
+    };
+
+    if verify {
+        let progress = ctx
+            .multi_progress
+            .insert_before(ctx.total_progress, ProgressBar::new_spinner());
+        progress.enable_steady_tick(Duration::from_millis(150));
+        let (_, hash) = stat_file(path, Utf8Path::new(uri), &progress)?;
+        progress.finish();
+        ctx.multi_progress.remove(&progress);
+
+        if meta.hash.as_deref() != Some(hash.as_str()) {
+            return Ok(None);
+        }
+    }
+
+    ctx.total_progress.inc(1);
+
+    Ok(Some(meta.clone()))
+}
+
+fn get_meta(path: &Path, ctx: GetMetaCtx<'_>) -> Result<Meta, Error> {
+    let relative_path = Utf8PathBuf::from(relative_uri(ctx.output_dir, path)?);
+
     let progress = ctx
         .multi_progress
         .insert_before(ctx.total_progress, ProgressBar::new_spinner());
@@ -235,6 +513,12 @@ pub enum Error {
 
     #[error("non-utf8 path: {path}")]
     NonUtf8Path { path: PathBuf },
+
+    #[error("json")]
+    Json(#[from] serde_json::Error),
+
+    #[error("string processing")]
+    Dialog(#[from] tui::dialoguer::Error),
 }
 
 /// Make a relative path that points to `to` if the current working directory is `from_dir`.
@@ -267,7 +551,228 @@ fn rel_path_from_to(from_dir: &Path, to: &Path) -> PathBuf {
 mod tests {
     use std::path::Path;
 
-    use super::rel_path_from_to;
+    use super::*;
+    use crate::package::fixture;
+
+    fn names(entries: &[Entry]) -> Vec<String> {
+        entries
+            .iter()
+            .map(|Entry { meta, .. }| format!("{}-{}-{}", meta.name, meta.source_release, meta.architecture))
+            .collect()
+    }
+
+    #[test]
+    fn keep_newest_releases() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, release, architecture) in [
+            ("nano", 1, "x86_64"),
+            ("nano", 3, "x86_64"),
+            ("nano", 2, "x86_64"),
+            ("nano", 1, "aarch64"),
+            ("bash", 4, "x86_64"),
+        ] {
+            fixture::stone(
+                dir.path(),
+                &Meta {
+                    source_release: release,
+                    architecture: architecture.to_owned(),
+                    ..fixture::meta(name)
+                }
+                .to_stone_payload(),
+                &[],
+            );
+        }
+        let index_dir = dir.path().canonicalize().unwrap();
+
+        let options = Options {
+            keep: 2,
+            prune: true,
+            yes: true,
+            ..Options::default()
+        };
+        index(&index_dir, None, &options).unwrap();
+
+        let json = fs::read_to_string(index_dir.join("stone.index.json")).unwrap();
+        let manifest: Manifest = serde_json::from_str(&json).unwrap();
+        assert_eq!(manifest.version, MANIFEST_VERSION);
+        assert_eq!(
+            manifest
+                .packages
+                .iter()
+                .map(|p| (p.name.as_str(), p.release, p.file.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                ("bash", 4, "bash-1.0-4-1-x86_64.stone"),
+                ("nano", 1, "nano-1.0-1-1-aarch64.stone"),
+                ("nano", 3, "nano-1.0-3-1-x86_64.stone"),
+                ("nano", 2, "nano-1.0-2-1-x86_64.stone"),
+            ]
+        );
+        let stone = &manifest.packages[0];
+        assert_eq!(stone.size, fs::metadata(index_dir.join(&stone.file)).unwrap().len());
+        assert_eq!(stone.sha256.len(), 64);
+
+        // The binary index holds the same packages
+        let mut index = stone::read(fs::File::open(index_dir.join("stone.index")).unwrap()).unwrap();
+        assert_eq!(index.payloads().unwrap().count(), 4);
+
+        // Only the superseded release was pruned
+        assert!(!index_dir.join("nano-1.0-1-1-x86_64.stone").exists());
+        assert_eq!(enumerate_stone_files(&index_dir).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn previous_entries() {
+        let written = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+        let meta = |uri: &str, size| Meta {
+            uri: Some(uri.to_owned()),
+            download_size: Some(size),
+            ..fixture::meta("nano")
+        };
+        let previous = Previous::new(
+            [
+                meta("nano-1.stone", 10),
+                meta("vim-1.stone", 20),
+                fixture::meta("unlisted"),
+            ],
+            written,
+        );
+
+        let before = written - Duration::from_secs(1);
+        assert!(previous.unchanged("nano-1.stone", 10, before).is_some());
+        // Resized or modified since
+        assert!(previous.unchanged("nano-1.stone", 11, before).is_none());
+        assert!(previous.unchanged("nano-1.stone", 10, written).is_none());
+        // Added since
+        assert!(previous.unchanged("nano-2.stone", 10, before).is_none());
+        assert!(Previous::default().unchanged("nano-1.stone", 10, before).is_none());
+
+        assert_eq!(previous.removed(&["vim-1.stone".to_owned()]), ["nano-1.stone"]);
+    }
+
+    #[test]
+    fn incremental_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let index_dir = dir.path().canonicalize().unwrap();
+        fixture::stone(&index_dir, &fixture::meta("nano").to_stone_payload(), &[]);
+        let vim = fixture::stone(&index_dir, &fixture::meta("vim").to_stone_payload(), &[]);
+        let bash = fixture::stone(&index_dir, &fixture::meta("bash").to_stone_payload(), &[]);
+
+        // Indexed after it was last modified
+        let nano = index_dir.join("nano-1.0-1-1-x86_64.stone");
+        let modified = SystemTime::now() - Duration::from_secs(60);
+        let set_modified = || {
+            fs::File::options()
+                .write(true)
+                .open(&nano)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        };
+        set_modified();
+
+        index(&index_dir, None, &Options::default()).unwrap();
+        let read = |name| fs::read(index_dir.join(name)).unwrap();
+
+        // Add, remove & replace a stone, then corrupt one without changing its size or mtime
+        fixture::stone(&index_dir, &fixture::meta("zsh").to_stone_payload(), &[]);
+        fs::remove_file(&bash).unwrap();
+        fs::remove_file(&vim).unwrap();
+        fixture::stone(
+            &index_dir,
+            &Meta {
+                source_release: 2,
+                ..fixture::meta("vim")
+            }
+            .to_stone_payload(),
+            &[],
+        );
+        let size = fs::metadata(&nano).unwrap().len();
+        fs::write(&nano, vec![0; size as usize]).unwrap();
+        set_modified();
+
+        let incremental = Options {
+            incremental: true,
+            ..Options::default()
+        };
+        index(&index_dir, None, &incremental).unwrap();
+        let (binary, json) = (read("stone.index"), read("stone.index.json"));
+
+        // Verifying catches the corrupted stone
+        let verify = Options {
+            verify: true,
+            ..incremental.clone()
+        };
+        assert!(matches!(index(&index_dir, None, &verify), Err(Error::StoneRead { .. })));
+
+        // The same as a full index, had the stone been left intact
+        fixture::stone(&index_dir, &fixture::meta("nano").to_stone_payload(), &[]);
+        set_modified();
+        index(&index_dir, None, &verify).unwrap();
+        assert_eq!(read("stone.index"), binary);
+        assert_eq!(read("stone.index.json"), json);
+        index(&index_dir, None, &Options::default()).unwrap();
+        assert_eq!(read("stone.index"), binary);
+        assert_eq!(read("stone.index.json"), json);
+
+        let manifest: Manifest = serde_json::from_slice(&json).unwrap();
+        assert_eq!(
+            manifest
+                .packages
+                .iter()
+                .map(|p| (p.name.as_str(), p.release))
+                .collect::<Vec<_>>(),
+            [("nano", 1), ("vim", 2), ("zsh", 1)]
+        );
+    }
+
+    #[test]
+    fn select_releases() {
+        let dir = tempfile::tempdir().unwrap();
+        let multi_progress = MultiProgress::with_draw_target(tui::ProgressDrawTarget::hidden());
+        let total_progress = multi_progress.add(ProgressBar::hidden());
+        let entry = |name, release| {
+            let path = fixture::stone(
+                dir.path(),
+                &Meta {
+                    source_release: release,
+                    ..fixture::meta(name)
+                }
+                .to_stone_payload(),
+                &[],
+            );
+            let meta = get_meta(
+                &path.canonicalize().unwrap(),
+                GetMetaCtx {
+                    output_dir: &dir.path().canonicalize().unwrap(),
+                    multi_progress: &multi_progress,
+                    total_progress: &total_progress,
+                },
+            )
+            .unwrap();
+            Entry { path, meta }
+        };
+
+        let entries = vec![entry("nano", 1), entry("nano", 2), entry("vim", 1)];
+
+        let selection = select(entries.clone(), 1).unwrap();
+        assert_eq!(names(&selection.kept), ["nano-2-x86_64", "vim-1-x86_64"]);
+        assert_eq!(names(&selection.excluded), ["nano-1-x86_64"]);
+
+        // Keeping more than there are keeps everything
+        let selection = select(entries.clone(), 5).unwrap();
+        assert_eq!(selection.kept.len(), 3);
+        assert!(selection.excluded.is_empty());
+
+        let dupe = Entry {
+            path: dir.path().join("copy.stone"),
+            meta: entries[0].meta.clone(),
+        };
+        assert!(matches!(
+            select(entries.into_iter().chain([dupe]).collect(), 1),
+            Err(Error::DuplicateRelease(_, 1))
+        ));
+    }
 
     #[test]
     fn test_rel_path_from_to_strips_prefix() {
@@ -0,0 +1,244 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Details of a package beyond its metadata, as shown by `moss info`
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use dag::Dag;
+use fs_err as fs;
+
+use crate::{
+    Client, Package, Provider, Registry,
+    client::{self, cache},
+    package,
+    registry::plugin::Origin,
+};
+
+/// A package with its origin, size & resolved dependencies,
+/// see [`Client::package_details`]
+#[derive(Debug, Clone)]
+pub struct Details {
+    pub package: Package,
+    /// Where the package is offered from, `None` if no source knows it
+    pub origin: Option<Origin>,
+    /// Size of the package's assets in the pool, if installed
+    pub installed_size: Option<u64>,
+    /// Direct dependencies, in order
+    pub dependencies: Vec<Dependency>,
+}
+
+/// A dependency with the package satisfying it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dependency {
+    pub dependency: crate::Dependency,
+    /// Name of the package providing the dependency, `None` if nothing does
+    pub package: Option<package::Name>,
+}
+
+/// Transitive dependencies of a package, see [`Client::dependency_tree`]
+///
+/// Dependencies closing a cycle are dropped, so walking the tree from its
+/// root always terminates
+#[derive(Debug, Clone)]
+pub struct Tree {
+    root: package::Name,
+    dag: Dag<package::Name>,
+}
+
+impl Tree {
+    /// The package the tree was resolved for
+    pub fn root(&self) -> &package::Name {
+        &self.root
+    }
+
+    /// Direct dependencies of `name`, sorted by name
+    pub fn dependencies(&self, name: &package::Name) -> Vec<&package::Name> {
+        let Some(index) = self.dag.get_index(name) else {
+            return vec![];
+        };
+
+        let mut dependencies = self.dag.successors(index).collect::<Vec<_>>();
+        dependencies.sort();
+        dependencies
+    }
+}
+
+pub fn details(client: &Client, package: &Package) -> Result<Details, client::Error> {
+    let installed_size = if package.flags.installed {
+        Some(
+            installed_sizes(client, [&package.id])?
+                .remove(&package.id)
+                .unwrap_or_default(),
+        )
+    } else {
+        None
+    };
+
+    let dependencies = package
+        .meta
+        .dependencies
+        .iter()
+        .map(|dependency| Dependency {
+            dependency: dependency.clone(),
+            package: resolve(&client.registry, dependency, package.flags.installed).map(|p| p.meta.name),
+        })
+        .collect();
+
+    Ok(Details {
+        package: package.clone(),
+        origin: client.registry.origin(&package.id),
+        installed_size,
+        dependencies,
+    })
+}
+
+/// Size of the assets in the pool of each of the installed `packages`
+pub fn installed_sizes<'a>(
+    client: &Client,
+    packages: impl IntoIterator<Item = &'a package::Id>,
+) -> Result<BTreeMap<package::Id, u64>, client::Error> {
+    Ok(client
+        .layout_db
+        .file_hashes_by_package(packages)?
+        .into_iter()
+        .map(|(package, hashes)| {
+            let size = hashes
+                .iter()
+                .filter_map(|hash| fs::metadata(cache::asset_path(&client.installation, hash)).ok())
+                .map(|metadata| metadata.len())
+                .sum();
+            (package, size)
+        })
+        .collect())
+}
+
+pub fn dependency_tree(client: &Client, package: &Package) -> Tree {
+    let mut dag = Dag::new();
+    let root = dag.add_node_or_get_index(&package.meta.name);
+
+    let mut expanded = BTreeSet::from([package.meta.name.clone()]);
+    let mut queue = VecDeque::from([(root, package.clone())]);
+
+    while let Some((index, package)) = queue.pop_front() {
+        for dependency in &package.meta.dependencies {
+            let Some(resolved) = resolve(&client.registry, dependency, package.flags.installed) else {
+                continue;
+            };
+
+            let dependency_index = dag.add_node_or_get_index(&resolved.meta.name);
+            // Refused if it closes a cycle
+            dag.add_edge(index, dependency_index);
+
+            if expanded.insert(resolved.meta.name.clone()) {
+                queue.push_back((dependency_index, resolved));
+            }
+        }
+    }
+
+    Tree {
+        root: package.meta.name.clone(),
+        dag,
+    }
+}
+
+/// Resolve the package providing `dependency`, preferring installed packages
+/// for `installed` dependents & available ones otherwise
+fn resolve(registry: &Registry, dependency: &crate::Dependency, installed: bool) -> Option<Package> {
+    let provider = Provider {
+        kind: dependency.kind,
+        name: dependency.name.clone(),
+    };
+
+    let (first, second) = if installed {
+        (
+            package::Flags::new().with_installed(),
+            package::Flags::new().with_available(),
+        )
+    } else {
+        (
+            package::Flags::new().with_available(),
+            package::Flags::new().with_installed(),
+        )
+    };
+
+    registry
+        .by_provider(&provider, first)
+        .next()
+        .or_else(|| registry.by_provider(&provider, second).next())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Installation, dependency, registry::plugin};
+
+    use super::*;
+
+    fn package(name: &str, installed: bool, dependencies: &[&str]) -> Package {
+        let provider = |name: &str| crate::Dependency {
+            kind: dependency::Kind::PackageName,
+            name: name.to_owned(),
+        };
+        let flags = if installed {
+            package::Flags::new().with_installed()
+        } else {
+            package::Flags::new().with_available()
+        };
+
+        Package {
+            id: package::Id::from(format!("{name}-{installed}")),
+            meta: package::Meta {
+                dependencies: dependencies.iter().copied().map(provider).collect(),
+                ..package::fixture::meta(name)
+            },
+            flags,
+        }
+    }
+
+    #[test]
+    fn details_and_tree() {
+        let root = tempfile::tempdir().unwrap();
+        let nano = package("nano", false, &["ncurses", "glibc", "missing"]);
+
+        let mut registry = Registry::default();
+        registry.add_plugin(plugin::Plugin::Test(plugin::Test::new(
+            1,
+            vec![
+                nano.clone(),
+                package("ncurses", false, &["glibc"]),
+                // Installed glibc is preferred by installed dependents only
+                package("glibc", true, &["libgcc"]),
+                package("glibc", false, &["nano"]),
+                package("libgcc", true, &["glibc"]),
+            ],
+        )));
+
+        let installation = Installation::open(root.path(), None).unwrap();
+        let client = Client::mocked(installation, registry).unwrap();
+
+        let details = client.package_details(&nano).unwrap();
+        assert_eq!(details.origin, Some(Origin::Local));
+        assert_eq!(details.installed_size, None);
+        assert_eq!(
+            details
+                .dependencies
+                .iter()
+                .map(|dependency| dependency.package.as_ref().map(package::Name::as_str))
+                .collect::<Vec<_>>(),
+            [Some("glibc"), None, Some("ncurses")]
+        );
+
+        let tree = client.dependency_tree(&nano);
+        let dependencies = |name: &str| {
+            tree.dependencies(&package::Name::from(name.to_owned()))
+                .into_iter()
+                .map(package::Name::as_str)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(tree.root().as_str(), "nano");
+        assert_eq!(dependencies("nano"), ["glibc", "ncurses"]);
+        assert_eq!(dependencies("ncurses"), ["glibc"]);
+        // Available glibc depending back on nano closes a cycle, which is dropped
+        assert!(dependencies("glibc").is_empty());
+    }
+}
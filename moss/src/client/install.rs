@@ -3,37 +3,94 @@
 
 //! Installation-specific code for several core moss operations
 
-use std::time::{Duration, Instant};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    time::{Duration, Instant},
+};
 
+use itertools::Itertools;
 use thiserror::Error;
 use tracing::{Instrument, debug, info, info_span, instrument};
-use tui::{
-    dialoguer::{Confirm, theme::ColorfulTheme},
-    pretty::autoprint_columns,
-};
 
 use crate::{
     Package, Provider,
-    client::{self, Client},
+    client::{
+        self, Client,
+        interaction::{Candidate, Event, Question, Resolution},
+    },
+    manifest::Manifest,
     package::{self, Flags},
-    registry::transaction,
+    registry::{suggest, transaction},
     runtime,
     state::Selection,
 };
 
+/// Most names suggested for a package which can't be found
+const MAX_SUGGESTIONS: usize = 3;
+
 /// Install a set of packages.
 ///
+/// When several distinct packages satisfy a requested provider, the one named
+/// in `choices` is installed. Otherwise the [`Interaction`](super::interaction::Interaction)
+/// picks one, or the highest priority candidate is taken with `yes`.
+///
 /// If this call is successful a new State is recorded into the [`super::db::state::Database`].
 /// Upon completion the `/usr` tree is "hot swapped" with the staging tree through `renameat2` call.
 #[instrument(skip(client), fields(ephemeral = client.is_ephemeral()))]
-pub fn install(client: &mut Client, pkgs: &[&str], yes: bool, simulate: bool) -> Result<Timing, Error> {
-    let mut timing = Timing::default();
-    let mut instant = Instant::now();
-
+pub fn install(
+    client: &mut Client,
+    pkgs: &[&str],
+    choices: &[&str],
+    yes: bool,
+    simulate: bool,
+) -> Result<Outcome, Error> {
     // Resolve input packages
-    let input = resolve_input(pkgs, client)?;
+    let input = resolve_input(pkgs, client, choices, yes)?;
     debug!(resolved_packages = input.len(), "Resolved input packages");
 
+    let requested = pkgs.iter().map(|pkg| pkg.to_string()).zip(input).collect();
+
+    install_packages(client, requested, yes, simulate)
+}
+
+/// Install the explicit packages of a [`Manifest`], at their pinned versions
+/// where still available.
+///
+/// When `strict`, any package no longer available at its pinned version is
+/// an error, otherwise the latest version is installed instead.
+#[instrument(skip_all, fields(ephemeral = client.is_ephemeral()))]
+pub fn install_manifest(
+    client: &mut Client,
+    manifest: &Manifest,
+    strict: bool,
+    yes: bool,
+    simulate: bool,
+) -> Result<Outcome, Error> {
+    let input = resolve_manifest(manifest, client, strict)?;
+    debug!(resolved_packages = input.len(), "Resolved manifest packages");
+
+    let requested = manifest
+        .packages
+        .iter()
+        .map(|entry| entry.name.clone())
+        .zip(input)
+        .collect();
+
+    install_packages(client, requested, yes, simulate)
+}
+
+/// Install the `requested` packages, resolved to their ids, & their dependencies
+fn install_packages(
+    client: &mut Client,
+    requested: Vec<(String, package::Id)>,
+    yes: bool,
+    simulate: bool,
+) -> Result<Outcome, Error> {
+    let mut timing = Timing::default();
+    let mut instant = Instant::now();
+    let input = requested.iter().map(|(_, id)| id.clone()).collect::<Vec<_>>();
+
     // Add all inputs
     let mut tx = client.registry.transaction(transaction::Lookup::PreferInstalled)?;
 
@@ -42,6 +99,19 @@ pub fn install(client: &mut Client, pkgs: &[&str], yes: bool, simulate: bool) ->
     // Resolve transaction to metadata
     let resolved = client.resolve_packages(tx.finalize())?;
 
+    let graph = Graph {
+        dependencies: resolved
+            .iter()
+            .map(|package| {
+                (
+                    package.id.clone(),
+                    tx.dependencies(&package.id).cloned().sorted().collect(),
+                )
+            })
+            .collect(),
+        requested,
+    };
+
     // Get installed packages to check against
     let installed = client.registry.list_installed().collect::<Vec<_>>();
     let is_installed = |p: &Package| installed.iter().any(|i| i.meta.name == p.meta.name);
@@ -55,6 +125,18 @@ pub fn install(client: &mut Client, pkgs: &[&str], yes: bool, simulate: bool) ->
         .filter(|p| client.is_ephemeral() || !is_installed(p))
         .collect::<Vec<_>>();
 
+    // Nothing we add may conflict with each other or what's already installed
+    let installed_packages = if client.is_ephemeral() {
+        vec![]
+    } else {
+        installed.iter().collect()
+    };
+    // Installing never removes, installed packages are only replaced by name
+    let conflicts = find_conflicts(&missing, &installed_packages, &BTreeSet::new());
+    if !conflicts.is_empty() {
+        return Err(Error::Conflicts(conflicts));
+    }
+
     timing.resolve = instant.elapsed();
     info!(
         total_resolved = resolved.len(),
@@ -64,45 +146,38 @@ pub fn install(client: &mut Client, pkgs: &[&str], yes: bool, simulate: bool) ->
         "Package resolution completed"
     );
 
-    // If no new packages exist, exit and print
+    // If no new packages exist, exit and report
     // packages already installed
     if missing.is_empty() {
-        let installed = resolved
+        let unchanged = resolved
             .iter()
             .filter(|p| is_installed(p) && input.contains(&p.id))
+            .cloned()
             .collect::<Vec<_>>();
 
-        if !installed.is_empty() {
-            println!("The following package(s) are already installed:");
-            println!();
-            autoprint_columns(&installed);
+        if !unchanged.is_empty() {
+            client.interaction.report(Event::Resolved(Resolution {
+                unchanged,
+                ..Default::default()
+            }));
         }
 
-        return Ok(timing);
+        return Ok(Outcome { timing, graph });
     }
 
     // Testing panic for hyperfine benchmarking purposes (build flag tuning)
     // panic!();
 
-    println!("The following package(s) will be installed:");
-    println!();
-    autoprint_columns(&missing);
-    println!();
+    client.interaction.report(Event::Resolved(Resolution {
+        added: missing.iter().map(|p| (*p).clone()).collect(),
+        ..Default::default()
+    }));
 
     if simulate {
-        return Ok(timing);
+        return Ok(Outcome { timing, graph });
     }
 
-    // Must we prompt?
-    let result = if yes {
-        true
-    } else {
-        Confirm::with_theme(&ColorfulTheme::default())
-            .with_prompt(" Do you wish to continue? ")
-            .default(false)
-            .interact()?
-    };
-    if !result {
+    if !client.confirm(yes, &Question::Continue)? {
         return Err(Error::Cancelled);
     }
 
@@ -116,8 +191,8 @@ pub fn install(client: &mut Client, pkgs: &[&str], yes: bool, simulate: bool) ->
         event_type = "progress_start"
     );
 
-    // Cache packages
-    runtime::block_on(client.cache_packages(&missing).in_current_span())?;
+    // Cache packages, every one is needed for the new state so any failure aborts
+    runtime::block_on(client.cache_packages(&missing).in_current_span())?.into_result()?;
 
     timing.fetch = instant.elapsed();
     info!(
@@ -149,6 +224,7 @@ pub fn install(client: &mut Client, pkgs: &[&str], yes: bool, simulate: bool) ->
 
     // Perfect, apply state.
     client.new_state(&new_state_pkgs, "Install")?;
+    client.interaction.report(Event::Done);
 
     timing.blit = instant.elapsed();
 
@@ -158,39 +234,213 @@ pub fn install(client: &mut Client, pkgs: &[&str], yes: bool, simulate: bool) ->
         "Installation completed successfully"
     );
 
-    Ok(timing)
+    Ok(Outcome { timing, graph })
 }
 
 /// Resolves the package arguments as valid input packages. Returns an error
 /// if any args are invalid.
 #[instrument(skip(client))]
-fn resolve_input(pkgs: &[&str], client: &Client) -> Result<Vec<package::Id>, Error> {
-    // Parse pkg args into valid / invalid sets
-    let queried = pkgs.iter().map(|p| find_packages(p, client));
+fn resolve_input(pkgs: &[&str], client: &Client, choices: &[&str], yes: bool) -> Result<Vec<package::Id>, Error> {
+    pkgs.iter()
+        .map(|id| {
+            let candidates = find_packages(id, client);
+            choose_package(id, &candidates, client, choices, yes)
+        })
+        .collect()
+}
+
+/// Pick one of the distinct `candidates` satisfying `id`, preferring any
+/// named in `choices`
+fn choose_package(
+    id: &str,
+    candidates: &[Package],
+    client: &Client,
+    choices: &[&str],
+    yes: bool,
+) -> Result<package::Id, Error> {
+    if let Some(chosen) = candidates.iter().find(|p| choices.contains(&p.meta.name.as_str())) {
+        return Ok(chosen.id.clone());
+    }
+
+    match candidates {
+        [] => {
+            let Ok(provider) = Provider::from_name(id) else {
+                return Err(Error::NoPackage(id.to_owned(), vec![]));
+            };
+            let suggestions = client.registry.suggest(&provider, MAX_SUGGESTIONS);
+
+            // Offer the suggestion if it's likely what was meant, unless answering
+            // every question with yes
+            if !yes && let Some(close) = suggest::single_close(&suggestions) {
+                let suggestion = close.provider.to_name();
+                let question = Question::Suggestion {
+                    requested: id.to_owned(),
+                    suggestion: suggestion.clone(),
+                };
+                if client.interaction.confirm(&question)? {
+                    let candidates = find_packages(&suggestion, client);
+                    return choose_package(&suggestion, &candidates, client, choices, yes);
+                }
+            }
+
+            Err(Error::NoPackage(
+                id.to_owned(),
+                suggestions.iter().map(|s| s.provider.to_name()).collect(),
+            ))
+        }
+        // Candidates are pre-sorted, highest priority first
+        [only] => Ok(only.id.clone()),
+        [first, ..] if yes => Ok(first.id.clone()),
+        _ => {
+            let candidates = candidates
+                .iter()
+                .map(|p| Candidate {
+                    package: p.clone(),
+                    repository: client.package_repository(&p.id),
+                })
+                .collect::<Vec<_>>();
+
+            let index = client.interaction.choose(id, &candidates)?;
+            let chosen = candidates.get(index).ok_or(Error::NoPackage(id.to_owned(), vec![]))?;
+
+            Ok(chosen.package.id.clone())
+        }
+    }
+}
+
+/// Resolves the packages of a [`Manifest`], preferring their pinned versions
+fn resolve_manifest(manifest: &Manifest, client: &Client, strict: bool) -> Result<Vec<package::Id>, Error> {
+    for (id, _) in &manifest.repositories {
+        if !client.repositories.list().any(|(configured, _)| configured == id) {
+            client.interaction.report(Event::Warning(format!(
+                "repository {id} of the manifest isn't configured, add it with `moss repo add`"
+            )));
+        }
+    }
 
     let mut results = vec![];
+    let mut unavailable = vec![];
 
-    for (id, pkg) in queried {
-        if let Some(pkg) = pkg {
-            results.push(pkg.id);
-        } else {
-            return Err(Error::NoPackage(id));
+    for entry in &manifest.packages {
+        let name = package::Name::from(entry.name.clone());
+        let candidates = client
+            .registry
+            .by_name(&name, Flags::new().with_available())
+            .collect::<Vec<_>>();
+
+        let pinned = candidates
+            .iter()
+            .find(|p| p.meta.version_identifier == entry.version && p.meta.source_release == entry.release);
+
+        match (pinned, candidates.first()) {
+            (Some(package), _) => results.push(package.id.clone()),
+            (None, Some(latest)) => {
+                let pinned = format!("{} {}-{}", entry.name, entry.version, entry.release);
+                if !strict {
+                    client.interaction.report(Event::Warning(format!(
+                        "{pinned} is no longer available, using {}-{}",
+                        latest.meta.version_identifier, latest.meta.source_release
+                    )));
+                    results.push(latest.id.clone());
+                }
+                unavailable.push(pinned);
+            }
+            (None, None) => return Err(Error::NoPackage(entry.name.clone(), vec![])),
         }
     }
 
+    if strict && !unavailable.is_empty() {
+        return Err(Error::UnavailableVersions(unavailable));
+    }
+
     Ok(results)
 }
 
-/// Resolve a package name to the first package
-fn find_packages(id: &str, client: &Client) -> (String, Option<Package>) {
-    let provider = Provider::from_name(id).unwrap();
-    let result = client
+/// Resolve a package name to the distinct packages providing it, with the
+/// highest priority first
+fn find_packages(id: &str, client: &Client) -> Vec<Package> {
+    let Ok(provider) = Provider::from_name(id) else {
+        return vec![];
+    };
+
+    // Only the first of each name, pre-sorted
+    client
         .registry
         .by_provider(&provider, Flags::new().with_available())
-        .next();
+        .unique_by(|p| p.meta.name.to_string())
+        .collect()
+}
+
+/// A package that can't be installed alongside another
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    /// Package being added
+    pub package: package::Name,
+    /// Package it conflicts with
+    pub with: package::Name,
+    /// Provider declared as conflicting by either party
+    pub provider: Provider,
+    /// `with` is already installed
+    pub installed: bool,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} conflicts with {} ({})", self.package, self.with, self.provider)?;
+        if self.installed {
+            write!(f, ", remove it first with `moss remove {}`", self.with)?;
+        }
+        Ok(())
+    }
+}
+
+/// Find conflicts between the `selected` packages being added, and against
+/// the `installed` packages that aren't being `removed` or replaced
+pub(crate) fn find_conflicts(
+    selected: &[&Package],
+    installed: &[&Package],
+    removed: &BTreeSet<package::Id>,
+) -> Vec<Conflict> {
+    let replaced = selected.iter().map(|p| &p.meta.name).collect::<BTreeSet<_>>();
+    let installed = installed
+        .iter()
+        .filter(|p| !removed.contains(&p.id) && !replaced.contains(&p.meta.name))
+        .collect::<Vec<_>>();
+
+    // Declared in either direction, since only one party may know about the other
+    let conflicting_provider = |a: &Package, b: &Package| {
+        a.meta
+            .conflicts
+            .iter()
+            .find(|c| b.meta.providers.contains(c))
+            .or_else(|| b.meta.conflicts.iter().find(|c| a.meta.providers.contains(c)))
+            .cloned()
+    };
 
-    // First only, pre-sorted
-    (id.into(), result)
+    selected
+        .iter()
+        .enumerate()
+        .flat_map(|(idx, package)| {
+            // Only pair each selected package once
+            let others = selected[idx + 1..]
+                .iter()
+                .map(|other| (other, false))
+                .chain(installed.iter().map(|other| (*other, true)));
+
+            others.filter_map(move |(other, installed)| {
+                if other.meta.name == package.meta.name {
+                    return None;
+                }
+
+                Some(Conflict {
+                    package: package.meta.name.clone(),
+                    with: other.meta.name.clone(),
+                    provider: conflicting_provider(package, other)?,
+                    installed,
+                })
+            })
+        })
+        .collect()
 }
 
 /// Simple timing information for Install
@@ -201,6 +451,22 @@ pub struct Timing {
     pub blit: Duration,
 }
 
+/// The packages an install resolved, and why each was
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Graph {
+    /// Each package as requested, with the package it resolved to
+    pub requested: Vec<(String, package::Id)>,
+    /// The packages each resolved package directly depends on
+    pub dependencies: BTreeMap<package::Id, Vec<package::Id>>,
+}
+
+/// Result of an install
+#[derive(Default)]
+pub struct Outcome {
+    pub timing: Timing,
+    pub graph: Graph,
+}
+
 /// Error's specific to installation operations
 #[derive(Debug, Error)]
 pub enum Error {
@@ -212,9 +478,17 @@ pub enum Error {
     #[error("client")]
     Client(#[from] client::Error),
 
-    /// The given package couldn't be found
-    #[error("no package found: {0}")]
-    NoPackage(String),
+    /// The given package couldn't be found, with similar names which could
+    #[error("no package found: {0}{suggestions}", suggestions = suggest::did_you_mean(.1))]
+    NoPackage(String, Vec<String>),
+
+    /// Pinned manifest versions which are no longer available
+    #[error("no longer available: {}", .0.join(", "))]
+    UnavailableVersions(Vec<String>),
+
+    /// Packages being added conflict with each other or installed packages
+    #[error("conflicting packages: {}", .0.iter().join("; "))]
+    Conflicts(Vec<Conflict>),
 
     /// A transaction specific error occurred
     #[error("transaction")]
@@ -224,11 +498,332 @@ pub enum Error {
     #[error("db")]
     DB(#[from] crate::db::Error),
 
-    /// Had issues processing user-provided string input
-    #[error("string processing")]
-    Dialog(#[from] tui::dialoguer::Error),
-
     /// We forgot how disks work
     #[error("io")]
     Io(#[from] std::io::Error),
 }
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::{
+        Dependency, Installation,
+        client::interaction::Headless,
+        manifest,
+        registry::{Plugin, Registry, plugin},
+    };
+
+    fn package(id: &'static str, conflicts: &[&str], flags: Flags) -> Package {
+        let name = id.split('-').next().unwrap();
+
+        Package {
+            id: package::Id::from(id),
+            meta: package::Meta {
+                providers: [
+                    Provider::package_name(name),
+                    Provider::from_str(&format!("binary({name})")).unwrap(),
+                ]
+                .into(),
+                conflicts: conflicts.iter().map(|c| Provider::from_str(c).unwrap()).collect(),
+                ..package::fixture::meta(name)
+            },
+            flags,
+        }
+    }
+
+    fn conflict(package: &str, with: &str, provider: &str, installed: bool) -> Conflict {
+        Conflict {
+            package: package::Name::from(package.to_owned()),
+            with: package::Name::from(with.to_owned()),
+            provider: Provider::from_str(provider).unwrap(),
+            installed,
+        }
+    }
+
+    #[test]
+    fn conflicting_pair() {
+        let vim = package("vim-1", &["binary(vi)"], Flags::new().with_available());
+        let vi = package("vi-1", &[], Flags::new().with_available());
+        let nano = package("nano-1", &[], Flags::new().with_available());
+
+        // Selected together, declared by either side
+        assert_eq!(
+            find_conflicts(&[&vim, &vi, &nano], &[], &BTreeSet::new()),
+            [conflict("vim", "vi", "binary(vi)", false)]
+        );
+        assert_eq!(
+            find_conflicts(&[&vi, &vim], &[], &BTreeSet::new()),
+            [conflict("vi", "vim", "binary(vi)", false)]
+        );
+
+        // Against an installed package
+        assert_eq!(
+            find_conflicts(&[&vi], &[&vim], &BTreeSet::new()),
+            [conflict("vi", "vim", "binary(vi)", true)]
+        );
+
+        // Unless it's being removed in the same transaction
+        assert!(find_conflicts(&[&vi], &[&vim], &BTreeSet::from([vim.id.clone()])).is_empty());
+        assert!(find_conflicts(&[&nano], &[&vim], &BTreeSet::new()).is_empty());
+    }
+
+    #[test]
+    fn install_graph() {
+        let depending = |id, dependencies: &[&str]| {
+            let mut package = package(id, &[], Flags::new().with_available());
+            package.meta.dependencies = dependencies
+                .iter()
+                .map(|dependency| Dependency::from_str(dependency).unwrap())
+                .collect();
+            package
+        };
+
+        let mut registry = Registry::default();
+        registry.add_plugin(Plugin::Test(plugin::Test::new(
+            1,
+            vec![
+                depending("cmake-1", &["name(curl)", "binary(make)"]),
+                depending("curl-1", &["name(zlib)"]),
+                depending("make-1", &[]),
+                depending("zlib-1", &[]),
+                depending("ninja-1", &[]),
+            ],
+        )));
+
+        let root = tempfile::tempdir().unwrap();
+        let installation = Installation::open(root.path(), None).unwrap();
+        let mut client = Client::mocked(installation, registry).unwrap();
+
+        let outcome = install(&mut client, &["binary(cmake)", "ninja"], &[], true, true).unwrap();
+        let id = |id: &str| package::Id::from(id.to_owned());
+
+        assert_eq!(
+            outcome.graph.requested,
+            [
+                ("binary(cmake)".to_owned(), id("cmake-1")),
+                ("ninja".to_owned(), id("ninja-1")),
+            ]
+        );
+        assert_eq!(
+            outcome.graph.dependencies,
+            BTreeMap::from([
+                (id("cmake-1"), vec![id("curl-1"), id("make-1")]),
+                (id("curl-1"), vec![id("zlib-1")]),
+                (id("make-1"), vec![]),
+                (id("ninja-1"), vec![]),
+                (id("zlib-1"), vec![]),
+            ])
+        );
+    }
+
+    #[test]
+    fn install_conflicting() {
+        let mut registry = Registry::default();
+        registry.add_plugin(Plugin::Test(plugin::Test::new(
+            1,
+            vec![
+                package("vim-1", &["name(vi)"], Flags::new().with_installed().with_explicit()),
+                package("vi-1", &[], Flags::new().with_available()),
+            ],
+        )));
+
+        let root = tempfile::tempdir().unwrap();
+        let installation = Installation::open(root.path(), None).unwrap();
+        let mut client = Client::mocked(installation, registry).unwrap();
+
+        let Err(error) = install(&mut client, &["vi"], &[], true, true) else {
+            panic!("expected install to fail");
+        };
+
+        let Error::Conflicts(conflicts) = &error else {
+            panic!("expected conflict, got {error:?}");
+        };
+        assert_eq!(conflicts, &[conflict("vi", "vim", "name(vi)", true)]);
+        assert_eq!(
+            error.to_string(),
+            "conflicting packages: vi conflicts with vim (name(vi)), remove it first with `moss remove vim`"
+        );
+    }
+
+    #[test]
+    fn choose_provider() {
+        let java = |id| {
+            let mut package = package(id, &[], Flags::new().with_available());
+            package
+                .meta
+                .providers
+                .insert(Provider::from_str("binary(java)").unwrap());
+            package
+        };
+
+        let mut registry = Registry::default();
+        registry.add_plugin(Plugin::Test(plugin::Test::new(1, vec![java("temurin-1")])));
+        registry.add_plugin(Plugin::Test(plugin::Test::new(2, vec![java("openjdk-1")])));
+
+        let root = tempfile::tempdir().unwrap();
+        let installation = Installation::open(root.path(), None).unwrap();
+        let mut client = Client::mocked(installation, registry).unwrap();
+
+        // Highest priority candidate without asking
+        assert_eq!(
+            resolve_input(&["binary(java)"], &client, &[], true).unwrap(),
+            [package::Id::from("openjdk-1")]
+        );
+
+        // Overridden by name, without prompting either way
+        for yes in [true, false] {
+            assert_eq!(
+                resolve_input(&["binary(java)"], &client, &["temurin"], yes).unwrap(),
+                [package::Id::from("temurin-1")]
+            );
+        }
+
+        // Choices don't affect a provider satisfied by a single package
+        assert_eq!(
+            resolve_input(&["openjdk"], &client, &["temurin"], false).unwrap(),
+            [package::Id::from("openjdk-1")]
+        );
+        assert!(matches!(
+            resolve_input(&["binary(python)"], &client, &[], true),
+            Err(Error::NoPackage(id, _)) if id == "binary(python)"
+        ));
+
+        install(&mut client, &["binary(java)"], &["temurin"], false, true).unwrap();
+    }
+
+    #[test]
+    fn suggest_mistyped() {
+        let root = tempfile::tempdir().unwrap();
+        let installation = Installation::open(root.path(), None).unwrap();
+        let client = |answer| {
+            let mut registry = Registry::default();
+            registry.add_plugin(Plugin::Test(plugin::Test::new(
+                1,
+                ["firefox-1", "fish-1", "git-1", "gitg-1"]
+                    .into_iter()
+                    .map(|id| package(id, &[], Flags::new().with_available()))
+                    .collect(),
+            )));
+
+            let headless = Headless::new(answer);
+            let client = Client::mocked(installation.clone(), registry)
+                .unwrap()
+                .with_interaction(headless.clone());
+            (client, headless)
+        };
+        let (accepting, headless) = client(true);
+
+        // A single close match is offered in place of the typo
+        assert_eq!(
+            resolve_input(&["firefx"], &accepting, &[], false).unwrap(),
+            [package::Id::from("firefox-1")]
+        );
+        assert_eq!(
+            headless.questions(),
+            [Question::Suggestion {
+                requested: "firefx".to_owned(),
+                suggestion: "firefox".to_owned(),
+            }]
+        );
+
+        // Never guessed with yes, and ambiguous typos are only listed
+        let yes = resolve_input(&["firefx"], &accepting, &[], true);
+        assert!(matches!(yes, Err(Error::NoPackage(_, suggestions)) if suggestions == ["firefox"]));
+        let ambiguous = resolve_input(&["gi"], &accepting, &[], false).unwrap_err();
+        assert_eq!(ambiguous.to_string(), "no package found: gi, did you mean git or gitg?");
+        assert_eq!(headless.questions().len(), 1);
+
+        // Declined
+        let (declining, _) = client(false);
+        assert_eq!(
+            resolve_input(&["binary(firefx)"], &declining, &[], false)
+                .unwrap_err()
+                .to_string(),
+            "no package found: binary(firefx), did you mean binary(firefox)?"
+        );
+    }
+
+    #[test]
+    fn manifest_round_trip() {
+        let versioned = |id, version: &str, release, flags| {
+            let mut package = package(id, &[], flags);
+            package.meta.version_identifier = version.to_owned();
+            package.meta.source_release = release;
+            package
+        };
+        let available = Flags::new().with_available();
+
+        // Exporting machine has `nano` & `vim` explicitly installed
+        let nano = versioned("nano-1", "8.0", 1, available);
+        let vim = versioned("vim-3", "9.1", 3, available);
+        let lib = versioned("lib-1", "1.0", 1, available);
+
+        let root = tempfile::tempdir().unwrap();
+        let installation = Installation::open(root.path(), None).unwrap();
+        let mut registry = Registry::default();
+        registry.add_plugin(Plugin::Test(plugin::Test::new(1, vec![])));
+        let client = Client::mocked(installation, registry).unwrap();
+
+        for package in [&nano, &vim, &lib] {
+            client.install_db.add(package.id.clone(), package.meta.clone()).unwrap();
+        }
+        let state = client
+            .state_db
+            .add(
+                &[
+                    Selection::explicit(vim.id.clone()),
+                    Selection::explicit(nano.id.clone()),
+                    Selection {
+                        explicit: false,
+                        ..Selection::explicit(lib.id.clone())
+                    },
+                ],
+                &[],
+                None,
+                None,
+            )
+            .unwrap();
+
+        let manifest = client.export_manifest(state.id).unwrap();
+        assert_eq!(
+            manifest
+                .packages
+                .iter()
+                .map(|entry| format!("{} {}-{}", entry.name, entry.version, entry.release))
+                .collect::<Vec<_>>(),
+            ["nano 8.0-1", "vim 9.1-3"]
+        );
+
+        for format in [manifest::Format::Yaml, manifest::Format::Json] {
+            let encoded = manifest.encode(format).unwrap();
+            assert_eq!(Manifest::decode(&encoded, format).unwrap(), manifest);
+        }
+
+        // Importing machine only has a newer `vim`
+        let mut registry = Registry::default();
+        registry.add_plugin(Plugin::Test(plugin::Test::new(
+            1,
+            vec![
+                nano.clone(),
+                versioned("nano-2", "8.1", 2, available),
+                versioned("vim-4", "9.2", 4, available),
+            ],
+        )));
+        let root = tempfile::tempdir().unwrap();
+        let installation = Installation::open(root.path(), None).unwrap();
+        let mut client = Client::mocked(installation, registry).unwrap();
+
+        assert_eq!(
+            resolve_manifest(&manifest, &client, false).unwrap(),
+            [package::Id::from("nano-1"), package::Id::from("vim-4")]
+        );
+        let Err(Error::UnavailableVersions(unavailable)) = resolve_manifest(&manifest, &client, true) else {
+            panic!("expected strict import to fail");
+        };
+        assert_eq!(unavailable, ["vim 9.1-3"]);
+
+        install_manifest(&mut client, &manifest, false, true, true).unwrap();
+    }
+}
@@ -0,0 +1,353 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Questions & progress events of client operations
+//!
+//! Transactions never prompt or print themselves. They ask an [`Interaction`]
+//! to confirm each step and report typed [`Event`]s to it, so frontends other
+//! than the moss CLI (i.e. an installer GUI) can drive them. The CLI uses
+//! [`Terminal`], while [`Headless`] suits automation & tests.
+
+use std::{
+    io::{self, IsTerminal},
+    sync::{Arc, Mutex},
+};
+
+use chrono::Local;
+use tui::{
+    Styled,
+    dialoguer::{Confirm, Select, theme::ColorfulTheme},
+    pretty::autoprint_columns,
+};
+
+use super::BlitStats;
+use crate::{Package, installation::LockHolder, package, repository, state};
+
+/// Frontend of client operations, answering questions & receiving events
+pub trait Interaction: Send + Sync {
+    /// Ask whether to go ahead, `Ok(false)` cancels the operation
+    fn confirm(&self, question: &Question) -> io::Result<bool>;
+
+    /// Pick one of the distinct `candidates` satisfying `provider`, returning
+    /// its index. Candidates are sorted by priority, highest first.
+    fn choose(&self, provider: &str, candidates: &[Candidate]) -> io::Result<usize>;
+
+    /// Observe the progress of an operation
+    fn report(&self, event: Event);
+
+    /// Whether the progress of [`Event`]s is rendered by the frontend itself, hiding
+    /// the bars & lines the client otherwise draws while caching, blitting & running triggers
+    fn renders_progress(&self) -> bool {
+        false
+    }
+}
+
+/// A question asked before an operation changes the system
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Question {
+    /// Apply the changes of the last [`Event::Resolved`]
+    Continue,
+    /// Remove these protected packages, which may leave the system unbootable
+    RemoveProtected(Vec<String>),
+    /// Repair the last [`Event::Issues`], changing the system state
+    Repair,
+    /// Use the only close `suggestion` for a `requested` package which
+    /// can't be found
+    Suggestion { requested: String, suggestion: String },
+}
+
+/// A package which could be chosen for a provider
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub package: Package,
+    /// Highest priority repository offering it, if any
+    pub repository: Option<repository::Id>,
+}
+
+/// Progress of a client operation
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// The packages an operation changes, reported before confirmation
+    Resolved(Resolution),
+    /// Something the user should know, which doesn't stop the operation
+    Warning(String),
+    /// Another process holds the installation lock, which is waited for.
+    /// The holder is `None` if it couldn't be determined.
+    WaitingForLock(Option<LockHolder>),
+    /// Download progress of a package, from `0.0` to `1.0`
+    Downloading { package: package::Name, pct: f32 },
+    /// A downloaded package is being unpacked into the asset cache
+    Unpacking { package: package::Name },
+    /// A package was unpacked into the asset cache, `was_cached` if it wasn't downloaded
+    Cached { package: package::Name, was_cached: bool },
+    /// A package couldn't be fetched or unpacked, failing the operation once the others
+    /// are cached
+    CacheFailed { package: package::Name },
+    /// Packages are being blitted to a new root
+    Blitting,
+    /// Entries of the new root blitted so far, reported each hundredth of the `total`
+    Blitted { entries: u64, total: u64 },
+    /// The new root was blitted, or every state verification reblitted
+    BlitFinished(BlitStats),
+    /// Triggers of the given scope are running
+    TriggersStage(TriggersStage),
+    /// Packages which are no longer part of the new state
+    Removed(Vec<package::Name>),
+    /// Paths of removed packages which are kept, see [`super::retain`]
+    Retained(Vec<String>),
+    /// Verification of the assets & states started
+    Verifying,
+    /// Problems found by verification, empty if there are none
+    Issues(Vec<String>),
+    /// Packages are being reinstalled to repair verification issues
+    Reinstalling,
+    /// States affected by verification issues are being reblitted
+    Reblitting,
+    /// A state was reblitted
+    Reblitted(state::Id),
+    /// All verification issues were repaired
+    Repaired,
+    /// The operation completed successfully
+    Done,
+}
+
+/// Scope of the triggers being run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggersStage {
+    /// Triggers run in an isolated container before the new state is activated
+    Transaction,
+    /// Triggers run against the new root
+    System,
+}
+
+/// Packages changed by an operation
+#[derive(Debug, Clone, Default)]
+pub struct Resolution {
+    /// Packages being installed
+    pub added: Vec<Package>,
+    /// Installed packages replaced by a different release, as `(old, new)`
+    pub updated: Vec<(Package, Package)>,
+    /// Packages being removed
+    pub removed: Vec<Package>,
+    /// Requested packages which are already installed
+    pub unchanged: Vec<Package>,
+}
+
+impl Resolution {
+    /// Returns `true` if nothing is changed or was requested
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty() && self.unchanged.is_empty()
+    }
+}
+
+/// Interactive prompts & output of the moss CLI
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Terminal;
+
+impl Interaction for Terminal {
+    fn confirm(&self, question: &Question) -> io::Result<bool> {
+        let prompt = match question {
+            Question::Continue => " Do you wish to continue? ".to_owned(),
+            Question::RemoveProtected(packages) => {
+                println!(
+                    "{} Removing protected package(s) {} may leave the system unbootable",
+                    "WARNING".yellow(),
+                    packages.join(", ").bold()
+                );
+                println!();
+                format!(" Do you really wish to remove {}? ", packages.join(", "))
+            }
+            Question::Repair => {
+                " Fixing issues, this will change your system state. Do you wish to continue? ".to_owned()
+            }
+            // Only guess what was meant when someone can answer
+            Question::Suggestion { .. } if !io::stdin().is_terminal() => return Ok(false),
+            Question::Suggestion { requested, suggestion } => {
+                format!(" No package found for {requested}, did you mean {suggestion}? ")
+            }
+        };
+
+        Ok(Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(prompt)
+            .default(false)
+            .interact()?)
+    }
+
+    fn choose(&self, provider: &str, candidates: &[Candidate]) -> io::Result<usize> {
+        let items = candidates
+            .iter()
+            .map(|candidate| {
+                let meta = &candidate.package.meta;
+                let repository = candidate
+                    .repository
+                    .as_ref()
+                    .map(ToString::to_string)
+                    .unwrap_or_else(|| "local".to_owned());
+                format!(
+                    "{} {}-{} [{repository}] {}",
+                    meta.name, meta.version_identifier, meta.source_release, meta.summary
+                )
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Select::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!(
+                " Multiple packages provide {provider}, which should be installed? "
+            ))
+            .items(&items)
+            .default(0)
+            .interact()?)
+    }
+
+    fn report(&self, event: Event) {
+        match event {
+            Event::Resolved(resolution) => print_resolution(&resolution),
+            Event::Warning(warning) => eprintln!("{}: {warning}", "WARNING".yellow()),
+            Event::WaitingForLock(Some(holder)) => eprintln!(
+                "{} for lock held by PID {}, started {}",
+                "Waiting".yellow(),
+                holder.pid,
+                holder.since.with_timezone(&Local).format("%H:%M")
+            ),
+            Event::WaitingForLock(None) => eprintln!("{} for another process to release the lock", "Waiting".yellow()),
+            Event::Removed(packages) => {
+                for package in packages {
+                    println!("{} {}", "Removed".red(), package.as_str().bold());
+                }
+            }
+            Event::Retained(paths) => {
+                for path in paths {
+                    println!("{} {}", "Retained".yellow(), path.dim());
+                }
+            }
+            Event::Verifying => println!("Verifying assets"),
+            Event::Issues(issues) => {
+                if issues.is_empty() {
+                    println!("No issues found");
+                    return;
+                }
+
+                println!(
+                    "Found {} issue{}",
+                    issues.len(),
+                    if issues.len() == 1 { "" } else { "s" }
+                );
+                for issue in &issues {
+                    println!(" {} {issue}", "×".yellow());
+                }
+            }
+            Event::Reinstalling => println!("Reinstalling packages"),
+            Event::Reblitting => println!("Reblitting affected states"),
+            Event::BlitFinished(stats) => print_blit_stats(&stats),
+            Event::Reblitted(state) => println!(" {} state #{state}", "»".green()),
+            Event::Repaired => println!("All issues resolved"),
+            // Rendered by the progress bars
+            Event::Downloading { .. }
+            | Event::Unpacking { .. }
+            | Event::Cached { .. }
+            | Event::CacheFailed { .. }
+            | Event::Blitting
+            | Event::Blitted { .. }
+            | Event::TriggersStage(_)
+            | Event::Done => {}
+        }
+    }
+}
+
+/// i.e. `51234 entries blitted in 0.42s (122.0k / s)`
+fn print_blit_stats(stats: &BlitStats) {
+    let num_entries = stats.num_entries();
+    let elapsed = stats.elapsed.as_secs_f32();
+
+    println!(
+        "\n{} entries blitted in {} {}",
+        num_entries.to_string().bold(),
+        format!("{elapsed:.2}s").bold(),
+        format!("({:.1}k / s)", num_entries as f32 / elapsed / 1_000.0).dim()
+    );
+}
+
+fn print_resolution(resolution: &Resolution) {
+    if resolution.is_empty() {
+        println!("No package changes");
+        return;
+    }
+
+    if !resolution.unchanged.is_empty() {
+        println!("The following package(s) are already installed:");
+        println!();
+        autoprint_columns(&resolution.unchanged);
+        println!();
+    }
+    if !resolution.added.is_empty() {
+        println!("The following package(s) will be installed:");
+        println!();
+        autoprint_columns(&resolution.added);
+        println!();
+    }
+    if !resolution.updated.is_empty() {
+        let updated = resolution
+            .updated
+            .iter()
+            .map(|(old, new)| package::Update { old, new })
+            .collect::<Vec<_>>();
+
+        println!("The following package(s) will be updated:");
+        println!();
+        autoprint_columns(&updated);
+        println!();
+    }
+    if !resolution.removed.is_empty() {
+        println!("The following package(s) will be removed:");
+        println!();
+        autoprint_columns(&resolution.removed);
+        println!();
+    }
+}
+
+/// Answers every question the same way, picks the highest priority candidate
+/// & records what happened
+#[derive(Debug, Default)]
+pub struct Headless {
+    answer: bool,
+    questions: Mutex<Vec<Question>>,
+    events: Mutex<Vec<Event>>,
+}
+
+impl Headless {
+    /// Answer `answer` to every question
+    pub fn new(answer: bool) -> Arc<Self> {
+        Arc::new(Self {
+            answer,
+            ..Default::default()
+        })
+    }
+
+    /// Questions asked so far
+    pub fn questions(&self) -> Vec<Question> {
+        self.questions.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Events reported so far
+    pub fn events(&self) -> Vec<Event> {
+        self.events.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl Interaction for Headless {
+    fn confirm(&self, question: &Question) -> io::Result<bool> {
+        self.questions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(question.clone());
+        Ok(self.answer)
+    }
+
+    fn choose(&self, _provider: &str, _candidates: &[Candidate]) -> io::Result<usize> {
+        Ok(0)
+    }
+
+    fn report(&self, event: Event) {
+        self.events.lock().unwrap_or_else(|e| e.into_inner()).push(event);
+    }
+}
@@ -10,41 +10,49 @@
 
 use std::{
     borrow::Borrow,
+    collections::{BTreeMap, BTreeSet},
     fmt, io,
     os::{fd::RawFd, unix::fs::symlink},
     path::{Path, PathBuf},
+    sync::Arc,
     time::{Duration, Instant},
 };
 
 use astr::AStr;
+use chrono::{DateTime, Local, Utc};
 use fs_err as fs;
-use futures_util::{StreamExt, TryStreamExt, stream};
+use futures_util::{StreamExt, stream};
 use itertools::Itertools;
 use nix::{
     errno::Errno,
-    fcntl::{self, OFlag},
-    libc::{AT_FDCWD, RENAME_EXCHANGE, SYS_renameat2, syscall},
-    sys::stat::{Mode, fchmodat, mkdirat},
+    fcntl::{self, AtFlags, OFlag},
+    sys::stat::{Mode, fchmodat, fstatat, mkdirat},
     unistd::{close, linkat, mkdir, symlinkat},
 };
 use postblit::TriggerScope;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use stone::{StoneDecodedPayload, StonePayloadLayoutFile, StonePayloadLayoutRecord};
 use thiserror::Error;
-use tracing::{info, info_span, trace};
-use tui::{MultiProgress, ProgressBar, ProgressStyle, Styled};
+use tracing::{info, info_span, trace, warn};
+use tui::{ProgressBar, ProgressStyle, Styled};
 use vfs::tree::{BlitFile, Element, builder::TreeBuilder};
 
 use self::install::install;
+use self::interaction::{Event, Interaction, Question, Terminal, TriggersStage};
 use self::prune::{prune_cache, prune_states};
 use self::remove::remove;
 use self::sync::sync;
 use self::verify::verify;
 use crate::{
-    Installation, Package, Provider, Registry, Signal, State, SystemModel,
+    Installation, Package, Provider, ProviderGlob, Registry, Signal, State, SystemModel,
     client::fetch::fetch,
-    db, environment, installation, package,
-    registry::plugin::{self, Plugin},
+    db, environment, installation,
+    manifest::{self, Manifest},
+    package, progress,
+    registry::{
+        plugin::{self, Plugin},
+        suggest,
+    },
     repository, runtime, signal,
     state::{self, Selection},
     system_model::{self, LoadedSystemModel},
@@ -55,18 +63,29 @@ pub use self::index::index;
 pub use self::self_upgrade::self_upgrade;
 
 mod boot;
-mod cache;
-mod fetch;
-mod install;
+pub(crate) mod cache;
+pub(crate) mod fetch;
 mod postblit;
-mod remove;
+pub(crate) mod remove;
 mod self_upgrade;
-mod sync;
-mod verify;
+mod swap;
 
 pub mod extract;
+pub mod filediff;
+pub mod hooks;
 pub mod index;
+pub mod info;
+pub mod install;
+pub mod interaction;
+pub mod model;
+pub mod protected;
 pub mod prune;
+pub mod quota;
+pub mod retain;
+pub mod rollback;
+pub mod stats;
+pub mod sync;
+pub mod verify;
 
 /// A builder for [`Client`]
 pub struct ClientBuilder {
@@ -74,7 +93,8 @@ pub struct ClientBuilder {
     installation: Installation,
     repositories: Option<repository::Map>,
     system_model_path: Option<PathBuf>,
-    blit_root: Option<PathBuf>,
+    blit_root: Option<(PathBuf, EphemeralOptions)>,
+    interaction: Option<Arc<dyn Interaction>>,
 }
 
 impl ClientBuilder {
@@ -90,6 +110,13 @@ impl ClientBuilder {
         self
     }
 
+    /// Set the [`Interaction`] confirming & observing operations, instead of
+    /// prompting & printing on the [`Terminal`]
+    pub fn interaction(mut self, interaction: Arc<dyn Interaction>) -> ClientBuilder {
+        self.interaction = Some(interaction);
+        self
+    }
+
     /// Set the client to an ephemeral client that doesn't record state changes
     /// and blits to a different root.
     ///
@@ -98,8 +125,14 @@ impl ClientBuilder {
     ///
     /// Returns an error on construction if `blit_root` is the same as the installation
     /// root, since the system client should always be stateful.
-    pub fn ephemeral(mut self, blit_root: impl Into<PathBuf>) -> ClientBuilder {
-        self.blit_root = Some(blit_root.into());
+    pub fn ephemeral(self, blit_root: impl Into<PathBuf>) -> ClientBuilder {
+        self.ephemeral_with_options(blit_root, EphemeralOptions::default())
+    }
+
+    /// Set the client to an ephemeral client, controlling which triggers run
+    /// with [`EphemeralOptions`]. See [`ClientBuilder::ephemeral`].
+    pub fn ephemeral_with_options(mut self, blit_root: impl Into<PathBuf>, options: EphemeralOptions) -> ClientBuilder {
+        self.blit_root = Some((blit_root.into(), options));
         self
     }
 
@@ -110,6 +143,16 @@ impl ClientBuilder {
                 Some(system_model::load(&path)?.ok_or(Error::ImportSystemModelDoesntExist(path.to_owned()))?);
         }
 
+        // Finish any `/usr` swap interrupted by a previous invocation, unless
+        // another process holding the lock may still be swapping
+        let usr = self.installation.root.join("usr");
+        if !self.installation.read_only()
+            && swap::is_pending(&usr)
+            && let Some(_lock) = self.installation.try_lock()?
+        {
+            swap::recover(&usr)?;
+        }
+
         let config = config::Manager::system(&self.installation.root, "moss");
         let install_db = db::meta::Database::new(self.installation.db_path("install").to_str().unwrap_or_default())?;
         let state_db = db::state::Database::new(self.installation.db_path("state").to_str().unwrap_or_default())?;
@@ -123,21 +166,42 @@ impl ClientBuilder {
             repository::Manager::with_config_manager(config.clone(), self.installation.clone())?
         };
 
-        let registry = build_registry(&self.installation, &repositories, &install_db, &state_db)?;
+        let (overlay, overlay_skipped) = plugin::Cobble::load_overlay(&config, &self.installation.root);
+        let (local, skipped) = plugin::Cobble::load(&config, &self.installation.root);
+        for (path, error) in overlay_skipped.into_iter().chain(skipped) {
+            warn!(path = %path.display(), %error, "Skipping unreadable local stone");
+        }
+
+        let registry = build_registry(
+            &self.installation,
+            &repositories,
+            &overlay,
+            &local,
+            &install_db,
+            &state_db,
+        )?;
 
         let mut client = Client {
             config,
             installation: self.installation,
             repositories,
+            overlay,
+            local,
             registry,
             install_db,
             state_db,
             layout_db,
             scope: Scope::Stateful,
+            interaction: self.interaction.unwrap_or_else(|| Arc::new(Terminal)),
         };
 
-        if let Some(blit_root) = self.blit_root {
-            client = client.ephemeral(blit_root)?;
+        // Finish any state removal interrupted by a previous invocation
+        if !client.installation.read_only() {
+            prune::recover(&client)?;
+        }
+
+        if let Some((blit_root, options)) = self.blit_root {
+            client = client.ephemeral_with_options(blit_root, options)?;
         }
         Ok(client)
     }
@@ -159,8 +223,14 @@ pub struct Client {
     config: config::Manager,
     /// All of our configured repositories, to seed the [`crate::registry::Registry`]
     repositories: repository::Manager,
+    /// Stones of the local overlay repository, preferred over every other
+    overlay: plugin::Cobble,
+    /// Stones from the configured local directories
+    local: plugin::Cobble,
     /// Operational scope (real systems, ephemeral, etc)
     scope: Scope,
+    /// Confirms & observes operations
+    interaction: Arc<dyn Interaction>,
 }
 
 impl Client {
@@ -172,6 +242,7 @@ impl Client {
             repositories: None,
             system_model_path: None,
             blit_root: None,
+            interaction: None,
         }
     }
 
@@ -180,19 +251,185 @@ impl Client {
         Self::builder(client_name.to_string(), installation).build()
     }
 
+    /// The [`Installation`] this client operates on
+    pub fn installation(&self) -> &Installation {
+        &self.installation
+    }
+
+    /// Reload the active state & configured repositories, picking up changes
+    /// made since this client was constructed, i.e. by an earlier command
+    /// sharing it
+    pub fn reload(&mut self) -> Result<(), Error> {
+        self.installation.reload_active_state();
+        self.refresh_overlay_stones();
+
+        if self.repositories.is_config_source() {
+            self.repositories =
+                repository::Manager::with_config_manager(self.config.clone(), self.installation.clone())?;
+        }
+
+        self.registry = build_registry(
+            &self.installation,
+            &self.repositories,
+            &self.overlay,
+            &self.local,
+            &self.install_db,
+            &self.state_db,
+        )?;
+
+        Ok(())
+    }
+
     /// Returns `true` if this is an ephemeral client
     pub fn is_ephemeral(&self) -> bool {
         matches!(self.scope, Scope::Ephemeral { .. })
     }
 
+    /// Returns `true` if this is an ephemeral client which can't use the
+    /// network, see [`EphemeralOptions::offline`]
+    pub fn is_offline(&self) -> bool {
+        matches!(self.scope, Scope::Ephemeral { options, .. } if options.offline)
+    }
+
+    /// Use `interaction` to confirm & observe operations, see [`ClientBuilder::interaction`]
+    pub fn with_interaction(mut self, interaction: Arc<dyn Interaction>) -> Self {
+        self.interaction = interaction;
+        self
+    }
+
+    /// Ask the [`Interaction`] to confirm `question`, unless answered by `yes`
+    fn confirm(&self, yes: bool, question: &Question) -> io::Result<bool> {
+        if yes {
+            return Ok(true);
+        }
+        self.interaction.confirm(question)
+    }
+
+    /// Acquire exclusive access to the installation for a mutating operation,
+    /// see [`Installation::lock`]
+    ///
+    /// Read-only operations mustn't take the lock, so they keep working while
+    /// another process changes the installation
+    pub(crate) fn lock(&self) -> Result<installation::Lock, Error> {
+        self.installation
+            .lock(|holder| self.interaction.report(Event::WaitingForLock(holder.cloned())))
+            .map_err(|error| match error {
+                installation::Error::Locked(Some(holder)) => Error::Locked {
+                    pid: holder.pid,
+                    since: holder.since,
+                },
+                error => Error::Installation(error),
+            })
+    }
+
+    /// Acquire the lock of [`Client::lock`], unless the operation is only
+    /// simulated
+    fn lock_unless(&self, simulate: bool) -> Result<Option<installation::Lock>, Error> {
+        if simulate { Ok(None) } else { self.lock().map(Some) }
+    }
+
+    /// Acquire the lock of [`Client::lock`] for an operation building on the
+    /// active state, failing with [`Error::StateChanged`] if another process
+    /// activated a state since this client was built
+    fn lock_state(&self) -> Result<installation::Lock, Error> {
+        let lock = self.lock()?;
+        if self.state_changed() {
+            return Err(Error::StateChanged);
+        }
+        Ok(lock)
+    }
+
+    /// Acquire the lock of [`Client::lock`] unless `simulate`, first reloading
+    /// the client if another process activated a state since it was built,
+    /// see [`Client::reload`]
+    fn lock_reloaded(&mut self, simulate: bool) -> Result<Option<installation::Lock>, Error> {
+        if simulate {
+            return Ok(None);
+        }
+        let lock = self.lock()?;
+        if self.state_changed() {
+            self.reload()?;
+        }
+        Ok(Some(lock))
+    }
+
+    /// Returns `true` if the active state recorded in the installation tree
+    /// is no longer the one this client was built with
+    ///
+    /// Ephemeral clients don't build on the active state, so never see it change
+    fn state_changed(&self) -> bool {
+        !self.scope.is_ephemeral() && self.installation.read_active_state() != self.installation.active_state
+    }
+
     /// Perform package installation
-    pub fn install(&mut self, packages: &[&str], yes: bool, simulate: bool) -> Result<install::Timing, Error> {
-        install(self, packages, yes, simulate).map_err(|error| Error::Install(Box::new(error)))
+    ///
+    /// Packages named in `choices` are picked when several satisfy a requested provider.
+    /// The [`install::Graph`] returned tells why each package was resolved.
+    pub fn install(
+        &mut self,
+        packages: &[&str],
+        choices: &[&str],
+        yes: bool,
+        simulate: bool,
+    ) -> Result<install::Outcome, Error> {
+        let _lock = self.lock_reloaded(simulate)?;
+        install(self, packages, choices, yes, simulate).map_err(|error| Error::Install(Box::new(error)))
+    }
+
+    /// Install the explicit packages of a [`Manifest`] at their pinned versions,
+    /// falling back to the latest available unless `strict`
+    pub fn install_manifest(
+        &mut self,
+        manifest: &Manifest,
+        strict: bool,
+        yes: bool,
+        simulate: bool,
+    ) -> Result<install::Outcome, Error> {
+        let _lock = self.lock_reloaded(simulate)?;
+        install::install_manifest(self, manifest, strict, yes, simulate)
+            .map_err(|error| Error::Install(Box::new(error)))
     }
 
     /// Perform package removals
-    pub fn remove(&mut self, packages: &[&str], yes: bool, simulate: bool) -> Result<remove::Timing, Error> {
-        remove(self, packages, yes, simulate).map_err(|error| Error::Remove(Box::new(error)))
+    ///
+    /// Removing a [protected](protected) package, directly or as a dependent,
+    /// fails unless `allow_essential`
+    pub fn remove(
+        &mut self,
+        packages: &[&str],
+        yes: bool,
+        simulate: bool,
+        allow_essential: bool,
+    ) -> Result<remove::Timing, Error> {
+        let _lock = self.lock_reloaded(simulate)?;
+        remove(self, packages, yes, simulate, allow_essential).map_err(|error| Error::Remove(Box::new(error)))
+    }
+
+    /// Package names starting with `prefix` for shell completion, capped to `limit`
+    ///
+    /// Installed names come from every state rather than just the active one,
+    /// as that can be answered with a single indexed query
+    pub fn complete_package_names(
+        &self,
+        prefix: &str,
+        installed: bool,
+        limit: usize,
+    ) -> Result<Vec<package::Name>, Error> {
+        if installed {
+            return Ok(self.install_db.names_with_prefix(prefix, limit)?);
+        }
+
+        let mut names = BTreeSet::new();
+        for repo in self.repositories.active() {
+            names.extend(repo.db.names_with_prefix(prefix, limit)?);
+        }
+
+        Ok(names.into_iter().take(limit).collect())
+    }
+
+    /// Names of the packages guarded against removal, see [`protected`]
+    pub fn protected_packages(&self) -> BTreeSet<String> {
+        protected::load(&self.config)
     }
 
     /// Perform package fetches
@@ -200,9 +437,51 @@ impl Client {
         fetch(self, packages, output_dir, verbose).map_err(|error| Error::Fetch(Box::new(error)))
     }
 
-    /// Perform a sync
-    pub fn sync(&mut self, yes: bool, simulate: bool) -> Result<sync::Timing, Error> {
-        sync(self, yes, simulate).map_err(|error| Error::Sync(Box::new(error)))
+    /// Perform a sync, keeping packages with names matching the `exclude` globs
+    /// or those of the `sync.d` configs at their installed release, see [`sync::Exclusions`]
+    pub fn sync(&mut self, yes: bool, simulate: bool, exclude: &[String]) -> Result<sync::Timing, Error> {
+        let _lock = self.lock_reloaded(simulate)?;
+        sync(self, yes, simulate, exclude).map_err(|error| Error::Sync(Box::new(error)))
+    }
+
+    /// Converge the system with the provided [`SystemModel`], configuring its
+    /// repositories & syncing to exactly its packages
+    pub fn apply_system_model(&mut self, model: SystemModel, yes: bool) -> Result<(), Error> {
+        let _lock = self.lock_reloaded(false)?;
+        model::apply(self, &model, yes).map_err(|error| Error::Model(Box::new(error)))
+    }
+
+    /// Compare the system model recorded for the active state, `/usr/lib/system-model.kdl`,
+    /// with its explicit packages & the configured repositories
+    ///
+    /// Returns `None` if no model is recorded
+    pub fn model_status(&self) -> Result<Option<model::Drift>, Error> {
+        let Some(model) = system_model::load(&self.installation.root.join("usr/lib/system-model.kdl"))
+            .map_err(Error::LoadSystemModel)?
+        else {
+            return Ok(None);
+        };
+
+        // Read from the tree, like the model, as a transaction may have activated another state
+        let explicit = match self.installation.read_active_state() {
+            Some(id) => {
+                let state = self.state_db.get(id)?;
+                self.resolve_packages(state.selections.iter().filter_map(|s| s.explicit.then_some(&s.package)))?
+            }
+            None => vec![],
+        };
+
+        Ok(Some(model::drift(
+            &SystemModel::from(model),
+            &explicit,
+            self.repositories.list(),
+        )))
+    }
+
+    /// List the changes a sync would apply, without modifying any state, cache
+    /// or blit root
+    pub fn pending_sync(&self, exclude: &[String]) -> Result<Vec<sync::Pending>, Error> {
+        sync::pending(self, exclude).map_err(|error| Error::Sync(Box::new(error)))
     }
 
     /// Transition to an ephemeral client that doesn't record state changes
@@ -214,6 +493,16 @@ impl Client {
     /// Returns an error if `blit_root` is the same as the installation root,
     /// since the system client should always be stateful.
     pub fn ephemeral(self, blit_root: impl Into<PathBuf>) -> Result<Self, Error> {
+        self.ephemeral_with_options(blit_root, EphemeralOptions::default())
+    }
+
+    /// Reconfigure the client to become ephemeral, controlling which triggers
+    /// run with [`EphemeralOptions`]. See [`Client::ephemeral`].
+    pub fn ephemeral_with_options(
+        self,
+        blit_root: impl Into<PathBuf>,
+        options: EphemeralOptions,
+    ) -> Result<Self, Error> {
         let blit_root = blit_root.into();
 
         if blit_root.canonicalize()? == self.installation.root.canonicalize()? {
@@ -221,7 +510,7 @@ impl Client {
         }
 
         Ok(Self {
-            scope: Scope::Ephemeral { blit_root },
+            scope: Scope::Ephemeral { blit_root, options },
             ..self
         })
     }
@@ -230,13 +519,28 @@ impl Client {
     /// are downloaded and added to the meta db
     pub async fn ensure_repos_initialized(&mut self) -> Result<usize, Error> {
         let num_initialized = self.repositories.ensure_all_initialized().await?;
-        self.registry = build_registry(&self.installation, &self.repositories, &self.install_db, &self.state_db)?;
+        self.registry = build_registry(
+            &self.installation,
+            &self.repositories,
+            &self.overlay,
+            &self.local,
+            &self.install_db,
+            &self.state_db,
+        )?;
         Ok(num_initialized)
     }
 
     /// Reload all configured repositories and refreshes their index file, then update
     /// registry with all active repositories.
+    ///
+    /// Offline clients keep the indexes they have, see [`Client::is_offline`].
     pub async fn refresh_repositories(&mut self) -> Result<(), Error> {
+        if self.is_offline() {
+            return Ok(());
+        }
+
+        let _lock = self.lock()?;
+
         // Reload manager if config sourced to pickup config changes
         // then refresh indexes
         if self.repositories.is_config_source() {
@@ -246,16 +550,88 @@ impl Client {
         self.repositories.refresh_all().await?;
 
         // Rebuild registry
-        self.registry = build_registry(&self.installation, &self.repositories, &self.install_db, &self.state_db)?;
+        self.registry = build_registry(
+            &self.installation,
+            &self.repositories,
+            &self.overlay,
+            &self.local,
+            &self.install_db,
+            &self.state_db,
+        )?;
 
         Ok(())
     }
 
-    pub fn verify(&self, yes: bool, verbose: bool) -> Result<(), Error> {
+    /// Rescan the configured directories of local stones, reading only new or
+    /// modified ones, then update the registry with them.
+    ///
+    /// Stones which can't be read are skipped with a warning.
+    pub fn refresh_local(&mut self) -> Result<(), Error> {
+        self.refresh_overlay_stones();
+        for (path, error) in self.local.refresh() {
+            self.interaction.report(Event::Warning(format!(
+                "Skipping unreadable local stone {}: {error}",
+                path.display()
+            )));
+        }
+
+        self.registry = build_registry(
+            &self.installation,
+            &self.repositories,
+            &self.overlay,
+            &self.local,
+            &self.install_db,
+            &self.state_db,
+        )?;
+
+        Ok(())
+    }
+
+    /// Rescan the local overlay repository if it was modified since the last
+    /// scan, then update the registry with it. Returns whether it was rescanned.
+    ///
+    /// Stones which can't be read are skipped with a warning.
+    pub fn refresh_overlay(&mut self) -> Result<bool, Error> {
+        if !self.refresh_overlay_stones() {
+            return Ok(false);
+        }
+
+        self.registry = build_registry(
+            &self.installation,
+            &self.repositories,
+            &self.overlay,
+            &self.local,
+            &self.install_db,
+            &self.state_db,
+        )?;
+
+        Ok(true)
+    }
+
+    /// Rescan the local overlay repository if modified, leaving the registry
+    /// to be rebuilt. Returns whether it was rescanned.
+    fn refresh_overlay_stones(&mut self) -> bool {
+        let Some(skipped) = self.overlay.refresh_if_modified() else {
+            return false;
+        };
+
+        for (path, error) in skipped {
+            self.interaction.report(Event::Warning(format!(
+                "Skipping unreadable stone {} of the local repository: {error}",
+                path.display()
+            )));
+        }
+
+        true
+    }
+
+    /// Verify the assets & state trees of this installation, restricted
+    /// by the provided [`verify::Options`], and fix any issues found
+    pub fn verify(&self, yes: bool, options: &verify::Options) -> Result<(), Error> {
         if self.scope.is_ephemeral() {
             return Err(Error::EphemeralProhibitedOperation);
         }
-        verify(self, yes, verbose)?;
+        verify(self, yes, options)?;
         Ok(())
     }
 
@@ -263,24 +639,35 @@ impl Client {
     ///
     /// This allows automatic removal of unused states (and their associated assets)
     /// from the disk, acting as a garbage collection facility.
-    pub fn prune_states(&self, strategy: prune::Strategy<'_>, yes: bool) -> Result<(), Error> {
+    ///
+    /// With `dry_run`, nothing is removed and the report describes what would be.
+    pub fn prune_states(
+        &self,
+        strategy: prune::Strategy<'_>,
+        yes: bool,
+        dry_run: bool,
+    ) -> Result<prune::PruneReport, Error> {
         if self.scope.is_ephemeral() {
             return Err(Error::EphemeralProhibitedOperation);
         }
+        let _lock = if dry_run { None } else { Some(self.lock_state()?) };
 
-        prune_states(self, strategy, yes)?;
-
-        Ok(())
+        Ok(prune_states(self, strategy, yes, dry_run)?)
     }
 
     /// Prune all cached data that isn't related to any states or active repositories.
     ///
     /// This will remove all downloaded stones & unpacked asset data for packages not
-    /// in that set.
-    pub fn prune_cache(&self) -> Result<usize, Error> {
+    /// in that set. With `dry_run`, nothing is removed and the report describes what
+    /// would be.
+    ///
+    /// Assets still linked from elsewhere are kept. With `repair`, those only linked
+    /// from the staging tree of an interrupted blit are removed along with those links.
+    pub fn prune_cache(&self, dry_run: bool, repair: bool) -> Result<prune::PruneReport, Error> {
         if self.scope.is_ephemeral() {
             return Err(Error::EphemeralProhibitedOperation);
         }
+        let _lock = self.lock_unless(dry_run)?;
 
         prune_cache(
             &self.state_db,
@@ -288,10 +675,35 @@ impl Client {
             &self.layout_db,
             &self.installation,
             &self.repositories,
+            dry_run,
+            repair,
         )
         .map_err(Error::Prune)
     }
 
+    /// Compute disk usage statistics for the installation, see [`stats::Stats`]
+    pub fn statistics(&self) -> Result<stats::Stats, Error> {
+        stats::statistics(self)
+    }
+
+    /// Compute how much of the asset pool the state `id` shares with other
+    /// states, and how much only it references
+    pub fn state_disk_usage(&self, id: state::Id) -> Result<stats::StateUsage, Error> {
+        // Fail the same way as other lookups of an unknown state
+        self.state_db.get(id)?;
+
+        Ok(self.state_disk_usages()?.remove(&id).unwrap_or_default())
+    }
+
+    /// Compute the [`stats::StateUsage`] of every state
+    pub fn state_disk_usages(&self) -> Result<BTreeMap<state::Id, stats::StateUsage>, Error> {
+        Ok(stats::state_usages(
+            &self.state_db.all()?,
+            &self.layout_db,
+            &self.installation,
+        )?)
+    }
+
     /// Resolves the provided id with the underlying registry, returning the first matching [`Package`]
     pub fn resolve_package(&self, package: &package::Id) -> Result<Package, Error> {
         self.registry
@@ -300,6 +712,51 @@ impl Client {
             .ok_or(Error::MissingMetadata(package.clone()))
     }
 
+    /// Returns the highest priority active repository which provides the package
+    pub fn package_repository(&self, package: &package::Id) -> Option<repository::Id> {
+        self.repositories
+            .active()
+            .sorted_by_key(|repo| std::cmp::Reverse(u64::from(repo.repository.priority)))
+            .find(|repo| repo.db.get(package).is_ok())
+            .map(|repo| repo.id)
+    }
+
+    /// Suggest up to `limit` available providers resembling the mistyped `provider`,
+    /// most similar first
+    pub fn suggest(&self, provider: &Provider, limit: usize) -> Vec<suggest::Suggestion> {
+        self.registry.suggest(provider, limit)
+    }
+
+    /// Returns the origin, installed size & resolved dependencies of `package`
+    pub fn package_details(&self, package: &Package) -> Result<info::Details, Error> {
+        info::details(self, package)
+    }
+
+    /// Returns the size of the assets of each of the installed `packages`
+    pub fn installed_sizes<'a>(
+        &self,
+        packages: impl IntoIterator<Item = &'a package::Id>,
+    ) -> Result<BTreeMap<package::Id, u64>, Error> {
+        info::installed_sizes(self, packages)
+    }
+
+    /// Resolve the transitive dependencies of `package`, preferring installed
+    /// packages for installed dependents
+    pub fn dependency_tree(&self, package: &Package) -> info::Tree {
+        info::dependency_tree(self, package)
+    }
+
+    /// Compare the layout of package `name` as selected in state `from` with
+    /// the one selected in state `to`
+    pub fn package_file_diff(
+        &self,
+        name: &package::Name,
+        from: state::Id,
+        to: state::Id,
+    ) -> Result<filediff::FileDiff, Error> {
+        filediff::package_file_diff(self, name, from, to)
+    }
+
     /// Resolves the provided id's with the underlying registry, returning
     /// the first [`Package`] for each id.
     ///
@@ -325,11 +782,43 @@ impl Client {
             .collect()
     }
 
+    /// Returns packages with a provider matching `glob`, once per id
+    pub fn lookup_packages_by_provider_glob(&self, glob: &ProviderGlob, flags: package::Flags) -> Vec<Package> {
+        self.registry
+            .by_provider_glob(glob, flags)
+            .unique_by(|p| p.id.clone())
+            .collect()
+    }
+
+    /// Returns the best available candidate of each package in `names`, from a
+    /// single listing of the registry rather than a lookup per package
+    pub fn best_available(&self, names: &BTreeSet<package::Name>) -> BTreeMap<package::Name, Package> {
+        let mut best = BTreeMap::new();
+
+        // Listed by plugin priority, then newest release first
+        for package in self.registry.list(package::Flags::new().with_available()) {
+            if names.contains(&package.meta.name) {
+                best.entry(package.meta.name.clone()).or_insert(package);
+            }
+        }
+
+        best
+    }
+
     /// Return a sorted iterator of packages matching the given flags
     pub fn list_packages(&self, flags: package::Flags) -> impl Iterator<Item = Package> + '_ {
         self.registry.list(flags)
     }
 
+    /// Restrict queries of this client to the packages of the repository `id`
+    pub fn restrict_to_repository(&mut self, id: &repository::Id) -> Result<(), Error> {
+        if self.registry.retain_origin(&plugin::Origin::Repository(id.clone())) {
+            Ok(())
+        } else {
+            Err(Error::UnknownRepository(id.clone()))
+        }
+    }
+
     /// Returns all packages with names containing the provided keyword
     /// and match the given flags
     pub fn search_packages<'a>(
@@ -340,11 +829,19 @@ impl Client {
         self.registry.by_keyword(keyword, flags)
     }
 
+    /// Returns all packages whose name, summary or description contain every
+    /// word of `text` and match the given flags, best matches first
+    pub fn search(&self, text: &str, flags: package::Flags) -> Vec<Package> {
+        self.registry.search(text, flags)
+    }
+
     /// Activates the provided state and runs system triggers once applied.
     ///
     /// The current state gets archived.\
     /// Returns the old state that was archived.
     pub fn activate_state(&self, id: state::Id, skip_triggers: bool, skip_boot: bool) -> Result<state::Id, Error> {
+        let _lock = self.lock_state()?;
+
         // Fetch the new state
         let new = self.state_db.get(id).map_err(|_| Error::StateDoesntExist(id))?;
 
@@ -357,6 +854,15 @@ impl Client {
             return Err(Error::StateAlreadyActive(id));
         }
 
+        let hook_payload = hooks::Payload::new(
+            hooks::Operation::Activate,
+            &self.installation.root,
+            Some(&self.state_db.get(old)?),
+            &new.selections,
+            Some(new.id),
+        );
+        self.run_hooks(&hook_payload)?;
+
         let staging_dir = self.installation.staging_dir();
 
         // Ensure staging dir exists
@@ -375,26 +881,94 @@ impl Client {
 
         // Build VFS from new state selections
         // to build triggers from
-        let fstree = self.vfs(new.selections.iter().map(|selection| &selection.package))?;
+        let fstree = self.state_vfs(&new)?;
 
         if !skip_triggers {
             // Run system triggers
-            Self::apply_triggers(TriggerScope::System(&self.installation, &self.scope), &fstree)?;
+            self.apply_triggers(TriggerScope::System(&self.installation, &self.scope), &fstree)?;
         }
 
         if !skip_boot {
             boot::synchronize(self, &new)?;
         }
 
+        self.run_hooks(&hook_payload.at(hooks::Stage::Post, None))?;
+
         Ok(old)
     }
 
+    /// Run the transaction hooks of `payload.stage`, see [`hooks`]
+    ///
+    /// A failed pre-transaction hook fails the operation, while failed
+    /// post-transaction hooks are reported as warnings. Ephemeral clients
+    /// don't change states, so never run hooks.
+    fn run_hooks(&self, payload: &hooks::Payload) -> Result<(), Error> {
+        if self.scope.is_ephemeral() {
+            return Ok(());
+        }
+
+        let failures =
+            hooks::run(&self.installation.root, payload, environment::HOOK_TIMEOUT).unwrap_or_else(|e| vec![e]);
+
+        match payload.stage {
+            hooks::Stage::Pre => match failures.into_iter().next() {
+                Some(error) => Err(Error::PreTransactionHook(error)),
+                None => Ok(()),
+            },
+            hooks::Stage::Post => {
+                for error in failures {
+                    self.interaction
+                        .report(Event::Warning(format!("Post-transaction {error}")));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Find the state `steps` before the active one, skipping any that were pruned
+    pub fn rollback_target(&self, steps: usize) -> Result<state::Id, Error> {
+        rollback::target(self, steps)
+    }
+
+    /// Roll back to the provided state, re-blitting its archived tree first when `hard`
+    ///
+    /// Returns the previously active state
+    pub fn rollback(&self, id: state::Id, hard: bool) -> Result<state::Id, Error> {
+        let _lock = self.lock_state()?;
+        rollback::rollback(self, id, hard)
+    }
+
+    /// Protect the provided state from pruning with [`prune::Strategy::KeepTagged`]
+    /// by appending the [`state::PROTECTED_MARKER`] to its description.
+    pub fn protect_state(&self, id: state::Id) -> Result<(), Error> {
+        if self.scope.is_ephemeral() {
+            return Err(Error::EphemeralProhibitedOperation);
+        }
+
+        let _lock = self.lock()?;
+        let state = self.state_db.get(id).map_err(|_| Error::StateDoesntExist(id))?;
+
+        if state.is_protected() {
+            return Ok(());
+        }
+
+        let description = match state.description {
+            Some(description) => format!("{description} {}", state::PROTECTED_MARKER),
+            None => state::PROTECTED_MARKER.to_owned(),
+        };
+
+        self.state_db.set_description(id, Some(&description))?;
+
+        Ok(())
+    }
+
     /// Create a new recorded state from the provided packages
     /// provided packages and write that state ID to the installation
     /// Then blit the filesystem, promote it, finally archiving the active ID
     ///
     /// Returns `None` if the client is ephemeral
     pub fn new_state(&self, selections: &[Selection], summary: impl ToString) -> Result<Option<State>, Error> {
+        let _lock = self.lock_state()?;
         let _guard = signal::ignore([Signal::SIGINT])?;
         let _fd = signal::inhibit(
             vec!["shutdown", "sleep", "idle", "handle-lid-switch"],
@@ -426,19 +1000,43 @@ impl Client {
         );
 
         let old_state = self.installation.active_state;
+        let old = old_state.map(|id| self.state_db.get(id)).transpose()?;
+
+        // Hooks may abort the transaction before anything is changed
+        let hook_payload = hooks::Payload::new(
+            hooks::Operation::NewState,
+            &self.installation.root,
+            old.as_ref(),
+            selections,
+            None,
+        )
+        .with_summary(summary.to_string());
+        self.run_hooks(&hook_payload)?;
 
-        let fstree = self.blit_root(selections.iter().map(|s| &s.package))?;
+        // Ephemeral roots start afresh, so never retain anything
+        let retained = match (&self.scope, &old) {
+            (Scope::Stateful, Some(old)) => retain::removed(&self.layout_db, old, selections)?,
+            _ => vec![],
+        };
+        let (layouts, retained) = retain::layouts(&self.layout_db, selections, retained)?;
+        let (fstree, stats) = self.blit_tree(vfs(layouts)?)?;
+        self.interaction.report(Event::BlitFinished(stats));
 
         let result = match &self.scope {
             Scope::Stateful => {
                 // Add to db
-                let state = self.state_db.add(selections, Some(&summary.to_string()), None)?;
+                let state = self
+                    .state_db
+                    .add(selections, &retained, Some(&summary.to_string()), None)?;
+                self.record_history(&state, old_state)?;
 
                 self.apply_stateful_blit(fstree, &state, old_state, system_model)?;
 
+                self.run_hooks(&hook_payload.at(hooks::Stage::Post, Some(state.id)))?;
+
                 Ok(Some(state))
             }
-            Scope::Ephemeral { blit_root } => {
+            Scope::Ephemeral { blit_root, .. } => {
                 self.apply_ephemeral_blit(fstree, blit_root, system_model)?;
 
                 Ok(None)
@@ -455,14 +1053,53 @@ impl Client {
         result
     }
 
+    /// Record the origin and package delta of `state` relative to the `previous` state
+    fn record_history(&self, state: &State, previous: Option<state::Id>) -> Result<(), Error> {
+        let previous = match previous {
+            Some(id) => self.state_db.get(id)?.selections,
+            None => vec![],
+        };
+
+        let old = previous.iter().map(|s| &s.package).collect::<BTreeSet<_>>();
+        let new = state.selections.iter().map(|s| &s.package).collect::<BTreeSet<_>>();
+
+        let added = new.difference(&old).map(|id| (*id).clone()).collect::<Vec<_>>();
+        let removed = old.difference(&new).map(|id| (*id).clone()).collect::<Vec<_>>();
+
+        self.state_db
+            .add_history(state.id, &state::Origin::current(), &added, &removed)?;
+
+        Ok(())
+    }
+
     /// Apply all triggers with the given scope, wrapping with a progressbar.
-    fn apply_triggers(scope: TriggerScope<'_>, fstree: &vfs::Tree<PendingFile>) -> Result<(), postblit::Error> {
+    fn apply_triggers(&self, scope: TriggerScope<'_>, fstree: &vfs::Tree<PendingFile>) -> Result<(), postblit::Error> {
+        self.run_triggers(scope, fstree, |trigger| trigger.execute())
+    }
+
+    /// Run the triggers of `scope` with `execute`, skipping those whose paths are
+    /// unchanged since they last succeeded, see [`postblit::Cache`]
+    fn run_triggers(
+        &self,
+        scope: TriggerScope<'_>,
+        fstree: &vfs::Tree<PendingFile>,
+        execute: impl Fn(&postblit::TriggerRunner<'_>) -> Result<(), postblit::Error>,
+    ) -> Result<(), postblit::Error> {
+        self.interaction.report(Event::TriggersStage(match &scope {
+            TriggerScope::Transaction(..) => TriggersStage::Transaction,
+            TriggerScope::System(..) => TriggersStage::System,
+        }));
+
         let triggers = postblit::triggers(scope, fstree)?;
+        let mut cache = postblit::Cache::load(scope);
 
-        let progress = ProgressBar::new(triggers.len() as u64).with_style(
-            ProgressStyle::with_template("\n|{bar:20.green/blue}| {pos}/{len} {msg}")
-                .unwrap()
-                .progress_chars("■≡=- "),
+        let progress = reporter(&*self.interaction).counter(
+            "Ran triggers",
+            ProgressBar::new(triggers.len() as u64).with_style(
+                ProgressStyle::with_template("\n|{bar:20.green/blue}| {pos}/{len} {msg}")
+                    .unwrap()
+                    .progress_chars("■≡=- "),
+            ),
         );
 
         let phase_name = match &scope {
@@ -485,8 +1122,17 @@ impl Client {
             event_type = "progress_start",
         );
 
-        for (i, trigger) in progress.wrap_iter(triggers.iter()).enumerate() {
-            trigger.execute()?;
+        for (i, trigger) in triggers.iter().enumerate() {
+            if cache.is_fresh(trigger) {
+                info!("Skipping `{}`, the paths it matches are unchanged", trigger.handler());
+                progress.inc(1);
+                continue;
+            }
+
+            // Only recorded once it succeeded, so a failed trigger runs again next time
+            execute(trigger)?;
+            cache.record(trigger);
+            progress.inc(1);
 
             info!(
                 progress = (i + 1) as f32 / triggers.len() as f32,
@@ -506,6 +1152,8 @@ impl Client {
             event_type = "progress_completed",
         );
 
+        cache.save()?;
+
         progress.finish_and_clear();
 
         Ok(())
@@ -532,7 +1180,7 @@ impl Client {
         fs::create_dir_all(isolation_etc)?;
 
         // Apply transaction triggers
-        Self::apply_triggers(TriggerScope::Transaction(&self.installation, &self.scope), &fstree)?;
+        self.apply_triggers(TriggerScope::Transaction(&self.installation, &self.scope), &fstree)?;
 
         // Staging is only used with [`Scope::Stateful`]
         self.promote_staging()?;
@@ -545,7 +1193,7 @@ impl Client {
         }
 
         // At this point we're allowed to run system triggers
-        Self::apply_triggers(TriggerScope::System(&self.installation, &self.scope), &fstree)?;
+        self.apply_triggers(TriggerScope::System(&self.installation, &self.scope), &fstree)?;
 
         boot::synchronize(self, state)?;
 
@@ -562,20 +1210,38 @@ impl Client {
         record_system_model(blit_root, system_model)?;
 
         create_root_links(blit_root)?;
+
+        // Shared by every blit, relinking only what's missing
         create_root_links(&self.installation.isolation_dir())?;
 
         // The container running triggers expects /etc to exist
         let etc = blit_root.join("etc");
         fs::create_dir_all(etc)?;
 
-        // ephemeral tx triggers
-        Self::apply_triggers(TriggerScope::Transaction(&self.installation, &self.scope), &fstree)?;
-        // ephemeral system triggers
-        Self::apply_triggers(TriggerScope::System(&self.installation, &self.scope), &fstree)?;
+        for scope in self.ephemeral_trigger_scopes() {
+            self.apply_triggers(scope, &fstree)?;
+        }
 
         Ok(())
     }
 
+    /// Trigger scopes to run for an ephemeral blit, as enabled by [`EphemeralOptions`]
+    fn ephemeral_trigger_scopes(&self) -> Vec<TriggerScope<'_>> {
+        let options = match &self.scope {
+            Scope::Stateful => EphemeralOptions::default(),
+            Scope::Ephemeral { options, .. } => *options,
+        };
+
+        let transaction = options
+            .run_transaction_triggers
+            .then_some(TriggerScope::Transaction(&self.installation, &self.scope));
+        let system = options
+            .run_system_triggers
+            .then_some(TriggerScope::System(&self.installation, &self.scope));
+
+        transaction.into_iter().chain(system).collect()
+    }
+
     /// "Activate" the staging tree
     /// In practice, this means we perform an atomic swap of the `/usr` directory on the
     /// host filesystem with the `/usr` tree within the transaction tree.
@@ -583,6 +1249,8 @@ impl Client {
     /// This is performed using `renameat2` and results in instantly available, atomically updated
     /// `/usr`. In combination with the mandated "`/usr`` merge" and statelessness approach of
     /// Serpent OS, provides a unique atomic upgrade strategy.
+    ///
+    /// Filesystems lacking `RENAME_EXCHANGE` fall back to a recoverable, non-atomic swap.
     fn promote_staging(&self) -> Result<(), Error> {
         if self.scope.is_ephemeral() {
             return Err(Error::EphemeralProhibitedOperation);
@@ -597,30 +1265,11 @@ impl Client {
         }
 
         // Now swap staging with live
-        Self::atomic_swap(&usr_source, &usr_target)?;
+        swap::exchange(&usr_source, &usr_target)?;
 
         Ok(())
     }
 
-    /// syscall based wrapper for renameat2 so we can support musl libc which
-    /// unfortunately does not expose the API.
-    /// largely modelled on existing renameat2 API in nix crae
-    fn atomic_swap<A: ?Sized + nix::NixPath, B: ?Sized + nix::NixPath>(old_path: &A, new_path: &B) -> nix::Result<()> {
-        let result = old_path.with_nix_path(|old| {
-            new_path.with_nix_path(|new| unsafe {
-                syscall(
-                    SYS_renameat2,
-                    AT_FDCWD,
-                    old.as_ptr(),
-                    AT_FDCWD,
-                    new.as_ptr(),
-                    RENAME_EXCHANGE,
-                )
-            })
-        })?? as i32;
-        Errno::result(result).map(drop)
-    }
-
     /// Archive old states (currently not "activated") into their respective tree
     fn archive_state(&self, id: state::Id) -> Result<(), Error> {
         if self.scope.is_ephemeral() {
@@ -641,15 +1290,32 @@ impl Client {
     }
 
     /// Download & unpack the provided packages. Packages already cached will be validated & skipped.
-    pub async fn cache_packages<T>(&self, packages: &[T]) -> Result<(), Error>
+    ///
+    /// Transient download failures are retried, and a package failing outright
+    /// doesn't stop the others from being cached. Only the packages cached
+    /// successfully are recorded in the databases, see [`CacheOutcome`].
+    /// Offline clients fail packages which are neither cached nor local.
+    pub async fn cache_packages<T>(&self, packages: &[T]) -> Result<CacheOutcome, Error>
+    where
+        T: Borrow<Package>,
+    {
+        if self.is_offline() {
+            self.cache_packages_with(&cache::Offline, packages).await
+        } else {
+            self.cache_packages_with(&cache::Network, packages).await
+        }
+    }
+
+    async fn cache_packages_with<T>(&self, fetcher: &impl cache::Fetcher, packages: &[T]) -> Result<CacheOutcome, Error>
     where
         T: Borrow<Package>,
     {
         // Setup progress bar
-        let multi_progress = MultiProgress::new();
+        let reporter = reporter(&*self.interaction);
 
         // Add bar to track total package counts
-        let total_progress = multi_progress.add(
+        let total_progress = reporter.counter(
+            "Downloaded",
             ProgressBar::new(packages.len() as u64).with_style(
                 ProgressStyle::with_template("\n|{bar:20.cyan/blue}| {pos}/{len}")
                     .unwrap()
@@ -661,32 +1327,121 @@ impl Client {
         let unpacking_in_progress = cache::UnpackingInProgress::default();
 
         // Download and unpack each package
-        let cached = stream::iter(packages)
+        let results = stream::iter(packages)
             .map(|package| async {
                 let package: &Package = package.borrow();
+                let result = self
+                    .cache_package(fetcher, package, &reporter, &total_progress, &unpacking_in_progress)
+                    .await;
+                (package, result)
+            })
+            // Use max network concurrency since we download files here
+            .buffer_unordered(environment::MAX_NETWORK_CONCURRENCY)
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut failed = vec![];
+        let mut cached = vec![];
+        for (package, result) in results {
+            match result {
+                Ok(unpacked) => cached.push(unpacked),
+                Err(error) => failed.push(CacheFailure {
+                    id: package.id.clone(),
+                    package: package.meta.name.clone(),
+                    error,
+                }),
+            }
+        }
+        let cached_ids = cached.iter().map(|(p, _): &(Package, _)| p.id.clone()).collect();
 
-                // Setup the progress bar and set as downloading
-                let progress_bar = multi_progress.insert_before(
-                    &total_progress,
-                    ProgressBar::new(package.meta.download_size.unwrap_or_default())
-                        .with_message(format!(
-                            "{} {}",
-                            "Downloading".blue(),
-                            package.meta.name.as_str().bold(),
-                        ))
-                        .with_style(
-                            ProgressStyle::with_template(
-                                " {spinner} |{percent:>3}%| {wide_msg} {binary_bytes_per_sec:>.dim} ",
-                            )
-                            .unwrap()
-                            .tick_chars("--=≡■≡=--"),
-                        ),
-                );
-                progress_bar.enable_steady_tick(Duration::from_millis(150));
+        // Add layouts & packages of those successfully unpacked to DBs
+        runtime::unblock({
+            let layout_db = self.layout_db.clone();
+            let install_db = self.install_db.clone();
+            move || {
+                total_progress.restart("Stored", 2);
+                total_progress.set_message("Storing DB layouts");
+                total_progress.tick();
+
+                // Add layouts
+                layout_db.batch_add(cached.iter().flat_map(|(p, u)| {
+                    u.payloads
+                        .iter()
+                        .flat_map(StoneDecodedPayload::layout)
+                        .flat_map(|p| p.body.as_slice())
+                        .map(|layout| (&p.id, layout))
+                }))?;
+
+                total_progress.inc(1);
+                total_progress.set_message("Storing DB packages");
+
+                // Add packages
+                install_db.batch_add(cached.into_iter().map(|(p, _)| (p.id, p.meta)).collect())?;
+
+                total_progress.inc(1);
+
+                Ok::<_, Error>(())
+            }
+        })
+        .await?;
+
+        // Remove progress
+        reporter.clear()?;
 
-                // Download and update progress
-                let download = cache::fetch(&package.meta, &self.installation, |progress| {
+        if let Some(max_size) = quota::max_size(&self.config) {
+            let needed = packages
+                .iter()
+                .filter_map(|package| package.borrow().meta.hash.clone())
+                .collect();
+            if let Err(error) = quota::enforce(self, max_size, needed) {
+                self.interaction.report(Event::Warning(format!(
+                    "Failed to enforce the download cache quota: {error}"
+                )));
+            }
+        }
+
+        Ok(CacheOutcome {
+            cached: cached_ids,
+            failed,
+        })
+    }
+
+    /// Download & unpack a single package, retrying transient download failures
+    async fn cache_package(
+        &self,
+        fetcher: &impl cache::Fetcher,
+        package: &Package,
+        reporter: &progress::Reporter,
+        total_progress: &progress::Counter,
+        unpacking_in_progress: &cache::UnpackingInProgress,
+    ) -> Result<(Package, cache::UnpackedAsset), Error> {
+        // Setup the progress bar and set as downloading
+        let progress_bar = reporter.insert_before(
+            total_progress,
+            ProgressBar::new(package.meta.download_size.unwrap_or_default())
+                .with_message(format!(
+                    "{} {}",
+                    "Downloading".blue(),
+                    package.meta.name.as_str().bold(),
+                ))
+                .with_style(
+                    ProgressStyle::with_template(" {spinner} |{percent:>3}%| {wide_msg} {binary_bytes_per_sec:>.dim} ")
+                        .unwrap()
+                        .tick_chars("--=≡■≡=--"),
+                ),
+        );
+        progress_bar.enable_steady_tick(Duration::from_millis(150));
+
+        let mut attempt = 0;
+        let download = loop {
+            // Download and update progress
+            let result = fetcher
+                .fetch(&package.meta, &self.installation, |progress| {
                     progress_bar.inc(progress.delta);
+                    self.interaction.report(Event::Downloading {
+                        package: package.meta.name.clone(),
+                        pct: progress.pct(),
+                    });
                     info!(
                         progress = progress.completed as f32 / progress.total as f32,
                         current = progress.completed as usize,
@@ -696,122 +1451,118 @@ impl Client {
                         package.meta.name
                     );
                 })
-                .await
-                .map_err(|err| Error::CacheFetch(err, package.meta.name.clone()))?;
-
-                let is_cached = download.was_cached;
-
-                // Move rest of blocking code to threadpool
-
-                let multi_progress = multi_progress.clone();
-                let total_progress = total_progress.clone();
-                let unpacking_in_progress = unpacking_in_progress.clone();
-                let package = (*package).clone();
-                let current_span = tracing::Span::current();
-
-                runtime::unblock(move || {
-                    let _guard = current_span.enter();
-                    let package_name = &package.meta.name;
-                    let download_path = download.path().to_owned();
-
-                    // Set progress to unpacking
-                    progress_bar.set_message(format!("{} {}", "Unpacking".yellow(), package_name.to_string().bold()));
-                    progress_bar.set_length(1000);
+                .await;
+
+            match result {
+                Ok(download) => break download,
+                Err(err) if err.is_retryable() && attempt + 1 < environment::MAX_FETCH_ATTEMPTS => {
+                    let delay = fetcher.backoff(attempt);
+                    attempt += 1;
+                    warn!(
+                        error = format!("{err:#}"),
+                        attempt,
+                        delay_ms = delay.as_millis(),
+                        "Retrying download of {}",
+                        package.meta.name
+                    );
                     progress_bar.set_position(0);
-
-                    // Unpack and update progress
-                    let unpacked = download
-                        .unpack(unpacking_in_progress.clone(), {
-                            let progress_bar = progress_bar.clone();
-                            let package_name = package_name.clone();
-
-                            move |progress| {
-                                progress_bar.set_position((progress.pct() * 1000.0) as u64);
-                                info!(
-                                    progress = progress.completed as f32 / progress.total as f32,
-                                    current = progress.completed as usize,
-                                    total = progress.total as usize,
-                                    event_type = "progress_update",
-                                    "Unpacking {package_name}",
-                                );
-                            }
-                        })
-                        .map_err(|err| Error::CacheUnpack(err, package_name.clone(), download_path))?;
-
-                    // Remove this progress bar
-                    progress_bar.finish();
-                    multi_progress.remove(&progress_bar);
-
-                    let cached_tag = is_cached
-                        .then_some(format!("{}", " (cached)".dim()))
-                        .unwrap_or_default();
-
-                    // Write installed line
-                    multi_progress.suspend(|| {
-                        println!(
-                            "{} {}{cached_tag}",
-                            "Installed".green(),
-                            package_name.to_string().bold()
-                        );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(err) => {
+                    report_cache_failure(package, reporter, total_progress, &progress_bar);
+                    self.interaction.report(Event::CacheFailed {
+                        package: package.meta.name.clone(),
                     });
+                    return Err(Error::CacheFetch(err, package.meta.name.clone()));
+                }
+            }
+        };
 
-                    // Inc total progress by 1
-                    total_progress.inc(1);
-
+        let is_cached = download.was_cached;
+
+        // Move rest of blocking code to threadpool
+
+        let reporter = reporter.clone();
+        let total_progress = total_progress.clone();
+        let unpacking_in_progress = unpacking_in_progress.clone();
+        let package = package.clone();
+        let interaction = self.interaction.clone();
+        let current_span = tracing::Span::current();
+
+        runtime::unblock(move || {
+            let _guard = current_span.enter();
+            let package_name = &package.meta.name;
+            let download_path = download.path().to_owned();
+
+            // Set progress to unpacking
+            interaction.report(Event::Unpacking {
+                package: package_name.clone(),
+            });
+            progress_bar.set_message(format!("{} {}", "Unpacking".yellow(), package_name.to_string().bold()));
+            progress_bar.set_length(1000);
+            progress_bar.set_position(0);
+
+            // Unpack and update progress
+            let unpacked = download.unpack(unpacking_in_progress.clone(), {
+                let progress_bar = progress_bar.clone();
+                let package_name = package_name.clone();
+
+                move |progress| {
+                    progress_bar.set_position((progress.pct() * 1000.0) as u64);
                     info!(
-                        progress = total_progress.position() as f32 / total_progress.length().unwrap_or(1) as f32,
-                        current = total_progress.position() as usize,
-                        total = total_progress.length().unwrap_or(0) as usize,
+                        progress = progress.completed as f32 / progress.total as f32,
+                        current = progress.completed as usize,
+                        total = progress.total as usize,
                         event_type = "progress_update",
-                        "Cached {}",
-                        package_name
+                        "Unpacking {package_name}",
                     );
+                }
+            });
+            let unpacked = match unpacked {
+                Ok(unpacked) => unpacked,
+                Err(err) => {
+                    report_cache_failure(&package, &reporter, &total_progress, &progress_bar);
+                    interaction.report(Event::CacheFailed {
+                        package: package_name.clone(),
+                    });
+                    return Err(Error::CacheUnpack(Box::new(err), package_name.clone(), download_path));
+                }
+            };
 
-                    Ok((package, unpacked)) as Result<(Package, cache::UnpackedAsset), Error>
-                })
-                .await
-            })
-            // Use max network concurrency since we download files here
-            .buffer_unordered(environment::MAX_NETWORK_CONCURRENCY)
-            .try_collect::<Vec<_>>()
-            .await?;
-
-        // Add layouts & packages to DBs
-        runtime::unblock({
-            let layout_db = self.layout_db.clone();
-            let install_db = self.install_db.clone();
-            move || {
-                total_progress.set_position(0);
-                total_progress.set_length(2);
-                total_progress.set_message("Storing DB layouts");
-                total_progress.tick();
+            // Remove this progress bar
+            progress_bar.finish();
+            reporter.remove(&progress_bar);
 
-                // Add layouts
-                layout_db.batch_add(cached.iter().flat_map(|(p, u)| {
-                    u.payloads
-                        .iter()
-                        .flat_map(StoneDecodedPayload::layout)
-                        .flat_map(|p| p.body.as_slice())
-                        .map(|layout| (&p.id, layout))
-                }))?;
+            let cached_tag = is_cached
+                .then_some(format!("{}", " (cached)".dim()))
+                .unwrap_or_default();
 
-                total_progress.inc(1);
-                total_progress.set_message("Storing DB packages");
+            // Write installed line
+            reporter.println(format_args!(
+                "{} {}{cached_tag}",
+                "Installed".green(),
+                package_name.to_string().bold()
+            ));
+            interaction.report(Event::Cached {
+                package: package_name.clone(),
+                was_cached: is_cached,
+            });
 
-                // Add packages
-                install_db.batch_add(cached.into_iter().map(|(p, _)| (p.id, p.meta)).collect())?;
+            // Inc total progress by 1
+            total_progress.inc(1);
 
-                total_progress.inc(1);
+            info!(
+                progress = total_progress.position() as f32 / total_progress.length().unwrap_or(1) as f32,
+                current = total_progress.position() as usize,
+                total = total_progress.length().unwrap_or(0) as usize,
+                event_type = "progress_update",
+                "Cached {}",
+                package_name
+            );
 
-                Ok::<_, Error>(())
-            }
+            Ok((package, unpacked))
         })
-        .await?;
-
-        // Remove progress
-        multi_progress.clear()?;
-
-        Ok(())
+        .await
     }
 
     /// Build a [`vfs::Tree`] for the specified package IDs
@@ -825,6 +1576,11 @@ impl Client {
         vfs(self.layout_db.query(packages)?)
     }
 
+    /// Build a [`vfs::Tree`] for the selected & retained packages of `state`
+    pub fn state_vfs(&self, state: &State) -> Result<vfs::Tree<PendingFile>, Error> {
+        vfs(retain::state_layouts(&self.layout_db, state)?)
+    }
+
     /// Blit the packages to a filesystem root
     ///
     /// This functionality is core to all moss filesystem transactions, forming the entire
@@ -837,20 +1593,28 @@ impl Client {
     ///
     /// This provides a very quick means to generate a hardlinked "snapshot" on-demand,
     /// which can then be activated via [`Self::promote_staging`]
-    pub fn blit_root<'a>(
-        &self,
-        packages: impl IntoIterator<Item = &'a package::Id>,
-    ) -> Result<vfs::Tree<PendingFile>, Error> {
+    pub fn blit_root<'a>(&self, packages: impl IntoIterator<Item = &'a package::Id>) -> Result<BlitStats, Error> {
+        let (_, stats) = self.blit_tree(self.vfs(packages)?)?;
+
+        Ok(stats)
+    }
+
+    /// Blit the selected & retained packages of `state` to a filesystem root,
+    /// returning the blitted tree, see [`Self::blit_root`]
+    pub fn blit_state(&self, state: &State) -> Result<(vfs::Tree<PendingFile>, BlitStats), Error> {
+        self.blit_tree(self.state_vfs(state)?)
+    }
+
+    fn blit_tree(&self, fstree: vfs::Tree<PendingFile>) -> Result<(vfs::Tree<PendingFile>, BlitStats), Error> {
         let blit_target = match &self.scope {
             Scope::Stateful => self.installation.staging_dir(),
-            Scope::Ephemeral { blit_root } => blit_root.to_owned(),
+            Scope::Ephemeral { blit_root, .. } => blit_root.to_owned(),
         };
 
-        let fstree = self.vfs(packages)?;
-
-        blit_root(&self.installation, &fstree, &blit_target)?;
+        self.interaction.report(Event::Blitting);
+        let stats = blit_root(&self.installation, &fstree, &blit_target, &*self.interaction)?;
 
-        Ok(fstree)
+        Ok((fstree, stats))
     }
 
     fn load_or_create_system_model(&self, path: PathBuf, state: &State) -> Result<SystemModel, Error> {
@@ -890,6 +1654,45 @@ impl Client {
         self.load_or_create_system_model(path, &state)
     }
 
+    /// Export the explicit packages of the provided state as a [`Manifest`],
+    /// pinned to their installed versions
+    pub fn export_manifest(&self, state: state::Id) -> Result<Manifest, Error> {
+        let state = self.state_db.get(state)?;
+
+        let packages = state
+            .selections
+            .iter()
+            .filter(|selection| selection.explicit)
+            .map(|selection| {
+                let meta = self.install_db.get(&selection.package)?;
+                let index = self.package_repository(&selection.package).and_then(|id| {
+                    self.repositories
+                        .active()
+                        .find(|repo| repo.id == id)
+                        .and_then(|repo| repo.index_uri())
+                });
+
+                Ok(manifest::Entry {
+                    name: meta.name.to_string(),
+                    version: meta.version_identifier,
+                    release: meta.source_release,
+                    index,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?
+            .into_iter()
+            .sorted_by(|a, b| a.name.cmp(&b.name))
+            .collect();
+
+        let repositories = self
+            .repositories
+            .active()
+            .map(|repo| (repo.id, repo.repository))
+            .collect();
+
+        Ok(Manifest::new(repositories, packages))
+    }
+
     /// Print boot status to stdout
     pub fn print_boot_status(&self) -> Result<(), Error> {
         boot::print_status(&self.installation).map_err(Error::Boot)
@@ -901,11 +1704,27 @@ impl Client {
             return Err(Error::NoActiveState);
         };
 
+        let _lock = self.lock()?;
         let state = self.state_db.get(state_id)?;
 
         boot::synchronize(self, &state).map_err(Error::Boot)
     }
 
+    /// Remove boot entries & kernels of states which no longer exist
+    ///
+    /// Returns the removed paths, or those which would be removed if `dry_run`
+    pub fn cleanup_boot(&self, dry_run: bool) -> Result<Vec<PathBuf>, Error> {
+        if self.scope.is_ephemeral() {
+            return Err(Error::EphemeralProhibitedOperation);
+        }
+
+        let _lock = self.lock_unless(dry_run)?;
+        let live = self.state_db.list_ids()?.into_iter().map(|(id, _)| id).collect();
+        let cleanup = boot::cleanup(self, &live, dry_run).map_err(Error::Boot)?;
+
+        Ok(cleanup.paths().map(Path::to_path_buf).collect())
+    }
+
     /// List all states for this moss [`Installation`]
     pub fn list_states(&self) -> Result<Vec<State>, Error> {
         self.state_db
@@ -915,6 +1734,11 @@ impl Client {
             .collect()
     }
 
+    /// Returns the history of all states, or only the provided state, oldest first
+    pub fn state_history(&self, state: Option<state::Id>) -> Result<Vec<state::History>, Error> {
+        Ok(self.state_db.history(state)?)
+    }
+
     /// Return a [`State`] for the provided state id
     pub fn get_state(&self, id: state::Id) -> Result<State, Error> {
         self.state_db.get(id).map_err(Error::Db)
@@ -947,16 +1771,45 @@ impl Client {
             config,
             installation,
             repositories,
+            overlay: plugin::Cobble::default(),
+            local: plugin::Cobble::default(),
             registry,
             install_db,
             state_db,
             layout_db,
             scope: Scope::Stateful,
+            interaction: Arc::new(Terminal),
         })
     }
 }
 
-/// Add root symlinks & os-release file
+/// Replace the progress bar of a package which failed to cache with a failure line
+fn report_cache_failure(
+    package: &Package,
+    reporter: &progress::Reporter,
+    total_progress: &progress::Counter,
+    progress_bar: &ProgressBar,
+) {
+    progress_bar.finish_and_clear();
+    reporter.remove(progress_bar);
+    reporter.println(format_args!(
+        "{} {}",
+        "Failed".red(),
+        package.meta.name.to_string().bold()
+    ));
+    total_progress.inc(1);
+}
+
+/// Reporter of progress bars, hidden when the `interaction` renders progress itself
+fn reporter(interaction: &dyn Interaction) -> progress::Reporter {
+    if interaction.renders_progress() {
+        progress::Reporter::plain(io::sink())
+    } else {
+        progress::Reporter::new()
+    }
+}
+
+/// Add root symlinks & os-release file, keeping those already in place
 fn create_root_links(root: &Path) -> io::Result<()> {
     let links = vec![
         ("usr/sbin", "sbin"),
@@ -970,11 +1823,13 @@ fn create_root_links(root: &Path) -> io::Result<()> {
         let final_target = root.join(target);
         let staging_target = root.join(format!("{target}.next"));
 
-        if staging_target.exists() {
+        // Left by an interrupted link, which may dangle
+        if staging_target.symlink_metadata().is_ok() {
             fs::remove_file(&staging_target)?;
         }
 
-        if final_target.exists() && final_target.is_symlink() && final_target.read_link()?.to_string_lossy() == source {
+        // Never follow an existing link, it may point outside of `root`
+        if final_target.is_symlink() && final_target.read_link()?.to_string_lossy() == source {
             continue 'linker;
         }
         symlink(source, &staging_target)?;
@@ -1011,15 +1866,26 @@ pub fn vfs(layouts: Vec<(package::Id, StonePayloadLayoutRecord)>) -> Result<vfs:
 /// file descriptors, linking files from the assets store to provide deduplication.
 ///
 /// This provides a very quick means to generate a hardlinked "snapshot" on-demand,
-/// which can then be activated via [`Self::promote_staging`]
-pub fn blit_root(installation: &Installation, tree: &vfs::Tree<PendingFile>, blit_target: &Path) -> Result<(), Error> {
+/// which can then be activated via [`Client::promote_staging`]
+///
+/// Returns what was written, rendering it is left to the [`Interaction`]
+pub fn blit_root(
+    installation: &Installation,
+    tree: &vfs::Tree<PendingFile>,
+    blit_target: &Path,
+    interaction: &dyn Interaction,
+) -> Result<BlitStats, Error> {
     // undirt.
     fs::remove_dir_all(blit_target)?;
 
-    let progress = ProgressBar::new(1).with_style(
-        ProgressStyle::with_template("\n|{bar:20.red/blue}| {pos}/{len} {msg}")
-            .unwrap()
-            .progress_chars("■≡=- "),
+    let reporter = reporter(interaction);
+    let progress = reporter.counter(
+        "Blitted",
+        ProgressBar::new(1).with_style(
+            ProgressStyle::with_template("\n|{bar:20.red/blue}| {pos}/{len} {msg}")
+                .unwrap()
+                .progress_chars("■≡=- "),
+        ),
     );
     progress.set_message("Blitting filesystem");
     progress.enable_steady_tick(Duration::from_millis(150));
@@ -1028,8 +1894,7 @@ pub fn blit_root(installation: &Installation, tree: &vfs::Tree<PendingFile>, bli
     let now = Instant::now();
     let mut stats = BlitStats::default();
 
-    progress.set_length(tree.len());
-    progress.set_position(0_u64);
+    progress.restart("Blitted", tree.len());
 
     let cache_dir = installation.assets_path("v2");
     let cache_fd = fcntl::open(&cache_dir, OFlag::O_DIRECTORY | OFlag::O_RDONLY, Mode::empty())?;
@@ -1051,7 +1916,7 @@ pub fn blit_root(installation: &Installation, tree: &vfs::Tree<PendingFile>, bli
                         .into_par_iter()
                         .map(|child| {
                             let _guard = current_span.enter();
-                            blit_element(root_dir, cache_fd, child, &progress)
+                            blit_element(root_dir, cache_fd, child, &progress, interaction)
                         })
                         .try_reduce(BlitStats::default, |a, b| Ok(a.merge(b)))?,
                 );
@@ -1065,17 +1930,9 @@ pub fn blit_root(installation: &Installation, tree: &vfs::Tree<PendingFile>, bli
 
     progress.finish_and_clear();
 
-    let elapsed = now.elapsed();
-    let num_entries = stats.num_entries();
+    stats.elapsed = now.elapsed();
 
-    println!(
-        "\n{} entries blitted in {} {}",
-        num_entries.to_string().bold(),
-        format!("{:.2}s", elapsed.as_secs_f32()).bold(),
-        format!("({:.1}k / s)", num_entries as f32 / elapsed.as_secs_f32() / 1_000.0).dim()
-    );
-
-    Ok(())
+    Ok(stats)
 }
 
 /// Recursively write a directory, or a single flat inode, to the staging tree.
@@ -1085,12 +1942,19 @@ fn blit_element(
     parent: RawFd,
     cache: RawFd,
     element: Element<'_, PendingFile>,
-    progress: &ProgressBar,
+    progress: &progress::Counter,
+    interaction: &dyn Interaction,
 ) -> Result<BlitStats, Error> {
     let mut stats = BlitStats::default();
 
     progress.inc(1);
 
+    // Report each hundredth, rather than every entry
+    let (entries, total) = (progress.position(), progress.length().unwrap_or_default());
+    if total > 0 && entries * 100 / total != (entries - 1) * 100 / total {
+        interaction.report(Event::Blitted { entries, total });
+    }
+
     let (_, item) = match &element {
         Element::Directory(_, item, _) => ("directory", item),
         Element::Child(_, item) => ("file", item),
@@ -1119,7 +1983,7 @@ fn blit_element(
                     .into_par_iter()
                     .map(|child| {
                         let _guard = current_span.enter();
-                        blit_element(newdir, cache, child, progress)
+                        blit_element(newdir, cache, child, progress, interaction)
                     })
                     .try_reduce(BlitStats::default, |a, b| Ok(a.merge(b)))?,
             );
@@ -1192,6 +2056,9 @@ fn blit_element_item(
                         Mode::from_bits_truncate(item.layout.mode),
                         nix::sys::stat::FchmodatFlags::NoFollowSymlink,
                     )?;
+
+                    let linked = fstatat(parent, subpath, AtFlags::AT_SYMLINK_NOFOLLOW)?;
+                    stats.bytes_linked += linked.st_size as u64;
                 }
             }
 
@@ -1307,7 +2174,32 @@ fn record_system_model(root: &Path, system_model: SystemModel) -> Result<(), Err
 #[derive(Clone, Debug)]
 enum Scope {
     Stateful,
-    Ephemeral { blit_root: PathBuf },
+    Ephemeral {
+        blit_root: PathBuf,
+        options: EphemeralOptions,
+    },
+}
+
+/// Controls which triggers an ephemeral [`Client`] runs against its blit root
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EphemeralOptions {
+    /// Run system-scope triggers, which may write outside of `/usr`
+    pub run_system_triggers: bool,
+    /// Run transaction-scope triggers, isolated to `/usr`
+    pub run_transaction_triggers: bool,
+    /// Never use the network: repository refreshes are skipped and packages
+    /// must be in the download cache or from local repositories
+    pub offline: bool,
+}
+
+impl Default for EphemeralOptions {
+    fn default() -> Self {
+        Self {
+            run_system_triggers: true,
+            run_transaction_triggers: true,
+            offline: false,
+        }
+    }
 }
 
 impl Scope {
@@ -1401,11 +2293,15 @@ impl fmt::Display for PendingFile {
 ///
 /// * `installation` - Describe our installation target tree
 /// * `repositories` - Configured repositories to laoad [`crate::registry::Plugin::Repository`]
+/// * `overlay`      - Stones of the local overlay repository, loaded as [`crate::registry::Plugin::Cobble`]
+/// * `local`        - Stones of the local directories, loaded as [`crate::registry::Plugin::Cobble`]
 /// * `installdb`    - Installation database opened in the installation tree
 /// * `statedb`      - State database opened in the installation tree
 fn build_registry(
     installation: &Installation,
     repositories: &repository::Manager,
+    overlay: &plugin::Cobble,
+    local: &plugin::Cobble,
     installdb: &db::meta::Database,
     statedb: &db::state::Database,
 ) -> Result<Registry, Error> {
@@ -1416,7 +2312,9 @@ fn build_registry(
 
     let mut registry = Registry::default();
 
-    registry.add_plugin(Plugin::Cobble(plugin::Cobble::default()));
+    // Ahead of every other plugin, so its stones win ties of priority
+    registry.add_plugin(Plugin::Cobble(overlay.clone()));
+    registry.add_plugin(Plugin::Cobble(local.clone()));
     registry.add_plugin(Plugin::Active(plugin::Active::new(state, installdb.clone())));
 
     for repo in repositories.active() {
@@ -1426,27 +2324,78 @@ fn build_registry(
     Ok(registry)
 }
 
-#[derive(Debug, Clone, Copy, Default)]
-struct BlitStats {
-    num_files: u64,
-    num_symlinks: u64,
-    num_dirs: u64,
+/// What [`blit_root`] wrote to a filesystem root
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BlitStats {
+    /// Regular files, linked from the assets or created empty
+    pub num_files: u64,
+    pub num_symlinks: u64,
+    pub num_dirs: u64,
+    /// Combined size of the files linked from the assets
+    pub bytes_linked: u64,
+    /// Time taken to blit
+    pub elapsed: Duration,
 }
 
 impl BlitStats {
-    fn merge(self, other: Self) -> Self {
+    /// Combine the stats of two blits, or of two subtrees of one
+    pub fn merge(self, other: Self) -> Self {
         Self {
             num_files: self.num_files + other.num_files,
             num_symlinks: self.num_symlinks + other.num_symlinks,
             num_dirs: self.num_dirs + other.num_dirs,
+            bytes_linked: self.bytes_linked + other.bytes_linked,
+            elapsed: self.elapsed + other.elapsed,
         }
     }
 
-    fn num_entries(&self) -> u64 {
+    /// Every inode written
+    pub fn num_entries(&self) -> u64 {
         self.num_files + self.num_symlinks + self.num_dirs
     }
 }
 
+/// Outcome of [`Client::cache_packages`]
+#[derive(Debug, Default)]
+pub struct CacheOutcome {
+    /// Packages unpacked & recorded in the databases
+    pub cached: Vec<package::Id>,
+    /// Packages which couldn't be fetched or unpacked, after retrying
+    pub failed: Vec<CacheFailure>,
+}
+
+impl CacheOutcome {
+    /// Fails with a consolidated error naming every failed package, if any
+    pub fn into_result(self) -> Result<Vec<package::Id>, Error> {
+        if self.failed.is_empty() {
+            Ok(self.cached)
+        } else {
+            Err(Error::CachePackages(self.failed))
+        }
+    }
+}
+
+/// A package which failed to cache
+#[derive(Debug)]
+pub struct CacheFailure {
+    pub id: package::Id,
+    pub package: package::Name,
+    pub error: Error,
+}
+
+impl fmt::Display for CacheFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The outer error already names the package
+        let mut source = std::error::Error::source(&self.error);
+        write!(f, "{}", self.package)?;
+        while let Some(error) = source {
+            write!(f, ": {error}")?;
+            source = error.source();
+        }
+        Ok(())
+    }
+}
+
 /// Client-relevant error mapping type
 #[derive(Debug, Error)]
 pub enum Error {
@@ -1456,8 +2405,22 @@ pub enum Error {
     StateAlreadyActive(state::Id),
     #[error("state {0} doesn't exist")]
     StateDoesntExist(state::Id),
+    #[error("no state {0} before the active state")]
+    NoPreviousState(usize),
+    #[error("archive of state {0} is missing, reblit it with --hard")]
+    StateArchiveMissing(state::Id),
     #[error("No metadata found for package {0:?}")]
     MissingMetadata(package::Id),
+    #[error("active state was changed by another process, retry")]
+    StateChanged,
+    #[error("installation is locked by PID {pid}, started {}", since.with_timezone(&Local).format("%Y-%m-%d %H:%M"))]
+    Locked { pid: u32, since: DateTime<Utc> },
+    #[error("pre-transaction hook")]
+    PreTransactionHook(#[source] hooks::Error),
+    #[error("package {0} is not selected in the verified states")]
+    PackageNotSelected(package::Name),
+    #[error("package {0} is not selected in state {1}")]
+    NotInState(package::Name, state::Id),
     #[error("Ephemeral client not allowed on installation root")]
     EphemeralInstallationRoot,
     #[error("Operation not allowed with ephemeral client")]
@@ -1467,7 +2430,9 @@ pub enum Error {
     #[error("fetch package {1}")]
     CacheFetch(#[source] cache::FetchError, package::Name),
     #[error("unpack package {1}, file {2}")]
-    CacheUnpack(#[source] cache::UnpackError, package::Name, PathBuf),
+    CacheUnpack(#[source] Box<cache::UnpackError>, package::Name, PathBuf),
+    #[error("failed to cache {} package(s): {}", .0.len(), .0.iter().join("; "))]
+    CachePackages(Vec<CacheFailure>),
     #[error("repository manager")]
     Repository(#[from] repository::manager::Error),
     #[error("db")]
@@ -1476,6 +2441,8 @@ pub enum Error {
     Prune(#[from] prune::Error),
     #[error("io")]
     Io(#[from] io::Error),
+    #[error("swap")]
+    Swap(#[from] swap::Error),
     #[error("filesystem")]
     Filesystem(#[from] vfs::tree::Error),
     #[error("blit")]
@@ -1496,6 +2463,8 @@ pub enum Error {
     LoadSystemModel(#[from] system_model::LoadError),
     #[error("update system model")]
     UpdateSystemModel(#[from] system_model::UpdateError),
+    #[error("manifest")]
+    Manifest(#[from] manifest::Error),
     #[error("install")]
     Install(#[source] Box<install::Error>),
     #[error("remove")]
@@ -1504,6 +2473,768 @@ pub enum Error {
     Fetch(#[source] Box<fetch::Error>),
     #[error("sync")]
     Sync(#[source] Box<sync::Error>),
+    #[error("apply system model")]
+    Model(#[source] Box<model::Error>),
     #[error("system model doesn't exist at {0:?}")]
     ImportSystemModelDoesntExist(PathBuf),
+    #[error("repository {0} isn't configured or is disabled")]
+    UnknownRepository(repository::Id),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Matches any file under `/usr/lib/stub`
+    const STUB_TRIGGER: &str = r#"
+name: stub
+description: Stub trigger
+handlers:
+    stub:
+        run: /usr/bin/true
+        args: ["$(name)"]
+paths:
+    "/usr/lib/stub/(name:*)":
+        handlers:
+            - stub
+"#;
+
+    /// Scope of each trigger loaded for an ephemeral blit of `fstree`
+    fn loaded_triggers(client: &Client, fstree: &vfs::Tree<PendingFile>) -> Vec<&'static str> {
+        client
+            .ephemeral_trigger_scopes()
+            .into_iter()
+            .flat_map(|scope| {
+                let name = match scope {
+                    TriggerScope::Transaction(..) => "transaction",
+                    TriggerScope::System(..) => "system",
+                };
+                postblit::triggers(scope, fstree)
+                    .unwrap()
+                    .into_iter()
+                    .map(move |_| name)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ephemeral_trigger_options() {
+        let root = tempfile::tempdir().unwrap();
+        let blit_root = tempfile::tempdir().unwrap();
+
+        for domain in ["tx", "sys"] {
+            let dir = blit_root
+                .path()
+                .join("usr/share/moss/triggers")
+                .join(format!("{domain}.d"));
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("stub.yaml"), STUB_TRIGGER).unwrap();
+        }
+
+        let fstree = vfs(vec![(
+            package::Id::from("stub"),
+            StonePayloadLayoutRecord {
+                uid: 0,
+                gid: 0,
+                mode: 0o644,
+                tag: 0,
+                file: StonePayloadLayoutFile::Regular(0, "lib/stub/file".into()),
+            },
+        )])
+        .unwrap();
+
+        let client = || {
+            let installation = Installation::open(root.path(), None).unwrap();
+            Client::mocked(installation, Registry::default()).unwrap()
+        };
+        let ephemeral = |run_transaction_triggers, run_system_triggers| {
+            client()
+                .ephemeral_with_options(
+                    blit_root.path(),
+                    EphemeralOptions {
+                        run_system_triggers,
+                        run_transaction_triggers,
+                        offline: false,
+                    },
+                )
+                .unwrap()
+        };
+
+        assert_eq!(
+            loaded_triggers(&ephemeral(true, true), &fstree),
+            ["transaction", "system"]
+        );
+        assert_eq!(loaded_triggers(&ephemeral(true, false), &fstree), ["transaction"]);
+        assert_eq!(loaded_triggers(&ephemeral(false, true), &fstree), ["system"]);
+        assert!(loaded_triggers(&ephemeral(false, false), &fstree).is_empty());
+
+        // Defaults keep running everything
+        let default = client().ephemeral(blit_root.path()).unwrap();
+        assert_eq!(loaded_triggers(&default, &fstree), ["transaction", "system"]);
+    }
+
+    #[test]
+    fn cached_system_triggers() {
+        let root = tempfile::tempdir().unwrap();
+
+        let dir = root.path().join("usr/share/moss/triggers/sys.d");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("stub.yaml"), STUB_TRIGGER).unwrap();
+
+        let file = root.path().join("usr/lib/stub/file");
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, "").unwrap();
+
+        let fstree = vfs(vec![(
+            package::Id::from("stub"),
+            StonePayloadLayoutRecord {
+                uid: 0,
+                gid: 0,
+                mode: 0o644,
+                tag: 0,
+                file: StonePayloadLayoutFile::Regular(0, "lib/stub/file".into()),
+            },
+        )])
+        .unwrap();
+
+        // Runs the system triggers of a new client, returning how many executed
+        let try_run = |force, fail| {
+            let installation = Installation::open(root.path(), None).unwrap().force_triggers(force);
+            let client = Client::mocked(installation, Registry::default()).unwrap();
+
+            let executed = std::cell::Cell::new(0);
+            let result = client.run_triggers(
+                TriggerScope::System(&client.installation, &client.scope),
+                &fstree,
+                |_| {
+                    executed.set(executed.get() + 1);
+                    if fail {
+                        Err(io::Error::other("handler failed").into())
+                    } else {
+                        Ok(())
+                    }
+                },
+            );
+            (executed.get(), result)
+        };
+        let run = |force| {
+            let (executed, result) = try_run(force, false);
+            result.unwrap();
+            executed
+        };
+
+        // Failures aren't recorded, so run again
+        let (executed, result) = try_run(false, true);
+        assert_eq!(executed, 1);
+        assert!(result.is_err());
+
+        assert_eq!(run(false), 1);
+        // Identical inputs, skipped
+        assert_eq!(run(false), 0);
+        assert_eq!(run(true), 1);
+        // Cached within the root, as cache dirs can be shared
+        assert!(root.path().join(".moss/cache/triggers.json").exists());
+
+        // Touching a matched path runs it again
+        fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(std::time::SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(run(false), 1);
+        assert_eq!(run(false), 0);
+
+        // Ephemeral roots are never cached
+        let installation = Installation::open(root.path(), None).unwrap();
+        let ephemeral = Scope::Ephemeral {
+            blit_root: root.path().to_owned(),
+            options: EphemeralOptions::default(),
+        };
+        let scope = TriggerScope::System(&installation, &ephemeral);
+        let cache = postblit::Cache::load(scope);
+        assert!(
+            postblit::triggers(scope, &fstree)
+                .unwrap()
+                .iter()
+                .all(|trigger| !cache.is_fresh(trigger))
+        );
+    }
+
+    /// Fails each package's first attempts with a transient error, as many
+    /// times as configured, before fetching it for real
+    struct FlakyFetcher {
+        failures: BTreeMap<&'static str, u32>,
+        attempts: std::sync::Mutex<BTreeMap<String, u32>>,
+    }
+
+    impl cache::Fetcher for FlakyFetcher {
+        async fn fetch(
+            &self,
+            meta: &package::Meta,
+            installation: &Installation,
+            on_progress: impl Fn(cache::Progress),
+        ) -> Result<cache::Download, cache::FetchError> {
+            let attempt = {
+                let mut attempts = self.attempts.lock().unwrap();
+                let attempt = attempts.entry(meta.name.to_string()).or_default();
+                *attempt += 1;
+                *attempt
+            };
+
+            if attempt <= self.failures.get(meta.name.as_str()).copied().unwrap_or_default() {
+                return Err(cache::FetchError::Request {
+                    source: crate::request::Error::Read(io::Error::other("connection reset")),
+                });
+            }
+
+            cache::fetch(meta, installation, on_progress).await
+        }
+
+        fn backoff(&self, _attempt: u32) -> Duration {
+            Duration::ZERO
+        }
+    }
+
+    /// Metadata of the test `bash-completion` stone, fetched from its local path
+    fn stone_meta() -> package::Meta {
+        let stone_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../test/bash-completion-2.11-1-1-x86_64.stone");
+        let bytes = fs::read(&stone_path).unwrap();
+
+        let mut stone = stone::read_bytes(&bytes).unwrap();
+        let payloads = stone.payloads().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        let meta = payloads.iter().find_map(StoneDecodedPayload::meta).unwrap();
+        let mut meta = package::Meta::from_stone_payload(&meta.body).unwrap();
+        meta.uri = Some(
+            url::Url::from_file_path(stone_path.canonicalize().unwrap())
+                .unwrap()
+                .to_string(),
+        );
+        meta.hash = Some(crate::util::sha256_hash(&mut bytes.as_slice()).unwrap());
+        meta
+    }
+
+    #[test]
+    fn cache_partial_failure() {
+        let meta = stone_meta();
+
+        let package = |name: &str, meta: &package::Meta| Package {
+            id: package::Id::from(name.to_owned()),
+            meta: package::Meta {
+                name: package::Name::from(name.to_owned()),
+                ..meta.clone()
+            },
+            flags: package::Flags::default(),
+        };
+        // Recovers on the last attempt
+        let flaky = package("bash-completion", &meta);
+        // Never recovers
+        let down = package("down", &meta);
+        // Hard failure, never retried
+        let broken = package(
+            "broken",
+            &package::Meta {
+                uri: None,
+                ..meta.clone()
+            },
+        );
+
+        let root = tempfile::tempdir().unwrap();
+        let installation = Installation::open(root.path(), None).unwrap();
+        let client = Client::mocked(installation, Registry::default()).unwrap();
+
+        let fetcher = FlakyFetcher {
+            failures: BTreeMap::from([
+                ("bash-completion", environment::MAX_FETCH_ATTEMPTS - 1),
+                ("down", u32::MAX),
+            ]),
+            attempts: Default::default(),
+        };
+
+        let outcome = runtime::block_on(client.cache_packages_with(&fetcher, &[&flaky, &down, &broken])).unwrap();
+
+        assert_eq!(outcome.cached, std::slice::from_ref(&flaky.id));
+        assert_eq!(
+            outcome
+                .failed
+                .iter()
+                .map(|failure| failure.package.to_string())
+                .sorted()
+                .collect::<Vec<_>>(),
+            ["broken", "down"]
+        );
+        assert_eq!(
+            *fetcher.attempts.lock().unwrap(),
+            BTreeMap::from([
+                ("bash-completion".to_owned(), environment::MAX_FETCH_ATTEMPTS),
+                ("broken".to_owned(), 1),
+                ("down".to_owned(), environment::MAX_FETCH_ATTEMPTS),
+            ])
+        );
+
+        // Only the cached package is recorded
+        assert!(client.install_db.get(&flaky.id).is_ok());
+        assert!(client.install_db.get(&down.id).is_err());
+        assert!(!client.layout_db.query([&flaky.id]).unwrap().is_empty());
+        assert!(client.layout_db.query([&down.id]).unwrap().is_empty());
+
+        let error = outcome.into_result().unwrap_err().to_string();
+        assert!(error.starts_with("failed to cache 2 package(s): "), "{error}");
+        assert!(error.contains("broken: missing URL"), "{error}");
+        assert!(error.contains("down: io: connection reset"), "{error}");
+    }
+
+    #[test]
+    fn offline_cache() {
+        let meta = stone_meta();
+        let local = Package {
+            id: package::Id::from("local".to_owned()),
+            meta: meta.clone(),
+            flags: package::Flags::default(),
+        };
+        let remote = Package {
+            id: package::Id::from("remote".to_owned()),
+            meta: package::Meta {
+                name: package::Name::from("remote".to_owned()),
+                uri: Some("https://example.com/bash-completion.stone".to_owned()),
+                ..meta
+            },
+            flags: package::Flags::default(),
+        };
+
+        let root = tempfile::tempdir().unwrap();
+        let blit_root = tempfile::tempdir().unwrap();
+        let installation = Installation::open(root.path(), None).unwrap();
+        let mut client = Client::mocked(installation, Registry::default())
+            .unwrap()
+            .ephemeral_with_options(
+                blit_root.path(),
+                EphemeralOptions {
+                    offline: true,
+                    ..EphemeralOptions::default()
+                },
+            )
+            .unwrap();
+        assert!(client.is_offline());
+        runtime::block_on(client.refresh_repositories()).unwrap();
+
+        // Not downloaded, nor retried
+        let outcome = runtime::block_on(client.cache_packages(&[&remote])).unwrap();
+        assert!(outcome.cached.is_empty());
+        assert!(matches!(
+            outcome.failed[0].error,
+            Error::CacheFetch(cache::FetchError::Offline { .. }, _)
+        ));
+
+        // Local repositories are still available
+        let outcome = runtime::block_on(client.cache_packages(&[&local])).unwrap();
+        assert_eq!(outcome.cached, std::slice::from_ref(&local.id));
+
+        // As are stones already in the download cache
+        let outcome = runtime::block_on(client.cache_packages(&[&remote])).unwrap();
+        assert_eq!(outcome.cached, std::slice::from_ref(&remote.id));
+    }
+
+    #[test]
+    fn headless_transaction() {
+        let package = Package {
+            id: package::Id::from("bash-completion".to_owned()),
+            meta: stone_meta(),
+            flags: package::Flags::new().with_available(),
+        };
+        let client = |interaction: Arc<interaction::Headless>, blit_root: &Path| {
+            let mut registry = Registry::default();
+            registry.add_plugin(Plugin::Test(plugin::Test::new(1, vec![package.clone()])));
+
+            let root = tempfile::tempdir().unwrap();
+            let installation = Installation::open(root.path(), None).unwrap();
+            let client = Client::mocked(installation, registry)
+                .unwrap()
+                .ephemeral(blit_root)
+                .unwrap()
+                .with_interaction(interaction);
+            (root, client)
+        };
+        // Consecutive events of the same kind are collapsed
+        let kinds = |events: Vec<Event>| {
+            events
+                .into_iter()
+                .map(|event| match event {
+                    Event::Resolved(resolution) => format!(
+                        "resolved {}",
+                        resolution.added.iter().map(|p| p.meta.name.to_string()).join(" ")
+                    ),
+                    Event::Downloading { package, .. } => format!("downloading {package}"),
+                    Event::Unpacking { package } => format!("unpacking {package}"),
+                    Event::Cached { package, .. } => format!("cached {package}"),
+                    Event::Blitting => "blitting".to_owned(),
+                    Event::Blitted { .. } => "blitted".to_owned(),
+                    Event::BlitFinished(_) => "blit finished".to_owned(),
+                    Event::TriggersStage(stage) => format!("triggers {stage:?}"),
+                    Event::Done => "done".to_owned(),
+                    event => panic!("unexpected event {event:?}"),
+                })
+                .dedup()
+                .collect::<Vec<_>>()
+        };
+
+        // Declined
+        let blit_root = tempfile::tempdir().unwrap();
+        let headless = interaction::Headless::new(false);
+        let (_root, mut declined) = client(headless.clone(), blit_root.path());
+        assert!(matches!(
+            declined.install(&["bash-completion"], &[], false, false),
+            Err(Error::Install(error)) if matches!(*error, install::Error::Cancelled)
+        ));
+        assert_eq!(headless.questions(), [Question::Continue]);
+        assert_eq!(kinds(headless.events()), ["resolved bash-completion"]);
+        assert!(!blit_root.path().join("usr").exists());
+
+        // Simulated, then cached & blitted as an accepted install would, short
+        // of creating the new state
+        let headless = interaction::Headless::new(true);
+        let (_root, mut accepted) = client(headless.clone(), blit_root.path());
+        accepted.install(&["bash-completion"], &[], false, true).unwrap();
+        runtime::block_on(accepted.cache_packages(&[&package]))
+            .unwrap()
+            .into_result()
+            .unwrap();
+        accepted.blit_root([&package.id]).unwrap();
+        assert!(headless.questions().is_empty());
+        assert_eq!(
+            kinds(headless.events()),
+            [
+                "resolved bash-completion",
+                "downloading bash-completion",
+                "unpacking bash-completion",
+                "cached bash-completion",
+                "blitting",
+                "blitted",
+            ]
+        );
+        assert!(
+            blit_root
+                .path()
+                .join("usr/share/bash-completion/completions/7z")
+                .exists()
+        );
+    }
+
+    #[test]
+    fn pre_transaction_hook_aborts() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = tempfile::tempdir().unwrap();
+        let hooks = root.path().join("etc/moss/hooks/pre-transaction.d");
+        fs::create_dir_all(&hooks).unwrap();
+        fs::write(hooks.join("10-veto"), "#!/bin/sh\nexit 1\n").unwrap();
+        fs::set_permissions(hooks.join("10-veto"), std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let package = package::Id::from("bash-completion".to_owned());
+        let mut registry = Registry::default();
+        registry.add_plugin(Plugin::Test(plugin::Test::new(
+            1,
+            vec![Package {
+                id: package.clone(),
+                meta: stone_meta(),
+                flags: package::Flags::new().with_available(),
+            }],
+        )));
+
+        let installation = Installation::open(root.path(), None).unwrap();
+        let client = Client::mocked(installation, registry).unwrap();
+
+        let result = client.new_state(&[Selection::explicit(package)], "Install");
+
+        assert!(matches!(
+            result,
+            Err(Error::PreTransactionHook(hooks::Error::Failed(..)))
+        ));
+        assert!(client.state_db.all().unwrap().is_empty());
+        assert!(!client.installation.staging_dir().join("usr").exists());
+    }
+
+    #[test]
+    fn root_links_stay_rooted() {
+        let root = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(outside.path().join("file"), "host").unwrap();
+
+        // A stale absolute link pointing out of the root is replaced, not followed
+        symlink(outside.path(), root.path().join("lib")).unwrap();
+
+        create_root_links(root.path()).unwrap();
+
+        for (source, target) in [("usr/bin", "bin"), ("usr/lib", "lib"), ("usr/lib", "lib64")] {
+            assert_eq!(fs::read_link(root.path().join(target)).unwrap(), Path::new(source));
+        }
+        assert_eq!(
+            fs::read_dir(outside.path()).unwrap().count(),
+            1,
+            "nothing was written outside of the root"
+        );
+
+        // Relinking is a no-op, even after an interrupted link left a dangling one
+        symlink("missing", root.path().join("bin.next")).unwrap();
+        create_root_links(root.path()).unwrap();
+        create_root_links(root.path()).unwrap();
+        assert_eq!(fs::read_link(root.path().join("bin")).unwrap(), Path::new("usr/bin"));
+        assert!(fs::symlink_metadata(root.path().join("bin.next")).is_err());
+    }
+
+    #[test]
+    fn concurrent_clients() {
+        let root = tempfile::tempdir().unwrap();
+        let client = |wait: bool| {
+            let installation = Installation::open(root.path(), None).unwrap().wait_for_lock(wait);
+            let interaction = interaction::Headless::new(true);
+            let client = Client::mocked(installation, Registry::default())
+                .unwrap()
+                .with_interaction(interaction.clone());
+            (client, interaction)
+        };
+
+        let (first, _) = client(false);
+        let lock = first.lock().unwrap();
+        // Nested operations of the holder share its lock
+        first.prune_cache(false, false).unwrap();
+
+        // Others fail immediately, naming the holder
+        let (second, _) = client(false);
+        assert!(matches!(
+            second.prune_cache(false, false),
+            Err(Error::Locked { pid, .. }) if pid == std::process::id()
+        ));
+        // Read-only operations don't need the lock
+        second.prune_cache(true, false).unwrap();
+        second.list_states().unwrap();
+
+        // Or wait for the holder to finish
+        let (waiting, interaction) = client(true);
+        let waiter = std::thread::spawn(move || waiting.prune_cache(false, false).map(drop));
+        while interaction.events().is_empty() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!waiter.is_finished());
+        assert!(matches!(
+            &interaction.events()[..],
+            [Event::WaitingForLock(Some(holder))] if holder.pid == std::process::id()
+        ));
+
+        drop(lock);
+        waiter.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn state_changed_before_lock() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("usr")).unwrap();
+        fs::write(root.path().join("usr/.stateID"), "1").unwrap();
+
+        let client = || Client::new("test", Installation::open(root.path(), None).unwrap()).unwrap();
+
+        let mut first = client();
+        for id in 1..=3 {
+            first.state_db.add(&[], &[], None, None).unwrap();
+            if id > 1 {
+                let usr = first.installation.root_path(id.to_string()).join("usr");
+                fs::create_dir_all(&usr).unwrap();
+                fs::write(usr.join(".stateID"), id.to_string()).unwrap();
+            }
+        }
+
+        // Another client's transaction lands after `first` was built
+        client().activate_state(state::Id::from(2), true, true).unwrap();
+
+        // Operations building on the stale active state refuse to run
+        assert!(matches!(
+            first.activate_state(state::Id::from(3), true, true),
+            Err(Error::StateChanged)
+        ));
+        assert!(matches!(
+            first.rollback(state::Id::from(1), false),
+            Err(Error::StateChanged)
+        ));
+        assert!(first.installation.root_path("3").exists());
+        assert_eq!(first.installation.read_active_state(), Some(state::Id::from(2)));
+
+        // Or reload first, building on the new one
+        drop(first.lock_reloaded(false).unwrap());
+        assert_eq!(first.installation.active_state, Some(state::Id::from(2)));
+        assert_eq!(
+            first.activate_state(state::Id::from(3), true, true).unwrap(),
+            state::Id::from(2)
+        );
+        assert!(first.installation.root_path("2").join("usr").exists());
+    }
+
+    #[test]
+    fn swap_recovery_waits_for_lock() {
+        let root = tempfile::tempdir().unwrap();
+        let holder = Installation::open(root.path(), None).unwrap();
+
+        // A fallback swap of `/usr` which hasn't moved anything yet
+        let staged = holder.staging_dir().join("usr");
+        let usr = root.path().join("usr");
+        for (dir, tree) in [(&staged, "new"), (&usr, "old")] {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("tree"), tree).unwrap();
+        }
+        let marker = root.path().join("usr.moss-swap.pending");
+        fs::write(&marker, format!("started\n{}", staged.display())).unwrap();
+
+        // Left alone while the swapping process may still hold the lock
+        let lock = holder.lock(|_| {}).unwrap();
+        Client::new("test", Installation::open(root.path(), None).unwrap()).unwrap();
+        assert!(marker.exists());
+        assert_eq!(fs::read_to_string(usr.join("tree")).unwrap(), "old");
+
+        // Then finished by the next client
+        drop(lock);
+        Client::new("test", Installation::open(root.path(), None).unwrap()).unwrap();
+        assert!(!marker.exists());
+        assert_eq!(fs::read_to_string(usr.join("tree")).unwrap(), "new");
+    }
+
+    #[test]
+    fn blit_stats() {
+        let root = tempfile::tempdir().unwrap();
+        let blit_target = tempfile::tempdir().unwrap();
+        let installation = Installation::open(root.path(), None).unwrap();
+
+        let (bash_hash, libc_hash) = (
+            0x0123_4567_89ab_cdef_0123_4567_89ab_cdef_u128,
+            0xfedc_ba98_7654_3210_fedc_ba98_7654_3210_u128,
+        );
+        for (hash, len) in [(bash_hash, 64), (libc_hash, 128)] {
+            let path = cache::asset_path(&installation, &format!("{hash:02x}"));
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, vec![0; len]).unwrap();
+        }
+
+        let record = |file| {
+            (
+                package::Id::from("bash"),
+                StonePayloadLayoutRecord {
+                    uid: 0,
+                    gid: 0,
+                    mode: 0o644,
+                    tag: 0,
+                    file,
+                },
+            )
+        };
+        let fstree = vfs(vec![
+            record(StonePayloadLayoutFile::Regular(bash_hash, "bin/bash".into())),
+            record(StonePayloadLayoutFile::Symlink("bash".into(), "bin/sh".into())),
+            record(StonePayloadLayoutFile::Regular(libc_hash, "lib/libc.so.6".into())),
+            // The empty file is created rather than linked
+            record(StonePayloadLayoutFile::Regular(
+                0x99aa_06d3_0147_98d8_6001_c324_468d_497f,
+                "share/empty".into(),
+            )),
+            record(StonePayloadLayoutFile::Directory("share/doc".into())),
+        ])
+        .unwrap();
+
+        let stats = blit_root(
+            &installation,
+            &fstree,
+            blit_target.path(),
+            &*interaction::Headless::new(true),
+        )
+        .unwrap();
+
+        // `usr`, `usr/bin`, `usr/lib`, `usr/share` & `usr/share/doc`
+        assert_eq!(
+            stats,
+            BlitStats {
+                num_files: 3,
+                num_symlinks: 1,
+                num_dirs: 5,
+                bytes_linked: 192,
+                elapsed: stats.elapsed,
+            }
+        );
+        assert_eq!(stats.num_entries(), 9);
+        assert_eq!(
+            fs::read(blit_target.path().join("usr/lib/libc.so.6")).unwrap().len(),
+            128
+        );
+
+        // Several blits, i.e. of verification, add up
+        let combined = stats.merge(stats);
+        assert_eq!(combined.num_entries(), 18);
+        assert_eq!(combined.bytes_linked, 384);
+        assert_eq!(combined.elapsed, stats.elapsed * 2);
+    }
+
+    #[test]
+    fn local_overlay() {
+        let root = tempfile::tempdir().unwrap();
+        let config = root.path().join("etc/moss/local-repo.d");
+        fs::create_dir_all(&config).unwrap();
+        fs::write(config.join("builds.yaml"), "path: /var/lib/moss/local-repo\n").unwrap();
+        let overlay = root.path().join("var/lib/moss/local-repo");
+        fs::create_dir_all(&overlay).unwrap();
+
+        let installation = Installation::open(root.path(), None).unwrap();
+        let headless = interaction::Headless::new(true);
+        let mut client = Client::new(environment::NAME, installation)
+            .unwrap()
+            .with_interaction(headless.clone());
+
+        // A remote repository offering an older release
+        let remote = Package {
+            id: package::Id::from("bash-completion-remote"),
+            meta: package::Meta {
+                version_identifier: "2.10".to_owned(),
+                source_release: 0,
+                ..stone_meta()
+            },
+            flags: package::Flags::new().with_available(),
+        };
+        let resolve = |client: &mut Client| {
+            client.registry.add_plugin(Plugin::Test(
+                plugin::Test::new(100, vec![remote.clone()])
+                    .with_origin(plugin::Origin::Repository(repository::Id::new("volatile"))),
+            ));
+            client.install(&["bash-completion"], &[], true, true).unwrap();
+            let resolution = headless
+                .events()
+                .into_iter()
+                .rev()
+                .find_map(|event| match event {
+                    Event::Resolved(resolution) => Some(resolution),
+                    _ => None,
+                })
+                .unwrap();
+            let added = resolution.added.into_iter().exactly_one().unwrap();
+            (added.meta.version_identifier, client.registry.origin(&added.id))
+        };
+
+        assert_eq!(
+            resolve(&mut client),
+            (
+                "2.10".to_owned(),
+                Some(plugin::Origin::Repository(repository::Id::new("volatile")))
+            )
+        );
+
+        // Picked up once the overlay is modified, timestamps being too coarse to
+        // tell apart changes made right after the scan
+        fs::copy(
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("../test/bash-completion-2.11-1-1-x86_64.stone"),
+            overlay.join("bash-completion-2.11-1-1-x86_64.stone"),
+        )
+        .unwrap();
+        fs::File::open(&overlay)
+            .unwrap()
+            .set_modified(std::time::SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        assert!(client.refresh_overlay().unwrap());
+        assert!(!client.refresh_overlay().unwrap());
+
+        assert_eq!(resolve(&mut client), ("2.11".to_owned(), Some(plugin::Origin::Local)));
+    }
 }
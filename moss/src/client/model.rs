@@ -0,0 +1,509 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Reconcile the system with a [`SystemModel`]
+
+use itertools::Itertools;
+use thiserror::Error;
+use tui::Styled;
+
+use crate::{
+    Client, Package, Provider, Registry, Repository, SystemModel, client, environment, package, registry::transaction,
+    repository, runtime, state::Selection,
+};
+
+use super::{
+    build_registry, install,
+    interaction::{Event, Question},
+    sync::Changes,
+};
+
+/// A repository change needed to satisfy the model
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RepositoryChange {
+    /// Not configured, or configured with a different source or priority
+    Add(repository::Id, Box<Repository>),
+    /// Configured but disabled
+    Enable(repository::Id),
+}
+
+/// Everything required to converge the system with a [`SystemModel`]
+#[derive(Debug)]
+pub struct Plan {
+    pub repositories: Vec<RepositoryChange>,
+    /// Packages of the active state
+    pub installed: Vec<Package>,
+    /// Fully resolved package set of the model
+    pub finalized: Vec<Package>,
+}
+
+impl Plan {
+    fn changes(&self) -> Changes<'_> {
+        Changes::new(&self.installed, &self.finalized, false)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.repositories.is_empty() && self.changes().is_empty()
+    }
+}
+
+/// How the system drifted from the model recorded for its active state,
+/// see [`Client::model_status`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Drift {
+    /// Packages declared by the model which no explicitly installed package provides
+    pub only_in_model: Vec<Provider>,
+    /// Explicitly installed packages the model doesn't declare
+    pub only_installed: Vec<package::Name>,
+    /// Repositories configured differently to the model
+    pub repositories: Vec<RepositoryDrift>,
+}
+
+impl Drift {
+    /// Returns `true` if the system matches its model
+    pub fn is_empty(&self) -> bool {
+        self.only_in_model.is_empty() && self.only_installed.is_empty() && self.repositories.is_empty()
+    }
+}
+
+/// A repository configured differently to the model
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RepositoryDrift {
+    /// Declared by the model, but not configured or disabled
+    OnlyInModel(repository::Id),
+    /// Configured & enabled, but not declared by the model
+    OnlyConfigured(repository::Id),
+    /// Configured with a different source or priority than declared
+    Mismatch(repository::Id),
+    /// Declared disabled by the model, but configured & enabled
+    EnabledOutsideModel(repository::Id),
+}
+
+/// Compare the `model` with the `explicit` packages of the active state
+/// & the `configured` repositories
+pub fn drift<'a>(
+    model: &SystemModel,
+    explicit: &[Package],
+    configured: impl IntoIterator<Item = (&'a repository::Id, &'a Repository)>,
+) -> Drift {
+    let only_in_model = model
+        .packages
+        .iter()
+        .filter(|provider| !explicit.iter().any(|package| package.meta.providers.contains(provider)))
+        .cloned()
+        .collect();
+    let only_installed = explicit
+        .iter()
+        .filter(|package| model.packages.is_disjoint(&package.meta.providers))
+        .map(|package| package.meta.name.clone())
+        .sorted()
+        .dedup()
+        .collect();
+
+    let configured = configured.into_iter().collect::<Vec<_>>();
+    let mut repositories = model
+        .repositories
+        .iter()
+        .filter_map(
+            |(id, declared)| match configured.iter().find(|(configured, _)| *configured == id) {
+                Some((_, repo)) if repo.source != declared.source || repo.priority != declared.priority => {
+                    Some(RepositoryDrift::Mismatch(id.clone()))
+                }
+                Some((_, repo)) if repo.active == declared.active => None,
+                Some((_, repo)) if repo.active => Some(RepositoryDrift::EnabledOutsideModel(id.clone())),
+                _ => Some(RepositoryDrift::OnlyInModel(id.clone())),
+            },
+        )
+        .collect::<Vec<_>>();
+    repositories.extend(
+        configured
+            .iter()
+            .filter(|(id, repo)| repo.active && model.repositories.get(id).is_none())
+            .map(|(id, _)| RepositoryDrift::OnlyConfigured((*id).clone())),
+    );
+
+    Drift {
+        only_in_model,
+        only_installed,
+        repositories,
+    }
+}
+
+/// Apply the system `model`, configuring its repositories & creating a new
+/// state with exactly its packages and their dependencies
+///
+/// Nothing is changed unless every package of the model can be resolved
+pub fn apply(client: &mut Client, model: &SystemModel, yes: bool) -> Result<(), Error> {
+    if client.is_ephemeral() {
+        return Err(client::Error::EphemeralProhibitedOperation.into());
+    }
+
+    // Resolve against the model's repositories only, without touching the
+    // configuration until the plan is confirmed. These share their cache with
+    // configured repositories of the same source, so indexes are fetched once.
+    let mut declared = repository::Manager::with_explicit(
+        environment::NAME,
+        model.repositories.clone(),
+        client.installation.clone(),
+    )?;
+    runtime::block_on(declared.ensure_all_initialized())?;
+    let registry = build_registry(
+        &client.installation,
+        &declared,
+        &client.overlay,
+        &client.local,
+        &client.install_db,
+        &client.state_db,
+    )?;
+
+    let plan = plan(&registry, client.repositories.list(), model)?;
+
+    if plan.is_empty() {
+        println!("System already matches the model");
+        return Ok(());
+    }
+
+    print_repositories(&plan.repositories);
+    let changes = plan.changes();
+    let conflicts = changes.conflicts();
+    if !conflicts.is_empty() {
+        return Err(Error::Conflicts(conflicts));
+    }
+    if !changes.is_empty() {
+        client.interaction.report(Event::Resolved(changes.resolution()));
+    }
+
+    if !client.confirm(yes, &Question::Continue)? {
+        return Err(Error::Cancelled);
+    }
+
+    for change in &plan.repositories {
+        match change {
+            RepositoryChange::Add(id, repository) => {
+                client
+                    .repositories
+                    .add_repository(id.clone(), Repository::clone(repository))?;
+            }
+            RepositoryChange::Enable(id) => runtime::block_on(client.repositories.enable(id))?,
+        }
+    }
+    if !plan.repositories.is_empty() {
+        runtime::block_on(client.ensure_repos_initialized())?;
+    }
+
+    if changes.is_empty() {
+        return Ok(());
+    }
+
+    runtime::block_on(client.cache_packages(&changes.synced()))?.into_result()?;
+
+    // Packages declared by the model are explicit, the rest are transitive
+    let selections = plan
+        .finalized
+        .iter()
+        .map(|package| Selection {
+            package: package.id.clone(),
+            explicit: model.packages.intersection(&package.meta.providers).next().is_some(),
+            reason: None,
+        })
+        .collect::<Vec<_>>();
+
+    client.new_state(&selections, "Apply system model")?;
+
+    Ok(())
+}
+
+/// Plan the changes to converge the `configured` repositories & installed
+/// packages of `registry` with the `model`
+pub fn plan<'a>(
+    registry: &Registry,
+    configured: impl IntoIterator<Item = (&'a repository::Id, &'a Repository)>,
+    model: &SystemModel,
+) -> Result<Plan, Error> {
+    let repositories = repository_changes(configured, &model.repositories);
+
+    let (packages, missing): (Vec<_>, Vec<_>) = model.packages.iter().partition_map(|provider| {
+        match registry
+            .by_provider_id_only(provider, package::Flags::default().with_available())
+            .next()
+        {
+            Some(id) => itertools::Either::Left(id),
+            None => itertools::Either::Right(provider.clone()),
+        }
+    });
+    if !missing.is_empty() {
+        return Err(Error::MissingPackages(missing));
+    }
+
+    let mut tx = registry.transaction(transaction::Lookup::AvailableOnly)?;
+    tx.add(packages)?;
+
+    let finalized = tx
+        .finalize()
+        .map(|id| {
+            registry
+                .by_id(id)
+                .next()
+                .ok_or(client::Error::MissingMetadata(id.clone()))
+        })
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .sorted_by(|a, b| a.meta.name.cmp(&b.meta.name))
+        .dedup_by(|a, b| a.id == b.id)
+        .collect();
+
+    Ok(Plan {
+        repositories,
+        installed: registry.list_installed().collect(),
+        finalized,
+    })
+}
+
+/// Repositories of the `model` which aren't configured as declared
+fn repository_changes<'a>(
+    configured: impl IntoIterator<Item = (&'a repository::Id, &'a Repository)>,
+    model: &repository::Map,
+) -> Vec<RepositoryChange> {
+    let configured = configured.into_iter().collect::<Vec<_>>();
+
+    model
+        .iter()
+        .filter_map(
+            |(id, declared)| match configured.iter().find(|(configured, _)| *configured == id) {
+                Some((_, repo)) if repo.source == declared.source && repo.priority == declared.priority => {
+                    (declared.active && !repo.active).then(|| RepositoryChange::Enable(id.clone()))
+                }
+                _ => Some(RepositoryChange::Add(id.clone(), Box::new(declared.clone()))),
+            },
+        )
+        .collect()
+}
+
+fn print_repositories(changes: &[RepositoryChange]) {
+    if changes.is_empty() {
+        return;
+    }
+
+    println!("The following repositories will be configured: ");
+    println!();
+    for change in changes {
+        match change {
+            RepositoryChange::Add(id, repository) => {
+                println!(" {} {}", "add".green(), id.to_string().bold());
+                let uri = match &repository.source {
+                    repository::Source::DirectIndex(uri) => uri,
+                    repository::Source::RootIndex(source) => &source.base_uri,
+                };
+                println!("     {}", uri.as_str().dim());
+            }
+            RepositoryChange::Enable(id) => println!(" {} {}", "enable".green(), id.to_string().bold()),
+        }
+    }
+    println!();
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(
+        "Packages defined in system model do not exist in any of its repositories: {}",
+        .0.iter().join(", ")
+    )]
+    MissingPackages(Vec<Provider>),
+
+    #[error("conflicting packages: {}", .0.iter().join("; "))]
+    Conflicts(Vec<install::Conflict>),
+
+    #[error("cancelled")]
+    Cancelled,
+
+    #[error("client")]
+    Client(#[from] client::Error),
+
+    #[error("repository manager")]
+    Repository(#[from] repository::manager::Error),
+
+    #[error("io")]
+    Io(#[from] std::io::Error),
+
+    #[error("transaction")]
+    Transaction(#[from] transaction::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use fs_err as fs;
+
+    use super::*;
+    use crate::{
+        Dependency,
+        registry::{Plugin, plugin},
+        system_model,
+    };
+
+    const MODEL: &str = r#"
+        repositories {
+            volatile {
+                uri "https://packages.aerynos.dev/volatile/x86_64/stone.index"
+                priority 0
+            }
+            local {
+                uri "file:///var/cache/local/stone.index"
+                priority 10
+            }
+        }
+        packages {
+            a
+            d
+        }
+    "#;
+
+    fn package(id: &'static str, deps: &[&str], flags: package::Flags) -> Package {
+        let name = id.split('-').next().unwrap();
+
+        Package {
+            id: package::Id::from(id),
+            meta: package::Meta {
+                source_release: id.rsplit('-').next().unwrap().parse().unwrap(),
+                dependencies: deps.iter().map(|d| Dependency::from_str(d).unwrap()).collect(),
+                ..package::fixture::meta(name)
+            },
+            flags,
+        }
+    }
+
+    fn registry() -> Registry {
+        let installed = package::Flags::new().with_installed();
+        let available = package::Flags::new().with_available();
+
+        let mut registry = Registry::default();
+        registry.add_plugin(Plugin::Test(plugin::Test::new(
+            1,
+            vec![
+                package("a-1", &[], installed.with_explicit()),
+                package("b-1", &[], installed.with_explicit()),
+                package("a-2", &["name(c)"], available),
+                package("c-1", &[], available),
+                package("d-1", &[], available),
+            ],
+        )));
+        registry
+    }
+
+    fn model(content: &str) -> SystemModel {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("system-model.kdl");
+        fs::write(&path, content).unwrap();
+        system_model::load(&path).unwrap().unwrap().into()
+    }
+
+    #[test]
+    fn reconcile() {
+        let model = model(MODEL);
+
+        // `volatile` is configured but disabled, `local` isn't configured
+        let volatile = repository::Id::new("volatile");
+        let mut configured = model.repositories.get(&volatile).unwrap().clone();
+        configured.active = false;
+        let configured = [(volatile.clone(), configured)];
+
+        let planned = plan(&registry(), configured.iter().map(|(id, repo)| (id, repo)), &model).unwrap();
+
+        let local = repository::Id::new("local");
+        assert_eq!(
+            planned.repositories,
+            vec![
+                RepositoryChange::Add(local.clone(), Box::new(model.repositories.get(&local).unwrap().clone())),
+                RepositoryChange::Enable(volatile),
+            ]
+        );
+
+        let ids = |packages: &[&Package]| packages.iter().map(|p| p.id.to_string()).sorted().collect::<Vec<_>>();
+        let changes = planned.changes();
+        assert_eq!(
+            ids(&planned.finalized.iter().collect::<Vec<_>>()),
+            ["a-2", "c-1", "d-1"]
+        );
+        assert_eq!(ids(&changes.added), ["c-1", "d-1"]);
+        assert_eq!(ids(&changes.updated.iter().map(|u| u.new).collect::<Vec<_>>()), ["a-2"]);
+        assert_eq!(ids(&changes.removed), ["b-1"]);
+
+        // Once configured as declared, only packages differ
+        let planned = plan(&registry(), &model.repositories, &model).unwrap();
+        assert!(planned.repositories.is_empty());
+    }
+
+    #[test]
+    fn unresolvable_packages() {
+        let model = model(&MODEL.replace(
+            "            d\n",
+            "            d\n            nope\n            missing\n",
+        ));
+
+        let Err(Error::MissingPackages(missing)) = plan(&registry(), &model.repositories, &model) else {
+            panic!("expected missing packages");
+        };
+        assert_eq!(
+            missing.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            ["name(missing)", "name(nope)"]
+        );
+    }
+
+    #[test]
+    fn model_drift() {
+        let model = model(MODEL);
+        let installed = package::Flags::new().with_installed().with_explicit();
+        let explicit = [package("a-1", &[], installed), package("b-1", &[], installed)];
+
+        let volatile = repository::Id::new("volatile");
+        let local = repository::Id::new("local");
+        let extra = repository::Id::new("extra");
+        let disabled = repository::Id::new("disabled");
+
+        let mut configured = model.repositories.clone().into_iter().collect::<Vec<_>>();
+        for (id, repo) in &mut configured {
+            if *id == local {
+                repo.priority = repository::Priority::new(20);
+            }
+        }
+        let mut other = model.repositories.get(&volatile).unwrap().clone();
+        configured.push((extra.clone(), other.clone()));
+        other.active = false;
+        configured.push((disabled, other));
+
+        let status = drift(&model, &explicit, configured.iter().map(|(id, repo)| (id, repo)));
+        assert_eq!(
+            status,
+            Drift {
+                only_in_model: vec![Provider::from_str("name(d)").unwrap()],
+                only_installed: vec![package::Name::from("b".to_owned())],
+                repositories: vec![RepositoryDrift::Mismatch(local), RepositoryDrift::OnlyConfigured(extra)],
+            }
+        );
+        assert!(!status.is_empty());
+
+        // A declared repository which is disabled
+        let mut configured = model.repositories.clone().into_iter().collect::<Vec<_>>();
+        for (id, repo) in &mut configured {
+            repo.active = *id != volatile;
+        }
+        let explicit = [package("a-2", &[], installed), package("d-1", &[], installed)];
+        let status = drift(&model, &explicit, configured.iter().map(|(id, repo)| (id, repo)));
+        assert_eq!(status.repositories, [RepositoryDrift::OnlyInModel(volatile.clone())]);
+        assert!(status.only_in_model.is_empty() && status.only_installed.is_empty());
+
+        // Disabled by the model, but enabled
+        let mut model = model;
+        model.repositories.get_mut(&volatile).unwrap().active = false;
+        let status = drift(&model, &explicit, configured.iter().map(|(id, repo)| (id, repo)));
+        assert!(status.repositories.is_empty());
+        let mut enabled = model.repositories.clone();
+        enabled.get_mut(&volatile).unwrap().active = true;
+        let status = drift(&model, &explicit, &enabled);
+        assert_eq!(status.repositories, [RepositoryDrift::EnabledOutsideModel(volatile)]);
+
+        // Configured as declared
+        assert!(drift(&model, &explicit, &model.repositories).is_empty());
+    }
+}
@@ -7,18 +7,27 @@
 //!
 //! Note that currently we only load from `/usr/share/moss/triggers/{tx,sys.d}/*.yaml`
 //! and do not yet support local triggers
+//!
+//! System triggers of a stateful client are skipped when the paths they match, and
+//! the mtimes or targets of those paths, are unchanged since they last succeeded. See [`Cache`].
 use std::{
+    collections::{BTreeMap, BTreeSet},
+    io,
+    os::unix::ffi::OsStrExt,
     path::{Path, PathBuf},
     process,
+    time::SystemTime,
 };
 
 use crate::Installation;
 use container::Container;
+use fs_err as fs;
 use itertools::Itertools;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tracing::{error, warn};
 use triggers::format::{CompiledHandler, Handler, Trigger};
+use xxhash_rust::xxh3::Xxh3;
 
 use super::PendingFile;
 
@@ -60,11 +69,11 @@ impl TriggerScope<'_> {
         match self {
             TriggerScope::Transaction(install, scope) => match scope {
                 super::Scope::Stateful => install.staging_dir().clone(),
-                super::Scope::Ephemeral { blit_root } => blit_root.clone(),
+                super::Scope::Ephemeral { blit_root, .. } => blit_root.clone(),
             },
             TriggerScope::System(install, scope) => match scope {
                 super::Scope::Stateful => install.root.clone(),
-                super::Scope::Ephemeral { blit_root } => blit_root.clone(),
+                super::Scope::Ephemeral { blit_root, .. } => blit_root.clone(),
             },
         }
     }
@@ -74,25 +83,49 @@ impl TriggerScope<'_> {
         match self {
             TriggerScope::Transaction(install, scope) => match scope {
                 super::Scope::Stateful => install.root.join(path),
-                super::Scope::Ephemeral { blit_root } => blit_root.join(path),
+                super::Scope::Ephemeral { blit_root, .. } => blit_root.join(path),
             },
             TriggerScope::System(install, scope) => match scope {
                 super::Scope::Stateful => install.root.join(path),
-                super::Scope::Ephemeral { blit_root } => blit_root.join(path),
+                super::Scope::Ephemeral { blit_root, .. } => blit_root.join(path),
             },
         }
     }
 
+    /// Returns true if the trigger runs against the running system, which is
+    /// only the case for system triggers of a stateful client on the host
+    ///
+    /// Ephemeral blits & alternate roots must never be escaped, even when the
+    /// installation itself is the host
+    fn is_host(&self) -> bool {
+        match self {
+            TriggerScope::Transaction(..) => false,
+            TriggerScope::System(install, scope) => matches!(scope, super::Scope::Stateful) && install.is_host(),
+        }
+    }
+
+    /// Path of the [`Cache`] of this scope, `None` if its results aren't cached
+    ///
+    /// Only system triggers of the installation itself are cached, within its root as
+    /// cache dirs can be shared by several. Ephemeral roots are fresh every time and
+    /// transaction triggers run against a new staging tree
+    fn cache_path(&self) -> Option<PathBuf> {
+        match self {
+            TriggerScope::System(install, super::Scope::Stateful) => Some(install.trigger_cache_path()),
+            _ => None,
+        }
+    }
+
     /// Join guest paths, inside the staging filesystem. Ensure no sandbox break for ephemeral
     fn guest_path(&self, path: impl AsRef<Path>) -> PathBuf {
         match self {
             TriggerScope::Transaction(install, scope) => match scope {
                 super::Scope::Stateful => install.staging_path(path),
-                super::Scope::Ephemeral { blit_root } => blit_root.join(path),
+                super::Scope::Ephemeral { blit_root, .. } => blit_root.join(path),
             },
             TriggerScope::System(install, scope) => match scope {
                 super::Scope::Stateful => install.root.join(path),
-                super::Scope::Ephemeral { blit_root } => blit_root.join(path),
+                super::Scope::Ephemeral { blit_root, .. } => blit_root.join(path),
             },
         }
     }
@@ -103,6 +136,8 @@ impl TriggerScope<'_> {
 pub(super) struct TriggerRunner<'a> {
     scope: TriggerScope<'a>,
     trigger: CompiledHandler,
+    /// Paths of the filesystem tree matched by the trigger
+    paths: BTreeSet<String>,
 }
 
 /// Load all triggers matching the given scope and staging filesystem
@@ -145,9 +180,9 @@ pub(super) fn triggers<'a>(
     let mut collection = triggers::Collection::new(triggers.iter())?;
     collection.process_paths(fstree.iter().map(|m| m.to_string()));
     let computed_commands = collection
-        .bake()?
+        .bake_with_paths()?
         .into_iter()
-        .map(|trigger| TriggerRunner { scope, trigger })
+        .map(|(trigger, paths)| TriggerRunner { scope, trigger, paths })
         .collect_vec();
     Ok(computed_commands)
 }
@@ -157,13 +192,24 @@ impl TriggerRunner<'_> {
         self.trigger.handler()
     }
 
+    /// Fingerprint of the handler & the current state of the paths it matches
+    fn fingerprint(&self) -> u64 {
+        fingerprint(
+            self.handler(),
+            self.paths.iter().map(|path| {
+                let input = Input::of(&self.scope.guest_path(path.trim_start_matches('/')));
+                (path.as_str(), input)
+            }),
+        )
+    }
+
     /// Execute a trigger, taking care to account for the transaction scope and client scope
     ///
     /// All transaction triggers are run via sandboxing ([`container::Container`]) to limit their
     /// system view, and limit write access.
     /// System triggers will execute without any sandboxing when moss is used directly against the
     /// live root filesystem, and will force sandboxing when using a non-`/` root (such as using the
-    /// `-D argument with `moss install`)
+    /// `-D` argument with `moss install`) or an ephemeral blit root
     pub fn execute(&self) -> Result<(), Error> {
         match self.scope {
             TriggerScope::Transaction(install, _) => {
@@ -177,8 +223,8 @@ impl TriggerRunner<'_> {
                 Ok(isolation.run(|| execute_trigger_directly(&self.trigger))?)
             }
             TriggerScope::System(install, _) => {
-                // OK, if the root == `/` then we can run directly, otherwise we need to containerise with RW.
-                if install.root.to_string_lossy() == "/" {
+                // OK, if we target the host then we can run directly, otherwise we need to containerise with RW.
+                if self.scope.is_host() {
                     Ok(execute_trigger_directly(&self.trigger)?)
                 } else {
                     let isolation = Container::new(install.isolation_dir())
@@ -194,33 +240,164 @@ impl TriggerRunner<'_> {
     }
 }
 
-/// Internal executor for triggers.
+/// State of a path matched by a trigger, as fingerprinted
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Input {
+    /// Missing from the filesystem
+    Missing,
+    /// Only its presence, as the mtime of a directory changes with any of its entries
+    Directory,
+    /// Its target, as relinking needn't change the mtime of the link
+    Symlink(PathBuf),
+    File(Option<SystemTime>),
+}
+
+impl Input {
+    fn of(path: &Path) -> Self {
+        let Ok(meta) = fs::symlink_metadata(path) else {
+            return Self::Missing;
+        };
+
+        if meta.is_symlink() {
+            fs::read_link(path).map_or(Self::Missing, Self::Symlink)
+        } else if meta.is_dir() {
+            Self::Directory
+        } else {
+            Self::File(meta.modified().ok())
+        }
+    }
+}
+
+/// Hash `handler` along with each of its matched paths & their [`Input`]
+fn fingerprint<'a>(handler: &Handler, inputs: impl IntoIterator<Item = (&'a str, Input)>) -> u64 {
+    let mut hasher = Xxh3::new();
+
+    hasher.update(handler.to_string().as_bytes());
+
+    for (path, input) in inputs {
+        // Delimit, so adjacent paths can't run together
+        hasher.update(&[0]);
+        hasher.update(path.as_bytes());
+
+        match input {
+            Input::Missing => hasher.update(&[0xff]),
+            Input::Directory => hasher.update(&[1]),
+            Input::Symlink(target) => {
+                hasher.update(&[2]);
+                hasher.update(target.as_os_str().as_bytes());
+                hasher.update(&[0]);
+            }
+            Input::File(mtime) => {
+                hasher.update(&[3]);
+                if let Some(mtime) = mtime.and_then(|mtime| mtime.duration_since(SystemTime::UNIX_EPOCH).ok()) {
+                    hasher.update(&mtime.as_nanos().to_le_bytes());
+                }
+            }
+        }
+    }
+
+    hasher.digest()
+}
+
+/// Fingerprints of the triggers that last succeeded, used to skip triggers
+/// whose inputs are unchanged since
+///
+/// Stored within the root of the installation, see [`TriggerScope::cache_path`]
+#[derive(Debug, Default)]
+pub(super) struct Cache {
+    path: Option<PathBuf>,
+    /// Run every trigger regardless, see [`Installation::force_triggers`]
+    force: bool,
+    fingerprints: Fingerprints,
+}
+
+/// Fingerprint of each handler, keyed by its command line
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Fingerprints(BTreeMap<String, u64>);
+
+impl Cache {
+    /// Load the cache of `scope`, empty when it's not cached or unreadable
+    pub fn load(scope: TriggerScope<'_>) -> Self {
+        let force = match scope {
+            TriggerScope::Transaction(install, _) | TriggerScope::System(install, _) => install.force_triggers,
+        };
+        let Some(path) = scope.cache_path() else {
+            return Self::default();
+        };
+
+        let fingerprints = match fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|error| {
+                warn!(path = ?path, %error, "Ignoring corrupt trigger cache");
+                Fingerprints::default()
+            }),
+            Err(_) => Fingerprints::default(),
+        };
+
+        Self {
+            path: Some(path),
+            force,
+            fingerprints,
+        }
+    }
+
+    /// Returns `true` if `trigger` can be skipped, as the paths it matches are
+    /// unchanged since it last succeeded
+    pub fn is_fresh(&self, trigger: &TriggerRunner<'_>) -> bool {
+        self.path.is_some() && !self.force && self.is_recorded(&trigger.handler().to_string(), trigger.fingerprint())
+    }
+
+    fn is_recorded(&self, key: &str, fingerprint: u64) -> bool {
+        self.fingerprints.0.get(key) == Some(&fingerprint)
+    }
+
+    /// Record `trigger` has succeeded, fingerprinting the paths it matches
+    /// after it ran, as it may have changed them itself
+    pub fn record(&mut self, trigger: &TriggerRunner<'_>) {
+        if self.path.is_some() {
+            self.fingerprints
+                .0
+                .insert(trigger.handler().to_string(), trigger.fingerprint());
+        }
+    }
+
+    /// Persist the recorded fingerprints
+    pub fn save(&self) -> Result<(), Error> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(Error::SaveCache)?;
+        }
+        let json = serde_json::to_vec(&self.fingerprints).map_err(|e| Error::SaveCache(e.into()))?;
+        fs::write(path, json).map_err(Error::SaveCache)
+    }
+}
+
+/// Internal executor for triggers, failing unless the handler exits successfully
 fn execute_trigger_directly(trigger: &CompiledHandler) -> Result<(), Error> {
     match trigger.handler() {
         Handler::Run { run, args } => {
             let cmd = process::Command::new(run).args(args).current_dir("/").output()?;
 
-            if let Some(code) = cmd.status.code() {
-                if code != 0 {
-                    // Convert outputs once and reuse
-                    let stdout = String::from_utf8_lossy(&cmd.stdout);
-                    let stderr = String::from_utf8_lossy(&cmd.stderr);
-
-                    warn!(
-                        command = run,
-                        args = ?args,
-                        exit_code = code,
-                        stdout = %stdout,
-                        stderr = %stderr,
-                        "Trigger exited with non-zero status code"
-                    );
-                }
-            } else {
+            if !cmd.status.success() {
+                // Convert outputs once and reuse
+                let stdout = String::from_utf8_lossy(&cmd.stdout);
+                let stderr = String::from_utf8_lossy(&cmd.stderr);
+
                 error!(
                     command = run,
                     args = ?args,
-                    "Failed to execute trigger"
+                    exit_code = cmd.status.code(),
+                    stdout = %stdout,
+                    stderr = %stderr,
+                    "Trigger failed"
                 );
+
+                return Err(Error::Failed {
+                    command: run.clone(),
+                    status: cmd.status,
+                });
             }
         }
         Handler::Delete { .. } => todo!(),
@@ -238,5 +415,139 @@ pub enum Error {
     Triggers(#[from] triggers::Error),
 
     #[error("io")]
-    IO(#[from] std::io::Error),
+    IO(#[from] io::Error),
+
+    #[error("trigger `{command}` failed, {status}")]
+    Failed {
+        command: String,
+        status: process::ExitStatus,
+    },
+
+    #[error("save trigger cache")]
+    SaveCache(#[source] io::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::client::{EphemeralOptions, Scope};
+
+    #[test]
+    fn fingerprints() {
+        let run = |args: &[&str]| Handler::Run {
+            run: "/usr/bin/ldconfig".to_owned(),
+            args: args.iter().map(ToString::to_string).collect(),
+        };
+        let at = |secs| Input::File(Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs)));
+        let link = |target: &str| Input::Symlink(target.into());
+
+        let base = fingerprint(&run(&[]), [("/usr/lib/a.so", at(1)), ("/usr/lib/b.so", at(2))]);
+
+        // Stable for identical inputs
+        assert_eq!(
+            base,
+            fingerprint(&run(&[]), [("/usr/lib/a.so", at(1)), ("/usr/lib/b.so", at(2))])
+        );
+
+        for changed in [
+            fingerprint(&run(&["-X"]), [("/usr/lib/a.so", at(1)), ("/usr/lib/b.so", at(2))]),
+            fingerprint(&run(&[]), [("/usr/lib/a.so", at(1)), ("/usr/lib/b.so", at(3))]),
+            fingerprint(&run(&[]), [("/usr/lib/a.so", at(1)), ("/usr/lib/b.so", Input::Missing)]),
+            fingerprint(
+                &run(&[]),
+                [("/usr/lib/a.so", at(1)), ("/usr/lib/b.so", Input::Directory)],
+            ),
+            fingerprint(&run(&[]), [("/usr/lib/a.so", at(1)), ("/usr/lib/b.so", link("b.so.2"))]),
+            fingerprint(&run(&[]), [("/usr/lib/a.so", at(1))]),
+            fingerprint(&run(&[]), [("/usr/lib/a.so", at(1)), ("/usr/lib/c.so", at(2))]),
+            fingerprint(&run(&[]), [("/usr/lib/a.so/usr/lib/b.so", at(2))]),
+        ] {
+            assert_ne!(base, changed);
+        }
+        assert_ne!(
+            fingerprint(&run(&[]), [("/usr/lib/b.so", link("b.so.1"))]),
+            fingerprint(&run(&[]), [("/usr/lib/b.so", link("b.so.2"))])
+        );
+
+        let mut cache = Cache {
+            path: Some(PathBuf::from("triggers.json")),
+            ..Cache::default()
+        };
+        assert!(!cache.is_recorded("ldconfig", base));
+        cache.fingerprints.0.insert("ldconfig".to_owned(), base);
+        assert!(cache.is_recorded("ldconfig", base));
+        assert!(!cache.is_recorded("ldconfig", base + 1));
+        assert!(!cache.is_recorded("fc-cache", base));
+    }
+
+    #[test]
+    fn inputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name| dir.path().join(name);
+
+        fs::write(path("a.so.1"), "").unwrap();
+        std::os::unix::fs::symlink("a.so.1", path("a.so")).unwrap();
+        fs::create_dir(path("fonts")).unwrap();
+
+        assert!(matches!(Input::of(&path("a.so.1")), Input::File(Some(_))));
+        assert_eq!(Input::of(&path("a.so")), Input::Symlink("a.so.1".into()));
+        assert_eq!(Input::of(&path("b.so")), Input::Missing);
+
+        // Unchanged by new entries
+        assert_eq!(Input::of(&path("fonts")), Input::Directory);
+        fs::write(path("fonts/new.ttf"), "").unwrap();
+        assert_eq!(Input::of(&path("fonts")), Input::Directory);
+    }
+
+    #[test]
+    fn failed_handlers() {
+        let execute = |run: &str| {
+            let handler = Handler::Run {
+                run: run.to_owned(),
+                args: vec![],
+            };
+            let matched = fnmatch::Match {
+                path: String::new(),
+                variables: BTreeMap::new(),
+            };
+            execute_trigger_directly(&handler.compiled(&matched))
+        };
+
+        assert!(execute("true").is_ok());
+        assert!(matches!(execute("false"), Err(Error::Failed { status, .. }) if status.code() == Some(1)));
+        assert!(matches!(execute("/nonexistent/trigger"), Err(Error::IO(_))));
+    }
+
+    #[test]
+    fn rooted_paths() {
+        let root = tempfile::tempdir().unwrap();
+        let blit_root = tempfile::tempdir().unwrap();
+        let installation = Installation::open(root.path(), None).unwrap();
+
+        let stateful = Scope::Stateful;
+        let ephemeral = Scope::Ephemeral {
+            blit_root: blit_root.path().to_owned(),
+            options: EphemeralOptions::default(),
+        };
+
+        for (scope, expected_root) in [(&stateful, root.path()), (&ephemeral, blit_root.path())] {
+            for trigger_scope in [
+                TriggerScope::Transaction(&installation, scope),
+                TriggerScope::System(&installation, scope),
+            ] {
+                // An alternate root is never the host, so always containerised
+                assert!(!trigger_scope.is_host());
+
+                for path in [
+                    trigger_scope.root_dir(),
+                    trigger_scope.host_path("etc"),
+                    trigger_scope.guest_path("usr"),
+                ] {
+                    assert!(path.starts_with(expected_root), "{path:?} escapes {expected_root:?}");
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,55 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Packages guarded against removal
+//!
+//! Loaded from `protected.d` configs, i.e. `/etc/moss/protected.d/local.yaml`:
+//!
+//! ```yaml
+//! packages:
+//!   - bash
+//!   - moss
+//! ```
+//!
+//! The configured packages are protected alongside [`environment::PROTECTED_PACKAGES`].
+
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Package, Provider, environment};
+
+/// A list of protected package names
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Protected {
+    #[serde(default)]
+    pub packages: BTreeSet<String>,
+}
+
+impl config::Config for Protected {
+    fn domain() -> String {
+        "protected".into()
+    }
+}
+
+/// Union of the vendor default & all configured protected packages
+pub fn load(config: &config::Manager) -> BTreeSet<String> {
+    environment::PROTECTED_PACKAGES
+        .iter()
+        .map(|name| (*name).to_owned())
+        .chain(
+            config
+                .load::<Protected>()
+                .into_iter()
+                .flat_map(|config| config.value.packages),
+        )
+        .collect()
+}
+
+/// Returns `true` if `package` provides any of the `protected` names
+pub fn is_protected(protected: &BTreeSet<String>, package: &Package) -> bool {
+    protected
+        .iter()
+        .filter_map(|name| Provider::from_name(name).ok())
+        .any(|provider| package.meta.providers.contains(&provider))
+}
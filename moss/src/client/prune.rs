@@ -6,8 +6,19 @@
 //! Quite simply this is a strategy based garbage collector for unused/unwanted
 //! system states (i.e. historical snapshots) that cleans up database entries
 //! and assets on disk by way of refcounting.
+//!
+//! Removal is crash-safe: a tombstone listing the states & packages being
+//! removed is written before anything is touched, and cleared once the
+//! archived trees, database rows & orphaned files are gone. A client opening
+//! an installation with a stale tombstone finishes the removal, see
+//! [`recover`].
+//!
+//! Pruning the cache also checks the link count of each asset of the pool, as
+//! assets still hardlinked from elsewhere, i.e. the staging tree of an
+//! interrupted blit, mustn't be removed, see [`verdict`].
 
 use std::collections::{BTreeMap, BTreeSet};
+use std::os::unix::fs::MetadataExt;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::{Duration, Instant};
@@ -16,40 +27,476 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use chrono::{DateTime, TimeDelta, Utc};
 use fs_err as fs;
+use humansize::BINARY;
 use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use tracing::info;
+use tracing::{info, warn};
 use tui::{ProgressBar, ProgressStyle, Styled};
 use tui::{
     dialoguer::{Confirm, theme::ColorfulTheme},
     pretty::autoprint_columns,
 };
 
-use crate::client::boot;
+use crate::client::{Event, boot, stats};
 use crate::util;
-use crate::{Client, Installation, State, client::cache, db, package, repository, state};
+use crate::{Client, Installation, State, client::cache, db, installation, package, repository, state};
 
 /// The prune strategy for removing old states
 #[derive(Debug, Clone, Copy)]
 pub enum Strategy<'a> {
     /// Keep the most recent N states, remove the rest
     KeepRecent { keep: u64, include_newer: bool },
+    /// Remove archived states created longer ago than the provided age.
+    ///
+    /// The active state and the most recently created state are always kept.
+    OlderThan(Duration),
+    /// Apply the wrapped strategy, but keep any state marked as protected
+    KeepTagged(&'a Strategy<'a>),
     /// Removes state(s)
-    Remove(&'a [state::Id]),
+    ///
+    /// The most recently created state is only removed with `force`, while the
+    /// active state never is.
+    Remove { ids: &'a [state::Id], force: bool },
+}
+
+impl Strategy<'_> {
+    /// Resolve the ids of all `states` to be removed by this strategy, relative to
+    /// the `current` (active) state and the point in time `now`
+    fn removal_ids(&self, states: &[State], current: state::Id, now: DateTime<Utc>) -> Vec<state::Id> {
+        match *self {
+            Strategy::KeepRecent { keep, include_newer } => {
+                // Filter for all removal candidates
+                let candidates = states
+                    .iter()
+                    .filter(|state| {
+                        if include_newer {
+                            state.id != current
+                        } else {
+                            state.id < current
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                // Deduct current state from num candidates to keep
+                let candidate_limit = (keep as usize).saturating_sub(1);
+
+                // Calculate how many candidate states over the limit we are
+                let num_to_remove = candidates.len().saturating_sub(candidate_limit);
+
+                // Sort ascending and assign first `num_to_remove` for removal
+                candidates
+                    .into_iter()
+                    .sorted_by_key(|state| state.created)
+                    .take(num_to_remove)
+                    .map(|state| state.id)
+                    .collect()
+            }
+            Strategy::OlderThan(age) => {
+                // An age reaching before the epoch can't match anything
+                let Some(cutoff) = TimeDelta::from_std(age)
+                    .ok()
+                    .and_then(|age| now.checked_sub_signed(age))
+                else {
+                    return vec![];
+                };
+
+                let newest = states
+                    .iter()
+                    .max_by_key(|state| (state.created, state.id))
+                    .map(|state| state.id);
+
+                states
+                    .iter()
+                    .filter(|state| state.id != current && Some(state.id) != newest && state.created < cutoff)
+                    .map(|state| state.id)
+                    .collect()
+            }
+            Strategy::KeepTagged(strategy) => strategy
+                .removal_ids(states, current, now)
+                .into_iter()
+                .filter(|id| states.iter().any(|state| state.id == *id && !state.is_protected()))
+                .collect(),
+            Strategy::Remove { ids, .. } => states
+                .iter()
+                .filter_map(|state| ids.contains(&state.id).then_some(state.id))
+                .collect(),
+        }
+    }
+}
+
+/// Summary of the states and files removed by a prune operation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PruneReport {
+    /// Number of states removed
+    pub removed_states: usize,
+    /// Number of cached stones & unpacked assets removed
+    pub removed_assets: usize,
+    /// Total size of all removed files, in bytes
+    pub bytes: u64,
+    /// Number of unreferenced assets kept as they're still linked from elsewhere
+    pub linked_assets: usize,
+    /// Number of assets referenced by a layout but linked into no tree
+    pub unlinked_assets: usize,
+}
+
+/// Everything to be removed by a prune operation
+///
+/// The plan is computed up-front so a dry run reports exactly
+/// what is removed once the plan is applied.
+struct Plan {
+    /// States to remove
+    states: Vec<State>,
+    /// Packages no longer referenced once `states` are removed
+    packages: Vec<package::Id>,
+    /// Orphaned package stones in the download cache
+    downloads: Orphans,
+    /// Orphaned unpacked assets in the CAS
+    assets: Orphans,
+    /// Every asset of the CAS, as found when planning
+    pool: Vec<PathBuf>,
+    /// Hashes of the assets referenced once `packages` are removed
+    referenced: BTreeSet<String>,
+    /// Assets whose links disagree with the layouts referencing them
+    links: Links,
+}
+
+impl Plan {
+    /// Build a plan removing the provided states & packages from
+    /// the databases, along with any files only they reference
+    fn new(
+        states: Vec<State>,
+        packages: Vec<package::Id>,
+        installation: &Installation,
+        install_db: &db::meta::Database,
+        layout_db: &db::layout::Database,
+    ) -> Result<Self, Error> {
+        let excluded = packages.iter().cloned().collect::<BTreeSet<_>>();
+
+        let downloads_root = installation.cache_path("downloads").join("v1");
+        let downloads = Orphans::find(
+            // all files under root
+            &enumerate_files(&downloads_root)?,
+            downloads_root,
+            // final set of hashes to compare against
+            &install_db.file_hashes_excluding(&excluded)?,
+            // path builder using hash
+            |hash| cache::download_path(installation, &hash).ok(),
+        );
+
+        let pool = enumerate_files(installation.assets_path("v2"))?;
+        let referenced = layout_db.file_hashes_excluding(&excluded)?;
+        let assets = Orphans::find(&pool, installation.assets_path("v2"), &referenced, |hash| {
+            Some(cache::asset_path(installation, &hash))
+        });
+
+        Ok(Self {
+            states,
+            packages,
+            downloads,
+            assets,
+            pool,
+            referenced,
+            links: Links::default(),
+        })
+    }
+
+    /// Check the link count of each asset of the pool, keeping the orphaned
+    /// assets still linked from elsewhere, see [`verdict`]
+    ///
+    /// With `repair`, links of the staging tree are found first, so assets only
+    /// it still links to are removed along with those links.
+    fn check_links(
+        &mut self,
+        installation: &Installation,
+        state_db: &db::state::Database,
+        layout_db: &db::layout::Database,
+        repair: bool,
+    ) -> Result<(), Error> {
+        // Only the assets of packages in a state are linked into a tree
+        let installed = state_db
+            .all()?
+            .into_iter()
+            .flat_map(|state| state.packages())
+            .collect::<BTreeSet<_>>();
+        let installed = layout_db
+            .file_hashes_by_package(&installed)?
+            .into_values()
+            .flatten()
+            .collect::<BTreeSet<_>>();
+        let staging = installation.staging_dir();
+        let staging_links = if repair {
+            links_by_inode(&staging)?
+        } else {
+            BTreeMap::new()
+        };
+
+        for asset in &self.pool {
+            let hash = asset.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+            let metadata = fs::symlink_metadata(asset)?;
+            let nlink = metadata.nlink();
+            let staged = staging_links
+                .get(&(metadata.dev(), metadata.ino()))
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            let in_staging = !staged.is_empty() && staged.len() as u64 == nlink - 1;
+
+            let reference = if installed.contains(hash) {
+                Reference::Installed
+            } else if self.referenced.contains(hash) {
+                Reference::Cached
+            } else {
+                Reference::None
+            };
+
+            match verdict(reference, nlink, in_staging) {
+                Verdict::Keep | Verdict::Cached | Verdict::Remove => {}
+                Verdict::Repair => self.links.staged.extend_from_slice(staged),
+                Verdict::Linked => {
+                    if !staged.is_empty() {
+                        warn!(
+                            "keeping unreferenced asset {asset:?} with {nlink} links, {} of which are in {staging:?}",
+                            staged.len()
+                        );
+                    } else if repair {
+                        warn!("keeping unreferenced asset {asset:?} with {nlink} links outside of {staging:?}");
+                    } else {
+                        warn!(
+                            "keeping unreferenced asset {asset:?} with {nlink} links, likely from an interrupted \
+                             blit in {staging:?}, see `moss cache prune --repair`"
+                        );
+                    }
+                    self.assets.keep(asset);
+                    self.links.linked.push(asset.clone());
+                }
+                Verdict::Unlinked => self.links.unlinked.push(asset.clone()),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn report(&self) -> PruneReport {
+        PruneReport {
+            removed_states: self.states.len(),
+            removed_assets: self.downloads.files.len() + self.assets.files.len(),
+            bytes: self.downloads.bytes + self.assets.bytes,
+            linked_assets: self.links.linked.len(),
+            unlinked_assets: self.links.unlinked.len(),
+        }
+    }
+
+    /// Print a table of the files to be removed and the space reclaimed
+    fn print_summary(&self) {
+        let rows = [
+            ("Stones", self.downloads.files.len().to_string(), self.downloads.bytes),
+            ("Assets", self.assets.files.len().to_string(), self.assets.bytes),
+            ("Total", String::new(), self.downloads.bytes + self.assets.bytes),
+        ];
+        let width = rows.iter().map(|(_, count, _)| count.len()).max().unwrap_or_default();
+
+        for (name, count, bytes) in rows {
+            println!(
+                "{:<8}{count:>width$}  {}",
+                name.bold(),
+                humansize::format_size(bytes, BINARY).dim()
+            );
+        }
+        println!();
+    }
+}
+
+/// Print the assets only each of the `states` references, which pruning it alone reclaims
+fn print_exclusive_usage(states: &[State], usages: &BTreeMap<state::Id, stats::StateUsage>) {
+    let width = states
+        .iter()
+        .map(|state| state.id.to_string().len())
+        .max()
+        .unwrap_or_default();
+
+    println!("Assets exclusive to each state:");
+    println!();
+    for state in states {
+        let usage = usages.get(&state.id).copied().unwrap_or_default();
+        println!(
+            " #{:<width$}  {}",
+            state.id.to_string().bold(),
+            humansize::format_size(usage.exclusive_bytes, BINARY).dim()
+        );
+    }
+    println!();
+}
+
+/// Which layouts reference an asset of the pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reference {
+    /// Of a package in a state
+    Installed,
+    /// Only of packages in no state, i.e. cached from an active repository
+    Cached,
+    None,
+}
+
+/// What becomes of an asset of the pool when pruning the cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verdict {
+    /// Referenced by a layout of an installed package & linked into a tree
+    Keep,
+    /// Only referenced by layouts of packages in no state, so linked nowhere
+    Cached,
+    /// Referenced by no layout & linked nowhere
+    Remove,
+    /// Referenced by no layout & otherwise only linked from the staging tree of
+    /// an interrupted blit, removed along with those links
+    Repair,
+    /// Referenced by no layout but still linked from elsewhere, so kept
+    Linked,
+    /// Referenced by a layout of an installed package but linked into no tree,
+    /// which is reported as the row may be stale
+    Unlinked,
+}
+
+/// The [`Verdict`] on an asset with its `reference` by layouts & `nlink` links
+/// including its own, the others of which are all `in_staging`
+fn verdict(reference: Reference, nlink: u64, in_staging: bool) -> Verdict {
+    match (reference, nlink, in_staging) {
+        (Reference::Installed, 0..=1, _) => Verdict::Unlinked,
+        (Reference::Installed | Reference::Cached, 2.., _) => Verdict::Keep,
+        (Reference::Cached, _, _) => Verdict::Cached,
+        (Reference::None, 0..=1, _) => Verdict::Remove,
+        (Reference::None, _, true) => Verdict::Repair,
+        (Reference::None, _, false) => Verdict::Linked,
+    }
+}
+
+/// Assets of the pool whose links disagree with the layouts referencing them
+#[derive(Debug, Default)]
+struct Links {
+    /// Orphaned assets kept as they're still linked from elsewhere
+    linked: Vec<PathBuf>,
+    /// Referenced assets linked into no tree
+    unlinked: Vec<PathBuf>,
+    /// Links of the staging tree to orphaned assets, removed when repairing
+    staged: Vec<PathBuf>,
+}
+
+/// Paths of the files under `root`, by their device & inode
+fn links_by_inode(root: &Path) -> io::Result<BTreeMap<(u64, u64), Vec<PathBuf>>> {
+    let mut links = BTreeMap::<_, Vec<_>>::new();
+
+    for path in enumerate_files(root)? {
+        let metadata = fs::symlink_metadata(&path)?;
+        links.entry((metadata.dev(), metadata.ino())).or_default().push(path);
+    }
+
+    Ok(links)
+}
+
+/// Files under `root` which are no longer referenced by any package
+struct Orphans {
+    root: PathBuf,
+    /// Path to each orphaned file
+    files: Vec<PathBuf>,
+    /// Size on disk of all orphaned files, including partial downloads
+    bytes: u64,
+}
+
+impl Orphans {
+    /// Find all `files` under `root` that don't exist in the provided `final_hashes` set
+    fn find(
+        files: &[PathBuf],
+        root: PathBuf,
+        final_hashes: &BTreeSet<String>,
+        compute_path: impl Fn(String) -> Option<PathBuf>,
+    ) -> Self {
+        // Compute hashes to remove by (installed - final)
+        let installed_hashes = file_hashes(files);
+
+        let mut orphans = vec![];
+        let mut bytes = 0;
+
+        for hash in installed_hashes.difference(final_hashes) {
+            // Compute path to file using hash
+            let Some(file) = compute_path(hash.clone()) else {
+                continue;
+            };
+
+            bytes += [file.clone(), file.with_added_extension("part")]
+                .iter()
+                .filter_map(|path| fs::symlink_metadata(path).ok())
+                .map(|metadata| metadata.len())
+                .sum::<u64>();
+
+            orphans.push(file);
+        }
+
+        Self {
+            root,
+            files: orphans,
+            bytes,
+        }
+    }
+
+    /// Keep `file`, no longer counting it as orphaned
+    fn keep(&mut self, file: &Path) {
+        let Some(index) = self.files.iter().position(|orphan| orphan == file) else {
+            return;
+        };
+        let file = self.files.remove(index);
+
+        self.bytes -= [file.clone(), file.with_added_extension("part")]
+            .iter()
+            .filter_map(|path| fs::symlink_metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .sum::<u64>();
+    }
+
+    /// Remove each orphaned file and it's parent dir if empty
+    fn remove(&self) -> Result<(), Error> {
+        for file in &self.files {
+            let partial = file.with_added_extension("part");
+
+            // Remove if it exists
+            if file.exists() {
+                fs::remove_file(file)?;
+            }
+
+            // Remove partial file if it exists
+            if partial.exists() {
+                fs::remove_file(&partial)?;
+            }
+
+            // Try to remove leading parent dirs if they're
+            // now empty
+            if let Some(parent) = file.parent() {
+                let _ = remove_empty_dirs(parent, &self.root);
+            }
+        }
+
+        Ok(())
+    }
 }
 
 /// Prune old states using [`Strategy`] and garbage collect
 /// all cached data related to those states being removed
-pub(super) fn prune_states(client: &Client, strategy: Strategy<'_>, yes: bool) -> Result<(), Error> {
+///
+/// When `dry_run` is set, nothing is removed and the returned
+/// [`PruneReport`] describes what would have been removed.
+pub(super) fn prune_states(
+    client: &Client,
+    strategy: Strategy<'_>,
+    yes: bool,
+    dry_run: bool,
+) -> Result<PruneReport, Error> {
     let installation = &client.installation;
     let layout_db = &client.layout_db;
     let state_db = &client.state_db;
     let install_db = &client.install_db;
 
     let mut timing = Timing::default();
-    let mut instant = Instant::now();
+    let instant = Instant::now();
 
     // Only prune if the moss root has an active state (otherwise
     // it's probably borked or not setup yet)
@@ -58,71 +505,52 @@ pub(super) fn prune_states(client: &Client, strategy: Strategy<'_>, yes: bool) -
     };
     let current_state = state_db.get(current_state_id)?;
 
-    let state_ids = state_db.list_ids()?;
+    let states = state_db.all()?;
 
     // Find each state we need to remove
-    let removal_ids = match strategy {
-        Strategy::KeepRecent { keep, include_newer } => {
-            // Filter for all removal candidates
-            let candidates = state_ids
-                .iter()
-                .filter(|(id, _)| {
-                    if include_newer {
-                        *id != current_state.id
-                    } else {
-                        *id < current_state.id
-                    }
-                })
-                .collect::<Vec<_>>();
-            // Deduct current state from num candidates to keep
-            let candidate_limit = (keep as usize).saturating_sub(1);
-
-            // Calculate how many candidate states over the limit we are
-            let num_to_remove = candidates.len().saturating_sub(candidate_limit);
-
-            // Sort ascending and assign first `num_to_remove` as `Status::Remove`
-            candidates
-                .into_iter()
-                .sorted_by_key(|(_, created)| *created)
-                .enumerate()
-                .filter_map(|(idx, (id, _))| if idx < num_to_remove { Some(*id) } else { None })
-                .collect::<Vec<_>>()
-        }
-        Strategy::Remove(remove) => state_ids
-            .iter()
-            .filter_map(|(id, _)| remove.contains(id).then_some(*id))
-            .collect(),
-    };
+    let removal_ids = strategy.removal_ids(&states, current_state.id, Utc::now());
 
     // Bail if there's no states to remove
     if removal_ids.is_empty() {
-        // TODO: Print no states to be removed
-        return Ok(());
+        println!("No states to be removed");
+        return Ok(PruneReport::default());
+    }
+
+    // Removing the most recent state loses the latest changes
+    if let Strategy::Remove { force: false, .. } = strategy
+        && let Some(newest) = states.iter().max_by_key(|state| (state.created, state.id))
+        && removal_ids.contains(&newest.id)
+    {
+        return Err(Error::PruneNewest(newest.id));
     }
 
+    // What each state would reclaim if it was pruned alone
+    let usages = if dry_run {
+        stats::state_usages(&states, layout_db, installation)?
+    } else {
+        BTreeMap::new()
+    };
+
     // Keep track of how many active states are using a package
     let mut packages_counts = BTreeMap::<package::Id, usize>::new();
     let mut removals = vec![];
 
     // Get net refcount of each package in all states
-    for (id, _) in state_ids {
-        // Get metadata
-        let state = state_db.get(id)?;
-
+    for state in states {
         // Increment each package
-        for selection in &state.selections {
-            *packages_counts.entry(selection.package.clone()).or_default() += 1;
+        for package in state.packages() {
+            *packages_counts.entry(package).or_default() += 1;
         }
 
         // Decrement if removal
-        if removal_ids.contains(&id) {
+        if removal_ids.contains(&state.id) {
             // Ensure we're not pruning the active state!!
-            if id == current_state.id {
+            if state.id == current_state.id {
                 return Err(Error::PruneCurrent);
             }
 
-            for selection in &state.selections {
-                *packages_counts.entry(selection.package.clone()).or_default() -= 1;
+            for package in state.packages() {
+                *packages_counts.entry(package).or_default() -= 1;
             }
             removals.push(state);
         }
@@ -136,20 +564,31 @@ pub(super) fn prune_states(client: &Client, strategy: Strategy<'_>, yes: bool) -
         .filter_map(|(pkg, count)| (count == 0).then_some(pkg))
         .collect::<Vec<_>>();
 
+    let plan = Plan::new(removals, package_removals, installation, install_db, layout_db)?;
+
     timing.resolve = instant.elapsed();
     info!(
-        total_resolved_states = removals.len(),
-        total_resolved_packages = package_removals.len(),
+        total_resolved_states = plan.states.len(),
+        total_resolved_packages = plan.packages.len(),
         resolve_time_ms = timing.resolve.as_millis(),
         "Resolved states marked for removal"
     );
-    instant = Instant::now();
 
     // Print out the states to be removed to the user
-    println!("The following state(s) will be removed:");
+    if dry_run {
+        println!("The following state(s) would be removed:");
+    } else {
+        println!("The following state(s) will be removed:");
+    }
     println!();
-    autoprint_columns(&removals.iter().map(state::ColumnDisplay).collect::<Vec<_>>());
+    autoprint_columns(&plan.states.iter().map(state::ColumnDisplay).collect::<Vec<_>>());
     println!();
+    plan.print_summary();
+
+    if dry_run {
+        print_exclusive_usage(&plan.states, &usages);
+        return Ok(plan.report());
+    }
 
     let result = if yes {
         true
@@ -163,44 +602,51 @@ pub(super) fn prune_states(client: &Client, strategy: Strategy<'_>, yes: bool) -
         return Err(Error::Cancelled);
     }
 
-    // Prune these states / packages from all dbs
-    prune_databases(&removals, &package_removals, state_db, install_db, layout_db)?;
+    remove(client, &plan, &mut timing, |_| Ok(()))?;
 
-    timing.prune_db = instant.elapsed();
-    info!(
-        prune_db_time_ms = timing.prune_db.as_millis(),
-        "Pruned stale packages & states from databases"
-    );
-    instant = Instant::now();
+    // Sync boot to ensure pruned states are removed from boot entries
+    boot::synchronize(client, &current_state).map_err(Error::SyncBoot)?;
 
-    // Remove orphaned downloads
-    remove_orphaned_files(
-        // root
-        installation.cache_path("downloads").join("v1"),
-        // final set of hashes to compare against
-        install_db.file_hashes()?,
-        // path builder using hash
-        |hash| cache::download_path(installation, &hash).ok(),
-    )?;
+    // Entries of pruned states beyond those kept by synchronization
+    let live = state_db.list_ids()?.into_iter().map(|(id, _)| id).collect();
+    boot::cleanup(client, &live, false).map_err(Error::CleanupBoot)?;
 
-    // Remove orphaned assets
-    remove_orphaned_files(
-        // root
-        installation.assets_path("v2"),
-        // final set of hashes to compare against
-        layout_db.file_hashes()?,
-        // path builder using hash
-        |hash| Some(cache::asset_path(installation, &hash)),
-    )?;
+    Ok(plan.report())
+}
 
-    timing.orphaned_files = instant.elapsed();
-    info!(
-        orphaned_file_time_ms = timing.orphaned_files.as_millis(),
-        "Removed orphaned files"
-    );
-    instant = Instant::now();
+/// Steps of removing the states of a [`Plan`], in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    /// The tombstone is written
+    Tombstone,
+    /// Archived state trees are removed
+    Archives,
+    /// States & packages are removed from the databases
+    Databases,
+    /// Orphaned downloads & assets are removed
+    Files,
+}
+
+/// Remove the states & packages of `plan`, writing a tombstone first and
+/// clearing it once everything is removed
+///
+/// `checkpoint` is called after each [`Step`], failures interrupting the
+/// removal as a crash would.
+fn remove(
+    client: &Client,
+    plan: &Plan,
+    timing: &mut Timing,
+    mut checkpoint: impl FnMut(Step) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let installation = &client.installation;
 
-    let archive_paths = removals
+    Tombstone::new(&plan.states, &plan.packages).write(installation)?;
+    checkpoint(Step::Tombstone)?;
+
+    let mut instant = Instant::now();
+
+    let archive_paths = plan
+        .states
         .iter()
         .map(|s| installation.root_path(s.id.to_string()))
         .collect::<Vec<_>>();
@@ -221,17 +667,154 @@ pub(super) fn prune_states(client: &Client, strategy: Strategy<'_>, yes: bool) -
         progress = 1.0,
         event_type = "progress_completed",
     );
+    checkpoint(Step::Archives)?;
+    instant = Instant::now();
 
-    // Sync boot to ensure pruned states are removed from boot entries
-    boot::synchronize(client, &current_state).map_err(Error::SyncBoot)?;
+    // Prune these states / packages from all dbs
+    prune_databases(
+        &plan.states,
+        &plan.packages,
+        &client.state_db,
+        &client.install_db,
+        &client.layout_db,
+    )?;
 
-    Ok(())
+    timing.prune_db = instant.elapsed();
+    info!(
+        prune_db_time_ms = timing.prune_db.as_millis(),
+        "Pruned stale packages & states from databases"
+    );
+    checkpoint(Step::Databases)?;
+    instant = Instant::now();
+
+    // Remove orphaned downloads & assets
+    plan.downloads.remove()?;
+    plan.assets.remove()?;
+
+    timing.orphaned_files = instant.elapsed();
+    info!(
+        orphaned_file_time_ms = timing.orphaned_files.as_millis(),
+        "Removed orphaned files"
+    );
+    checkpoint(Step::Files)?;
+
+    Tombstone::clear(installation)
+}
+
+/// Finish a state removal interrupted by a previous invocation, if its
+/// tombstone was left behind
+///
+/// Nothing is done while another process holds the installation lock, as it
+/// may still be removing those states. Boot entries of the removed states
+/// are dropped by the next boot synchronization.
+pub(super) fn recover(client: &Client) -> Result<(), Error> {
+    let installation = &client.installation;
+
+    if !installation.removal_tombstone_path().exists() {
+        return Ok(());
+    }
+    let Some(_lock) = installation.try_lock()? else {
+        return Ok(());
+    };
+    // Read once locked, the holder may have just finished
+    let Some(tombstone) = Tombstone::read(installation)? else {
+        return Ok(());
+    };
+
+    let states = tombstone
+        .states
+        .iter()
+        .copied()
+        .map(state::Id::from)
+        .collect::<Vec<_>>();
+    client.interaction.report(Event::Warning(format!(
+        "finishing the interrupted removal of state(s) {}",
+        states.iter().map(|id| format!("#{id}")).join(", ")
+    )));
+
+    let archive_paths = states
+        .iter()
+        .map(|id| installation.root_path(id.to_string()))
+        .collect::<Vec<_>>();
+    util::par_remove_dirs_all(&archive_paths, |_, _| {})?;
+
+    client.state_db.batch_remove(&states)?;
+
+    // Only packages no remaining state references, as when the removal began
+    let referenced = client
+        .state_db
+        .all()?
+        .into_iter()
+        .flat_map(|state| state.packages())
+        .collect::<BTreeSet<_>>();
+    let packages = tombstone
+        .packages
+        .into_iter()
+        .map(package::Id::from)
+        .filter(|package| !referenced.contains(package))
+        .collect::<Vec<_>>();
+    client.install_db.batch_remove(&packages)?;
+    client.layout_db.batch_remove(&packages)?;
+
+    let plan = Plan::new(vec![], packages, installation, &client.install_db, &client.layout_db)?;
+    plan.downloads.remove()?;
+    plan.assets.remove()?;
+
+    Tombstone::clear(installation)
+}
+
+/// States & packages being removed, see [`Installation::removal_tombstone_path`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Tombstone {
+    states: Vec<i32>,
+    packages: Vec<String>,
+}
+
+impl Tombstone {
+    fn new(states: &[State], packages: &[package::Id]) -> Self {
+        Self {
+            states: states.iter().map(|state| state.id.into()).collect(),
+            packages: packages.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    fn read(installation: &Installation) -> Result<Option<Self>, Error> {
+        match fs::read(installation.removal_tombstone_path()) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes).map_err(io::Error::other)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Atomically replace the tombstone, so it's never read half-written
+    fn write(&self, installation: &Installation) -> Result<(), Error> {
+        let path = installation.removal_tombstone_path();
+        let partial = path.with_added_extension("part");
+
+        let mut file = fs::File::create(&partial)?;
+        serde_json::to_writer(&mut file, self).map_err(io::Error::other)?;
+        file.sync_all()?;
+        fs::rename(&partial, &path)?;
+
+        Ok(())
+    }
+
+    fn clear(installation: &Installation) -> Result<(), Error> {
+        match fs::remove_file(installation.removal_tombstone_path()) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
 }
 
 /// Prune all cached data that isn't related to any states
 /// or active repositories. This will remove all downloaded
 /// stones & unpacked asset data for packages not in that set.
 ///
+/// When `dry_run` is set, nothing is removed and the returned
+/// [`PruneReport`] describes what would have been removed.
+///
 /// # Arguments
 ///
 /// * - `state_db`     - Installation's state database
@@ -239,77 +822,82 @@ pub(super) fn prune_states(client: &Client, strategy: Strategy<'_>, yes: bool) -
 /// * - `layout_db`    - Installation's layout database
 /// * - `installation` - Client specific target filesystem encapsulation
 /// * - `repositories` - All configured repositories
+/// * - `dry_run`      - Only compute the report, without removing anything
+/// * - `repair`       - Also remove links of the staging tree to orphaned assets
 pub(super) fn prune_cache(
     state_db: &db::state::Database,
     install_db: &db::meta::Database,
     layout_db: &db::layout::Database,
     installation: &Installation,
     repositories: &repository::Manager,
-) -> Result<usize, Error> {
-    // Prune all packages from our internal DBs that aren't
-    // part of a state or an active repository
-    {
-        // Packages in all states (active + archived)
-        let state_packages = state_db
-            .all()?
-            .into_iter()
-            .flat_map(|state| state.selections.into_iter().map(|selection| selection.package))
-            .collect::<BTreeSet<_>>();
+    dry_run: bool,
+    repair: bool,
+) -> Result<PruneReport, Error> {
+    let packages = unreferenced_packages(state_db, install_db, layout_db, repositories)?;
 
-        // Packages in all active repos
-        let repo_packages = repositories
-            .active()
-            .map(|repo| repo.db.package_ids())
-            .collect::<Result<Vec<_>, _>>()?
-            .into_iter()
-            .flatten()
-            .collect::<BTreeSet<_>>();
+    // We can then prune "orphaned package artefacts" / packages artefacts
+    // on disk but not defined in our internal dbs
+    let mut plan = Plan::new(vec![], packages, installation, install_db, layout_db)?;
+    plan.check_links(installation, state_db, layout_db, repair)?;
 
-        // Keep state + active repo packages
-        let packages_to_keep = state_packages.into_iter().chain(repo_packages).collect::<BTreeSet<_>>();
+    if dry_run {
+        plan.print_summary();
+        return Ok(plan.report());
+    }
 
-        // Prune packages not in `packages_to_keep` from layout db (layout entries)
-        {
-            let layout_packages = layout_db.package_ids()?;
-            let to_remove = layout_packages.difference(&packages_to_keep);
-            layout_db.batch_remove(to_remove)?;
-        }
+    layout_db.batch_remove(&plan.packages)?;
+    install_db.batch_remove(&plan.packages)?;
 
-        // Prune packages not in `packages_to_keep` from install db (meta entries)
-        {
-            let install_packages = install_db.package_ids()?;
-            let to_remove = install_packages.difference(&packages_to_keep);
-            install_db.batch_remove(to_remove)?;
-        }
+    // Left behind by an interrupted blit, as the staging tree is emptied by each
+    for link in &plan.links.staged {
+        fs::remove_file(link)?;
     }
 
-    let mut num_removed_files = 0;
+    // Remove orphaned downloads (package stones)
+    plan.downloads.remove()?;
+    // Remove orphaned assets (unpacked package assets in CAS)
+    plan.assets.remove()?;
 
-    // Now we can prune "orphaned package artefacts" / packages artefacts
-    // on disk but not defined in our internal dbs
-    {
-        // Remove orphaned downloads (package stones)
-        num_removed_files += remove_orphaned_files(
-            // root
-            installation.cache_path("downloads").join("v1"),
-            // final set of hashes to compare against
-            install_db.file_hashes()?,
-            // path builder using hash
-            |hash| cache::download_path(installation, &hash).ok(),
-        )?;
+    Ok(plan.report())
+}
 
-        // Remove orphaned assets (unpacked package assets in CAS)
-        num_removed_files += remove_orphaned_files(
-            // root
-            installation.assets_path("v2"),
-            // final set of hashes to compare against
-            layout_db.file_hashes()?,
-            // path builder using hash
-            |hash| Some(cache::asset_path(installation, &hash)),
-        )?;
-    }
+/// Returns packages recorded in the install or layout database which aren't
+/// part of any state or active repository
+pub(super) fn unreferenced_packages(
+    state_db: &db::state::Database,
+    install_db: &db::meta::Database,
+    layout_db: &db::layout::Database,
+    repositories: &repository::Manager,
+) -> Result<Vec<package::Id>, Error> {
+    // Packages in all states (active + archived)
+    let state_packages = state_db
+        .all()?
+        .into_iter()
+        .flat_map(|state| state.packages())
+        .collect::<BTreeSet<_>>();
+
+    // Packages in all active repos
+    let repo_packages = repositories
+        .active()
+        .map(|repo| repo.db.package_ids())
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .flatten()
+        .collect::<BTreeSet<_>>();
+
+    // Keep state + active repo packages
+    let packages_to_keep = state_packages.into_iter().chain(repo_packages).collect::<BTreeSet<_>>();
 
-    Ok(num_removed_files)
+    // Packages not in `packages_to_keep` from the layout db (layout entries)
+    // and install db (meta entries)
+    Ok(layout_db
+        .package_ids()?
+        .into_iter()
+        .chain(install_db.package_ids()?)
+        .collect::<BTreeSet<_>>()
+        .difference(&packages_to_keep)
+        .cloned()
+        .collect())
 }
 
 /// Removes the provided states & packages from the databases
@@ -340,51 +928,15 @@ fn prune_databases(
     Ok(())
 }
 
-/// Removes all files under `root` that no longer exist in the provided `final_hashes` set
-fn remove_orphaned_files(
-    root: PathBuf,
-    final_hashes: BTreeSet<String>,
-    compute_path: impl Fn(String) -> Option<PathBuf>,
-) -> Result<usize, Error> {
-    // Compute hashes to remove by (installed - final)
-    let installed_hashes = enumerate_file_hashes(&root)?;
-    let hashes_to_remove = installed_hashes.difference(&final_hashes);
-
-    // Remove each and it's parent dir if empty
-    hashes_to_remove.into_iter().try_fold(0, |acc, hash| {
-        // Compute path to file using hash
-        let Some(file) = compute_path(hash.clone()) else {
-            return Ok(acc);
-        };
-        let partial = file.with_added_extension("part");
-
-        // Remove if it exists
-        if file.exists() {
-            fs::remove_file(&file)?;
-        }
-
-        // Remove partial file if it exists
-        if partial.exists() {
-            fs::remove_file(&partial)?;
-        }
-
-        // Try to remove leading parent dirs if they're
-        // now empty
-        if let Some(parent) = file.parent() {
-            let _ = remove_empty_dirs(parent, &root);
-        }
-
-        Ok(acc + 1)
-    })
-}
-
-/// Returns all nested files under `root` and parses the file name as a hash
-fn enumerate_file_hashes(root: impl AsRef<Path>) -> io::Result<BTreeSet<String>> {
-    let files = enumerate_files(root)?;
-
-    let path_to_hash = |path: PathBuf| path.file_name().and_then(|s| s.to_str()).unwrap_or_default().to_owned();
+/// Parses the file name of each of `files` as a hash
+fn file_hashes(files: &[PathBuf]) -> BTreeSet<String> {
+    // Partial downloads are accounted for alongside their final hash
+    let path_to_hash = |path: &PathBuf| {
+        let name = path.file_name().and_then(|s| s.to_str()).unwrap_or_default();
+        name.strip_suffix(".part").unwrap_or(name).to_owned()
+    };
 
-    Ok(files.into_iter().map(path_to_hash).collect())
+    files.iter().map(path_to_hash).collect()
 }
 
 /// Returns all nested files under `root`
@@ -506,12 +1058,384 @@ pub enum Error {
     NoActiveState,
     #[error("cannot prune the currently active state")]
     PruneCurrent,
+    #[error("state {0} is the most recent state, use --force to remove it")]
+    PruneNewest(state::Id),
     #[error("db")]
     DB(#[from] db::Error),
     #[error("io")]
     Io(#[from] io::Error),
+    #[error("installation")]
+    Installation(#[from] installation::Error),
     #[error("string processing")]
     Dialog(#[from] tui::dialoguer::Error),
     #[error("synchronize boot")]
     SyncBoot(#[source] boot::Error),
+    #[error("clean up boot entries")]
+    CleanupBoot(#[source] boot::Error),
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap()
+    }
+
+    /// States 1..=5, where state `n` was created `10 * (6 - n)` days ago
+    fn states() -> Vec<State> {
+        (1..=5)
+            .map(|id| State {
+                id: state::Id::from(id),
+                summary: None,
+                description: None,
+                selections: vec![],
+                retained: vec![],
+                created: now() - TimeDelta::days(10 * (6 - i64::from(id))),
+                kind: state::Kind::Transaction,
+            })
+            .collect()
+    }
+
+    fn ids(ids: &[i32]) -> Vec<state::Id> {
+        ids.iter().copied().map(state::Id::from).collect()
+    }
+
+    #[test]
+    fn keep_recent() {
+        let states = states();
+        let current = state::Id::from(4);
+
+        let strategy = Strategy::KeepRecent {
+            keep: 2,
+            include_newer: false,
+        };
+        assert_eq!(strategy.removal_ids(&states, current, now()), ids(&[1, 2]));
+
+        let strategy = Strategy::KeepRecent {
+            keep: 2,
+            include_newer: true,
+        };
+        assert_eq!(strategy.removal_ids(&states, current, now()), ids(&[1, 2, 3]));
+    }
+
+    #[test]
+    fn older_than() {
+        let states = states();
+
+        // 1 => 50 days, 2 => 40 days, 3 => 30 days, ...
+        let strategy = Strategy::OlderThan(DAY * 35);
+        assert_eq!(strategy.removal_ids(&states, state::Id::from(5), now()), ids(&[1, 2]));
+
+        // Never remove the active state
+        let strategy = Strategy::OlderThan(DAY * 5);
+        assert_eq!(
+            strategy.removal_ids(&states, state::Id::from(2), now()),
+            ids(&[1, 3, 4])
+        );
+
+        // Never remove the most recent state, even if everything is older
+        let strategy = Strategy::OlderThan(Duration::ZERO);
+        assert_eq!(
+            strategy.removal_ids(&states, state::Id::from(1), now()),
+            ids(&[2, 3, 4])
+        );
+
+        // Cutoff before the epoch matches nothing
+        let strategy = Strategy::OlderThan(Duration::MAX);
+        assert!(strategy.removal_ids(&states, state::Id::from(5), now()).is_empty());
+    }
+
+    #[test]
+    fn keep_tagged() {
+        let mut states = states();
+        states[0].description = Some(format!("before upgrade {}", state::PROTECTED_MARKER));
+        states[2].summary = Some(state::PROTECTED_MARKER.to_owned());
+
+        let inner = Strategy::OlderThan(Duration::ZERO);
+        let strategy = Strategy::KeepTagged(&inner);
+        assert_eq!(strategy.removal_ids(&states, state::Id::from(5), now()), ids(&[2, 4]));
+
+        let inner = Strategy::KeepRecent {
+            keep: 1,
+            include_newer: false,
+        };
+        let strategy = Strategy::KeepTagged(&inner);
+        assert_eq!(strategy.removal_ids(&states, state::Id::from(5), now()), ids(&[2, 4]));
+    }
+
+    #[test]
+    fn remove() {
+        let states = states();
+
+        let remove = ids(&[2, 4, 7]);
+        let strategy = Strategy::Remove {
+            ids: &remove,
+            force: false,
+        };
+        assert_eq!(strategy.removal_ids(&states, state::Id::from(5), now()), ids(&[2, 4]));
+    }
+
+    /// A client of an installation whose active state is 2 of states 1..=3
+    fn client_with_states(root: &Path) -> Client {
+        fs::create_dir_all(root.join("usr")).unwrap();
+        fs::write(root.join("usr").join(".stateID"), "2").unwrap();
+
+        let installation = Installation::open(root, None).unwrap();
+        let client = Client::mocked(installation, crate::registry::Registry::default()).unwrap();
+        for package in ["a", "b", "c"] {
+            let selections = [state::Selection::explicit(package::Id::from(package))];
+            client.state_db.add(&selections, &[], None, None).unwrap();
+        }
+        client
+    }
+
+    #[test]
+    fn remove_guards() {
+        let root = tempfile::tempdir().unwrap();
+        let client = client_with_states(root.path());
+
+        let prune = |remove: &[i32], force| {
+            prune_states(
+                &client,
+                Strategy::Remove {
+                    ids: &ids(remove),
+                    force,
+                },
+                true,
+                false,
+            )
+        };
+
+        assert!(matches!(prune(&[1, 3], false), Err(Error::PruneNewest(id)) if id == state::Id::from(3)));
+        assert!(matches!(prune(&[2], false), Err(Error::PruneCurrent)));
+        assert!(matches!(prune(&[2], true), Err(Error::PruneCurrent)));
+        assert_eq!(client.state_db.all().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn interrupted_removal() {
+        let steps = [Step::Tombstone, Step::Archives, Step::Databases, Step::Files];
+
+        for interrupted in steps.map(Some).into_iter().chain([None]) {
+            let root = tempfile::tempdir().unwrap();
+            let client = client_with_states(root.path());
+            let installation = &client.installation;
+
+            let archive = installation.root_path("1");
+            fs::create_dir_all(archive.join("usr")).unwrap();
+            fs::write(archive.join("usr").join("file"), "").unwrap();
+
+            // Unreferenced by any layout, so orphaned
+            let asset = cache::asset_path(installation, "0123456789abcdef0123456789abcdef");
+            fs::create_dir_all(asset.parent().unwrap()).unwrap();
+            fs::write(&asset, "").unwrap();
+
+            let states = vec![client.state_db.get(state::Id::from(1)).unwrap()];
+            let plan = Plan::new(
+                states,
+                vec![package::Id::from("a")],
+                installation,
+                &client.install_db,
+                &client.layout_db,
+            )
+            .unwrap();
+
+            let result = super::remove(&client, &plan, &mut Timing::default(), |step| {
+                if Some(step) == interrupted {
+                    Err(io::Error::other("interrupted").into())
+                } else {
+                    Ok(())
+                }
+            });
+            assert_eq!(result.is_err(), interrupted.is_some(), "{interrupted:?}");
+            assert_eq!(
+                installation.removal_tombstone_path().exists(),
+                interrupted.is_some(),
+                "{interrupted:?}"
+            );
+
+            recover(&client).unwrap();
+
+            assert!(!installation.removal_tombstone_path().exists(), "{interrupted:?}");
+            assert!(!archive.exists(), "{interrupted:?}");
+            assert!(!asset.exists(), "{interrupted:?}");
+            assert_eq!(
+                client
+                    .state_db
+                    .list_ids()
+                    .unwrap()
+                    .into_iter()
+                    .map(|(id, _)| id)
+                    .collect::<Vec<_>>(),
+                ids(&[2, 3]),
+                "{interrupted:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn prune_cache_dry_run() {
+        let root = tempfile::tempdir().unwrap();
+        let installation = Installation::open(root.path(), None).unwrap();
+        let client = Client::mocked(installation, crate::registry::Registry::default()).unwrap();
+
+        let hash = "0123456789abcdef0123456789abcdef";
+        let asset = cache::asset_path(&client.installation, hash);
+        let download = cache::download_path(&client.installation, hash).unwrap();
+        let partial = download.with_extension("part");
+
+        for (path, len) in [(&asset, 16), (&download, 32), (&partial, 8)] {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, vec![0; len]).unwrap();
+        }
+
+        let prune = |dry_run| {
+            prune_cache(
+                &client.state_db,
+                &client.install_db,
+                &client.layout_db,
+                &client.installation,
+                &client.repositories,
+                dry_run,
+                false,
+            )
+            .unwrap()
+        };
+
+        let expected = PruneReport {
+            removed_states: 0,
+            removed_assets: 2,
+            bytes: 56,
+            ..PruneReport::default()
+        };
+
+        assert_eq!(prune(true), expected);
+        assert!(asset.exists() && download.exists() && partial.exists());
+
+        assert_eq!(prune(false), expected);
+        assert!(!asset.exists() && !download.exists() && !partial.exists());
+
+        assert_eq!(prune(true), PruneReport::default());
+    }
+
+    #[test]
+    fn verdicts() {
+        for reference in [Reference::Installed, Reference::Cached, Reference::None] {
+            for nlink in [1, 2, 5] {
+                for in_staging in [false, true] {
+                    let expected = match (reference, nlink > 1, in_staging) {
+                        (Reference::Installed | Reference::Cached, true, _) => Verdict::Keep,
+                        (Reference::Installed, false, _) => Verdict::Unlinked,
+                        (Reference::Cached, false, _) => Verdict::Cached,
+                        (Reference::None, false, _) => Verdict::Remove,
+                        (Reference::None, true, true) => Verdict::Repair,
+                        (Reference::None, true, false) => Verdict::Linked,
+                    };
+                    assert_eq!(
+                        verdict(reference, nlink, in_staging),
+                        expected,
+                        "{reference:?} {nlink} {in_staging}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn prune_cache_links() {
+        let root = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir_in(root.path()).unwrap();
+        let installation = Installation::open(root.path(), None).unwrap();
+        let client = Client::mocked(installation, crate::registry::Registry::default()).unwrap();
+        let installation = &client.installation;
+
+        // Only `bash` is part of a state
+        let bash = package::Id::from("bash");
+        client
+            .state_db
+            .add(&[state::Selection::explicit(bash.clone())], &[], None, None)
+            .unwrap();
+        let (installed, orphaned, manual, staged, cached) = (1_u128, 2_u128, 3_u128, 4_u128, 5_u128);
+        let regular = |hash, path: &str| stone::StonePayloadLayoutRecord {
+            uid: 0,
+            gid: 0,
+            mode: 0o644,
+            tag: 0,
+            file: stone::StonePayloadLayoutFile::Regular(hash, path.into()),
+        };
+        client.layout_db.add(&bash, &regular(installed, "bin/bash")).unwrap();
+
+        let asset = |hash: u128| {
+            let path = cache::asset_path(installation, &format!("{hash:02x}"));
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, vec![0; 8]).unwrap();
+            path
+        };
+        let link = |asset: &Path, path: PathBuf| {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::hard_link(asset, &path).unwrap();
+            path
+        };
+        // Referenced but never blitted
+        let installed = asset(installed);
+        let orphaned = asset(orphaned);
+        let manual = asset(manual);
+        link(&manual, outside.path().join("manual"));
+        let staged = asset(staged);
+        let staged_link = link(&staged, installation.staging_path("usr/bin/staged"));
+
+        let prune = |dry_run, repair| {
+            prune_cache(
+                &client.state_db,
+                &client.install_db,
+                &client.layout_db,
+                installation,
+                &client.repositories,
+                dry_run,
+                repair,
+            )
+            .unwrap()
+        };
+
+        // Without repairing, links of the staging tree are as any other
+        let report = prune(true, false);
+        assert_eq!(
+            report,
+            PruneReport {
+                removed_assets: 1,
+                bytes: 8,
+                linked_assets: 2,
+                unlinked_assets: 1,
+                ..PruneReport::default()
+            }
+        );
+        assert_eq!(prune(true, true).linked_assets, 1);
+        assert!(orphaned.exists() && staged_link.exists());
+
+        assert_eq!(prune(false, false).removed_assets, 1);
+        assert!(!orphaned.exists());
+        assert!(manual.exists() && staged.exists() && installed.exists());
+
+        let report = prune(false, true);
+        assert_eq!((report.removed_assets, report.linked_assets), (1, 1));
+        assert!(!staged.exists() && !staged_link.exists());
+        assert!(manual.exists() && installed.exists());
+        assert_eq!(prune(true, true).unlinked_assets, 1);
+
+        // Assets of packages in no state, i.e. cached from an active repository, are linked nowhere
+        let vim = package::Id::from("vim");
+        client.layout_db.add(&vim, &regular(cached, "bin/vim")).unwrap();
+        let cached = asset(cached);
+        let mut plan = Plan::new(vec![], vec![], installation, &client.install_db, &client.layout_db).unwrap();
+        plan.check_links(installation, &client.state_db, &client.layout_db, false)
+            .unwrap();
+        assert_eq!(plan.links.unlinked, [installed]);
+        assert_eq!(plan.links.linked, [manual]);
+        assert!(plan.assets.files.is_empty() && cached.exists());
+    }
 }
@@ -0,0 +1,291 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Size limit of the download cache
+//!
+//! Loaded from `cache.d` configs, i.e. `/etc/moss/cache.d/local.yaml`:
+//!
+//! ```yaml
+//! max_size: 2GiB
+//! ```
+//!
+//! Sizes are in bytes, or use a binary `K`, `M`, `G` or `T` suffix. Once
+//! packages are cached, the least recently used stones are evicted until the
+//! downloads fit the quota. Stones of the active state and of the packages
+//! just cached are never evicted, and the asset pool is left untouched.
+
+use std::{collections::BTreeSet, path::PathBuf, time::SystemTime};
+
+use fs_err as fs;
+use serde::{Deserialize, Deserializer, Serialize, de};
+use tracing::info;
+
+use crate::{
+    Client,
+    client::{
+        self,
+        stats::{self, Usage},
+    },
+};
+
+/// Limits of the download cache
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cache {
+    /// Most bytes of stones kept in the download cache
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub max_size: Option<u64>,
+}
+
+impl config::Config for Cache {
+    fn domain() -> String {
+        "cache".into()
+    }
+}
+
+/// The smallest configured quota, if any
+pub fn max_size(config: &config::Manager) -> Option<u64> {
+    config
+        .load::<Cache>()
+        .into_iter()
+        .filter_map(|config| config.value.max_size)
+        .min()
+}
+
+/// A stone in the download cache
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub path: PathBuf,
+    /// Hash of the stone, from its file name
+    pub hash: String,
+    pub bytes: u64,
+    /// When the stone was last read, to evict the least recently used first
+    pub accessed: SystemTime,
+}
+
+/// Pick the `candidates` to evict for the rest to fit `max_size`, least
+/// recently used first
+///
+/// Candidates whose hash is `needed` are never evicted, but count toward the
+/// quota all the same
+pub fn evict(mut candidates: Vec<Candidate>, needed: &BTreeSet<String>, max_size: u64) -> Vec<Candidate> {
+    let mut total = candidates.iter().map(|candidate| candidate.bytes).sum::<u64>();
+
+    candidates.retain(|candidate| !needed.contains(&candidate.hash));
+    candidates.sort_by(|a, b| a.accessed.cmp(&b.accessed).then_with(|| a.path.cmp(&b.path)));
+
+    candidates
+        .into_iter()
+        .take_while(|candidate| {
+            let over = total > max_size;
+            total = total.saturating_sub(candidate.bytes);
+            over
+        })
+        .collect()
+}
+
+/// Evict stones from the download cache of the client's installation until
+/// it fits `max_size`, keeping those of the active state & the `needed` hashes
+pub fn enforce(client: &Client, max_size: u64, mut needed: BTreeSet<String>) -> Result<Usage, client::Error> {
+    if let Some(id) = client.installation.active_state {
+        for package in client.state_db.get(id)?.packages() {
+            if let Some(hash) = client.install_db.get(&package).ok().and_then(|meta| meta.hash) {
+                needed.insert(hash);
+            }
+        }
+    }
+
+    let mut candidates = vec![];
+    stats::walk(
+        &client.installation.cache_path("downloads").join("v1"),
+        &mut |path, metadata| {
+            let hash = path.file_name().and_then(|s| s.to_str()).unwrap_or_default();
+
+            // Partial downloads may still be written to, never evict them
+            if hash.ends_with(".part") {
+                needed.insert(hash.to_owned());
+            }
+
+            candidates.push(Candidate {
+                path: path.to_owned(),
+                hash: hash.to_owned(),
+                bytes: metadata.len(),
+                accessed: metadata.accessed().unwrap_or(SystemTime::UNIX_EPOCH),
+            });
+        },
+    )?;
+
+    let mut evicted = Usage::default();
+    for candidate in evict(candidates, &needed, max_size) {
+        fs::remove_file(&candidate.path)?;
+        evicted.add(candidate.bytes);
+    }
+
+    if evicted.files > 0 {
+        info!(
+            files = evicted.files,
+            bytes = evicted.bytes,
+            max_size,
+            "Evicted least recently used downloads"
+        );
+    }
+
+    Ok(evicted)
+}
+
+fn deserialize_size<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        Text(String),
+    }
+
+    match Option::<Size>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Size::Bytes(bytes)) => Ok(Some(bytes)),
+        Some(Size::Text(text)) => parse_size(&text)
+            .map(Some)
+            .ok_or_else(|| de::Error::custom(format!("invalid size `{text}`"))),
+    }
+}
+
+/// Parse a size such as `512M` or `2GiB` into bytes
+pub fn parse_size(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+
+    let shift = match unit.trim().trim_end_matches("iB").trim_end_matches('B') {
+        "" => 0,
+        "K" | "k" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return None,
+    };
+
+    number.parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs::FileTimes, time::Duration};
+
+    use super::*;
+    use crate::{Installation, Package, client::cache, package, registry::Registry, runtime, state::Selection};
+
+    fn candidate(hash: &str, bytes: u64, accessed: u64) -> Candidate {
+        Candidate {
+            path: PathBuf::from(hash),
+            hash: hash.to_owned(),
+            bytes,
+            accessed: SystemTime::UNIX_EPOCH + Duration::from_secs(accessed),
+        }
+    }
+
+    fn hashes(candidates: &[Candidate]) -> Vec<&str> {
+        candidates.iter().map(|candidate| candidate.hash.as_str()).collect()
+    }
+
+    #[test]
+    fn least_recently_used() {
+        let candidates = vec![
+            candidate("recent", 40, 300),
+            candidate("oldest", 30, 100),
+            candidate("old", 20, 200),
+            candidate("needed", 50, 0),
+        ];
+        let needed = BTreeSet::from(["needed".to_owned()]);
+
+        // 140 bytes in total
+        assert!(evict(candidates.clone(), &needed, 140).is_empty());
+        assert_eq!(hashes(&evict(candidates.clone(), &needed, 139)), ["oldest"]);
+        assert_eq!(hashes(&evict(candidates.clone(), &needed, 90)), ["oldest", "old"]);
+        // Needed stones are kept even if over the quota
+        assert_eq!(
+            hashes(&evict(candidates.clone(), &needed, 0)),
+            ["oldest", "old", "recent"]
+        );
+        assert_eq!(hashes(&evict(candidates, &BTreeSet::new(), 0)).len(), 4);
+    }
+
+    #[test]
+    fn sizes() {
+        let config = |yaml: &str| serde_yaml::from_str::<Cache>(yaml).map(|cache| cache.max_size);
+
+        assert_eq!(config("max_size: 4096").unwrap(), Some(4096));
+        assert_eq!(config("max_size: 512M").unwrap(), Some(512 << 20));
+        assert_eq!(config("max_size: 2GiB").unwrap(), Some(2 << 30));
+        assert_eq!(config("max_size: 1 TB").unwrap(), Some(1 << 40));
+        assert_eq!(config("{}").unwrap(), None);
+        assert!(config("max_size: 2 gigs").is_err());
+        assert!(config("max_size: G").is_err());
+    }
+
+    #[test]
+    fn tiny_quota() {
+        // The first state recorded is active
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("usr")).unwrap();
+        fs::write(root.path().join("usr/.stateID"), "1").unwrap();
+        let installation = Installation::open(root.path(), None).unwrap();
+        let client = Client::mocked(installation, Registry::default()).unwrap();
+
+        let config = root.path().join("etc/moss/cache.d");
+        fs::create_dir_all(&config).unwrap();
+        fs::write(config.join("local.yaml"), "max_size: 100").unwrap();
+        assert_eq!(max_size(&client.config), Some(100));
+
+        let stone = |hash: &str, len: usize, accessed: u64| {
+            let path = cache::download_path(&client.installation, hash).unwrap();
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, vec![0; len]).unwrap();
+            let times = FileTimes::new().set_accessed(SystemTime::UNIX_EPOCH + Duration::from_secs(accessed));
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .file()
+                .set_times(times)
+                .unwrap();
+            path
+        };
+        let active = stone("aaaaa-active", 60, 100);
+        let oldest = stone("bbbbb-oldest", 40, 200);
+        let old = stone("ccccc-old", 40, 300);
+        let recent = stone("ddddd-recent", 20, 400);
+
+        // The stone of the active state is the least recently used
+        let bash = package::Id::from("bash");
+        client
+            .install_db
+            .add(
+                bash.clone(),
+                package::Meta {
+                    hash: Some("aaaaa-active".to_owned()),
+                    ..package::fixture::meta("bash")
+                },
+            )
+            .unwrap();
+        client
+            .state_db
+            .add(&[Selection::explicit(bash)], &[], None, None)
+            .unwrap();
+
+        // Enforced even when nothing is fetched
+        let outcome = runtime::block_on(client.cache_packages::<Package>(&[])).unwrap();
+        assert!(outcome.failed.is_empty());
+
+        assert!(active.exists());
+        assert!(!oldest.exists());
+        assert!(!old.exists());
+        assert!(recent.exists());
+
+        let stats = client.statistics().unwrap();
+        assert_eq!(stats.stones, Usage { files: 2, bytes: 80 });
+        assert_eq!(stats.cache_quota, Some(100));
+    }
+}
@@ -9,17 +9,30 @@ use std::{
 use itertools::{Either, Itertools};
 use thiserror::Error;
 use tracing::{debug, info, instrument, warn};
-use tui::{
-    Styled,
-    dialoguer::{Confirm, theme::ColorfulTheme},
-    pretty::autoprint_columns,
-};
 
-use crate::{Client, Provider, client, db, registry::transaction, state::Selection};
+use crate::{
+    Client, Provider,
+    client::{
+        self,
+        interaction::{Event, Question, Resolution},
+        protected,
+    },
+    db,
+    registry::transaction,
+    state::Selection,
+};
 
 /// Remove a set of packages.
+///
+/// Fails if a protected package would be removed, unless `allow_essential`
 #[instrument(skip(client), fields(ephemeral = client.is_ephemeral()))]
-pub fn remove(client: &mut Client, pkgs: &[&str], yes: bool, simulate: bool) -> Result<Timing, Error> {
+pub fn remove(
+    client: &mut Client,
+    pkgs: &[&str],
+    yes: bool,
+    simulate: bool,
+    allow_essential: bool,
+) -> Result<Timing, Error> {
     let mut timing = Timing::default();
     let mut instant = Instant::now();
 
@@ -38,10 +51,8 @@ pub fn remove(client: &mut Client, pkgs: &[&str], yes: bool, simulate: bool) ->
     });
 
     // Bail if there's packages not installed
-    // TODO: Add error hookups
     if !not_installed.is_empty() {
-        println!("Missing packages in lookup: {not_installed:?}");
-        return Err(Error::NoSuchPackage);
+        return Err(Error::NoSuchPackage(not_installed));
     }
 
     // First resolve a transaction where all requested packages are removed from the install
@@ -98,33 +109,39 @@ pub fn remove(client: &mut Client, pkgs: &[&str], yes: bool, simulate: bool) ->
         );
     }
 
-    println!("The following package(s) will be removed:");
-    println!();
-    autoprint_columns(&removed);
-    println!();
+    // Guard against removing packages the system can't boot or recover without,
+    // whether requested or removed as a dependent
+    let protected = client.protected_packages();
+    let essential = removed
+        .iter()
+        .filter(|package| protected::is_protected(&protected, package))
+        .map(|package| package.meta.name.to_string())
+        .collect::<Vec<_>>();
+    if !essential.is_empty() && !allow_essential {
+        return Err(Error::Protected(essential));
+    }
+
+    client.interaction.report(Event::Resolved(Resolution {
+        removed: removed.clone(),
+        ..Default::default()
+    }));
 
     if simulate {
         return Ok(timing);
     }
 
-    let result = if yes {
-        true
-    } else {
-        Confirm::with_theme(&ColorfulTheme::default())
-            .with_prompt(" Do you wish to continue? ")
-            .default(false)
-            .interact()?
-    };
-    if !result {
+    if !essential.is_empty() && !client.confirm(yes, &Question::RemoveProtected(essential))? {
+        return Err(Error::Cancelled);
+    }
+    if !client.confirm(yes, &Question::Continue)? {
         return Err(Error::Cancelled);
     }
 
     instant = Instant::now();
 
-    // Print each package to stdout
-    for package in &removed {
-        println!("{} {}", "Removed".red(), package.meta.name.as_str().bold());
-    }
+    client.interaction.report(Event::Removed(
+        removed.iter().map(|package| package.meta.name.clone()).collect(),
+    ));
 
     // Map finalized state to a [`Selection`] by referencing
     // it's value from the previous state
@@ -160,7 +177,20 @@ pub fn remove(client: &mut Client, pkgs: &[&str], yes: bool, simulate: bool) ->
     };
 
     // Apply state
-    client.new_state(&new_state_pkgs, "Remove")?;
+    let state = client.new_state(&new_state_pkgs, "Remove")?;
+
+    // Report what was kept of the packages just removed
+    let retained = state
+        .iter()
+        .flat_map(|state| &state.retained)
+        .filter(|retained| removed.iter().any(|package| package.id == retained.package))
+        .flat_map(|retained| &retained.paths)
+        .map(|path| format!("/usr/{path}"))
+        .collect::<Vec<_>>();
+    if !retained.is_empty() {
+        client.interaction.report(Event::Retained(retained));
+    }
+    client.interaction.report(Event::Done);
 
     timing.blit = instant.elapsed();
 
@@ -178,8 +208,11 @@ pub enum Error {
     #[error("cancelled")]
     Cancelled,
 
-    #[error("no such package")]
-    NoSuchPackage,
+    #[error("no such package installed: {}", .0.iter().join(", "))]
+    NoSuchPackage(Vec<Provider>),
+
+    #[error("refusing to remove protected package(s) {}, pass --allow-essential to override", .0.join(", "))]
+    Protected(Vec<String>),
 
     #[error("client")]
     Client(#[from] client::Error),
@@ -192,9 +225,6 @@ pub enum Error {
 
     #[error("io")]
     Io(#[from] std::io::Error),
-
-    #[error("string processing")]
-    Dialog(#[from] tui::dialoguer::Error),
 }
 
 /// Simple timing information for Remove
@@ -203,3 +233,103 @@ pub struct Timing {
     pub resolve: Duration,
     pub blit: Duration,
 }
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use fs_err as fs;
+
+    use super::*;
+    use crate::{
+        Dependency, Installation, Package, Registry, package,
+        registry::{Plugin, plugin},
+    };
+
+    fn package(name: &str, deps: &[&str], explicit: bool) -> Package {
+        let flags = package::Flags::new().with_installed();
+
+        Package {
+            id: package::Id::from(format!("{name}-1")),
+            meta: package::Meta {
+                dependencies: deps.iter().map(|d| Dependency::from_str(d).unwrap()).collect(),
+                ..package::fixture::meta(name)
+            },
+            flags: if explicit { flags.with_explicit() } else { flags },
+        }
+    }
+
+    /// `bash` is protected by default & depends on `readline`
+    fn client(root: &tempfile::TempDir) -> Client {
+        let mut registry = Registry::default();
+        registry.add_plugin(Plugin::Test(plugin::Test::new(
+            1,
+            vec![
+                package("bash", &["name(readline)"], true),
+                package("readline", &[], false),
+                package("nano", &[], true),
+            ],
+        )));
+
+        Client::mocked(Installation::open(root.path(), None).unwrap(), registry).unwrap()
+    }
+
+    fn protected(result: Result<Timing, Error>) -> Vec<String> {
+        match result {
+            Err(Error::Protected(packages)) => packages,
+            Err(err) => panic!("unexpected error {err}"),
+            Ok(_) => panic!("removal of protected packages succeeded"),
+        }
+    }
+
+    #[test]
+    fn direct_removal() {
+        let root = tempfile::tempdir().unwrap();
+        let mut client = client(&root);
+
+        assert_eq!(protected(remove(&mut client, &["bash"], true, true, false)), ["bash"]);
+        assert!(remove(&mut client, &["nano"], true, true, false).is_ok());
+    }
+
+    #[test]
+    fn transitive_removal() {
+        let root = tempfile::tempdir().unwrap();
+        let mut client = client(&root);
+
+        // Removing `readline` takes its dependent `bash` with it
+        assert_eq!(
+            protected(remove(&mut client, &["readline"], true, true, false)),
+            ["bash"]
+        );
+    }
+
+    #[test]
+    fn allow_essential() {
+        let root = tempfile::tempdir().unwrap();
+        let mut client = client(&root);
+
+        assert!(remove(&mut client, &["readline"], true, true, true).is_ok());
+        assert!(remove(&mut client, &["bash"], true, true, true).is_ok());
+    }
+
+    #[test]
+    fn configured_packages() {
+        let root = tempfile::tempdir().unwrap();
+        let mut client = client(&root);
+
+        // A config adds to the vendor default
+        let dir = root.path().join("etc/moss/protected.d");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("local.yaml"), "packages:\n  - nano\n").unwrap();
+
+        assert_eq!(
+            client.protected_packages(),
+            ["bash", "coreutils", "glibc", "moss", "nano", "systemd"]
+                .into_iter()
+                .map(ToOwned::to_owned)
+                .collect::<BTreeSet<_>>()
+        );
+        assert_eq!(protected(remove(&mut client, &["nano"], true, true, false)), ["nano"]);
+        assert_eq!(protected(remove(&mut client, &["bash"], true, true, false)), ["bash"]);
+    }
+}
@@ -0,0 +1,247 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Files kept on disk when the package providing them is removed
+//!
+//! Recipes mark paths with `retain: true`, which boulder records in the
+//! layout tag as [`StonePayloadLayoutRecord::TAG_RETAIN`]. Once such a
+//! package is removed, its retained entries stay part of every new state
+//! until another package provides the same path. Each state records them (see
+//! [`State::retained`]), so they're carried on by later transactions and
+//! reblits of the state.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use stone::StonePayloadLayoutRecord;
+
+use crate::{
+    State, db, package,
+    state::{Retained, Selection},
+};
+
+/// Layout entries along with the package providing each
+pub type Layouts = Vec<(package::Id, StonePayloadLayoutRecord)>;
+
+/// Retained entries of the packages selected in `old` but not in `selections`,
+/// along with those `old` itself retained from packages which aren't selected
+pub fn removed(
+    layout_db: &db::layout::Database,
+    old: &State,
+    selections: &[Selection],
+) -> Result<Vec<Retained>, db::Error> {
+    let selected = selections.iter().map(|s| &s.package).collect::<BTreeSet<_>>();
+    let removed = old
+        .selections
+        .iter()
+        .map(|s| &s.package)
+        .filter(|package| !selected.contains(package))
+        .collect::<Vec<_>>();
+
+    let mut retained = BTreeMap::<package::Id, Vec<String>>::new();
+    for (package, layout) in layout_db.query(removed)? {
+        if layout.is_retained() {
+            retained
+                .entry(package)
+                .or_default()
+                .push(layout.file.target().to_owned());
+        }
+    }
+    for previous in &old.retained {
+        if !selected.contains(&previous.package) {
+            retained
+                .entry(previous.package.clone())
+                .or_default()
+                .extend(previous.paths.iter().cloned());
+        }
+    }
+
+    Ok(retained
+        .into_iter()
+        .map(|(package, mut paths)| {
+            paths.sort();
+            paths.dedup();
+            Retained { package, paths }
+        })
+        .collect())
+}
+
+/// Layouts of the `selections` plus the `retained` entries no selected
+/// package provides, returned along with the entries which remain retained
+pub fn layouts(
+    layout_db: &db::layout::Database,
+    selections: &[Selection],
+    retained: Vec<Retained>,
+) -> Result<(Layouts, Vec<Retained>), db::Error> {
+    let mut layouts = layout_db.query(selections.iter().map(|s| &s.package))?;
+    if retained.is_empty() {
+        return Ok((layouts, retained));
+    }
+
+    let provided = layouts
+        .iter()
+        .map(|(_, layout)| layout.file.target().to_owned())
+        .collect::<BTreeSet<_>>();
+
+    let mut remaining = BTreeMap::<package::Id, Vec<String>>::new();
+    for (package, layout) in layout_db.query(retained.iter().map(|r| &r.package))? {
+        let path = layout.file.target();
+        let is_retained = retained
+            .iter()
+            .any(|r| r.package == package && r.paths.iter().any(|p| p == path));
+
+        if is_retained && !provided.contains(path) {
+            remaining.entry(package.clone()).or_default().push(path.to_owned());
+            layouts.push((package, layout));
+        }
+    }
+
+    let retained = remaining
+        .into_iter()
+        .map(|(package, mut paths)| {
+            paths.sort();
+            Retained { package, paths }
+        })
+        .collect();
+
+    Ok((layouts, retained))
+}
+
+/// Layouts of the selected & retained packages of `state`
+pub fn state_layouts(layout_db: &db::layout::Database, state: &State) -> Result<Layouts, db::Error> {
+    Ok(layouts(layout_db, &state.selections, state.retained.clone())?.0)
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+    use itertools::Itertools;
+    use stone::StonePayloadLayoutFile;
+
+    use super::*;
+    use crate::state;
+
+    fn layout(path: &str, retain: bool) -> StonePayloadLayoutRecord {
+        StonePayloadLayoutRecord {
+            uid: 0,
+            gid: 0,
+            mode: 0o644,
+            tag: if retain {
+                StonePayloadLayoutRecord::TAG_RETAIN
+            } else {
+                0
+            },
+            file: StonePayloadLayoutFile::Regular(0, path.into()),
+        }
+    }
+
+    fn state(packages: &[&'static str], retained: Vec<Retained>) -> State {
+        State {
+            id: state::Id::from(1),
+            summary: None,
+            description: None,
+            selections: selections(packages),
+            retained,
+            created: Utc::now(),
+            kind: state::Kind::Transaction,
+        }
+    }
+
+    fn selections(packages: &[&'static str]) -> Vec<Selection> {
+        packages
+            .iter()
+            .map(|package| Selection::explicit(package::Id::from(*package)))
+            .collect()
+    }
+
+    fn retained(package: &'static str, paths: &[&str]) -> Retained {
+        Retained {
+            package: package::Id::from(package),
+            paths: paths.iter().map(|path| path.to_string()).collect(),
+        }
+    }
+
+    fn database() -> db::layout::Database {
+        let db = db::layout::Database::new(":memory:").unwrap();
+        for (package, layouts) in [
+            ("bash", vec![layout("bin/bash", false)]),
+            (
+                "nano",
+                vec![
+                    layout("bin/nano", false),
+                    layout("share/nano/state", true),
+                    layout("share/nano/history", true),
+                ],
+            ),
+            ("vim", vec![layout("bin/vim", false), layout("share/vim/viminfo", true)]),
+            // Takes over a path retained from nano
+            ("nano-state", vec![layout("share/nano/history", false)]),
+        ] {
+            let id = package::Id::from(package);
+            db.batch_add(layouts.iter().map(|layout| (&id, layout))).unwrap();
+        }
+        db
+    }
+
+    #[test]
+    fn retain_removed() {
+        let db = database();
+        let old = state(&["bash", "nano", "vim"], vec![]);
+
+        // Nothing removed, nothing retained
+        assert!(removed(&db, &old, &old.selections).unwrap().is_empty());
+
+        let retained_nano = retained("nano", &["share/nano/history", "share/nano/state"]);
+        assert_eq!(
+            removed(&db, &old, &selections(&["bash", "vim"])).unwrap(),
+            std::slice::from_ref(&retained_nano)
+        );
+
+        // Entries retained by the old state are carried on, unless reinstalled
+        let old = state(&["bash", "vim"], vec![retained_nano.clone()]);
+        assert_eq!(
+            removed(&db, &old, &selections(&["bash"])).unwrap(),
+            [retained_nano, retained("vim", &["share/vim/viminfo"])]
+        );
+        assert!(
+            removed(&db, &old, &selections(&["bash", "nano", "vim"]))
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn retained_layouts() {
+        let db = database();
+        let paths = |layouts: &[(package::Id, StonePayloadLayoutRecord)]| {
+            layouts
+                .iter()
+                .map(|(package, layout)| format!("{package}:{}", layout.file.target()))
+                .sorted()
+                .collect::<Vec<_>>()
+        };
+
+        let (layouts, remaining) = layouts(
+            &db,
+            &selections(&["bash", "nano-state"]),
+            vec![retained("nano", &["share/nano/history", "share/nano/state"])],
+        )
+        .unwrap();
+
+        // The path provided by a selected package is no longer retained
+        assert_eq!(
+            paths(&layouts),
+            [
+                "bash:bin/bash",
+                "nano-state:share/nano/history",
+                "nano:share/nano/state"
+            ]
+        );
+        assert_eq!(remaining, [retained("nano", &["share/nano/state"])]);
+
+        let state = state(&["bash"], remaining);
+        assert_eq!(
+            paths(&state_layouts(&db, &state).unwrap()),
+            ["bash:bin/bash", "nano:share/nano/state"]
+        );
+    }
+}
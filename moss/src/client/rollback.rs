@@ -0,0 +1,101 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Roll back to a previous state
+
+use crate::{
+    Client, Package, Signal,
+    client::{self, interaction::Event, verify},
+    package, runtime, signal, state,
+};
+
+/// Find the state `steps` before the active one
+pub fn target(client: &Client, steps: usize) -> Result<state::Id, client::Error> {
+    let active = client.installation.active_state.ok_or(client::Error::NoActiveState)?;
+
+    // Pruned states are no longer recorded, so they're skipped over
+    let ids = client
+        .state_db
+        .list_ids()?
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect::<Vec<_>>();
+
+    previous(&ids, active, steps).ok_or(client::Error::NoPreviousState(steps))
+}
+
+/// Activate state `id`, re-blitting its archived tree first if `hard`
+///
+/// Returns the previously active state, which is now archived
+pub fn rollback(client: &Client, id: state::Id, hard: bool) -> Result<state::Id, client::Error> {
+    if client.scope.is_ephemeral() {
+        return Err(client::Error::EphemeralProhibitedOperation);
+    }
+
+    let state = client
+        .state_db
+        .get(id)
+        .map_err(|_| client::Error::StateDoesntExist(id))?;
+
+    if hard {
+        let packages = state
+            .selections
+            .iter()
+            .map(|s| {
+                client.install_db.get(&s.package).map(|meta| Package {
+                    id: s.package.clone(),
+                    meta,
+                    flags: package::Flags::default(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Ensure every asset is available, cached packages are validated & skipped
+        runtime::block_on(client.cache_packages(&packages))?.into_result()?;
+
+        let _guard = signal::ignore([Signal::SIGINT])?;
+
+        let (fstree, stats) = client.blit_state(&state)?;
+        client.interaction.report(Event::BlitFinished(stats));
+        verify::reblit_archived(client, &state, fstree)?;
+    } else if !client.installation.root_path(id.to_string()).join("usr").exists() {
+        return Err(client::Error::StateArchiveMissing(id));
+    }
+
+    client.activate_state(id, false, false)
+}
+
+/// The state `steps` before `active` within the ordered `ids`
+fn previous(ids: &[state::Id], active: state::Id, steps: usize) -> Option<state::Id> {
+    ids.iter()
+        .filter(|id| **id < active)
+        .rev()
+        .nth(steps.checked_sub(1)?)
+        .copied()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ids(ids: &[i32]) -> Vec<state::Id> {
+        ids.iter().copied().map(state::Id::from).collect()
+    }
+
+    #[test]
+    fn previous_state() {
+        let active = state::Id::from(7);
+
+        // States 3, 5 & 6 have been pruned
+        let states = ids(&[1, 2, 4, 7, 8]);
+
+        assert_eq!(previous(&states, active, 1), Some(state::Id::from(4)));
+        assert_eq!(previous(&states, active, 2), Some(state::Id::from(2)));
+        assert_eq!(previous(&states, active, 3), Some(state::Id::from(1)));
+        assert_eq!(previous(&states, active, 4), None);
+        assert_eq!(previous(&states, active, 0), None);
+
+        // Oldest state has nothing to roll back to
+        assert_eq!(previous(&states, state::Id::from(1), 1), None);
+    }
+}
@@ -110,7 +110,7 @@ pub fn self_upgrade(client: &mut Client, simulate: bool) -> Result<(), Error> {
     };
 
     // Cache new moss
-    runtime::block_on(client.cache_packages(slice::from_ref(new_moss)).in_current_span())?;
+    runtime::block_on(client.cache_packages(slice::from_ref(new_moss)).in_current_span())?.into_result()?;
 
     // Calculate the new state of packages (prev_state - prev_moss + new_moss)
     let new_state_pkgs = {
@@ -0,0 +1,378 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Disk usage statistics of an installation
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io,
+    path::{Path, PathBuf},
+};
+
+use fs_err as fs;
+
+use crate::{
+    Client, Installation, State,
+    client::{self, cache, prune, quota},
+    db, state,
+};
+
+/// Databases of the installation, by file name
+const DATABASES: &[&str] = &["install", "state", "layout"];
+
+/// Number & total size of a set of files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+impl Usage {
+    pub(super) fn add(&mut self, bytes: u64) {
+        self.files += 1;
+        self.bytes += bytes;
+    }
+}
+
+/// Disk usage of an installation, see [`Client::statistics`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Recorded states, including the active state
+    pub states: usize,
+    /// States with an archived tree on disk
+    pub archived_states: usize,
+    /// Downloaded stones, including partial downloads
+    pub stones: Usage,
+    /// Most bytes of stones kept, see [`quota`]
+    pub cache_quota: Option<u64>,
+    /// Unpacked assets in the content addressable store
+    pub assets: Usage,
+    /// Stones `moss cache prune` would remove
+    pub orphaned_stones: Usage,
+    /// Assets `moss cache prune` would remove
+    pub orphaned_assets: Usage,
+    /// Size of each database, including its write-ahead log
+    pub databases: Vec<(String, u64)>,
+}
+
+/// Asset pool usage of a state, see [`Client::state_disk_usage`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StateUsage {
+    /// Size of the assets also referenced by other states
+    pub shared_bytes: u64,
+    /// Size of the assets only this state references, which pruning it reclaims
+    pub exclusive_bytes: u64,
+}
+
+/// Compute the [`StateUsage`] of each of the `states`, relative to each other
+///
+/// Assets missing from the pool count as empty
+pub fn state_usages(
+    states: &[State],
+    layout_db: &db::layout::Database,
+    installation: &Installation,
+) -> Result<BTreeMap<state::Id, StateUsage>, db::Error> {
+    let packages = states
+        .iter()
+        .flat_map(|state| state.selections.iter().map(|selection| &selection.package))
+        .collect::<BTreeSet<_>>();
+    let package_hashes = layout_db.file_hashes_by_package(packages)?;
+
+    let state_hashes = states
+        .iter()
+        .map(|state| {
+            let hashes = state
+                .selections
+                .iter()
+                .filter_map(|selection| package_hashes.get(&selection.package))
+                .flatten()
+                .collect::<BTreeSet<_>>();
+            (state.id, hashes)
+        })
+        .collect::<Vec<_>>();
+
+    // Number of states referencing each asset
+    let mut references = BTreeMap::<&String, usize>::new();
+    for hash in state_hashes.iter().flat_map(|(_, hashes)| hashes) {
+        *references.entry(hash).or_default() += 1;
+    }
+
+    let sizes = references
+        .keys()
+        .map(|hash| {
+            let size = fs::metadata(cache::asset_path(installation, hash))
+                .map(|metadata| metadata.len())
+                .unwrap_or_default();
+            (*hash, size)
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    Ok(state_hashes
+        .into_iter()
+        .map(|(id, hashes)| {
+            let mut usage = StateUsage::default();
+            for hash in hashes {
+                if references[hash] == 1 {
+                    usage.exclusive_bytes += sizes[hash];
+                } else {
+                    usage.shared_bytes += sizes[hash];
+                }
+            }
+            (id, usage)
+        })
+        .collect())
+}
+
+/// Compute the [`Stats`] of the client's installation
+///
+/// Orphans are detected the same way as [`Client::prune_cache`], without removing anything
+pub fn statistics(client: &Client) -> Result<Stats, client::Error> {
+    let installation = &client.installation;
+
+    let states = client.state_db.list_ids()?;
+    let archived_states = states
+        .iter()
+        .filter(|(id, _)| installation.root_path(id.to_string()).join("usr").exists())
+        .count();
+
+    let excluded = prune::unreferenced_packages(
+        &client.state_db,
+        &client.install_db,
+        &client.layout_db,
+        &client.repositories,
+    )?
+    .into_iter()
+    .collect::<BTreeSet<_>>();
+
+    let (stones, orphaned_stones) = usage(
+        installation.cache_path("downloads").join("v1"),
+        &client.install_db.file_hashes_excluding(&excluded)?,
+    )?;
+    let (assets, orphaned_assets) = usage(
+        installation.assets_path("v2"),
+        &client.layout_db.file_hashes_excluding(&excluded)?,
+    )?;
+
+    let databases = DATABASES
+        .iter()
+        .map(|name| Ok((name.to_string(), installation.db_size(name)?)))
+        .collect::<io::Result<_>>()?;
+
+    Ok(Stats {
+        states: states.len(),
+        archived_states,
+        stones,
+        cache_quota: quota::max_size(&client.config),
+        assets,
+        orphaned_stones,
+        orphaned_assets,
+        databases,
+    })
+}
+
+/// Usage of all files under `root`, and of those whose hash isn't `referenced`
+fn usage(root: PathBuf, referenced: &BTreeSet<String>) -> io::Result<(Usage, Usage)> {
+    let mut total = Usage::default();
+    let mut orphaned = Usage::default();
+
+    walk(&root, &mut |path, metadata| {
+        let bytes = metadata.len();
+
+        // Partial downloads are accounted for alongside their final hash
+        let name = path.file_name().and_then(|s| s.to_str()).unwrap_or_default();
+        let hash = name.strip_suffix(".part").unwrap_or(name);
+
+        total.add(bytes);
+        if !referenced.contains(hash) {
+            orphaned.add(bytes);
+        }
+    })?;
+
+    Ok((total, orphaned))
+}
+
+/// Call `f` with the path & metadata of each file nested under `dir`, without
+/// collecting them up front
+pub(super) fn walk(dir: &Path, f: &mut impl FnMut(&Path, &std::fs::Metadata)) -> io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+
+        if file_type.is_dir() {
+            walk(&entry.path(), f)?;
+        } else if file_type.is_file() {
+            f(&entry.path(), &entry.metadata()?);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use stone::{StonePayloadLayoutFile, StonePayloadLayoutRecord};
+
+    use super::*;
+    use crate::{Installation, client::cache, package, registry::Registry, state::Selection};
+
+    #[test]
+    fn seeded_installation() {
+        let root = tempfile::tempdir().unwrap();
+        let installation = Installation::open(root.path(), None).unwrap();
+        let client = Client::mocked(installation, Registry::default()).unwrap();
+
+        let kept = package::Id::from("bash");
+        let dropped = package::Id::from("nano");
+        let kept_hash = 0x0123_4567_89ab_cdef_0123_4567_89ab_cdef_u128;
+        let dropped_hash = 0xfedc_ba98_7654_3210_fedc_ba98_7654_3210_u128;
+
+        // Only `bash` is part of a state
+        let state = client
+            .state_db
+            .add(&[Selection::explicit(kept.clone())], &[], None, None)
+            .unwrap();
+        fs::create_dir_all(client.installation.root_path(state.id.to_string()).join("usr")).unwrap();
+        client.state_db.add(&[], &[], None, None).unwrap();
+
+        let layout = |hash, path: &str| StonePayloadLayoutRecord {
+            uid: 0,
+            gid: 0,
+            mode: 0o644,
+            tag: 0,
+            file: StonePayloadLayoutFile::Regular(hash, path.into()),
+        };
+        client
+            .layout_db
+            .batch_add([
+                (&kept, &layout(kept_hash, "bin/bash")),
+                (&dropped, &layout(dropped_hash, "bin/nano")),
+            ])
+            .unwrap();
+
+        let write = |path: PathBuf, len| {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, vec![0; len]).unwrap();
+        };
+        write(cache::asset_path(&client.installation, &format!("{kept_hash:02x}")), 64);
+        write(
+            cache::asset_path(&client.installation, &format!("{dropped_hash:02x}")),
+            16,
+        );
+        let stone = cache::download_path(&client.installation, "0123456789abcdef").unwrap();
+        write(stone.with_added_extension("part"), 8);
+
+        // Mocked databases are in memory, stand in for one on disk
+        write(client.installation.db_path("install"), 4096);
+        write(client.installation.db_path("install-wal"), 512);
+
+        let stats = statistics(&client).unwrap();
+
+        assert_eq!(stats.states, 2);
+        assert_eq!(stats.archived_states, 1);
+        assert_eq!(stats.assets, Usage { files: 2, bytes: 80 });
+        assert_eq!(stats.orphaned_assets, Usage { files: 1, bytes: 16 });
+        assert_eq!(stats.stones, Usage { files: 1, bytes: 8 });
+        assert_eq!(stats.orphaned_stones, Usage { files: 1, bytes: 8 });
+        assert_eq!(
+            stats.databases,
+            vec![
+                ("install".to_owned(), 4608),
+                ("state".to_owned(), 0),
+                ("layout".to_owned(), 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn overlapping_states() {
+        let root = tempfile::tempdir().unwrap();
+        let installation = Installation::open(root.path(), None).unwrap();
+        let client = Client::mocked(installation, Registry::default()).unwrap();
+
+        let bash = package::Id::from("bash");
+        let nano = package::Id::from("nano");
+        let vim = package::Id::from("vim");
+        let (bash_hash, nano_hash, vim_hash, libc_hash) = (1_u128, 2_u128, 3_u128, 4_u128);
+
+        let layout = |hash, path: &str| StonePayloadLayoutRecord {
+            uid: 0,
+            gid: 0,
+            mode: 0o644,
+            tag: 0,
+            file: StonePayloadLayoutFile::Regular(hash, path.into()),
+        };
+        // Every package ships the same library, `nano` & `vim` share a file
+        client
+            .layout_db
+            .batch_add([
+                (&bash, &layout(bash_hash, "bin/bash")),
+                (&bash, &layout(libc_hash, "lib/libc.so")),
+                (&nano, &layout(nano_hash, "bin/nano")),
+                (&nano, &layout(vim_hash, "share/editor")),
+                (&nano, &layout(libc_hash, "lib/libc.so")),
+                (&vim, &layout(vim_hash, "bin/vim")),
+                (&vim, &layout(libc_hash, "lib/libc.so")),
+            ])
+            .unwrap();
+
+        for (hash, len) in [(bash_hash, 64), (nano_hash, 16), (vim_hash, 32), (libc_hash, 128)] {
+            let path = cache::asset_path(&client.installation, &format!("{hash:02x}"));
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, vec![0; len]).unwrap();
+        }
+
+        let first = client
+            .state_db
+            .add(
+                &[Selection::explicit(bash.clone()), Selection::explicit(nano.clone())],
+                &[],
+                None,
+                None,
+            )
+            .unwrap();
+        let second = client
+            .state_db
+            .add(
+                &[Selection::explicit(bash.clone()), Selection::explicit(vim.clone())],
+                &[],
+                None,
+                None,
+            )
+            .unwrap();
+        let empty = client.state_db.add(&[], &[], None, None).unwrap();
+
+        let usages = state_usages(&client.state_db.all().unwrap(), &client.layout_db, &client.installation).unwrap();
+
+        assert_eq!(
+            usages[&first.id],
+            StateUsage {
+                shared_bytes: 64 + 32 + 128,
+                exclusive_bytes: 16,
+            }
+        );
+        assert_eq!(
+            usages[&second.id],
+            StateUsage {
+                shared_bytes: 64 + 32 + 128,
+                exclusive_bytes: 0,
+            }
+        );
+        assert_eq!(usages[&empty.id], StateUsage::default());
+
+        // Alone, a state pins all its assets exclusively
+        let usages = state_usages(std::slice::from_ref(&second), &client.layout_db, &client.installation).unwrap();
+        assert_eq!(
+            usages[&second.id],
+            StateUsage {
+                shared_bytes: 0,
+                exclusive_bytes: 64 + 32 + 128,
+            }
+        );
+    }
+}
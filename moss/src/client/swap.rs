@@ -0,0 +1,421 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Swap the staged `/usr` tree with the live one
+//!
+//! `renameat2(RENAME_EXCHANGE)` swaps both trees in a single atomic step, however
+//! some filesystems (older overlayfs, certain network mounts) reject it. There we fall
+//! back to three plain renames via a temporary sibling directory. A marker records the
+//! swap in progress so if we're interrupted, the next moss invocation can finish it.
+
+use std::{
+    ffi::OsStr,
+    io::{self, Write},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use fs_err as fs;
+use nix::{
+    NixPath,
+    errno::Errno,
+    libc::{AT_FDCWD, EINVAL, ENOSYS, RENAME_EXCHANGE, SYS_renameat2, syscall},
+};
+use thiserror::Error;
+use tracing::{info, warn};
+
+/// Swap `source` with `target`, atomically if the filesystem supports it
+pub fn exchange(source: &Path, target: &Path) -> Result<(), Error> {
+    swap(&System, source, target).map_err(|e| Error::Swap(e, source.to_owned(), target.to_owned()))
+}
+
+/// Finish a fallback swap of `target` interrupted by a previous invocation
+pub fn recover(target: &Path) -> Result<(), Error> {
+    recover_with(&System, target).map_err(|e| Error::Recover(e, target.to_owned()))
+}
+
+/// Returns `true` if a fallback swap of `target` was left unfinished
+pub fn is_pending(target: &Path) -> bool {
+    marker_path(target).exists()
+}
+
+/// Temporary sibling holding the old `target` tree during a fallback swap
+fn temp_path(target: &Path) -> PathBuf {
+    sibling(target, "moss-swap")
+}
+
+/// Marker recording a fallback swap of `target` is in progress
+fn marker_path(target: &Path) -> PathBuf {
+    sibling(target, "moss-swap.pending")
+}
+
+fn sibling(target: &Path, suffix: &str) -> PathBuf {
+    let mut name = target.file_name().unwrap_or_default().to_owned();
+    name.push(".");
+    name.push(suffix);
+    target.with_file_name(name)
+}
+
+fn parent(path: &Path) -> &Path {
+    path.parent().unwrap_or(Path::new("/"))
+}
+
+/// Progress of a fallback swap, as recorded by the marker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    /// Marker written, nothing has been moved yet
+    Started,
+    /// `target` has been moved aside, the remaining steps are
+    /// determined by which paths exist
+    Moved,
+}
+
+impl Phase {
+    fn as_str(self) -> &'static str {
+        match self {
+            Phase::Started => "started",
+            Phase::Moved => "moved",
+        }
+    }
+}
+
+struct Marker {
+    phase: Phase,
+    source: PathBuf,
+}
+
+impl Marker {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = format!("{}\n", self.phase.as_str()).into_bytes();
+        bytes.extend_from_slice(self.source.as_os_str().as_bytes());
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed swap marker");
+
+        let split = bytes.iter().position(|b| *b == b'\n').ok_or_else(invalid)?;
+        let (phase, source) = (&bytes[..split], &bytes[split + 1..]);
+
+        let phase = match phase {
+            b"started" => Phase::Started,
+            b"moved" => Phase::Moved,
+            _ => return Err(invalid()),
+        };
+        if source.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            phase,
+            source: PathBuf::from(OsStr::from_bytes(source)),
+        })
+    }
+}
+
+/// Filesystem operations used by the swap, abstracted so the
+/// fallback can be exercised without an unsupported filesystem
+trait Ops {
+    /// Atomically exchange `a` with `b`
+    fn exchange(&self, a: &Path, b: &Path) -> io::Result<()>;
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+    fn exists(&self, path: &Path) -> io::Result<bool>;
+    /// Durably write `contents` to `path`
+    fn write_marker(&self, path: &Path, contents: &[u8]) -> io::Result<()>;
+    fn read_marker(&self, path: &Path) -> io::Result<Option<Vec<u8>>>;
+    fn remove_marker(&self, path: &Path) -> io::Result<()>;
+    /// Flush directory entries of `path` to disk
+    fn sync_dir(&self, path: &Path) -> io::Result<()>;
+}
+
+fn swap(ops: &impl Ops, source: &Path, target: &Path) -> io::Result<()> {
+    match ops.exchange(source, target) {
+        Err(error) if matches!(error.raw_os_error(), Some(EINVAL | ENOSYS)) => {
+            warn!(
+                ?target,
+                "filesystem doesn't support atomic exchange, falling back to a non-atomic swap"
+            );
+            fallback(ops, source, target)
+        }
+        result => result,
+    }
+}
+
+fn fallback(ops: &impl Ops, source: &Path, target: &Path) -> io::Result<()> {
+    let marker = Marker {
+        phase: Phase::Started,
+        source: source.to_owned(),
+    };
+    ops.write_marker(&marker_path(target), &marker.encode())?;
+
+    finish(ops, marker, target)
+}
+
+fn recover_with(ops: &impl Ops, target: &Path) -> io::Result<()> {
+    let Some(bytes) = ops.read_marker(&marker_path(target))? else {
+        return Ok(());
+    };
+    let marker = Marker::decode(&bytes)?;
+
+    info!(?target, source = ?marker.source, "finishing interrupted swap");
+
+    finish(ops, marker, target)
+}
+
+/// Drive the fallback swap to completion from whichever step it reached
+///
+/// Each rename is synced before the next step, so at any point both trees are
+/// complete at one of `source`, `target` or the temporary path.
+fn finish(ops: &impl Ops, marker: Marker, target: &Path) -> io::Result<()> {
+    let Marker { phase, source } = marker;
+    let temp = temp_path(target);
+    let marker = marker_path(target);
+
+    let in_flight = ops.exists(&temp)?;
+
+    // Once moved with nothing left aside, only the marker remains
+    if phase == Phase::Moved && !in_flight {
+        ops.remove_marker(&marker)?;
+        return ops.sync_dir(parent(target));
+    }
+
+    // 1. Move the live tree aside
+    if !in_flight {
+        ops.rename(target, &temp)?;
+        ops.sync_dir(parent(target))?;
+    }
+    if phase == Phase::Started {
+        let moved = Marker {
+            phase: Phase::Moved,
+            source: source.clone(),
+        };
+        ops.write_marker(&marker, &moved.encode())?;
+    }
+
+    // 2. Promote the new tree
+    if !ops.exists(target)? {
+        ops.rename(&source, target)?;
+        ops.sync_dir(parent(&source))?;
+        ops.sync_dir(parent(target))?;
+    }
+
+    // 3. Move the old tree into the vacated source
+    ops.rename(&temp, &source)?;
+    ops.sync_dir(parent(target))?;
+    ops.sync_dir(parent(&source))?;
+
+    ops.remove_marker(&marker)?;
+    ops.sync_dir(parent(target))
+}
+
+/// The real filesystem
+struct System;
+
+impl Ops for System {
+    /// syscall based wrapper for renameat2 so we can support musl libc which
+    /// unfortunately does not expose the API.
+    /// largely modelled on existing renameat2 API in nix crae
+    fn exchange(&self, a: &Path, b: &Path) -> io::Result<()> {
+        let result = a.with_nix_path(|old| {
+            b.with_nix_path(|new| unsafe {
+                syscall(
+                    SYS_renameat2,
+                    AT_FDCWD,
+                    old.as_ptr(),
+                    AT_FDCWD,
+                    new.as_ptr(),
+                    RENAME_EXCHANGE,
+                )
+            })
+        })?? as i32;
+        Ok(Errno::result(result).map(drop)?)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn exists(&self, path: &Path) -> io::Result<bool> {
+        path.try_exists()
+    }
+
+    fn write_marker(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        let mut file = fs::File::create(path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        self.sync_dir(parent(path))
+    }
+
+    fn read_marker(&self, path: &Path) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(path) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn remove_marker(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn sync_dir(&self, path: &Path) -> io::Result<()> {
+        fs::File::open(path)?.sync_all()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("swap {1:?} with {2:?}")]
+    Swap(#[source] io::Error, PathBuf, PathBuf),
+    #[error("recover interrupted swap of {1:?}")]
+    Recover(#[source] io::Error, PathBuf),
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        cell::{Cell, RefCell},
+        collections::BTreeMap,
+    };
+
+    use super::*;
+
+    /// In-memory filesystem of labelled trees, which rejects atomic exchange
+    /// and can simulate a crash after a number of mutating operations
+    #[derive(Default)]
+    struct Mock {
+        trees: RefCell<BTreeMap<PathBuf, &'static str>>,
+        markers: RefCell<BTreeMap<PathBuf, Vec<u8>>>,
+        crash_after: Cell<Option<usize>>,
+    }
+
+    impl Mock {
+        fn new(crash_after: Option<usize>) -> Self {
+            let mock = Self::default();
+            mock.trees.borrow_mut().insert("/usr".into(), "old");
+            mock.trees.borrow_mut().insert("/.moss/staging/usr".into(), "new");
+            mock.crash_after.set(crash_after);
+            mock
+        }
+
+        fn mutate(&self) -> io::Result<()> {
+            match self.crash_after.get() {
+                Some(0) => Err(io::Error::other("crashed")),
+                Some(n) => {
+                    self.crash_after.set(Some(n - 1));
+                    Ok(())
+                }
+                None => Ok(()),
+            }
+        }
+
+        fn tree(&self, path: &str) -> Option<&'static str> {
+            self.trees.borrow().get(Path::new(path)).copied()
+        }
+    }
+
+    impl Ops for Mock {
+        fn exchange(&self, _a: &Path, _b: &Path) -> io::Result<()> {
+            Err(Errno::EINVAL.into())
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+            self.mutate()?;
+            let mut trees = self.trees.borrow_mut();
+            if trees.contains_key(to) {
+                return Err(Errno::ENOTEMPTY.into());
+            }
+            let tree = trees.remove(from).ok_or(io::ErrorKind::NotFound)?;
+            trees.insert(to.to_owned(), tree);
+            Ok(())
+        }
+
+        fn exists(&self, path: &Path) -> io::Result<bool> {
+            Ok(self.trees.borrow().contains_key(path) || self.markers.borrow().contains_key(path))
+        }
+
+        fn write_marker(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+            self.mutate()?;
+            self.markers.borrow_mut().insert(path.to_owned(), contents.to_owned());
+            Ok(())
+        }
+
+        fn read_marker(&self, path: &Path) -> io::Result<Option<Vec<u8>>> {
+            Ok(self.markers.borrow().get(path).cloned())
+        }
+
+        fn remove_marker(&self, path: &Path) -> io::Result<()> {
+            self.mutate()?;
+            self.markers.borrow_mut().remove(path).ok_or(io::ErrorKind::NotFound)?;
+            Ok(())
+        }
+
+        fn sync_dir(&self, _path: &Path) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn assert_swapped(mock: &Mock) {
+        assert_eq!(mock.tree("/usr"), Some("new"));
+        assert_eq!(mock.tree("/.moss/staging/usr"), Some("old"));
+        assert_eq!(mock.tree("/usr.moss-swap"), None);
+        assert!(mock.markers.borrow().is_empty());
+    }
+
+    #[test]
+    fn fallback_swap() {
+        let mock = Mock::new(None);
+
+        swap(&mock, Path::new("/.moss/staging/usr"), Path::new("/usr")).unwrap();
+
+        assert_swapped(&mock);
+    }
+
+    #[test]
+    fn recover_after_crash() {
+        // Marker, rename aside, marker, promote, restore & marker removal
+        const STEPS: usize = 6;
+
+        for crash_after in 0..STEPS {
+            let mock = Mock::new(Some(crash_after));
+
+            swap(&mock, Path::new("/.moss/staging/usr"), Path::new("/usr")).unwrap_err();
+
+            // Both complete trees survive the crash
+            let mut trees = mock.trees.borrow().values().copied().collect::<Vec<_>>();
+            trees.sort();
+            assert_eq!(trees, ["new", "old"], "crashed after {crash_after} operations");
+
+            mock.crash_after.set(None);
+            recover_with(&mock, Path::new("/usr")).unwrap();
+
+            if crash_after == 0 {
+                // Never started, nothing to recover
+                assert_eq!(mock.tree("/usr"), Some("old"));
+                assert_eq!(mock.tree("/.moss/staging/usr"), Some("new"));
+                assert!(mock.markers.borrow().is_empty());
+            } else {
+                assert_swapped(&mock);
+            }
+        }
+
+        // Runs to completion without crashing
+        let mock = Mock::new(Some(STEPS));
+        swap(&mock, Path::new("/.moss/staging/usr"), Path::new("/usr")).unwrap();
+        assert_swapped(&mock);
+    }
+
+    #[test]
+    fn marker_roundtrip() {
+        let marker = Marker {
+            phase: Phase::Moved,
+            source: "/.moss/staging/usr".into(),
+        };
+        let decoded = Marker::decode(&marker.encode()).unwrap();
+
+        assert_eq!(decoded.phase, Phase::Moved);
+        assert_eq!(decoded.source, marker.source);
+        assert!(Marker::decode(b"unknown\n/usr").is_err());
+        assert!(Marker::decode(b"started\n").is_err());
+    }
+}
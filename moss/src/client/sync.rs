@@ -7,22 +7,87 @@ use std::{
 };
 
 use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tracing::{Instrument, debug, info, info_span};
-use tui::{
-    dialoguer::{Confirm, theme::ColorfulTheme},
-    pretty::autoprint_columns,
-};
 
 use crate::{
-    Client, Package, Provider, client, db, package,
+    Client, Package, Provider, ProviderGlob,
+    client::{
+        self,
+        install::{Conflict, find_conflicts},
+        interaction::{Event, Question, Resolution},
+    },
+    db, dependency, package,
     registry::transaction,
     runtime,
     state::Selection,
     system_model::{self, LoadedSystemModel},
 };
 
-pub fn sync(client: &Client, yes: bool, simulate: bool) -> Result<Timing, Error> {
+/// Sync settings, loaded from `sync.d` configs, i.e. `/etc/moss/sync.d/local.yaml`:
+///
+/// ```yaml
+/// exclude:
+///   - mesa*
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Config {
+    /// Names of packages kept at their installed release, see [`Exclusions`]
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+impl config::Config for Config {
+    fn domain() -> String {
+        "sync".into()
+    }
+}
+
+/// Globs over names of packages a sync keeps at their installed release,
+/// as if temporarily pinned
+///
+/// Excluded packages which aren't installed can still be added as new
+/// dependencies.
+#[derive(Debug, Clone, Default)]
+pub struct Exclusions(Vec<ProviderGlob>);
+
+impl Exclusions {
+    /// Exclude packages with names matching any of `patterns`, where `*`
+    /// matches any run of characters & `?` any single character
+    pub fn new(patterns: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        Self(
+            patterns
+                .into_iter()
+                .map(|pattern| ProviderGlob::new(pattern.as_ref()))
+                .collect(),
+        )
+    }
+
+    /// `patterns` along with those of all `sync.d` configs
+    fn load(config: &config::Manager, patterns: &[String]) -> Self {
+        let configured = config
+            .load::<Config>()
+            .into_iter()
+            .flat_map(|config| config.value.exclude);
+
+        Self::new(patterns.iter().cloned().chain(configured))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn matches(&self, name: &package::Name) -> bool {
+        let provider = Provider {
+            kind: dependency::Kind::PackageName,
+            name: name.to_string(),
+        };
+        self.0.iter().any(|glob| glob.matches(&provider))
+    }
+}
+
+pub fn sync(client: &Client, yes: bool, simulate: bool, exclude: &[String]) -> Result<Timing, Error> {
     let mut timing = Timing::default();
     let mut instant = Instant::now();
 
@@ -33,11 +98,8 @@ pub fn sync(client: &Client, yes: bool, simulate: bool) -> Result<Timing, Error>
     let installed = client.registry.list_installed().collect::<Vec<_>>();
 
     // Resolve the final state of packages after considering sync updates
-    let finalized = if let Some(system_model) = &system_model {
-        resolve_with_system_model(client, system_model)?
-    } else {
-        resolve_with_installed(client, &installed)?
-    };
+    let exclusions = Exclusions::load(&client.config, exclude);
+    let finalized = resolve(client, system_model.as_ref(), &installed, &exclusions)?;
     debug!(count = finalized.len(), "Full package list after sync");
     for package in &finalized {
         debug!(
@@ -56,28 +118,14 @@ pub fn sync(client: &Client, yes: bool, simulate: bool) -> Result<Timing, Error>
         "Package resolution completed"
     );
 
-    // Synced are packages are:
-    //
-    // Stateful: Not installed
-    // Ephemeral: All
-    let synced = finalized
-        .iter()
-        .filter(|p| client.is_ephemeral() || !installed.iter().any(|i| i.id == p.id))
-        .collect::<Vec<_>>();
-    let (added, updated): (Vec<_>, Vec<_>) = synced.iter().partition_map(|p| {
-        if let Some(i) = installed.iter().find(|i| i.meta.name == p.meta.name)
-            && !client.is_ephemeral()
-        {
-            itertools::Either::Right(package::Update { old: i, new: p })
-        } else {
-            itertools::Either::Left(*p)
-        }
-    });
-    let removed = installed
-        .iter()
-        .filter(|p| !client.is_ephemeral() && !finalized.iter().any(|f| f.meta.name == p.meta.name))
-        .cloned()
-        .collect::<Vec<_>>();
+    let changes = Changes::new(&installed, &finalized, client.is_ephemeral());
+    let Changes {
+        added,
+        updated,
+        removed,
+        ..
+    } = &changes;
+    let synced = changes.synced();
 
     info!(
         added_packages = added.len(),
@@ -86,44 +134,18 @@ pub fn sync(client: &Client, yes: bool, simulate: bool) -> Result<Timing, Error>
         "Sync analysis completed"
     );
 
-    if synced.is_empty() && removed.is_empty() {
-        println!("No packages to sync");
-        return Ok(timing);
+    let conflicts = changes.conflicts();
+    if !conflicts.is_empty() {
+        return Err(Error::Conflicts(conflicts));
     }
 
-    if !added.is_empty() {
-        println!("The following packages will be added: ");
-        println!();
-        autoprint_columns(added.as_slice());
-        println!();
-    }
-    if !updated.is_empty() {
-        println!("The following packages will be updated: ");
-        println!();
-        autoprint_columns(updated.as_slice());
-        println!();
-    }
-    if !removed.is_empty() {
-        println!("The following orphaned packages will be removed: ");
-        println!();
-        autoprint_columns(removed.as_slice());
-        println!();
-    }
+    client.interaction.report(Event::Resolved(changes.resolution()));
 
-    if simulate {
+    if changes.is_empty() || simulate {
         return Ok(timing);
     }
 
-    // Must we prompt?
-    let result = if yes {
-        true
-    } else {
-        Confirm::with_theme(&ColorfulTheme::default())
-            .with_prompt(" Do you wish to continue? ")
-            .default(false)
-            .interact()?
-    };
-    if !result {
+    if !client.confirm(yes, &Question::Continue)? {
         return Err(Error::Cancelled);
     }
 
@@ -137,7 +159,8 @@ pub fn sync(client: &Client, yes: bool, simulate: bool) -> Result<Timing, Error>
         event_type = "progress_start"
     );
 
-    runtime::block_on(client.cache_packages(&synced).in_current_span())?;
+    // Every synced package is part of the new state, so any failure aborts
+    runtime::block_on(client.cache_packages(&synced).in_current_span())?.into_result()?;
 
     timing.fetch = instant.elapsed();
     info!(
@@ -203,6 +226,7 @@ pub fn sync(client: &Client, yes: bool, simulate: bool) -> Result<Timing, Error>
 
     // Perfect, apply state.
     client.new_state(&new_selections, "Sync")?;
+    client.interaction.report(Event::Done);
 
     timing.blit = instant.elapsed();
 
@@ -215,12 +239,224 @@ pub fn sync(client: &Client, yes: bool, simulate: bool) -> Result<Timing, Error>
     Ok(timing)
 }
 
+/// Returns the changes a sync would apply, without fetching or blitting anything
+pub fn pending(client: &Client, exclude: &[String]) -> Result<Vec<Pending>, Error> {
+    let installed = client.registry.list_installed().collect::<Vec<_>>();
+
+    let exclusions = Exclusions::load(&client.config, exclude);
+    let finalized = resolve(
+        client,
+        client.installation.system_model.as_ref(),
+        &installed,
+        &exclusions,
+    )?;
+
+    let changes = Changes::new(&installed, &finalized, client.is_ephemeral());
+
+    let origin = |package: &Package| client.package_repository(&package.id).map(|id| id.to_string());
+
+    let added = changes.added.iter().map(|new| Pending {
+        name: new.meta.name.to_string(),
+        kind: PendingKind::Add,
+        installed: None,
+        candidate: Some(version_release(new)),
+        repository: origin(new),
+    });
+    let updated = changes.updated.iter().map(|update| Pending {
+        name: update.new.meta.name.to_string(),
+        kind: PendingKind::Update,
+        installed: Some(version_release(update.old)),
+        candidate: Some(version_release(update.new)),
+        repository: origin(update.new),
+    });
+    let removed = changes.removed.iter().map(|old| Pending {
+        name: old.meta.name.to_string(),
+        kind: PendingKind::Remove,
+        installed: Some(version_release(old)),
+        candidate: None,
+        repository: None,
+    });
+
+    Ok(added
+        .chain(updated)
+        .chain(removed)
+        .sorted_by(|a, b| a.name.cmp(&b.name))
+        .collect())
+}
+
+fn version_release(package: &Package) -> String {
+    format!("{}-{}", package.meta.version_identifier, package.meta.source_release)
+}
+
+/// Packages added, updated & removed by a sync relative to the installed set
+pub(super) struct Changes<'a> {
+    pub(super) added: Vec<&'a Package>,
+    pub(super) updated: Vec<package::Update<'a>>,
+    pub(super) removed: Vec<&'a Package>,
+    /// Installed packages the changes apply to, none when ephemeral
+    installed: &'a [Package],
+}
+
+impl<'a> Changes<'a> {
+    pub(super) fn new(installed: &'a [Package], finalized: &'a [Package], ephemeral: bool) -> Self {
+        // Synced are packages are:
+        //
+        // Stateful: Not installed
+        // Ephemeral: All
+        let (added, updated) = finalized
+            .iter()
+            .filter(|p| ephemeral || !installed.iter().any(|i| i.id == p.id))
+            .partition_map(|p| {
+                if let Some(i) = installed.iter().find(|i| i.meta.name == p.meta.name)
+                    && !ephemeral
+                {
+                    itertools::Either::Right(package::Update { old: i, new: p })
+                } else {
+                    itertools::Either::Left(p)
+                }
+            });
+        let removed = installed
+            .iter()
+            .filter(|p| !ephemeral && !finalized.iter().any(|f| f.meta.name == p.meta.name))
+            .collect();
+
+        Self {
+            added,
+            updated,
+            removed,
+            installed: if ephemeral { &[] } else { installed },
+        }
+    }
+
+    /// Conflicts between the synced packages and those which remain installed
+    pub(super) fn conflicts(&self) -> Vec<Conflict> {
+        let installed = self.installed.iter().collect::<Vec<_>>();
+        let removed = self.removed.iter().map(|p| p.id.clone()).collect();
+
+        find_conflicts(&self.synced(), &installed, &removed)
+    }
+
+    /// Packages which need to be fetched & blitted
+    pub(super) fn synced(&self) -> Vec<&'a Package> {
+        self.added
+            .iter()
+            .copied()
+            .chain(self.updated.iter().map(|update| update.new))
+            .collect()
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+
+    /// The changes to report for confirmation
+    pub(super) fn resolution(&self) -> Resolution {
+        Resolution {
+            added: self.added.iter().map(|p| (*p).clone()).collect(),
+            updated: self
+                .updated
+                .iter()
+                .map(|update| (update.old.clone(), update.new.clone()))
+                .collect(),
+            removed: self.removed.iter().map(|p| (*p).clone()).collect(),
+            unchanged: vec![],
+        }
+    }
+}
+
+/// Returns the resolved package set after sync, keeping the installed release
+/// of packages matching `exclusions`
+fn resolve(
+    client: &Client,
+    system_model: Option<&LoadedSystemModel>,
+    installed: &[Package],
+    exclusions: &Exclusions,
+) -> Result<Vec<Package>, Error> {
+    let resolve = |pinned: &[&Package]| match system_model {
+        Some(system_model) => resolve_with_system_model(client, system_model, pinned),
+        None => resolve_with_installed(client, installed, pinned),
+    };
+
+    let finalized = resolve(&[])?;
+
+    if exclusions.is_empty() {
+        return Ok(finalized);
+    }
+
+    // Pin only the excluded packages the sync would change, so those it
+    // would remove still are
+    let pinned = installed
+        .iter()
+        .filter(|i| {
+            exclusions.matches(&i.meta.name) && finalized.iter().any(|f| f.meta.name == i.meta.name && f.id != i.id)
+        })
+        .collect::<Vec<_>>();
+
+    let finalized = if pinned.is_empty() {
+        finalized
+    } else {
+        resolve(&pinned)?
+    };
+
+    // A newer release still resolved was required by a dependency, or an entry of
+    // the system model, the pinned one lacks
+    for pin in &pinned {
+        let Some(newer) = finalized
+            .iter()
+            .find(|f| f.meta.name == pin.meta.name && f.id != pin.id)
+        else {
+            continue;
+        };
+        let only_newer =
+            |provider: &Provider| newer.meta.providers.contains(provider) && !pin.meta.providers.contains(provider);
+
+        if let Some((dependent, provider)) = finalized.iter().find_map(|dependent| {
+            dependent
+                .meta
+                .dependencies
+                .iter()
+                .map(|dependency| Provider {
+                    kind: dependency.kind,
+                    name: dependency.name.clone(),
+                })
+                .find(only_newer)
+                .map(|provider| (dependent.meta.name.clone(), provider))
+        }) {
+            return Err(Error::ExcludedDependency {
+                excluded: pin.meta.name.clone(),
+                dependent,
+                provider,
+            });
+        }
+
+        if let Some(entry) = system_model.and_then(|model| model.packages.iter().find(|provider| only_newer(provider)))
+        {
+            return Err(Error::ExcludedModelEntry {
+                excluded: pin.meta.name.clone(),
+                entry: entry.clone(),
+            });
+        }
+    }
+
+    for package in finalized
+        .iter()
+        .filter(|p| exclusions.matches(&p.meta.name) && !installed.iter().any(|i| i.meta.name == p.meta.name))
+    {
+        client.interaction.report(Event::Warning(format!(
+            "{} is excluded from sync, but added as a new dependency",
+            package.meta.name
+        )));
+    }
+
+    Ok(finalized)
+}
+
 /// Returns the resolved package set w/ sync'd changes swapped in using
-/// the provided installed `packages`
+/// the provided installed `packages`, keeping the `pinned` ones
 ///
 /// Used to sync in "implicit" mode, where the active state is the source of truth
 #[tracing::instrument(skip_all)]
-fn resolve_with_installed(client: &Client, packages: &[Package]) -> Result<Vec<Package>, Error> {
+fn resolve_with_installed(client: &Client, packages: &[Package], pinned: &[&Package]) -> Result<Vec<Package>, Error> {
     let all_ids = packages.iter().map(|p| &p.id).collect::<BTreeSet<_>>();
 
     // For each explicit package, replace it w/ it's sync'd change (if available)
@@ -232,6 +468,10 @@ fn resolve_with_installed(client: &Client, packages: &[Package]) -> Result<Vec<P
                 return None;
             }
 
+            if pinned.iter().any(|pin| pin.id == p.id) {
+                return Some(p.id.clone());
+            }
+
             // Get first available = use highest priority
             if let Some(lookup) = client
                 .registry
@@ -248,8 +488,9 @@ fn resolve_with_installed(client: &Client, packages: &[Package]) -> Result<Vec<P
 
     // Build a new tx from this sync'd package set
     let mut tx = client.registry.transaction(transaction::Lookup::PreferAvailable)?;
-    // Add all explicit packages to build the final tx state
-    tx.add(with_sync)?;
+    // Add all explicit packages to build the final tx state, pinned ones first
+    // so dependencies on them resolve to the pinned release
+    tx.add(pinned.iter().map(|p| p.id.clone()).chain(with_sync).collect())?;
 
     // Resolve the tx
     Ok(client.resolve_packages(tx.finalize())?)
@@ -258,14 +499,23 @@ fn resolve_with_installed(client: &Client, packages: &[Package]) -> Result<Vec<P
 /// Returns the resolved package set based on the packages defined in the system model
 ///
 /// System model is the source of truth here vs "implicit" mode which relies on the active
-/// state + configured repos as the source of truth
+/// state + configured repos as the source of truth. The `pinned` installed packages are
+/// kept over available ones.
 #[tracing::instrument(skip_all)]
-fn resolve_with_system_model(client: &Client, system_model: &LoadedSystemModel) -> Result<Vec<Package>, Error> {
+fn resolve_with_system_model(
+    client: &Client,
+    system_model: &LoadedSystemModel,
+    pinned: &[&Package],
+) -> Result<Vec<Package>, Error> {
     // Lookup the available package for each
     let packages = system_model
         .packages
         .iter()
         .map(|provider| {
+            if let Some(pin) = pinned.iter().find(|pin| pin.meta.providers.contains(provider)) {
+                return Ok(pin.id.clone());
+            }
+
             client
                 .registry
                 .by_provider_id_only(provider, package::Flags::default().with_available())
@@ -276,12 +526,33 @@ fn resolve_with_system_model(client: &Client, system_model: &LoadedSystemModel)
 
     // Add them to a transaction that only resolves transitives from available repositories
     let mut tx = client.registry.transaction(transaction::Lookup::AvailableOnly)?;
-    tx.add(packages)?;
+    tx.add(pinned.iter().map(|p| p.id.clone()).chain(packages).collect())?;
 
     // Resolve the tx
     Ok(client.resolve_packages(tx.finalize())?)
 }
 
+/// A single package change a sync would apply
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Pending {
+    pub name: String,
+    pub kind: PendingKind,
+    /// Installed version-release, if any
+    pub installed: Option<String>,
+    /// Candidate version-release, if any
+    pub candidate: Option<String>,
+    /// Repository providing the candidate
+    pub repository: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PendingKind {
+    Add,
+    Update,
+    Remove,
+}
+
 /// Simple timing information for Sync
 #[derive(Default)]
 pub struct Timing {
@@ -295,6 +566,23 @@ pub enum Error {
     #[error("Package defined in system-model does not exist in any repository: {0}")]
     MissingSystemModelPackage(Provider),
 
+    #[error(
+        "{excluded} is excluded from sync, but {dependent} depends on {provider} which only a newer {excluded} provides"
+    )]
+    ExcludedDependency {
+        excluded: package::Name,
+        dependent: package::Name,
+        provider: Provider,
+    },
+
+    #[error(
+        "{excluded} is excluded from sync, but the system model lists {entry} which only a newer {excluded} provides"
+    )]
+    ExcludedModelEntry { excluded: package::Name, entry: Provider },
+
+    #[error("conflicting packages: {}", .0.iter().join("; "))]
+    Conflicts(Vec<Conflict>),
+
     #[error("cancelled")]
     Cancelled,
 
@@ -304,9 +592,6 @@ pub enum Error {
     #[error("db")]
     DB(#[from] db::Error),
 
-    #[error("string processing")]
-    Dialog(#[from] tui::dialoguer::Error),
-
     #[error("transaction")]
     Transaction(#[from] transaction::Error),
 
@@ -316,3 +601,264 @@ pub enum Error {
     #[error("load system model")]
     LoadSystemModel(#[from] system_model::LoadError),
 }
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::{
+        Dependency, Installation,
+        registry::{Plugin, Registry, plugin},
+    };
+
+    fn package(id: &'static str, version: &str, release: u64, deps: &[&str], flags: package::Flags) -> Package {
+        let name = id.split('-').next().unwrap();
+
+        Package {
+            id: package::Id::from(id),
+            meta: package::Meta {
+                version_identifier: version.to_owned(),
+                source_release: release,
+                dependencies: deps.iter().map(|d| Dependency::from_str(d).unwrap()).collect(),
+                ..package::fixture::meta(name)
+            },
+            flags,
+        }
+    }
+
+    fn installed() -> package::Flags {
+        package::Flags::new().with_installed()
+    }
+
+    fn available() -> package::Flags {
+        package::Flags::new().with_available()
+    }
+
+    fn registry() -> Registry {
+        let mut registry = Registry::default();
+
+        registry.add_plugin(Plugin::Test(plugin::Test::new(
+            1,
+            vec![
+                package("a-1", "1.0", 1, &["name(c)"], installed().with_explicit()),
+                package("b-1", "2.0", 1, &[], installed().with_explicit().with_available()),
+                package("c-1", "1.0", 1, &[], installed()),
+                package("a-2", "1.1", 2, &["name(d)"], available()),
+                package("d-1", "1.0", 1, &[], available()),
+            ],
+        )));
+
+        registry
+    }
+
+    #[test]
+    fn classify() {
+        let registry = registry();
+        let installed = registry.list_installed().collect::<Vec<_>>();
+        let finalized = ["a-2", "b-1", "d-1"]
+            .into_iter()
+            .map(|id| registry.by_id(&package::Id::from(id)).next().unwrap())
+            .collect::<Vec<_>>();
+
+        let ids = |packages: &[&Package]| packages.iter().map(|p| p.id.to_string()).sorted().collect::<Vec<_>>();
+
+        let changes = Changes::new(&installed, &finalized, false);
+        assert_eq!(ids(&changes.added), ["d-1"]);
+        assert_eq!(
+            changes
+                .updated
+                .iter()
+                .map(|u| (u.old.id.to_string(), u.new.id.to_string()))
+                .collect::<Vec<_>>(),
+            [("a-1".to_owned(), "a-2".to_owned())]
+        );
+        assert_eq!(ids(&changes.removed), ["c-1"]);
+        assert_eq!(ids(&changes.synced()), ["a-2", "d-1"]);
+
+        // Ephemeral blits everything fresh
+        let changes = Changes::new(&installed, &finalized, true);
+        assert_eq!(ids(&changes.added), ["a-2", "b-1", "d-1"]);
+        assert!(changes.updated.is_empty());
+        assert!(changes.removed.is_empty());
+    }
+
+    #[test]
+    fn sync_conflicts() {
+        let conflicting = |id, conflicts: &str| {
+            let mut package = package(id, "1.0", 1, &[], available());
+            package.meta.conflicts = [Provider::from_str(conflicts).unwrap()].into();
+            package
+        };
+        let installed = vec![
+            package("a-1", "1.0", 1, &[], installed()),
+            package("b-1", "1.0", 1, &[], installed()),
+        ];
+        let conflict = |package: &str, with: &str, installed| Conflict {
+            package: package::Name::from(package.to_owned()),
+            with: package::Name::from(with.to_owned()),
+            provider: Provider::from_str(&format!("name({with})")).unwrap(),
+            installed,
+        };
+
+        // Conflicts with a package which stays installed
+        let finalized = vec![
+            installed[0].clone(),
+            installed[1].clone(),
+            conflicting("c-1", "name(a)"),
+        ];
+        let changes = Changes::new(&installed, &finalized, false);
+        assert_eq!(changes.conflicts(), [conflict("c", "a", true)]);
+
+        // Nothing conflicts once the sync removes it
+        let finalized = vec![installed[1].clone(), conflicting("c-1", "name(a)")];
+        assert!(Changes::new(&installed, &finalized, false).conflicts().is_empty());
+
+        // Or between synced packages themselves
+        let finalized = vec![
+            conflicting("c-1", "name(d)"),
+            package("d-1", "1.0", 1, &[], available()),
+        ];
+        let changes = Changes::new(&installed, &finalized, false);
+        assert_eq!(changes.conflicts(), [conflict("c", "d", false)]);
+    }
+
+    #[test]
+    fn pending_changes() {
+        let root = tempfile::tempdir().unwrap();
+        let installation = Installation::open(root.path(), None).unwrap();
+        let client = Client::mocked(installation, registry()).unwrap();
+
+        let pending = pending(&client, &[]).unwrap();
+
+        let change = |name: &str, kind, installed: Option<&str>, candidate: Option<&str>| Pending {
+            name: name.to_owned(),
+            kind,
+            installed: installed.map(str::to_owned),
+            candidate: candidate.map(str::to_owned),
+            repository: None,
+        };
+
+        assert_eq!(
+            pending,
+            [
+                change("a", PendingKind::Update, Some("1.0-1"), Some("1.1-2")),
+                change("c", PendingKind::Remove, Some("1.0-1"), None),
+                change("d", PendingKind::Add, None, Some("1.0-1")),
+            ]
+        );
+    }
+
+    /// Names & versions of the packages a sync of `registry` resolves, excluding `exclude`
+    fn resolved(registry: Registry, exclude: &[&str]) -> Result<(Vec<String>, Vec<String>), Error> {
+        let root = tempfile::tempdir().unwrap();
+        let installation = Installation::open(root.path(), None).unwrap();
+        let headless = client::interaction::Headless::new(true);
+        let client = Client::mocked(installation, registry)
+            .unwrap()
+            .with_interaction(headless.clone());
+
+        let installed = client.registry.list_installed().collect::<Vec<_>>();
+        let finalized = resolve(&client, None, &installed, &Exclusions::new(exclude))?;
+
+        let warnings = headless
+            .events()
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::Warning(warning) => Some(warning),
+                _ => None,
+            })
+            .collect();
+
+        Ok((finalized.iter().map(|p| p.id.to_string()).sorted().collect(), warnings))
+    }
+
+    #[test]
+    fn exclusions() {
+        let (ids, warnings) = resolved(registry(), &[]).unwrap();
+        assert_eq!(ids, ["a-2", "b-1", "d-1"]);
+        assert!(warnings.is_empty());
+
+        // Pinned at the installed release, along with its dependencies
+        for pattern in ["a", "a*", "?", "*"] {
+            let (ids, warnings) = resolved(registry(), &[pattern]).unwrap();
+            assert_eq!(ids, ["a-1", "b-1", "c-1"], "{pattern}");
+            assert!(warnings.is_empty());
+        }
+
+        // Packages that aren't changed are unaffected
+        let (ids, _) = resolved(registry(), &["b*", "x*"]).unwrap();
+        assert_eq!(ids, ["a-2", "b-1", "d-1"]);
+
+        // Excluded new dependencies are still added
+        let (ids, warnings) = resolved(registry(), &["d"]).unwrap();
+        assert_eq!(ids, ["a-2", "b-1", "d-1"]);
+        assert_eq!(warnings, ["d is excluded from sync, but added as a new dependency"]);
+    }
+
+    #[test]
+    fn exclusion_conflicts() {
+        let with_provider = |mut package: Package, provider: &str| {
+            package.meta.providers.insert(Provider::from_str(provider).unwrap());
+            package
+        };
+
+        let mut registry = Registry::default();
+        registry.add_plugin(Plugin::Test(plugin::Test::new(
+            1,
+            vec![
+                package("a-1", "1.0", 1, &["soname(libc.so.1)"], installed().with_explicit()),
+                with_provider(package("c-1", "1.0", 1, &[], installed()), "soname(libc.so.1)"),
+                package("a-2", "1.1", 2, &["soname(libc.so.2)"], available()),
+                with_provider(package("c-2", "2.0", 2, &[], available()), "soname(libc.so.2)"),
+            ],
+        )));
+
+        let Err(error) = resolved(registry, &["c"]) else {
+            panic!("excluded dependency resolved");
+        };
+        assert_eq!(
+            error.to_string(),
+            "c is excluded from sync, but a depends on soname(libc.so.2) which only a newer c provides"
+        );
+        assert!(matches!(
+            error,
+            Error::ExcludedDependency { excluded, dependent, .. } if excluded.as_str() == "c" && dependent.as_str() == "a"
+        ));
+    }
+
+    #[test]
+    fn excluded_model_entry() {
+        let with_provider = |mut package: Package, provider: &str| {
+            package.meta.providers.insert(Provider::from_str(provider).unwrap());
+            package
+        };
+
+        let mut registry = Registry::default();
+        registry.add_plugin(Plugin::Test(plugin::Test::new(
+            1,
+            vec![
+                package("a-1", "1.0", 1, &[], installed().with_explicit().with_available()),
+                with_provider(package("c-1", "1.0", 1, &[], installed()), "soname(libc.so.1)"),
+                with_provider(package("c-2", "2.0", 2, &[], available()), "soname(libc.so.2)"),
+            ],
+        )));
+
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("system-model.kdl");
+        fs_err::write(&path, "repositories\npackages {\n    a\n    \"soname(libc.so.2)\"\n}\n").unwrap();
+        let model = system_model::load(&path).unwrap().unwrap();
+
+        let installation = Installation::open(root.path(), None).unwrap();
+        let client = Client::mocked(installation, registry).unwrap();
+        let installed = client.registry.list_installed().collect::<Vec<_>>();
+
+        let Err(error) = resolve(&client, Some(&model), &installed, &Exclusions::new(["c"])) else {
+            panic!("excluded model entry resolved");
+        };
+        assert_eq!(
+            error.to_string(),
+            "c is excluded from sync, but the system model lists soname(libc.so.2) which only a newer c provides"
+        );
+    }
+}
@@ -4,128 +4,160 @@
 use std::{
     collections::{BTreeMap, BTreeSet},
     fmt, io,
-    path::PathBuf,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
 };
 
 use astr::AStr;
 use fs_err as fs;
 use rayon::iter::{IntoParallelIterator as _, IntoParallelRefIterator as _, ParallelIterator as _};
-use stone::{StoneDigestWriter, StoneDigestWriterHasher, StonePayloadLayoutFile};
-use tui::{
-    ProgressBar, ProgressStyle, Styled,
-    dialoguer::{Confirm, theme::ColorfulTheme},
-};
+use stone::{StoneDigestWriter, StoneDigestWriterHasher, StonePayloadLayoutFile, StonePayloadLayoutRecord};
+use tui::{ProgressBar, ProgressStyle, Styled};
 use vfs::tree::BlitFile;
 
 use crate::{
-    Client, Package, Signal,
-    client::{self, cache},
+    Client, Package, Signal, State,
+    client::{
+        self, BlitStats, cache,
+        interaction::{Event, Question},
+    },
+    db::layout::VerifiedAsset,
     package, runtime, signal, state,
 };
 
-pub fn verify(client: &Client, yes: bool, verbose: bool) -> Result<(), client::Error> {
-    println!("Verifying assets");
+/// Restricts what is checked (and fixed) by [`verify`]
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Only verify the assets & paths of these packages, or everything if empty
+    pub packages: Vec<package::Name>,
+    /// Only verify these states, or all states if empty. Defaults to the
+    /// active state when `packages` are provided
+    pub states: Vec<state::Id>,
+    /// Only check assets exist, skipping re-hashing of their contents
+    pub quick: bool,
+    /// Re-hash every asset, even if unchanged since it was last verified
+    pub full: bool,
+    /// Print the outcome of each check
+    pub verbose: bool,
+}
+
+pub fn verify(client: &Client, yes: bool, options: &Options) -> Result<(), client::Error> {
+    let verbose = options.verbose;
+
+    // Held from the scan on, as its results are recorded and repaired, which
+    // would be stale if another transaction committed in between
+    let _lock = client.lock_state()?;
+
+    // Get all states
+    let states = client.state_db.all()?;
+
+    let scope = Scope::new(&states, client.installation.active_state, options, |id| {
+        client.install_db.get(id).ok().map(|meta| meta.name)
+    })?;
+
+    client.interaction.report(Event::Verifying);
 
     // Get all installed layouts, this is our source of truth
     let layouts = client.layout_db.all()?;
 
     // Group by unique assets (hash)
-    let mut unique_assets = BTreeMap::new();
-    for (package, layout) in layouts {
-        let StonePayloadLayoutFile::Regular(hash, file) = layout.file else {
-            continue;
-        };
-        unique_assets
-            .entry(format!("{hash:02x}"))
-            .or_insert_with(Vec::new)
-            .push((package, file));
-    }
-
-    let pb = ProgressBar::new(unique_assets.len() as u64)
-        .with_message("Verifying")
-        .with_style(
-            ProgressStyle::with_template("\n|{bar:20.red/blue}| {pos}/{len} {wide_msg}")
-                .unwrap()
-                .progress_chars("■≡=- "),
-        );
+    let unique_assets = unique_assets(layouts, &scope);
+
+    let reporter = client::reporter(&*client.interaction);
+    let pb = reporter.counter(
+        "Verified",
+        ProgressBar::new(unique_assets.len() as u64)
+            .with_message("Verifying")
+            .with_style(
+                ProgressStyle::with_template("\n|{bar:20.red/blue}| {pos}/{len} {wide_msg}")
+                    .unwrap()
+                    .progress_chars("■≡=- "),
+            ),
+    );
     pb.tick();
 
+    // Assets verified by a previous run, skipped if unchanged since
+    let known = if options.full {
+        BTreeMap::new()
+    } else {
+        client.layout_db.verified_assets()?
+    };
+
     // For each asset, ensure it exists in the content store and isn't corrupt (hash is correct)
-    let mut issues = unique_assets
+    let (mut issues, verified) = unique_assets
         .into_par_iter()
-        .try_fold(Vec::new, |mut acc, (hash, meta)| -> io::Result<_> {
-            // Padded so output is consistent
-            let display_hash = format!("{hash:0>32}");
-
-            let path = cache::asset_path(&client.installation, &hash);
-
-            let files = meta.iter().map(|(_, file)| file).cloned().collect::<BTreeSet<_>>();
+        .try_fold(
+            || (Vec::new(), Vec::new()),
+            |(mut issues, mut verified), (hash, meta)| -> io::Result<_> {
+                // Padded so output is consistent
+                let display_hash = format!("{hash:0>32}");
 
-            pb.set_message(format!("Verifying {display_hash}"));
-
-            if !path.exists() {
-                pb.inc(1);
-                if verbose {
-                    pb.suspend(|| println!(" {} {display_hash} - {files:?}", "×".yellow()));
-                }
-                acc.push(Issue::MissingAsset {
-                    hash,
-                    files,
-                    packages: meta.into_iter().map(|(package, _)| package).collect(),
-                });
-                return Ok(acc);
-            }
+                let path = cache::asset_path(&client.installation, &hash);
 
-            let mut hasher = StoneDigestWriterHasher::new();
-            let mut digest_writer = StoneDigestWriter::new(io::sink(), &mut hasher);
-            let mut file = fs::File::open(&path)?;
+                let files = meta.iter().map(|(_, file)| file).cloned().collect::<BTreeSet<_>>();
 
-            // Copy bytes to null sink so we don't
-            // explode memory
-            io::copy(&mut file, &mut digest_writer)?;
+                pb.set_message(format!("Verifying {display_hash}"));
 
-            let verified_hash = format!("{:02x}", hasher.digest128());
+                let check = check_asset(&path, &hash, known.get(&hash), options.quick)?;
 
-            if verified_hash != hash {
                 pb.inc(1);
                 if verbose {
-                    pb.suspend(|| println!(" {} {display_hash} - {files:?}", "×".yellow()));
+                    let mark = match check {
+                        AssetCheck::Missing | AssetCheck::Corrupt => "×".yellow(),
+                        AssetCheck::Exists | AssetCheck::Unchanged | AssetCheck::Verified(_) => "»".green(),
+                    };
+                    reporter.println(format_args!(" {mark} {display_hash} - {files:?}"));
                 }
-                acc.push(Issue::CorruptAsset {
-                    hash,
-                    files,
-                    packages: meta.into_iter().map(|(package, _)| package).collect(),
-                });
-                return Ok(acc);
-            }
 
-            pb.inc(1);
-            if verbose {
-                pb.suspend(|| println!(" {} {display_hash} - {files:?}", "»".green()));
-            }
-
-            Ok(acc)
-        })
-        .try_reduce(Vec::new, try_reduce_vec_concat)?;
+                let packages = || meta.iter().map(|(package, _)| package.clone()).collect();
+
+                match check {
+                    AssetCheck::Missing => issues.push(Issue::MissingAsset {
+                        hash,
+                        files,
+                        packages: packages(),
+                    }),
+                    AssetCheck::Corrupt => issues.push(Issue::CorruptAsset {
+                        hash,
+                        files,
+                        packages: packages(),
+                    }),
+                    AssetCheck::Verified(asset) => verified.push(asset),
+                    AssetCheck::Exists | AssetCheck::Unchanged => {}
+                }
 
-    // Get all states
-    let states = client.state_db.all()?;
+                Ok((issues, verified))
+            },
+        )
+        .try_reduce(
+            || (Vec::new(), Vec::new()),
+            |(mut issues, mut verified), (mut other_issues, mut other_verified)| {
+                issues.append(&mut other_issues);
+                verified.append(&mut other_verified);
+                Ok((issues, verified))
+            },
+        )?;
+
+    // Record what we verified, so the next run can skip it
+    client.layout_db.set_verified_assets(&verified)?;
+
+    let verified_states = states
+        .iter()
+        .filter(|state| scope.states.contains(&state.id))
+        .collect::<Vec<_>>();
 
-    pb.set_length(states.len() as u64);
-    pb.set_position(0);
-    pb.suspend(|| {
-        println!("Verifying states");
-    });
+    pb.restart("Verified", verified_states.len() as u64);
+    reporter.println("Verifying states");
 
     // Check the VFS of each state exists properly on the FS
-    let states_issues = states
+    let states_issues = verified_states
         .par_iter()
         .try_fold(Vec::new, |mut acc, state| {
             pb.set_message(format!("Verifying state #{}", state.id));
 
             let is_active = client.installation.active_state == Some(state.id);
 
-            let vfs = client.vfs(state.selections.iter().map(|s| &s.package))?;
+            let vfs = client.state_vfs(state)?;
 
             let base = if is_active {
                 client.installation.root.join("usr")
@@ -135,6 +167,7 @@ pub fn verify(client: &Client, yes: bool, verbose: bool) -> Result<(), client::E
 
             let state_issues: Vec<_> = vfs
                 .iter()
+                .filter(|file| scope.contains_package(&file.id))
                 .filter_map(|file| {
                     let path = base.join(file.path().strip_prefix("/usr/").unwrap_or_default());
 
@@ -158,7 +191,7 @@ pub fn verify(client: &Client, yes: bool, verbose: bool) -> Result<(), client::E
                 } else {
                     "»".green()
                 };
-                pb.suspend(|| println!(" {mark} state #{}", state.id));
+                reporter.println(format_args!(" {mark} state #{}", state.id));
             }
 
             acc.extend(state_issues);
@@ -169,30 +202,14 @@ pub fn verify(client: &Client, yes: bool, verbose: bool) -> Result<(), client::E
 
     pb.finish_and_clear();
 
+    client
+        .interaction
+        .report(Event::Issues(issues.iter().map(ToString::to_string).collect()));
     if issues.is_empty() {
-        println!("No issues found");
         return Ok(());
     }
 
-    println!(
-        "Found {} issue{}",
-        issues.len(),
-        if issues.len() == 1 { "" } else { "s" }
-    );
-
-    for issue in &issues {
-        println!(" {} {issue}", "×".yellow());
-    }
-
-    let result = if yes {
-        true
-    } else {
-        Confirm::with_theme(&ColorfulTheme::default())
-            .with_prompt(" Fixing issues, this will change your system state. Do you wish to continue? ")
-            .default(false)
-            .interact()?
-    };
-    if !result {
+    if !client.confirm(yes, &Question::Repair)? {
         return Err(client::Error::Cancelled);
     }
 
@@ -220,15 +237,15 @@ pub fn verify(client: &Client, yes: bool, verbose: bool) -> Result<(), client::E
             fs::remove_file(&path)?;
         }
 
-        println!("Reinstalling packages");
+        client.interaction.report(Event::Reinstalling);
 
         // And re-cache all packages that comprise the corrupt / missing asset
-        runtime::block_on(client.cache_packages(&issue_packages))?;
+        runtime::block_on(client.cache_packages(&issue_packages))?.into_result()?;
     }
 
-    // Now we must fix any states that referenced these packages
+    // Now we must fix any verified states that referenced these packages
     // or had their own VFS issues that require a reblit
-    let issue_states = states
+    let issue_states = verified_states
         .iter()
         .filter_map(|state| {
             state
@@ -240,7 +257,7 @@ pub fn verify(client: &Client, yes: bool, verbose: bool) -> Result<(), client::E
         .chain(issues.iter().filter_map(Issue::state))
         .collect::<BTreeSet<_>>();
 
-    println!("Reblitting affected states");
+    client.interaction.report(Event::Reblitting);
 
     let _guard = signal::ignore([Signal::SIGINT])?;
     let _fd = signal::inhibit(
@@ -250,7 +267,8 @@ pub fn verify(client: &Client, yes: bool, verbose: bool) -> Result<(), client::E
         "block".into(),
     );
 
-    // Reblit each state
+    // Reblit each state, reporting their combined stats
+    let mut stats = BlitStats::default();
     for id in issue_states {
         let state = states
             .iter()
@@ -260,7 +278,8 @@ pub fn verify(client: &Client, yes: bool, verbose: bool) -> Result<(), client::E
         let is_active = client.installation.active_state == Some(state.id);
 
         // Blits to staging dir
-        let fstree = client.blit_root(state.selections.iter().map(|s| &s.package))?;
+        let (fstree, blitted) = client.blit_state(state)?;
+        stats = stats.merge(blitted);
 
         if is_active {
             let system_model =
@@ -271,38 +290,204 @@ pub fn verify(client: &Client, yes: bool, verbose: bool) -> Result<(), client::E
             // Remove corrupt (swapped) state from staging directory
             fs::remove_dir_all(client.installation.staging_dir())?;
         } else {
-            let system_model = client.load_or_create_system_model(
-                client
-                    .installation
-                    .root_path(state.id.to_string())
-                    .join("usr/lib/system-model.kdl"),
-                state,
-            )?;
-
-            // Use the staged blit as an ephereral target for the non-active state
-            // then archive it to it's archive directory
-            client::record_state_id(&client.installation.staging_dir(), state.id)?;
-            client.apply_ephemeral_blit(fstree, &client.installation.staging_dir(), system_model)?;
-
-            // Remove the old archive state so the new blit can be archived
-            fs::remove_dir_all(client.installation.root_path(state.id.to_string())).or_else(|e| {
-                if e.kind() == io::ErrorKind::NotFound {
-                    Ok(())
-                } else {
-                    Err(e)
-                }
-            })?;
-            client.archive_state(state.id)?;
+            reblit_archived(client, state, fstree)?;
         }
 
-        println!(" {} state #{}", "»".green(), state.id);
+        client.interaction.report(Event::Reblitted(state.id));
     }
 
-    println!("All issues resolved");
+    client.interaction.report(Event::BlitFinished(stats));
+
+    client.interaction.report(Event::Repaired);
+    client.interaction.report(Event::Done);
 
     Ok(())
 }
 
+/// Replace the archived tree of the non-active `state` with the freshly
+/// blitted `fstree` in the staging directory
+pub(super) fn reblit_archived(
+    client: &Client,
+    state: &State,
+    fstree: vfs::Tree<client::PendingFile>,
+) -> Result<(), client::Error> {
+    let system_model = client.load_or_create_system_model(
+        client
+            .installation
+            .root_path(state.id.to_string())
+            .join("usr/lib/system-model.kdl"),
+        state,
+    )?;
+
+    // Use the staged blit as an ephereral target for the non-active state
+    // then archive it to it's archive directory
+    client::record_state_id(&client.installation.staging_dir(), state.id)?;
+    client.apply_ephemeral_blit(fstree, &client.installation.staging_dir(), system_model)?;
+
+    // Remove the old archive state so the new blit can be archived
+    fs::remove_dir_all(client.installation.root_path(state.id.to_string())).or_else(|e| {
+        if e.kind() == io::ErrorKind::NotFound {
+            Ok(())
+        } else {
+            Err(e)
+        }
+    })?;
+    client.archive_state(state.id)?;
+
+    Ok(())
+}
+/// The resolved set of states & packages to verify
+#[derive(Debug, PartialEq, Eq)]
+struct Scope {
+    states: BTreeSet<state::Id>,
+    /// Packages to verify, or `None` to verify every package
+    packages: Option<BTreeSet<package::Id>>,
+}
+
+impl Scope {
+    /// Resolve the [`Options`] against the known `states`, using `name_of`
+    /// to lookup the name of each selected package
+    fn new(
+        states: &[State],
+        active: Option<state::Id>,
+        options: &Options,
+        name_of: impl Fn(&package::Id) -> Option<package::Name>,
+    ) -> Result<Self, client::Error> {
+        if let Some(id) = options.states.iter().find(|id| !states.iter().any(|s| s.id == **id)) {
+            return Err(client::Error::StateDoesntExist(*id));
+        }
+
+        if options.packages.is_empty() && options.states.is_empty() {
+            return Ok(Self {
+                states: states.iter().map(|s| s.id).collect(),
+                packages: None,
+            });
+        }
+
+        let scoped_states = if !options.states.is_empty() {
+            options.states.iter().copied().collect::<BTreeSet<_>>()
+        } else {
+            BTreeSet::from([active.ok_or(client::Error::NoActiveState)?])
+        };
+
+        let selected = states
+            .iter()
+            .filter(|state| scoped_states.contains(&state.id))
+            .flat_map(|state| &state.selections)
+            .map(|selection| &selection.package)
+            .collect::<BTreeSet<_>>();
+
+        let packages = if options.packages.is_empty() {
+            selected.into_iter().cloned().collect()
+        } else {
+            let named = selected
+                .into_iter()
+                .filter_map(|id| Some((name_of(id)?, id.clone())))
+                .filter(|(name, _)| options.packages.contains(name))
+                .collect::<Vec<_>>();
+
+            if let Some(name) = options
+                .packages
+                .iter()
+                .find(|name| !named.iter().any(|(n, _)| n == *name))
+            {
+                return Err(client::Error::PackageNotSelected(name.clone()));
+            }
+
+            named.into_iter().map(|(_, id)| id).collect()
+        };
+
+        Ok(Self {
+            states: scoped_states,
+            packages: Some(packages),
+        })
+    }
+
+    fn contains_package(&self, package: &package::Id) -> bool {
+        self.packages.as_ref().is_none_or(|packages| packages.contains(package))
+    }
+}
+
+/// Group the regular files of each scoped layout by their asset hash
+fn unique_assets(
+    layouts: Vec<(package::Id, StonePayloadLayoutRecord)>,
+    scope: &Scope,
+) -> BTreeMap<String, Vec<(package::Id, AStr)>> {
+    let mut unique_assets = BTreeMap::new();
+
+    for (package, layout) in layouts {
+        if !scope.contains_package(&package) {
+            continue;
+        }
+        let StonePayloadLayoutFile::Regular(hash, file) = layout.file else {
+            continue;
+        };
+        unique_assets
+            .entry(format!("{hash:02x}"))
+            .or_insert_with(Vec::new)
+            .push((package, file));
+    }
+
+    unique_assets
+}
+
+/// Outcome of checking a single asset
+#[derive(Debug, PartialEq, Eq)]
+enum AssetCheck {
+    Missing,
+    Corrupt,
+    /// Exists, but wasn't re-hashed since `quick` was requested
+    Exists,
+    /// Size & mtime match the last successful verification, so it wasn't re-hashed
+    Unchanged,
+    /// Re-hashed successfully
+    Verified(VerifiedAsset),
+}
+
+/// Check the asset at `path` matches `hash`, skipping the re-hash if it's unchanged
+/// since the `known` verification or only existence is checked when `quick`
+fn check_asset(path: &Path, hash: &str, known: Option<&VerifiedAsset>, quick: bool) -> io::Result<AssetCheck> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(AssetCheck::Missing),
+        Err(e) => return Err(e),
+    };
+
+    let asset = VerifiedAsset {
+        hash: hash.to_owned(),
+        size: metadata.len(),
+        mtime: metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_nanos() as i64)
+            .unwrap_or_default(),
+    };
+
+    if known == Some(&asset) {
+        return Ok(AssetCheck::Unchanged);
+    }
+
+    if quick {
+        return Ok(AssetCheck::Exists);
+    }
+
+    let mut hasher = StoneDigestWriterHasher::new();
+    let mut digest_writer = StoneDigestWriter::new(io::sink(), &mut hasher);
+    let mut file = fs::File::open(path)?;
+
+    // Copy bytes to null sink so we don't
+    // explode memory
+    io::copy(&mut file, &mut digest_writer)?;
+
+    let verified_hash = format!("{:02x}", hasher.digest128());
+
+    if verified_hash != hash {
+        return Ok(AssetCheck::Corrupt);
+    }
+
+    Ok(AssetCheck::Verified(asset))
+}
+
 #[derive(Debug)]
 enum Issue {
     CorruptAsset {
@@ -359,3 +544,228 @@ fn try_reduce_vec_concat<T, E>(mut a: Vec<T>, mut b: Vec<T>) -> Result<Vec<T>, E
     a.append(&mut b);
     Ok(a)
 }
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+
+    use super::*;
+
+    fn state(id: i32, packages: &[&'static str]) -> State {
+        State {
+            id: state::Id::from(id),
+            summary: None,
+            description: None,
+            selections: packages
+                .iter()
+                .map(|p| state::Selection::explicit(package::Id::from(*p)))
+                .collect(),
+            retained: vec![],
+            created: Utc::now(),
+            kind: state::Kind::Transaction,
+        }
+    }
+
+    /// Package ids are `{name}-{release}`
+    fn name_of(id: &package::Id) -> Option<package::Name> {
+        let (name, _) = id.as_str().rsplit_once('-')?;
+        Some(package::Name::from(name.to_owned()))
+    }
+
+    fn states() -> Vec<State> {
+        vec![
+            state(1, &["bash-1", "nano-1"]),
+            state(2, &["bash-2", "nano-1"]),
+            state(3, &["bash-2", "helix-1"]),
+        ]
+    }
+
+    fn ids(ids: &[&'static str]) -> Option<BTreeSet<package::Id>> {
+        Some(ids.iter().map(|id| package::Id::from(*id)).collect())
+    }
+
+    fn state_ids(ids: &[i32]) -> BTreeSet<state::Id> {
+        ids.iter().copied().map(state::Id::from).collect()
+    }
+
+    fn names(names: &[&str]) -> Vec<package::Name> {
+        names
+            .iter()
+            .map(|name| package::Name::from((*name).to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn scope_everything() {
+        let scope = Scope::new(&states(), Some(state::Id::from(3)), &Options::default(), name_of).unwrap();
+
+        assert_eq!(scope.states, state_ids(&[1, 2, 3]));
+        assert_eq!(scope.packages, None);
+        assert!(scope.contains_package(&package::Id::from("anything-1")));
+    }
+
+    #[test]
+    fn scope_packages() {
+        let active = Some(state::Id::from(3));
+
+        // Names resolve against the active state only
+        let options = Options {
+            packages: names(&["bash"]),
+            ..Default::default()
+        };
+        let scope = Scope::new(&states(), active, &options, name_of).unwrap();
+        assert_eq!(scope.states, state_ids(&[3]));
+        assert_eq!(scope.packages, ids(&["bash-2"]));
+        assert!(!scope.contains_package(&package::Id::from("bash-1")));
+
+        // Unless states are provided
+        let options = Options {
+            packages: names(&["bash", "nano"]),
+            states: vec![state::Id::from(1), state::Id::from(2)],
+            ..Default::default()
+        };
+        let scope = Scope::new(&states(), active, &options, name_of).unwrap();
+        assert_eq!(scope.states, state_ids(&[1, 2]));
+        assert_eq!(scope.packages, ids(&["bash-1", "bash-2", "nano-1"]));
+
+        // Unknown names are an error
+        let options = Options {
+            packages: names(&["nano"]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            Scope::new(&states(), active, &options, name_of),
+            Err(client::Error::PackageNotSelected(name)) if name.as_str() == "nano"
+        ));
+
+        // As is no active state to resolve names against
+        assert!(matches!(
+            Scope::new(&states(), None, &options, name_of),
+            Err(client::Error::NoActiveState)
+        ));
+    }
+
+    #[test]
+    fn scope_states() {
+        let options = Options {
+            states: vec![state::Id::from(1)],
+            ..Default::default()
+        };
+        let scope = Scope::new(&states(), None, &options, name_of).unwrap();
+        assert_eq!(scope.states, state_ids(&[1]));
+        assert_eq!(scope.packages, ids(&["bash-1", "nano-1"]));
+
+        let options = Options {
+            states: vec![state::Id::from(9)],
+            ..Default::default()
+        };
+        assert!(matches!(
+            Scope::new(&states(), None, &options, name_of),
+            Err(client::Error::StateDoesntExist(id)) if id == state::Id::from(9)
+        ));
+    }
+
+    #[test]
+    fn scoped_assets() {
+        let layout = |package: &'static str, file: StonePayloadLayoutFile| {
+            (
+                package::Id::from(package),
+                StonePayloadLayoutRecord {
+                    uid: 0,
+                    gid: 0,
+                    mode: 0o644,
+                    tag: 0,
+                    file,
+                },
+            )
+        };
+        let regular = |hash, path: &str| StonePayloadLayoutFile::Regular(hash, AStr::from(path));
+        let layouts = || {
+            vec![
+                layout("bash-2", regular(0xa, "bin/bash")),
+                layout("bash-2", StonePayloadLayoutFile::Directory(AStr::from("share/bash"))),
+                layout("nano-1", regular(0xb, "bin/nano")),
+                layout("helix-1", regular(0xb, "bin/hx")),
+            ]
+        };
+
+        let everything = Scope {
+            states: state_ids(&[3]),
+            packages: None,
+        };
+        let assets = unique_assets(layouts(), &everything);
+        assert_eq!(assets.keys().collect::<Vec<_>>(), ["0a", "0b"]);
+        assert_eq!(assets["0b"].len(), 2);
+
+        let helix = Scope {
+            states: state_ids(&[3]),
+            packages: ids(&["helix-1"]),
+        };
+        let assets = unique_assets(layouts(), &helix);
+        assert_eq!(
+            assets,
+            BTreeMap::from([(
+                "0b".to_owned(),
+                vec![(package::Id::from("helix-1"), AStr::from("bin/hx"))]
+            )])
+        );
+    }
+
+    #[test]
+    fn incremental_asset_check() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let write_asset = |name: &str, contents: &[u8]| {
+            let path = dir.path().join(name);
+            fs::write(&path, contents).unwrap();
+
+            let mut hasher = StoneDigestWriterHasher::new();
+            let mut digest_writer = StoneDigestWriter::new(io::sink(), &mut hasher);
+            io::copy(&mut &contents[..], &mut digest_writer).unwrap();
+
+            (path, format!("{:02x}", hasher.digest128()))
+        };
+
+        let (touched, touched_hash) = write_asset("touched", b"touched contents");
+        let (untouched, untouched_hash) = write_asset("untouched", b"untouched contents");
+
+        // First run hashes everything
+        let AssetCheck::Verified(touched_record) = check_asset(&touched, &touched_hash, None, false).unwrap() else {
+            panic!("expected touched asset to be verified");
+        };
+        let AssetCheck::Verified(untouched_record) = check_asset(&untouched, &untouched_hash, None, false).unwrap()
+        else {
+            panic!("expected untouched asset to be verified");
+        };
+
+        // Corrupt the touched asset without changing it's size & bump it's mtime
+        fs::write(&touched, b"corrupt contents").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&touched)
+            .unwrap()
+            .file()
+            .set_modified(UNIX_EPOCH + std::time::Duration::from_secs(1))
+            .unwrap();
+
+        // Touched asset is re-hashed and found corrupt, untouched is skipped
+        assert_eq!(
+            check_asset(&touched, &touched_hash, Some(&touched_record), false).unwrap(),
+            AssetCheck::Corrupt
+        );
+        assert_eq!(
+            check_asset(&untouched, &untouched_hash, Some(&untouched_record), false).unwrap(),
+            AssetCheck::Unchanged
+        );
+
+        // Quick only checks for existence
+        assert_eq!(
+            check_asset(&touched, &touched_hash, None, true).unwrap(),
+            AssetCheck::Exists
+        );
+        assert_eq!(
+            check_asset(&dir.path().join("missing"), &touched_hash, None, true).unwrap(),
+            AssetCheck::Missing
+        );
+    }
+}
@@ -0,0 +1,9 @@
+-- SPDX-FileCopyrightText: 2026 AerynOS Developers
+-- SPDX-License-Identifier: MPL-2.0
+
+CREATE TABLE IF NOT EXISTS verified_assets (
+    hash TEXT NOT NULL PRIMARY KEY,
+    size BIGINT NOT NULL,
+    mtime BIGINT NOT NULL,
+    verified BIGINT NOT NULL DEFAULT (unixepoch())
+);
@@ -0,0 +1,6 @@
+-- SPDX-FileCopyrightText: 2026 AerynOS Developers
+-- SPDX-License-Identifier: MPL-2.0
+
+DROP INDEX IF EXISTS layout_regular_hash;
+DROP INDEX IF EXISTS layout_path;
+ALTER TABLE layout DROP COLUMN path;
@@ -0,0 +1,17 @@
+-- SPDX-FileCopyrightText: 2026 AerynOS Developers
+-- SPDX-License-Identifier: MPL-2.0
+
+ALTER TABLE layout ADD COLUMN path TEXT NOT NULL DEFAULT '';
+
+-- Regular files, symlinks & unknown entries store their path as the second value
+UPDATE layout
+SET path = COALESCE(
+    CASE
+        WHEN entry_type IN ('regular', 'symlink', 'unknown') THEN entry_value2
+        ELSE entry_value1
+    END,
+    ''
+);
+
+CREATE INDEX IF NOT EXISTS layout_path ON layout (path);
+CREATE INDEX IF NOT EXISTS layout_regular_hash ON layout (entry_value1) WHERE entry_type = 'regular';
@@ -2,10 +2,13 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use astr::AStr;
+use diesel::SqliteConnection;
 use diesel::prelude::*;
-use diesel::{Connection as _, SqliteConnection};
 use diesel_migrations::{EmbeddedMigrations, MigrationHarness, embed_migrations};
-use std::{borrow::Cow, collections::BTreeSet};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+};
 
 use stone::{StonePayloadLayoutFile, StonePayloadLayoutRecord};
 
@@ -18,6 +21,15 @@ const MIGRATIONS: EmbeddedMigrations = embed_migrations!("src/db/layout/migratio
 
 mod schema;
 
+/// An asset which was last verified with the given size & mtime
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedAsset {
+    pub hash: String,
+    pub size: u64,
+    /// Modification time in nanoseconds since the unix epoch
+    pub mtime: i64,
+}
+
 #[derive(Debug, Clone)]
 pub struct Database {
     conn: Connection,
@@ -25,13 +37,25 @@ pub struct Database {
 
 impl Database {
     pub fn new(url: &str) -> Result<Self, Error> {
-        let mut conn = SqliteConnection::establish(url)?;
+        let conn = Connection::new(url)?;
 
-        conn.run_pending_migrations(MIGRATIONS).map_err(Error::Migration)?;
+        conn.exec(|conn| {
+            conn.run_pending_migrations(MIGRATIONS)
+                .map(drop)
+                .map_err(Error::Migration)
+        })?;
 
-        Ok(Database {
-            conn: Connection::new(conn),
-        })
+        Ok(Database { conn })
+    }
+
+    /// Check the database for corruption, see [`Error::Corrupt`]
+    pub fn check(&self) -> Result<(), Error> {
+        self.conn.check()
+    }
+
+    /// Rebuild the database, reclaiming unused space
+    pub fn vacuum(&self) -> Result<(), Error> {
+        self.conn.vacuum()
     }
 
     /// Retrieve all entries for a given package by ID
@@ -69,6 +93,18 @@ impl Database {
         })
     }
 
+    /// Retrieve all entries with the given `path`, relative to `/usr`
+    pub fn query_path(&self, path: &str) -> Result<Vec<(package::Id, StonePayloadLayoutRecord)>, Error> {
+        self.conn.exec(|conn| {
+            model::layout::table
+                .select(model::Layout::as_select())
+                .filter(model::layout::path.eq(path))
+                .load_iter(conn)?
+                .map(map_layout)
+                .collect()
+        })
+    }
+
     pub fn package_ids(&self) -> Result<BTreeSet<package::Id>, Error> {
         self.conn.exec(|conn| {
             Ok(model::layout::table
@@ -95,6 +131,96 @@ impl Database {
         })
     }
 
+    /// Returns the hashes of all regular files, ignoring entries of the `excluded` packages
+    pub fn file_hashes_excluding(&self, excluded: &BTreeSet<package::Id>) -> Result<BTreeSet<String>, Error> {
+        self.conn.exec(|conn| {
+            let rows = model::layout::table
+                .select((model::layout::package_id, model::layout::entry_value1.assume_not_null()))
+                .filter(model::layout::entry_type.eq("regular"))
+                .load::<(AStr, String)>(conn)?;
+
+            Ok(rows
+                .into_iter()
+                .filter(|(package, _)| !excluded.contains(&package::Id::from(package.clone())))
+                .filter_map(|(_, hash)| hash.parse::<u128>().ok().map(|hash| format!("{hash:02x}")))
+                .collect())
+        })
+    }
+
+    /// Returns the hashes of the regular files of each of the `packages`
+    pub fn file_hashes_by_package<'a>(
+        &self,
+        packages: impl IntoIterator<Item = &'a package::Id>,
+    ) -> Result<BTreeMap<package::Id, BTreeSet<String>>, Error> {
+        self.conn.exec(|conn| {
+            let packages = packages.into_iter().map(package::Id::as_str).collect::<Vec<_>>();
+
+            let mut output = BTreeMap::<package::Id, BTreeSet<String>>::new();
+
+            for chunk in packages.chunks(MAX_VARIABLE_NUMBER) {
+                let rows = model::layout::table
+                    .select((model::layout::package_id, model::layout::entry_value1.assume_not_null()))
+                    .filter(model::layout::entry_type.eq("regular"))
+                    .filter(model::layout::package_id.eq_any(chunk))
+                    .load::<(AStr, String)>(conn)?;
+
+                for (package, hash) in rows {
+                    if let Ok(hash) = hash.parse::<u128>() {
+                        output
+                            .entry(package::Id::from(package))
+                            .or_default()
+                            .insert(format!("{hash:02x}"));
+                    }
+                }
+            }
+
+            Ok(output)
+        })
+    }
+
+    /// Returns all assets recorded by [`Database::set_verified_assets`], keyed by hash
+    pub fn verified_assets(&self) -> Result<BTreeMap<String, VerifiedAsset>, Error> {
+        self.conn.exec(|conn| {
+            Ok(model::verified_assets::table
+                .select(model::VerifiedAsset::as_select())
+                .load::<model::VerifiedAsset>(conn)?
+                .into_iter()
+                .map(|row| {
+                    (
+                        row.hash.clone(),
+                        VerifiedAsset {
+                            hash: row.hash,
+                            size: row.size as u64,
+                            mtime: row.mtime,
+                        },
+                    )
+                })
+                .collect())
+        })
+    }
+
+    /// Record the provided assets as successfully verified
+    pub fn set_verified_assets(&self, assets: &[VerifiedAsset]) -> Result<(), Error> {
+        self.conn.exclusive_tx(|tx| {
+            let rows = assets
+                .iter()
+                .map(|asset| model::NewVerifiedAsset {
+                    hash: &asset.hash,
+                    size: asset.size as i64,
+                    mtime: asset.mtime,
+                })
+                .collect::<Vec<_>>();
+
+            for chunk in rows.chunks(MAX_VARIABLE_NUMBER / 3) {
+                diesel::replace_into(model::verified_assets::table)
+                    .values(chunk)
+                    .execute(tx)?;
+            }
+
+            Ok(())
+        })
+    }
+
     pub fn add(&self, package: &package::Id, layout: &StonePayloadLayoutRecord) -> Result<(), Error> {
         self.batch_add(vec![(package, layout)])
     }
@@ -111,7 +237,7 @@ impl Database {
                 .map(|(package_id, layout)| {
                     ids.push(package_id.as_str());
 
-                    let (entry_type, entry_value1, entry_value2) = encode_entry(&layout.file);
+                    let (entry_type, entry_value1, entry_value2, path) = encode_entry(&layout.file);
 
                     model::NewLayout {
                         package_id: package_id.to_string(),
@@ -122,6 +248,7 @@ impl Database {
                         entry_type,
                         entry_value1,
                         entry_value2,
+                        path,
                     }
                 })
                 .collect::<Vec<_>>();
@@ -130,7 +257,7 @@ impl Database {
             ids.dedup();
             batch_remove_impl(&ids, tx)?;
 
-            for chunk in values.chunks(MAX_VARIABLE_NUMBER / 8) {
+            for chunk in values.chunks(MAX_VARIABLE_NUMBER / 9) {
                 diesel::insert_into(model::layout::table).values(chunk).execute(tx)?;
             }
 
@@ -163,7 +290,7 @@ fn batch_remove_impl(packages: &[&str], tx: &mut SqliteConnection) -> Result<(),
 fn map_layout(result: QueryResult<model::Layout>) -> Result<(package::Id, StonePayloadLayoutRecord), Error> {
     let row = result?;
 
-    let entry = decode_entry(row.entry_type, row.entry_value1, row.entry_value2).ok_or(Error::LayoutEntryDecode)?;
+    let entry = decode_entry(row.entry_type, row.entry_value1, row.path).ok_or(Error::LayoutEntryDecode)?;
 
     let layout = StonePayloadLayoutRecord {
         uid: row.uid as u32,
@@ -176,39 +303,39 @@ fn map_layout(result: QueryResult<model::Layout>) -> Result<(package::Id, StoneP
     Ok((row.package_id, layout))
 }
 
-fn decode_entry(
-    entry_type: String,
-    entry_value1: Option<AStr>,
-    entry_value2: Option<AStr>,
-) -> Option<StonePayloadLayoutFile> {
+/// Decode an entry from its type, first value & path
+///
+/// The path of regular files, symlinks & unknown entries was historically
+/// stored as the second value, which is still written for compatibility
+fn decode_entry(entry_type: String, entry_value1: Option<AStr>, path: AStr) -> Option<StonePayloadLayoutFile> {
     match entry_type.as_str() {
         "regular" => {
             let hash = entry_value1?.parse::<u128>().ok()?;
-            let name = entry_value2?;
 
-            Some(StonePayloadLayoutFile::Regular(hash, name))
+            Some(StonePayloadLayoutFile::Regular(hash, path))
         }
-        "symlink" => Some(StonePayloadLayoutFile::Symlink(entry_value1?, entry_value2?)),
-        "directory" => Some(StonePayloadLayoutFile::Directory(entry_value1?)),
-        "character-device" => Some(StonePayloadLayoutFile::CharacterDevice(entry_value1?)),
-        "block-device" => Some(StonePayloadLayoutFile::BlockDevice(entry_value1?)),
-        "fifo" => Some(StonePayloadLayoutFile::Fifo(entry_value1?)),
-        "socket" => Some(StonePayloadLayoutFile::Socket(entry_value1?)),
-        "unknown" => Some(StonePayloadLayoutFile::Unknown(entry_value1?, entry_value2?)),
+        "symlink" => Some(StonePayloadLayoutFile::Symlink(entry_value1?, path)),
+        "directory" => Some(StonePayloadLayoutFile::Directory(path)),
+        "character-device" => Some(StonePayloadLayoutFile::CharacterDevice(path)),
+        "block-device" => Some(StonePayloadLayoutFile::BlockDevice(path)),
+        "fifo" => Some(StonePayloadLayoutFile::Fifo(path)),
+        "socket" => Some(StonePayloadLayoutFile::Socket(path)),
+        "unknown" => Some(StonePayloadLayoutFile::Unknown(entry_value1?, path)),
         _ => None,
     }
 }
 
-fn encode_entry(entry: &StonePayloadLayoutFile) -> (&'static str, Option<Cow<'_, str>>, Option<&str>) {
+/// Encode an entry as its type, values & path
+fn encode_entry(entry: &StonePayloadLayoutFile) -> (&'static str, Option<Cow<'_, str>>, Option<&str>, &str) {
     match entry {
-        StonePayloadLayoutFile::Regular(hash, name) => ("regular", Some(hash.to_string().into()), Some(name)),
-        StonePayloadLayoutFile::Symlink(a, b) => ("symlink", Some(a.into()), Some(b)),
-        StonePayloadLayoutFile::Directory(name) => ("directory", Some(name.into()), None),
-        StonePayloadLayoutFile::CharacterDevice(name) => ("character-device", Some(name.into()), None),
-        StonePayloadLayoutFile::BlockDevice(name) => ("block-device", Some(name.into()), None),
-        StonePayloadLayoutFile::Fifo(name) => ("fifo", Some(name.into()), None),
-        StonePayloadLayoutFile::Socket(name) => ("socket", Some(name.into()), None),
-        StonePayloadLayoutFile::Unknown(a, b) => ("unknown", Some(a.into()), Some(b)),
+        StonePayloadLayoutFile::Regular(hash, name) => ("regular", Some(hash.to_string().into()), Some(name), name),
+        StonePayloadLayoutFile::Symlink(a, b) => ("symlink", Some(a.into()), Some(b), b),
+        StonePayloadLayoutFile::Directory(name) => ("directory", Some(name.into()), None, name),
+        StonePayloadLayoutFile::CharacterDevice(name) => ("character-device", Some(name.into()), None, name),
+        StonePayloadLayoutFile::BlockDevice(name) => ("block-device", Some(name.into()), None, name),
+        StonePayloadLayoutFile::Fifo(name) => ("fifo", Some(name.into()), None, name),
+        StonePayloadLayoutFile::Socket(name) => ("socket", Some(name.into()), None, name),
+        StonePayloadLayoutFile::Unknown(a, b) => ("unknown", Some(a.into()), Some(b), b),
     }
 }
 
@@ -220,7 +347,7 @@ mod model {
 
     use crate::package;
 
-    pub use super::schema::layout;
+    pub use super::schema::{layout, verified_assets};
 
     #[derive(Queryable, Selectable, Identifiable)]
     #[diesel(table_name = layout)]
@@ -235,6 +362,7 @@ mod model {
         pub entry_type: String,
         pub entry_value1: Option<AStr>,
         pub entry_value2: Option<AStr>,
+        pub path: AStr,
     }
 
     #[derive(Insertable)]
@@ -248,6 +376,23 @@ mod model {
         pub entry_type: &'a str,
         pub entry_value1: Option<Cow<'a, str>>,
         pub entry_value2: Option<&'a str>,
+        pub path: &'a str,
+    }
+
+    #[derive(Queryable, Selectable)]
+    #[diesel(table_name = verified_assets)]
+    pub struct VerifiedAsset {
+        pub hash: String,
+        pub size: i64,
+        pub mtime: i64,
+    }
+
+    #[derive(Insertable)]
+    #[diesel(table_name = verified_assets)]
+    pub struct NewVerifiedAsset<'a> {
+        pub hash: &'a str,
+        pub size: i64,
+        pub mtime: i64,
     }
 }
 
@@ -281,4 +426,102 @@ mod test {
 
         assert_eq!(count, all.len());
     }
+
+    #[test]
+    fn query_path() {
+        let database = Database::new(":memory:").unwrap();
+
+        let layout = |file| StonePayloadLayoutRecord {
+            uid: 0,
+            gid: 0,
+            mode: 0o644,
+            tag: 0,
+            file,
+        };
+        let bash = package::Id::from("bash");
+        let busybox = package::Id::from("busybox");
+
+        database
+            .batch_add([
+                (&bash, &layout(StonePayloadLayoutFile::Regular(1, "bin/bash".into()))),
+                (&bash, &layout(StonePayloadLayoutFile::Directory("bin".into()))),
+                (
+                    &busybox,
+                    &layout(StonePayloadLayoutFile::Symlink("busybox".into(), "bin/sh".into())),
+                ),
+                (
+                    &busybox,
+                    &layout(StonePayloadLayoutFile::Regular(2, "bin/busybox".into())),
+                ),
+            ])
+            .unwrap();
+
+        let found = database.query_path("bin/sh").unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, busybox);
+        assert_eq!(
+            found[0].1.file,
+            StonePayloadLayoutFile::Symlink("busybox".into(), "bin/sh".into())
+        );
+
+        let found = database.query_path("bin").unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].1.file, StonePayloadLayoutFile::Directory("bin".into()));
+
+        assert!(database.query_path("bin/zsh").unwrap().is_empty());
+        assert_eq!(
+            database.file_hashes().unwrap(),
+            BTreeSet::from(["01".to_owned(), "02".to_owned()])
+        );
+    }
+
+    #[test]
+    fn path_backfill() {
+        use diesel::{Connection as _, connection::SimpleConnection};
+
+        let dir = tempfile::tempdir().unwrap();
+        let url = dir.path().join("layout.db");
+        let url = url.to_str().unwrap();
+
+        // Database created before the path column existed
+        {
+            let mut conn = SqliteConnection::establish(url).unwrap();
+            conn.run_next_migration(MIGRATIONS).unwrap();
+            conn.run_next_migration(MIGRATIONS).unwrap();
+            conn.batch_execute(
+                "INSERT INTO layout (package_id, uid, gid, mode, tag, entry_type, entry_value1, entry_value2) VALUES
+                    ('bash', 0, 0, 420, 0, 'regular', '1', 'bin/bash'),
+                    ('bash', 0, 0, 493, 0, 'directory', 'bin', NULL),
+                    ('bash', 0, 0, 511, 0, 'symlink', 'bash', 'bin/sh'),
+                    ('bash', 0, 0, 420, 0, 'fifo', 'run/fifo', NULL);",
+            )
+            .unwrap();
+        }
+
+        let database = Database::new(url).unwrap();
+
+        let paths = database
+            .conn
+            .exec(|conn| {
+                model::layout::table
+                    .select((model::layout::entry_type, model::layout::path))
+                    .order(model::layout::id)
+                    .load::<(String, String)>(conn)
+            })
+            .unwrap();
+        assert_eq!(
+            paths,
+            [
+                ("regular".to_owned(), "bin/bash".to_owned()),
+                ("directory".to_owned(), "bin".to_owned()),
+                ("symlink".to_owned(), "bin/sh".to_owned()),
+                ("fifo".to_owned(), "run/fifo".to_owned()),
+            ]
+        );
+
+        // Backfilled entries decode as before
+        let found = database.query_path("bin/bash").unwrap();
+        assert_eq!(found[0].1.file, StonePayloadLayoutFile::Regular(1, "bin/bash".into()));
+        assert_eq!(database.all().unwrap().len(), 4);
+    }
 }
@@ -11,5 +11,17 @@ diesel::table! {
         entry_type -> Text,
         entry_value1 -> Nullable<Text>,
         entry_value2 -> Nullable<Text>,
+        path -> Text,
     }
 }
+
+diesel::table! {
+    verified_assets (hash) {
+        hash -> Text,
+        size -> BigInt,
+        mtime -> BigInt,
+        verified -> BigInt,
+    }
+}
+
+diesel::allow_tables_to_appear_in_same_query!(layout, verified_assets);
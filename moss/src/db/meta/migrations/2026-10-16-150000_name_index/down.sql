@@ -0,0 +1,4 @@
+-- SPDX-FileCopyrightText: 2026 AerynOS Developers
+-- SPDX-License-Identifier: MPL-2.0
+
+DROP INDEX IF EXISTS meta_name;
@@ -0,0 +1,5 @@
+-- SPDX-FileCopyrightText: 2026 AerynOS Developers
+-- SPDX-License-Identifier: MPL-2.0
+
+-- Serves prefix lookups of package names, i.e. for shell completion
+CREATE INDEX IF NOT EXISTS meta_name ON meta (name);
@@ -0,0 +1,7 @@
+-- SPDX-FileCopyrightText: 2026 AerynOS Developers
+-- SPDX-License-Identifier: MPL-2.0
+
+ALTER TABLE meta DROP COLUMN build_profile;
+ALTER TABLE meta DROP COLUMN build_time;
+ALTER TABLE meta DROP COLUMN build_host;
+ALTER TABLE meta DROP COLUMN builder;
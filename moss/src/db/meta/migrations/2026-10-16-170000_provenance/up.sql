@@ -0,0 +1,8 @@
+-- SPDX-FileCopyrightText: 2026 AerynOS Developers
+-- SPDX-License-Identifier: MPL-2.0
+
+-- Who built each package, absent for those built before it was recorded
+ALTER TABLE meta ADD COLUMN builder TEXT NULL;
+ALTER TABLE meta ADD COLUMN build_host TEXT NULL;
+ALTER TABLE meta ADD COLUMN build_time BIGINT NULL;
+ALTER TABLE meta ADD COLUMN build_profile TEXT NULL;
@@ -0,0 +1,9 @@
+-- SPDX-FileCopyrightText: 2026 AerynOS Developers
+-- SPDX-License-Identifier: MPL-2.0
+
+-- Hash of the stone index the packages were ingested from, a single row at most.
+-- Empty until the next ingestion, so databases migrated since are ingested anew
+CREATE TABLE IF NOT EXISTS meta_index (
+    id INTEGER PRIMARY KEY NOT NULL CHECK (id = 0),
+    hash TEXT NOT NULL
+);
@@ -1,20 +1,29 @@
 // SPDX-FileCopyrightText: 2023 AerynOS Developers
 // SPDX-License-Identifier: MPL-2.0
 
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{BTreeMap, BTreeSet, btree_map};
+use std::str::FromStr;
 
 use astr::AStr;
+use diesel::SqliteConnection;
+use diesel::connection::SimpleConnection;
 use diesel::prelude::*;
-use diesel::{Connection as _, SqliteConnection};
+use diesel::sql_types::Text;
 use diesel_migrations::{EmbeddedMigrations, MigrationHarness, embed_migrations};
 
 use crate::db::Connection;
 use crate::package::{self, Meta};
-use crate::{Dependency, Provider};
+use crate::{Dependency, Provider, ProviderGlob, dependency};
 
 pub use super::Error;
 use super::MAX_VARIABLE_NUMBER;
 
+diesel::infix_operator!(Glob, " GLOB ", backend: diesel::sqlite::Sqlite);
+
+/// Packages inserted at once by [`Database::replace_all`], bounding the rows
+/// built in memory
+const REPLACE_CHUNK_SIZE: usize = 1000;
+
 const MIGRATIONS: EmbeddedMigrations = embed_migrations!("src/db/meta/migrations");
 
 mod schema;
@@ -22,35 +31,97 @@ mod schema;
 #[derive(Debug)]
 pub enum Filter<'a> {
     Provider(Provider),
+    /// Packages with any provider matching the glob
+    ProviderGlob(&'a ProviderGlob),
     Dependency(Dependency),
     Name(package::Name),
     Keyword(&'a str),
 }
 
+/// Full text index over name, summary & description, kept in sync by triggers
+///
+/// This lives outside of the migrations as FTS5 may not be compiled into
+/// every sqlite, in which case [`Database::search`] falls back to `LIKE`
+const SEARCH_INDEX: &str = "
+    CREATE VIRTUAL TABLE meta_search USING fts5(name, summary, description, content='meta');
+    CREATE TRIGGER meta_search_insert AFTER INSERT ON meta BEGIN
+        INSERT INTO meta_search(rowid, name, summary, description)
+            VALUES (new.rowid, new.name, new.summary, new.description);
+    END;
+    CREATE TRIGGER meta_search_delete AFTER DELETE ON meta BEGIN
+        INSERT INTO meta_search(meta_search, rowid, name, summary, description)
+            VALUES ('delete', old.rowid, old.name, old.summary, old.description);
+    END;
+    CREATE TRIGGER meta_search_update AFTER UPDATE ON meta BEGIN
+        INSERT INTO meta_search(meta_search, rowid, name, summary, description)
+            VALUES ('delete', old.rowid, old.name, old.summary, old.description);
+        INSERT INTO meta_search(rowid, name, summary, description)
+            VALUES (new.rowid, new.name, new.summary, new.description);
+    END;
+    INSERT INTO meta_search(meta_search) VALUES ('rebuild');
+";
+
 #[derive(Debug, Clone)]
 pub struct Database {
     conn: Connection,
+    /// Whether the `meta_search` full text index is available
+    fts: bool,
 }
 
 impl Database {
     pub fn new(url: &str) -> Result<Self, Error> {
-        let mut conn = SqliteConnection::establish(url)?;
+        let conn = Connection::new(url)?;
 
-        conn.run_pending_migrations(MIGRATIONS).map_err(Error::Migration)?;
+        conn.exec(|conn| {
+            conn.run_pending_migrations(MIGRATIONS)
+                .map(drop)
+                .map_err(Error::Migration)
+        })?;
 
-        Ok(Database {
-            conn: Connection::new(conn),
-        })
+        let fts = conn.exec(create_search_index)?;
+
+        Ok(Database { conn, fts })
+    }
+
+    /// Check the database for corruption, see [`Error::Corrupt`]
+    pub fn check(&self) -> Result<(), Error> {
+        self.conn.check()
+    }
+
+    /// Rebuild the database, reclaiming unused space
+    pub fn vacuum(&self) -> Result<(), Error> {
+        self.conn.vacuum()?;
+
+        // Vacuuming may renumber the rowids the search index refers to
+        if self.fts {
+            self.conn
+                .exec(|conn| conn.batch_execute("INSERT INTO meta_search(meta_search) VALUES ('rebuild');"))?;
+        }
+
+        Ok(())
     }
 
     pub fn wipe(&self) -> Result<(), Error> {
         self.conn.exclusive_tx(|tx| {
             // Cascading wipes other tables
             diesel::delete(model::meta::table).execute(tx)?;
+            diesel::delete(model::meta_index::table).execute(tx)?;
             Ok(())
         })
     }
 
+    /// Hash of the index the packages were last ingested from, see [`Database::replace_all`]
+    ///
+    /// `None` when wiped, or never ingested since created or migrated.
+    pub fn index_hash(&self) -> Result<Option<String>, Error> {
+        self.conn.exec(|conn| {
+            Ok(model::meta_index::table
+                .select(model::meta_index::hash)
+                .first::<String>(conn)
+                .optional()?)
+        })
+    }
+
     pub fn get(&self, package: &package::Id) -> Result<Meta, Error> {
         self.conn.exec(|conn| {
             let meta = model::meta::table
@@ -59,6 +130,7 @@ impl Database {
                 .first::<model::Meta>(conn)?;
             let licenses = model::License::belonging_to(&meta)
                 .select(model::meta_licenses::license)
+                .order_by(model::meta_licenses::license)
                 .load::<String>(conn)?;
             let dependencies = model::Dependency::belonging_to(&meta)
                 .select(model::Dependency::as_select())
@@ -76,6 +148,8 @@ impl Database {
                 .map(|p| Ok(p?.conflict))
                 .collect::<Result<_, Error>>()?;
 
+            let provenance = meta.provenance();
+
             Ok(Meta {
                 name: meta.name,
                 version_identifier: meta.version_identifier,
@@ -93,6 +167,7 @@ impl Database {
                 uri: meta.uri,
                 hash: meta.hash,
                 download_size: meta.download_size.map(|size| size as u64),
+                provenance,
             })
         })
     }
@@ -103,6 +178,7 @@ impl Database {
                 .select(model::meta_providers::package)
                 .distinct()
                 .filter(model::meta_providers::provider.eq(provider.to_string()))
+                .order_by(model::meta_providers::package)
                 .load_iter::<AStr, _>(conn)?
                 .map(|result| {
                     let id = result?;
@@ -112,10 +188,12 @@ impl Database {
         })
     }
 
+    /// Packages matching `filter`, ordered by name, then newest release first
     pub fn query(&self, filter: Option<Filter<'_>>) -> Result<Vec<(package::Id, Meta)>, Error> {
         self.conn.exec(|conn| {
             let map_row = |result| {
                 let meta: model::Meta = result?;
+                let provenance = meta.provenance();
 
                 Ok((
                     package::Id::from(AStr::from(meta.package)),
@@ -136,24 +214,46 @@ impl Database {
                         uri: meta.uri,
                         hash: meta.hash,
                         download_size: meta.download_size.map(|size| size as u64),
+                        provenance,
                     },
                 ))
             };
 
-            let mut entries: BTreeMap<package::Id, Meta> = match &filter {
+            // Name, then newest release first, so listings don't depend on the row order
+            let order = (
+                model::meta::name,
+                model::meta::source_release.desc(),
+                model::meta::build_release.desc(),
+                model::meta::package,
+            );
+
+            let rows: Vec<(package::Id, Meta)> = match &filter {
                 Some(Filter::Provider(provider)) => model::meta::table
                     .select(model::Meta::as_select())
                     .inner_join(model::meta_providers::table)
                     .filter(model::meta_providers::provider.eq(provider.to_string()))
+                    .order_by(order)
                     .load_iter::<model::Meta, _>(conn)?,
+                Some(Filter::ProviderGlob(glob)) => {
+                    // `[` opens a character class in `GLOB`, which providers have no use for
+                    let pattern = glob.as_str().replace('[', "[[]");
+                    model::meta::table
+                        .select(model::Meta::as_select())
+                        .inner_join(model::meta_providers::table)
+                        .filter(Glob::new(model::meta_providers::provider, pattern.into_sql::<Text>()))
+                        .order_by(order)
+                        .load_iter::<model::Meta, _>(conn)?
+                }
                 Some(Filter::Dependency(dependency)) => model::meta::table
                     .select(model::Meta::as_select())
                     .inner_join(model::meta_dependencies::table)
                     .filter(model::meta_dependencies::dependency.eq(dependency.to_string()))
+                    .order_by(order)
                     .load_iter::<model::Meta, _>(conn)?,
                 Some(Filter::Name(name)) => model::meta::table
                     .select(model::Meta::as_select())
                     .filter(model::meta::name.eq(name.to_string()))
+                    .order_by(order)
                     .load_iter::<model::Meta, _>(conn)?,
                 Some(Filter::Keyword(keyword)) => {
                     let pattern = format!("%{keyword}%");
@@ -164,15 +264,27 @@ impl Database {
                                 .like(pattern.clone())
                                 .or(model::meta::summary.like(pattern)),
                         )
+                        .order_by(order)
                         .load_iter::<model::Meta, _>(conn)?
                 }
                 None => model::meta::table
                     .select(model::Meta::as_select())
+                    .order_by(order)
                     .load_iter::<model::Meta, _>(conn)?,
             }
             .map(map_row)
             .collect::<Result<_, Error>>()?;
 
+            // Joins yield a package once per matching provider or dependency
+            let mut ids = Vec::with_capacity(rows.len());
+            let mut entries = BTreeMap::new();
+            for (id, meta) in rows {
+                if let btree_map::Entry::Vacant(entry) = entries.entry(id.clone()) {
+                    entry.insert(meta);
+                    ids.push(id);
+                }
+            }
+
             let package_ids = entries
                 .keys()
                 .map(|id| model::PackageId { id: id.to_string() })
@@ -181,6 +293,7 @@ impl Database {
             for chunk in package_ids.chunks(MAX_VARIABLE_NUMBER) {
                 // Add licenses
                 model::License::belonging_to(chunk)
+                    .order_by(model::meta_licenses::license)
                     .load_iter::<model::License, _>(conn)?
                     .try_for_each::<_, Result<_, Error>>(|result| {
                         let row = result?;
@@ -224,7 +337,144 @@ impl Database {
                     })?;
             }
 
-            Ok(entries.into_iter().collect())
+            Ok(ids.into_iter().filter_map(|id| entries.remove_entry(&id)).collect())
+        })
+    }
+
+    /// Returns packages whose name, summary or description contain every
+    /// word of `text`, best matches first
+    ///
+    /// Name matches rank above summary matches, which rank above description
+    /// matches. Within each, the full text index ranks by relevance.
+    pub fn search(&self, text: &str) -> Result<Vec<(package::Id, Meta)>, Error> {
+        if text.split_whitespace().next().is_none() {
+            return Ok(vec![]);
+        }
+
+        let candidates = if self.fts {
+            self.search_index(text)?
+        } else {
+            self.search_like(text)?
+        };
+
+        candidates
+            .into_iter()
+            .map(|id| {
+                let meta = self.get(&id)?;
+                Ok((id, meta))
+            })
+            .collect::<Result<Vec<_>, Error>>()
+            .map(|mut results| {
+                // Stable, so relevance is kept within each kind of match
+                results.sort_by_key(|(_, meta)| meta.search_match(text).unwrap_or(package::SearchMatch::Description));
+                results
+            })
+    }
+
+    /// Candidate ids from the full text index, ordered by relevance
+    fn search_index(&self, text: &str) -> Result<Vec<package::Id>, Error> {
+        // Quote each word to escape FTS syntax, matching as a prefix
+        let query = text
+            .split_whitespace()
+            .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        self.conn.exec(|conn| {
+            Ok(diesel::sql_query(
+                "SELECT meta.package FROM meta_search \
+                 JOIN meta ON meta.rowid = meta_search.rowid \
+                 WHERE meta_search MATCH ? \
+                 ORDER BY bm25(meta_search, 10.0, 5.0, 1.0), meta.name, meta.package",
+            )
+            .bind::<Text, _>(query)
+            .load::<SearchRow>(conn)?
+            .into_iter()
+            .map(|row| package::Id::from(row.package))
+            .collect())
+        })
+    }
+
+    /// Candidate ids from a `LIKE` scan, used without the full text index
+    fn search_like(&self, text: &str) -> Result<Vec<package::Id>, Error> {
+        self.conn.exec(|conn| {
+            let mut query = model::meta::table
+                .select(model::meta::package)
+                .order_by((model::meta::name, model::meta::package))
+                .into_boxed();
+
+            for word in text.split_whitespace() {
+                let pattern = format!("%{word}%");
+                query = query.filter(
+                    model::meta::name
+                        .like(pattern.clone())
+                        .or(model::meta::summary.like(pattern.clone()))
+                        .or(model::meta::description.like(pattern)),
+                );
+            }
+
+            Ok(query
+                .load_iter::<AStr, _>(conn)?
+                .map(|result| result.map(package::Id::from))
+                .collect::<Result<_, _>>()?)
+        })
+    }
+
+    /// Distinct package names starting with `prefix`, in order & capped to `limit`
+    ///
+    /// Queried as a range rather than `LIKE`, so the `meta_name` index is used
+    pub fn names_with_prefix(&self, prefix: &str, limit: usize) -> Result<Vec<package::Name>, Error> {
+        // Sorts after any name starting with `prefix`
+        let upper = format!("{prefix}\u{10FFFF}");
+
+        self.conn.exec(|conn| {
+            Ok(model::meta::table
+                .select(model::meta::name)
+                .filter(model::meta::name.ge(prefix))
+                .filter(model::meta::name.lt(upper))
+                .distinct()
+                .order_by(model::meta::name)
+                .limit(limit as i64)
+                .load_iter::<String, _>(conn)?
+                .map(|result| result.map(package::Name::from))
+                .collect::<Result<_, _>>()?)
+        })
+    }
+
+    /// Distinct names of `kind` providers starting with `prefix`, in order & capped to `limit`
+    ///
+    /// Package names are queried via [`Database::names_with_prefix`]
+    pub fn provider_names_with_prefix(
+        &self,
+        kind: dependency::Kind,
+        prefix: &str,
+        limit: usize,
+    ) -> Result<Vec<String>, Error> {
+        if kind == dependency::Kind::PackageName {
+            return Ok(self
+                .names_with_prefix(prefix, limit)?
+                .into_iter()
+                .map(String::from)
+                .collect());
+        }
+
+        let lower = format!("{kind}({prefix}");
+        let upper = format!("{lower}\u{10FFFF}");
+
+        self.conn.exec(|conn| {
+            Ok(model::meta_providers::table
+                .select(model::meta_providers::provider)
+                .filter(model::meta_providers::provider.ge(&lower))
+                .filter(model::meta_providers::provider.lt(upper))
+                .distinct()
+                .order_by(model::meta_providers::provider)
+                .limit(limit as i64)
+                .load_iter::<String, _>(conn)?
+                .filter_map(|result| match result {
+                    Ok(provider) => Provider::from_str(&provider).ok().map(|p| Ok(p.name)),
+                    Err(error) => Some(Err(error)),
+                })
+                .collect::<Result<_, _>>()?)
         })
     }
 
@@ -250,6 +500,22 @@ impl Database {
         })
     }
 
+    /// Returns the stone hashes of all packages, except the `excluded` packages
+    pub fn file_hashes_excluding(&self, excluded: &BTreeSet<package::Id>) -> Result<BTreeSet<String>, Error> {
+        self.conn.exec(|conn| {
+            let rows = model::meta::table
+                .select((model::meta::package, model::meta::hash.assume_not_null()))
+                .filter(model::meta::hash.is_not_null())
+                .load::<(AStr, String)>(conn)?;
+
+            Ok(rows
+                .into_iter()
+                .filter(|(package, _)| !excluded.contains(&package::Id::from(package.clone())))
+                .map(|(_, hash)| hash)
+                .collect())
+        })
+    }
+
     pub fn add(&self, id: package::Id, meta: Meta) -> Result<(), Error> {
         self.batch_add(vec![(id, meta)])
     }
@@ -257,95 +523,29 @@ impl Database {
     pub fn batch_add(&self, packages: Vec<(package::Id, Meta)>) -> Result<(), Error> {
         self.conn.exclusive_tx(|tx| {
             let ids = packages.iter().map(|(id, _)| id.as_str()).collect::<Vec<_>>();
-            let entries = packages
-                .iter()
-                .map(|(package, meta)| model::NewMeta {
-                    package: package.as_str(),
-                    name: meta.name.as_str(),
-                    version_identifier: &meta.version_identifier,
-                    source_release: meta.source_release as i32,
-                    build_release: meta.build_release as i32,
-                    architecture: &meta.architecture,
-                    summary: &meta.summary,
-                    description: &meta.description,
-                    source_id: &meta.source_id,
-                    homepage: &meta.homepage,
-                    uri: meta.uri.as_deref(),
-                    hash: meta.hash.as_deref(),
-                    download_size: meta.download_size.map(|size| size as i64),
-                })
-                .collect::<Vec<_>>();
-            let licenses = packages
-                .iter()
-                .flat_map(|(package, meta)| {
-                    meta.licenses.iter().map(|license| {
-                        (
-                            model::meta_licenses::package.eq(package.as_str()),
-                            model::meta_licenses::license.eq(license),
-                        )
-                    })
-                })
-                .collect::<Vec<_>>();
-            let dependencies = packages
-                .iter()
-                .flat_map(|(package, meta)| {
-                    meta.dependencies.iter().map(|dependency| {
-                        (
-                            model::meta_dependencies::package.eq(package.as_str()),
-                            model::meta_dependencies::dependency.eq(dependency.to_string()),
-                        )
-                    })
-                })
-                .collect::<Vec<_>>();
-            let providers = packages
-                .iter()
-                .flat_map(|(package, meta)| {
-                    meta.providers.iter().map(|provider| {
-                        (
-                            model::meta_providers::package.eq(package.as_str()),
-                            model::meta_providers::provider.eq(provider.to_string()),
-                        )
-                    })
-                })
-                .collect::<Vec<_>>();
-            let conflicts = packages
-                .iter()
-                .flat_map(|(package, meta)| {
-                    meta.conflicts.iter().map(|conflict| {
-                        (
-                            model::meta_conflicts::package.eq(package.as_str()),
-                            model::meta_conflicts::conflict.eq(conflict.to_string()),
-                        )
-                    })
-                })
-                .collect::<Vec<_>>();
-
             batch_remove_impl(&ids, tx)?;
+            batch_insert_impl(&packages, tx)
+        })
+    }
 
-            for chunk in entries.chunks(MAX_VARIABLE_NUMBER / 13) {
-                diesel::insert_into(model::meta::table).values(chunk).execute(tx)?;
-            }
-            for chunk in licenses.chunks(MAX_VARIABLE_NUMBER / 2) {
-                diesel::insert_or_ignore_into(model::meta_licenses::table)
-                    .values(chunk)
-                    .execute(tx)?;
-            }
-            for chunk in dependencies.chunks(MAX_VARIABLE_NUMBER / 2) {
-                diesel::insert_or_ignore_into(model::meta_dependencies::table)
-                    .values(chunk)
-                    .execute(tx)?;
-            }
-            for chunk in providers.chunks(MAX_VARIABLE_NUMBER / 2) {
-                diesel::insert_or_ignore_into(model::meta_providers::table)
-                    .values(chunk)
-                    .execute(tx)?;
-            }
-            for chunk in conflicts.chunks(MAX_VARIABLE_NUMBER / 2) {
-                diesel::insert_or_ignore_into(model::meta_conflicts::table)
-                    .values(chunk)
-                    .execute(tx)?;
+    /// Replace all packages with `packages` of the index hashed `index_hash`,
+    /// inserted [`REPLACE_CHUNK_SIZE`] at a time
+    ///
+    /// Wiping & inserting happens in a single transaction, so the database is
+    /// never left empty when interrupted
+    pub fn replace_all(&self, packages: Vec<(package::Id, Meta)>, index_hash: &str) -> Result<(), Error> {
+        self.conn.exclusive_tx(|tx| {
+            // Cascading wipes other tables
+            diesel::delete(model::meta::table).execute(tx)?;
+
+            for chunk in packages.chunks(REPLACE_CHUNK_SIZE) {
+                batch_insert_impl(chunk, tx)?;
             }
 
+            diesel::replace_into(model::meta_index::table)
+                .values((model::meta_index::id.eq(0), model::meta_index::hash.eq(index_hash)))
+                .execute(tx)?;
+
             Ok(())
         })
     }
@@ -363,6 +563,133 @@ impl Database {
     }
 }
 
+/// Create the search index if missing, returning whether it's available
+fn create_search_index(conn: &mut SqliteConnection) -> Result<bool, Error> {
+    let exists = diesel::sql_query("SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'meta_search'")
+        .load::<TableName>(conn)?
+        .into_iter()
+        .any(|table| table.name == "meta_search");
+
+    if exists {
+        return Ok(true);
+    }
+
+    match diesel::Connection::transaction(conn, |conn| conn.batch_execute(SEARCH_INDEX)) {
+        Ok(()) => Ok(true),
+        Err(error) => {
+            log::warn!("full text search unavailable, falling back to LIKE: {error}");
+            Ok(false)
+        }
+    }
+}
+
+#[derive(QueryableByName)]
+struct TableName {
+    #[diesel(sql_type = Text)]
+    name: String,
+}
+
+#[derive(QueryableByName)]
+struct SearchRow {
+    #[diesel(sql_type = Text)]
+    package: String,
+}
+
+fn batch_insert_impl(packages: &[(package::Id, Meta)], tx: &mut SqliteConnection) -> Result<(), Error> {
+    let entries = packages
+        .iter()
+        .map(|(package, meta)| model::NewMeta {
+            package: package.as_str(),
+            name: meta.name.as_str(),
+            version_identifier: &meta.version_identifier,
+            source_release: meta.source_release as i32,
+            build_release: meta.build_release as i32,
+            architecture: &meta.architecture,
+            summary: &meta.summary,
+            description: &meta.description,
+            source_id: &meta.source_id,
+            homepage: &meta.homepage,
+            uri: meta.uri.as_deref(),
+            hash: meta.hash.as_deref(),
+            download_size: meta.download_size.map(|size| size as i64),
+            builder: meta.provenance.builder.as_deref(),
+            build_host: meta.provenance.host.as_deref(),
+            build_time: meta.provenance.time.map(|time| time as i64),
+            build_profile: meta.provenance.profile.as_deref(),
+        })
+        .collect::<Vec<_>>();
+    let licenses = packages
+        .iter()
+        .flat_map(|(package, meta)| {
+            meta.licenses.iter().map(|license| {
+                (
+                    model::meta_licenses::package.eq(package.as_str()),
+                    model::meta_licenses::license.eq(license),
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+    let dependencies = packages
+        .iter()
+        .flat_map(|(package, meta)| {
+            meta.dependencies.iter().map(|dependency| {
+                (
+                    model::meta_dependencies::package.eq(package.as_str()),
+                    model::meta_dependencies::dependency.eq(dependency.to_string()),
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+    let providers = packages
+        .iter()
+        .flat_map(|(package, meta)| {
+            meta.providers.iter().map(|provider| {
+                (
+                    model::meta_providers::package.eq(package.as_str()),
+                    model::meta_providers::provider.eq(provider.to_string()),
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+    let conflicts = packages
+        .iter()
+        .flat_map(|(package, meta)| {
+            meta.conflicts.iter().map(|conflict| {
+                (
+                    model::meta_conflicts::package.eq(package.as_str()),
+                    model::meta_conflicts::conflict.eq(conflict.to_string()),
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for chunk in entries.chunks(MAX_VARIABLE_NUMBER / 13) {
+        diesel::insert_into(model::meta::table).values(chunk).execute(tx)?;
+    }
+    for chunk in licenses.chunks(MAX_VARIABLE_NUMBER / 2) {
+        diesel::insert_or_ignore_into(model::meta_licenses::table)
+            .values(chunk)
+            .execute(tx)?;
+    }
+    for chunk in dependencies.chunks(MAX_VARIABLE_NUMBER / 2) {
+        diesel::insert_or_ignore_into(model::meta_dependencies::table)
+            .values(chunk)
+            .execute(tx)?;
+    }
+    for chunk in providers.chunks(MAX_VARIABLE_NUMBER / 2) {
+        diesel::insert_or_ignore_into(model::meta_providers::table)
+            .values(chunk)
+            .execute(tx)?;
+    }
+    for chunk in conflicts.chunks(MAX_VARIABLE_NUMBER / 2) {
+        diesel::insert_or_ignore_into(model::meta_conflicts::table)
+            .values(chunk)
+            .execute(tx)?;
+    }
+
+    Ok(())
+}
+
 fn batch_remove_impl(packages: &[&str], tx: &mut SqliteConnection) -> Result<(), Error> {
     for chunk in packages.chunks(MAX_VARIABLE_NUMBER) {
         diesel::delete(model::meta::table.filter(model::meta::package.eq_any(chunk))).execute(tx)?;
@@ -378,7 +705,9 @@ mod model {
         prelude::Insertable,
     };
 
-    pub use crate::db::meta::schema::{meta, meta_conflicts, meta_dependencies, meta_licenses, meta_providers};
+    pub use crate::db::meta::schema::{
+        meta, meta_conflicts, meta_dependencies, meta_index, meta_licenses, meta_providers,
+    };
     use crate::package;
 
     #[derive(Queryable, Selectable, Identifiable)]
@@ -399,6 +728,21 @@ mod model {
         pub uri: Option<String>,
         pub hash: Option<String>,
         pub download_size: Option<i64>,
+        pub builder: Option<String>,
+        pub build_host: Option<String>,
+        pub build_time: Option<i64>,
+        pub build_profile: Option<String>,
+    }
+
+    impl Meta {
+        pub fn provenance(&self) -> package::meta::Provenance {
+            package::meta::Provenance {
+                builder: self.builder.clone(),
+                host: self.build_host.clone(),
+                time: self.build_time.map(|time| time as u64),
+                profile: self.build_profile.clone(),
+            }
+        }
     }
 
     #[derive(Queryable, Selectable, Identifiable)]
@@ -468,6 +812,10 @@ mod model {
         pub uri: Option<&'a str>,
         pub hash: Option<&'a str>,
         pub download_size: Option<i64>,
+        pub builder: Option<&'a str>,
+        pub build_host: Option<&'a str>,
+        pub build_time: Option<i64>,
+        pub build_profile: Option<&'a str>,
     }
 }
 
@@ -559,4 +907,314 @@ mod test {
         // correctly.
         assert_eq!(retrieved_conflicts, vec![&pineapple_provider]);
     }
+
+    #[test]
+    fn search_ranking() {
+        let bash_completion = include_bytes!("../../../../test/bash-completion-2.11-1-1-x86_64.stone");
+        let mut stone = stone::read_bytes(bash_completion).unwrap();
+        let payloads = stone.payloads().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        let meta_payload = payloads.iter().find_map(StoneDecodedPayload::meta).unwrap();
+        let template = Meta::from_stone_payload(&meta_payload.body).unwrap();
+
+        let package = |name: &str, summary: &str, description: &str| {
+            (
+                package::Id::from(name.to_owned()),
+                Meta {
+                    name: name.to_owned().into(),
+                    summary: summary.to_owned(),
+                    description: description.to_owned(),
+                    ..template.clone()
+                },
+            )
+        };
+        let names = |results: Vec<(package::Id, Meta)>| {
+            results
+                .into_iter()
+                .map(|(_, meta)| meta.name.as_str().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        let fts = Database::new(":memory:").unwrap();
+        assert!(fts.fts);
+        let like = Database {
+            fts: false,
+            ..Database::new(":memory:").unwrap()
+        };
+
+        for db in [fts, like] {
+            db.batch_add(vec![
+                package(
+                    "yq",
+                    "Portable YAML processor",
+                    "Like jq, but for YAML and JSON documents",
+                ),
+                package(
+                    "jq",
+                    "Command-line JSON processor",
+                    "Slice, filter and map structured data",
+                ),
+                package("json-c", "JSON implementation in C", "Reference counting object model"),
+            ])
+            .unwrap();
+
+            // Name, then summary, then description matches
+            assert_eq!(names(db.search("json").unwrap()), vec!["json-c", "jq", "yq"]);
+            // Every word must match, across any of the fields
+            assert_eq!(names(db.search("JSON filter").unwrap()), vec!["jq"]);
+            // Words match as prefixes, both are summary matches so order is by relevance
+            let mut prefix = names(db.search("proc").unwrap());
+            prefix.sort();
+            assert_eq!(prefix, vec!["jq", "yq"]);
+            assert!(db.search("toml").unwrap().is_empty());
+            assert!(db.search("  ").unwrap().is_empty());
+
+            // Index follows removals, updates & vacuuming
+            db.remove(&package::Id::from("jq")).unwrap();
+            db.batch_add(vec![package("json-c", "A JSON library for C", "")])
+                .unwrap();
+            db.vacuum().unwrap();
+            assert_eq!(names(db.search("json").unwrap()), vec!["json-c", "yq"]);
+            assert_eq!(names(db.search("library").unwrap()), vec!["json-c"]);
+            assert!(db.search("reference").unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn name_prefix() {
+        let bash_completion = include_bytes!("../../../../test/bash-completion-2.11-1-1-x86_64.stone");
+        let mut stone = stone::read_bytes(bash_completion).unwrap();
+        let payloads = stone.payloads().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        let meta_payload = payloads.iter().find_map(StoneDecodedPayload::meta).unwrap();
+        let template = Meta::from_stone_payload(&meta_payload.body).unwrap();
+
+        let db = Database::new(":memory:").unwrap();
+        db.batch_add(
+            [
+                ("bash-1", "bash"),
+                ("bash-2", "bash"),
+                ("bash-completion", "bash-completion"),
+                ("bc", "bc"),
+                ("nano", "nano"),
+            ]
+            .into_iter()
+            .map(|(id, name)| {
+                (
+                    package::Id::from(id.to_owned()),
+                    Meta {
+                        name: name.to_owned().into(),
+                        ..template.clone()
+                    },
+                )
+            })
+            .collect(),
+        )
+        .unwrap();
+
+        let names = |prefix, limit| {
+            db.names_with_prefix(prefix, limit)
+                .unwrap()
+                .into_iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        // Names are distinct & ordered
+        assert_eq!(names("ba", 10), ["bash", "bash-completion"]);
+        assert_eq!(names("b", 10), ["bash", "bash-completion", "bc"]);
+        assert_eq!(names("b", 2), ["bash", "bash-completion"]);
+        assert_eq!(names("", 10), ["bash", "bash-completion", "bc", "nano"]);
+        assert!(names("vim", 10).is_empty());
+        // Prefixes are case sensitive, like package names
+        assert!(names("BA", 10).is_empty());
+
+        // The range query is served by the name index
+        let plan = db
+            .conn
+            .exec(|conn| {
+                Ok::<_, Error>(
+                    diesel::sql_query(
+                        "EXPLAIN QUERY PLAN SELECT DISTINCT name FROM meta WHERE name >= 'b' AND name < 'c'",
+                    )
+                    .load::<QueryPlan>(conn)?,
+                )
+            })
+            .unwrap();
+        assert!(plan.iter().any(|row| row.detail.contains("meta_name")), "{plan:?}");
+    }
+
+    #[test]
+    fn provider_glob() {
+        let bash_completion = include_bytes!("../../../../test/bash-completion-2.11-1-1-x86_64.stone");
+        let mut stone = stone::read_bytes(bash_completion).unwrap();
+        let payloads = stone.payloads().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        let meta_payload = payloads.iter().find_map(StoneDecodedPayload::meta).unwrap();
+        let template = Meta::from_stone_payload(&meta_payload.body).unwrap();
+
+        let db = Database::new(":memory:").unwrap();
+        db.batch_add(
+            [
+                (
+                    "openssl",
+                    &["soname(libssl.so.3(x86_64))", "soname(libcrypto.so.3(x86_64))"][..],
+                ),
+                ("openssl-devel", &["pkgconfig(libssl)", "pkgconfig(openssl)"]),
+                ("zlib", &["soname(libz.so.1(x86_64))"]),
+                ("zlib-devel", &["pkgconfig(zlib)", "pkgconfig32(zlib)"]),
+                ("weird", &["binary([bracket])"]),
+            ]
+            .into_iter()
+            .map(|(name, providers)| {
+                let mut providers = providers
+                    .iter()
+                    .map(|provider| Provider::from_str(provider).unwrap())
+                    .collect::<BTreeSet<_>>();
+                providers.insert(Provider::package_name(name));
+                (
+                    package::Id::from(name.to_owned()),
+                    Meta {
+                        name: name.to_owned().into(),
+                        providers,
+                        ..template.clone()
+                    },
+                )
+            })
+            .collect(),
+        )
+        .unwrap();
+
+        let names = |pattern| {
+            let glob = ProviderGlob::new(pattern);
+            let packages = db.query(Some(Filter::ProviderGlob(&glob))).unwrap();
+            // Matched in memory the same way
+            for (_, meta) in &packages {
+                assert!(
+                    meta.providers.iter().any(|provider| glob.matches(provider)),
+                    "{pattern}"
+                );
+            }
+            packages
+                .into_iter()
+                .map(|(_, meta)| meta.name.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names("soname(libssl.so.*)"), ["openssl"]);
+        assert_eq!(names("soname(lib*.so.?(x86_64))"), ["openssl", "zlib"]);
+        // Matched once however many providers match
+        assert_eq!(names("*ssl*"), ["openssl", "openssl-devel"]);
+        assert_eq!(names("pkgconfig(zlib)"), ["zlib-devel"]);
+        assert_eq!(names("pkgconfig*(zlib)"), ["zlib-devel"]);
+        // Without a kind, package names are matched
+        assert_eq!(names("zlib*"), ["zlib", "zlib-devel"]);
+        assert_eq!(names("*(zlib)"), ["zlib", "zlib-devel"]);
+        // Brackets are literal, as are other characters
+        assert_eq!(names("binary([bracket])"), ["weird"]);
+        assert!(names("binary([b]racket)").is_empty());
+        assert!(names("soname(LIBSSL*)").is_empty());
+        assert!(names("soname(libssl.so)").is_empty());
+    }
+
+    #[test]
+    fn query_ordering() {
+        let bash_completion = include_bytes!("../../../../test/bash-completion-2.11-1-1-x86_64.stone");
+        let mut stone = stone::read_bytes(bash_completion).unwrap();
+        let payloads = stone.payloads().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        let meta_payload = payloads.iter().find_map(StoneDecodedPayload::meta).unwrap();
+        let template = Meta::from_stone_payload(&meta_payload.body).unwrap();
+
+        let db = Database::new(":memory:").unwrap();
+        // Ids sort differently to the names, & rows are inserted in neither order
+        db.batch_add(
+            [("c", "zlib", 3), ("a", "nano", 1), ("d", "bash", 1), ("b", "nano", 2)]
+                .into_iter()
+                .map(|(id, name, release)| {
+                    (
+                        package::Id::from(id.to_owned()),
+                        Meta {
+                            name: name.to_owned().into(),
+                            source_release: release,
+                            licenses: vec!["MIT".to_owned(), "GPL-2.0-or-later".to_owned(), "Apache-2.0".to_owned()],
+                            providers: BTreeSet::from([Provider::package_name("editor")]),
+                            ..template.clone()
+                        },
+                    )
+                })
+                .collect(),
+        )
+        .unwrap();
+
+        let packages = db.query(None).unwrap();
+        // By name, then newest release first
+        assert_eq!(
+            packages
+                .iter()
+                .map(|(id, meta)| format!("{id} {}-{}", meta.name, meta.source_release))
+                .collect::<Vec<_>>(),
+            ["d bash-1", "b nano-2", "a nano-1", "c zlib-3"]
+        );
+
+        let licenses = ["Apache-2.0", "GPL-2.0-or-later", "MIT"];
+        for (id, meta) in &packages {
+            assert_eq!(meta.licenses, licenses);
+            assert_eq!(db.get(id).unwrap().licenses, licenses);
+        }
+
+        // Joins keep the order
+        let names = db
+            .query(Some(Filter::Provider(Provider::package_name("editor"))))
+            .unwrap()
+            .into_iter()
+            .map(|(id, _)| id.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["d", "b", "a", "c"]);
+
+        assert_eq!(
+            db.provider_packages(&Provider::package_name("editor")).unwrap(),
+            ["a", "b", "c", "d"].map(|id| package::Id::from(id.to_owned()))
+        );
+    }
+
+    #[derive(Debug, QueryableByName)]
+    struct QueryPlan {
+        #[diesel(sql_type = Text)]
+        detail: String,
+    }
+
+    #[test]
+    fn check_truncated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("install.db");
+        let url = path.to_str().unwrap();
+
+        let bash_completion = include_bytes!("../../../../test/bash-completion-2.11-1-1-x86_64.stone");
+        let mut stone = stone::read_bytes(bash_completion).unwrap();
+        let payloads = stone.payloads().unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        let meta_payload = payloads.iter().find_map(StoneDecodedPayload::meta).unwrap();
+        let meta = Meta::from_stone_payload(&meta_payload.body).unwrap();
+
+        {
+            let db = Database::new(url).unwrap();
+            db.batch_add(
+                (0..256)
+                    .map(|i| (package::Id::from(format!("test-{i}")), meta.clone()))
+                    .collect(),
+            )
+            .unwrap();
+            db.check().unwrap();
+            db.vacuum().unwrap();
+            db.check().unwrap();
+        }
+
+        // Lose the tail of the file, as an unclean shutdown might
+        let file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        let len = file.metadata().unwrap().len();
+        file.set_len(len / 2).unwrap();
+        drop(file);
+
+        let result = Database::new(url).and_then(|db| db.check());
+        assert!(
+            matches!(&result, Err(Error::Corrupt { db, details }) if db == url && !details.is_empty()),
+            "{result:?}"
+        );
+    }
 }
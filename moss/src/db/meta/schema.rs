@@ -15,6 +15,10 @@ diesel::table! {
         uri -> Nullable<Text>,
         hash -> Nullable<Text>,
         download_size -> Nullable<BigInt>,
+        builder -> Nullable<Text>,
+        build_host -> Nullable<Text>,
+        build_time -> Nullable<BigInt>,
+        build_profile -> Nullable<Text>,
     }
 }
 
@@ -32,6 +36,13 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    meta_index (id) {
+        id -> Integer,
+        hash -> Text,
+    }
+}
+
 diesel::table! {
     meta_licenses (package, license) {
         package -> Text,
@@ -51,4 +62,11 @@ diesel::joinable!(meta_dependencies -> meta (package));
 diesel::joinable!(meta_licenses -> meta (package));
 diesel::joinable!(meta_providers -> meta (package));
 
-diesel::allow_tables_to_appear_in_same_query!(meta, meta_conflicts, meta_dependencies, meta_licenses, meta_providers);
+diesel::allow_tables_to_appear_in_same_query!(
+    meta,
+    meta_conflicts,
+    meta_dependencies,
+    meta_index,
+    meta_licenses,
+    meta_providers,
+);
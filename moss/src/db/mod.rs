@@ -2,14 +2,23 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use std::{
-    fmt,
+    env, fmt,
+    path::Path,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use chrono::{DateTime, Utc};
-use diesel::SqliteConnection;
+use diesel::{
+    Connection as _, QueryableByName, RunQueryDsl, SqliteConnection,
+    connection::SimpleConnection,
+    sql_types::{BigInt, Nullable, Text},
+};
+use nix::unistd::{AccessFlags, access};
 use thiserror::Error;
 
+use crate::environment;
+
 pub mod layout;
 pub mod meta;
 pub mod state;
@@ -20,15 +29,48 @@ pub mod state;
 const MAX_VARIABLE_NUMBER: usize = 32766;
 
 #[derive(Clone)]
-struct Connection(Arc<Mutex<SqliteConnection>>);
+struct Connection {
+    inner: Arc<Mutex<SqliteConnection>>,
+    /// Database the connection was established with
+    url: Arc<str>,
+}
 
 impl Connection {
-    fn new(connection: SqliteConnection) -> Self {
-        Self(Arc::new(Mutex::new(connection)))
+    /// Connect to `url`, configured so readers aren't blocked by a writer in
+    /// another process and writers wait on each other rather than failing
+    fn new(url: &str) -> Result<Self, Error> {
+        let writable = writable(url);
+
+        // Those who can't write the database, i.e. unprivileged users listing
+        // packages, can't create the files sqlite keeps next to it in WAL mode
+        let mut connection = if !writable && Path::new(url).exists() {
+            SqliteConnection::establish(&read_only(url))?
+        } else {
+            SqliteConnection::establish(url)?
+        };
+
+        // Switching the journal mode writes the database, so those who can't keep the current mode
+        if writable {
+            connection
+                .batch_execute("PRAGMA journal_mode = WAL;")
+                .map_err(|error| corrupt_or(url, error))?;
+        }
+
+        connection
+            .batch_execute(&format!(
+                "PRAGMA synchronous = NORMAL; PRAGMA busy_timeout = {};",
+                busy_timeout().as_millis()
+            ))
+            .map_err(|error| corrupt_or(url, error))?;
+
+        Ok(Self {
+            inner: Arc::new(Mutex::new(connection)),
+            url: url.into(),
+        })
     }
 
     fn exec<T>(&self, f: impl FnOnce(&mut SqliteConnection) -> T) -> T {
-        let mut _guard = self.0.lock().expect("mutex guard");
+        let mut _guard = self.inner.lock().expect("mutex guard");
         f(&mut _guard)
     }
 
@@ -36,9 +78,86 @@ impl Connection {
     where
         E: From<diesel::result::Error>,
     {
-        let mut _guard = self.0.lock().expect("mutex guard");
+        let mut _guard = self.inner.lock().expect("mutex guard");
         _guard.exclusive_transaction(|tx| f(tx))
     }
+
+    /// Run sqlite's integrity & foreign key checks, returning [`Error::Corrupt`]
+    /// with the reported problems if any are found
+    fn check(&self) -> Result<(), Error> {
+        let details = self
+            .exec(|conn| {
+                let mut details = diesel::sql_query("PRAGMA integrity_check")
+                    .load::<IntegrityCheck>(conn)?
+                    .into_iter()
+                    .map(|row| row.integrity_check)
+                    .filter(|message| message != "ok")
+                    .collect::<Vec<_>>();
+
+                details.extend(
+                    diesel::sql_query("PRAGMA foreign_key_check")
+                        .load::<ForeignKeyCheck>(conn)?
+                        .into_iter()
+                        .map(|row| {
+                            format!(
+                                "row {} in {} references missing {} row",
+                                row.rowid.unwrap_or_default(),
+                                row.table,
+                                row.parent
+                            )
+                        }),
+                );
+
+                Ok(details)
+            })
+            .map_err(|error| corrupt_or(&self.url, error))?;
+
+        if details.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Corrupt {
+                db: self.url.to_string(),
+                details,
+            })
+        }
+    }
+
+    /// Rebuild the database file, reclaiming unused pages
+    fn vacuum(&self) -> Result<(), Error> {
+        self.exec(|conn| conn.batch_execute("VACUUM; PRAGMA wal_checkpoint(TRUNCATE);"))
+            .map_err(|error| corrupt_or(&self.url, error))
+    }
+}
+
+#[derive(QueryableByName)]
+struct IntegrityCheck {
+    #[diesel(sql_type = Text)]
+    integrity_check: String,
+}
+
+#[derive(QueryableByName)]
+struct ForeignKeyCheck {
+    #[diesel(sql_type = Text)]
+    table: String,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    rowid: Option<i64>,
+    #[diesel(sql_type = Text)]
+    parent: String,
+}
+
+/// Map sqlite reporting a malformed database file to [`Error::Corrupt`]
+fn corrupt_or(db: &str, error: diesel::result::Error) -> Error {
+    match &error {
+        diesel::result::Error::DatabaseError(_, info)
+            if info.message().contains("malformed") || info.message().contains("not a database") =>
+        {
+            Error::Corrupt {
+                db: db.to_owned(),
+                details: vec![info.message().to_owned()],
+            }
+        }
+        _ => Error::Diesel(error),
+    }
 }
 
 impl fmt::Debug for Connection {
@@ -47,6 +166,42 @@ impl fmt::Debug for Connection {
     }
 }
 
+/// Whether the database file at `url` and its directory, where sqlite keeps
+/// the write-ahead log, can be written
+fn writable(url: &str) -> bool {
+    let path = Path::new(url);
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+
+    access(path, AccessFlags::W_OK).is_ok() && access(dir, AccessFlags::W_OK).is_ok()
+}
+
+/// URI opening the database file at `url` read-only
+///
+/// Reading a database in WAL mode needs its shared memory index, which is
+/// only around while a writer has it open. Without one, nothing is writing
+/// and the database is opened as immutable, so no index is needed.
+fn read_only(url: &str) -> String {
+    let path = url.replace('%', "%25").replace('?', "%3f").replace('#', "%23");
+
+    if Path::new(&format!("{url}-shm")).exists() {
+        format!("file:{path}?mode=ro")
+    } else {
+        format!("file:{path}?mode=ro&immutable=1")
+    }
+}
+
+/// How long to wait on a database locked by another moss process
+fn busy_timeout() -> Duration {
+    env::var(environment::DB_BUSY_TIMEOUT_VAR)
+        .ok()
+        .and_then(|millis| millis.parse().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(environment::DB_BUSY_TIMEOUT)
+}
+
 pub struct Timestamp(pub DateTime<Utc>);
 
 impl TryFrom<i64> for Timestamp {
@@ -65,6 +220,9 @@ pub enum Error {
     RowNotFound,
     #[error("failed to decode layout entry")]
     LayoutEntryDecode,
+    /// sqlite found the database to be corrupt
+    #[error("database {db} is corrupt: {}", details.join("; "))]
+    Corrupt { db: String, details: Vec<String> },
     #[error("invalid timestamp: {0}")]
     InvalidTimestamp(i64),
     #[error("diesel")]
@@ -74,3 +232,81 @@ pub enum Error {
     #[error("diesel migration")]
     Migration(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(QueryableByName)]
+    struct JournalMode {
+        #[diesel(sql_type = Text)]
+        journal_mode: String,
+    }
+
+    #[test]
+    fn journal_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = dir.path().join("install");
+        let url = url.to_str().unwrap();
+
+        let mode = |connection: &Connection| {
+            connection
+                .exec(|conn| diesel::sql_query("PRAGMA journal_mode").get_result::<JournalMode>(conn))
+                .unwrap()
+                .journal_mode
+        };
+
+        assert_eq!(mode(&Connection::new(url).unwrap()), "wal");
+        assert!(writable(url));
+
+        // Nothing to write, i.e. in memory or without access, keeps the mode
+        assert!(!writable(":memory:"));
+        assert!(!writable(dir.path().join("missing/install").to_str().unwrap()));
+        assert_eq!(mode(&Connection::new(":memory:").unwrap()), "memory");
+    }
+
+    #[derive(QueryableByName)]
+    struct Value {
+        #[diesel(sql_type = Text)]
+        value: String,
+    }
+
+    #[test]
+    fn read_only_wal() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = dir.path().join("install");
+        let url = url.to_str().unwrap();
+        let shm = dir.path().join("install-shm");
+
+        let read = || {
+            let mut conn = SqliteConnection::establish(&read_only(url)).unwrap();
+            let values = diesel::sql_query("SELECT value FROM entries")
+                .load::<Value>(&mut conn)
+                .unwrap()
+                .into_iter()
+                .map(|row| row.value)
+                .collect::<Vec<_>>();
+            let write = conn.batch_execute("INSERT INTO entries VALUES ('c');");
+            (values, write.is_err())
+        };
+
+        let writer = Connection::new(url).unwrap();
+        writer
+            .exec(|conn| conn.batch_execute("CREATE TABLE entries (value TEXT); INSERT INTO entries VALUES ('a');"))
+            .unwrap();
+
+        // Shares the index of the open writer, seeing what it committed
+        assert!(shm.exists());
+        assert_eq!(read(), (vec!["a".to_owned()], true));
+
+        writer
+            .exec(|conn| conn.batch_execute("INSERT INTO entries VALUES ('b');"))
+            .unwrap();
+        drop(writer);
+
+        // The closed writer removed its index, which isn't created to read
+        assert!(!shm.exists());
+        assert_eq!(read(), (vec!["a".to_owned(), "b".to_owned()], true));
+        assert!(!shm.exists());
+    }
+}
@@ -0,0 +1,5 @@
+-- SPDX-FileCopyrightText: 2026 AerynOS Developers
+-- SPDX-License-Identifier: MPL-2.0
+
+DROP TABLE IF EXISTS history_changes;
+DROP TABLE IF EXISTS history;
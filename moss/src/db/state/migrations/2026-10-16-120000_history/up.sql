@@ -0,0 +1,18 @@
+-- SPDX-FileCopyrightText: 2026 AerynOS Developers
+-- SPDX-License-Identifier: MPL-2.0
+
+CREATE TABLE IF NOT EXISTS history (
+    state_id INTEGER NOT NULL PRIMARY KEY,
+    command TEXT NOT NULL,
+    uid INTEGER NOT NULL,
+    username TEXT NULL,
+    FOREIGN KEY(state_id) REFERENCES state(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS history_changes (
+    state_id INTEGER NOT NULL,
+    package_id TEXT NOT NULL,
+    added BOOLEAN NOT NULL,
+    PRIMARY KEY(state_id, package_id),
+    FOREIGN KEY(state_id) REFERENCES history(state_id) ON DELETE CASCADE
+);
@@ -0,0 +1,10 @@
+-- SPDX-FileCopyrightText: 2026 AerynOS Developers
+-- SPDX-License-Identifier: MPL-2.0
+
+CREATE TABLE IF NOT EXISTS state_retained (
+    state_id INTEGER NOT NULL,
+    package_id TEXT NOT NULL,
+    path TEXT NOT NULL,
+    PRIMARY KEY(state_id, package_id, path),
+    FOREIGN KEY(state_id) REFERENCES state(id) ON DELETE CASCADE
+);
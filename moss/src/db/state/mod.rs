@@ -3,13 +3,12 @@
 
 use chrono::{DateTime, Utc};
 use diesel::prelude::*;
-use diesel::{Connection as _, SqliteConnection};
 use diesel_migrations::{EmbeddedMigrations, MigrationHarness, embed_migrations};
 use itertools::Itertools;
 
 use super::{Connection, Error, MAX_VARIABLE_NUMBER};
-use crate::State;
-use crate::state::{self, Id, Selection};
+use crate::state::{self, Id, Retained, Selection};
+use crate::{State, package};
 
 const MIGRATIONS: EmbeddedMigrations = embed_migrations!("src/db/state/migrations");
 
@@ -22,13 +21,25 @@ pub struct Database {
 
 impl Database {
     pub fn new(url: &str) -> Result<Self, Error> {
-        let mut conn = SqliteConnection::establish(url)?;
+        let conn = Connection::new(url)?;
 
-        conn.run_pending_migrations(MIGRATIONS).map_err(Error::Migration)?;
+        conn.exec(|conn| {
+            conn.run_pending_migrations(MIGRATIONS)
+                .map(drop)
+                .map_err(Error::Migration)
+        })?;
 
-        Ok(Database {
-            conn: Connection::new(conn),
-        })
+        Ok(Database { conn })
+    }
+
+    /// Check the database for corruption, see [`Error::Corrupt`]
+    pub fn check(&self) -> Result<(), Error> {
+        self.conn.check()
+    }
+
+    /// Rebuild the database, reclaiming unused space
+    pub fn vacuum(&self) -> Result<(), Error> {
+        self.conn.vacuum()
     }
 
     pub fn list_ids(&self) -> Result<Vec<(Id, DateTime<Utc>)>, Error> {
@@ -64,6 +75,13 @@ impl Database {
                     )
                 })
                 .into_group_map();
+            let mut retained = model::state_retained::table
+                .select(model::Retained::as_select())
+                .order_by((model::state_retained::package_id, model::state_retained::path))
+                .load::<model::Retained>(conn)?
+                .into_iter()
+                .map(|row| (Id::from(row.state_id), row))
+                .into_group_map();
 
             Ok(states
                 .into_iter()
@@ -75,6 +93,7 @@ impl Database {
                         summary: state.summary,
                         description: state.description,
                         selections,
+                        retained: group_retained(retained.remove(&id).unwrap_or_default()),
                         created: state.created.0,
                         kind: state.kind,
                     }
@@ -101,12 +120,17 @@ impl Database {
                     })
                 })
                 .collect::<Result<_, Error>>()?;
+            let retained = model::Retained::belonging_to(&state)
+                .select(model::Retained::as_select())
+                .order_by((model::state_retained::package_id, model::state_retained::path))
+                .load(conn)?;
 
             Ok(State {
                 id: state.id.into(),
                 summary: state.summary,
                 description: state.description,
                 selections,
+                retained: group_retained(retained),
                 created: state.created.0,
                 kind: state.kind,
             })
@@ -116,6 +140,7 @@ impl Database {
     pub fn add(
         &self,
         selections: &[Selection],
+        retained: &[Retained],
         summary: Option<&str>,
         description: Option<&str>,
     ) -> Result<State, Error> {
@@ -148,11 +173,139 @@ impl Database {
                         .execute(tx)?;
                 }
 
+                let retained = retained
+                    .iter()
+                    .flat_map(|retained| {
+                        retained.paths.iter().map(|path| model::NewRetained {
+                            state_id: id,
+                            package_id: retained.package.as_str(),
+                            path,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                for chunk in retained.chunks(MAX_VARIABLE_NUMBER / 3) {
+                    diesel::insert_into(model::state_retained::table)
+                        .values(chunk)
+                        .execute(tx)?;
+                }
+
                 Ok(id.into())
             })
             .and_then(|id| self.get(id))
     }
 
+    pub fn set_description(&self, state: Id, description: Option<&str>) -> Result<(), Error> {
+        self.conn.exec(|conn| {
+            let updated = diesel::update(model::state::table.find(i32::from(state)))
+                .set(model::state::description.eq(description))
+                .execute(conn)?;
+
+            if updated == 0 {
+                return Err(Error::RowNotFound);
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Record the [`state::Origin`] and package delta of a newly created state
+    pub fn add_history(
+        &self,
+        state: Id,
+        origin: &state::Origin,
+        added: &[package::Id],
+        removed: &[package::Id],
+    ) -> Result<(), Error> {
+        self.conn.exclusive_tx(|tx| {
+            let state_id = i32::from(state);
+
+            diesel::insert_into(model::history::table)
+                .values(model::NewHistory {
+                    state_id,
+                    command: &origin.command,
+                    uid: origin.uid as i32,
+                    username: origin.username.as_deref(),
+                })
+                .execute(tx)?;
+
+            let changes = added
+                .iter()
+                .map(|package| (package, true))
+                .chain(removed.iter().map(|package| (package, false)))
+                .map(|(package, added)| model::NewChange {
+                    state_id,
+                    package_id: package.as_str(),
+                    added,
+                })
+                .collect::<Vec<_>>();
+
+            for chunk in changes.chunks(MAX_VARIABLE_NUMBER / 3) {
+                diesel::insert_into(model::history_changes::table)
+                    .values(chunk)
+                    .execute(tx)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Returns the [`state::History`] of all states, or only `state` if provided, in
+    /// chronological order
+    pub fn history(&self, state: Option<Id>) -> Result<Vec<state::History>, Error> {
+        self.conn.exec(|conn| {
+            let mut query = model::state::table
+                .left_join(model::history::table)
+                .select((model::State::as_select(), Option::<model::History>::as_select()))
+                .order_by((model::state::created, model::state::id))
+                .into_boxed();
+
+            if let Some(id) = state {
+                query = query.filter(model::state::id.eq(i32::from(id)));
+            }
+
+            let rows = query.load::<(model::State, Option<model::History>)>(conn)?;
+
+            let mut changes = model::history_changes::table
+                .select(model::Change::as_select())
+                .load::<model::Change>(conn)?
+                .into_iter()
+                .map(|change| (change.state_id, change))
+                .into_group_map();
+
+            Ok(rows
+                .into_iter()
+                .map(|(state, history)| {
+                    let (added, removed) =
+                        changes
+                            .remove(&state.id)
+                            .unwrap_or_default()
+                            .into_iter()
+                            .partition_map(|change| {
+                                if change.added {
+                                    itertools::Either::Left(change.package_id)
+                                } else {
+                                    itertools::Either::Right(change.package_id)
+                                }
+                            });
+
+                    state::History {
+                        state: state.id.into(),
+                        created: state.created.0,
+                        summary: state.summary,
+                        origin: history.map(|history| state::Origin {
+                            command: history.command,
+                            uid: history.uid as u32,
+                            username: history.username,
+                        }),
+                        added,
+                        removed,
+                    }
+                })
+                .collect())
+        })
+    }
+
     pub fn remove(&self, state: &Id) -> Result<(), Error> {
         self.batch_remove(Some(state))
     }
@@ -162,6 +315,9 @@ impl Database {
             let states = states.into_iter().map(|id| i32::from(*id)).collect::<Vec<_>>();
 
             for chunk in states.chunks(MAX_VARIABLE_NUMBER) {
+                diesel::delete(model::history_changes::table.filter(model::history_changes::state_id.eq_any(chunk)))
+                    .execute(tx)?;
+                diesel::delete(model::history::table.filter(model::history::state_id.eq_any(chunk))).execute(tx)?;
                 // Cascading wipes other tables
                 diesel::delete(model::state::table.filter(model::state::id.eq_any(chunk))).execute(tx)?;
             }
@@ -171,6 +327,18 @@ impl Database {
     }
 }
 
+/// Group the retained `rows` of a state, ordered by package & path, by their package
+fn group_retained(rows: Vec<model::Retained>) -> Vec<Retained> {
+    rows.into_iter()
+        .chunk_by(|row| row.package_id.clone())
+        .into_iter()
+        .map(|(package, rows)| Retained {
+            package,
+            paths: rows.map(|row| row.path).collect(),
+        })
+        .collect()
+}
+
 mod model {
     use astr::AStr;
     use diesel::{
@@ -183,7 +351,7 @@ mod model {
 
     use crate::{db::Timestamp, package, state::Kind};
 
-    pub use super::schema::{state, state_selections};
+    pub use super::schema::{history, history_changes, state, state_retained, state_selections};
 
     #[derive(Queryable, Selectable, Identifiable)]
     #[diesel(table_name = state)]
@@ -210,6 +378,17 @@ mod model {
         pub reason: Option<String>,
     }
 
+    #[derive(Queryable, Selectable, Identifiable, Associations)]
+    #[diesel(table_name = state_retained)]
+    #[diesel(primary_key(state_id, package_id, path))]
+    #[diesel(belongs_to(State))]
+    pub struct Retained {
+        pub state_id: i32,
+        #[diesel(deserialize_as = AStr)]
+        pub package_id: package::Id,
+        pub path: String,
+    }
+
     #[derive(Queryable, Selectable, Identifiable)]
     #[diesel(table_name = state)]
     #[diesel(check_for_backend(Sqlite))]
@@ -236,6 +415,50 @@ mod model {
         pub explicit: bool,
         pub reason: Option<&'a str>,
     }
+
+    #[derive(Insertable)]
+    #[diesel(table_name = state_retained)]
+    pub struct NewRetained<'a> {
+        pub state_id: i32,
+        pub package_id: &'a str,
+        pub path: &'a str,
+    }
+
+    #[derive(Queryable, Selectable)]
+    #[diesel(table_name = history)]
+    #[diesel(check_for_backend(Sqlite))]
+    pub struct History {
+        pub command: String,
+        pub uid: i32,
+        pub username: Option<String>,
+    }
+
+    #[derive(Queryable, Selectable)]
+    #[diesel(table_name = history_changes)]
+    #[diesel(check_for_backend(Sqlite))]
+    pub struct Change {
+        pub state_id: i32,
+        #[diesel(deserialize_as = AStr)]
+        pub package_id: package::Id,
+        pub added: bool,
+    }
+
+    #[derive(Insertable)]
+    #[diesel(table_name = history)]
+    pub struct NewHistory<'a> {
+        pub state_id: i32,
+        pub command: &'a str,
+        pub uid: i32,
+        pub username: Option<&'a str>,
+    }
+
+    #[derive(Insertable)]
+    #[diesel(table_name = history_changes)]
+    pub struct NewChange<'a> {
+        pub state_id: i32,
+        pub package_id: &'a str,
+        pub added: bool,
+    }
 }
 
 #[cfg(test)]
@@ -243,7 +466,6 @@ mod test {
     use chrono::Utc;
 
     use super::*;
-    use crate::package;
 
     #[test]
     fn create_insert_select() {
@@ -255,7 +477,20 @@ mod test {
             Selection::explicit(package::Id::from("pkg c")),
         ];
 
-        let state = database.add(&selections, Some("test"), Some("test")).unwrap();
+        let retained = vec![
+            Retained {
+                package: package::Id::from("pkg d"),
+                paths: vec!["share/d/history".to_owned(), "share/d/state".to_owned()],
+            },
+            Retained {
+                package: package::Id::from("pkg e"),
+                paths: vec!["share/e/state".to_owned()],
+            },
+        ];
+
+        let state = database
+            .add(&selections, &retained, Some("test"), Some("test"))
+            .unwrap();
 
         // First record
         assert_eq!(i32::from(state.id), 1);
@@ -269,5 +504,107 @@ mod test {
         assert_eq!(state.description.as_deref(), Some("test"));
 
         assert_eq!(state.selections, selections);
+        assert_eq!(state.retained, retained);
+        assert_eq!(database.all().unwrap()[0].retained, retained);
+    }
+
+    #[test]
+    fn set_description() {
+        let database = Database::new(":memory:").unwrap();
+
+        let state = database.add(&[], &[], Some("test"), None).unwrap();
+        database.set_description(state.id, Some("protected")).unwrap();
+
+        let state = database.get(state.id).unwrap();
+        assert_eq!(state.description.as_deref(), Some("protected"));
+
+        assert!(matches!(
+            database.set_description(state.id.next(), None),
+            Err(Error::RowNotFound)
+        ));
+    }
+
+    #[test]
+    fn history() {
+        let database = Database::new(":memory:").unwrap();
+
+        // Predates history being recorded
+        let first = database.add(&[], &[], Some("first"), None).unwrap();
+
+        let second = database
+            .add(
+                &[Selection::explicit(package::Id::from("pkg a"))],
+                &[],
+                Some("second"),
+                None,
+            )
+            .unwrap();
+        let origin = state::Origin {
+            command: "moss install a".to_owned(),
+            uid: 1000,
+            username: Some("user".to_owned()),
+        };
+        database
+            .add_history(
+                second.id,
+                &origin,
+                &[package::Id::from("pkg a")],
+                &[package::Id::from("pkg b")],
+            )
+            .unwrap();
+
+        let history = database.history(None).unwrap();
+        assert_eq!(history.len(), 2);
+
+        assert_eq!(history[0].state, first.id);
+        assert_eq!(history[0].origin, None);
+        assert!(history[0].added.is_empty() && history[0].removed.is_empty());
+
+        assert_eq!(history[1].state, second.id);
+        assert_eq!(history[1].summary.as_deref(), Some("second"));
+        assert_eq!(history[1].origin.as_ref(), Some(&origin));
+        assert_eq!(history[1].added, [package::Id::from("pkg a")]);
+        assert_eq!(history[1].removed, [package::Id::from("pkg b")]);
+
+        let history = database.history(Some(second.id)).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].state, second.id);
+
+        // History is removed alongside its state
+        database.remove(&second.id).unwrap();
+        assert!(database.history(Some(second.id)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn read_during_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = dir.path().join("state.db");
+        let url = url.to_str().unwrap();
+
+        let writer = Database::new(url).unwrap();
+        let reader = Database::new(url).unwrap();
+
+        let first = writer.add(&[], &[], Some("first"), None).unwrap();
+
+        writer
+            .conn
+            .exclusive_tx(|tx| {
+                diesel::insert_into(model::state::table)
+                    .values(model::NewState {
+                        summary: Some("second"),
+                        description: None,
+                        kind: state::Kind::Transaction.to_string(),
+                    })
+                    .execute(tx)?;
+
+                // Reader sees the last commit without waiting on the open transaction
+                let ids = reader.list_ids().unwrap();
+                assert_eq!(ids.into_iter().map(|(id, _)| id).collect::<Vec<_>>(), [first.id]);
+
+                Ok::<_, Error>(())
+            })
+            .unwrap();
+
+        assert_eq!(reader.list_ids().unwrap().len(), 2);
     }
 }
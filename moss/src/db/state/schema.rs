@@ -20,6 +20,34 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    state_retained (state_id, package_id, path) {
+        state_id -> Integer,
+        package_id -> Text,
+        path -> Text,
+    }
+}
+
+diesel::table! {
+    history (state_id) {
+        state_id -> Integer,
+        command -> Text,
+        uid -> Integer,
+        username -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    history_changes (state_id, package_id) {
+        state_id -> Integer,
+        package_id -> Text,
+        added -> Bool,
+    }
+}
+
 diesel::joinable!(state_selections -> state (state_id));
+diesel::joinable!(state_retained -> state (state_id));
+diesel::joinable!(history -> state (state_id));
+diesel::joinable!(history_changes -> history (state_id));
 
-diesel::allow_tables_to_appear_in_same_query!(state, state_selections);
+diesel::allow_tables_to_appear_in_same_query!(state, state_selections, state_retained, history, history_changes);
@@ -22,6 +22,7 @@
 use std::str::FromStr;
 
 use derive_more::Display;
+use regex::Regex;
 use stone::StonePayloadMetaDependency;
 use thiserror::Error;
 
@@ -247,6 +248,60 @@ impl TryFrom<String> for Provider {
     }
 }
 
+/// A glob over providers, i.e. `soname(libssl.so.*)`
+///
+/// `*` matches any run of characters & `?` any single character. Patterns
+/// without a kind match package names, as with [`Provider::from_name`].
+#[derive(Debug, Clone, Display)]
+#[display("{glob}")]
+pub struct ProviderGlob {
+    glob: String,
+    regex: Regex,
+}
+
+impl ProviderGlob {
+    pub fn new(pattern: &str) -> Self {
+        let glob = if pattern.contains('(') {
+            pattern.to_owned()
+        } else {
+            format!("{}({pattern})", Kind::PackageName)
+        };
+
+        // Everything but the wildcards is literal, including the `(` & `)` of the kind
+        let regex = glob
+            .split_inclusive(['*', '?'])
+            .map(|part| match part.strip_suffix('*') {
+                Some(text) => format!("{}.*", regex::escape(text)),
+                None => match part.strip_suffix('?') {
+                    Some(text) => format!("{}.", regex::escape(text)),
+                    None => regex::escape(part),
+                },
+            })
+            .collect::<String>();
+        let regex = Regex::new(&format!("^{regex}$")).expect("escaped glob is valid");
+
+        Self { glob, regex }
+    }
+
+    /// The pattern, matched against whole providers as displayed
+    pub fn as_str(&self) -> &str {
+        &self.glob
+    }
+
+    /// Whether `provider` matches the pattern
+    pub fn matches(&self, provider: &Provider) -> bool {
+        self.regex.is_match(&provider.to_string())
+    }
+}
+
+impl PartialEq for ProviderGlob {
+    fn eq(&self, other: &Self) -> bool {
+        self.glob == other.glob
+    }
+}
+
+impl Eq for ProviderGlob {}
+
 /// Parse the [`Kind`] of dependency or provider from the string
 fn parse(s: &str) -> Result<(Kind, String), ParseError> {
     let (kind, rest) = s.split_once('(').ok_or(ParseError(s.to_owned()))?;
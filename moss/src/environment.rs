@@ -1,12 +1,28 @@
 // SPDX-FileCopyrightText: 2023 AerynOS Developers
 // SPDX-License-Identifier: MPL-2.0
 
+use std::time::Duration;
+
 pub const NAME: &str = env!("CARGO_PKG_NAME");
 /// Max concurrency for disk tasks
 pub const MAX_DISK_CONCURRENCY: usize = 16;
 /// Max concurrency for network tasks
 pub const MAX_NETWORK_CONCURRENCY: usize = 8;
+/// Attempts to fetch a package before giving up on transient failures
+pub const MAX_FETCH_ATTEMPTS: u32 = 3;
+/// Delay before the first fetch retry, doubling with each further attempt
+pub const FETCH_RETRY_BACKOFF: Duration = Duration::from_millis(500);
 /// Buffer size used when reading a file, 4 MiB
 pub const FILE_READ_BUFFER_SIZE: usize = 4 * 1024 * 1024;
 /// Threshold to begin chunking file during read, 16 KiB
 pub const FILE_READ_CHUNK_THRESHOLD: usize = 16 * 1024;
+/// Time to wait on a database locked by another process, 5 seconds
+pub const DB_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Overrides [`DB_BUSY_TIMEOUT`] in milliseconds, i.e. for slow disks
+pub const DB_BUSY_TIMEOUT_VAR: &str = "MOSS_DB_BUSY_TIMEOUT";
+/// Packages protected from removal when no `protected` config is present
+pub const PROTECTED_PACKAGES: &[&str] = &["bash", "coreutils", "glibc", "moss", "systemd"];
+/// Levels of subdirectories scanned for local stones when a `local` config doesn't set a depth
+pub const LOCAL_STONES_MAX_DEPTH: usize = 3;
+/// Time a transaction hook may run before it's killed, 5 minutes
+pub const HOOK_TIMEOUT: Duration = Duration::from_secs(5 * 60);
@@ -0,0 +1,445 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Stable classification of errors, for tooling wrapping moss
+//!
+//! Each error maps to an [`ErrorCode`] with an exhaustive match, so new variants
+//! have to be classified rather than silently counting as [`ErrorCode::Generic`].
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::client::{Error, cache, extract, fetch, index, install, model, remove, sync};
+use crate::{installation, registry::transaction, repository, request};
+
+/// Class of an error, used as the exit code of the CLI
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorCode {
+    /// Anything not classified otherwise
+    Generic = 1,
+    /// A package, dependency, state or repository asked for doesn't exist
+    Resolution = 2,
+    /// A network request failed, or the network can't be used
+    Network = 3,
+    /// The installation is locked, or was changed, by another process
+    LockHeld = 4,
+    /// Cancelled at the user's request
+    Cancelled = 5,
+    /// Content didn't match the hash it was expected to have
+    Verification = 6,
+    /// Repositories configured with a legacy index URI need to be upgraded
+    OutdatedRepositories = 7,
+    /// Changes are pending, as listed by `moss sync --dry-run`
+    Pending = 100,
+}
+
+impl ErrorCode {
+    /// The exit code of the CLI
+    pub fn exit_code(self) -> i32 {
+        self as i32
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ErrorCode::Generic => "generic",
+            ErrorCode::Resolution => "resolution",
+            ErrorCode::Network => "network",
+            ErrorCode::LockHeld => "lock-held",
+            ErrorCode::Cancelled => "cancelled",
+            ErrorCode::Verification => "verification",
+            ErrorCode::OutdatedRepositories => "outdated-repositories",
+            ErrorCode::Pending => "pending",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// An error classified by an [`ErrorCode`]
+pub trait Coded {
+    fn code(&self) -> ErrorCode;
+}
+
+impl Coded for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Error::StateDoesntExist(_)
+            | Error::NoPreviousState(_)
+            | Error::MissingMetadata(_)
+            | Error::PackageNotSelected(_)
+            | Error::NotInState(..)
+            | Error::UnknownRepository(_) => ErrorCode::Resolution,
+            Error::Locked { .. } | Error::StateChanged => ErrorCode::LockHeld,
+            Error::Cancelled => ErrorCode::Cancelled,
+            Error::Installation(error) => error.code(),
+            Error::CacheFetch(error, _) => error.code(),
+            Error::CacheUnpack(error, ..) => error.code(),
+            // Classified by the first, as they're fetched alike
+            Error::CachePackages(failures) => failures
+                .first()
+                .map_or(ErrorCode::Generic, |failure| failure.error.code()),
+            Error::Repository(error) => error.code(),
+            Error::Install(error) => error.code(),
+            Error::Remove(error) => error.code(),
+            Error::Fetch(error) => error.code(),
+            Error::Sync(error) => error.code(),
+            Error::Model(error) => error.code(),
+            Error::NoActiveState
+            | Error::StateAlreadyActive(_)
+            | Error::StateArchiveMissing(_)
+            | Error::PreTransactionHook(_)
+            | Error::EphemeralInstallationRoot
+            | Error::EphemeralProhibitedOperation
+            | Error::Db(_)
+            | Error::Prune(_)
+            | Error::Io(_)
+            | Error::Swap(_)
+            | Error::Filesystem(_)
+            | Error::Blit(_)
+            | Error::PostBlit(_)
+            | Error::Boot(_)
+            | Error::Dialog(_)
+            | Error::BlitSignalIgnore(_)
+            | Error::LoadSystemModel(_)
+            | Error::UpdateSystemModel(_)
+            | Error::Manifest(_)
+            | Error::ImportSystemModelDoesntExist(_) => ErrorCode::Generic,
+        }
+    }
+}
+
+impl Coded for install::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            install::Error::Cancelled => ErrorCode::Cancelled,
+            install::Error::Client(error) => error.code(),
+            install::Error::NoPackage(..) | install::Error::UnavailableVersions(_) | install::Error::Conflicts(_) => {
+                ErrorCode::Resolution
+            }
+            install::Error::Transaction(error) => error.code(),
+            install::Error::DB(_) | install::Error::Io(_) => ErrorCode::Generic,
+        }
+    }
+}
+
+impl Coded for remove::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            remove::Error::Cancelled => ErrorCode::Cancelled,
+            remove::Error::NoSuchPackage(_) => ErrorCode::Resolution,
+            remove::Error::Client(error) => error.code(),
+            remove::Error::Transaction(error) => error.code(),
+            remove::Error::Protected(_) | remove::Error::DB(_) | remove::Error::Io(_) => ErrorCode::Generic,
+        }
+    }
+}
+
+impl Coded for sync::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            sync::Error::MissingSystemModelPackage(_)
+            | sync::Error::ExcludedDependency { .. }
+            | sync::Error::ExcludedModelEntry { .. }
+            | sync::Error::Conflicts(_) => ErrorCode::Resolution,
+            sync::Error::Cancelled => ErrorCode::Cancelled,
+            sync::Error::Client(error) => error.code(),
+            sync::Error::Transaction(error) => error.code(),
+            sync::Error::DB(_) | sync::Error::Io(_) | sync::Error::LoadSystemModel(_) => ErrorCode::Generic,
+        }
+    }
+}
+
+impl Coded for fetch::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            fetch::Error::Cancelled => ErrorCode::Cancelled,
+            fetch::Error::Client(error) => error.code(),
+            fetch::Error::NoPackage(_) => ErrorCode::Resolution,
+            fetch::Error::FetchPackage(error, _) => error.code(),
+            fetch::Error::Io(_)
+            | fetch::Error::Dialog(_)
+            | fetch::Error::ParseError(_)
+            | fetch::Error::NoFileNameInUri(_) => ErrorCode::Generic,
+        }
+    }
+}
+
+impl Coded for model::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            model::Error::MissingPackages(_) | model::Error::Conflicts(_) => ErrorCode::Resolution,
+            model::Error::Cancelled => ErrorCode::Cancelled,
+            model::Error::Client(error) => error.code(),
+            model::Error::Repository(error) => error.code(),
+            model::Error::Transaction(error) => error.code(),
+            model::Error::Io(_) => ErrorCode::Generic,
+        }
+    }
+}
+
+impl Coded for extract::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            extract::Error::Client(error) => error.code(),
+            extract::Error::Installation(error) => error.code(),
+            extract::Error::MissingMeta
+            | extract::Error::MissingContent
+            | extract::Error::MissingAsset(_)
+            | extract::Error::TruncatedContent(_)
+            | extract::Error::MalformedMeta(_)
+            | extract::Error::IO(_)
+            | extract::Error::Format(_) => ErrorCode::Generic,
+        }
+    }
+}
+
+impl Coded for index::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            index::Error::Client(error) => error.code(),
+            index::Error::Io(_)
+            | index::Error::StoneRead { .. }
+            | index::Error::StoneWrite { .. }
+            | index::Error::DuplicateRelease(..)
+            | index::Error::MissingMetaPayload
+            | index::Error::MissingMetaField(_)
+            | index::Error::StripPrefix(_)
+            | index::Error::NonUtf8Path { .. }
+            | index::Error::Json(_)
+            | index::Error::Dialog(_) => ErrorCode::Generic,
+        }
+    }
+}
+
+impl Coded for cache::FetchError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            cache::FetchError::Request { source } => source.code(),
+            cache::FetchError::Offline { .. } => ErrorCode::Network,
+            cache::FetchError::MissingHash
+            | cache::FetchError::MalformedHash { .. }
+            | cache::FetchError::BinaryStoneHashMismatch { .. } => ErrorCode::Verification,
+            cache::FetchError::MissingUrl | cache::FetchError::InvalidUrl { .. } | cache::FetchError::Io { .. } => {
+                ErrorCode::Generic
+            }
+        }
+    }
+}
+
+impl Coded for cache::UnpackError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            cache::UnpackError::FileUnpackHashMismatch { .. } => ErrorCode::Verification,
+            cache::UnpackError::MissingContent
+            | cache::UnpackError::ReadStone { .. }
+            | cache::UnpackError::Io { .. } => ErrorCode::Generic,
+        }
+    }
+}
+
+impl Coded for transaction::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            transaction::Error::NoCandidate(_) => ErrorCode::Resolution,
+            transaction::Error::NotImplemented | transaction::Error::Database(_) => ErrorCode::Generic,
+        }
+    }
+}
+
+impl Coded for installation::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            installation::Error::Locked(_) => ErrorCode::LockHeld,
+            installation::Error::Lockfile(error) => error.code(),
+            installation::Error::RootInvalid
+            | installation::Error::CacheInvalid
+            | installation::Error::LoadSystemModel(_) => ErrorCode::Generic,
+        }
+    }
+}
+
+impl Coded for repository::manager::Error {
+    fn code(&self) -> ErrorCode {
+        use repository::manager::Error;
+
+        match self {
+            Error::FetchIndex(error) => error.code(),
+            Error::UnknownRepo(_) | Error::MissingRootIndexVersion(_) => ErrorCode::Resolution,
+            Error::OutdatedRepos(..) => ErrorCode::OutdatedRepositories,
+            Error::ExplicitUnsupported
+            | Error::MissingMetaField(_)
+            | Error::CreateDir(_)
+            | Error::RemoveDir(_)
+            | Error::OpenIndex(_)
+            | Error::ReadStone(_)
+            | Error::Database(_)
+            | Error::SaveConfig(_)
+            | Error::ResolveHistoryIndexUri(_)
+            | Error::ReadCachedIndexUri(_)
+            | Error::WriteCachedIndexUri(_)
+            | Error::ReadLastRefresh(_)
+            | Error::WriteLastRefresh(_)
+            | Error::ParseCachedIndexUri(_)
+            | Error::UnsupportedRepos(_) => ErrorCode::Generic,
+        }
+    }
+}
+
+impl Coded for repository::FetchError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            repository::FetchError::Request(error) => error.code(),
+            repository::FetchError::Io(_) => ErrorCode::Generic,
+        }
+    }
+}
+
+impl Coded for request::Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            // Reading the body of a response fails alike when the connection drops
+            request::Error::Fetch(_) | request::Error::Read(_) => ErrorCode::Network,
+            request::Error::DecodeJson(_) => ErrorCode::Generic,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+
+    use chrono::Utc;
+
+    use super::*;
+    use crate::{package, state};
+
+    #[test]
+    fn client_error_codes() {
+        let name = || package::Name::from("nano".to_owned());
+        let io = || io::Error::other("io");
+
+        for (error, code) in [
+            (Error::StateDoesntExist(state::Id::from(3)), ErrorCode::Resolution),
+            (Error::NotInState(name(), state::Id::from(3)), ErrorCode::Resolution),
+            (
+                Error::Locked {
+                    pid: 42,
+                    since: Utc::now(),
+                },
+                ErrorCode::LockHeld,
+            ),
+            (
+                Error::Installation(installation::Error::Locked(None)),
+                ErrorCode::LockHeld,
+            ),
+            (Error::Cancelled, ErrorCode::Cancelled),
+            (
+                Error::CacheFetch(
+                    cache::FetchError::Offline {
+                        package: "nano".to_owned(),
+                    },
+                    name(),
+                ),
+                ErrorCode::Network,
+            ),
+            (
+                Error::CacheFetch(
+                    cache::FetchError::Request {
+                        source: request::Error::Read(io()),
+                    },
+                    name(),
+                ),
+                ErrorCode::Network,
+            ),
+            (
+                Error::CacheFetch(
+                    cache::FetchError::BinaryStoneHashMismatch {
+                        package: "nano".to_owned(),
+                        expected: "a".to_owned(),
+                        actual: "b".to_owned(),
+                    },
+                    name(),
+                ),
+                ErrorCode::Verification,
+            ),
+            (
+                Error::CacheUnpack(
+                    Box::new(cache::UnpackError::FileUnpackHashMismatch {
+                        path: "usr/bin/nano".into(),
+                        expected: 1,
+                        actual: 2,
+                    }),
+                    name(),
+                    "usr/bin/nano".into(),
+                ),
+                ErrorCode::Verification,
+            ),
+            (
+                Error::Repository(repository::manager::Error::FetchIndex(repository::FetchError::Request(
+                    request::Error::Read(io()),
+                ))),
+                ErrorCode::Network,
+            ),
+            (
+                Error::Repository(repository::manager::Error::UnknownRepo(repository::Id::new("volatile"))),
+                ErrorCode::Resolution,
+            ),
+            (Error::Io(io()), ErrorCode::Generic),
+            (Error::NoActiveState, ErrorCode::Generic),
+        ] {
+            assert_eq!(error.code(), code, "{error:?}");
+        }
+    }
+
+    #[test]
+    fn operation_error_codes() {
+        let wrapped = |error: Error| install::Error::Client(error);
+
+        for (error, code) in [
+            (
+                Error::Install(Box::new(install::Error::NoPackage("nan".to_owned(), vec![]))),
+                ErrorCode::Resolution,
+            ),
+            (
+                Error::Install(Box::new(install::Error::Transaction(transaction::Error::NoCandidate(
+                    "libnano".to_owned(),
+                )))),
+                ErrorCode::Resolution,
+            ),
+            (
+                Error::Install(Box::new(wrapped(Error::Cancelled))),
+                ErrorCode::Cancelled,
+            ),
+            (
+                Error::Remove(Box::new(remove::Error::NoSuchPackage(vec![]))),
+                ErrorCode::Resolution,
+            ),
+            (
+                Error::Remove(Box::new(remove::Error::Protected(vec!["glibc".to_owned()]))),
+                ErrorCode::Generic,
+            ),
+            (
+                Error::Fetch(Box::new(fetch::Error::NoPackage("nano".to_owned()))),
+                ErrorCode::Resolution,
+            ),
+            (Error::Sync(Box::new(sync::Error::Cancelled)), ErrorCode::Cancelled),
+            (
+                Error::Model(Box::new(model::Error::MissingPackages(vec![]))),
+                ErrorCode::Resolution,
+            ),
+            (
+                Error::Model(Box::new(model::Error::Transaction(transaction::Error::NotImplemented))),
+                ErrorCode::Generic,
+            ),
+        ] {
+            assert_eq!(error.code(), code, "{error:?}");
+        }
+
+        assert_eq!(ErrorCode::Verification.exit_code(), 6);
+        assert_eq!(ErrorCode::Pending.exit_code(), 100);
+        assert_eq!(ErrorCode::LockHeld.to_string(), "lock-held");
+    }
+}
@@ -3,7 +3,10 @@
 
 //! Encapsulation of a target installation filesystem
 
-use std::path::{Path, PathBuf};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
 
 use fs_err as fs;
 use log::{trace, warn};
@@ -18,6 +21,8 @@ use crate::{
 
 mod lockfile;
 
+pub use self::lockfile::{Holder as LockHolder, Lock};
+
 /// System mutability - do we have readwrite?
 #[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display)]
 #[strum(serialize_all = "kebab-case")]
@@ -48,8 +53,17 @@ pub struct Installation {
     pub system_model: Option<LoadedSystemModel>,
 
     /// Acquired locks that guarantee exclusive access
-    /// to the installation for mutable operations
-    _locks: Vec<lockfile::Lock>,
+    /// to the cache dir for mutable operations
+    _locks: Vec<Lock>,
+
+    /// Lock of mutating operations, see [`Installation::lock`]
+    lock: lockfile::Recorded,
+
+    /// Block while another process holds [`Installation::lock`]
+    wait_for_lock: bool,
+
+    /// Run system triggers even when their inputs are unchanged
+    pub(crate) force_triggers: bool,
 }
 
 impl Installation {
@@ -87,11 +101,11 @@ impl Installation {
         trace!("Mutability: {mutability}");
         trace!("Root dir: {root:?}");
 
-        // Get exclusive access to work within these directories
-        let _locks = if matches!(mutability, Mutability::ReadWrite) {
-            acquire_locks(&root.join(".moss"), cache_dir.as_deref())?
-        } else {
-            vec![]
+        // Get exclusive access to work within the cache dir, the root itself
+        // is only locked by mutating operations
+        let _locks = match (&cache_dir, mutability) {
+            (Some(dir), Mutability::ReadWrite) => acquire_locks(dir)?,
+            _ => vec![],
         };
 
         let active_state = read_state_id(&root);
@@ -105,6 +119,8 @@ impl Installation {
         let system_model =
             system_model::load(&root.join("etc/moss/system-model.kdl")).map_err(Error::LoadSystemModel)?;
 
+        let lock = lockfile::Recorded::new(root.join(".moss").join("lock"));
+
         Ok(Self {
             root,
             mutability,
@@ -112,14 +128,87 @@ impl Installation {
             cache_dir,
             system_model,
             _locks,
+            lock,
+            wait_for_lock: false,
+            force_triggers: false,
         })
     }
 
+    /// Block mutating operations until another process holding the lock
+    /// releases it, rather than failing with [`Error::Locked`]
+    pub fn wait_for_lock(self, wait: bool) -> Self {
+        Self {
+            wait_for_lock: wait,
+            ..self
+        }
+    }
+
+    /// Run every matched system trigger, even those whose inputs are
+    /// unchanged since they last succeeded
+    pub fn force_triggers(self, force: bool) -> Self {
+        Self {
+            force_triggers: force,
+            ..self
+        }
+    }
+
+    /// Re-read the [`Installation::active_state`], which changes when another
+    /// state is activated after [`Installation::open`]
+    pub fn reload_active_state(&mut self) {
+        self.active_state = self.read_active_state();
+    }
+
+    /// The active state as recorded in the installation tree, which differs from
+    /// [`Installation::active_state`] once another state is activated
+    pub(crate) fn read_active_state(&self) -> Option<state::Id> {
+        read_state_id(&self.root)
+    }
+
+    /// Acquire exclusive access to the installation for a mutating operation,
+    /// held until the returned [`Lock`] and all its clones are dropped.
+    ///
+    /// The lock is reentrant, and shared by clones of this installation. If
+    /// another process holds it, `waiting` is called before blocking when
+    /// [`Installation::wait_for_lock`] is set, otherwise [`Error::Locked`]
+    /// is returned.
+    pub fn lock(&self, waiting: impl FnOnce(Option<&LockHolder>)) -> Result<Lock, Error> {
+        self.lock
+            .acquire(|holder| {
+                if self.wait_for_lock {
+                    waiting(holder);
+                }
+                self.wait_for_lock
+            })
+            .map_err(|error| match error {
+                lockfile::Error::Locked(holder) => Error::Locked(holder),
+                error => Error::Lockfile(error),
+            })
+    }
+
+    /// Acquire [`Installation::lock`] unless another process holds it, without
+    /// ever waiting. Returns `None` if the lock is held elsewhere.
+    pub fn try_lock(&self) -> Result<Option<Lock>, Error> {
+        match self.lock.acquire(|_| false) {
+            Ok(lock) => Ok(Some(lock)),
+            Err(lockfile::Error::Locked(_)) => Ok(None),
+            Err(error) => Err(Error::Lockfile(error)),
+        }
+    }
+
     /// Return true if we lack write access
     pub fn read_only(&self) -> bool {
         matches!(self.mutability, Mutability::ReadOnly)
     }
 
+    /// Return true if the installation is the running system, rather than an
+    /// alternate root such as `moss -D /mnt`
+    ///
+    /// Only the host may have its boot partitions detected & mounted, or run
+    /// system triggers outside a container
+    pub fn is_host(&self) -> bool {
+        is_host_root(&self.root)
+    }
+
     // Helper to form paths
     fn moss_path(&self, path: impl AsRef<Path>) -> PathBuf {
         self.root.join(".moss").join(path)
@@ -130,6 +219,18 @@ impl Installation {
         self.moss_path("db").join(path)
     }
 
+    /// Size of the database `name` on disk, including its write-ahead log
+    pub fn db_size(&self, name: &str) -> io::Result<u64> {
+        [self.db_path(name), self.db_path(format!("{name}-wal"))]
+            .into_iter()
+            .map(|path| match fs::metadata(path) {
+                Ok(meta) => Ok(meta.len()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+                Err(e) => Err(e),
+            })
+            .sum()
+    }
+
     /// Build a cache path relative to the moss root, or
     /// from the custom cache dir, if provided
     pub fn cache_path(&self, path: impl AsRef<Path>) -> PathBuf {
@@ -175,33 +276,39 @@ impl Installation {
         self.root_path("isolation").join(path)
     }
 
+    /// Path to the tombstone of a state removal in progress
+    pub fn removal_tombstone_path(&self) -> PathBuf {
+        self.moss_path("removal.tombstone")
+    }
+
+    /// Path to the fingerprints of the system triggers last run against the root
+    ///
+    /// Always in the root's own cache dir, never a custom [`Self::cache_dir`], as that
+    /// may be shared by several roots whose fingerprints would then clobber each other
+    pub fn trigger_cache_path(&self) -> PathBuf {
+        self.moss_path("cache").join("triggers.json")
+    }
+
     /// Path to the system model file
     pub fn system_model_path(&self) -> PathBuf {
         self.root.join("etc/moss/system-model.kdl")
     }
 }
 
-/// Blocks until lockfiles can be obtained for the
-/// root `moss` path and if provided, the custom
-/// cache path
+/// Returns true if `root` resolves to `/`, i.e. `/mnt/..`
+fn is_host_root(root: &Path) -> bool {
+    fs::canonicalize(root).unwrap_or_else(|_| root.to_owned()) == Path::new("/")
+}
+
+/// Blocks until a lockfile can be obtained for the
+/// custom cache path
 ///
 /// Locks are held until dropped
-pub fn acquire_locks(moss_path: &Path, cache_dir: Option<&Path>) -> Result<Vec<lockfile::Lock>, Error> {
-    let mut locks = vec![];
-
-    locks.push(lockfile::acquire(
-        moss_path.join(".moss-lockfile"),
-        format!("{} another process is using the moss root", "Blocking".yellow().bold()),
-    )?);
-
-    if let Some(path) = cache_dir {
-        locks.push(lockfile::acquire(
-            path.join(".moss-lockfile"),
-            format!("{} another process is using the cache dir", "Blocking".yellow().bold()),
-        )?);
-    }
-
-    Ok(locks)
+pub fn acquire_locks(cache_dir: &Path) -> Result<Vec<Lock>, Error> {
+    Ok(vec![lockfile::acquire(
+        cache_dir.join(".moss-lockfile"),
+        format!("{} another process is using the cache dir", "Blocking".yellow().bold()),
+    )?])
 }
 
 /// In older versions of moss, the `/usr` entry was a symlink
@@ -273,6 +380,24 @@ pub enum Error {
     CacheInvalid,
     #[error("acquiring lockfile")]
     Lockfile(#[from] lockfile::Error),
+    #[error("installation is locked by another process")]
+    Locked(Option<LockHolder>),
     #[error("load system model")]
     LoadSystemModel(#[from] system_model::LoadError),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn host_root() {
+        let root = tempfile::tempdir().unwrap();
+        assert!(is_host_root(Path::new("/")));
+        assert!(is_host_root(&root.path().join("..").join("..").join("..").join("..")));
+        assert!(!is_host_root(root.path()));
+
+        let installation = Installation::open(root.path(), None).unwrap();
+        assert!(!installation.is_host());
+    }
+}
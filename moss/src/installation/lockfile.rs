@@ -3,16 +3,22 @@
 
 use std::{
     fmt,
-    io::{self},
+    io::{self, Read, Seek, Write},
     os::fd::AsRawFd,
     path::PathBuf,
-    sync::Arc,
+    sync::{Arc, Mutex, Weak},
+    thread,
+    time::Duration,
 };
 
+use chrono::{DateTime, Utc};
 use fs_err::File;
 use nix::fcntl::{FlockArg, flock};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::{Coded, ErrorCode};
+
 /// An acquired file lock guaranteeing exclusive access
 /// to the underlying directory.
 ///
@@ -44,10 +50,155 @@ pub fn acquire(path: impl Into<PathBuf>, block_msg: impl fmt::Display) -> Result
     Ok(Lock(Arc::new(file)))
 }
 
+/// The process holding a [`Recorded`] lock, written into the lock file as JSON
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Holder {
+    pub pid: u32,
+    /// When the lock was acquired
+    pub since: DateTime<Utc>,
+    /// Command line of the holder
+    pub command: String,
+}
+
+impl Holder {
+    fn current() -> Self {
+        Self {
+            pid: std::process::id(),
+            since: Utc::now(),
+            command: std::env::args().collect::<Vec<_>>().join(" "),
+        }
+    }
+}
+
+/// An exclusive lock recording its [`Holder`], which is reentrant within
+/// a process: acquiring it again while held shares the existing [`Lock`]
+///
+/// Clones share the same lock
+#[derive(Debug, Clone)]
+pub struct Recorded {
+    path: PathBuf,
+    held: Arc<Mutex<Weak<File>>>,
+}
+
+impl Recorded {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            held: Arc::default(),
+        }
+    }
+
+    /// Acquire the lock, unless another process (or another [`Recorded`]
+    /// for the same path) holds it.
+    ///
+    /// If held, `wait` decides whether to block until it's released, and
+    /// otherwise [`Error::Locked`] is returned.
+    pub fn acquire(&self, wait: impl FnOnce(Option<&Holder>) -> bool) -> Result<Lock, Error> {
+        let mut held = self.held.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(file) = held.upgrade() {
+            return Ok(Lock(file));
+        }
+
+        let mut file = File::options()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(false)
+            .open(&self.path)?;
+
+        match flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+            Ok(_) => {}
+            Err(nix::errno::Errno::EWOULDBLOCK) => {
+                let holder = read_holder(&mut file);
+                if !wait(holder.as_ref()) {
+                    return Err(Error::Locked(holder));
+                }
+                flock(file.as_raw_fd(), FlockArg::LockExclusive)?;
+            }
+            Err(e) => Err(e)?,
+        }
+
+        // Replace whatever a previous holder left behind
+        file.set_len(0)?;
+        file.rewind()?;
+        serde_json::to_writer(&mut file, &Holder::current()).map_err(io::Error::other)?;
+        file.flush()?;
+
+        let file = Arc::new(file);
+        *held = Arc::downgrade(&file);
+
+        Ok(Lock(file))
+    }
+}
+
+/// Read the [`Holder`] of a locked file, `None` if it isn't written shortly
+/// after the lock was acquired
+fn read_holder(file: &mut File) -> Option<Holder> {
+    for _ in 0..10 {
+        let mut contents = String::new();
+        if file.rewind().is_ok()
+            && file.read_to_string(&mut contents).is_ok()
+            && let Ok(holder) = serde_json::from_str(&contents)
+        {
+            return Some(holder);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    None
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("io")]
     Io(#[from] io::Error),
     #[error("obtaining exclusive file lock")]
     Flock(#[from] nix::Error),
+    #[error("locked by another process")]
+    Locked(Option<Holder>),
+}
+
+impl Coded for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Error::Locked(_) => ErrorCode::LockHeld,
+            Error::Io(_) | Error::Flock(_) => ErrorCode::Generic,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn recorded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lock");
+
+        let first = Recorded::new(&path);
+        let lock = first.acquire(|_| unreachable!()).unwrap();
+
+        // Reentrant, including for clones
+        let again = first.clone().acquire(|_| unreachable!()).unwrap();
+
+        let holder = serde_json::from_str::<Holder>(&fs_err::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(holder.pid, std::process::id());
+
+        // Another lock of the same file is refused with the holder
+        let second = Recorded::new(&path);
+        let mut seen = None;
+        let result = second.acquire(|holder| {
+            seen = holder.cloned();
+            false
+        });
+        assert!(matches!(result, Err(Error::Locked(Some(ref h))) if *h == holder));
+        assert_eq!(seen, Some(holder));
+
+        // Released once every share is dropped
+        drop(lock);
+        assert!(matches!(second.acquire(|_| false), Err(Error::Locked(_))));
+        drop(again);
+        second.acquire(|_| false).unwrap();
+    }
 }
@@ -2,7 +2,8 @@
 // SPDX-License-Identifier: MPL-2.0
 
 pub use self::client::{Client, ClientBuilder};
-pub use self::dependency::{Dependency, Provider};
+pub use self::dependency::{Dependency, Provider, ProviderGlob};
+pub use self::error_code::{Coded, ErrorCode};
 pub use self::installation::Installation;
 pub use self::package::Package;
 pub use self::registry::Registry;
@@ -15,8 +16,11 @@ pub mod client;
 pub mod db;
 pub mod dependency;
 pub mod environment;
+pub mod error_code;
 pub mod installation;
+pub mod manifest;
 pub mod package;
+pub mod progress;
 pub mod registry;
 pub mod repository;
 pub mod request;
@@ -3,7 +3,7 @@
 
 use std::{error::Error, sync::Arc};
 
-use moss::repository;
+use moss::{Coded, repository};
 use tracing::error;
 use tui::Styled;
 
@@ -12,24 +12,24 @@ mod cli;
 /// Main entry point
 fn main() {
     if let Err(error) = cli::process() {
-        if let Some(error) = error_needs_manual_handling(&error) {
-            match error {
-                ManuallyHandledError::UnsupportedRepos(_) => todo!("handle unsupported repo format"),
-                ManuallyHandledError::OutdatedRepos(manager_source, outdated_repos) => {
+        // Outdated repositories are reported as any other error with `--format json`, rather than upgraded
+        if !cli::reported(&error) && !cli::report_json(&error) {
+            match error_needs_manual_handling(&error) {
+                Some(ManuallyHandledError::UnsupportedRepos(_)) => todo!("handle unsupported repo format"),
+                Some(ManuallyHandledError::OutdatedRepos(manager_source, outdated_repos)) => {
                     repository::handle_outdated_index_uris(&manager_source, outdated_repos);
                 }
+                None => report_error(&error),
             }
-        } else {
-            report_error(error);
         }
 
-        std::process::exit(1);
+        std::process::exit(error.code().exit_code());
     }
 }
 
 /// Report an execution error to the user
-fn report_error(error: cli::Error) {
-    let sources = sources(&error);
+fn report_error(error: &cli::Error) {
+    let sources = sources(error);
     let error = sources.join(": ");
     error!(error, "Command execution failed");
     println!("{}: {error}", "Error".red());
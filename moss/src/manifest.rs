@@ -0,0 +1,143 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Portable manifest of a state's explicit packages
+//!
+//! Unlike a [`crate::SystemModel`], a [`Manifest`] pins the exact version of
+//! every package, so another machine can be brought to the same selections.
+
+use std::{io, path::Path};
+
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use url::Url;
+
+use crate::repository;
+
+/// Current version of the manifest format
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Format version, see [`VERSION`]
+    pub version: u32,
+    /// Active repositories of the exporting installation
+    #[serde(default)]
+    pub repositories: repository::Map,
+    /// Explicitly selected packages
+    pub packages: Vec<Entry>,
+}
+
+/// An explicit package, pinned to the version it was exported with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    pub version: String,
+    pub release: u64,
+    /// Index the package was installed from, if still available
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<Url>,
+}
+
+/// Encoding of a [`Manifest`] on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Yaml,
+    Json,
+}
+
+impl Format {
+    /// Format by file extension, defaulting to [`Format::Yaml`]
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Self::Json,
+            _ => Self::Yaml,
+        }
+    }
+}
+
+impl Manifest {
+    pub fn new(repositories: repository::Map, packages: Vec<Entry>) -> Self {
+        Self {
+            version: VERSION,
+            repositories,
+            packages,
+        }
+    }
+
+    pub fn encode(&self, format: Format) -> Result<String, Error> {
+        Ok(match format {
+            Format::Yaml => serde_yaml::to_string(self)?,
+            Format::Json => serde_json::to_string_pretty(self)?,
+        })
+    }
+
+    pub fn decode(content: &str, format: Format) -> Result<Self, Error> {
+        let manifest: Self = match format {
+            Format::Yaml => serde_yaml::from_str(content)?,
+            Format::Json => serde_json::from_str(content)?,
+        };
+
+        if manifest.version > VERSION {
+            return Err(Error::UnsupportedVersion(manifest.version));
+        }
+
+        Ok(manifest)
+    }
+
+    /// Load a manifest, with the [`Format`] determined by its extension
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let content = fs::read_to_string(path)?;
+        Self::decode(&content, Format::from_path(path))
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("io")]
+    Io(#[from] io::Error),
+    #[error("yaml")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("json")]
+    Json(#[from] serde_json::Error),
+    #[error("unsupported manifest version {0}, at most {VERSION} is supported")]
+    UnsupportedVersion(u32),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_versions() {
+        let content = r#"
+version: 1
+repositories:
+  volatile:
+    description: Volatile
+    uri: https://packages.aerynos.dev/volatile/x86_64/stone.index
+    priority: 0
+packages:
+  - name: nano
+    version: "8.0"
+    release: 1
+    index: https://packages.aerynos.dev/volatile/x86_64/stone.index
+"#;
+
+        let manifest = Manifest::decode(content, Format::Yaml).unwrap();
+        assert_eq!(manifest.packages.len(), 1);
+        assert!(manifest.repositories.contains_id(&repository::Id::new("volatile")));
+
+        // JSON is detected by extension
+        assert_eq!(Format::from_path(Path::new("manifest.json")), Format::Json);
+        let json = manifest.encode(Format::Json).unwrap();
+        assert_eq!(Manifest::decode(&json, Format::Json).unwrap(), manifest);
+
+        let newer = content.replace("version: 1", "version: 2");
+        assert!(matches!(
+            Manifest::decode(&newer, Format::Yaml),
+            Err(Error::UnsupportedVersion(2))
+        ));
+    }
+}
@@ -0,0 +1,65 @@
+// SPDX-FileCopyrightText: 2023 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Package metadata & stones for tests
+
+use std::path::{Path, PathBuf};
+
+use fs_err as fs;
+use stone::{StoneHeaderV1FileType, StonePayloadLayoutRecord, StonePayloadMetaRecord, StoneWriter};
+
+use super::{Meta, Name};
+use crate::Provider;
+
+/// Metadata of `name` 1.0, release 1, built for x86_64 & providing its name
+///
+/// Override fields with struct update syntax, i.e. `Meta { source_release: 2, ..meta("nano") }`
+pub fn meta(name: &str) -> Meta {
+    Meta {
+        name: Name::from(name.to_owned()),
+        version_identifier: "1.0".to_owned(),
+        source_release: 1,
+        build_release: 1,
+        architecture: "x86_64".to_owned(),
+        summary: String::new(),
+        description: String::new(),
+        source_id: name.to_owned(),
+        homepage: String::new(),
+        licenses: vec![],
+        dependencies: Default::default(),
+        providers: [Provider::package_name(name)].into(),
+        conflicts: Default::default(),
+        uri: None,
+        hash: None,
+        download_size: None,
+        provenance: Default::default(),
+    }
+}
+
+/// Write a stone of `meta` records & `layouts` to `dir`, returning its path
+///
+/// The file is named after the package as boulder would, i.e. `nano-1.0-1-1-x86_64.stone`
+pub fn stone(dir: &Path, meta: &[StonePayloadMetaRecord], layouts: &[StonePayloadLayoutRecord]) -> PathBuf {
+    let Meta {
+        name,
+        version_identifier,
+        source_release,
+        build_release,
+        architecture,
+        ..
+    } = Meta::from_stone_payload(meta).unwrap();
+    let path = dir.join(format!(
+        "{name}-{version_identifier}-{source_release}-{build_release}-{architecture}.stone"
+    ));
+
+    fs::create_dir_all(dir).unwrap();
+    let mut file = fs::File::create(&path).unwrap();
+    let mut writer = StoneWriter::new(&mut file, StoneHeaderV1FileType::Binary).unwrap();
+    writer.add_payload(meta).unwrap();
+    if !layouts.is_empty() {
+        writer.add_payload(layouts).unwrap();
+    }
+    writer.finalize().unwrap();
+
+    path
+}
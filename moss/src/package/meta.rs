@@ -5,6 +5,7 @@ use std::collections::BTreeSet;
 
 use astr::AStr;
 use derive_more::{Debug, Display, From, Into};
+use serde::Serialize;
 use stone::{StonePayloadMetaPrimitive, StonePayloadMetaRecord, StonePayloadMetaTag};
 use thiserror::Error;
 
@@ -29,6 +30,15 @@ impl Name {
     }
 }
 
+/// The most relevant field of a [`Meta`] matched by a search, best first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, strum::Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum SearchMatch {
+    Name,
+    Summary,
+    Description,
+}
+
 /// The metadata of a [`super::Package`]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Meta {
@@ -64,6 +74,32 @@ pub struct Meta {
     pub hash: Option<String>,
     /// How big is this package in the repo..?
     pub download_size: Option<u64>,
+    /// Who built the package, when recorded
+    pub provenance: Provenance,
+}
+
+/// Which builder built a package, where, when & with what profile
+///
+/// Each is absent for packages built before they were recorded.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Provenance {
+    /// Name & version, i.e. `boulder version v0.26.6`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub builder: Option<String>,
+    /// Architecture of the host built on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// Seconds since the epoch, honoring `SOURCE_DATE_EPOCH`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+}
+
+impl Provenance {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
 }
 
 impl Meta {
@@ -80,6 +116,12 @@ impl Meta {
         let uri = find_meta_string(payload, StonePayloadMetaTag::PackageURI).ok();
         let hash = find_meta_string(payload, StonePayloadMetaTag::PackageHash).ok();
         let download_size = find_meta_u64(payload, StonePayloadMetaTag::PackageSize).ok();
+        let provenance = Provenance {
+            builder: find_meta_string(payload, StonePayloadMetaTag::Builder).ok(),
+            host: find_meta_string(payload, StonePayloadMetaTag::BuildHost).ok(),
+            time: find_meta_u64(payload, StonePayloadMetaTag::BuildTime).ok(),
+            profile: find_meta_string(payload, StonePayloadMetaTag::BuildProfile).ok(),
+        };
 
         let licenses = payload
             .iter()
@@ -114,6 +156,7 @@ impl Meta {
             uri,
             hash,
             download_size,
+            provenance,
         })
     }
 
@@ -173,6 +216,27 @@ impl Meta {
                 StonePayloadMetaPrimitive::Uint64(size),
             )
         }))
+        .chain(
+            self.provenance
+                .builder
+                .map(|builder| (StonePayloadMetaTag::Builder, StonePayloadMetaPrimitive::String(builder))),
+        )
+        .chain(
+            self.provenance
+                .host
+                .map(|host| (StonePayloadMetaTag::BuildHost, StonePayloadMetaPrimitive::String(host))),
+        )
+        .chain(
+            self.provenance
+                .time
+                .map(|time| (StonePayloadMetaTag::BuildTime, StonePayloadMetaPrimitive::Uint64(time))),
+        )
+        .chain(self.provenance.profile.map(|profile| {
+            (
+                StonePayloadMetaTag::BuildProfile,
+                StonePayloadMetaPrimitive::String(profile),
+            )
+        }))
         .chain(
             self.licenses
                 .into_iter()
@@ -211,6 +275,27 @@ impl Meta {
         .collect()
     }
 
+    /// Returns the most relevant field matched if every word of `text` appears,
+    /// case-insensitively, in the name, summary or description
+    pub fn search_match(&self, text: &str) -> Option<SearchMatch> {
+        let words = text.split_whitespace().map(str::to_lowercase).collect::<Vec<_>>();
+        let fields = [
+            (SearchMatch::Name, self.name.as_str().to_lowercase()),
+            (SearchMatch::Summary, self.summary.to_lowercase()),
+            (SearchMatch::Description, self.description.to_lowercase()),
+        ];
+
+        let found = |word: &String| fields.iter().any(|(_, field)| field.contains(word.as_str()));
+        if words.is_empty() || !words.iter().all(found) {
+            return None;
+        }
+
+        fields
+            .into_iter()
+            .find(|(_, field)| words.iter().any(|word| field.contains(word.as_str())))
+            .map(|(kind, _)| kind)
+    }
+
     /// Return a reusable ID
     pub fn id(&self) -> Id {
         Id(format!(
@@ -295,3 +380,40 @@ fn meta_conflict(meta: &StonePayloadMetaRecord) -> Option<Provider> {
 #[derive(Debug, Error)]
 #[error("Missing metadata field: {0:?}")]
 pub struct MissingMetaFieldError(pub StonePayloadMetaTag);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::package::fixture;
+
+    fn meta(provenance: Provenance) -> Meta {
+        Meta {
+            licenses: vec!["GPL-3.0-or-later".to_owned()],
+            provenance,
+            ..fixture::meta("nano")
+        }
+    }
+
+    #[test]
+    fn provenance_roundtrip() {
+        let provenance = Provenance {
+            builder: Some("boulder version v0.26.6".to_owned()),
+            host: Some("x86_64".to_owned()),
+            time: Some(1_700_000_000),
+            profile: Some("default-x86_64".to_owned()),
+        };
+        let payload = meta(provenance.clone()).to_stone_payload();
+        assert!(
+            payload
+                .iter()
+                .any(|record| record.tag == StonePayloadMetaTag::BuildProfile)
+        );
+        assert_eq!(Meta::from_stone_payload(&payload).unwrap(), meta(provenance));
+
+        // Packages built before provenance was recorded lack it
+        let payload = meta(Provenance::default()).to_stone_payload();
+        let read = Meta::from_stone_payload(&payload).unwrap();
+        assert!(read.provenance.is_empty());
+        assert_eq!(read, meta(Provenance::default()));
+    }
+}
@@ -7,8 +7,10 @@ use astr::AStr;
 use derive_more::{Debug, Display, From, Into};
 use itertools::Itertools;
 
-pub use self::meta::{Meta, MissingMetaFieldError, Name};
+pub use self::meta::{Meta, MissingMetaFieldError, Name, Provenance, SearchMatch};
 
+#[cfg(any(test, feature = "testing"))]
+pub mod fixture;
 pub mod meta;
 pub mod render;
 
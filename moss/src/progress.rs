@@ -0,0 +1,307 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Progress reporting which degrades to plain lines when bars aren't wanted
+//!
+//! Bars redraw with terminal control sequences, which garble logs when output
+//! isn't a terminal. In plain mode a [`Counter`] instead prints a line such as
+//! `Downloaded 12/40` each time it crosses another tenth of its length.
+
+use std::{
+    fmt,
+    io::{self, Write},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use tui::{MultiProgress, ProgressBar, ProgressDrawTarget};
+
+static PLAIN: AtomicBool = AtomicBool::new(false);
+
+/// Report progress with plain lines instead of bars process-wide, i.e.
+/// with `--no-progress` or when stdout isn't a terminal
+pub fn set_plain(plain: bool) {
+    PLAIN.store(plain, Ordering::Relaxed);
+}
+
+/// Returns `true` if plain progress was requested with [`set_plain`]
+pub fn is_plain() -> bool {
+    PLAIN.load(Ordering::Relaxed)
+}
+
+type Output = Arc<Mutex<Box<dyn Write + Send>>>;
+
+/// Renders a group of progress bars, or plain lines in their place
+#[derive(Clone)]
+pub struct Reporter {
+    kind: Kind,
+}
+
+#[derive(Clone)]
+enum Kind {
+    Bars(MultiProgress),
+    Plain(Output),
+}
+
+impl Default for Reporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Reporter {
+    /// Create a reporter honoring [`set_plain`] & [`tui::set_quiet`]
+    pub fn new() -> Self {
+        if !is_plain() {
+            Self::bars()
+        } else if tui::is_quiet() {
+            Self::plain(io::sink())
+        } else {
+            Self::plain(io::stdout())
+        }
+    }
+
+    /// Render bars to stderr, unless [`tui::set_quiet`]
+    pub fn bars() -> Self {
+        Self {
+            kind: Kind::Bars(MultiProgress::with_draw_target(tui::draw_target())),
+        }
+    }
+
+    /// Write plain progress lines to `out`
+    pub fn plain(out: impl Write + Send + 'static) -> Self {
+        Self {
+            kind: Kind::Plain(Arc::new(Mutex::new(Box::new(out)))),
+        }
+    }
+
+    pub fn is_plain(&self) -> bool {
+        matches!(self.kind, Kind::Plain(_))
+    }
+
+    /// Track overall progress with `bar`, reported as `{verb} {pos}/{len}` in plain mode
+    pub fn counter(&self, verb: &str, bar: ProgressBar) -> Counter {
+        match &self.kind {
+            Kind::Bars(multi) => Counter {
+                bar: multi.add(bar),
+                plain: None,
+            },
+            Kind::Plain(out) => Counter {
+                bar: hidden(bar),
+                plain: Some(Arc::new(PlainCounter {
+                    out: out.clone(),
+                    stage: Mutex::new(Stage {
+                        verb: verb.to_owned(),
+                        reported: None,
+                    }),
+                })),
+            },
+        }
+    }
+
+    /// Add a transient bar, which is never shown in plain mode
+    pub fn add(&self, bar: ProgressBar) -> ProgressBar {
+        match &self.kind {
+            Kind::Bars(multi) => multi.add(bar),
+            Kind::Plain(_) => hidden(bar),
+        }
+    }
+
+    /// Add a transient bar above `counter`, which is never shown in plain mode
+    pub fn insert_before(&self, counter: &Counter, bar: ProgressBar) -> ProgressBar {
+        match &self.kind {
+            Kind::Bars(multi) => multi.insert_before(&counter.bar, bar),
+            Kind::Plain(_) => hidden(bar),
+        }
+    }
+
+    pub fn remove(&self, bar: &ProgressBar) {
+        if let Kind::Bars(multi) = &self.kind {
+            multi.remove(bar);
+        }
+    }
+
+    /// Print a line without it being overdrawn by bars
+    pub fn println(&self, line: impl fmt::Display) {
+        match &self.kind {
+            Kind::Bars(multi) => multi.suspend(|| println!("{line}")),
+            Kind::Plain(out) => write_line(out, line),
+        }
+    }
+
+    /// Remove all bars from the terminal
+    pub fn clear(&self) -> io::Result<()> {
+        match &self.kind {
+            Kind::Bars(multi) => multi.clear(),
+            Kind::Plain(_) => Ok(()),
+        }
+    }
+}
+
+/// Overall progress of a [`Reporter`]
+#[derive(Clone)]
+pub struct Counter {
+    bar: ProgressBar,
+    plain: Option<Arc<PlainCounter>>,
+}
+
+struct PlainCounter {
+    out: Output,
+    stage: Mutex<Stage>,
+}
+
+struct Stage {
+    verb: String,
+    /// Last tenth of the length a line was printed for
+    reported: Option<u64>,
+}
+
+impl Counter {
+    pub fn inc(&self, delta: u64) {
+        self.bar.inc(delta);
+        self.report();
+    }
+
+    pub fn set_position(&self, pos: u64) {
+        self.bar.set_position(pos);
+        self.report();
+    }
+
+    pub fn set_length(&self, len: u64) {
+        self.bar.set_length(len);
+    }
+
+    /// Only shown on the bar, plain mode reports counts alone
+    pub fn set_message(&self, msg: impl Into<std::borrow::Cow<'static, str>>) {
+        self.bar.set_message(msg);
+    }
+
+    /// Start counting a new stage of `len` steps, reported with `verb`
+    pub fn restart(&self, verb: &str, len: u64) {
+        self.bar.set_length(len);
+        self.bar.set_position(0);
+        if let Some(plain) = &self.plain {
+            let mut stage = plain.stage.lock().unwrap_or_else(|e| e.into_inner());
+            stage.verb = verb.to_owned();
+            stage.reported = None;
+        }
+    }
+
+    pub fn position(&self) -> u64 {
+        self.bar.position()
+    }
+
+    pub fn length(&self) -> Option<u64> {
+        self.bar.length()
+    }
+
+    pub fn tick(&self) {
+        self.bar.tick();
+    }
+
+    pub fn enable_steady_tick(&self, interval: Duration) {
+        if self.plain.is_none() {
+            self.bar.enable_steady_tick(interval);
+        }
+    }
+
+    pub fn finish_and_clear(&self) {
+        self.bar.finish_and_clear();
+    }
+
+    fn report(&self) {
+        let Some(plain) = &self.plain else {
+            return;
+        };
+
+        // Hold the lock while reading the position, so lines are printed in order
+        let mut stage = plain.stage.lock().unwrap_or_else(|e| e.into_inner());
+        let pos = self.bar.position();
+        let len = self.bar.length().unwrap_or_default();
+        if len == 0 {
+            return;
+        }
+
+        let tenth = pos.min(len) * 10 / len;
+        if stage.reported.is_some_and(|reported| reported >= tenth) {
+            return;
+        }
+        stage.reported = Some(tenth);
+
+        write_line(&plain.out, format_args!("{} {pos}/{len}", stage.verb));
+    }
+}
+
+fn hidden(bar: ProgressBar) -> ProgressBar {
+    bar.set_draw_target(ProgressDrawTarget::hidden());
+    bar
+}
+
+fn write_line(out: &Output, line: impl fmt::Display) {
+    let mut out = out.lock().unwrap_or_else(|e| e.into_inner());
+    // Progress is best effort, a closed pipe mustn't fail the operation
+    let _ = writeln!(out, "{line}");
+    let _ = out.flush();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Shared buffer to inspect what a plain [`Reporter`] wrote
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn plain_lines() {
+        let buffer = Buffer::default();
+        let reporter = Reporter::plain(buffer.clone());
+        assert!(reporter.is_plain());
+
+        let counter = reporter.counter("Downloaded", ProgressBar::new(40));
+        let package = reporter.insert_before(&counter, ProgressBar::new(1024));
+        for _ in 0..12 {
+            package.inc(512);
+            counter.inc(1);
+        }
+        reporter.println("Installed nano");
+        counter.set_position(40);
+
+        // A new stage reports from the start
+        counter.restart("Stored", 2);
+        counter.inc(1);
+        counter.inc(1);
+
+        // Without a length there is nothing to report
+        let spinner = reporter.counter("Refreshed", ProgressBar::new_spinner());
+        spinner.inc(1);
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            output.lines().collect::<Vec<_>>(),
+            [
+                "Downloaded 1/40",
+                "Downloaded 4/40",
+                "Downloaded 8/40",
+                "Downloaded 12/40",
+                "Installed nano",
+                "Downloaded 40/40",
+                "Stored 1/2",
+                "Stored 2/2",
+            ]
+        );
+    }
+}
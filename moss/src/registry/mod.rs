@@ -6,13 +6,14 @@
 
 use itertools::Itertools;
 
-use crate::Provider;
 use crate::package::{self, Package};
+use crate::{Provider, ProviderGlob};
 
 pub use self::plugin::Plugin;
 pub use self::transaction::Transaction;
 
 pub mod plugin;
+pub mod suggest;
 pub mod transaction;
 
 /// A registry is composed of multiple "query plugins" that
@@ -29,6 +30,12 @@ impl Registry {
         self.plugins.push(plugin);
     }
 
+    /// Keep only the plugins offering packages from `origin`, returning whether any are
+    pub fn retain_origin(&mut self, origin: &plugin::Origin) -> bool {
+        self.plugins.retain(|plugin| plugin.origin() == *origin);
+        !self.plugins.is_empty()
+    }
+
     fn query<'a, T, I>(&'a self, query: impl Fn(&'a Plugin) -> I + Copy + 'a) -> impl Iterator<Item = T> + 'a
     where
         I: IntoIterator<Item = T> + 'a,
@@ -48,6 +55,15 @@ impl Registry {
         self.query(move |plugin| plugin.query_provider(provider, flags))
     }
 
+    /// Return a sorted stream of [`Package`] with a provider matching `glob`
+    pub fn by_provider_glob<'a>(
+        &'a self,
+        glob: &'a ProviderGlob,
+        flags: package::Flags,
+    ) -> impl Iterator<Item = Package> + 'a {
+        self.query(move |plugin| plugin.query_provider_glob(glob, flags))
+    }
+
     /// Optimized version of `by_provider` returning [`package::Id`] only
     pub fn by_provider_id_only<'a>(
         &'a self,
@@ -75,9 +91,52 @@ impl Registry {
         self.query(move |plugin| plugin.query_keyword(keyword, flags))
     }
 
+    /// Return [`Package`]s matching every word of `text` in their name, summary
+    /// or description, best matches first
+    ///
+    /// Results are merged across plugins by [`package::SearchMatch`], then plugin
+    /// priority, then each plugin's own ranking
+    pub fn search(&self, text: &str, flags: package::Flags) -> Vec<Package> {
+        self.query(move |plugin| plugin.search(text, flags))
+            .enumerate()
+            .sorted_by_key(|(rank, package)| {
+                (
+                    package
+                        .meta
+                        .search_match(text)
+                        .unwrap_or(package::SearchMatch::Description),
+                    *rank,
+                )
+            })
+            .map(|(_, package)| package)
+            .unique_by(|package| package.id.clone())
+            .collect()
+    }
+
+    /// Returns the [`plugin::Origin`] of the highest priority plugin offering `id`,
+    /// preferring those it can be installed from over the installed packages
+    pub fn origin(&self, id: &package::Id) -> Option<plugin::Origin> {
+        self.plugins
+            .iter()
+            .sorted_by(|a, b| a.priority().cmp(&b.priority()).reverse())
+            .filter(|plugin| plugin.package(id).is_some())
+            .map(Plugin::origin)
+            .min_by_key(|origin| *origin == plugin::Origin::Installed)
+    }
+
+    /// Suggest up to `limit` available providers of the same kind resembling
+    /// the mistyped `provider`, most similar first
+    pub fn suggest(&self, provider: &Provider, limit: usize) -> Vec<suggest::Suggestion> {
+        suggest::suggest(&self.plugins, provider, limit)
+    }
+
     /// Return a sorted stream of [`Package`] matching the given [`Flags`]
     ///
+    /// Packages are ordered by plugin priority, then by name & newest release
+    /// first within each plugin, see [`db::meta::Database::query`]
+    ///
     /// [`Flags`]: package::Flags
+    /// [`db::meta::Database::query`]: crate::db::meta::Database::query
     pub fn list(&self, flags: package::Flags) -> impl Iterator<Item = Package> + '_ {
         self.query(move |plugin| plugin.list(flags))
     }
@@ -122,6 +181,7 @@ mod test {
                 uri: Default::default(),
                 hash: Default::default(),
                 download_size: Default::default(),
+                provenance: Default::default(),
             },
             flags: package::Flags::default(),
         };
@@ -177,6 +237,7 @@ mod test {
                 uri: Default::default(),
                 hash: Default::default(),
                 download_size: Default::default(),
+                provenance: Default::default(),
             },
             flags,
         };
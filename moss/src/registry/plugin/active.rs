@@ -3,7 +3,7 @@
 
 use log::warn;
 
-use crate::{Package, Provider, State, db, package};
+use crate::{Package, Provider, ProviderGlob, State, db, package};
 
 // TODO:
 #[derive(Debug, Clone)]
@@ -42,9 +42,18 @@ impl Active {
 
     /// Query, restricted to state
     fn query(&self, flags: package::Flags, filter: Option<db::meta::Filter<'_>>) -> Vec<Package> {
+        self.select(flags, |db| db.query(filter))
+    }
+
+    /// Load packages with `load`, restricted to state
+    fn select(
+        &self,
+        flags: package::Flags,
+        load: impl FnOnce(&db::meta::Database) -> Result<Vec<(package::Id, package::Meta)>, db::meta::Error>,
+    ) -> Vec<Package> {
         if flags.installed || flags == package::Flags::default() {
             // TODO: Error handling
-            let packages = match self.db.query(filter) {
+            let packages = match load(&self.db) {
                 Ok(packages) => packages,
                 Err(error) => {
                     warn!("failed to query repository packages: {error}");
@@ -75,11 +84,21 @@ impl Active {
         self.query(flags, Some(db::meta::Filter::Keyword(keyword)))
     }
 
+    /// Search name, summary & description, best matches first
+    pub fn search(&self, text: &str, flags: package::Flags) -> Vec<Package> {
+        self.select(flags, |db| db.search(text))
+    }
+
     /// Query all packages that match the given provider identity
     pub fn query_provider(&self, provider: &Provider, flags: package::Flags) -> Vec<Package> {
         self.query(flags, Some(db::meta::Filter::Provider(provider.clone())))
     }
 
+    /// Query all packages with a provider matching `glob`
+    pub fn query_provider_glob(&self, glob: &ProviderGlob, flags: package::Flags) -> Vec<Package> {
+        self.query(flags, Some(db::meta::Filter::ProviderGlob(glob)))
+    }
+
     /// Query matching by name
     pub fn query_name(&self, package_name: &package::Name, flags: package::Flags) -> Vec<Package> {
         self.query(flags, Some(db::meta::Filter::Name(package_name.clone())))
@@ -1,45 +1,222 @@
 // SPDX-FileCopyrightText: 2023 AerynOS Developers
 // SPDX-License-Identifier: MPL-2.0
 
+//! Loose local `.stone` files, i.e. a directory of freshly built packages
+//!
+//! Directories are configured with `local.d` configs, i.e.
+//! `/etc/moss/local.d/builds.yaml`, relative to the installation root:
+//!
+//! ```yaml
+//! directories:
+//!   - path: /var/cache/boulder/artefacts
+//!     depth: 2
+//! ```
+//!
+//! The local overlay repository is a flat directory configured with `local-repo`
+//! configs, i.e. `/etc/moss/local-repo.d/builds.yaml`:
+//!
+//! ```yaml
+//! path: /var/lib/moss/local-repo
+//! ```
+//!
+//! Its stones are preferred over those of every repository, and it's only
+//! rescanned once the directory is modified, i.e. when `boulder build --mv-to-repo local`
+//! moves stones into it, see [`Cobble::refresh_if_modified`].
+
 use std::collections::BTreeMap;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
-use fs_err::File;
+use fs_err::{self as fs, File};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
 use stone::{StoneDecodedPayload, StoneReadError};
 use thiserror::Error;
 
-use crate::Provider;
+use super::provider_names;
 use crate::package::{self, Meta, MissingMetaFieldError, Package, meta};
+use crate::{Provider, ProviderGlob, dependency, environment};
+
+/// Directories of local stones
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub directories: Vec<Directory>,
+}
+
+impl config::Config for Config {
+    fn domain() -> String {
+        "local".into()
+    }
+}
+
+/// Name of the local overlay repository, i.e. as targeted by `boulder build --mv-to-repo`
+pub const OVERLAY_NAME: &str = "local";
+
+/// Directory of the local overlay repository
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OverlayConfig {
+    pub path: PathBuf,
+}
+
+impl config::Config for OverlayConfig {
+    fn domain() -> String {
+        "local-repo".into()
+    }
+}
+
+/// A directory scanned for stones
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Directory {
+    pub path: PathBuf,
+    /// Levels of subdirectories to scan, [`environment::LOCAL_STONES_MAX_DEPTH`] if unset
+    #[serde(default)]
+    pub depth: Option<usize>,
+}
 
-// TODO:
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
 pub struct Cobble {
     // Storage of local packages
     packages: BTreeMap<meta::Id, State>,
+    /// Directories to scan & how deep
+    directories: Vec<(PathBuf, usize)>,
+    /// Stones found by the last scan, to only read new or modified ones
+    scanned: BTreeMap<PathBuf, Scanned>,
+    /// When each directory was last modified, as of the last scan
+    modified: BTreeMap<PathBuf, SystemTime>,
+}
+
+/// A stone found while scanning
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Scanned {
+    stamp: Stamp,
+    /// `None` if it couldn't be read
+    id: Option<meta::Id>,
+}
+
+/// Identifies a version of a file without reading it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    modified: SystemTime,
+    size: u64,
 }
 
 impl Cobble {
+    /// Scan the directories of all `local` configs, relative to `root`
+    ///
+    /// Returns the stones which couldn't be read, alongside the plugin
+    pub fn load(config: &config::Manager, root: &Path) -> (Self, Vec<(PathBuf, Error)>) {
+        let directories = config
+            .load::<Config>()
+            .into_iter()
+            .flat_map(|config| config.value.directories)
+            .map(|directory| {
+                let path = root.join(directory.path.strip_prefix("/").unwrap_or(&directory.path));
+                (path, directory.depth.unwrap_or(environment::LOCAL_STONES_MAX_DEPTH))
+            })
+            .collect();
+
+        let mut cobble = Self::with_directories(directories);
+        let skipped = cobble.refresh();
+        (cobble, skipped)
+    }
+
+    /// Scan the local overlay repository, the directories of all `local-repo`
+    /// configs relative to `root`
+    ///
+    /// Returns the stones which couldn't be read, alongside the plugin
+    pub fn load_overlay(config: &config::Manager, root: &Path) -> (Self, Vec<(PathBuf, Error)>) {
+        let directories = overlay_directories(config, root)
+            .into_iter()
+            .map(|path| (path, 0))
+            .collect();
+
+        let mut cobble = Self::with_directories(directories);
+        let skipped = cobble.refresh();
+        (cobble, skipped)
+    }
+
+    /// Scan `directories`, each with its depth, once refreshed
+    pub fn with_directories(directories: Vec<(PathBuf, usize)>) -> Self {
+        Self {
+            directories,
+            ..Default::default()
+        }
+    }
+
+    /// Rescan the directories, only reading stones which are new or modified
+    /// since the last scan
+    ///
+    /// Stones which can't be read are skipped & returned, once per modification
+    pub fn refresh(&mut self) -> Vec<(PathBuf, Error)> {
+        // Taken first, so modifications during the scan are caught by the next
+        self.modified = modified_directories(&self.directories);
+
+        let mut found = BTreeMap::new();
+        for (directory, depth) in &self.directories {
+            find_stones(directory, *depth, &mut found);
+        }
+
+        // Forget stones which were removed or modified
+        let stale = self
+            .scanned
+            .iter()
+            .filter(|(path, scanned)| found.get(*path) != Some(&scanned.stamp))
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        for path in stale {
+            if let Some(Scanned { id: Some(id), .. }) = self.scanned.remove(&path)
+                && self.packages.get(&id).is_some_and(|state| state.path == path)
+            {
+                self.packages.remove(&id);
+            }
+        }
+
+        let mut skipped = vec![];
+        for (path, stamp) in found {
+            if self.scanned.contains_key(&path) {
+                continue;
+            }
+
+            let id = match read_meta(&path) {
+                Ok(meta) => {
+                    let id = meta.id();
+                    self.packages.insert(
+                        id.clone(),
+                        State {
+                            path: path.clone(),
+                            meta,
+                        },
+                    );
+                    Some(id)
+                }
+                Err(error) => {
+                    skipped.push((path.clone(), error));
+                    None
+                }
+            };
+            self.scanned.insert(path, Scanned { stamp, id });
+        }
+
+        skipped
+    }
+
+    /// [`Self::refresh`] once a directory was modified since the last scan,
+    /// returning `None` otherwise
+    ///
+    /// Only the directories themselves are checked, so stones changed within
+    /// their subdirectories, or rewritten in place, are missed
+    pub fn refresh_if_modified(&mut self) -> Option<Vec<(PathBuf, Error)>> {
+        (modified_directories(&self.directories) != self.modified).then(|| self.refresh())
+    }
+
     /// Add a package to the cobble set
     pub fn add_package(&mut self, path: impl Into<PathBuf>) -> Result<meta::Id, Error> {
         let path = path.into();
-        let mut file = File::open(&path)?;
-        let mut reader = stone::read(&mut file)?;
-        let mut payloads = reader.payloads()?;
-
-        // Grab the metapayload
-        let metadata = payloads
-            .find_map(|result| {
-                if let Ok(StoneDecodedPayload::Meta(meta)) = result {
-                    Some(meta)
-                } else {
-                    None
-                }
-            })
-            .ok_or(Error::MissingMetaPayload)?;
 
         // Whack it into the cobbler
-        let meta = Meta::from_stone_payload(&metadata.body)?;
+        let meta = read_meta(&path)?;
         let id = meta.id();
         let ret = id.clone();
 
@@ -76,19 +253,109 @@ impl Cobble {
         })
     }
 
+    /// Search name, summary & description, best matches first
+    pub fn search(&self, text: &str, flags: package::Flags) -> Vec<Package> {
+        let mut packages = self.query(flags, |meta| meta.search_match(text).is_some());
+        packages.sort_by_key(|package| package.meta.search_match(text));
+        packages
+    }
+
     pub fn query_provider(&self, provider: &Provider, flags: package::Flags) -> Vec<Package> {
         self.query(flags, |meta| meta.providers.contains(provider))
     }
 
+    pub fn query_provider_glob(&self, glob: &ProviderGlob, flags: package::Flags) -> Vec<Package> {
+        self.query(flags, |meta| {
+            meta.providers.iter().any(|provider| glob.matches(provider))
+        })
+    }
+
     pub fn query_name(&self, package_name: &package::Name, flags: package::Flags) -> Vec<Package> {
         self.query(flags, |meta| meta.name == *package_name)
     }
 
+    /// Names of `kind` providers starting with `prefix`, capped to `limit`
+    pub fn provider_names(&self, kind: dependency::Kind, prefix: &str, limit: usize) -> Vec<String> {
+        provider_names(self.packages.values().map(|state| &state.meta), kind, prefix, limit)
+    }
+
     pub fn priority(&self) -> u64 {
         u64::MAX
     }
 }
 
+/// Directories of the local overlay repository configured by `config`, relative
+/// to `root`, in order
+pub fn overlay_directories(config: &config::Manager, root: &Path) -> Vec<PathBuf> {
+    config
+        .load::<OverlayConfig>()
+        .into_iter()
+        .map(|config| root.join(config.value.path.strip_prefix("/").unwrap_or(&config.value.path)))
+        .sorted()
+        .dedup()
+        .collect()
+}
+
+/// When each of the `directories` which exist was last modified
+fn modified_directories(directories: &[(PathBuf, usize)]) -> BTreeMap<PathBuf, SystemTime> {
+    directories
+        .iter()
+        .filter_map(|(directory, _)| Some((directory.clone(), fs::metadata(directory).ok()?.modified().ok()?)))
+        .collect()
+}
+
+/// Add the `.stone` files of `directory` to `found`, descending `depth` levels
+/// of subdirectories. Unreadable directories are ignored.
+fn find_stones(directory: &Path, depth: usize, found: &mut BTreeMap<PathBuf, Stamp>) {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(metadata) = fs::metadata(&path) else {
+            continue;
+        };
+
+        if metadata.is_dir() {
+            if depth > 0 {
+                find_stones(&path, depth - 1, found);
+            }
+        } else if metadata.is_file()
+            && path.extension().is_some_and(|extension| extension == "stone")
+            && let Ok(modified) = metadata.modified()
+        {
+            found.insert(
+                path,
+                Stamp {
+                    modified,
+                    size: metadata.len(),
+                },
+            );
+        }
+    }
+}
+
+/// Read the metadata payload of the stone at `path`
+fn read_meta(path: &Path) -> Result<Meta, Error> {
+    let mut file = File::open(path)?;
+    let mut reader = stone::read(&mut file)?;
+    let mut payloads = reader.payloads()?;
+
+    // Grab the metapayload
+    let metadata = payloads
+        .find_map(|result| {
+            if let Ok(StoneDecodedPayload::Meta(meta)) = result {
+                Some(meta)
+            } else {
+                None
+            }
+        })
+        .ok_or(Error::MissingMetaPayload)?;
+
+    Ok(Meta::from_stone_payload(&metadata.body)?)
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct State {
     path: PathBuf,
@@ -120,3 +387,137 @@ pub enum Error {
     #[error("metadata")]
     Metadata(#[from] MissingMetaFieldError),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::package::fixture;
+    use crate::registry::{Plugin, Registry};
+
+    fn names(cobble: &Cobble) -> Vec<String> {
+        let mut names = cobble
+            .list(package::Flags::new().with_available())
+            .into_iter()
+            .map(|package| package.meta.name.to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn recursive_scan() {
+        let dir = tempfile::tempdir().unwrap();
+        fixture::stone(dir.path(), &fixture::meta("nano").to_stone_payload(), &[]);
+        fixture::stone(&dir.path().join("a/b"), &fixture::meta("vim").to_stone_payload(), &[]);
+        // Deeper than the scan descends
+        fixture::stone(
+            &dir.path().join("a/b/c"),
+            &fixture::meta("emacs").to_stone_payload(),
+            &[],
+        );
+
+        let mut cobble = Cobble::with_directories(vec![(dir.path().to_owned(), 2)]);
+        assert!(cobble.refresh().is_empty());
+        assert_eq!(names(&cobble), ["nano", "vim"]);
+
+        let mut registry = Registry::default();
+        registry.add_plugin(Plugin::Cobble(cobble));
+        let vim = registry
+            .by_name(
+                &package::Name::from("vim".to_owned()),
+                package::Flags::new().with_available(),
+            )
+            .collect::<Vec<_>>();
+        assert_eq!(vim.len(), 1);
+    }
+
+    #[test]
+    fn refresh_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let nano = fixture::stone(dir.path(), &fixture::meta("nano").to_stone_payload(), &[]);
+
+        let mut cobble = Cobble::with_directories(vec![(dir.path().to_owned(), 1)]);
+        assert!(cobble.refresh().is_empty());
+        assert_eq!(names(&cobble), ["nano"]);
+
+        // Newly dropped & removed stones are picked up
+        fixture::stone(
+            &dir.path().join("builds"),
+            &fixture::meta("vim").to_stone_payload(),
+            &[],
+        );
+        fs::remove_file(&nano).unwrap();
+        assert!(cobble.refresh().is_empty());
+        assert_eq!(names(&cobble), ["vim"]);
+
+        // Corrupt stones are skipped & only reported once
+        let corrupt = dir.path().join("corrupt.stone");
+        fs::write(&corrupt, "not a stone").unwrap();
+        let skipped = cobble.refresh();
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].0, corrupt);
+        assert!(cobble.refresh().is_empty());
+        assert_eq!(names(&cobble), ["vim"]);
+    }
+
+    #[test]
+    fn load_config() {
+        let root = tempfile::tempdir().unwrap();
+        let config = root.path().join("etc/moss/local.d");
+        fs::create_dir_all(&config).unwrap();
+        fs::write(
+            config.join("builds.yaml"),
+            "directories:\n  - path: /srv/stones\n    depth: 0\n",
+        )
+        .unwrap();
+        fixture::stone(
+            &root.path().join("srv/stones"),
+            &fixture::meta("nano").to_stone_payload(),
+            &[],
+        );
+        fixture::stone(
+            &root.path().join("srv/stones/nested"),
+            &fixture::meta("vim").to_stone_payload(),
+            &[],
+        );
+
+        let manager = config::Manager::system(root.path(), "moss");
+        let (cobble, skipped) = Cobble::load(&manager, root.path());
+        assert!(skipped.is_empty());
+        assert_eq!(names(&cobble), ["nano"]);
+    }
+
+    #[test]
+    fn overlay_modified() {
+        let root = tempfile::tempdir().unwrap();
+        let config = root.path().join("etc/moss/local-repo.d");
+        fs::create_dir_all(&config).unwrap();
+        fs::write(config.join("builds.yaml"), "path: /var/lib/moss/local-repo\n").unwrap();
+
+        let overlay = root.path().join("var/lib/moss/local-repo");
+        fixture::stone(&overlay, &fixture::meta("nano").to_stone_payload(), &[]);
+        // The overlay is flat
+        fixture::stone(&overlay.join("nested"), &fixture::meta("vim").to_stone_payload(), &[]);
+
+        let manager = config::Manager::system(root.path(), "moss");
+        assert_eq!(
+            overlay_directories(&manager, root.path()),
+            std::slice::from_ref(&overlay)
+        );
+        let (mut cobble, skipped) = Cobble::load_overlay(&manager, root.path());
+        assert!(skipped.is_empty());
+        assert_eq!(names(&cobble), ["nano"]);
+        assert!(cobble.refresh_if_modified().is_none());
+
+        // Picked up once the directory is modified, timestamps being too coarse
+        // to tell apart changes made right after the scan
+        fixture::stone(&overlay, &fixture::meta("vim").to_stone_payload(), &[]);
+        File::open(&overlay)
+            .unwrap()
+            .set_modified(SystemTime::now() + std::time::Duration::from_secs(60))
+            .unwrap();
+        assert!(cobble.refresh_if_modified().is_some_and(|skipped| skipped.is_empty()));
+        assert_eq!(names(&cobble), ["nano", "vim"]);
+        assert!(cobble.refresh_if_modified().is_none());
+    }
+}
@@ -9,11 +9,15 @@
 //!
 //! [`Registry`]: super::Registry
 
-use crate::Provider;
+use derive_more::Display;
+
+use std::collections::BTreeSet;
+
 use crate::registry::package::{self, Package};
+use crate::{Provider, ProviderGlob, dependency};
 
 pub use self::active::Active;
-pub use self::cobble::Cobble;
+pub use self::cobble::{Cobble, OVERLAY_NAME, overlay_directories};
 pub use self::repository::Repository;
 #[cfg(any(test, feature = "testing"))]
 pub use self::test::Test;
@@ -35,6 +39,20 @@ pub enum Plugin {
     Test(Test),
 }
 
+/// Where a [`Plugin`] offers packages from
+#[derive(Debug, Clone, PartialEq, Eq, Display)]
+pub enum Origin {
+    /// Installed packages, which may no longer be available elsewhere
+    #[display("installed")]
+    Installed,
+    /// Stones from the local filesystem
+    #[display("local")]
+    Local,
+    /// A configured repository
+    #[display("{_0}")]
+    Repository(crate::repository::Id),
+}
+
 impl Plugin {
     /// Return a package for the given [`package::Id`]. Returns `None` if
     /// the `package` cannot be located.
@@ -72,6 +90,19 @@ impl Plugin {
         })
     }
 
+    /// Returns packages matching every word of `text` in their name, summary
+    /// or description, ranked best first
+    pub fn search(&self, text: &str, flags: package::Flags) -> Vec<Package> {
+        match self {
+            Plugin::Active(plugin) => plugin.search(text, flags),
+            Plugin::Cobble(plugin) => plugin.search(text, flags),
+            Plugin::Repository(plugin) => plugin.search(text, flags),
+
+            #[cfg(any(test, feature = "testing"))]
+            Plugin::Test(plugin) => plugin.search(text, flags),
+        }
+    }
+
     /// Returns a list of packages with matching `provider` and `flags`
     pub fn query_provider(&self, provider: &Provider, flags: package::Flags) -> package::Sorted<Vec<Package>> {
         package::Sorted::new(match self {
@@ -84,6 +115,18 @@ impl Plugin {
         })
     }
 
+    /// Returns a list of packages with a provider matching `glob` and `flags`
+    pub fn query_provider_glob(&self, glob: &ProviderGlob, flags: package::Flags) -> package::Sorted<Vec<Package>> {
+        package::Sorted::new(match self {
+            Plugin::Active(plugin) => plugin.query_provider_glob(glob, flags),
+            Plugin::Cobble(plugin) => plugin.query_provider_glob(glob, flags),
+            Plugin::Repository(plugin) => plugin.query_provider_glob(glob, flags),
+
+            #[cfg(any(test, feature = "testing"))]
+            Plugin::Test(plugin) => plugin.query_provider_glob(glob, flags),
+        })
+    }
+
     pub fn query_provider_id_only(
         &self,
         provider: &Provider,
@@ -115,6 +158,31 @@ impl Plugin {
         })
     }
 
+    /// Names of available `kind` providers starting with `prefix`, capped to `limit`
+    pub fn provider_names(&self, kind: dependency::Kind, prefix: &str, limit: usize) -> Vec<String> {
+        match self {
+            // Installed packages can't be installed again
+            Plugin::Active(_) => vec![],
+            Plugin::Cobble(plugin) => plugin.provider_names(kind, prefix, limit),
+            Plugin::Repository(plugin) => plugin.provider_names(kind, prefix, limit),
+
+            #[cfg(any(test, feature = "testing"))]
+            Plugin::Test(plugin) => plugin.provider_names(kind, prefix, limit),
+        }
+    }
+
+    /// Where this plugin offers packages from
+    pub fn origin(&self) -> Origin {
+        match self {
+            Plugin::Active(_) => Origin::Installed,
+            Plugin::Cobble(_) => Origin::Local,
+            Plugin::Repository(plugin) => Origin::Repository(plugin.id().clone()),
+
+            #[cfg(any(test, feature = "testing"))]
+            Plugin::Test(plugin) => plugin.origin.clone(),
+        }
+    }
+
     /// Plugin priority
     ///
     /// Higher priority = better chance of selection
@@ -130,19 +198,60 @@ impl Plugin {
     }
 }
 
+/// Distinct names of `kind` providers of `metas` starting with `prefix`, in
+/// order & capped to `limit`
+fn provider_names<'a>(
+    metas: impl IntoIterator<Item = &'a package::Meta>,
+    kind: dependency::Kind,
+    prefix: &str,
+    limit: usize,
+) -> Vec<String> {
+    metas
+        .into_iter()
+        .flat_map(|meta| {
+            let names = if kind == dependency::Kind::PackageName {
+                vec![meta.name.as_str()]
+            } else {
+                meta.providers
+                    .iter()
+                    .filter(|provider| provider.kind == kind)
+                    .map(|provider| provider.name.as_str())
+                    .collect()
+            };
+            names.into_iter().filter(|name| name.starts_with(prefix))
+        })
+        .map(ToOwned::to_owned)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .take(limit)
+        .collect()
+}
+
 #[cfg(any(test, feature = "testing"))]
 pub mod test {
-    use super::{Package, Provider, package};
+    use itertools::Itertools;
+
+    use super::{Origin, Package, Provider, ProviderGlob, dependency, package};
 
     #[derive(Debug, Clone, PartialEq, Eq)]
     pub struct Test {
         pub priority: u64,
+        pub origin: Origin,
         packages: Vec<Package>,
     }
 
     impl Test {
         pub fn new(priority: u64, packages: Vec<Package>) -> Self {
-            Self { priority, packages }
+            Self {
+                priority,
+                origin: Origin::Local,
+                packages,
+            }
+        }
+
+        /// Offer the packages from `origin` rather than the local filesystem
+        pub fn with_origin(self, origin: Origin) -> Self {
+            Self { origin, ..self }
         }
 
         pub fn package(&self, package: &package::Id) -> Option<Package> {
@@ -169,6 +278,16 @@ pub mod test {
                 .collect()
         }
 
+        pub fn search(&self, text: &str, flags: package::Flags) -> Vec<Package> {
+            self.packages
+                .iter()
+                .filter(|p| p.flags.contains(flags))
+                .filter_map(|p| Some((p.meta.search_match(text)?, p)))
+                .sorted_by_key(|(kind, _)| *kind)
+                .map(|(_, p)| p.clone())
+                .collect()
+        }
+
         pub fn query_provider(&self, provider: &Provider, flags: package::Flags) -> Vec<Package> {
             self.packages
                 .iter()
@@ -177,6 +296,14 @@ pub mod test {
                 .collect()
         }
 
+        pub fn query_provider_glob(&self, glob: &ProviderGlob, flags: package::Flags) -> Vec<Package> {
+            self.packages
+                .iter()
+                .filter(|p| p.meta.providers.iter().any(|provider| glob.matches(provider)) && p.flags.contains(flags))
+                .cloned()
+                .collect()
+        }
+
         pub fn query_provider_id_only(&self, provider: &Provider, flags: package::Flags) -> Vec<package::Id> {
             self.packages
                 .iter()
@@ -193,5 +320,10 @@ pub mod test {
                 .cloned()
                 .collect()
         }
+
+        pub fn provider_names(&self, kind: dependency::Kind, prefix: &str, limit: usize) -> Vec<String> {
+            let available = self.packages.iter().filter(|p| p.flags.available).map(|p| &p.meta);
+            super::provider_names(available, kind, prefix, limit)
+        }
     }
 }
@@ -4,7 +4,7 @@
 use log::warn;
 
 use crate::{
-    Provider, db,
+    Provider, ProviderGlob, db, dependency,
     package::{self, Package},
     repository,
 };
@@ -23,6 +23,10 @@ impl Repository {
         self.active.repository.priority.into()
     }
 
+    pub fn id(&self) -> &repository::Id {
+        &self.active.id
+    }
+
     pub fn package(&self, id: &package::Id) -> Option<Package> {
         let index_uri = self.active.index_uri()?;
 
@@ -51,9 +55,18 @@ impl Repository {
     }
 
     fn query(&self, flags: package::Flags, filter: Option<db::meta::Filter<'_>>) -> Vec<Package> {
+        self.select(flags, |db| db.query(filter))
+    }
+
+    /// Load packages with `load` from the repository index
+    fn select(
+        &self,
+        flags: package::Flags,
+        load: impl FnOnce(&db::meta::Database) -> Result<Vec<(package::Id, package::Meta)>, db::meta::Error>,
+    ) -> Vec<Package> {
         if flags.available || flags == package::Flags::default() {
             // TODO: Error handling
-            let packages = match self.active.db.query(filter) {
+            let packages = match load(&self.active.db) {
                 Ok(packages) => packages,
                 Err(error) => {
                     warn!("failed to query repository packages: {error}");
@@ -82,11 +95,21 @@ impl Repository {
         self.query(flags, Some(db::meta::Filter::Keyword(keyword)))
     }
 
+    /// Search name, summary & description, best matches first
+    pub fn search(&self, text: &str, flags: package::Flags) -> Vec<Package> {
+        self.select(flags, |db| db.search(text))
+    }
+
     /// Query all packages that match the given provider identity
     pub fn query_provider(&self, provider: &Provider, flags: package::Flags) -> Vec<Package> {
         self.query(flags, Some(db::meta::Filter::Provider(provider.clone())))
     }
 
+    /// Query all packages with a provider matching `glob`
+    pub fn query_provider_glob(&self, glob: &ProviderGlob, flags: package::Flags) -> Vec<Package> {
+        self.query(flags, Some(db::meta::Filter::ProviderGlob(glob)))
+    }
+
     pub fn query_name(&self, package_name: &package::Name, flags: package::Flags) -> Vec<Package> {
         self.query(flags, Some(db::meta::Filter::Name(package_name.clone())))
     }
@@ -105,6 +128,17 @@ impl Repository {
             vec![]
         }
     }
+
+    /// Names of `kind` providers starting with `prefix`, capped to `limit`
+    pub fn provider_names(&self, kind: dependency::Kind, prefix: &str, limit: usize) -> Vec<String> {
+        match self.active.db.provider_names_with_prefix(kind, prefix, limit) {
+            Ok(names) => names,
+            Err(error) => {
+                warn!("failed to query repository providers: {error}");
+                vec![]
+            }
+        }
+    }
 }
 
 impl PartialEq for Repository {
@@ -0,0 +1,219 @@
+// SPDX-FileCopyrightText: 2026 AerynOS Developers
+// SPDX-License-Identifier: MPL-2.0
+
+//! Suggestions for mistyped package names & providers
+//!
+//! Candidates are ranked by their Jaro-Winkler similarity to the input. The
+//! metric weighs a shared prefix heavily, so only names starting with the
+//! same character are scanned, which the name index of each repository
+//! serves without reading every package.
+
+use std::collections::BTreeSet;
+
+use rapidfuzz::distance::jaro_winkler;
+
+use super::Plugin;
+use crate::Provider;
+
+/// Most candidates scanned per plugin
+const MAX_SCANNED: usize = 10_000;
+
+/// Least similarity of a suggested candidate
+const MIN_SIMILARITY: f64 = 0.8;
+
+/// Least similarity of a suggestion close enough to offer in place of the input
+const CLOSE_SIMILARITY: f64 = 0.93;
+
+/// An available provider resembling a mistyped one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub provider: Provider,
+    /// Jaro-Winkler similarity to the input, from `0.0` to `1.0`
+    pub similarity: f64,
+}
+
+impl Suggestion {
+    /// Returns `true` if the suggestion is likely what was meant
+    pub fn is_close(&self) -> bool {
+        self.similarity >= CLOSE_SIMILARITY
+    }
+}
+
+/// The only close suggestion, which can be offered in place of the input
+pub fn single_close(suggestions: &[Suggestion]) -> Option<&Suggestion> {
+    match suggestions.iter().filter(|s| s.is_close()).collect::<Vec<_>>()[..] {
+        [only] => Some(only),
+        _ => None,
+    }
+}
+
+/// Phrase the `names` of suggestions as a question appended to an error,
+/// i.e. `, did you mean firefox or firefly?`
+pub fn did_you_mean(names: &[String]) -> String {
+    match names {
+        [] => String::new(),
+        [only] => format!(", did you mean {only}?"),
+        [init @ .., last] => format!(", did you mean {} or {last}?", init.join(", ")),
+    }
+}
+
+pub(super) fn suggest(plugins: &[Plugin], provider: &Provider, limit: usize) -> Vec<Suggestion> {
+    let input = provider.name.to_lowercase();
+    let Some(first) = input.chars().next() else {
+        return vec![];
+    };
+
+    let candidates = plugins
+        .iter()
+        .flat_map(|plugin| plugin.provider_names(provider.kind, &first.to_string(), MAX_SCANNED))
+        .collect::<BTreeSet<_>>();
+
+    rank(&input, candidates.iter().map(String::as_str), limit)
+        .into_iter()
+        .map(|(name, similarity)| Suggestion {
+            provider: Provider {
+                kind: provider.kind,
+                name: name.to_owned(),
+            },
+            similarity,
+        })
+        .collect()
+}
+
+/// The `candidates` resembling `input` other than itself, most similar first
+/// & capped to `limit`
+pub fn rank<'a>(input: &str, candidates: impl IntoIterator<Item = &'a str>, limit: usize) -> Vec<(&'a str, f64)> {
+    let scorer = jaro_winkler::BatchComparator::new(input.chars());
+    let args = jaro_winkler::Args::default().score_cutoff(MIN_SIMILARITY);
+
+    let mut ranked = candidates
+        .into_iter()
+        .filter(|candidate| *candidate != input)
+        .filter_map(|candidate| {
+            let similarity = scorer.normalized_similarity_with_args(candidate.chars(), &args)?;
+            Some((candidate, similarity))
+        })
+        .collect::<Vec<_>>();
+
+    ranked.sort_by(|(a, a_similarity), (b, b_similarity)| b_similarity.total_cmp(a_similarity).then(a.cmp(b)));
+    ranked.truncate(limit);
+    ranked
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Package, package, registry::plugin};
+
+    /// Names of a small repository, with typos made against it
+    const CORPUS: &[&str] = &[
+        "btop",
+        "firefox",
+        "fish",
+        "flatpak",
+        "gimp",
+        "git",
+        "gitg",
+        "htop",
+        "nano",
+        "neovim",
+        "python",
+        "python-pip",
+        "thunderbird",
+        "vim",
+        "vlc",
+    ];
+
+    fn top(input: &str) -> Vec<&'static str> {
+        rank(input, CORPUS.iter().copied(), 3)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    #[test]
+    fn known_typos() {
+        assert_eq!(top("firefx")[0], "firefox");
+        assert_eq!(top("fierfox")[0], "firefox");
+        assert_eq!(top("pyhton")[0], "python");
+        assert_eq!(top("neovin")[0], "neovim");
+        assert_eq!(top("thunderbrid")[0], "thunderbird");
+        assert_eq!(top("flatpack")[0], "flatpak");
+
+        // At most `limit`, most similar first
+        assert_eq!(top("gi"), ["git", "gimp", "gitg"]);
+
+        // Nothing resembles these
+        assert!(top("kernel").is_empty());
+        assert!(top("").is_empty());
+    }
+
+    #[test]
+    fn close_suggestions() {
+        let suggestions = |input: &str| {
+            rank(input, CORPUS.iter().copied(), 3)
+                .into_iter()
+                .map(|(name, similarity)| Suggestion {
+                    provider: Provider::package_name(name),
+                    similarity,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let firefox = suggestions("firefx");
+        assert_eq!(single_close(&firefox).unwrap().provider.name, "firefox");
+
+        // Ambiguous or distant typos aren't offered in place of the input
+        assert!(single_close(&suggestions("pythonpip")).is_none());
+        assert!(single_close(&suggestions("gi")).is_none());
+    }
+
+    #[test]
+    fn phrasing() {
+        let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(did_you_mean(&[]), "");
+        assert_eq!(did_you_mean(&names(&["git"])), ", did you mean git?");
+        assert_eq!(
+            did_you_mean(&names(&["git", "gitg", "gimp"])),
+            ", did you mean git, gitg or gimp?"
+        );
+    }
+
+    #[test]
+    fn registry_suggestions() {
+        let package = |name: &str, flags, providers: &[&str]| Package {
+            id: package::Id::from(name.to_owned()),
+            meta: package::Meta {
+                providers: providers.iter().map(|p| Provider::from_name(p).unwrap()).collect(),
+                ..package::fixture::meta(name)
+            },
+            flags,
+        };
+        let available = package::Flags::new().with_available();
+
+        let mut registry = super::super::Registry::default();
+        registry.add_plugin(Plugin::Test(plugin::Test::new(
+            1,
+            vec![
+                package("firefox", available, &["binary(firefox)"]),
+                package("fish", available, &["binary(fish)"]),
+                // Only installed, so it can't be installed
+                package("firefly", package::Flags::new().with_installed(), &[]),
+            ],
+        )));
+
+        let names = |provider: &str| {
+            registry
+                .suggest(&Provider::from_name(provider).unwrap(), 3)
+                .into_iter()
+                .map(|suggestion| suggestion.provider.to_name())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(names("firefx"), ["firefox"]);
+        assert_eq!(names("Firefx"), ["firefox"]);
+        assert_eq!(names("binary(fihs)"), ["binary(fish)"]);
+        assert!(names("soname(firefx)").is_empty());
+    }
+}
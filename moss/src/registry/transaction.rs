@@ -84,6 +84,14 @@ impl Transaction<'_> {
         self.packages.topo()
     }
 
+    /// The packages `package` directly depends on, once added
+    pub fn dependencies(&self, package: &package::Id) -> impl Iterator<Item = &package::Id> + '_ {
+        self.packages
+            .get_index(package)
+            .into_iter()
+            .flat_map(|node| self.packages.successors(node))
+    }
+
     /// Update internal package graph with all incoming packages & their deps
     #[tracing::instrument(skip_all, fields(lookup = %self.lookup))]
     pub fn add(&mut self, incoming: Vec<package::Id>) -> Result<(), Error> {
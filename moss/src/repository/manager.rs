@@ -8,19 +8,22 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use astr::AStr;
-use fs_err::{self as fs, File};
+use chrono::{DateTime, Utc};
+use fs_err as fs;
 use futures_util::{StreamExt, stream};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
 use stone::{StoneDecodedPayload, StonePayloadMetaTag, StoneReadError};
 use thiserror::Error;
 use url::Url;
 use xxhash_rust::xxh3::xxh3_64;
 
-use tui::{MultiProgress, ProgressBar, ProgressStyle, Styled};
+use tui::{ProgressBar, ProgressStyle, Styled};
 
 use crate::{
     Installation,
     db::meta,
-    environment, package,
+    environment, package, progress,
     repository::{self, Format, OutdatedRepoIndexUri, Repository, format},
     runtime,
     system_model::LoadedSystemModel,
@@ -185,8 +188,18 @@ impl Manager {
         };
 
         if repo.repository.active {
-            let file = fetch_index(&self.source, &repo, &self.installation).await?;
-            runtime::unblock(move || update_meta_db(&repo, &file)).await?;
+            let dir = cache_dir(self.source.identifier(), &repo.repository, &self.installation);
+
+            let result = async {
+                let file = fetch_index(&self.source, &repo, &self.installation).await?;
+                runtime::unblock(move || update_meta_db(&repo, &file)).await.map(drop)
+            }
+            .await;
+
+            // Record the outcome even when refreshing failed, the refresh error takes precedence
+            let recorded = save_last_refresh(&dir, &Refresh::new(&result));
+            result?;
+            recorded?;
         }
 
         Ok(())
@@ -195,13 +208,13 @@ impl Manager {
     /// Refresh all [`Repository`]'s by fetching it's latest index
     /// file and updating it's associated meta database
     pub async fn refresh_all(&self) -> Result<(), Error> {
-        let mpb = MultiProgress::new();
+        let reporter = progress::Reporter::new();
 
         // Fetch index files asynchronously and then
         // update to DB
         stream::iter(self.repositories.iter().filter(|(_, r)| r.repository.active))
             .map(|(id, _)| async {
-                let pb = mpb.add(
+                let pb = reporter.add(
                     ProgressBar::new_spinner()
                         .with_style(
                             ProgressStyle::with_template(" {spinner} {wide_msg}")
@@ -214,7 +227,9 @@ impl Manager {
 
                 self.refresh(id).await?;
 
-                pb.suspend(|| println!("{} {}", "Refreshed".green(), *id));
+                if !tui::is_quiet() {
+                    reporter.println(format_args!("{} {}", "Refreshed".green(), *id));
+                }
 
                 Ok(())
             })
@@ -258,13 +273,13 @@ impl Manager {
             return Ok(0);
         }
 
-        let mpb = MultiProgress::new();
+        let reporter = progress::Reporter::new();
 
         // Fetch index files asynchronously and then
         // update to DB
         stream::iter(&uninitialized)
             .map(|id| async {
-                let pb = mpb.add(
+                let pb = reporter.add(
                     ProgressBar::new_spinner()
                         .with_style(
                             ProgressStyle::with_template(" {spinner} {wide_msg}")
@@ -277,7 +292,9 @@ impl Manager {
 
                 self.refresh(id).await?;
 
-                pb.suspend(|| println!("{} {}", "Refreshed".green(), *id));
+                if !tui::is_quiet() {
+                    reporter.println(format_args!("{} {}", "Refreshed".green(), *id));
+                }
 
                 Ok(()) as Result<_, Error>
             })
@@ -334,6 +351,31 @@ impl Manager {
         Ok(Removal::ConfigDeleted(true))
     }
 
+    /// Describe the cached state of the repository `id`
+    pub fn info(&self, id: &repository::Id) -> Result<Info, Error> {
+        let Some(repo) = self.repositories.get(id) else {
+            return Err(Error::UnknownRepo(id.clone()));
+        };
+
+        let dir = cache_dir(self.source.identifier(), &repo.repository, &self.installation);
+        let index = fs::metadata(dir.join("stone.index")).ok();
+        let db = fs::metadata(dir.join("db")).ok();
+
+        Ok(Info {
+            id: id.clone(),
+            repository: repo.repository.clone(),
+            index_uri: repo.index_uri(),
+            packages: repo.db.package_ids()?.len(),
+            index_modified: index
+                .as_ref()
+                .and_then(|meta| meta.modified().ok())
+                .map(DateTime::<Utc>::from),
+            index_size: index.map(|meta| meta.len()),
+            db_size: db.map(|meta| meta.len()),
+            last_refresh: load_last_refresh(&dir)?,
+        })
+    }
+
     /// List all of the known repositories
     pub fn list(&self) -> impl ExactSizeIterator<Item = (&repository::Id, &Repository)> {
         self.repositories.iter().map(|(id, state)| (id, &state.repository))
@@ -391,6 +433,72 @@ fn cache_dir(identifier: &str, repo: &Repository, installation: &Installation) -
     installation.repo_path(hash)
 }
 
+/// Outcome of the most recent refresh of a repository
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Refresh {
+    pub time: DateTime<Utc>,
+    /// Why the refresh failed, `None` if it succeeded
+    pub error: Option<String>,
+}
+
+impl Refresh {
+    fn new(result: &Result<(), Error>) -> Self {
+        Self {
+            time: Utc::now(),
+            error: result.as_ref().err().map(|error| {
+                // Include the whole chain, top level messages are terse
+                let mut message = error.to_string();
+                let mut source = std::error::Error::source(error);
+                while let Some(error) = source {
+                    message.push_str(&format!(": {error}"));
+                    source = error.source();
+                }
+                message
+            }),
+        }
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Cached state of a single repository, see [`Manager::info`]
+#[derive(Debug, Clone)]
+pub struct Info {
+    pub id: repository::Id,
+    pub repository: Repository,
+    pub index_uri: Option<Url>,
+    /// Number of packages in the meta db
+    pub packages: usize,
+    /// When the stone index was last fetched, `None` if it never was
+    pub index_modified: Option<DateTime<Utc>>,
+    pub index_size: Option<u64>,
+    pub db_size: Option<u64>,
+    pub last_refresh: Option<Refresh>,
+}
+
+const LAST_REFRESH_FILE: &str = "refresh.json";
+
+fn save_last_refresh(dir: &Path, refresh: &Refresh) -> Result<(), Error> {
+    let json = serde_json::to_vec(refresh).map_err(|e| Error::WriteLastRefresh(e.into()))?;
+    fs::write(dir.join(LAST_REFRESH_FILE), json).map_err(Error::WriteLastRefresh)
+}
+
+fn load_last_refresh(dir: &Path) -> Result<Option<Refresh>, Error> {
+    let path = dir.join(LAST_REFRESH_FILE);
+
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let content = fs::read(path).map_err(Error::ReadLastRefresh)?;
+
+    serde_json::from_slice(&content)
+        .map(Some)
+        .map_err(|e| Error::ReadLastRefresh(e.into()))
+}
+
 /// Open the meta db file, ensuring it's
 /// directory exists
 fn open_meta_db(identifier: &str, repo: &Repository, installation: &Installation) -> Result<meta::Database, Error> {
@@ -472,20 +580,25 @@ async fn fetch_index(
     Ok(out_path)
 }
 
-/// Updates a stones metadata into the meta db
-fn update_meta_db(state: &repository::Cached, index_path: &Path) -> Result<(), Error> {
-    // Wipe db since we're refreshing from a new index file
-    state.db.wipe()?;
+/// Updates a stones metadata into the meta db, returning `false` if skipped
+/// as the index is unchanged since last ingested
+fn update_meta_db(state: &repository::Cached, index_path: &Path) -> Result<bool, Error> {
+    let content = fs::read(index_path).map_err(Error::OpenIndex)?;
+    let hash = format!("{:02x}", xxh3_64(&content));
+
+    // Recorded by the meta db itself, so a recreated or migrated db is ingested anew
+    if state.db.index_hash()?.is_some_and(|ingested| ingested == hash) {
+        return Ok(false);
+    }
 
     // Get a stream of payloads
-    let mut file = File::open(index_path).map_err(Error::OpenIndex)?;
-    let mut reader = stone::read(&mut file)?;
+    let mut reader = stone::read_bytes(&content)?;
 
     let payloads = reader.payloads()?.collect::<Result<Vec<_>, _>>()?;
 
-    // Construct Meta for each payload
+    // Construct Meta for each payload, which is pure CPU so spread across cores
     let packages = payloads
-        .into_iter()
+        .into_par_iter()
         .filter_map(|payload| {
             if let StoneDecodedPayload::Meta(meta) = payload {
                 Some(meta)
@@ -507,10 +620,10 @@ fn update_meta_db(state: &repository::Cached, index_path: &Path) -> Result<(), E
         })
         .collect::<Result<Vec<_>, Error>>()?;
 
-    // Batch add to db
-    state.db.batch_add(packages)?;
+    // Replace everything at once, so an interrupted refresh keeps the old packages
+    state.db.replace_all(packages, &hash)?;
 
-    Ok(())
+    Ok(true)
 }
 
 async fn resolve_index_from_root(
@@ -575,6 +688,10 @@ pub enum Error {
     ReadCachedIndexUri(#[source] io::Error),
     #[error("write cached index uri")]
     WriteCachedIndexUri(#[source] io::Error),
+    #[error("read last refresh")]
+    ReadLastRefresh(#[source] io::Error),
+    #[error("write last refresh")]
+    WriteLastRefresh(#[source] io::Error),
     #[error("parse cached index uri")]
     ParseCachedIndexUri(#[source] url::ParseError),
     #[error("one or more repositories has an unsupported format")]
@@ -670,3 +787,158 @@ fn identify_legacy_index_uri(uri: &Url) -> Option<LegacyIndexUri> {
         None
     }
 }
+
+#[cfg(test)]
+mod test {
+    use stone::{StoneHeaderV1FileType, StoneWriter};
+
+    use super::*;
+
+    fn meta(name: &str) -> package::Meta {
+        package::Meta {
+            uri: Some(format!("{name}.stone")),
+            hash: Some(format!("{:02x}", xxh3_64(name.as_bytes()))),
+            download_size: Some(1),
+            ..package::fixture::meta(name)
+        }
+    }
+
+    /// Write an index of `names` to `dir`, returning its URI
+    fn write_index(dir: &Path, names: &[impl AsRef<str>]) -> Url {
+        fs::create_dir_all(dir).unwrap();
+        let path = dir.join("stone.index");
+        let mut file = fs::File::create(&path).unwrap();
+        let mut writer = StoneWriter::new(&mut file, StoneHeaderV1FileType::Repository).unwrap();
+        for name in names {
+            writer
+                .add_payload(meta(name.as_ref()).to_stone_payload().as_slice())
+                .unwrap();
+        }
+        writer.finalize().unwrap();
+
+        Url::from_file_path(path).unwrap()
+    }
+
+    fn repository(uri: Url) -> Repository {
+        Repository {
+            description: "...".to_owned(),
+            source: repository::Source::DirectIndex(uri),
+            priority: repository::Priority::new(0),
+            active: true,
+        }
+    }
+
+    #[test]
+    fn info() {
+        let root = tempfile::tempdir().unwrap();
+        let installation = Installation::open(root.path(), None).unwrap();
+        let volatile = repository::Id::new("volatile");
+        let unstable = repository::Id::new("unstable");
+
+        let manager = Manager::with_explicit(
+            "test",
+            repository::Map::with([
+                (
+                    volatile.clone(),
+                    repository(write_index(&root.path().join("volatile"), &["nano", "zsh"])),
+                ),
+                (
+                    unstable.clone(),
+                    // Never written, refreshing fails
+                    repository(Url::from_file_path(root.path().join("unstable/stone.index")).unwrap()),
+                ),
+            ]),
+            installation,
+        )
+        .unwrap();
+
+        // Nothing is known until refreshed
+        let info = manager.info(&volatile).unwrap();
+        assert_eq!(info.packages, 0);
+        assert_eq!(info.index_modified, None);
+        assert_eq!(info.last_refresh, None);
+
+        runtime::block_on(manager.refresh(&volatile)).unwrap();
+        assert!(runtime::block_on(manager.refresh(&unstable)).is_err());
+
+        let info = manager.info(&volatile).unwrap();
+        assert_eq!(info.packages, 2);
+        assert!(info.index_modified.is_some());
+        assert!(info.index_size.is_some_and(|size| size > 0));
+        assert!(info.last_refresh.as_ref().is_some_and(Refresh::succeeded));
+
+        let info = manager.info(&unstable).unwrap();
+        assert_eq!(info.packages, 0);
+        assert_eq!(info.index_modified, None);
+        let refresh = info.last_refresh.unwrap();
+        assert!(refresh.error.is_some_and(|error| error.starts_with("fetch index file")));
+
+        assert!(matches!(
+            manager.info(&repository::Id::new("missing")),
+            Err(Error::UnknownRepo(_))
+        ));
+    }
+
+    fn cached(dir: &Path) -> repository::Cached {
+        let uri = Url::from_file_path(dir.join("stone.index")).unwrap();
+        let db = meta::Database::new(dir.join("db").to_str().unwrap()).unwrap();
+        repository::Cached::new(repository::Id::new("test"), repository(uri), db, None, None)
+    }
+
+    #[test]
+    fn skip_unchanged_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = dir.path().join("stone.index");
+        let state = cached(dir.path());
+
+        write_index(dir.path(), &["nano", "zsh"]);
+        assert!(update_meta_db(&state, &index).unwrap());
+        assert_eq!(state.db.package_ids().unwrap().len(), 2);
+
+        // Not wiped when skipped
+        let marker = package::Id::from("marker".to_owned());
+        state.db.add(marker.clone(), meta("marker")).unwrap();
+        assert!(!update_meta_db(&state, &index).unwrap());
+        assert!(state.db.package_ids().unwrap().contains(&marker));
+
+        write_index(dir.path(), &["nano", "zsh", "fish"]);
+        assert!(update_meta_db(&state, &index).unwrap());
+        let ids = state.db.package_ids().unwrap();
+        assert_eq!(ids.len(), 3);
+        assert!(!ids.contains(&marker));
+
+        // Ingested anew when the db no longer has the packages of the index
+        state.db.wipe().unwrap();
+        assert!(update_meta_db(&state, &index).unwrap());
+        assert_eq!(state.db.package_ids().unwrap().len(), 3);
+
+        drop(state);
+        for file in ["db", "db-wal", "db-shm"] {
+            let _ = fs::remove_file(dir.path().join(file));
+        }
+        let state = cached(dir.path());
+        assert!(update_meta_db(&state, &index).unwrap());
+        assert_eq!(state.db.package_ids().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn large_index() {
+        const PACKAGES: usize = 5000;
+
+        let dir = tempfile::tempdir().unwrap();
+        let index = dir.path().join("stone.index");
+        let state = cached(dir.path());
+
+        let names = (0..PACKAGES).map(|i| format!("package-{i}")).collect::<Vec<_>>();
+        write_index(dir.path(), &names);
+
+        assert!(update_meta_db(&state, &index).unwrap());
+
+        assert_eq!(state.db.package_ids().unwrap().len(), PACKAGES);
+        let package = state
+            .db
+            .get(&package::Id::from(format!("{:02x}", xxh3_64(b"package-42"))))
+            .unwrap();
+        assert_eq!(package.name.as_str(), "package-42");
+    }
+}
@@ -45,11 +45,13 @@ impl Id {
 }
 
 /// Repository configuration data
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Repository {
     pub description: String,
     #[serde(flatten)]
     pub source: Source,
+    /// Repositories of higher priority are preferred, `0` unless given
+    #[serde(default)]
     pub priority: Priority,
     #[serde(default = "default_as_true")]
     pub active: bool,
@@ -101,7 +103,7 @@ impl Cached {
 }
 
 /// The selection priority of a [`Repository`]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display, From, Into)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Display, From, Into)]
 pub struct Priority(u64);
 
 impl Priority {
@@ -123,7 +125,7 @@ impl Ord for Priority {
 }
 
 /// A map of repositories
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Map(BTreeMap<Id, Repository>);
 
 impl Map {
@@ -135,10 +137,18 @@ impl Map {
         self.0.get(id)
     }
 
+    pub fn get_mut(&mut self, id: &Id) -> Option<&mut Repository> {
+        self.0.get_mut(id)
+    }
+
     pub fn add(&mut self, id: Id, repo: Repository) {
         self.0.insert(id, repo);
     }
 
+    pub fn remove(&mut self, id: &Id) -> Option<Repository> {
+        self.0.remove(id)
+    }
+
     pub fn contains_id(&self, id: &Id) -> bool {
         self.0.contains_key(id)
     }
@@ -195,7 +205,7 @@ pub enum FetchError {
     Io(#[from] io::Error),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Source {
     #[serde(rename = "uri")]
     DirectIndex(Url),
@@ -213,7 +223,7 @@ impl Source {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct RootIndexSource {
     pub base_uri: Url,
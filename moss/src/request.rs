@@ -2,6 +2,8 @@
 // SPDX-License-Identifier: MPL-2.0
 
 use std::{
+    collections::BTreeMap,
+    env,
     io::{self},
     path::Path,
     pin::Pin,
@@ -21,16 +23,64 @@ use crate::{environment, util::Sha256Wrapper};
 /// Shared client for tcp socket reuse and connection limit
 static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
 
+/// Proxy variables overriding those of the process environment
+static PROXY_VARS: OnceLock<BTreeMap<String, String>> = OnceLock::new();
+
+/// Use the proxies of `vars`, i.e. `https_proxy` & `no_proxy`, over those
+/// of the process environment
+///
+/// Only takes effect before the first request.
+pub fn set_proxies(vars: impl IntoIterator<Item = (String, String)>) {
+    let _ = PROXY_VARS.set(vars.into_iter().collect());
+}
+
 fn get_client() -> &'static reqwest::Client {
     CLIENT.get_or_init(|| {
-        reqwest::ClientBuilder::new()
-            .referer(false)
-            .user_agent(concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")))
-            .build()
-            .expect("build reqwest client")
+        let mut builder = reqwest::ClientBuilder::new().referer(false).user_agent(concat!(
+            env!("CARGO_PKG_NAME"),
+            "/",
+            env!("CARGO_PKG_VERSION")
+        ));
+
+        // Otherwise read from the process environment by reqwest
+        if let Some(vars) = PROXY_VARS.get().filter(|vars| !vars.is_empty()) {
+            for proxy in proxies(vars) {
+                builder = builder.proxy(proxy);
+            }
+        }
+
+        builder.build().expect("build reqwest client")
     })
 }
 
+/// The proxies of `vars`, falling back to those of the process environment
+fn proxies(vars: &BTreeMap<String, String>) -> Vec<reqwest::Proxy> {
+    let var = |name: &str| {
+        let upper = name.to_uppercase();
+        vars.get(name)
+            .or_else(|| vars.get(&upper))
+            .cloned()
+            .or_else(|| env::var(name).ok())
+            .or_else(|| env::var(&upper).ok())
+            .filter(|value| !value.is_empty())
+    };
+    let no_proxy = var("no_proxy").and_then(|no_proxy| reqwest::NoProxy::from_string(&no_proxy));
+
+    ["http_proxy", "https_proxy", "all_proxy"]
+        .into_iter()
+        .filter_map(|name| {
+            let url = var(name)?;
+            let proxy = match name {
+                "http_proxy" => reqwest::Proxy::http(url),
+                "https_proxy" => reqwest::Proxy::https(url),
+                "all_proxy" => reqwest::Proxy::all(url),
+                _ => return None,
+            };
+            Some(proxy.ok()?.no_proxy(no_proxy.clone()))
+        })
+        .collect()
+}
+
 /// Downloads a file to the provided path
 pub async fn download(url: Url, to: &Path) -> Result<(), Error> {
     let mut reader = fetch(url).await?;
@@ -9,6 +9,9 @@ use tui::{Styled, pretty};
 
 use crate::package;
 
+/// Marker recorded in a [`State`] description to protect it from pruning
+pub const PROTECTED_MARKER: &str = "[protected]";
+
 /// Unique identifier for [`State`]
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, From, Into, Display)]
 #[debug("{_0:?}")]
@@ -48,12 +51,77 @@ pub struct State {
     pub description: Option<String>,
     /// Selections in this state
     pub selections: Vec<Selection>,
+    /// Paths kept from packages removed by this or an earlier state
+    pub retained: Vec<Retained>,
     /// Creation timestamp
     pub created: DateTime<Utc>,
     /// Relevant type for this State
     pub kind: Kind,
 }
 
+impl State {
+    /// Returns `true` if the state summary or description carries the [`PROTECTED_MARKER`]
+    pub fn is_protected(&self) -> bool {
+        [&self.summary, &self.description]
+            .into_iter()
+            .flatten()
+            .any(|text| text.contains(PROTECTED_MARKER))
+    }
+
+    /// Packages providing the files of this state, whether selected or retained
+    pub fn packages(&self) -> Vec<package::Id> {
+        self.selections
+            .iter()
+            .map(|selection| selection.package.clone())
+            .chain(self.retained.iter().map(|retained| retained.package.clone()))
+            .collect()
+    }
+}
+
+/// Paths of a removed package kept in a [`State`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Retained {
+    pub package: package::Id,
+    /// Layout targets, relative to `/usr`
+    pub paths: Vec<String>,
+}
+
+/// Records who created a [`State`] and how it changed the package set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct History {
+    pub state: Id,
+    pub created: DateTime<Utc>,
+    pub summary: Option<String>,
+    /// `None` for states created before history was recorded
+    pub origin: Option<Origin>,
+    /// Packages added relative to the previously active state
+    pub added: Vec<package::Id>,
+    /// Packages removed relative to the previously active state
+    pub removed: Vec<package::Id>,
+}
+
+/// The invocation which created a [`State`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    /// The full command line
+    pub command: String,
+    pub uid: u32,
+    pub username: Option<String>,
+}
+
+impl Origin {
+    /// Capture the [`Origin`] of the running process
+    pub fn current() -> Self {
+        let uid = nix::unistd::getuid();
+
+        Self {
+            command: std::env::args().collect::<Vec<_>>().join(" "),
+            uid: uid.as_raw(),
+            username: nix::unistd::User::from_uid(uid).ok().flatten().map(|user| user.name),
+        }
+    }
+}
+
 /// The Selection records the presence of a package ID in a [`State`]
 /// It also records whether it was selected as a transitive dependency,
 /// along with an optional human-readable reason
@@ -106,3 +174,29 @@ impl pretty::ColumnDisplay for ColumnDisplay<'_> {
         let _ = write!(writer, "State {}{:width$}", self.0.id.to_string().bold(), " ");
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn packages() {
+        let state = State {
+            id: Id(1),
+            summary: None,
+            description: None,
+            selections: vec![Selection::explicit(package::Id::from("bash"))],
+            retained: vec![Retained {
+                package: package::Id::from("nano-1"),
+                paths: vec!["share/nano/history".to_owned(), "share/nano/state".to_owned()],
+            }],
+            created: Utc::now(),
+            kind: Kind::Transaction,
+        };
+
+        assert_eq!(
+            state.packages(),
+            [package::Id::from("bash"), package::Id::from("nano-1")]
+        );
+    }
+}
@@ -216,6 +216,7 @@ packages {
                 uri: None,
                 hash: None,
                 download_size: None,
+                provenance: Default::default(),
             },
             flags: package::Flags::default(),
         }